@@ -0,0 +1,479 @@
+//! Host sound on Channel::Audio: the system mix is captured on the host,
+//! sent as 20 ms Opus packets and played on the client through cpal
+//! Without the `audio` feature only the packet format is built
+
+#![allow(dead_code)]
+
+use anyhow::Result;
+
+use crate::protocol;
+
+/// Audio in one Opus packet, in ms
+pub const FRAME_MS: u32 = 20;
+/// A packet that arrives this much later than the quickest one is dropped
+pub const MAX_DELAY_MS: i64 = 200;
+/// How fast the quickest arrival is forgotten, so a route that got slower
+/// for good is played again after a while
+const DRIFT_MS_PER_SEC: i64 = 10;
+/// Most sound queued for the output device; older samples are dropped
+pub const MAX_QUEUED_MS: u32 = 120;
+
+/// Input devices taken for the system mix where the OS has no loopback
+const LOOPBACK_NAMES: [&str; 4] = ["blackhole", "soundflower", "loopback", "monitor"];
+
+/// [PACKET][captured_at u64 LE][opus]
+pub fn encode_packet(captured_at: u64, opus: &[u8]) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(9 + opus.len());
+    payload.push(protocol::audio::PACKET);
+    payload.extend(&captured_at.to_le_bytes());
+    payload.extend(opus);
+    payload
+}
+
+/// The capture stamp and Opus data of an audio::PACKET payload
+pub fn decode_packet(payload: &[u8]) -> Result<(u64, &[u8])> {
+    if payload.len() < 10 || payload[0] != protocol::audio::PACKET {
+        anyhow::bail!("Audio packet too short");
+    }
+    let captured_at = u64::from_le_bytes(payload[1..9].try_into()?);
+    Ok((captured_at, &payload[9..]))
+}
+
+/// Picks the packets still worth playing. Host and client clocks are never
+/// compared: the quickest packet sets how far apart they are, and one that
+/// arrives more than MAX_DELAY_MS behind it is dropped instead of queued
+#[derive(Debug, Default)]
+pub struct LateFilter {
+    /// Quickest (local - captured) offset and the local time it was seen
+    quickest: Option<(i64, u64)>,
+    pub dropped: u64,
+}
+
+impl LateFilter {
+    /// Whether a packet captured at `captured_at` (host ms) arriving at `now`
+    /// (local ms) is still on time
+    pub fn accept(&mut self, captured_at: u64, now: u64) -> bool {
+        let offset = now as i64 - captured_at as i64;
+        let quickest = match self.quickest {
+            Some((offset, seen)) => offset + now.saturating_sub(seen) as i64 * DRIFT_MS_PER_SEC / 1000,
+            None => offset,
+        };
+        if offset <= quickest {
+            self.quickest = Some((offset, now));
+            return true;
+        }
+        if offset - quickest > MAX_DELAY_MS {
+            self.dropped += 1;
+            return false;
+        }
+        true
+    }
+}
+
+/// Linear resampling of interleaved stereo, carried across calls
+#[derive(Debug)]
+pub struct Resampler {
+    /// Input frames per output frame
+    step: f64,
+    /// Next output position; 0 is the last frame of the previous call
+    pos: f64,
+    last: [f32; 2],
+}
+
+impl Resampler {
+    pub fn new(from: u32, to: u32) -> Self {
+        Self { step: from as f64 / to as f64, pos: 0.0, last: [0.0; 2] }
+    }
+
+    /// Append `input` to `out` at the output rate
+    pub fn process(&mut self, input: &[[f32; 2]], out: &mut Vec<f32>) {
+        if input.is_empty() {
+            return;
+        }
+        if self.step == 1.0 {
+            out.extend(input.iter().flatten());
+            return;
+        }
+        let frame = |i: usize| if i == 0 { self.last } else { input[i - 1] };
+        while self.pos < input.len() as f64 {
+            let i = self.pos as usize;
+            let t = (self.pos - i as f64) as f32;
+            let (a, b) = (frame(i), frame(i + 1));
+            out.push(a[0] + (b[0] - a[0]) * t);
+            out.push(a[1] + (b[1] - a[1]) * t);
+            self.pos += self.step;
+        }
+        self.pos -= input.len() as f64;
+        self.last = input[input.len() - 1];
+    }
+}
+
+/// Interleaved samples of `channels` channels as stereo frames; mono is
+/// doubled and channels past the first two are left out
+pub fn stereo_frames(samples: &[f32], channels: usize) -> impl Iterator<Item = [f32; 2]> + '_ {
+    samples.chunks_exact(channels.max(1)).map(|frame| match frame {
+        [mono] => [*mono, *mono],
+        [left, right, ..] => [*left, *right],
+        [] => [0.0; 2],
+    })
+}
+
+/// Whether an input device's name says it carries the system mix
+pub fn is_loopback_name(name: &str) -> bool {
+    let name = name.to_lowercase();
+    LOOPBACK_NAMES.iter().any(|loopback| name.contains(loopback))
+}
+
+#[cfg(feature = "audio")]
+pub use device::{capture, Playback};
+
+#[cfg(feature = "audio")]
+mod device {
+    use anyhow::{Context, Result};
+    use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+    use cpal::{FromSample, SampleFormat, SizedSample};
+    use once_cell::sync::Lazy;
+    use parking_lot::Mutex;
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::mpsc;
+    use std::sync::{Arc, Weak};
+    use tokio::sync::broadcast;
+
+    use super::*;
+
+    /// Largest Opus packet asked of the encoder
+    const MAX_PACKET: usize = 4000;
+    /// Longest Opus frame a decoder may return, in ms
+    const MAX_DECODED_MS: u32 = 120;
+    /// Packets kept for a viewer that falls behind (one second)
+    const PACKET_BACKLOG: usize = 50;
+
+    /// Captures shared by every viewer at the same rate
+    static CAPTURES: Lazy<Mutex<HashMap<u32, Weak<Capture>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+    /// The system mix as Opus packets at `rate`, started on first use and
+    /// stopped once the last viewer drops it
+    pub fn capture(rate: u32) -> Result<Arc<Capture>> {
+        let mut captures = CAPTURES.lock();
+        if let Some(capture) = captures.get(&rate).and_then(Weak::upgrade) {
+            return Ok(capture);
+        }
+        let capture = Arc::new(Capture::start(rate)?);
+        captures.insert(rate, Arc::downgrade(&capture));
+        Ok(capture)
+    }
+
+    pub struct Capture {
+        packets: broadcast::Sender<Arc<Vec<u8>>>,
+        /// Dropping it ends the thread that owns the stream
+        _stop: mpsc::Sender<()>,
+    }
+
+    impl Capture {
+        fn start(rate: u32) -> Result<Self> {
+            let (packets, _) = broadcast::channel(PACKET_BACKLOG);
+            let sink = packets.clone();
+            let stop = run_stream("audio-capture", move || open_loopback(rate, sink))?;
+            println!("[AUDIO] Capturing system sound at {} Hz", rate);
+            Ok(Self { packets, _stop: stop.0 })
+        }
+
+        /// audio::PACKET payloads from now on
+        pub fn subscribe(&self) -> broadcast::Receiver<Arc<Vec<u8>>> {
+            self.packets.subscribe()
+        }
+    }
+
+    /// Decodes packets and queues them for the default output device
+    pub struct Playback {
+        decoder: opus::Decoder,
+        resampler: Resampler,
+        late: LateFilter,
+        queue: Arc<Mutex<VecDeque<f32>>>,
+        max_queued: usize,
+        decoded: Vec<f32>,
+        resampled: Vec<f32>,
+        _stop: mpsc::Sender<()>,
+    }
+
+    impl Playback {
+        /// Play packets of `rate` Hz on the default output device
+        pub fn start(rate: u32) -> Result<Self> {
+            let queue = Arc::new(Mutex::new(VecDeque::new()));
+            let output = queue.clone();
+            let (stop, device_rate) = run_stream("audio-playback", move || open_output(output))?;
+            println!("[AUDIO] Playing host sound at {} Hz (device {} Hz)", rate, device_rate);
+            Ok(Self {
+                decoder: opus::Decoder::new(rate, opus::Channels::Stereo)?,
+                resampler: Resampler::new(rate, device_rate),
+                late: LateFilter::default(),
+                queue,
+                max_queued: (device_rate * MAX_QUEUED_MS / 1000) as usize * 2,
+                decoded: vec![0.0; (rate * MAX_DECODED_MS / 1000) as usize * 2],
+                resampled: Vec::new(),
+                _stop: stop,
+            })
+        }
+
+        /// Queue an audio::PACKET payload, unless it came too late
+        pub fn push(&mut self, payload: &[u8]) -> Result<()> {
+            let (captured_at, opus) = decode_packet(payload)?;
+            if !self.late.accept(captured_at, crate::latency::clock_ms()) {
+                return Ok(());
+            }
+            let frames = self.decoder.decode_float(opus, &mut self.decoded, false)?;
+            let stereo: Vec<[f32; 2]> = stereo_frames(&self.decoded[..frames * 2], 2).collect();
+            self.resampled.clear();
+            self.resampler.process(&stereo, &mut self.resampled);
+
+            let mut queue = self.queue.lock();
+            queue.extend(&self.resampled);
+            let excess = queue.len().saturating_sub(self.max_queued);
+            queue.drain(..excess);
+            Ok(())
+        }
+
+        /// Packets dropped for arriving late
+        pub fn dropped(&self) -> u64 {
+            self.late.dropped
+        }
+    }
+
+    /// Own a stream on its own thread (cpal streams cannot move between
+    /// threads) until the returned sender is dropped
+    fn run_stream<T: Send + 'static>(
+        name: &str,
+        open: impl FnOnce() -> Result<(cpal::Stream, T)> + Send + 'static,
+    ) -> Result<(mpsc::Sender<()>, T)> {
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        std::thread::Builder::new().name(name.to_string()).spawn(move || {
+            let stream = match open() {
+                Ok((stream, value)) => {
+                    let _ = ready_tx.send(Ok(value));
+                    stream
+                }
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+            };
+            // Returns once the sender is dropped
+            let _ = stop_rx.recv();
+            drop(stream);
+        })?;
+        let value = ready_rx.recv()??;
+        Ok((stop_tx, value))
+    }
+
+    /// WASAPI records what an output device plays when an input stream is
+    /// built on it
+    #[cfg(windows)]
+    fn loopback_device(host: &cpal::Host) -> Result<(cpal::Device, cpal::SupportedStreamConfig)> {
+        let device = host.default_output_device().context("No audio output device")?;
+        let config = device.default_output_config()?;
+        Ok((device, config))
+    }
+
+    /// CoreAudio and ALSA have no loopback of their own, so take an input
+    /// device that carries the system mix
+    #[cfg(not(windows))]
+    fn loopback_device(host: &cpal::Host) -> Result<(cpal::Device, cpal::SupportedStreamConfig)> {
+        let device = host
+            .input_devices()?
+            .find(|device| device.name().map(|name| is_loopback_name(&name)).unwrap_or(false))
+            .context("No loopback audio device (install BlackHole on macOS, or use a PulseAudio monitor)")?;
+        let config = device.default_input_config()?;
+        Ok((device, config))
+    }
+
+    fn open_loopback(rate: u32, packets: broadcast::Sender<Arc<Vec<u8>>>) -> Result<(cpal::Stream, ())> {
+        let host = cpal::default_host();
+        let (device, config) = loopback_device(&host)?;
+        let format = config.sample_format();
+        let config = config.config();
+        println!(
+            "[AUDIO] Loopback from {} ({} Hz, {} channels)",
+            device.name().unwrap_or_default(),
+            config.sample_rate.0,
+            config.channels
+        );
+        let encoder = PacketEncoder::new(config.sample_rate.0, rate, config.channels as usize, packets)?;
+        let stream = match format {
+            SampleFormat::F32 => build_input::<f32>(&device, &config, encoder)?,
+            SampleFormat::I16 => build_input::<i16>(&device, &config, encoder)?,
+            SampleFormat::U16 => build_input::<u16>(&device, &config, encoder)?,
+            other => anyhow::bail!("Unsupported capture format: {}", other),
+        };
+        stream.play()?;
+        Ok((stream, ()))
+    }
+
+    fn build_input<T: SizedSample>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        mut encoder: PacketEncoder,
+    ) -> Result<cpal::Stream>
+    where
+        f32: FromSample<T>,
+    {
+        let mut samples = Vec::new();
+        Ok(device.build_input_stream(
+            config,
+            move |data: &[T], _: &cpal::InputCallbackInfo| {
+                samples.clear();
+                samples.extend(data.iter().map(|s| s.to_sample::<f32>()));
+                encoder.push(&samples);
+            },
+            |e| eprintln!("[AUDIO] Capture stream error: {}", e),
+            None,
+        )?)
+    }
+
+    /// Cuts the captured sound into Opus packets
+    struct PacketEncoder {
+        opus: opus::Encoder,
+        resampler: Resampler,
+        channels: usize,
+        stereo: Vec<[f32; 2]>,
+        pending: Vec<f32>,
+        /// Interleaved samples in one packet
+        frame_len: usize,
+        packets: broadcast::Sender<Arc<Vec<u8>>>,
+    }
+
+    impl PacketEncoder {
+        fn new(device_rate: u32, rate: u32, channels: usize, packets: broadcast::Sender<Arc<Vec<u8>>>) -> Result<Self> {
+            Ok(Self {
+                opus: opus::Encoder::new(rate, opus::Channels::Stereo, opus::Application::Audio)?,
+                resampler: Resampler::new(device_rate, rate),
+                channels,
+                stereo: Vec::new(),
+                pending: Vec::new(),
+                frame_len: (rate * FRAME_MS / 1000) as usize * 2,
+                packets,
+            })
+        }
+
+        fn push(&mut self, samples: &[f32]) {
+            self.stereo.clear();
+            self.stereo.extend(stereo_frames(samples, self.channels));
+            self.resampler.process(&self.stereo, &mut self.pending);
+
+            while self.pending.len() >= self.frame_len {
+                match self.opus.encode_vec_float(&self.pending[..self.frame_len], MAX_PACKET) {
+                    // No viewer listening is fine
+                    Ok(opus) => {
+                        let _ = self.packets.send(Arc::new(encode_packet(crate::latency::clock_ms(), &opus)));
+                    }
+                    Err(e) => eprintln!("[AUDIO] Opus encode failed: {}", e),
+                }
+                self.pending.drain(..self.frame_len);
+            }
+        }
+    }
+
+    fn open_output(queue: Arc<Mutex<VecDeque<f32>>>) -> Result<(cpal::Stream, u32)> {
+        let host = cpal::default_host();
+        let device = host.default_output_device().context("No audio output device")?;
+        let config = device.default_output_config()?;
+        let format = config.sample_format();
+        let config = config.config();
+        let stream = match format {
+            SampleFormat::F32 => build_output::<f32>(&device, &config, queue)?,
+            SampleFormat::I16 => build_output::<i16>(&device, &config, queue)?,
+            SampleFormat::U16 => build_output::<u16>(&device, &config, queue)?,
+            other => anyhow::bail!("Unsupported playback format: {}", other),
+        };
+        stream.play()?;
+        Ok((stream, config.sample_rate.0))
+    }
+
+    fn build_output<T: SizedSample + FromSample<f32>>(
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+        queue: Arc<Mutex<VecDeque<f32>>>,
+    ) -> Result<cpal::Stream> {
+        let channels = config.channels as usize;
+        Ok(device.build_output_stream(
+            config,
+            move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+                let mut queue = queue.lock();
+                for frame in data.chunks_mut(channels) {
+                    // Silence when the queue runs dry
+                    let left = queue.pop_front().unwrap_or(0.0);
+                    let right = queue.pop_front().unwrap_or(left);
+                    for (i, sample) in frame.iter_mut().enumerate() {
+                        let value = match (channels, i) {
+                            (1, _) => (left + right) / 2.0,
+                            (_, 0) => left,
+                            (_, 1) => right,
+                            _ => 0.0,
+                        };
+                        *sample = T::from_sample(value);
+                    }
+                }
+            },
+            |e| eprintln!("[AUDIO] Playback stream error: {}", e),
+            None,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_round_trip() {
+        let packet = encode_packet(123_456, &[1, 2, 3]);
+        let (captured_at, opus) = decode_packet(&packet).unwrap();
+        assert_eq!(captured_at, 123_456);
+        assert_eq!(opus, [1, 2, 3]);
+        assert!(decode_packet(&packet[..9]).is_err());
+    }
+
+    #[test]
+    fn test_late_packets_dropped() {
+        let mut filter = LateFilter::default();
+        // Host clock 5 s behind ours, 30 ms on the way
+        assert!(filter.accept(1_000, 6_030));
+        assert!(filter.accept(1_020, 6_060));
+        // Held up 250 ms behind the quickest
+        assert!(!filter.accept(1_040, 6_320));
+        assert_eq!(filter.dropped, 1);
+        assert!(filter.accept(1_060, 6_100));
+
+        // The slower route is taken again once the quickest is forgotten
+        let mut filter = LateFilter::default();
+        assert!(filter.accept(0, 10));
+        assert!(!filter.accept(1_000, 1_310));
+        assert!(filter.accept(20_000, 20_310));
+    }
+
+    #[test]
+    fn test_resampler_rates() {
+        let input = vec![[0.5, -0.5]; 480];
+        let mut out = Vec::new();
+        Resampler::new(48000, 24000).process(&input, &mut out);
+        assert_eq!(out.len(), 480);
+
+        let mut resampler = Resampler::new(24000, 48000);
+        let mut out = Vec::new();
+        resampler.process(&input, &mut out);
+        resampler.process(&input, &mut out);
+        assert_eq!(out.len(), 4 * 480 * 2);
+        // Past the start, which eases in from silence
+        assert!(out[4..].chunks(2).all(|frame| frame == [0.5, -0.5]));
+    }
+
+    #[test]
+    fn test_stereo_frames() {
+        assert_eq!(stereo_frames(&[0.1, 0.2], 1).collect::<Vec<_>>(), [[0.1, 0.1], [0.2, 0.2]]);
+        let six = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        assert_eq!(stereo_frames(&six, 6).collect::<Vec<_>>(), [[1.0, 2.0]]);
+        assert!(is_loopback_name("BlackHole 2ch"));
+        assert!(is_loopback_name("Monitor of Built-in Audio"));
+        assert!(!is_loopback_name("MacBook Pro Microphone"));
+    }
+}
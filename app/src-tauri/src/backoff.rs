@@ -0,0 +1,78 @@
+//! Reconnect backoff for the host's relay connection, also pacing retries of a
+//! lost desktop duplication in capture.rs
+//!
+//! Delays double from `INITIAL_DELAY` up to a configured ceiling, and each is
+//! drawn at random from its upper half so a fleet of hosts that lost the relay
+//! together does not come back in lockstep.
+
+use rand::Rng;
+use std::time::Duration;
+
+/// Delay before the first reconnect attempt (before jitter)
+const INITIAL_DELAY: Duration = Duration::from_secs(2);
+
+pub struct Backoff {
+    max_delay: Duration,
+    /// 0 = keep trying
+    max_attempts: u32,
+    attempts: u32,
+}
+
+impl Backoff {
+    pub fn new(max_delay: Duration, max_attempts: u32) -> Self {
+        Self {
+            max_delay: max_delay.max(INITIAL_DELAY),
+            max_attempts,
+            attempts: 0,
+        }
+    }
+
+    /// Wait before the next attempt, or None once the attempts are used up
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if self.max_attempts > 0 && self.attempts >= self.max_attempts {
+            return None;
+        }
+        let ceiling = INITIAL_DELAY
+            .saturating_mul(1 << self.attempts.min(16))
+            .min(self.max_delay);
+        self.attempts += 1;
+
+        let half = ceiling / 2;
+        Some(half + half.mul_f64(rand::thread_rng().gen::<f64>()))
+    }
+
+    /// Attempts made since the last reset
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Start over after a successful connection
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_grows_with_jitter_and_gives_up() {
+        let mut backoff = Backoff::new(Duration::from_secs(30), 6);
+        let ceilings = [2, 4, 8, 16, 30, 30];
+        for ceiling in ceilings {
+            let delay = backoff.next_delay().unwrap();
+            let ceiling = Duration::from_secs(ceiling);
+            assert!(delay >= ceiling / 2 && delay <= ceiling, "{:?} outside {:?}", delay, ceiling);
+        }
+        assert_eq!(backoff.next_delay(), None);
+        assert_eq!(backoff.attempts(), 6);
+
+        backoff.reset();
+        assert!(backoff.next_delay().unwrap() <= INITIAL_DELAY);
+
+        // Unlimited attempts never run out
+        let mut forever = Backoff::new(Duration::from_secs(60), 0);
+        assert!((0..100).all(|_| forever.next_delay().is_some_and(|d| d <= Duration::from_secs(60))));
+    }
+}
@@ -4,11 +4,21 @@
 #![allow(unused_imports)]
 
 use anyhow::Result;
-use std::sync::atomic::{AtomicU8, AtomicU32, Ordering};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::tiles;
 
 /// Global quality setting (1-100, default 75)
 static JPEG_QUALITY: AtomicU8 = AtomicU8::new(75);
 
+/// Quality each new session starts at, from the `connection_quality` setting
+static DEFAULT_QUALITY: AtomicU8 = AtomicU8::new(75);
+
 /// Frame counter for statistics
 static FRAME_COUNT: AtomicU32 = AtomicU32::new(0);
 
@@ -17,6 +27,16 @@ pub fn set_quality(quality: u8) {
     JPEG_QUALITY.store(quality.clamp(1, 100), Ordering::Relaxed);
 }
 
+/// Set the quality new sessions start at (1-100)
+pub fn set_default_quality(quality: u8) {
+    DEFAULT_QUALITY.store(quality.clamp(1, 100), Ordering::Relaxed);
+}
+
+/// Go back to the starting quality, dropping what an earlier session asked for
+pub fn reset_quality() {
+    JPEG_QUALITY.store(DEFAULT_QUALITY.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
 /// Get current quality setting
 pub fn get_quality() -> u8 {
     JPEG_QUALITY.load(Ordering::Relaxed)
@@ -27,38 +47,950 @@ pub fn get_frame_count() -> u32 {
     FRAME_COUNT.load(Ordering::Relaxed)
 }
 
+/// Encode frames as PNG instead of JPEG
+static LOSSLESS: AtomicBool = AtomicBool::new(false);
+
+/// Switch between lossy JPEG and lossless PNG frames
+/// PNG frames are many times larger - meant for LAN/P2P sessions
+pub fn set_lossless(enabled: bool) {
+    LOSSLESS.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether frames are currently encoded losslessly
+pub fn is_lossless() -> bool {
+    LOSSLESS.load(Ordering::Relaxed)
+}
+
+/// Viewers that take frames unencoded (video codecs); while any do, every
+/// frame encoded is also kept as RGB for them
+static RGB_VIEWERS: AtomicUsize = AtomicUsize::new(0);
+
+/// The latest frame encoded, as RGB
+static LATEST_RGB: Lazy<Mutex<Option<RgbFrame>>> = Lazy::new(|| Mutex::new(None));
+
+static RGB_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// A captured frame as packed RGB, with the control policy's mask applied
+#[derive(Clone)]
+pub struct RgbFrame {
+    /// Tells a new frame from one already seen
+    pub seq: u64,
+    pub width: u32,
+    pub height: u32,
+    pub rgb: Arc<Vec<u8>>,
+}
+
+/// Keeps frames available from `latest_rgb` while alive
+pub struct RgbViewer(());
+
+impl RgbViewer {
+    pub fn register() -> Self {
+        RGB_VIEWERS.fetch_add(1, Ordering::Relaxed);
+        Self(())
+    }
+}
+
+impl Drop for RgbViewer {
+    fn drop(&mut self) {
+        if RGB_VIEWERS.fetch_sub(1, Ordering::Relaxed) == 1 {
+            *LATEST_RGB.lock() = None;
+        }
+    }
+}
+
+/// The latest frame encoded while an `RgbViewer` was alive
+pub fn latest_rgb() -> Option<RgbFrame> {
+    LATEST_RGB.lock().clone()
+}
+
+/// Encode packed RGB as PNG in lossless mode, otherwise JPEG at `quality`
+/// adjusted for the content (see `ContentMode`). The JPEG encoder never
+/// subsamples chroma, so coloured text keeps its edges at any quality.
+fn encode_frame(rgb: &[u8], width: u32, height: u32, quality: u8) -> Result<Vec<u8>> {
+    use image::codecs::jpeg::JpegEncoder;
+    use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+    use image::{ColorType, ImageEncoder};
+
+    // Windows the control policy hides never leave the host
+    let masked = crate::policy::masked_frame(rgb, width, height);
+    let rgb = masked.as_deref().unwrap_or(rgb);
+
+    if RGB_VIEWERS.load(Ordering::Relaxed) > 0 {
+        *LATEST_RGB.lock() = Some(RgbFrame {
+            seq: RGB_SEQ.fetch_add(1, Ordering::Relaxed),
+            width,
+            height,
+            rgb: Arc::new(rgb.to_vec()),
+        });
+    }
+
+    let mut out = Vec::new();
+    if is_lossless() {
+        // Fast compression: the frame rate matters more than a few percent of size
+        PngEncoder::new_with_quality(&mut out, CompressionType::Fast, FilterType::Sub)
+            .write_image(rgb, width, height, ColorType::Rgb8)?;
+    } else {
+        let quality = frame_quality(rgb, width, height, quality);
+        JpegEncoder::new_with_quality(&mut out, quality).encode(rgb, width, height, ColorType::Rgb8)?;
+    }
+    Ok(out)
+}
+
+/// Pack top-down rows of BGR(X) pixels into RGB
+/// Rows start every `pitch` bytes and may carry padding past the last pixel
+/// (GPU row alignment, odd-width displays); the last row may stop at its last
+/// pixel. None if the pixels are not 3 or 4 bytes, a row does not fit in
+/// the pitch, or the buffer is too short for `height` rows.
+fn bgr_rows_to_rgb(data: &[u8], pitch: usize, width: usize, height: usize, bytes_per_pixel: usize) -> Option<Vec<u8>> {
+    if !(3..=4).contains(&bytes_per_pixel) {
+        return None;
+    }
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for row in pixel_rows(data, pitch, width, height, bytes_per_pixel)? {
+        for pixel in row.chunks_exact(bytes_per_pixel) {
+            rgb.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+        }
+    }
+    Some(rgb)
+}
+
+/// The `height` rows of `width` pixels in a pitched buffer, without padding
+fn pixel_rows(data: &[u8], pitch: usize, width: usize, height: usize, bytes_per_pixel: usize) -> Option<impl Iterator<Item = &[u8]>> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let row_len = width.checked_mul(bytes_per_pixel)?;
+    let needed = pitch.checked_mul(height - 1)?.checked_add(row_len)?;
+    if pitch < row_len || data.len() < needed {
+        return None;
+    }
+    Some(data.chunks(pitch).take(height).map(move |row| &row[..row_len]))
+}
+
+/// Pixel layout of a duplicated desktop surface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SurfaceFormat {
+    /// 8-bit BGRA, the usual SDR desktop
+    Bgra8,
+    /// 10 bits per channel, red in the low bits; gamma encoded like SDR
+    Rgb10A2,
+    /// Linear scRGB half floats (1.0 = SDR reference white), used by HDR desktops
+    Rgba16Float,
+}
+
+/// Convert a captured surface to packed 8-bit RGB, tone-mapping HDR to SDR
+fn surface_rows_to_rgb(data: &[u8], pitch: usize, width: usize, height: usize, format: SurfaceFormat) -> Option<Vec<u8>> {
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    match format {
+        SurfaceFormat::Bgra8 => return bgr_rows_to_rgb(data, pitch, width, height, 4),
+        SurfaceFormat::Rgb10A2 => {
+            for row in pixel_rows(data, pitch, width, height, 4)? {
+                for pixel in row.chunks_exact(4) {
+                    let value = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                    // Top 8 of each 10-bit channel
+                    rgb.extend_from_slice(&[(value >> 2) as u8, (value >> 12) as u8, (value >> 22) as u8]);
+                }
+            }
+        }
+        SurfaceFormat::Rgba16Float => {
+            let table = &*HDR_TO_SRGB;
+            for row in pixel_rows(data, pitch, width, height, 8)? {
+                for pixel in row.chunks_exact(8) {
+                    for channel in pixel[..6].chunks_exact(2) {
+                        rgb.push(table[u16::from_le_bytes([channel[0], channel[1]]) as usize]);
+                    }
+                }
+            }
+        }
+    }
+    Some(rgb)
+}
+
+/// Linear level up to which HDR values pass through unchanged; brighter ones
+/// roll off towards white instead of clipping
+const HDR_KNEE: f32 = 0.75;
+
+/// 8-bit sRGB for every half-float bit pattern, so HDR frames convert by lookup
+static HDR_TO_SRGB: Lazy<Vec<u8>> = Lazy::new(|| {
+    (0..=u16::MAX).map(|bits| linear_to_srgb(tone_map(half_to_f32(bits)))).collect()
+});
+
+/// Compress linear scRGB into 0..=1 with a soft shoulder above `HDR_KNEE`
+fn tone_map(value: f32) -> f32 {
+    if value.is_nan() || value <= 0.0 {
+        return 0.0;
+    }
+    if value <= HDR_KNEE {
+        return value;
+    }
+    let shoulder = 1.0 - HDR_KNEE;
+    HDR_KNEE + shoulder * (1.0 - (-(value - HDR_KNEE) / shoulder).exp())
+}
+
+fn linear_to_srgb(linear: f32) -> u8 {
+    let encoded = if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// IEEE 754 half precision to f32
+fn half_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    let magnitude = match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    };
+    sign * magnitude
+}
+
+/// Image format of encoded frame data (protocol::video::FORMAT_*)
+pub fn frame_format(data: &[u8]) -> u8 {
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if data.starts_with(PNG_SIGNATURE) {
+        crate::protocol::video::FORMAT_PNG
+    } else {
+        crate::protocol::video::FORMAT_JPEG
+    }
+}
+
+/// A display that can be captured, in virtual-desktop coordinates
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct MonitorInfo {
+    /// Position in `ScreenCapture::list_monitors`, as sent in control::SELECT_MONITOR
+    pub index: u32,
+    /// Friendly name, e.g. DISPLAY2 or the RandR output name
+    pub name: String,
+    pub left: i32,
+    pub top: i32,
+    pub width: u32,
+    pub height: u32,
+    pub primary: bool,
+}
+
+/// Monitor picked with `ScreenCapture::select_monitor`; None captures the
+/// default (the primary monitor, or the whole screen on X11)
+static SELECTED_MONITOR: Lazy<Mutex<Option<MonitorInfo>>> = Lazy::new(|| Mutex::new(None));
+
+/// Set by `CaptureMode::AllMonitors`; wins over the selected monitor
+static ALL_MONITORS: AtomicBool = AtomicBool::new(false);
+
+/// What `ScreenCapture::set_capture_mode` points the capture at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", content = "index", rename_all = "snake_case")]
+pub enum CaptureMode {
+    /// One monitor, by `MonitorInfo::index`
+    Single(u32),
+    /// Every monitor, stitched into one frame of the whole virtual desktop
+    AllMonitors,
+}
+
+impl CaptureMode {
+    /// From the index sent in control::SELECT_MONITOR
+    pub fn from_wire(index: u32) -> Self {
+        if index == crate::protocol::control::SELECT_ALL_MONITORS {
+            Self::AllMonitors
+        } else {
+            Self::Single(index)
+        }
+    }
+
+    pub fn to_wire(self) -> u32 {
+        match self {
+            Self::Single(index) => index,
+            Self::AllMonitors => crate::protocol::control::SELECT_ALL_MONITORS,
+        }
+    }
+}
+
+/// The monitor picked for capture, if any; input injection follows it too
+pub fn selected_monitor() -> Option<MonitorInfo> {
+    SELECTED_MONITOR.lock().clone()
+}
+
+/// Whether frames show every monitor stitched together
+pub fn captures_all_monitors() -> bool {
+    ALL_MONITORS.load(Ordering::Relaxed)
+}
+
+/// Virtual-desktop area the frames show (left, top, width, height), for
+/// input injection; None for the platform default
+pub fn captured_bounds() -> Option<(i32, i32, u32, u32)> {
+    if captures_all_monitors() {
+        let monitors = ScreenCapture::list_monitors();
+        return desktop_bounds(monitors.iter().map(|m| (m.left, m.top, m.width, m.height)));
+    }
+    selected_monitor().map(|m| (m.left, m.top, m.width, m.height))
+}
+
+/// Find the selected monitor in a fresh list by name, since indices shift as
+/// monitors come and go; None if nothing is selected or it was unplugged
+fn resolve_monitor(monitors: &[MonitorInfo], selected: Option<&MonitorInfo>) -> Option<MonitorInfo> {
+    let selected = selected?;
+    monitors.iter().find(|m| m.name == selected.name).cloned()
+}
+
+/// The selected monitor as it is now laid out, or None for the default
+fn target_monitor() -> Option<MonitorInfo> {
+    let selected = selected_monitor()?;
+    resolve_monitor(&ScreenCapture::list_monitors(), Some(&selected))
+}
+
+/// Switch to `mode` and point the capture at it with `retarget`
+/// If that fails, the previous mode is restored and retargeted.
+fn switch_mode(
+    mode: CaptureMode,
+    monitors: Vec<MonitorInfo>,
+    mut retarget: impl FnMut() -> Result<()>,
+) -> Result<()> {
+    let previous = (selected_monitor(), captures_all_monitors());
+    match mode {
+        CaptureMode::Single(index) => {
+            let monitor = monitors.iter().find(|m| m.index == index)
+                .ok_or_else(|| anyhow::anyhow!("No monitor {}", index))?;
+            *SELECTED_MONITOR.lock() = Some(monitor.clone());
+            ALL_MONITORS.store(false, Ordering::Relaxed);
+        }
+        CaptureMode::AllMonitors => {
+            if monitors.is_empty() {
+                anyhow::bail!("No monitors to capture");
+            }
+            ALL_MONITORS.store(true, Ordering::Relaxed);
+        }
+    }
+
+    if let Err(e) = retarget() {
+        *SELECTED_MONITOR.lock() = previous.0;
+        ALL_MONITORS.store(previous.1, Ordering::Relaxed);
+        if let Err(restore) = retarget() {
+            println!("[CAPTURE] Failed to go back to the previous monitor: {}", restore);
+        }
+        return Err(e);
+    }
+
+    match selected_monitor() {
+        Some(monitor) if !captures_all_monitors() => println!(
+            "[CAPTURE] Capturing monitor {} ({}, {}x{} at {},{})",
+            monitor.index, monitor.name, monitor.width, monitor.height, monitor.left, monitor.top
+        ),
+        _ => println!("[CAPTURE] Capturing all {} monitors", monitors.len()),
+    }
+    Ok(())
+}
+
+impl ScreenCapture {
+    /// What changed since the frame numbered `since`, as tiles, when this
+    /// backend tracks damage (Windows desktop duplication); otherwise the
+    /// whole frame as from `capture`
+    #[cfg(not(windows))]
+    pub fn capture_delta(&mut self, _since: Option<u64>) -> Result<Delta> {
+        self.capture().map(Delta::full)
+    }
+
+    /// Capture the monitor at `index` from now on, also in captures created later
+    pub fn select_monitor(&mut self, index: u32) -> Result<MonitorInfo> {
+        self.set_capture_mode(CaptureMode::Single(index))?;
+        selected_monitor().ok_or_else(|| anyhow::anyhow!("No monitor {}", index))
+    }
+}
+
+/// Bounding box (left, top, width, height) of the given areas
+fn desktop_bounds(areas: impl IntoIterator<Item = (i32, i32, u32, u32)>) -> Option<(i32, i32, u32, u32)> {
+    let mut bounds: Option<(i64, i64, i64, i64)> = None;
+    for (left, top, width, height) in areas {
+        let (left, top) = (left as i64, top as i64);
+        let (right, bottom) = (left + width as i64, top + height as i64);
+        bounds = Some(match bounds {
+            None => (left, top, right, bottom),
+            Some((l, t, r, b)) => (l.min(left), t.min(top), r.max(right), b.max(bottom)),
+        });
+    }
+    let (left, top, right, bottom) = bounds?;
+    Some((left as i32, top as i32, (right - left) as u32, (bottom - top) as u32))
+}
+
+/// One monitor's RGB frame and where it sits on the virtual desktop
+struct Tile<'a> {
+    left: i32,
+    top: i32,
+    width: u32,
+    height: u32,
+    rgb: &'a [u8],
+}
+
+/// Compose monitor frames into one RGB frame covering `bounds`, so the
+/// top-left monitor (even at negative coordinates) lands at 0,0; what no
+/// monitor covers stays black. Tiles whose data is short are left out.
+fn stitch<'a>(bounds: (i32, i32, u32, u32), tiles: impl IntoIterator<Item = Tile<'a>>) -> Vec<u8> {
+    let (left, top, width, height) = bounds;
+    let mut frame = vec![0u8; width as usize * height as usize * 3];
+    for tile in tiles {
+        let row_len = tile.width as usize * 3;
+        if tile.rgb.len() < row_len * tile.height as usize {
+            continue;
+        }
+        // Clip to the frame
+        let x0 = (tile.left as i64 - left as i64).max(0);
+        let x1 = (tile.left as i64 - left as i64 + tile.width as i64).min(width as i64);
+        if x1 <= x0 {
+            continue;
+        }
+        let skip = (x0 - (tile.left as i64 - left as i64)) as usize * 3;
+        let copy = (x1 - x0) as usize * 3;
+        for (row, src) in tile.rgb.chunks_exact(row_len).take(tile.height as usize).enumerate() {
+            let y = tile.top as i64 - top as i64 + row as i64;
+            if y < 0 || y >= height as i64 {
+                continue;
+            }
+            let start = (y as usize * width as usize + x0 as usize) * 3;
+            frame[start..start + copy].copy_from_slice(&src[skip..skip + copy]);
+        }
+    }
+    frame
+}
+
+/// Capture backend preference (only Windows has more than one backend)
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptureBackend {
+    /// DXGI Desktop Duplication, falling back to GDI if it is unavailable
+    Auto = 0,
+    /// DXGI Desktop Duplication only
+    Dxgi = 1,
+    /// GDI BitBlt only (slower, but works in RDP sessions and on older GPUs)
+    Gdi = 2,
+}
+
+impl CaptureBackend {
+    /// Parse from the `capture_backend` setting value
+    pub fn from_setting(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(Self::Auto),
+            "dxgi" => Some(Self::Dxgi),
+            "gdi" => Some(Self::Gdi),
+            _ => None,
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Dxgi,
+            2 => Self::Gdi,
+            _ => Self::Auto,
+        }
+    }
+}
+
+/// Global backend preference, read when a capture is created
+static CAPTURE_BACKEND: AtomicU8 = AtomicU8::new(CaptureBackend::Auto as u8);
+
+/// Set the preferred capture backend (applies to captures created afterwards)
+pub fn set_backend(backend: CaptureBackend) {
+    CAPTURE_BACKEND.store(backend as u8, Ordering::Relaxed);
+}
+
+/// Get the preferred capture backend
+pub fn get_backend() -> CaptureBackend {
+    CaptureBackend::from_u8(CAPTURE_BACKEND.load(Ordering::Relaxed))
+}
+
+/// How JPEG quality follows the kind of content on screen
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentMode {
+    /// Classify each frame and adapt the quality to it
+    Auto = 0,
+    /// Always treat the screen as text (documents, terminals, code)
+    Text = 1,
+    /// Always treat the screen as photographic (video, images, games)
+    Photo = 2,
+    /// Encode at the session quality as-is
+    Off = 3,
+}
+
+impl ContentMode {
+    /// Parse from the `content_mode` setting value
+    pub fn from_setting(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(Self::Auto),
+            "text" => Some(Self::Text),
+            "photo" => Some(Self::Photo),
+            "off" => Some(Self::Off),
+            _ => None,
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Text,
+            2 => Self::Photo,
+            3 => Self::Off,
+            _ => Self::Auto,
+        }
+    }
+}
+
+static CONTENT_MODE: AtomicU8 = AtomicU8::new(ContentMode::Auto as u8);
+
+/// Set how the quality follows screen content (applies from the next frame)
+pub fn set_content_mode(mode: ContentMode) {
+    CONTENT_MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+/// Get how the quality follows screen content
+pub fn get_content_mode() -> ContentMode {
+    ContentMode::from_u8(CONTENT_MODE.load(Ordering::Relaxed))
+}
+
+/// What a frame mostly shows, as far as the JPEG encoder is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ContentKind {
+    /// Flat areas with sharp edges and few colours: ringing around glyphs shows
+    Text,
+    /// Smooth gradients and noise: artifacts hide, bytes are better saved
+    Photo,
+    /// Neither clearly, e.g. an empty desktop or a small video in a window
+    Mixed,
+}
+
+/// Every how many rows and columns the classifier samples a pixel pair
+const CONTENT_SAMPLE_STEP: usize = 4;
+
+/// Classify packed RGB from sparse samples of horizontally adjacent pixels:
+/// UI text leaves most neighbours identical with high-contrast edges between
+/// them and uses a small palette, photos rarely repeat a pixel exactly
+fn classify_content(rgb: &[u8], width: u32, height: u32) -> ContentKind {
+    let (width, height) = (width as usize, height as usize);
+    if width < 2 || rgb.len() < width * height * 3 {
+        return ContentKind::Mixed;
+    }
+
+    // Colours quantized to 5 bits per channel
+    let mut palette = vec![0u64; (1 << 15) / 64];
+    let mut colours = 0usize;
+    let (mut pairs, mut flat, mut edges) = (0usize, 0usize, 0usize);
+    let luma = |p: &[u8]| (p[0] as i32 * 77 + p[1] as i32 * 150 + p[2] as i32 * 29) >> 8;
+
+    for y in (0..height).step_by(CONTENT_SAMPLE_STEP) {
+        let row = &rgb[y * width * 3..(y + 1) * width * 3];
+        // Stagger the columns from row to row so a regular pattern is not missed
+        let offset = (y / CONTENT_SAMPLE_STEP) % CONTENT_SAMPLE_STEP;
+        for x in (offset..width - 1).step_by(CONTENT_SAMPLE_STEP) {
+            let a = &row[x * 3..x * 3 + 3];
+            let b = &row[x * 3 + 3..x * 3 + 6];
+            pairs += 1;
+            if a == b {
+                flat += 1;
+            } else if (luma(a) - luma(b)).abs() >= 64 {
+                edges += 1;
+            }
+
+            let key = ((a[0] as usize >> 3) << 10) | ((a[1] as usize >> 3) << 5) | (a[2] as usize >> 3);
+            let bit = 1u64 << (key % 64);
+            if palette[key / 64] & bit == 0 {
+                palette[key / 64] |= bit;
+                colours += 1;
+            }
+        }
+    }
+
+    let flat = flat as f64 / pairs as f64;
+    let edges = edges as f64 / pairs as f64;
+    if flat >= 0.5 && edges >= 0.01 && colours <= 2048 {
+        ContentKind::Text
+    } else if flat < 0.25 && colours >= 1024 {
+        ContentKind::Photo
+    } else {
+        ContentKind::Mixed
+    }
+}
+
+/// JPEG quality for content of `kind` when the session asked for `quality`
+/// Text gets a fifth more, up to 15 points (flat areas cost next to nothing,
+/// so the frame grows far less than the number suggests); photos a fifth
+/// less, up to 10. The shift scales with the requested quality, so a session
+/// QoS has throttled to a low budget stays low.
+fn content_quality(quality: u8, kind: ContentKind) -> u8 {
+    match kind {
+        ContentKind::Text => quality.saturating_add((quality / 5).min(15)).min(95).max(quality),
+        ContentKind::Photo => quality.saturating_sub((quality / 5).min(10)).max(20).min(quality),
+        ContentKind::Mixed => quality,
+    }
+}
+
+/// Consecutive frames a new classification must hold before it is used, so
+/// scrolling past an image does not make the quality pulse
+const CONTENT_SWITCH_FRAMES: u8 = 3;
+
+/// Classification of recent frames, with hysteresis
+struct ContentTracker {
+    current: ContentKind,
+    candidate: ContentKind,
+    streak: u8,
+}
+
+impl ContentTracker {
+    fn new() -> Self {
+        Self { current: ContentKind::Mixed, candidate: ContentKind::Mixed, streak: 0 }
+    }
+
+    /// Note the latest frame's kind; returns the kind to encode it as
+    fn observe(&mut self, kind: ContentKind) -> ContentKind {
+        if kind == self.current {
+            self.streak = 0;
+        } else if kind == self.candidate {
+            self.streak += 1;
+            if self.streak >= CONTENT_SWITCH_FRAMES {
+                self.current = kind;
+                self.streak = 0;
+            }
+        } else {
+            self.candidate = kind;
+            self.streak = 1;
+        }
+        self.current
+    }
+}
+
+static CONTENT_TRACKER: Lazy<Mutex<ContentTracker>> = Lazy::new(|| Mutex::new(ContentTracker::new()));
+
+/// Quality to encode this frame at, per the content mode
+fn frame_quality(rgb: &[u8], width: u32, height: u32, quality: u8) -> u8 {
+    let kind = match get_content_mode() {
+        ContentMode::Off => return quality,
+        ContentMode::Text => ContentKind::Text,
+        ContentMode::Photo => ContentKind::Photo,
+        ContentMode::Auto => CONTENT_TRACKER.lock().observe(classify_content(rgb, width, height)),
+    };
+    content_quality(quality, kind)
+}
+
+/// Quality for dirty-region tiles: what the frames are currently encoded at,
+/// without letting each small tile sway the content classification
+fn tile_quality(quality: u8) -> u8 {
+    let kind = match get_content_mode() {
+        ContentMode::Off => return quality,
+        ContentMode::Text => ContentKind::Text,
+        ContentMode::Photo => ContentKind::Photo,
+        ContentMode::Auto => CONTENT_TRACKER.lock().current,
+    };
+    content_quality(quality, kind)
+}
+
+/// What `ScreenCapture::capture_delta` found
+pub enum Delta {
+    /// Nothing changed since the frame asked about
+    Unchanged,
+    /// The areas that changed, each encoded on its own
+    Tiles { width: u32, height: u32, seq: u64, tiles: Vec<tiles::Tile> },
+    /// The whole frame as from `capture`; `seq` is None when the backend keeps
+    /// no damage log, so the next frame is whole again
+    Full { width: u32, height: u32, seq: Option<u64>, data: Vec<u8> },
+    /// A frame of the viewer's own video stream, ready to send (see `vpx`)
+    Encoded { width: u32, height: u32, payload: Vec<u8> },
+}
+
+impl Delta {
+    pub fn full((width, height, data): (u32, u32, Vec<u8>)) -> Self {
+        Self::Full { width, height, seq: None, data }
+    }
+
+    /// Size of the frame, unless nothing changed or the capture came back empty
+    pub fn size(&self) -> Option<(u32, u32)> {
+        match self {
+            Self::Unchanged => None,
+            Self::Full { data, .. } if data.is_empty() => None,
+            Self::Tiles { width, height, .. }
+            | Self::Full { width, height, .. }
+            | Self::Encoded { width, height, .. } => Some((*width, *height)),
+        }
+    }
+}
+
+/// Encode each of `rects` out of a packed RGB frame as JPEG, with the area a
+/// control policy hides blacked out as in `encode_frame`
+fn encode_tiles(rgb: &[u8], width: u32, rects: &[tiles::Rect], quality: u8) -> Result<Vec<tiles::Tile>> {
+    use image::codecs::jpeg::JpegEncoder;
+    use image::ColorType;
+
+    let hidden = crate::policy::blackout_rect();
+    let quality = tile_quality(quality);
+    rects.iter().map(|&rect| {
+        let mut pixels = tiles::crop(rgb, width, rect);
+        if let Some((left, top, right, bottom)) = hidden {
+            let (x, y) = (rect.x as i32, rect.y as i32);
+            crate::policy::blank_rect(
+                &mut pixels, rect.width as usize, rect.height as usize,
+                (left - x, top - y, right - x, bottom - y),
+            );
+        }
+        let mut data = Vec::new();
+        JpegEncoder::new_with_quality(&mut data, quality)
+            .encode(&pixels, rect.width, rect.height, ColorType::Rgb8)?;
+        Ok(tiles::Tile { rect, data })
+    }).collect()
+}
+
+/// Damage for the policy blackout having moved since `last` (updated): its
+/// old and new areas must be sent again, even if nothing changed there
+fn blackout_damage(last: &mut Option<(i32, i32, i32, i32)>, width: u32, height: u32) -> Option<Vec<tiles::Rect>> {
+    let current = crate::policy::blackout_rect();
+    if current == *last {
+        return None;
+    }
+    let previous = std::mem::replace(last, current);
+    Some([previous, current].into_iter().flatten()
+        .filter_map(|(left, top, right, bottom)| {
+            tiles::Rect::clipped(left as i64, top as i64, right as i64, bottom as i64, width, height)
+        })
+        .collect())
+}
+
+/// Bounds for the active/idle capture rate settings
+pub const MIN_CAPTURE_FPS: u32 = 1;
+pub const MAX_CAPTURE_FPS: u32 = 60;
+
+/// Capture rate while the screen is changing, and once it has gone static
+static ACTIVE_FPS: AtomicU32 = AtomicU32::new(30);
+static IDLE_FPS: AtomicU32 = AtomicU32::new(2);
+
+/// Set the active and idle capture rates (applies from the next frame)
+pub fn set_frame_rates(active_fps: u32, idle_fps: u32) {
+    ACTIVE_FPS.store(active_fps.clamp(MIN_CAPTURE_FPS, MAX_CAPTURE_FPS), Ordering::Relaxed);
+    IDLE_FPS.store(idle_fps.clamp(MIN_CAPTURE_FPS, MAX_CAPTURE_FPS), Ordering::Relaxed);
+}
+
+/// Get the (active, idle) capture rates
+pub fn frame_rates() -> (u32, u32) {
+    (ACTIVE_FPS.load(Ordering::Relaxed), IDLE_FPS.load(Ordering::Relaxed))
+}
+
+/// Number of recent frames kept for encode timing
+const ENCODE_STATS_WINDOW: usize = 60;
+
+/// Capture + encode timings of recently produced frames
+static ENCODE_TRACKER: Lazy<Mutex<EncodeTracker>> = Lazy::new(|| Mutex::new(EncodeTracker::new()));
+
+/// Capture/encode performance statistics
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct EncodeStats {
+    /// Average capture + encode time over the recent window (ms)
+    pub avg_encode_ms: f64,
+    /// Slowest capture + encode time in the recent window (ms)
+    pub max_encode_ms: f64,
+    /// Fresh frames produced in the last second
+    pub capture_fps: u32,
+    /// Total frames produced since start
+    pub frame_count: u32,
+}
+
+/// Moving window of per-frame capture + encode durations
+struct EncodeTracker {
+    samples: VecDeque<(Instant, Duration)>,
+}
+
+impl EncodeTracker {
+    fn new() -> Self {
+        Self {
+            samples: VecDeque::with_capacity(ENCODE_STATS_WINDOW),
+        }
+    }
+
+    fn record(&mut self, at: Instant, elapsed: Duration) {
+        if self.samples.len() >= ENCODE_STATS_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((at, elapsed));
+    }
+
+    fn stats(&self, now: Instant) -> EncodeStats {
+        let count = self.samples.len().max(1) as f64;
+        let total_ms: f64 = self.samples.iter().map(|(_, d)| d.as_secs_f64() * 1000.0).sum();
+        let max_ms = self.samples.iter()
+            .map(|(_, d)| d.as_secs_f64() * 1000.0)
+            .fold(0.0, f64::max);
+        let capture_fps = self.samples.iter()
+            .filter(|(at, _)| now.duration_since(*at) <= Duration::from_secs(1))
+            .count() as u32;
+
+        EncodeStats {
+            avg_encode_ms: total_ms / count,
+            max_encode_ms: max_ms,
+            capture_fps,
+            frame_count: FRAME_COUNT.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Record a freshly captured and encoded frame
+fn record_frame(started: Instant) {
+    FRAME_COUNT.fetch_add(1, Ordering::Relaxed);
+    ENCODE_TRACKER.lock().record(Instant::now(), started.elapsed());
+}
+
+/// Get capture/encode timing statistics
+pub fn get_encode_stats() -> EncodeStats {
+    ENCODE_TRACKER.lock().stats(Instant::now())
+}
+
+/// Times a lost desktop duplication is recreated before capture gives up on DXGI
+const DXGI_RETRIES: u32 = 5;
+
+/// Longest wait between two attempts at recreating it
+const DXGI_RETRY_MAX_DELAY: Duration = Duration::from_secs(8);
+
+/// Paces recreating a desktop duplication that was lost (mode change, desktop
+/// switch, UAC prompt), so a transient loss does not move capture to GDI
+struct DxgiRecovery {
+    backoff: crate::backoff::Backoff,
+    /// No attempt before this
+    retry_at: Option<Instant>,
+}
+
+impl DxgiRecovery {
+    fn new() -> Self {
+        Self {
+            backoff: crate::backoff::Backoff::new(DXGI_RETRY_MAX_DELAY, DXGI_RETRIES),
+            retry_at: None,
+        }
+    }
+
+    /// The duplication was lost again: schedule the next attempt, or false
+    /// once the retries are used up
+    fn failed(&mut self, now: Instant) -> bool {
+        match self.backoff.next_delay() {
+            Some(delay) => {
+                self.retry_at = Some(now + delay);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether DXGI may be tried again
+    fn due(&self, now: Instant) -> bool {
+        self.retry_at.is_none_or(|at| now >= at)
+    }
+
+    /// A capture went through; the next loss starts over
+    fn recovered(&mut self) {
+        self.backoff.reset();
+        self.retry_at = None;
+    }
+}
+
 #[cfg(windows)]
 mod windows_capture {
     use super::*;
     use anyhow::{Context, Result};
     use windows::{
         core::*,
-        Win32::Foundation::RECT,
+        Win32::Foundation::{BOOL, LPARAM, RECT, TRUE},
         Win32::Graphics::Direct3D::*,
         Win32::Graphics::Direct3D11::*,
         Win32::Graphics::Dxgi::Common::*,
         Win32::Graphics::Dxgi::*,
+        Win32::Graphics::Gdi::*,
+        Win32::UI::WindowsAndMessaging::{GetSystemMetrics, MONITORINFOF_PRIMARY, SM_CXSCREEN, SM_CYSCREEN},
     };
 
-    pub struct ScreenCapture {
+    /// Monitors in EnumDisplayMonitors order, which input injection enumerates in too
+    fn monitor_list() -> Vec<MonitorInfo> {
+        unsafe extern "system" fn collect(monitor: HMONITOR, _dc: HDC, _clip: *mut RECT, data: LPARAM) -> BOOL {
+            let monitors = &mut *(data.0 as *mut Vec<MonitorInfo>);
+            let mut info = MONITORINFOEXW::default();
+            info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+            if GetMonitorInfoW(monitor, &mut info.monitorInfo).as_bool() {
+                let rect = info.monitorInfo.rcMonitor;
+                // Device names look like \\.\DISPLAY1
+                let device = String::from_utf16_lossy(&info.szDevice);
+                monitors.push(MonitorInfo {
+                    index: monitors.len() as u32,
+                    name: device.trim_end_matches('\0').trim_start_matches(r"\\.\").to_string(),
+                    left: rect.left,
+                    top: rect.top,
+                    width: (rect.right - rect.left) as u32,
+                    height: (rect.bottom - rect.top) as u32,
+                    primary: info.monitorInfo.dwFlags & MONITORINFOF_PRIMARY != 0,
+                });
+            }
+            TRUE
+        }
+
+        let mut monitors: Vec<MonitorInfo> = Vec::new();
+        unsafe {
+            let _ = EnumDisplayMonitors(None, None, Some(collect), LPARAM(&mut monitors as *mut _ as isize));
+        }
+        monitors
+    }
+
+    /// DXGI Desktop Duplication backend
+    struct DxgiCapture {
         device: ID3D11Device,
         context: ID3D11DeviceContext,
         duplication: IDXGIOutputDuplication,
         staging: ID3D11Texture2D,
         width: u32,
         height: u32,
+        /// Desktop position of the duplicated output
+        origin: (i32, i32),
+        /// The monitor duplicated when stitching all of them; None follows the selection
+        pinned: Option<MonitorInfo>,
+        /// Layout of the duplicated surface, which the staging texture matches
+        format: SurfaceFormat,
         last_frame: Option<Vec<u8>>,
+        /// The latest desktop frame as RGB, which tiles are cut from
+        rgb: Option<Vec<u8>>,
+        /// What changed in each frame, for `capture_delta`
+        damage: tiles::DamageLog,
+        /// Where the policy blackout was when tiles were last cut
+        blackout: Option<(i32, i32, i32, i32)>,
+        /// Which output of a stitched frame this is, and where it sits in it
+        cursor_source: usize,
+        cursor_offset: (i32, i32),
         unchanged_count: u32,
         // Track if duplication needs recreation
         needs_recreate: bool,
+        // One-shot quality override for snapshot keyframes
+        quality_override: Option<u8>,
     }
 
-    impl ScreenCapture {
-        pub fn new() -> Result<Self> {
-            unsafe { Self::init() }
+    /// What `DxgiCapture::grab` got from the duplication
+    /// The duplication was lost and could not be recreated yet; worth retrying
+    #[derive(Debug)]
+    struct DuplicationLost;
+
+    impl std::fmt::Display for DuplicationLost {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str("Desktop duplication lost")
+        }
+    }
+
+    enum Grab {
+        /// A new desktop frame, as RGB, and what changed in it (None: unknown)
+        Frame(Vec<u8>, Option<Vec<tiles::Rect>>),
+        /// Nothing changed within the timeout
+        Unchanged,
+        /// Access lost (mode switch, UAC prompt); duplication is recreated next time
+        Lost(windows::core::Error),
+        /// The output changed size; duplication and staging were rebuilt
+        Resized,
+        /// The mapped texture could not hold the frame, at this row pitch
+        Malformed(usize),
+    }
+
+    impl DxgiCapture {
+        fn new() -> Result<Self> {
+            unsafe { Self::init(None) }
         }
 
-        unsafe fn init() -> Result<Self> {
+        unsafe fn init(pinned: Option<MonitorInfo>) -> Result<Self> {
             let mut device: Option<ID3D11Device> = None;
             let mut context: Option<ID3D11DeviceContext> = None;
 
@@ -77,24 +1009,115 @@ mod windows_capture {
             let device = device.context("No D3D11 device")?;
             let context = context.context("No D3D11 context")?;
 
-            let dxgi_device: IDXGIDevice = device.cast()?;
-            let adapter: IDXGIAdapter = dxgi_device.GetAdapter()?;
-            let output: IDXGIOutput = adapter.EnumOutputs(0)?;
-            let output1: IDXGIOutput1 = output.cast()?;
-
-            let mut desc = DXGI_OUTPUT_DESC::default();
-            output.GetDesc(&mut desc)?;
+            let monitor = pinned.clone().or_else(target_monitor);
+            let (output, desc) = Self::output_for(&device, monitor.as_ref())?;
             let width = (desc.DesktopCoordinates.right - desc.DesktopCoordinates.left) as u32;
             let height = (desc.DesktopCoordinates.bottom - desc.DesktopCoordinates.top) as u32;
+            let origin = (desc.DesktopCoordinates.left, desc.DesktopCoordinates.top);
 
-            let duplication = output1.DuplicateOutput(&device)?;
+            let duplication = Self::duplicate(&output, &device)?;
+            let format = Self::duplication_format(&duplication)?;
+            let staging = Self::create_staging(&device, width, height, format)?;
+            println!("[CAPTURE] Desktop surface format: {:?}", format);
+            // Reported relative to this output from the first frame on
+            crate::cursor::hide();
 
-            let tex_desc = D3D11_TEXTURE2D_DESC {
-                Width: width,
+            Ok(Self {
+                device,
+                context,
+                duplication,
+                staging,
+                width,
+                height,
+                origin,
+                pinned,
+                format,
+                last_frame: None,
+                rgb: None,
+                damage: tiles::DamageLog::default(),
+                blackout: None,
+                cursor_source: 0,
+                cursor_offset: (0, 0),
+                unchanged_count: 0,
+                needs_recreate: false,
+                quality_override: None,
+            })
+        }
+
+        /// The output showing `monitor`, or without one the primary monitor
+        /// (desktop origin); falls back to the adapter's first output only
+        /// when no monitor was asked for
+        unsafe fn output_for(device: &ID3D11Device, monitor: Option<&MonitorInfo>) -> Result<(IDXGIOutput, DXGI_OUTPUT_DESC)> {
+            let dxgi_device: IDXGIDevice = device.cast()?;
+            let adapter: IDXGIAdapter = dxgi_device.GetAdapter()?;
+            let origin = monitor.map_or((0, 0), |m| (m.left, m.top));
+
+            let mut first = None;
+            let mut index = 0;
+            while let Ok(output) = adapter.EnumOutputs(index) {
+                let mut desc = DXGI_OUTPUT_DESC::default();
+                output.GetDesc(&mut desc)?;
+                if (desc.DesktopCoordinates.left, desc.DesktopCoordinates.top) == origin {
+                    return Ok((output, desc));
+                }
+                first.get_or_insert((output, desc));
+                index += 1;
+            }
+            if let Some(monitor) = monitor {
+                anyhow::bail!("Monitor {} is not on the capture adapter", monitor.name);
+            }
+            first.context("No DXGI output")
+        }
+
+        /// Duplicate an output, accepting the HDR formats where Windows supports it
+        /// (IDXGIOutput5); plain DuplicateOutput fails on an HDR desktop
+        unsafe fn duplicate(output: &IDXGIOutput, device: &ID3D11Device) -> Result<IDXGIOutputDuplication> {
+            if let Ok(output5) = output.cast::<IDXGIOutput5>() {
+                let formats = [
+                    DXGI_FORMAT_R16G16B16A16_FLOAT,
+                    DXGI_FORMAT_R10G10B10A2_UNORM,
+                    DXGI_FORMAT_B8G8R8A8_UNORM,
+                ];
+                match output5.DuplicateOutput1(device, 0, &formats) {
+                    Ok(duplication) => return Ok(duplication),
+                    Err(e) => println!("[CAPTURE] DuplicateOutput1 failed ({}), trying DuplicateOutput", e),
+                }
+            }
+            let output1: IDXGIOutput1 = output.cast()?;
+            Ok(output1.DuplicateOutput(device)?)
+        }
+
+        unsafe fn duplication_format(duplication: &IDXGIOutputDuplication) -> Result<SurfaceFormat> {
+            let mut desc = DXGI_OUTDUPL_DESC::default();
+            duplication.GetDesc(&mut desc);
+            Self::surface_format(desc.ModeDesc.Format)
+        }
+
+        fn surface_format(format: DXGI_FORMAT) -> Result<SurfaceFormat> {
+            match format {
+                DXGI_FORMAT_B8G8R8A8_UNORM | DXGI_FORMAT_B8G8R8A8_UNORM_SRGB => Ok(SurfaceFormat::Bgra8),
+                DXGI_FORMAT_R10G10B10A2_UNORM => Ok(SurfaceFormat::Rgb10A2),
+                DXGI_FORMAT_R16G16B16A16_FLOAT => Ok(SurfaceFormat::Rgba16Float),
+                other => anyhow::bail!("Unsupported desktop surface format {}", other.0),
+            }
+        }
+
+        fn dxgi_format(format: SurfaceFormat) -> DXGI_FORMAT {
+            match format {
+                SurfaceFormat::Bgra8 => DXGI_FORMAT_B8G8R8A8_UNORM,
+                SurfaceFormat::Rgb10A2 => DXGI_FORMAT_R10G10B10A2_UNORM,
+                SurfaceFormat::Rgba16Float => DXGI_FORMAT_R16G16B16A16_FLOAT,
+            }
+        }
+
+        /// CPU-readable texture that desktop frames are copied into
+        unsafe fn create_staging(device: &ID3D11Device, width: u32, height: u32, format: SurfaceFormat) -> Result<ID3D11Texture2D> {
+            let tex_desc = D3D11_TEXTURE2D_DESC {
+                Width: width,
                 Height: height,
                 MipLevels: 1,
                 ArraySize: 1,
-                Format: DXGI_FORMAT_B8G8R8A8_UNORM,
+                Format: Self::dxgi_format(format),
                 SampleDesc: DXGI_SAMPLE_DESC { Count: 1, Quality: 0 },
                 Usage: D3D11_USAGE_STAGING,
                 BindFlags: 0,
@@ -104,142 +1127,872 @@ mod windows_capture {
 
             let mut staging: Option<ID3D11Texture2D> = None;
             device.CreateTexture2D(&tex_desc, None, Some(&mut staging))?;
-            let staging = staging.context("No staging texture")?;
+            staging.context("No staging texture")
+        }
+
+        /// Recreate duplication output (needed after display changes, UAC prompts,
+        /// HDR being switched on or off, etc.)
+        /// If the output size or format changed, the staging texture is rebuilt to match
+        unsafe fn recreate_duplication(&mut self) -> Result<()> {
+            let monitor = self.pinned.clone().or_else(target_monitor);
+            let (output, desc) = Self::output_for(&self.device, monitor.as_ref())?;
+            let width = (desc.DesktopCoordinates.right - desc.DesktopCoordinates.left) as u32;
+            let height = (desc.DesktopCoordinates.bottom - desc.DesktopCoordinates.top) as u32;
+            let origin = (desc.DesktopCoordinates.left, desc.DesktopCoordinates.top);
+
+            let duplication = Self::duplicate(&output, &self.device)?;
+            let format = Self::duplication_format(&duplication)?;
+
+            if origin != self.origin {
+                // Another monitor: the cached frame shows the old one
+                self.origin = origin;
+                self.last_frame = None;
+                self.unchanged_count = 0;
+            }
+
+            if width != self.width || height != self.height {
+                println!("[CAPTURE] Display resized {}x{} -> {}x{}, recreating staging texture", self.width, self.height, width, height);
+                self.staging = Self::create_staging(&self.device, width, height, format)?;
+                self.width = width;
+                self.height = height;
+                self.format = format;
+                // Cached frames have the old dimensions
+                self.last_frame = None;
+                self.unchanged_count = 0;
+            } else if format != self.format {
+                println!("[CAPTURE] Desktop surface format {:?} -> {:?}, recreating staging texture", self.format, format);
+                self.staging = Self::create_staging(&self.device, width, height, format)?;
+                self.format = format;
+            }
+
+            self.duplication = duplication;
+            self.needs_recreate = false;
+            // Whatever was missed meanwhile is not in the damage log
+            self.damage.reset();
+            self.rgb = None;
+            Ok(())
+        }
+
+        /// Follow a new monitor selection; only another output is duplicated
+        /// afresh, as a second duplication of the same output would fail
+        unsafe fn retarget(&mut self) -> Result<()> {
+            let origin = target_monitor().map_or((0, 0), |m| (m.left, m.top));
+            if origin == self.origin && !self.needs_recreate {
+                return Ok(());
+            }
+            self.recreate_duplication()
+        }
+
+        fn capture(&mut self) -> Result<(u32, u32, Vec<u8>)> {
+            unsafe { self.capture_internal() }
+        }
+
+        /// Capture a fresh keyframe at the given quality, bypassing the frame cache
+        fn capture_keyframe(&mut self, quality: u8) -> Result<(u32, u32, Vec<u8>)> {
+            self.quality_override = Some(quality.clamp(1, 100));
+            let result = unsafe { self.capture_internal() };
+            self.quality_override = None;
+            result
+        }
+
+        unsafe fn capture_internal(&mut self) -> Result<(u32, u32, Vec<u8>)> {
+            let started = Instant::now();
+
+            // Recreate duplication if needed
+            if self.needs_recreate {
+                if let Err(e) = self.recreate_duplication() {
+                    return Err(e.context(DuplicationLost));
+                }
+            }
+
+            let rgb = match self.grab(100)? {
+                Grab::Frame(rgb, damage) => {
+                    self.damage.record(damage);
+                    rgb
+                }
+                Grab::Unchanged => {
+                    // Snapshot requested but screen unchanged - re-encode the
+                    // last copied texture at the requested quality
+                    if self.quality_override.is_some() && self.last_frame.is_some() {
+                        return self.encode_staging();
+                    }
+
+                    // Another viewer's `capture_delta` took the new frame
+                    if self.last_frame.is_none() {
+                        if let Some(rgb) = &self.rgb {
+                            let jpeg = self.encode_frame(rgb)?;
+                            self.last_frame = Some(jpeg.clone());
+                            record_frame(started);
+                            return Ok((self.width, self.height, jpeg));
+                        }
+                    }
+
+                    // No new frame - return cached frame if available
+                    if let Some(ref frame) = self.last_frame {
+                        self.unchanged_count += 1;
+                        if self.unchanged_count <= 10 {
+                            return Ok((self.width, self.height, frame.clone()));
+                        }
+                    }
+                    return Ok((self.width, self.height, Vec::new()));
+                }
+                Grab::Lost(e) => return Err(anyhow::Error::from(e).context(DuplicationLost)),
+                // Answer this request empty; the next one has the new size
+                Grab::Resized => return Ok((self.width, self.height, Vec::new())),
+                Grab::Malformed(pitch) => return self.malformed_frame(pitch),
+            };
+
+            // Encode as JPEG with adaptive quality
+            let jpeg = self.encode_frame(&rgb)?;
+
+            // Cache the frame for reuse
+            self.last_frame = Some(jpeg.clone());
+            self.rgb = Some(rgb);
+
+            // Update frame counter and encode timing
+            record_frame(started);
+
+            Ok((self.width, self.height, jpeg))
+        }
+
+        /// Copy the next desktop frame out of the duplication, waiting up to
+        /// `timeout_ms` for one
+        unsafe fn grab(&mut self, timeout_ms: u32) -> Result<Grab> {
+            let mut frame_info = DXGI_OUTDUPL_FRAME_INFO::default();
+            let mut resource: Option<IDXGIResource> = None;
+
+            match self.duplication.AcquireNextFrame(timeout_ms, &mut frame_info, &mut resource) {
+                Ok(()) => {}
+                Err(e) if e.code() == DXGI_ERROR_WAIT_TIMEOUT => return Ok(Grab::Unchanged),
+                Err(e) if e.code() == DXGI_ERROR_ACCESS_LOST => {
+                    // Display mode changed or UAC prompt - need to recreate
+                    self.needs_recreate = true;
+                    return Ok(Grab::Lost(e));
+                }
+                Err(e) => return Err(e.into()),
+            }
+
+            // Reset unchanged counter since we have a new frame
+            self.unchanged_count = 0;
+
+            // Only readable until the frame is released
+            let damage = self.frame_damage(&frame_info);
+            self.track_pointer(&frame_info);
+
+            let resource = resource.context("No resource")?;
+            let texture: ID3D11Texture2D = resource.cast()?;
+
+            // The desktop no longer matches the staging texture (mode switched without
+            // ACCESS_LOST); rebuild at the new size
+            let mut frame_desc = D3D11_TEXTURE2D_DESC::default();
+            texture.GetDesc(&mut frame_desc);
+            if frame_desc.Width != self.width || frame_desc.Height != self.height {
+                self.duplication.ReleaseFrame()?;
+                self.recreate_duplication()?;
+                return Ok(Grab::Resized);
+            }
+
+            // The copy needs a staging texture of the same format; anything we
+            // can't convert is an error, so Auto falls back to GDI
+            let format = match Self::surface_format(frame_desc.Format) {
+                Ok(format) => format,
+                Err(e) => {
+                    self.duplication.ReleaseFrame()?;
+                    return Err(e);
+                }
+            };
+            if format != self.format {
+                println!("[CAPTURE] Desktop surface format {:?} -> {:?}, recreating staging texture", self.format, format);
+                self.staging = Self::create_staging(&self.device, self.width, self.height, format)?;
+                self.format = format;
+            }
+
+            self.context.CopyResource(&self.staging, &texture);
+
+            let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+            self.context.Map(&self.staging, 0, D3D11_MAP_READ, 0, Some(&mut mapped))?;
+
+            let pitch = mapped.RowPitch as usize;
+            let data = std::slice::from_raw_parts(
+                mapped.pData as *const u8,
+                pitch * self.height as usize,
+            );
+
+            let rgb = self.surface_to_rgb(data, pitch);
+            self.context.Unmap(&self.staging, 0);
+            self.duplication.ReleaseFrame()?;
+            Ok(match rgb {
+                Some(rgb) => Grab::Frame(rgb, damage),
+                None => Grab::Malformed(pitch),
+            })
+        }
+
+        /// Pass on where the pointer is and its new shape, when the acquired
+        /// frame says they changed; frames never show the pointer themselves
+        unsafe fn track_pointer(&self, info: &DXGI_OUTDUPL_FRAME_INFO) {
+            if info.LastMouseUpdateTime != 0 {
+                let pointer = info.PointerPosition;
+                crate::cursor::moved(
+                    self.cursor_source,
+                    pointer.Visible.as_bool(),
+                    pointer.Position.x + self.cursor_offset.0,
+                    pointer.Position.y + self.cursor_offset.1,
+                );
+            }
+            if info.PointerShapeBufferSize == 0 {
+                return;
+            }
+
+            let mut buffer = vec![0u8; info.PointerShapeBufferSize as usize];
+            let mut used = 0u32;
+            let mut shape = DXGI_OUTDUPL_POINTER_SHAPE_INFO::default();
+            if let Err(e) = self.duplication.GetFramePointerShape(
+                buffer.len() as u32,
+                buffer.as_mut_ptr() as *mut _,
+                &mut used,
+                &mut shape,
+            ) {
+                println!("[CAPTURE] Failed to read the cursor shape: {}", e);
+                return;
+            }
+
+            let hotspot = (shape.HotSpot.x.max(0) as u32, shape.HotSpot.y.max(0) as u32);
+            let pitch = shape.Pitch as usize;
+            let converted = match shape.Type as i32 {
+                t if t == DXGI_OUTDUPL_POINTER_SHAPE_TYPE_MONOCHROME.0 => {
+                    crate::cursor::Shape::from_monochrome(shape.Width, shape.Height / 2, pitch, &buffer, hotspot)
+                }
+                t if t == DXGI_OUTDUPL_POINTER_SHAPE_TYPE_COLOR.0 => {
+                    crate::cursor::Shape::from_bgra(shape.Width, shape.Height, pitch, &buffer, hotspot)
+                }
+                t if t == DXGI_OUTDUPL_POINTER_SHAPE_TYPE_MASKED_COLOR.0 => {
+                    crate::cursor::Shape::from_masked(shape.Width, shape.Height, pitch, &buffer, hotspot)
+                }
+                _ => None,
+            };
+            match converted {
+                Some(cursor) => crate::cursor::reshaped(&cursor),
+                None => println!("[CAPTURE] Ignoring {}x{} cursor of type {}", shape.Width, shape.Height, shape.Type),
+            }
+        }
+
+        /// Areas the acquired frame changed, from the duplication's metadata:
+        /// the destination and source of moved areas and the dirty rects
+        /// None when unknown, so everything may have changed
+        unsafe fn frame_damage(&self, info: &DXGI_OUTDUPL_FRAME_INFO) -> Option<Vec<tiles::Rect>> {
+            if info.TotalMetadataBufferSize == 0 {
+                // Without a new image only the pointer moved, which frames do not show
+                return (info.LastPresentTime == 0).then(Vec::new);
+            }
+            let capacity = info.TotalMetadataBufferSize as usize;
+            let mut damage = Vec::new();
+            let rect = |left: i32, top: i32, right: i32, bottom: i32| {
+                tiles::Rect::clipped(left as i64, top as i64, right as i64, bottom as i64, self.width, self.height)
+            };
+
+            let mut moves = vec![DXGI_OUTDUPL_MOVE_RECT::default(); capacity / std::mem::size_of::<DXGI_OUTDUPL_MOVE_RECT>() + 1];
+            let mut used = 0u32;
+            self.duplication.GetFrameMoveRects(
+                (moves.len() * std::mem::size_of::<DXGI_OUTDUPL_MOVE_RECT>()) as u32,
+                moves.as_mut_ptr(),
+                &mut used,
+            ).ok()?;
+            moves.truncate(used as usize / std::mem::size_of::<DXGI_OUTDUPL_MOVE_RECT>());
+            for moved in &moves {
+                let dest = moved.DestinationRect;
+                let (width, height) = (dest.right - dest.left, dest.bottom - dest.top);
+                let source = moved.SourcePoint;
+                damage.extend(rect(dest.left, dest.top, dest.right, dest.bottom));
+                damage.extend(rect(source.x, source.y, source.x + width, source.y + height));
+            }
+
+            let mut dirty = vec![RECT::default(); capacity / std::mem::size_of::<RECT>() + 1];
+            self.duplication.GetFrameDirtyRects(
+                (dirty.len() * std::mem::size_of::<RECT>()) as u32,
+                dirty.as_mut_ptr(),
+                &mut used,
+            ).ok()?;
+            dirty.truncate(used as usize / std::mem::size_of::<RECT>());
+            damage.extend(dirty.iter().filter_map(|r| rect(r.left, r.top, r.right, r.bottom)));
+            Some(damage)
+        }
+
+        /// What changed since frame `since` as tiles, when the damage log still
+        /// covers it and the change is small enough; otherwise the whole frame
+        unsafe fn capture_delta(&mut self, since: Option<u64>) -> Result<Delta> {
+            let started = Instant::now();
+
+            if self.needs_recreate {
+                if let Err(e) = self.recreate_duplication() {
+                    return Err(e.context(DuplicationLost));
+                }
+            }
+
+            match self.grab(100)? {
+                Grab::Frame(rgb, damage) => {
+                    self.damage.record(damage);
+                    self.rgb = Some(rgb);
+                    // The cached keyframe is behind now
+                    self.last_frame = None;
+                }
+                Grab::Unchanged => {}
+                Grab::Lost(e) => return Err(anyhow::Error::from(e).context(DuplicationLost)),
+                // Whole again once the duplication is back
+                Grab::Resized => return Ok(Delta::Unchanged),
+                Grab::Malformed(pitch) => {
+                    println!("[CAPTURE] Ignoring {}x{} frame with row pitch {}", self.width, self.height, pitch);
+                    return Ok(Delta::Unchanged);
+                }
+            }
+
+            if let Some(moved) = blackout_damage(&mut self.blackout, self.width, self.height) {
+                self.damage.record(Some(moved));
+            }
+            let Some(rgb) = &self.rgb else {
+                return Ok(Delta::Unchanged);
+            };
+            let (width, height, seq) = (self.width, self.height, self.damage.seq());
+
+            let planned = since
+                .and_then(|since| self.damage.since(since))
+                .and_then(|damage| tiles::plan(&damage, width, height));
+            match planned {
+                Some(rects) if rects.is_empty() => Ok(Delta::Unchanged),
+                Some(rects) => {
+                    let quality = self.quality_override
+                        .unwrap_or_else(|| JPEG_QUALITY.load(Ordering::Relaxed));
+                    let tiles = encode_tiles(rgb, width, &rects, quality)?;
+                    record_frame(started);
+                    Ok(Delta::Tiles { width, height, seq, tiles })
+                }
+                None => {
+                    let data = match &self.last_frame {
+                        Some(frame) => frame.clone(),
+                        None => {
+                            let jpeg = self.encode_frame(rgb)?;
+                            self.last_frame = Some(jpeg.clone());
+                            record_frame(started);
+                            jpeg
+                        }
+                    };
+                    Ok(Delta::Full { width, height, seq: Some(seq), data })
+                }
+            }
+        }
+
+        /// Encode whatever is currently in the staging texture
+        unsafe fn encode_staging(&mut self) -> Result<(u32, u32, Vec<u8>)> {
+            let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
+            self.context.Map(&self.staging, 0, D3D11_MAP_READ, 0, Some(&mut mapped))?;
+
+            let pitch = mapped.RowPitch as usize;
+            let data = std::slice::from_raw_parts(
+                mapped.pData as *const u8,
+                pitch * self.height as usize,
+            );
+
+            let rgb = self.surface_to_rgb(data, pitch);
+            self.context.Unmap(&self.staging, 0);
+            let Some(rgb) = rgb else {
+                return self.malformed_frame(pitch);
+            };
+
+            let jpeg = self.encode_frame(&rgb)?;
+            Ok((self.width, self.height, jpeg))
+        }
+
+        fn surface_to_rgb(&self, data: &[u8], pitch: usize) -> Option<Vec<u8>> {
+            surface_rows_to_rgb(data, pitch, self.width as usize, self.height as usize, self.format)
+        }
+
+        /// The mapped texture cannot hold the frame it claims; keep showing the last good one
+        fn malformed_frame(&self, pitch: usize) -> Result<(u32, u32, Vec<u8>)> {
+            println!("[CAPTURE] Ignoring {}x{} frame with row pitch {}", self.width, self.height, pitch);
+            match &self.last_frame {
+                Some(frame) => Ok((self.width, self.height, frame.clone())),
+                None => anyhow::bail!("Captured frame does not match the display size"),
+            }
+        }
+
+        fn encode_frame(&self, rgb: &[u8]) -> Result<Vec<u8>> {
+            let quality = self.quality_override
+                .unwrap_or_else(|| JPEG_QUALITY.load(Ordering::Relaxed));
+            encode_frame(rgb, self.width, self.height, quality)
+        }
+    }
+
+    /// Every output duplicated on its own and stitched into one frame of the
+    /// virtual desktop
+    struct DxgiDesktop {
+        /// One duplication per monitor, in `layout` order
+        outputs: Vec<DxgiCapture>,
+        /// Monitors as they were when the duplications were made
+        layout: Vec<MonitorInfo>,
+        /// Latest RGB frame of each output; None until it showed one
+        frames: Vec<Option<Vec<u8>>>,
+        bounds: (i32, i32, u32, u32),
+        last_frame: Option<Vec<u8>>,
+        unchanged_count: u32,
+        quality_override: Option<u8>,
+    }
+
+    impl DxgiDesktop {
+        unsafe fn new() -> Result<Self> {
+            let layout = monitor_list();
+            let mut outputs = Vec::with_capacity(layout.len());
+            for monitor in &layout {
+                outputs.push(DxgiCapture::init(Some(monitor.clone()))?);
+            }
+            let bounds = desktop_bounds(outputs.iter().map(|o| (o.origin.0, o.origin.1, o.width, o.height)))
+                .context("No DXGI output")?;
+            println!("[CAPTURE] Stitching {} outputs into {}x{}", outputs.len(), bounds.2, bounds.3);
+            for (source, output) in outputs.iter_mut().enumerate() {
+                output.cursor_source = source;
+                output.cursor_offset = (output.origin.0 - bounds.0, output.origin.1 - bounds.1);
+            }
 
             Ok(Self {
-                device,
-                context,
-                duplication,
-                staging,
-                width,
-                height,
+                frames: vec![None; outputs.len()],
+                outputs,
+                layout,
+                bounds,
                 last_frame: None,
                 unchanged_count: 0,
-                needs_recreate: false,
+                quality_override: None,
+            })
+        }
+
+        /// Duplicate the outputs afresh after the monitor layout changed
+        unsafe fn rebuild(&mut self) -> Result<()> {
+            // A second duplication of an output fails while the first is alive
+            self.outputs.clear();
+            *self = Self::new()?;
+            Ok(())
+        }
+
+        /// Nothing to follow when already stitching, unless monitors changed
+        unsafe fn retarget(&mut self) -> Result<()> {
+            if monitor_list() != self.layout {
+                self.rebuild()?;
+            }
+            Ok(())
+        }
+
+        fn capture(&mut self) -> Result<(u32, u32, Vec<u8>)> {
+            unsafe { self.capture_internal() }
+        }
+
+        fn capture_keyframe(&mut self, quality: u8) -> Result<(u32, u32, Vec<u8>)> {
+            self.quality_override = Some(quality.clamp(1, 100));
+            let result = unsafe { self.capture_internal() };
+            self.quality_override = None;
+            result
+        }
+
+        unsafe fn capture_internal(&mut self) -> Result<(u32, u32, Vec<u8>)> {
+            let started = Instant::now();
+            let (_, _, width, height) = self.bounds;
+
+            // Each output waits its share, so an idle desktop still answers in ~100ms
+            let timeout = 100 / self.outputs.len().max(1) as u32;
+            let mut changed = false;
+            let mut relayout = false;
+            for (output, frame) in self.outputs.iter_mut().zip(self.frames.iter_mut()) {
+                if output.needs_recreate {
+                    if let Err(e) = output.recreate_duplication() {
+                        // Usually a monitor was unplugged or rearranged
+                        println!("[CAPTURE] Failed to recreate duplication: {}", e);
+                        relayout = true;
+                        continue;
+                    }
+                }
+                match output.grab(timeout)? {
+                    Grab::Frame(rgb, _) => {
+                        *frame = Some(rgb);
+                        changed = true;
+                    }
+                    Grab::Resized => {
+                        *frame = None;
+                        relayout = true;
+                    }
+                    Grab::Malformed(pitch) => {
+                        println!("[CAPTURE] Ignoring {}x{} frame with row pitch {}", output.width, output.height, pitch);
+                    }
+                    Grab::Unchanged | Grab::Lost(_) => {}
+                }
+            }
+
+            if relayout && monitor_list() != self.layout {
+                self.rebuild()?;
+                return Ok((self.bounds.2, self.bounds.3, Vec::new()));
+            }
+
+            if !changed && (self.quality_override.is_none() || self.last_frame.is_none()) {
+                // No new frame - return cached frame if available
+                if let Some(ref frame) = self.last_frame {
+                    self.unchanged_count += 1;
+                    if self.unchanged_count <= 10 {
+                        return Ok((width, height, frame.clone()));
+                    }
+                }
+                return Ok((width, height, Vec::new()));
+            }
+            self.unchanged_count = 0;
+
+            let rgb = stitch(self.bounds, self.outputs.iter().zip(&self.frames).filter_map(|(output, frame)| {
+                Some(Tile {
+                    left: output.origin.0,
+                    top: output.origin.1,
+                    width: output.width,
+                    height: output.height,
+                    rgb: frame.as_deref()?,
+                })
+            }));
+            let quality = self.quality_override
+                .unwrap_or_else(|| JPEG_QUALITY.load(Ordering::Relaxed));
+            let jpeg = encode_frame(&rgb, width, height, quality)?;
+
+            self.last_frame = Some(jpeg.clone());
+            record_frame(started);
+
+            Ok((width, height, jpeg))
+        }
+    }
+
+    /// GDI BitBlt backend, used when desktop duplication is unavailable
+    /// (RDP sessions, older GPUs, some virtual machines)
+    struct GdiCapture {
+        width: u32,
+        height: u32,
+        quality_override: Option<u8>,
+    }
+
+    impl GdiCapture {
+        fn new() -> Result<Self> {
+            let (width, height) = unsafe { Self::screen_size()? };
+            Ok(Self {
+                width,
+                height,
+                quality_override: None,
             })
         }
 
-        /// Recreate duplication output (needed after display changes, UAC prompts, etc.)
-        unsafe fn recreate_duplication(&mut self) -> Result<()> {
-            let dxgi_device: IDXGIDevice = self.device.cast()?;
-            let adapter: IDXGIAdapter = dxgi_device.GetAdapter()?;
-            let output: IDXGIOutput = adapter.EnumOutputs(0)?;
-            let output1: IDXGIOutput1 = output.cast()?;
+        unsafe fn screen_size() -> Result<(u32, u32)> {
+            let width = GetSystemMetrics(SM_CXSCREEN);
+            let height = GetSystemMetrics(SM_CYSCREEN);
+            if width <= 0 || height <= 0 {
+                anyhow::bail!("Failed to query screen size");
+            }
+            Ok((width as u32, height as u32))
+        }
+
+        fn capture(&mut self) -> Result<(u32, u32, Vec<u8>)> {
+            unsafe { self.capture_gdi() }
+        }
+
+        fn capture_keyframe(&mut self, quality: u8) -> Result<(u32, u32, Vec<u8>)> {
+            self.quality_override = Some(quality.clamp(1, 100));
+            let result = unsafe { self.capture_gdi() };
+            self.quality_override = None;
+            result
+        }
+
+        unsafe fn capture_gdi(&mut self) -> Result<(u32, u32, Vec<u8>)> {
+            let started = Instant::now();
+
+            // GDI has no change notification, so pick up resolution and monitor
+            // changes per frame; the screen DC spans the whole virtual desktop
+            let (width, height, rgb) = if captures_all_monitors() {
+                let monitors = monitor_list();
+                let bounds = desktop_bounds(monitors.iter().map(|m| (m.left, m.top, m.width, m.height)))
+                    .context("No monitors to capture")?;
+                let mut grabbed = Vec::with_capacity(monitors.len());
+                for monitor in &monitors {
+                    grabbed.push(Self::blit(monitor.left, monitor.top, monitor.width, monitor.height)?);
+                }
+                // Blitting each monitor on its own leaves the gaps between them black
+                let rgb = stitch(bounds, monitors.iter().zip(&grabbed).map(|(monitor, rgb)| Tile {
+                    left: monitor.left,
+                    top: monitor.top,
+                    width: monitor.width,
+                    height: monitor.height,
+                    rgb,
+                }));
+                (bounds.2, bounds.3, rgb)
+            } else {
+                let (left, top, width, height) = match target_monitor() {
+                    Some(monitor) => (monitor.left, monitor.top, monitor.width, monitor.height),
+                    None => {
+                        let (width, height) = Self::screen_size()?;
+                        (0, 0, width, height)
+                    }
+                };
+                (width, height, Self::blit(left, top, width, height)?)
+            };
+            self.width = width;
+            self.height = height;
+
+            let quality = self.quality_override
+                .unwrap_or_else(|| JPEG_QUALITY.load(Ordering::Relaxed));
+            let jpeg = encode_frame(&rgb, width, height, quality)?;
+
+            record_frame(started);
+
+            Ok((width, height, jpeg))
+        }
+
+        /// Copy part of the virtual desktop as RGB
+        unsafe fn blit(left: i32, top: i32, width: u32, height: u32) -> Result<Vec<u8>> {
+            let screen_dc = GetDC(None);
+            if screen_dc.is_invalid() {
+                anyhow::bail!("Failed to get screen DC");
+            }
+
+            let mem_dc = CreateCompatibleDC(screen_dc);
+            let bitmap = CreateCompatibleBitmap(screen_dc, width as i32, height as i32);
+            let previous = SelectObject(mem_dc, bitmap);
+
+            let blit = BitBlt(
+                mem_dc, 0, 0, width as i32, height as i32,
+                screen_dc, left, top, SRCCOPY,
+            );
+
+            let mut pixels = vec![0u8; (width * height * 4) as usize];
+            let mut info = BITMAPINFO {
+                bmiHeader: BITMAPINFOHEADER {
+                    biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+                    biWidth: width as i32,
+                    // Negative height gives top-down rows, matching DXGI
+                    biHeight: -(height as i32),
+                    biPlanes: 1,
+                    biBitCount: 32,
+                    biCompression: BI_RGB.0,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+
+            let lines = if blit.is_ok() {
+                GetDIBits(
+                    mem_dc,
+                    bitmap,
+                    0,
+                    height,
+                    Some(pixels.as_mut_ptr() as *mut _),
+                    &mut info,
+                    DIB_RGB_COLORS,
+                )
+            } else {
+                0
+            };
+
+            SelectObject(mem_dc, previous);
+            let _ = DeleteObject(bitmap);
+            let _ = DeleteDC(mem_dc);
+            ReleaseDC(None, screen_dc);
+
+            blit.context("BitBlt failed")?;
+            if lines == 0 {
+                anyhow::bail!("GetDIBits failed");
+            }
+
+            bgr_rows_to_rgb(&pixels, width as usize * 4, width as usize, height as usize, 4)
+                .context("GetDIBits returned a short bitmap")
+        }
+    }
+
+    enum Backend {
+        Dxgi(DxgiCapture),
+        DxgiDesktop(DxgiDesktop),
+        Gdi(GdiCapture),
+    }
+
+    /// Windows screen capture - DXGI Desktop Duplication with a GDI fallback
+    pub struct ScreenCapture {
+        backend: Backend,
+        preference: CaptureBackend,
+        recovery: DxgiRecovery,
+    }
+
+    impl ScreenCapture {
+        pub fn new() -> Result<Self> {
+            // Physical pixel sizes, matching the coordinates InputInjector expects
+            crate::input::enable_dpi_awareness();
+
+            let preference = get_backend();
+            let backend = match preference {
+                CaptureBackend::Dxgi => Self::dxgi()?,
+                CaptureBackend::Gdi => Backend::Gdi(GdiCapture::new()?),
+                CaptureBackend::Auto => match Self::dxgi() {
+                    Ok(dxgi) => dxgi,
+                    Err(e) => {
+                        println!("[CAPTURE] DXGI duplication unavailable ({}), falling back to GDI", e);
+                        Backend::Gdi(GdiCapture::new()?)
+                    }
+                },
+            };
+
+            let capture = Self { backend, preference, recovery: DxgiRecovery::new() };
+            println!("[CAPTURE] Windows capture backend: {}", capture.backend_name());
+            Ok(capture)
+        }
+
+        /// Monitors that can be captured, in the order `sources` lists them
+        pub fn list_monitors() -> Vec<MonitorInfo> {
+            monitor_list()
+        }
+
+        /// DXGI backend for the capture mode: one output, or all of them stitched
+        fn dxgi() -> Result<Backend> {
+            unsafe {
+                if captures_all_monitors() {
+                    DxgiDesktop::new().map(Backend::DxgiDesktop)
+                } else {
+                    DxgiCapture::new().map(Backend::Dxgi)
+                }
+            }
+        }
+
+        /// Capture one monitor or all of them from now on, also in captures created later
+        /// DXGI duplicates the chosen outputs instead; with the Auto backend
+        /// monitors DXGI cannot duplicate (another GPU) are captured with GDI.
+        pub fn set_capture_mode(&mut self, mode: CaptureMode) -> Result<()> {
+            switch_mode(mode, Self::list_monitors(), || self.retarget())
+        }
 
-            self.duplication = output1.DuplicateOutput(&self.device)?;
-            self.needs_recreate = false;
-            Ok(())
+        fn retarget(&mut self) -> Result<()> {
+            let all = captures_all_monitors();
+            let result = match &mut self.backend {
+                Backend::Dxgi(dxgi) if !all => unsafe { dxgi.retarget() },
+                Backend::DxgiDesktop(desktop) if all => unsafe { desktop.retarget() },
+                // Reads the mode every frame
+                Backend::Gdi(_) if self.preference != CaptureBackend::Dxgi => return Ok(()),
+                _ => {
+                    // Only one duplication per output: release the current ones first
+                    self.backend = Backend::Gdi(GdiCapture::new()?);
+                    Self::dxgi().map(|dxgi| self.backend = dxgi)
+                }
+            };
+
+            match result {
+                Err(e) if self.preference == CaptureBackend::Auto => self.switch_to_gdi(e),
+                result => result,
+            }
         }
 
-        pub fn capture(&mut self) -> Result<(u32, u32, Vec<u8>)> {
-            unsafe { self.capture_internal() }
+        /// Name of the backend currently in use
+        pub fn backend_name(&self) -> &'static str {
+            match self.backend {
+                Backend::Dxgi(_) | Backend::DxgiDesktop(_) => "dxgi",
+                Backend::Gdi(_) => "gdi",
+            }
         }
 
-        unsafe fn capture_internal(&mut self) -> Result<(u32, u32, Vec<u8>)> {
-            // Recreate duplication if needed
-            if self.needs_recreate {
-                if let Err(e) = self.recreate_duplication() {
-                    println!("[CAPTURE] Failed to recreate duplication: {}", e);
-                    // Return last frame if available
-                    if let Some(ref frame) = self.last_frame {
-                        return Ok((self.width, self.height, frame.clone()));
+        pub fn capture(&mut self) -> Result<(u32, u32, Vec<u8>)> {
+            let result = match &mut self.backend {
+                Backend::Gdi(gdi) => return gdi.capture(),
+                _ if !self.recovery.due(Instant::now()) => return Ok(self.nothing_new()),
+                Backend::Dxgi(dxgi) => dxgi.capture(),
+                Backend::DxgiDesktop(desktop) => desktop.capture(),
+            };
+
+            match result {
+                Ok(frame) => {
+                    self.recovery.recovered();
+                    Ok(frame)
+                }
+                Err(e) => {
+                    if self.dxgi_failed(e)? {
+                        self.capture()
+                    } else {
+                        Ok(self.nothing_new())
                     }
-                    return Err(e);
                 }
             }
+        }
 
-            let mut frame_info = DXGI_OUTDUPL_FRAME_INFO::default();
-            let mut resource: Option<IDXGIResource> = None;
+        /// Capture a fresh keyframe at the given quality, bypassing the frame cache
+        pub fn capture_keyframe(&mut self, quality: u8) -> Result<(u32, u32, Vec<u8>)> {
+            let result = match &mut self.backend {
+                Backend::Gdi(gdi) => return gdi.capture_keyframe(quality),
+                _ if !self.recovery.due(Instant::now()) => return Ok(self.nothing_new()),
+                Backend::Dxgi(dxgi) => dxgi.capture_keyframe(quality),
+                Backend::DxgiDesktop(desktop) => desktop.capture_keyframe(quality),
+            };
 
-            match self.duplication.AcquireNextFrame(100, &mut frame_info, &mut resource) {
-                Ok(()) => {}
-                Err(e) if e.code() == DXGI_ERROR_WAIT_TIMEOUT => {
-                    // No new frame - return cached frame if available
-                    if let Some(ref frame) = self.last_frame {
-                        self.unchanged_count += 1;
-                        if self.unchanged_count <= 10 {
-                            return Ok((self.width, self.height, frame.clone()));
-                        }
-                    }
-                    return Ok((self.width, self.height, Vec::new()));
+            match result {
+                Ok(frame) => {
+                    self.recovery.recovered();
+                    Ok(frame)
                 }
-                Err(e) if e.code() == DXGI_ERROR_ACCESS_LOST => {
-                    // Display mode changed or UAC prompt - need to recreate
-                    self.needs_recreate = true;
-                    if let Some(ref frame) = self.last_frame {
-                        return Ok((self.width, self.height, frame.clone()));
+                Err(e) => {
+                    if self.dxgi_failed(e)? {
+                        self.capture_keyframe(quality)
+                    } else {
+                        Ok(self.nothing_new())
                     }
-                    return Err(e.into());
                 }
-                Err(e) => return Err(e.into()),
             }
-
-            // Reset unchanged counter since we have a new frame
-            self.unchanged_count = 0;
-
-            let resource = resource.context("No resource")?;
-            let texture: ID3D11Texture2D = resource.cast()?;
-
-            self.context.CopyResource(&self.staging, &texture);
-
-            let mut mapped = D3D11_MAPPED_SUBRESOURCE::default();
-            self.context.Map(&self.staging, 0, D3D11_MAP_READ, 0, Some(&mut mapped))?;
-
-            let pitch = mapped.RowPitch as usize;
-            let data = std::slice::from_raw_parts(
-                mapped.pData as *const u8,
-                pitch * self.height as usize,
-            );
-
-            // Convert BGRA to RGB and encode as JPEG with adaptive quality
-            let rgb = self.bgra_to_rgb(data, pitch);
-            let jpeg = self.encode_jpeg(&rgb)?;
-
-            self.context.Unmap(&self.staging, 0);
-            self.duplication.ReleaseFrame()?;
-
-            // Cache the frame for reuse
-            self.last_frame = Some(jpeg.clone());
-
-            // Update frame counter
-            FRAME_COUNT.fetch_add(1, Ordering::Relaxed);
-
-            Ok((self.width, self.height, jpeg))
         }
 
-        fn bgra_to_rgb(&self, bgra: &[u8], pitch: usize) -> Vec<u8> {
-            let mut rgb = Vec::with_capacity((self.width * self.height * 3) as usize);
-            for y in 0..self.height as usize {
-                for x in 0..self.width as usize {
-                    let i = y * pitch + x * 4;
-                    rgb.push(bgra[i + 2]); // R
-                    rgb.push(bgra[i + 1]); // G
-                    rgb.push(bgra[i]);     // B
+        /// What changed since frame `since` as tiles; only duplicating a single
+        /// output keeps a damage log, other backends send whole frames
+        pub fn capture_delta(&mut self, since: Option<u64>) -> Result<Delta> {
+            let result = match &mut self.backend {
+                Backend::Dxgi(_) if !self.recovery.due(Instant::now()) => return Ok(Delta::Unchanged),
+                Backend::Dxgi(dxgi) => unsafe { dxgi.capture_delta(since) },
+                _ => return self.capture().map(Delta::full),
+            };
+
+            match result {
+                Ok(delta) => {
+                    self.recovery.recovered();
+                    Ok(delta)
+                }
+                Err(e) => {
+                    if self.dxgi_failed(e)? {
+                        self.capture_delta(since)
+                    } else {
+                        Ok(Delta::Unchanged)
+                    }
                 }
             }
-            rgb
         }
 
-        fn encode_jpeg(&self, rgb: &[u8]) -> Result<Vec<u8>> {
-            use image::codecs::jpeg::JpegEncoder;
-            use image::ColorType;
-
-            let quality = JPEG_QUALITY.load(Ordering::Relaxed);
+        /// An empty frame at the duplicated size: nothing new while DXGI recovers
+        fn nothing_new(&self) -> (u32, u32, Vec<u8>) {
+            match &self.backend {
+                Backend::Dxgi(dxgi) => (dxgi.width, dxgi.height, Vec::new()),
+                Backend::DxgiDesktop(desktop) => (desktop.bounds.2, desktop.bounds.3, Vec::new()),
+                Backend::Gdi(_) => (0, 0, Vec::new()),
+            }
+        }
 
-            let mut jpeg = Vec::new();
-            {
-                let mut encoder = JpegEncoder::new_with_quality(&mut jpeg, quality);
-                encoder.encode(
-                    rgb,
-                    self.width,
-                    self.height,
-                    ColorType::Rgb8,
-                )?;
+        /// Handle a DXGI capture error: a lost duplication is retried with
+        /// backoff (false); anything else, or a loss that outlasts the retries,
+        /// moves an Auto capture to GDI (true, capture again) and fails otherwise
+        fn dxgi_failed(&mut self, error: anyhow::Error) -> Result<bool> {
+            if error.downcast_ref::<DuplicationLost>().is_some() && self.recovery.failed(Instant::now()) {
+                println!("[CAPTURE] {:#}, retrying DXGI (attempt {})", error, self.recovery.backoff.attempts());
+                return Ok(false);
             }
+            if self.preference != CaptureBackend::Auto {
+                return Err(error);
+            }
+            self.switch_to_gdi(error)?;
+            Ok(true)
+        }
 
-            Ok(jpeg)
+        /// Permanently switch this capture to GDI after a DXGI failure that
+        /// retrying did not fix
+        fn switch_to_gdi(&mut self, reason: anyhow::Error) -> Result<()> {
+            println!("[CAPTURE] DXGI capture failed ({:#}), switching to GDI", reason);
+            self.backend = Backend::Gdi(GdiCapture::new()?);
+            self.recovery.recovered();
+            Ok(())
         }
     }
+
 }
 
 #[cfg(windows)]
@@ -252,16 +2005,27 @@ mod macos_capture {
     use core_graphics::display::{CGDisplay, CGMainDisplayID};
     use std::sync::atomic::Ordering;
 
+    #[link(name = "CoreGraphics", kind = "framework")]
+    extern "C" {
+        fn CGPreflightScreenCaptureAccess() -> bool;
+    }
+
     pub struct ScreenCapture {
         display_id: u32,
         width: u32,
         height: u32,
         last_frame: Option<Vec<u8>>,
+        quality_override: Option<u8>,
     }
 
     impl ScreenCapture {
         pub fn new() -> Result<Self> {
-            let display_id = unsafe { CGMainDisplayID() };
+            // Without the permission CoreGraphics returns blank or no images
+            if !unsafe { CGPreflightScreenCaptureAccess() } {
+                anyhow::bail!("Screen Recording permission not granted");
+            }
+
+            let display_id = Self::target_display();
             let display = CGDisplay::new(display_id);
 
             let width = display.pixels_wide() as u32;
@@ -274,20 +2038,112 @@ mod macos_capture {
                 width,
                 height,
                 last_frame: None,
+                quality_override: None,
+            })
+        }
+
+        /// Active displays in CoreGraphics order; positions are in global points
+        pub fn list_monitors() -> Vec<MonitorInfo> {
+            CGDisplay::active_displays()
+                .unwrap_or_default()
+                .into_iter()
+                .enumerate()
+                .map(|(index, id)| {
+                    let display = CGDisplay::new(id);
+                    let bounds = display.bounds();
+                    MonitorInfo {
+                        index: index as u32,
+                        name: format!("Display {}", index + 1),
+                        left: bounds.origin.x as i32,
+                        top: bounds.origin.y as i32,
+                        width: display.pixels_wide() as u32,
+                        height: display.pixels_high() as u32,
+                        primary: display.is_main(),
+                    }
+                })
+                .collect()
+        }
+
+        /// Capture one monitor or all of them from now on, also in captures created later
+        pub fn set_capture_mode(&mut self, mode: CaptureMode) -> Result<()> {
+            switch_mode(mode, Self::list_monitors(), || {
+                // The display is looked up again on every capture
+                self.last_frame = None;
+                Ok(())
             })
         }
 
+        /// The selected display, or the main one
+        fn target_display() -> u32 {
+            target_monitor()
+                .and_then(|monitor| {
+                    let displays = CGDisplay::active_displays().ok()?;
+                    displays.get(monitor.index as usize).copied()
+                })
+                .unwrap_or_else(|| unsafe { CGMainDisplayID() })
+        }
+
+        /// Capture a fresh keyframe at the given quality
+        pub fn capture_keyframe(&mut self, quality: u8) -> Result<(u32, u32, Vec<u8>)> {
+            self.quality_override = Some(quality.clamp(1, 100));
+            let result = self.capture();
+            self.quality_override = None;
+            result
+        }
+
         pub fn capture(&mut self) -> Result<(u32, u32, Vec<u8>)> {
+            let started = Instant::now();
+
+            // The main display changes when monitors are unplugged or rearranged,
+            // and the viewer may pick another one
+            let display_id = Self::target_display();
+            if display_id != self.display_id {
+                println!("[CAPTURE] Captured display changed, switching capture target");
+                self.display_id = display_id;
+                self.last_frame = None;
+            }
+
+            let grabbed = if captures_all_monitors() {
+                self.grab_stitched()
+            } else {
+                self.grab_display(self.display_id)
+            };
+            let (width, height, rgb) = match grabbed {
+                Ok(frame) => frame,
+                Err(e) => {
+                    if let Some(ref frame) = self.last_frame {
+                        return Ok((self.width, self.height, frame.clone()));
+                    }
+                    return Err(e);
+                }
+            };
+
+            // Encode as JPEG
+            let jpeg = self.encode_frame(&rgb, width, height)?;
+
+            // Cache frame; the image carries the current size, so resolution
+            // switches are picked up here
+            if (width, height) != (self.width, self.height) {
+                println!("[CAPTURE] macOS display resized: {}x{}", width, height);
+            }
+            self.last_frame = Some(jpeg.clone());
+            self.width = width;
+            self.height = height;
+
+            // Update frame counter and encode timing
+            record_frame(started);
+
+            Ok((self.width, self.height, jpeg))
+        }
+
+        /// Read one display as RGB
+        fn grab_display(&self, display_id: u32) -> Result<(u32, u32, Vec<u8>)> {
             use core_graphics::display::CGDisplayCreateImage;
 
             // Create image from display
-            let image = unsafe { CGDisplayCreateImage(self.display_id) };
+            let image = unsafe { CGDisplayCreateImage(display_id) };
 
             if image.is_null() {
-                // Return last frame if capture failed
-                if let Some(ref frame) = self.last_frame {
-                    return Ok((self.width, self.height, frame.clone()));
-                }
                 anyhow::bail!("Failed to capture screen - check Screen Recording permission");
             }
 
@@ -295,6 +2151,7 @@ mod macos_capture {
             let width: usize;
             let height: usize;
             let bytes_per_row: usize;
+            let bits_per_pixel: usize;
             let pixel_data: Vec<u8>;
 
             unsafe {
@@ -305,66 +2162,67 @@ mod macos_capture {
                 width = cg_image.width();
                 height = cg_image.height();
                 bytes_per_row = cg_image.bytes_per_row();
+                bits_per_pixel = cg_image.bits_per_pixel();
 
                 // Get pixel data from CGImage
-                if let Some(data_provider) = cg_image.data_provider() {
-                    let data = data_provider.copy_data();
-                    pixel_data = data.bytes().to_vec();
-                } else {
-                    if let Some(ref frame) = self.last_frame {
-                        return Ok((self.width, self.height, frame.clone()));
-                    }
-                    anyhow::bail!("No data provider");
-                }
+                let data_provider = cg_image.data_provider().context("No data provider")?;
+                pixel_data = data_provider.copy_data().bytes().to_vec();
             }
 
-            // Convert BGRA/RGBA to RGB
-            let rgb = self.convert_to_rgb(&pixel_data, bytes_per_row, width, height);
-
-            // Encode as JPEG
-            let jpeg = self.encode_jpeg(&rgb, width as u32, height as u32)?;
-
-            // Cache frame
-            self.last_frame = Some(jpeg.clone());
-            self.width = width as u32;
-            self.height = height as u32;
-
-            // Update frame counter
-            FRAME_COUNT.fetch_add(1, Ordering::Relaxed);
-
-            Ok((self.width, self.height, jpeg))
+            // Convert BGRA to RGB; an image that doesn't match its own layout keeps the last frame
+            let Some(rgb) = self.convert_to_rgb(&pixel_data, bytes_per_row, bits_per_pixel, width, height) else {
+                println!(
+                    "[CAPTURE] Ignoring {}x{} image: {} bits per pixel, {} bytes per row, {} bytes",
+                    width, height, bits_per_pixel, bytes_per_row, pixel_data.len()
+                );
+                anyhow::bail!("Unsupported display image layout");
+            };
+            Ok((width as u32, height as u32, rgb))
         }
 
-        fn convert_to_rgb(&self, pixels: &[u8], bytes_per_row: usize, width: usize, height: usize) -> Vec<u8> {
-            let mut rgb = Vec::with_capacity(width * height * 3);
-
-            for y in 0..height {
-                for x in 0..width {
-                    let i = y * bytes_per_row + x * 4;
-                    if i + 3 < pixels.len() {
-                        // macOS uses BGRA format
-                        rgb.push(pixels[i + 2]); // R
-                        rgb.push(pixels[i + 1]); // G
-                        rgb.push(pixels[i]);     // B
-                    }
-                }
+        /// Read every active display and place them by their global position;
+        /// what lies between displays of different sizes is black
+        fn grab_stitched(&self) -> Result<(u32, u32, Vec<u8>)> {
+            let displays = CGDisplay::active_displays()
+                .map_err(|e| anyhow::anyhow!("Failed to list displays: {}", e))?;
+            let mut grabbed = Vec::with_capacity(displays.len());
+            for id in displays {
+                let origin = CGDisplay::new(id).bounds().origin;
+                let (width, height, rgb) = self.grab_display(id)?;
+                grabbed.push((origin.x as i32, origin.y as i32, width, height, rgb));
             }
-            rgb
-        }
-
-        fn encode_jpeg(&self, rgb: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
-            use image::codecs::jpeg::JpegEncoder;
-            use image::ColorType;
 
-            let quality = JPEG_QUALITY.load(Ordering::Relaxed);
+            let bounds = desktop_bounds(grabbed.iter().map(|(left, top, width, height, _)| (*left, *top, *width, *height)))
+                .context("No active displays")?;
+            let rgb = stitch(bounds, grabbed.iter().map(|(left, top, width, height, rgb)| Tile {
+                left: *left,
+                top: *top,
+                width: *width,
+                height: *height,
+                rgb,
+            }));
+            Ok((bounds.2, bounds.3, rgb))
+        }
 
-            let mut jpeg = Vec::new();
-            {
-                let mut encoder = JpegEncoder::new_with_quality(&mut jpeg, quality);
-                encoder.encode(rgb, width, height, ColorType::Rgb8)?;
+        fn convert_to_rgb(
+            &self,
+            pixels: &[u8],
+            bytes_per_row: usize,
+            bits_per_pixel: usize,
+            width: usize,
+            height: usize,
+        ) -> Option<Vec<u8>> {
+            // macOS display images are BGRA, 8 bits per channel
+            if bits_per_pixel != 32 {
+                return None;
             }
+            bgr_rows_to_rgb(pixels, bytes_per_row, width, height, 4)
+        }
 
-            Ok(jpeg)
+        fn encode_frame(&self, rgb: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
+            let quality = self.quality_override
+                .unwrap_or_else(|| JPEG_QUALITY.load(Ordering::Relaxed));
+            encode_frame(rgb, width, height, quality)
         }
     }
 }
@@ -390,9 +2248,15 @@ mod linux_capture {
     pub struct ScreenCapture {
         display: *mut Display,
         root: Window,
+        /// Size of the root window
         width: u32,
         height: u32,
+        /// Selected monitor; None captures the whole root window
+        monitor: Option<MonitorInfo>,
+        /// Every monitor when they are stitched together, otherwise empty
+        stitched: Vec<MonitorInfo>,
         last_frame: Option<Vec<u8>>,
+        quality_override: Option<u8>,
     }
 
     // Display pointer is thread-safe for our use case
@@ -404,7 +2268,10 @@ mod linux_capture {
             unsafe {
                 let display = XOpenDisplay(ptr::null());
                 if display.is_null() {
-                    anyhow::bail!("Failed to open X11 display - is DISPLAY set?");
+                    if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+                        anyhow::bail!("Wayland session without X11 access - screen capture needs XWayland");
+                    }
+                    anyhow::bail!("No display available - is DISPLAY set?");
                 }
 
                 let screen = XDefaultScreen(display);
@@ -414,99 +2281,244 @@ mod linux_capture {
 
                 println!("[CAPTURE] Linux X11 display: {}x{}", width, height);
 
-                Ok(Self {
+                let mut capture = Self {
                     display,
                     root,
                     width,
                     height,
+                    monitor: None,
+                    stitched: Vec::new(),
                     last_frame: None,
-                })
+                    quality_override: None,
+                };
+                capture.retarget();
+                Ok(capture)
+            }
+        }
+
+        /// RandR monitors, or the whole screen as the only one without RandR
+        pub fn list_monitors() -> Vec<MonitorInfo> {
+            use std::ffi::CStr;
+            use x11::xrandr::{XRRFreeMonitors, XRRGetMonitors};
+
+            unsafe {
+                let display = XOpenDisplay(ptr::null());
+                if display.is_null() {
+                    return Vec::new();
+                }
+                let root = XDefaultRootWindow(display);
+                let mut count = 0;
+                let listed = XRRGetMonitors(display, root, True, &mut count);
+
+                let mut monitors = Vec::new();
+                if !listed.is_null() {
+                    for (index, monitor) in std::slice::from_raw_parts(listed, count.max(0) as usize).iter().enumerate() {
+                        let name = XGetAtomName(display, monitor.name);
+                        let name = if name.is_null() {
+                            format!("Monitor {}", index + 1)
+                        } else {
+                            let owned = CStr::from_ptr(name).to_string_lossy().into_owned();
+                            XFree(name as *mut _);
+                            owned
+                        };
+                        monitors.push(MonitorInfo {
+                            index: index as u32,
+                            name,
+                            left: monitor.x,
+                            top: monitor.y,
+                            width: monitor.width.max(0) as u32,
+                            height: monitor.height.max(0) as u32,
+                            primary: monitor.primary != 0,
+                        });
+                    }
+                    XRRFreeMonitors(listed);
+                }
+
+                if monitors.is_empty() {
+                    let screen = XDefaultScreen(display);
+                    monitors.push(MonitorInfo {
+                        index: 0,
+                        name: "Screen".to_string(),
+                        left: 0,
+                        top: 0,
+                        width: XDisplayWidth(display, screen) as u32,
+                        height: XDisplayHeight(display, screen) as u32,
+                        primary: true,
+                    });
+                }
+                XCloseDisplay(display);
+                monitors
             }
         }
 
+        /// Capture one monitor or all of them from now on, also in captures created later
+        pub fn set_capture_mode(&mut self, mode: CaptureMode) -> Result<()> {
+            switch_mode(mode, Self::list_monitors(), || {
+                self.retarget();
+                Ok(())
+            })
+        }
+
+        /// Follow the capture mode and the current monitor layout
+        fn retarget(&mut self) {
+            self.monitor = target_monitor();
+            self.stitched = if captures_all_monitors() { Self::list_monitors() } else { Vec::new() };
+            self.last_frame = None;
+        }
+
+        /// The part of the root window captured: the selected monitor if it
+        /// lies within the root (XGetImage outside it is a fatal X error),
+        /// otherwise all of it
+        fn area(&self) -> (i32, i32, u32, u32) {
+            match &self.monitor {
+                Some(m) if m.left >= 0
+                    && m.top >= 0
+                    && m.width > 0
+                    && m.height > 0
+                    && m.left as u32 + m.width <= self.width
+                    && m.top as u32 + m.height <= self.height => (m.left, m.top, m.width, m.height),
+                _ => (0, 0, self.width, self.height),
+            }
+        }
+
+        /// The parts of the stitched monitors within the root window
+        fn stitched_areas(&self) -> Vec<(i32, i32, u32, u32)> {
+            self.stitched.iter().filter_map(|m| {
+                let (left, top) = (m.left.max(0), m.top.max(0));
+                let right = (m.left as i64 + m.width as i64).min(self.width as i64);
+                let bottom = (m.top as i64 + m.height as i64).min(self.height as i64);
+                (right > left as i64 && bottom > top as i64)
+                    .then(|| (left, top, (right - left as i64) as u32, (bottom - top as i64) as u32))
+            }).collect()
+        }
+
         pub fn capture(&mut self) -> Result<(u32, u32, Vec<u8>)> {
             unsafe { self.capture_x11() }
         }
 
+        /// Capture a fresh keyframe at the given quality
+        pub fn capture_keyframe(&mut self, quality: u8) -> Result<(u32, u32, Vec<u8>)> {
+            self.quality_override = Some(quality.clamp(1, 100));
+            let result = unsafe { self.capture_x11() };
+            self.quality_override = None;
+            result
+        }
+
+        /// Pick up root window size changes (xrandr mode switch, monitor unplugged)
+        /// XGetImage outside the root bounds is a fatal X error, so check every frame
+        unsafe fn refresh_size(&mut self) {
+            let mut root: Window = 0;
+            let (mut x, mut y) = (0, 0);
+            let (mut width, mut height, mut border, mut depth) = (0, 0, 0, 0);
+            let ok = XGetGeometry(
+                self.display, self.root, &mut root,
+                &mut x, &mut y, &mut width, &mut height, &mut border, &mut depth,
+            );
+            if ok != 0 && width > 0 && height > 0 && (width, height) != (self.width, self.height) {
+                println!("[CAPTURE] Linux X11 display resized: {}x{}", width, height);
+                self.width = width;
+                self.height = height;
+                // Monitors may have moved along with the resize; cached
+                // frames have the old dimensions
+                self.retarget();
+            }
+        }
+
         unsafe fn capture_x11(&mut self) -> Result<(u32, u32, Vec<u8>)> {
+            let started = Instant::now();
+            self.refresh_size();
+
+            let areas = self.stitched_areas();
+            let (width, height, grabbed) = match desktop_bounds(areas.iter().copied()) {
+                Some(bounds) => (bounds.2, bounds.3, self.grab_stitched(bounds, &areas)),
+                None => {
+                    let (x, y, width, height) = self.area();
+                    (width, height, self.grab(x, y, width, height))
+                }
+            };
+            let rgb = match grabbed {
+                Ok(rgb) => rgb,
+                Err(e) => {
+                    if let Some(ref frame) = self.last_frame {
+                        return Ok((width, height, frame.clone()));
+                    }
+                    return Err(e);
+                }
+            };
+
+            let jpeg = self.encode_frame(&rgb, width, height)?;
+
+            self.last_frame = Some(jpeg.clone());
+            record_frame(started);
+
+            Ok((width, height, jpeg))
+        }
+
+        /// Read part of the root window as RGB
+        unsafe fn grab(&self, x: i32, y: i32, width: u32, height: u32) -> Result<Vec<u8>> {
             // Use XGetImage (slower but always works)
             // all_planes() returns !0 which is equivalent to XAllPlanes()
             let image = XGetImage(
                 self.display,
                 self.root,
-                0,
-                0,
-                self.width,
-                self.height,
+                x,
+                y,
+                width,
+                height,
                 all_planes(),
                 ZPixmap,
             );
 
             if image.is_null() {
-                if let Some(ref frame) = self.last_frame {
-                    return Ok((self.width, self.height, frame.clone()));
-                }
                 anyhow::bail!("Failed to capture screen");
             }
 
             let rgb = self.ximage_to_rgb(image);
             XDestroyImage(image);
-
-            let jpeg = self.encode_jpeg(&rgb)?;
-
-            self.last_frame = Some(jpeg.clone());
-            FRAME_COUNT.fetch_add(1, Ordering::Relaxed);
-
-            Ok((self.width, self.height, jpeg))
+            rgb.ok_or_else(|| anyhow::anyhow!("Unsupported X11 image format"))
         }
 
-        unsafe fn ximage_to_rgb(&self, image: *mut XImage) -> Vec<u8> {
-            let width = (*image).width as usize;
-            let height = (*image).height as usize;
-            let bytes_per_line = (*image).bytes_per_line as usize;
-            let bits_per_pixel = (*image).bits_per_pixel;
-            let data = (*image).data as *const u8;
-
-            let mut rgb = Vec::with_capacity(width * height * 3);
-
-            for y in 0..height {
-                for x in 0..width {
-                    let pixel_offset = y * bytes_per_line + x * (bits_per_pixel as usize / 8);
-                    let pixel = data.add(pixel_offset);
-
-                    // X11 typically uses BGRA or BGR format
-                    if bits_per_pixel == 32 {
-                        rgb.push(*pixel.add(2)); // R
-                        rgb.push(*pixel.add(1)); // G
-                        rgb.push(*pixel.add(0)); // B
-                    } else if bits_per_pixel == 24 {
-                        rgb.push(*pixel.add(2)); // R
-                        rgb.push(*pixel.add(1)); // G
-                        rgb.push(*pixel.add(0)); // B
-                    } else {
-                        // Fallback for other formats
-                        rgb.push(0);
-                        rgb.push(0);
-                        rgb.push(0);
-                    }
-                }
+        /// Read each monitor on its own, so the root window between them
+        /// (undefined where no monitor shows it) comes out black
+        unsafe fn grab_stitched(&self, bounds: (i32, i32, u32, u32), areas: &[(i32, i32, u32, u32)]) -> Result<Vec<u8>> {
+            let mut grabbed = Vec::with_capacity(areas.len());
+            for &(x, y, width, height) in areas {
+                grabbed.push((x, y, width, height, self.grab(x, y, width, height)?));
             }
-            rgb
+            Ok(stitch(bounds, grabbed.iter().map(|(left, top, width, height, rgb)| Tile {
+                left: *left,
+                top: *top,
+                width: *width,
+                height: *height,
+                rgb,
+            })))
         }
 
-        fn encode_jpeg(&self, rgb: &[u8]) -> Result<Vec<u8>> {
-            use image::codecs::jpeg::JpegEncoder;
-            use image::ColorType;
-
-            let quality = JPEG_QUALITY.load(Ordering::Relaxed);
+        /// Convert a 24 or 32 bits per pixel ZPixmap (BGR(X) in memory) to RGB
+        /// None for other depths, or an image whose rows don't fit its data
+        unsafe fn ximage_to_rgb(&self, image: *mut XImage) -> Option<Vec<u8>> {
+            let width = usize::try_from((*image).width).ok()?;
+            let height = usize::try_from((*image).height).ok()?;
+            let bytes_per_line = usize::try_from((*image).bytes_per_line).ok()?;
+            let bits_per_pixel = (*image).bits_per_pixel;
+            if (*image).data.is_null() || !matches!(bits_per_pixel, 24 | 32) {
+                println!("[CAPTURE] Unsupported X11 image: {} bits per pixel", bits_per_pixel);
+                return None;
+            }
 
-            let mut jpeg = Vec::new();
-            {
-                let mut encoder = JpegEncoder::new_with_quality(&mut jpeg, quality);
-                encoder.encode(rgb, self.width, self.height, ColorType::Rgb8)?;
+            // XGetImage allocates bytes_per_line for every row
+            let data = std::slice::from_raw_parts((*image).data as *const u8, bytes_per_line.checked_mul(height)?);
+            let rgb = bgr_rows_to_rgb(data, bytes_per_line, width, height, bits_per_pixel as usize / 8);
+            if rgb.is_none() {
+                println!("[CAPTURE] Ignoring {}x{} image with {} bytes per line", width, height, bytes_per_line);
             }
+            rgb
+        }
 
-            Ok(jpeg)
+        fn encode_frame(&self, rgb: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
+            let quality = self.quality_override
+                .unwrap_or_else(|| JPEG_QUALITY.load(Ordering::Relaxed));
+            encode_frame(rgb, width, height, quality)
         }
     }
 
@@ -537,4 +2549,282 @@ impl ScreenCapture {
     pub fn capture(&mut self) -> Result<(u32, u32, Vec<u8>)> {
         Ok((1920, 1080, Vec::new()))
     }
+
+    pub fn capture_keyframe(&mut self, _quality: u8) -> Result<(u32, u32, Vec<u8>)> {
+        self.capture()
+    }
+
+    pub fn list_monitors() -> Vec<MonitorInfo> {
+        Vec::new()
+    }
+
+    pub fn set_capture_mode(&mut self, mode: CaptureMode) -> Result<()> {
+        switch_mode(mode, Self::list_monitors(), || Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dxgi_recovery_retries_before_fallback() {
+        let mut recovery = DxgiRecovery::new();
+        let now = Instant::now();
+        assert!(recovery.due(now));
+
+        // Each lost duplication waits out a delay before the next attempt
+        for _ in 0..DXGI_RETRIES {
+            assert!(recovery.failed(now));
+            assert!(!recovery.due(now));
+            assert!(recovery.due(now + DXGI_RETRY_MAX_DELAY));
+        }
+        // Only then does capture fall back to GDI
+        assert!(!recovery.failed(now));
+
+        // A capture that goes through earns a fresh set of retries
+        recovery.recovered();
+        assert!(recovery.due(now));
+        assert!(recovery.failed(now));
+    }
+
+    #[test]
+    fn test_encode_tracker_stats() {
+        let mut tracker = EncodeTracker::new();
+        let now = Instant::now();
+
+        tracker.record(now, Duration::from_millis(10));
+        tracker.record(now, Duration::from_millis(30));
+
+        let stats = tracker.stats(now);
+        assert!((stats.avg_encode_ms - 20.0).abs() < 0.01);
+        assert!((stats.max_encode_ms - 30.0).abs() < 0.01);
+        assert_eq!(stats.capture_fps, 2);
+
+        // Window is bounded
+        for _ in 0..ENCODE_STATS_WINDOW * 2 {
+            tracker.record(now, Duration::from_millis(5));
+        }
+        assert_eq!(tracker.samples.len(), ENCODE_STATS_WINDOW);
+    }
+
+    #[test]
+    fn test_switch_mode_rolls_back() {
+        let monitor = |index: u32, name: &str, left: i32| MonitorInfo {
+            index,
+            name: name.into(),
+            left,
+            top: 0,
+            width: 1920,
+            height: 1080,
+            primary: index == 0,
+        };
+        let monitors = vec![monitor(0, "DISPLAY1", 0), monitor(1, "DISPLAY2", 1920)];
+
+        switch_mode(CaptureMode::Single(1), monitors.clone(), || Ok(())).unwrap();
+        assert_eq!(selected_monitor().map(|m| m.name), Some("DISPLAY2".into()));
+
+        // Retargeting failed: back on the previous monitor, retargeted again
+        let mut calls = 0;
+        let failed = switch_mode(CaptureMode::AllMonitors, monitors.clone(), || {
+            calls += 1;
+            if calls == 1 { anyhow::bail!("Duplication lost") } else { Ok(()) }
+        });
+        assert!(failed.is_err());
+        assert_eq!(calls, 2);
+        assert!(!captures_all_monitors());
+        assert_eq!(selected_monitor().map(|m| m.index), Some(1));
+        assert!(switch_mode(CaptureMode::Single(7), monitors.clone(), || Ok(())).is_err());
+        assert_eq!(selected_monitor().map(|m| m.index), Some(1));
+
+        switch_mode(CaptureMode::AllMonitors, monitors.clone(), || Ok(())).unwrap();
+        assert!(captures_all_monitors());
+        switch_mode(CaptureMode::Single(0), monitors.clone(), || Ok(())).unwrap();
+        assert!(!captures_all_monitors());
+
+        // Followed by name when the layout changes; gone once unplugged
+        let moved = [monitor(0, "DISPLAY1", -1920), monitor(1, "DISPLAY2", 0)];
+        let resolved = resolve_monitor(&moved, selected_monitor().as_ref()).unwrap();
+        assert_eq!((resolved.index, resolved.left), (0, -1920));
+        assert!(resolve_monitor(&monitors[1..], selected_monitor().as_ref()).is_none());
+        *SELECTED_MONITOR.lock() = None;
+
+        assert_eq!(CaptureMode::from_wire(2), CaptureMode::Single(2));
+        assert_eq!(CaptureMode::from_wire(CaptureMode::AllMonitors.to_wire()), CaptureMode::AllMonitors);
+    }
+
+    #[test]
+    fn test_stitch_monitors() {
+        // A 2x2 monitor left of and lower than a 3x1 primary at the origin
+        let left = [1u8; 2 * 2 * 3];
+        let primary = [2u8; 3 * 3];
+        let tiles = [(-2, 1, 2, 2, &left[..]), (0, 0, 3, 1, &primary[..])];
+        let bounds = desktop_bounds(tiles.iter().map(|&(l, t, w, h, _)| (l, t, w, h))).unwrap();
+        assert_eq!(bounds, (-2, 0, 5, 3));
+
+        let frame = stitch(bounds, tiles.iter().map(|&(left, top, width, height, rgb)| Tile { left, top, width, height, rgb }));
+        let pixel = |x: usize, y: usize| frame[(y * 5 + x) * 3];
+        assert_eq!(frame.len(), 5 * 3 * 3);
+        // Normalized: the left monitor starts at x 0, the primary at x 2
+        assert_eq!((pixel(0, 1), pixel(1, 2), pixel(2, 0), pixel(4, 0)), (1, 1, 2, 2));
+        // Corners no monitor covers are black
+        assert_eq!((pixel(0, 0), pixel(1, 0), pixel(3, 1), pixel(4, 2)), (0, 0, 0, 0));
+
+        // Tiles hanging off the frame are clipped; short ones are left out
+        let clipped = stitch((0, 0, 2, 1), [
+            Tile { left: -1, top: 0, width: 2, height: 1, rgb: &[5; 6] },
+            Tile { left: 1, top: 0, width: 1, height: 1, rgb: &[9; 2] },
+        ]);
+        assert_eq!(clipped, vec![5, 5, 5, 0, 0, 0]);
+        assert!(desktop_bounds([]).is_none());
+    }
+
+    #[test]
+    fn test_capture_backend_from_setting() {
+        assert_eq!(CaptureBackend::from_setting("auto"), Some(CaptureBackend::Auto));
+        assert_eq!(CaptureBackend::from_setting("dxgi"), Some(CaptureBackend::Dxgi));
+        assert_eq!(CaptureBackend::from_setting("gdi"), Some(CaptureBackend::Gdi));
+        assert_eq!(CaptureBackend::from_setting("opengl"), None);
+        assert_eq!(ContentMode::from_setting("text"), Some(ContentMode::Text));
+        assert_eq!(ContentMode::from_setting("video"), None);
+    }
+
+    #[test]
+    fn test_content_classification() {
+        let (width, height) = (256u32, 128u32);
+
+        // Black glyph strokes on a white page
+        let mut text = vec![255u8; (width * height * 3) as usize];
+        for y in 0..height as usize {
+            for x in (0..width as usize).filter(|x| x % 12 < 2 && y % 16 < 10) {
+                text[(y * width as usize + x) * 3..][..3].copy_from_slice(&[0, 0, 0]);
+            }
+        }
+        assert_eq!(classify_content(&text, width, height), ContentKind::Text);
+
+        // Pseudo-random noise, like film grain
+        let mut seed = 0x2545_f491u32;
+        let photo: Vec<u8> = (0..width * height * 3).map(|_| {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            (seed >> 24) as u8
+        }).collect();
+        assert_eq!(classify_content(&photo, width, height), ContentKind::Photo);
+
+        // An empty desktop has no edges to protect
+        let blank = vec![40u8; (width * height * 3) as usize];
+        assert_eq!(classify_content(&blank, width, height), ContentKind::Mixed);
+
+        assert_eq!(content_quality(75, ContentKind::Text), 90);
+        assert_eq!(content_quality(75, ContentKind::Photo), 65);
+        assert_eq!(content_quality(95, ContentKind::Text), 95);
+        assert_eq!(content_quality(15, ContentKind::Photo), 15);
+        assert_eq!(content_quality(100, ContentKind::Text), 100);
+
+        // A switch needs a few frames in a row
+        let mut tracker = ContentTracker::new();
+        assert_eq!(tracker.observe(ContentKind::Text), ContentKind::Mixed);
+        assert_eq!(tracker.observe(ContentKind::Photo), ContentKind::Mixed);
+        for _ in 0..CONTENT_SWITCH_FRAMES - 1 {
+            assert_eq!(tracker.observe(ContentKind::Text), ContentKind::Mixed);
+        }
+        assert_eq!(tracker.observe(ContentKind::Text), ContentKind::Text);
+    }
+
+    #[test]
+    fn test_padded_rows_convert() {
+        // 3x2 BGRA with 4 bytes of padding per row (pitch 16), last row unpadded
+        let mut bgra = Vec::new();
+        for y in 0..2u8 {
+            for x in 0..3u8 {
+                bgra.extend_from_slice(&[x, y, 0xA0 + x, 0xFF]);
+            }
+            if y == 0 {
+                bgra.extend_from_slice(&[0xEE; 4]);
+            }
+        }
+        let rgb = bgr_rows_to_rgb(&bgra, 16, 3, 2, 4).unwrap();
+        assert_eq!(rgb, vec![
+            0xA0, 0, 0, 0xA1, 0, 1, 0xA2, 0, 2,
+            0xA0, 1, 0, 0xA1, 1, 1, 0xA2, 1, 2,
+        ]);
+
+        // 24 bits per pixel with an odd width: 5 * 3 = 15 bytes, padded to 16
+        let bgr: Vec<u8> = (0..16 * 2).map(|i| i as u8).collect();
+        let rgb = bgr_rows_to_rgb(&bgr, 16, 5, 2, 3).unwrap();
+        assert_eq!(rgb.len(), 5 * 2 * 3);
+        assert_eq!(&rgb[..3], &[2, 1, 0]);
+        assert_eq!(&rgb[15..18], &[18, 17, 16]);
+    }
+
+    #[test]
+    fn test_malformed_rows_rejected() {
+        let data = vec![0u8; 64];
+        // Buffer too short for the rows
+        assert!(bgr_rows_to_rgb(&data, 16, 4, 5, 4).is_none());
+        // Pitch narrower than a row
+        assert!(bgr_rows_to_rgb(&data, 12, 4, 2, 4).is_none());
+        // Unsupported pixel sizes, empty images, overflowing sizes
+        assert!(bgr_rows_to_rgb(&data, 16, 8, 2, 2).is_none());
+        assert!(bgr_rows_to_rgb(&data, 16, 0, 2, 4).is_none());
+        assert!(bgr_rows_to_rgb(&data, usize::MAX, 4, 3, 4).is_none());
+        // Exactly enough: the last row needs no padding
+        assert!(bgr_rows_to_rgb(&data[..16 * 3 + 16], 16, 4, 4, 4).is_some());
+    }
+
+    #[test]
+    fn test_hdr_surfaces_convert() {
+        assert_eq!(half_to_f32(0x3C00), 1.0);
+        assert_eq!(half_to_f32(0xB800), -0.5);
+        assert_eq!(half_to_f32(0x0001), 2f32.powi(-24));
+        assert!(half_to_f32(0x7E00).is_nan());
+
+        // 10-bit: red in the low bits, keep the top 8 of each channel
+        let packed = (0x3FFu32 | (0x200 << 10) | (0x004 << 20)).to_le_bytes();
+        let rgb = surface_rows_to_rgb(&packed, 4, 1, 1, SurfaceFormat::Rgb10A2).unwrap();
+        assert_eq!(rgb, vec![0xFF, 0x80, 0x01]);
+
+        // Half floats: black, mid grey, SDR white, a 4x highlight, then
+        // negative and NaN values that must not wrap around
+        let values: [u16; 6] = [0x0000, 0x3800, 0x3C00, 0x4400, 0xBC00, 0x7E00];
+        let mut data = Vec::new();
+        for pair in values.chunks(3) {
+            for &value in pair {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            data.extend_from_slice(&0x3C00u16.to_le_bytes());
+        }
+        let rgb = surface_rows_to_rgb(&data, 16, 2, 1, SurfaceFormat::Rgba16Float).unwrap();
+        assert_eq!(rgb.len(), 6);
+        assert_eq!(rgb[0], 0);
+        assert_eq!(rgb[1], 188);
+        // White keeps some headroom so brighter highlights still show
+        assert!(rgb[2] > 235 && rgb[2] < 255, "white mapped to {}", rgb[2]);
+        assert_eq!(rgb[3], 255);
+        assert_eq!(&rgb[4..], &[0, 0]);
+
+        // Too short for 8-byte pixels
+        assert!(surface_rows_to_rgb(&data[..12], 16, 2, 1, SurfaceFormat::Rgba16Float).is_none());
+    }
+
+    #[test]
+    fn test_lossless_encoding_round_trips() {
+        use crate::protocol::video::{FORMAT_JPEG, FORMAT_PNG};
+
+        let rgb: Vec<u8> = (0..16 * 8 * 3).map(|i| (i * 7 % 256) as u8).collect();
+
+        let jpeg = encode_frame(&rgb, 16, 8, 80).unwrap();
+        assert_eq!(frame_format(&jpeg), FORMAT_JPEG);
+
+        set_lossless(true);
+        let png = encode_frame(&rgb, 16, 8, 80);
+        set_lossless(false);
+        let png = png.unwrap();
+        assert_eq!(frame_format(&png), FORMAT_PNG);
+
+        // Lossless means pixel-exact
+        let decoded = image::load_from_memory(&png).unwrap().to_rgb8();
+        assert_eq!(decoded.as_raw(), &rgb);
+    }
 }
@@ -7,12 +7,16 @@
 //!   securedesk                       # Start GUI application
 //!   securedesk --id                  # Print device ID and exit
 //!   securedesk --new-id              # Generate new device ID
+//!   securedesk --wipe-identity       # Destroy this device's keys (decommissioning)
 //!   securedesk --get-alias           # Get current alias
 //!   securedesk --set-alias NAME      # Set alias
 //!   securedesk --version             # Print version
 //!   securedesk <address>             # Connect to remote address
 //!   securedesk --service             # Start as service/daemon
 //!   securedesk --listen              # Start listening for connections (headless)
+//!   securedesk --dump-keymap         # Print the key remapping tables
+//!   securedesk input click 100 200   # Send input to a session of the running app
+//!   securedesk sso login             # Sign in with SSO from another device's browser
 
 use clap::{Parser, Subcommand};
 
@@ -31,6 +35,10 @@ pub struct Cli {
     #[arg(long = "new-id")]
     pub new_id: bool,
 
+    /// Destroy this device's identity keys and stored SSO session, then exit
+    #[arg(long = "wipe-identity")]
+    pub wipe_identity: bool,
+
     /// Get the current alias
     #[arg(long = "get-alias")]
     pub get_alias: bool,
@@ -55,6 +63,10 @@ pub struct Cli {
     #[arg(value_name = "ADDRESS")]
     pub connect_to: Option<String>,
 
+    /// Print the key remapping tables (defaults plus config overrides) and exit
+    #[arg(long = "dump-keymap")]
+    pub dump_keymap: bool,
+
     /// Run in headless mode (no GUI)
     #[arg(long = "headless")]
     pub headless: bool,
@@ -81,13 +93,57 @@ pub enum Commands {
         #[command(subcommand)]
         action: RecordingAction,
     },
+    /// Send one input action to a session of the running app (needs automation_enabled)
+    Input {
+        /// Session to send to (default: the active session)
+        #[arg(long = "session", value_name = "SESSION_ID")]
+        session: Option<String>,
+        #[command(subcommand)]
+        action: InputAction,
+    },
+    /// List the sessions of the running app (needs automation_enabled)
+    Sessions,
+    /// SSO commands
+    Sso {
+        #[command(subcommand)]
+        action: SsoAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum InputAction {
+    /// Move the pointer to remote screen coordinates
+    Move {
+        x: i32,
+        y: i32,
+    },
+    /// Click at remote screen coordinates
+    Click {
+        x: i32,
+        y: i32,
+        /// 0 = left, 1 = right, 2 = middle
+        #[arg(long = "button", default_value_t = 0)]
+        button: u8,
+    },
+    /// Scroll by wheel notches (negative = up/left)
+    Scroll {
+        #[arg(allow_negative_numbers = true)]
+        dy: i32,
+        #[arg(long = "dx", default_value_t = 0, allow_negative_numbers = true)]
+        dx: i32,
+    },
+    /// Press and release a Windows virtual key (hex like 0x41, or decimal)
+    Key {
+        #[arg(value_name = "KEY_CODE")]
+        code: String,
+    },
 }
 
 #[derive(Subcommand, Debug)]
 pub enum LicenseAction {
     /// Show current license info
     Info,
-    /// Activate a license key
+    /// Activate a license key (a signed license, or a portal key "SD-..." online)
     Activate {
         #[arg(value_name = "LICENSE_KEY")]
         key: String,
@@ -126,6 +182,41 @@ pub enum ConfigAction {
         #[arg(value_name = "DEVICE_ID")]
         device_id: String,
     },
+    /// List blocked devices
+    BlockedDevices,
+    /// Refuse connection requests from a device without asking
+    Block {
+        #[arg(value_name = "DEVICE_ID")]
+        device_id: String,
+    },
+    /// Remove a device from the blocked list
+    Unblock {
+        #[arg(value_name = "DEVICE_ID")]
+        device_id: String,
+    },
+    /// Let viewers that give a password in without asking (unattended access);
+    /// the password is read from stdin and everyone else is refused
+    SetPassword,
+    /// Turn unattended access off; connection requests need approval again
+    ClearPassword,
+    /// List windows viewers may not control
+    ControlPolicy,
+    /// Drop viewer input while a matching window has focus (`*` is a wildcard)
+    Protect {
+        #[arg(value_name = "PATTERN")]
+        pattern: String,
+        /// Match the executable name (e.g. keepass.exe) instead of the window title
+        #[arg(long = "process")]
+        process: bool,
+        /// Also black out the window in the shared screen
+        #[arg(long = "blackout")]
+        blackout: bool,
+    },
+    /// Remove a window policy rule
+    Unprotect {
+        #[arg(value_name = "PATTERN")]
+        pattern: String,
+    },
 }
 
 #[derive(Subcommand, Debug)]
@@ -141,16 +232,28 @@ pub enum RecordingAction {
     },
 }
 
+#[derive(Subcommand, Debug)]
+pub enum SsoAction {
+    /// Sign in with a code entered in a browser on another device
+    Login {
+        /// Provider to sign in with (default: the first configured)
+        #[arg(long = "provider", value_name = "NAME")]
+        provider: Option<String>,
+    },
+}
+
 impl Cli {
     /// Check if CLI should run in headless/non-GUI mode
     pub fn is_headless_mode(&self) -> bool {
         self.headless
             || self.get_id
             || self.new_id
+            || self.wipe_identity
             || self.get_alias
             || self.set_alias.is_some()
             || self.service
             || self.listen
+            || self.dump_keymap
             || self.command.is_some()
     }
 }
@@ -190,6 +293,36 @@ pub fn handle_cli(cli: &Cli) -> Option<i32> {
         }
     }
 
+    // Handle --wipe-identity
+    if cli.wipe_identity {
+        // The SSO session is encrypted with a key derived from the identity
+        if let Ok(Some(identity)) = Identity::load_existing() {
+            if let Ok(mut sso) = crate::sso::SsoConfig::load(&identity) {
+                if sso.active_session.is_some() {
+                    if let Err(e) = sso.clear_session() {
+                        eprintln!("Error clearing SSO session: {}", e);
+                        return Some(1);
+                    }
+                    println!("Stored SSO session removed");
+                }
+            }
+        }
+        return match Identity::wipe() {
+            Ok(true) => {
+                println!("Device identity wiped; a new ID is created on next start");
+                Some(0)
+            }
+            Ok(false) => {
+                println!("No device identity to wipe");
+                Some(0)
+            }
+            Err(e) => {
+                eprintln!("Error wiping identity: {}", e);
+                Some(1)
+            }
+        };
+    }
+
     // Handle --get-alias
     if cli.get_alias {
         let config = ConnectionConfig::load_or_create().unwrap_or_default();
@@ -216,6 +349,14 @@ pub fn handle_cli(cli: &Cli) -> Option<i32> {
         return Some(0);
     }
 
+    // Handle --dump-keymap
+    if cli.dump_keymap {
+        let config = ConnectionConfig::load_or_create().unwrap_or_default();
+        config.apply_keymap();
+        print!("{}", crate::keymap::dump());
+        return Some(0);
+    }
+
     // Handle subcommands
     if let Some(ref command) = cli.command {
         return handle_subcommand(command);
@@ -228,6 +369,7 @@ pub fn handle_cli(cli: &Cli) -> Option<i32> {
 fn handle_subcommand(command: &Commands) -> Option<i32> {
     use crate::crypto::Identity;
     use crate::config::ConnectionConfig;
+    use crate::ipc;
     use crate::license::LicenseManager;
     use crate::recording;
 
@@ -240,6 +382,7 @@ fn handle_subcommand(command: &Commands) -> Option<i32> {
                     return Some(1);
                 }
             };
+            let settings = ConnectionConfig::load_or_create().unwrap_or_default().settings;
             let mut manager = LicenseManager::new(identity.public_key());
             let _ = manager.load();
 
@@ -260,6 +403,25 @@ fn handle_subcommand(command: &Commands) -> Option<i32> {
                     }
                     Some(0)
                 }
+                LicenseAction::Activate { key } if crate::license::License::is_activation_key(key) => {
+                    let fetched = tokio::runtime::Runtime::new()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|rt| rt.block_on(crate::license::activate_online(
+                            &settings.license_activation_url,
+                            key,
+                            &identity.device_id_raw(),
+                        )));
+                    match fetched.and_then(|license| manager.install(license)) {
+                        Ok(tier) => {
+                            println!("License activated: {}", tier.as_str());
+                            Some(0)
+                        }
+                        Err(e) => {
+                            eprintln!("Activation failed: {}", e);
+                            Some(1)
+                        }
+                    }
+                }
                 LicenseAction::Activate { key } => {
                     match manager.activate(key) {
                         Ok(tier) => {
@@ -296,10 +458,45 @@ fn handle_subcommand(command: &Commands) -> Option<i32> {
                     println!("Require Approval: {}", settings.require_approval);
                     println!("Lock on Disconnect: {}", settings.lock_on_disconnect);
                     println!("Session Timeout: {}s", settings.session_timeout);
+                    println!("Approval Timeout: {}s", settings.approval_timeout_secs);
+                    println!("Start View Only: {}", settings.start_view_only);
+                    println!("Window Policy: {} rule(s)", config.control_policy.len());
+                    println!("Unattended Access: {}", config.unattended_password.is_some());
                     println!("Start with System: {}", settings.start_with_windows);
                     println!("Minimize to Tray: {}", settings.minimize_to_tray);
                     println!("Show Notifications: {}", settings.show_notifications);
                     println!("Connection Quality: {}", settings.connection_quality);
+                    println!("Coalesce Frame Requests: {}", settings.coalesce_frame_requests);
+                    println!("Relay Multiplexing: {}", settings.relay_multiplexing);
+                    println!("Jitter Buffer: {} frames", settings.jitter_buffer_frames);
+                    println!("Snapshot Directory: {}", settings.snapshot_dir().display());
+                    println!("Capture Backend: {}", settings.capture_backend);
+                    println!("Content Mode: {}", settings.content_mode);
+                    println!("Capture FPS: {} active, {} idle", settings.capture_active_fps, settings.capture_idle_fps);
+                    println!("Scroll Sensitivity: {}%", settings.scroll_sensitivity);
+                    println!("Natural Scroll: {}", settings.natural_scroll);
+                    println!("Input Batching: {}ms", settings.input_batch_ms);
+                    println!("Clipboard Limit: {} MB", settings.max_clipboard_size_mb);
+                    println!("Metrics Endpoint: {} (127.0.0.1:{})", settings.metrics_enabled, settings.metrics_port);
+                    println!("Automation Socket: {}", settings.automation_enabled);
+                    match settings.reconnect_max_attempts {
+                        0 => println!("Host Reconnect: forever, up to {}s apart", settings.reconnect_max_backoff_secs),
+                        n => println!("Host Reconnect: {} attempts, up to {}s apart", n, settings.reconnect_max_backoff_secs),
+                    }
+                    if let Ok(dir) = settings.recordings_path() {
+                        println!("Recordings Directory: {}", dir.display());
+                    }
+                    if settings.proxy_url.is_empty() {
+                        println!("Proxy: (environment)");
+                    } else {
+                        println!("Proxy: {}", crate::proxy::masked_setting(&settings.proxy_url));
+                    }
+                    println!("Relay CA Certificate: {}", if settings.relay_ca_cert.is_empty() { "(none)" } else { &settings.relay_ca_cert });
+                    if settings.relay_tls_insecure {
+                        println!("Relay TLS: NOT VERIFIED (relay_tls_insecure)");
+                    }
+                    println!("STUN Enabled: {}", config.stun_enabled);
+                    println!("STUN Servers: {}", config.stun_servers.join(", "));
                     Some(0)
                 }
                 ConfigAction::Get { key } => {
@@ -309,10 +506,34 @@ fn handle_subcommand(command: &Commands) -> Option<i32> {
                         "require_approval" => format!("{}", settings.require_approval),
                         "lock_on_disconnect" => format!("{}", settings.lock_on_disconnect),
                         "session_timeout" => format!("{}", settings.session_timeout),
+                        "approval_timeout_secs" => format!("{}", settings.approval_timeout_secs),
+                        "start_view_only" => format!("{}", settings.start_view_only),
                         "start_with_windows" => format!("{}", settings.start_with_windows),
                         "minimize_to_tray" => format!("{}", settings.minimize_to_tray),
                         "show_notifications" => format!("{}", settings.show_notifications),
                         "connection_quality" => settings.connection_quality.clone(),
+                        "coalesce_frame_requests" => format!("{}", settings.coalesce_frame_requests),
+                        "relay_multiplexing" => format!("{}", settings.relay_multiplexing),
+                        "jitter_buffer_frames" => format!("{}", settings.jitter_buffer_frames),
+                        "snapshot_directory" => settings.snapshot_directory.clone(),
+                        "capture_backend" => settings.capture_backend.clone(),
+                        "content_mode" => settings.content_mode.clone(),
+                        "capture_active_fps" => format!("{}", settings.capture_active_fps),
+                        "capture_idle_fps" => format!("{}", settings.capture_idle_fps),
+                        "scroll_sensitivity" => format!("{}", settings.scroll_sensitivity),
+                        "natural_scroll" => format!("{}", settings.natural_scroll),
+                        "input_batch_ms" => format!("{}", settings.input_batch_ms),
+                        "max_clipboard_size_mb" => format!("{}", settings.max_clipboard_size_mb),
+                        "recordings_dir" => settings.recordings_dir.clone(),
+                        "proxy_url" => crate::proxy::masked_setting(&settings.proxy_url),
+                        "relay_ca_cert" => settings.relay_ca_cert.clone(),
+                        "license_activation_url" => settings.license_activation_url.clone(),
+                        "relay_tls_insecure" => format!("{}", settings.relay_tls_insecure),
+                        "metrics_enabled" => format!("{}", settings.metrics_enabled),
+                        "metrics_port" => format!("{}", settings.metrics_port),
+                        "automation_enabled" => format!("{}", settings.automation_enabled),
+                        "reconnect_max_attempts" => format!("{}", settings.reconnect_max_attempts),
+                        "reconnect_max_backoff_secs" => format!("{}", settings.reconnect_max_backoff_secs),
                         _ => {
                             eprintln!("Unknown config key: {}", key);
                             return Some(1);
@@ -324,7 +545,10 @@ fn handle_subcommand(command: &Commands) -> Option<i32> {
                 ConfigAction::Set { key, value } => {
                     let setting_value = match key.as_str() {
                         "p2p_enabled" | "require_approval" | "lock_on_disconnect" |
-                        "start_with_windows" | "minimize_to_tray" | "show_notifications" => {
+                        "start_with_windows" | "minimize_to_tray" | "show_notifications" |
+                        "natural_scroll" | "coalesce_frame_requests" | "relay_multiplexing" |
+                        "metrics_enabled" | "automation_enabled" | "start_view_only" |
+                        "relay_tls_insecure" => {
                             let bool_val = match value.to_lowercase().as_str() {
                                 "true" | "1" | "yes" | "on" => true,
                                 "false" | "0" | "no" | "off" => false,
@@ -335,7 +559,10 @@ fn handle_subcommand(command: &Commands) -> Option<i32> {
                             };
                             crate::config::SettingValue::Bool(bool_val)
                         }
-                        "session_timeout" => {
+                        "session_timeout" | "scroll_sensitivity" | "capture_active_fps" |
+                        "capture_idle_fps" | "approval_timeout_secs" | "input_batch_ms" |
+                        "jitter_buffer_frames" | "metrics_port" | "reconnect_max_attempts" |
+                        "reconnect_max_backoff_secs" | "max_clipboard_size_mb" => {
                             match value.parse::<u32>() {
                                 Ok(n) => crate::config::SettingValue::Number(n),
                                 Err(_) => {
@@ -344,7 +571,9 @@ fn handle_subcommand(command: &Commands) -> Option<i32> {
                                 }
                             }
                         }
-                        "connection_quality" => {
+                        "connection_quality" | "snapshot_directory" | "capture_backend" |
+                        "content_mode" | "recordings_dir" | "proxy_url" | "relay_ca_cert" |
+                        "license_activation_url" => {
                             crate::config::SettingValue::String(value.clone())
                         }
                         _ => {
@@ -388,12 +617,218 @@ fn handle_subcommand(command: &Commands) -> Option<i32> {
                     println!("Device {} removed from trusted list", device_id);
                     Some(0)
                 }
+                ConfigAction::BlockedDevices => {
+                    let devices = config.get_blocked_devices();
+                    if devices.is_empty() {
+                        println!("No blocked devices");
+                    } else {
+                        for device_id in devices {
+                            println!("{}", device_id);
+                        }
+                    }
+                    Some(0)
+                }
+                ConfigAction::Block { device_id } => {
+                    if let Err(e) = config.block_device(device_id) {
+                        eprintln!("Error blocking device: {}", e);
+                        return Some(1);
+                    }
+                    println!("Device {} blocked", device_id);
+                    Some(0)
+                }
+                ConfigAction::Unblock { device_id } => {
+                    if let Err(e) = config.unblock_device(device_id) {
+                        eprintln!("Error unblocking device: {}", e);
+                        return Some(1);
+                    }
+                    println!("Device {} removed from blocked list", device_id);
+                    Some(0)
+                }
+                ConfigAction::SetPassword => {
+                    let licensed = Identity::load_or_create()
+                        .map(|identity| {
+                            let mut manager = LicenseManager::new(identity.public_key());
+                            let _ = manager.load();
+                            manager.has_feature(crate::license::LicenseFeature::UnattendedAccess)
+                        })
+                        .unwrap_or(false);
+                    if !licensed {
+                        eprintln!("Unattended access requires a Pro license or higher");
+                        return Some(1);
+                    }
+                    println!("Access password:");
+                    let mut password = String::new();
+                    if let Err(e) = std::io::stdin().read_line(&mut password) {
+                        eprintln!("Error reading password: {}", e);
+                        return Some(1);
+                    }
+                    if let Err(e) = config.set_unattended_password(password.trim_end_matches(['\r', '\n'])) {
+                        eprintln!("Error setting password: {}", e);
+                        return Some(1);
+                    }
+                    println!("Unattended access enabled");
+                    Some(0)
+                }
+                ConfigAction::ClearPassword => {
+                    if let Err(e) = config.clear_unattended_password() {
+                        eprintln!("Error clearing password: {}", e);
+                        return Some(1);
+                    }
+                    println!("Unattended access disabled");
+                    Some(0)
+                }
+                ConfigAction::ControlPolicy => {
+                    if config.control_policy.is_empty() {
+                        println!("No window policy rules");
+                    }
+                    for rule in &config.control_policy {
+                        let match_on = match rule.match_on {
+                            crate::policy::MatchOn::Title => "title",
+                            crate::policy::MatchOn::Process => "process",
+                        };
+                        let blackout = if rule.blackout { ", blacked out" } else { "" };
+                        println!("{} ({}{})", rule.pattern, match_on, blackout);
+                    }
+                    Some(0)
+                }
+                ConfigAction::Protect { pattern, process, blackout } => {
+                    let mut rules = config.control_policy.clone();
+                    rules.push(crate::policy::PolicyRule {
+                        pattern: pattern.clone(),
+                        match_on: if *process { crate::policy::MatchOn::Process } else { crate::policy::MatchOn::Title },
+                        blackout: *blackout,
+                    });
+                    if let Err(e) = config.set_control_policy(rules) {
+                        eprintln!("Error adding policy rule: {}", e);
+                        return Some(1);
+                    }
+                    println!("Viewers can no longer control windows matching {}", pattern.trim());
+                    Some(0)
+                }
+                ConfigAction::Unprotect { pattern } => {
+                    let mut rules = config.control_policy.clone();
+                    rules.retain(|rule| rule.pattern != pattern.trim());
+                    if rules.len() == config.control_policy.len() {
+                        eprintln!("No policy rule for {}", pattern);
+                        return Some(1);
+                    }
+                    if let Err(e) = config.set_control_policy(rules) {
+                        eprintln!("Error removing policy rule: {}", e);
+                        return Some(1);
+                    }
+                    println!("Policy rule {} removed", pattern.trim());
+                    Some(0)
+                }
+            }
+        }
+        Commands::Input { session, action } => {
+            let action = match action {
+                InputAction::Move { x, y } => ipc::InputAction::Move { x: *x, y: *y },
+                InputAction::Click { x, y, button } => ipc::InputAction::Click { x: *x, y: *y, button: *button },
+                InputAction::Scroll { dy, dx } => ipc::InputAction::Scroll { dx: *dx, dy: *dy },
+                InputAction::Key { code } => {
+                    match crate::keymap::parse_code(code).ok().and_then(|c| u16::try_from(c).ok()) {
+                        Some(code) => ipc::InputAction::Key { code },
+                        None => {
+                            eprintln!("Invalid key code: {}", code);
+                            return Some(1);
+                        }
+                    }
+                }
+            };
+            match ipc::send(ipc::Command::Input { session: session.clone(), action }) {
+                Ok(_) => {
+                    println!("Input sent");
+                    Some(0)
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    Some(1)
+                }
+            }
+        }
+        Commands::Sessions => {
+            match ipc::send(ipc::Command::Sessions) {
+                Ok(result) => {
+                    let sessions = result.and_then(|v| v.as_array().cloned()).unwrap_or_default();
+                    if sessions.is_empty() {
+                        println!("No active sessions");
+                    }
+                    for session in sessions {
+                        println!("{} - {}{}",
+                            session["session_id"].as_str().unwrap_or_default(),
+                            session["remote_id"].as_str().unwrap_or_default(),
+                            if session["active"].as_bool() == Some(true) { " (active)" } else { "" },
+                        );
+                    }
+                    Some(0)
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    Some(1)
+                }
+            }
+        }
+        Commands::Sso { action } => {
+            let identity = match Identity::load_or_create() {
+                Ok(i) => i,
+                Err(e) => {
+                    eprintln!("Error loading identity: {}", e);
+                    return Some(1);
+                }
+            };
+            ConnectionConfig::load_or_create().unwrap_or_default().apply_proxy();
+            let mut manager = match crate::sso::SsoManager::new(&identity) {
+                Ok(m) => m,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return Some(1);
+                }
+            };
+
+            match action {
+                SsoAction::Login { provider } => {
+                    let provider = match provider {
+                        Some(name) => manager.config().get_provider(name),
+                        None => manager.list_providers().first(),
+                    };
+                    let Some(provider) = provider.cloned() else {
+                        eprintln!("No such SSO provider configured");
+                        return Some(1);
+                    };
+
+                    let session = tokio::runtime::Runtime::new()
+                        .map_err(anyhow::Error::from)
+                        .and_then(|rt| rt.block_on(async {
+                            let authorization = manager.start_device_login(&provider).await?;
+                            println!("To sign in, open {} and enter the code {}", authorization.verification_uri, authorization.user_code);
+                            if let Some(ref uri) = authorization.verification_uri_complete {
+                                println!("Or open {}", uri);
+                            }
+                            println!("Waiting for approval...");
+                            manager.wait_for_device_login(&provider, &authorization).await
+                        }));
+                    match session {
+                        Ok(session) => {
+                            let user = session.user.email.or(session.user.preferred_username).unwrap_or(session.user.sub);
+                            println!("Signed in to {} as {}", provider.name, user);
+                            Some(0)
+                        }
+                        Err(e) => {
+                            eprintln!("SSO login failed: {}", e);
+                            Some(1)
+                        }
+                    }
+                }
             }
         }
         Commands::Recording { action } => {
             match action {
                 RecordingAction::List => {
-                    match recording::list_recordings() {
+                    let config = ConnectionConfig::load_or_create().unwrap_or_default();
+                    let recordings = config.get_settings().recordings_path()
+                        .and_then(|dir| recording::list_recordings(&dir));
+                    match recordings {
                         Ok(recordings) => {
                             if recordings.is_empty() {
                                 println!("No recordings found");
@@ -419,7 +854,8 @@ fn handle_subcommand(command: &Commands) -> Option<i32> {
                     }
                 }
                 RecordingAction::Dir => {
-                    match recording::SessionRecorder::recordings_directory() {
+                    let config = ConnectionConfig::load_or_create().unwrap_or_default();
+                    match config.get_settings().recordings_path() {
                         Ok(dir) => {
                             println!("{}", dir.display());
                             Some(0)
@@ -431,7 +867,10 @@ fn handle_subcommand(command: &Commands) -> Option<i32> {
                     }
                 }
                 RecordingAction::Delete { path } => {
-                    match recording::delete_recording(path) {
+                    let config = ConnectionConfig::load_or_create().unwrap_or_default();
+                    let deleted = config.get_settings().recordings_path()
+                        .and_then(|dir| recording::delete_recording(&dir, path));
+                    match deleted {
                         Ok(_) => {
                             println!("Recording deleted");
                             Some(0)
@@ -454,19 +893,118 @@ pub async fn run_headless_listen(relay_address: Option<String>) -> anyhow::Resul
 
     let identity = Identity::load_or_create()?;
     println!("Device ID: {}", identity.device_id());
+    println!("Fingerprint: {}", identity.fingerprint());
+
+    let config = crate::config::ConnectionConfig::load_or_create().unwrap_or_default();
+    if let Some(backend) = crate::capture::CaptureBackend::from_setting(&config.settings.capture_backend) {
+        crate::capture::set_backend(backend);
+    }
+    if let Some(mode) = crate::capture::ContentMode::from_setting(&config.settings.content_mode) {
+        crate::capture::set_content_mode(mode);
+    }
+    config.apply_proxy();
+    config.apply_tls();
+    config.apply_metrics();
+    config.apply_stun();
+    config.apply_capture_rates();
+    config.apply_approval_timeout();
+    config.apply_session_timeout();
+    config.apply_clipboard_limit();
+    config.apply_start_view_only();
+    config.apply_lock_on_disconnect();
+    config.apply_blocked_devices();
+    config.apply_allowed_viewers();
+    config.apply_connection_quality();
+    config.apply_keymap();
+    config.apply_control_policy();
+
+    let mut license = crate::license::LicenseManager::new(identity.public_key());
+    let _ = license.load();
+    if license.key_id().is_some() {
+        match crate::license::fetch_revocations().await {
+            Ok(list) => match license.apply_revocations(&list) {
+                Ok(true) => println!("License was revoked - running as Free"),
+                Ok(false) => {}
+                Err(e) => eprintln!("Failed to save revocation check: {}", e),
+            },
+            Err(e) => eprintln!("License revocation check failed: {}", e),
+        }
+    }
+    config.apply_unattended_access(license.has_feature(crate::license::LicenseFeature::UnattendedAccess));
 
     let relay = relay_address.unwrap_or_else(|| "relay.securedesk.one:8443".to_string());
     println!("Connecting to relay: {}", relay);
 
-    let mut session = HostSession::start(relay, identity).await?;
+    let mut session = HostSession::start_with_alias(relay.clone(), identity.clone(), config.relay_alias()).await?;
     println!("Listening for incoming connections...");
     println!("Press Ctrl+C to stop");
 
-    // Run the host session loop
+    // Run the host session loop until asked to stop, reconnecting when the relay drops
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    let mut backoff = crate::backoff::Backoff::new(
+        std::time::Duration::from_secs(config.settings.reconnect_max_backoff_secs as u64),
+        config.settings.reconnect_max_attempts,
+    );
     loop {
-        if let Err(e) = session.run_once().await {
-            eprintln!("Host session error: {}", e);
-            tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+        tokio::select! {
+            result = session.run_once() => {
+                let Err(e) = result else { continue };
+                eprintln!("Host session error: {}", e);
+                loop {
+                    let Some(delay) = backoff.next_delay() else {
+                        anyhow::bail!("Giving up after {} reconnect attempts", backoff.attempts());
+                    };
+                    println!("Reconnecting in {:.1}s (attempt {})...", delay.as_secs_f64(), backoff.attempts());
+                    tokio::select! {
+                        _ = tokio::time::sleep(delay) => {}
+                        _ = &mut shutdown => {
+                            println!("Shutting down...");
+                            return Ok(());
+                        }
+                    }
+                    match HostSession::start_with_alias(relay.clone(), identity.clone(), config.relay_alias()).await {
+                        Ok(new_session) => {
+                            println!("Reconnected to relay: {}", relay);
+                            crate::metrics::record_relay_reconnect();
+                            session = new_session;
+                            backoff.reset();
+                            break;
+                        }
+                        Err(e) => eprintln!("Reconnect failed: {}", e),
+                    }
+                }
+            }
+            _ = &mut shutdown => {
+                println!("Shutting down...");
+                // Ends the session with the client and disables privacy mode
+                session.stop().await?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Resolves on Ctrl+C, or on SIGTERM on Unix (what service managers send)
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
         }
     }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
 }
@@ -6,31 +6,256 @@ use anyhow::Result;
 use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpStream;
-use tokio_rustls::rustls::{ClientConfig, RootCertStore};
-use tokio_rustls::TlsConnector;
 
-use crate::crypto::{Identity, SecureChannel};
+use crate::capture::CaptureMode;
+use crate::clipboard::ClipboardAck;
+use crate::crypto::{self, Identity, SecureChannel};
 use crate::p2p::{attempt_p2p_connection, gather_p2p_info, choose_p2p_port};
-use crate::protocol::{self, Channel, Frame};
-use crate::transport::{ConnectionType, P2PInfo};
+use crate::input::{self, ScrollAccumulator};
+use crate::mux::{MuxStream, RelayMux};
+use crate::protocol::{self, Capabilities, Channel, ChannelState, Frame, FrameReader};
+use crate::qos::{QosManager, QualityLevel};
+use crate::sources::{self, CaptureSource, SourceKind};
+use crate::transport::{ConnectionType, DirectReader, DirectWriter, P2PInfo};
+
+/// How long to wait for the host's HELLO before assuming a pre-HELLO host
+const HELLO_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// How long to wait for each step of the identity check and handshake
+const IDENTITY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How long to wait for the host to check an unattended access password
+const PASSWORD_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How long to wait for the relay to answer an alias lookup
+const ALIAS_RESOLVE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How long to wait for the host to list its monitors or windows
+const SOURCES_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How long to wait for the host's answer to a P2P offer
+const P2P_ANSWER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Wait before retrying P2P on a session that is on the relay; doubles after each failure
+const P2P_RETRY_INITIAL: std::time::Duration = std::time::Duration::from_secs(30);
+const P2P_RETRY_MAX: std::time::Duration = std::time::Duration::from_secs(600);
+
+/// How long the viewport must keep its size before the host is told about it
+const RESOLUTION_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(200);
+
+/// Frame pacing for sessions at a fixed quality level (~30 FPS)
+const DEFAULT_FRAME_INTERVAL: std::time::Duration = std::time::Duration::from_millis(33);
+
+/// Most often a frame's capture stamp is echoed for the latency figures
+const TIMING_PROBE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// An echo left unanswered this long is given up on
+const TIMING_PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How often the host is pinged for the round trip (control::PING)
+const PING_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// JPEG quality of a frame put back together from tiles, or decoded from VP8/VP9
+const CANVAS_QUALITY: u8 = 90;
+
+/// A frame of the remote screen as encoded by the host
+pub struct RemoteFrame {
+    pub width: u16,
+    pub height: u16,
+    /// protocol::video::FORMAT_JPEG or FORMAT_PNG
+    pub format: u8,
+    pub data: Vec<u8>,
+    /// Capture time on the host's clock (0 from hosts that do not stamp frames)
+    pub captured_at: u64,
+    /// When the frame arrived here
+    pub received_at: std::time::Instant,
+}
+
+/// Figures for `get_session_stats`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SessionStats {
+    pub connection_type: String,
+    /// Smoothed frame request round trip (0 before the first frame)
+    pub frame_rtt_ms: u32,
+    /// Capture on the host to presentation here
+    pub latency: crate::latency::LatencyStats,
+}
+
+/// Figures for `get_connection_stats`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ConnectionStats {
+    /// Smoothed ping round trip; None before the first answer or once they stopped
+    pub rtt_ms: Option<u32>,
+    /// Adaptive quality state; None while a fixed quality level is set
+    pub qos: Option<crate::qos::QosStats>,
+}
+
+/// Privacy state in effect on the host, as it last acknowledged it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct PrivacyStatus {
+    pub black_screen: bool,
+    pub input_blocked: bool,
+}
+
+/// How a client session reaches the relay
+enum RelayLink {
+    /// A TLS connection of its own, and the reader that keeps its frame boundaries
+    Direct(Box<tokio_rustls::client::TlsStream<TcpStream>>, FrameReader),
+    /// A stream on a connection shared with other sessions
+    Mux(MuxStream),
+}
+
+impl RelayLink {
+    async fn read_frame(&mut self) -> Result<Frame> {
+        match self {
+            RelayLink::Direct(stream, frames) => frames.read(stream).await,
+            RelayLink::Mux(stream) => stream.read_frame().await,
+        }
+    }
+
+    async fn write_frame(&mut self, frame: Frame) -> Result<()> {
+        match self {
+            RelayLink::Direct(stream, _) => ClientSession::write_frame_to_stream(stream, frame).await,
+            RelayLink::Mux(stream) => stream.write_frame(frame).await,
+        }
+    }
+
+    /// Refuse longer frames from a host that fragments its messages
+    /// A shared connection's reader also carries other hosts' frames, so it keeps the protocol limit
+    fn limit_frames(&mut self, max_len: usize) {
+        if let RelayLink::Direct(_, frames) = self {
+            frames.set_max_frame_size(max_len);
+        }
+    }
+}
 
 /// Client session - controlling a remote PC
 pub struct ClientSession {
-    stream: Option<tokio_rustls::client::TlsStream<TcpStream>>,
-    p2p_stream: Option<TcpStream>,
+    stream: Option<RelayLink>,
+    /// Direct link, only ever held once its own Noise handshake has secured it
+    /// It carries the session from the host's P2P_READY on (`connection_type` P2P)
+    p2p_link: Option<(DirectReader, DirectWriter)>,
+    /// Video replies lost with a dropped direct link, still to be counted as unchanged frames
+    lost_video_replies: u32,
     channel: Option<SecureChannel>,
+    /// Data frames are refused until the handshake settles encryption
+    channel_state: ChannelState,
+    /// Host messages arriving in fragments (feature::FRAGMENTS)
+    fragments: protocol::Reassembler,
     remote_id: String,
     connection_type: ConnectionType,
+    /// Connection type changed since the UI last took it
+    connection_type_changed: bool,
+    /// Whether to look for a direct link to the host
+    p2p_enabled: bool,
+    /// Local port offered for P2P
+    p2p_port: u16,
+    /// Our identity, for the handshake on each new direct link
+    identity: Identity,
+    /// When to try moving from the relay to P2P next (None while on P2P or disabled)
+    next_p2p_attempt: Option<std::time::Instant>,
+    /// Wait after the next failed attempt
+    p2p_retry_delay: std::time::Duration,
+    /// Host elevation level, once reported
+    host_elevated: Option<bool>,
+    /// Chat messages received from the host, not yet handed to the UI
+    chat_inbox: Vec<String>,
+    /// Files being sent to the host
+    uploads: Vec<crate::transfer::Upload>,
+    next_upload_id: u32,
+    /// Upload progress not yet handed to the UI
+    transfer_updates: Vec<crate::transfer::Progress>,
+    /// Host display size from the latest RESOLUTION_CHANGED, not yet handed to the UI
+    resolution_change: Option<(u16, u16)>,
+    /// The host's latest cursor update, not yet handed to the UI; keeps an
+    /// image from an earlier one the UI has not taken either
+    cursor_update: Option<crate::cursor::Message>,
+    /// Why the host cannot capture its screen, while it cannot
+    capture_unavailable: Option<String>,
+    /// Capture availability changed since the UI last took it
+    capture_status_changed: bool,
+    /// Whether the host uses our input, from its latest INPUT_CONTROL, not yet handed to the UI
+    input_control_change: Option<bool>,
+    /// Privacy state from the host's latest STATUS_ACK (None until it sends one)
+    privacy_status: Option<PrivacyStatus>,
+    /// Privacy state changed since the UI last took it
+    privacy_status_changed: bool,
+    /// Keystrokes are pinned to the window last clicked on the host
+    focus_lock: bool,
+    /// The host's sound is wanted (control::AUDIO)
+    audio_enabled: bool,
+    /// Plays the host's sound while it is wanted (`audio` feature)
+    #[cfg(feature = "audio")]
+    audio: Option<crate::audio::Playback>,
+    /// Capabilities negotiated with the host
+    capabilities: Capabilities,
+    /// Turns precise scroll deltas into notches for hosts without PRECISE_SCROLL
+    legacy_scroll: ScrollAccumulator,
+    /// Host X25519 key, once verified against the device ID and handshake
+    remote_key: Option<[u8; 32]>,
+    /// Fingerprint of the host's keys, once verified
+    remote_fingerprint: Option<String>,
+    /// Frame and snapshot requests the host has not answered yet
+    frames_in_flight: u32,
+    /// Keep at most one frame request outstanding instead of one per call
+    coalesce_frames: bool,
+    /// Frame that arrived while waiting for something else, returned by the next request
+    ready_frame: Option<RemoteFrame>,
+    /// Pointer events held back to go out as one input::INPUT_BATCH
+    input_batch: Vec<Vec<u8>>,
+    /// How long pointer events are held back (zero = each is sent at once)
+    input_batch_window: std::time::Duration,
+    /// A batch was started and its flush has not been scheduled yet
+    batch_flush_due: bool,
+    /// Viewport size, sent once a resize settles
+    resolution: crate::debounce::Debouncer<(u16, u16)>,
+    /// A resize started and its flush has not been scheduled yet
+    resolution_flush_due: bool,
+    /// When the oldest unanswered frame request went out, for the RTT metric
+    frame_requested_at: Option<std::time::Instant>,
+    /// Capture stamp last echoed to the host and when, until it answers
+    timing_probe: Option<(u64, std::time::Instant)>,
+    /// When the last echo went out, to space them by TIMING_PROBE_INTERVAL
+    last_timing_probe: Option<std::time::Instant>,
+    /// Capture-to-presentation latency from the host's answers
+    latency: crate::latency::LatencyTracker,
+    /// Last whole frame from the host, decoded into `canvas` once tiles arrive
+    keyframe: Option<Vec<u8>>,
+    /// The remote screen with tiles patched in since `keyframe`
+    canvas: Option<crate::tiles::Canvas>,
+    /// Decodes the host's VP8/VP9 video, from its first frame on
+    #[cfg(feature = "codec")]
+    vpx_decoder: Option<crate::vpx::Decoder>,
+    /// Frame request round trips, for the stats
+    frame_rtt: crate::qos::RttTracker,
+    /// Ping round trips; None until the host first answers
+    ping_rtt: Option<crate::qos::RttTracker>,
+    /// When the last ping went out, to space them by PING_INTERVAL
+    last_ping: Option<std::time::Instant>,
+    /// Fixed quality level the session runs at; None while it adapts ("auto")
+    quality_level: Option<QualityLevel>,
+    /// Adapts JPEG quality to the frame round trip; None while a fixed level is set
+    qos: Option<QosManager>,
+    /// JPEG quality the host was last asked for
+    quality_sent: Option<u8>,
+    /// Adapted quality to send ahead of the next frame request
+    pending_quality: Option<u8>,
+    /// Quickest control round trip while connecting; seeds adaptive quality
+    connect_rtt: Option<std::time::Duration>,
+    /// Counts this session in the local metrics while it is alive
+    _metrics: crate::metrics::SessionGuard,
 }
 
 impl ClientSession {
     /// Connect to remote device via relay (with optional P2P upgrade)
+    /// `expected_key` is the host's X25519 key when pinned from an earlier session
     pub async fn connect(
         relay_address: String,
         remote_id: String,
         identity: Identity,
+        expected_key: Option<[u8; 32]>,
     ) -> Result<Self> {
-        Self::connect_with_p2p(relay_address, remote_id, identity, true).await
+        Self::connect_with_p2p(relay_address, remote_id, identity, expected_key, true).await
     }
 
     /// Connect to remote device with explicit P2P control
@@ -38,34 +263,16 @@ impl ClientSession {
         relay_address: String,
         remote_id: String,
         identity: Identity,
+        expected_key: Option<[u8; 32]>,
         p2p_enabled: bool,
     ) -> Result<Self> {
-        // Parse address
-        let (host, port) = relay_address
-            .rsplit_once(':')
-            .ok_or_else(|| anyhow::anyhow!("Invalid relay address"))?;
-        let port: u16 = port.parse()?;
-
-        // TLS setup
-        let mut root_store = RootCertStore::empty();
-        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-
-        let config = ClientConfig::builder()
-            .with_root_certificates(root_store)
-            .with_no_client_auth();
-
-        let connector = TlsConnector::from(Arc::new(config));
-
-        // Connect to relay
-        let tcp = TcpStream::connect(format!("{}:{}", host, port)).await?;
-        let server_name = tokio_rustls::rustls::pki_types::ServerName::try_from(host.to_owned())?;
-        let mut stream = connector.connect(server_name, tcp).await?;
+        let mut stream = Self::open_relay_stream(&relay_address).await?;
 
         // Register as technician wanting to connect to remote_id
         let my_id = identity.device_id_raw();
         let target_id = remote_id.replace(' ', "");
 
-        stream.write_u8(0x02).await?; // Technician type
+        stream.write_u8(protocol::relay::CLIENT_TECHNICIAN).await?;
         // Use big-endian for protocol compatibility with Go server
         stream.write_all(&(my_id.len() as u16).to_be_bytes()).await?;
         stream.write_all(my_id.as_bytes()).await?;
@@ -94,65 +301,555 @@ impl ClientSession {
             anyhow::bail!("Connection failed: {}", error_msg);
         }
 
-        // P2P negotiation (if enabled)
-        let mut connection_type = ConnectionType::Relay;
-        let mut p2p_stream: Option<TcpStream> = None;
+        Self::establish(RelayLink::Direct(Box::new(stream), FrameReader::new()), target_id, identity, expected_key, p2p_enabled).await
+    }
 
+    /// Connect to remote device over a relay connection shared with other sessions
+    pub async fn connect_multiplexed(
+        mux: &Arc<RelayMux>,
+        remote_id: String,
+        identity: Identity,
+        expected_key: Option<[u8; 32]>,
+    ) -> Result<Self> {
+        let target_id = remote_id.replace(' ', "");
+        let stream = mux.open_stream(&target_id).await?;
+        println!("[CLIENT] Connected to {} over shared relay connection {}", target_id, mux.relay_address());
+        Self::establish(RelayLink::Mux(stream), target_id, identity, expected_key, true).await
+    }
+
+    /// Set up a session once the relay has connected us to the host:
+    /// P2P upgrade, capability negotiation and identity verification
+    async fn establish(
+        link: RelayLink,
+        target_id: String,
+        identity: Identity,
+        expected_key: Option<[u8; 32]>,
+        p2p_enabled: bool,
+    ) -> Result<Self> {
+        let mut session = Self {
+            stream: Some(link),
+            p2p_link: None,
+            lost_video_replies: 0,
+            channel: None,
+            channel_state: ChannelState::Pending,
+            fragments: protocol::Reassembler::default(),
+            remote_id: target_id,
+            connection_type: ConnectionType::Relay,
+            // The initial result reaches the UI like any later change
+            connection_type_changed: true,
+            p2p_enabled,
+            p2p_port: choose_p2p_port(&identity.device_id_raw()),
+            identity: identity.clone(),
+            next_p2p_attempt: None,
+            p2p_retry_delay: P2P_RETRY_INITIAL,
+            host_elevated: None,
+            chat_inbox: Vec::new(),
+            uploads: Vec::new(),
+            next_upload_id: 1,
+            transfer_updates: Vec::new(),
+            resolution_change: None,
+            cursor_update: None,
+            capture_unavailable: None,
+            capture_status_changed: false,
+            input_control_change: None,
+            privacy_status: None,
+            privacy_status_changed: false,
+            focus_lock: false,
+            audio_enabled: false,
+            #[cfg(feature = "audio")]
+            audio: None,
+            capabilities: Capabilities::legacy(),
+            legacy_scroll: ScrollAccumulator::default(),
+            remote_key: None,
+            remote_fingerprint: None,
+            frames_in_flight: 0,
+            coalesce_frames: true,
+            ready_frame: None,
+            input_batch: Vec::new(),
+            input_batch_window: std::time::Duration::ZERO,
+            batch_flush_due: false,
+            resolution: crate::debounce::Debouncer::new(RESOLUTION_DEBOUNCE),
+            resolution_flush_due: false,
+            frame_requested_at: None,
+            timing_probe: None,
+            last_timing_probe: None,
+            latency: crate::latency::LatencyTracker::default(),
+            keyframe: None,
+            canvas: None,
+            #[cfg(feature = "codec")]
+            vpx_decoder: None,
+            frame_rtt: crate::qos::RttTracker::with_estimate(0),
+            ping_rtt: None,
+            last_ping: None,
+            quality_level: None,
+            qos: None,
+            quality_sent: None,
+            pending_quality: None,
+            connect_rtt: None,
+            _metrics: crate::metrics::SessionGuard::new(crate::metrics::SessionRole::Client),
+        };
+
+        session.negotiate_capabilities().await?;
+        session.verify_identity(&identity, expected_key).await?;
+        // After the identity check: a direct link is secured against the verified host key
         if p2p_enabled {
             println!("[CLIENT] P2P enabled, gathering P2P info...");
-            let p2p_port = choose_p2p_port(&my_id);
-            let local_info = gather_p2p_info(p2p_enabled, p2p_port).await;
-
-            // Send P2P offer to host via relay
-            let offer_data = local_info.encode();
-            let offer_frame = Frame::control(protocol::control::P2P_OFFER, &offer_data);
-            Self::write_frame_to_stream(&mut stream, offer_frame).await?;
-            println!("[CLIENT] Sent P2P offer");
-
-            // Wait for P2P answer from host
-            if let Ok(answer_frame) = Self::read_frame_from_stream(&mut stream).await {
-                if answer_frame.channel == Channel::Control
-                    && !answer_frame.payload.is_empty()
-                    && answer_frame.payload[0] == protocol::control::P2P_ANSWER
-                {
-                    if let Ok(remote_info) = P2PInfo::decode(&answer_frame.payload[1..]) {
-                        println!("[CLIENT] Received P2P answer: {:?}", remote_info);
-
-                        // Attempt P2P connection
-                        if let Ok(Some(transport)) = attempt_p2p_connection(&remote_info, &local_info).await {
-                            println!("[CLIENT] P2P connection established!");
-                            p2p_stream = Some(transport.stream);
-                            connection_type = ConnectionType::P2P;
-
-                            // Notify host that P2P is ready
-                            let ready_frame = Frame::control(protocol::control::P2P_READY, &[]);
-                            Self::write_frame_to_stream(&mut stream, ready_frame).await?;
-                        } else {
-                            println!("[CLIENT] P2P failed, using relay");
-                            let failed_frame = Frame::control(protocol::control::P2P_FAILED, &[]);
-                            Self::write_frame_to_stream(&mut stream, failed_frame).await?;
-                        }
-                    }
-                }
+            session.negotiate_p2p().await?;
+        }
+        Ok(session)
+    }
+
+    /// Offer the host a direct link and move to it if one can be opened
+    /// Runs at connect, and again mid-session while the session is on the relay
+    async fn negotiate_p2p(&mut self) -> Result<()> {
+        // Without a verified host key the link could not be secured
+        let Some(host_key) = self.remote_key else {
+            println!("[CLIENT] Session is not end-to-end encrypted, staying on relay");
+            self.next_p2p_attempt = None;
+            return Ok(());
+        };
+        if !self.capabilities.supports_feature(protocol::feature::DIRECT_FRAMES) {
+            println!("[CLIENT] Host cannot take frames over a direct link, staying on relay");
+            self.next_p2p_attempt = None;
+            return Ok(());
+        }
+        let local_info = gather_p2p_info(self.p2p_enabled, self.p2p_port).await;
+        self.write_frame(Frame::control(protocol::control::P2P_OFFER, &local_info.encode())).await?;
+        println!("[CLIENT] Sent P2P offer");
+
+        let answer = match tokio::time::timeout(P2P_ANSWER_TIMEOUT, self.read_control(protocol::control::P2P_ANSWER)).await {
+            Ok(frame) => frame?,
+            Err(_) => {
+                println!("[CLIENT] Host did not answer the P2P offer, using relay");
+                self.next_p2p_attempt = None;
+                return Ok(());
             }
+        };
+        let Ok(remote_info) = P2PInfo::decode(&answer.payload[1..]) else {
+            self.next_p2p_attempt = None;
+            return Ok(());
+        };
+        println!("[CLIENT] Received P2P answer: {:?}", remote_info);
+
+        let lan_addr = crate::lan::last_seen(&self.remote_id);
+        let attempt = attempt_p2p_connection(&remote_info, &local_info, &self.identity, &host_key, lan_addr).await;
+        crate::metrics::record_p2p_attempt(matches!(attempt, Ok(Some(_))));
+        if let Ok(Some(transport)) = attempt {
+            println!("[CLIENT] P2P connection established and secured");
+            self.p2p_link = Some(transport.into_direct()?);
+            self.next_p2p_attempt = None;
+            self.p2p_retry_delay = P2P_RETRY_INITIAL;
+            // Our last frame on the relay; the session counts as P2P once the
+            // host's P2P_READY says its frames follow on the link too
+            self.write_frame(Frame::control(protocol::control::P2P_READY, &[])).await
+        } else {
+            println!("[CLIENT] P2P failed, using relay (retry in {}s)", self.p2p_retry_delay.as_secs());
+            self.next_p2p_attempt = Some(std::time::Instant::now() + self.p2p_retry_delay);
+            self.p2p_retry_delay = (self.p2p_retry_delay * 2).min(P2P_RETRY_MAX);
+            self.write_frame(Frame::control(protocol::control::P2P_FAILED, &[])).await
         }
+    }
 
-        let session = Self {
-            stream: Some(stream),
-            p2p_stream,
-            channel: None,
-            remote_id: target_id,
-            connection_type,
+    /// Retry P2P on a relayed session when due; a dropped direct link is
+    /// noticed as it is read or written
+    /// A retry holds the session for the attempt (a few seconds at most)
+    pub async fn maintain_p2p(&mut self) -> Result<()> {
+        if self.next_p2p_attempt.is_some_and(|at| std::time::Instant::now() >= at) {
+            println!("[CLIENT] Retrying P2P to {}", self.remote_id);
+            self.negotiate_p2p().await?;
+        }
+        Ok(())
+    }
+
+    /// The host let us in: it answers a P2P offer with its addresses only from now on
+    fn accepted(&mut self) {
+        if self.next_p2p_attempt.is_some() {
+            self.next_p2p_attempt = Some(std::time::Instant::now());
+        }
+    }
+
+    /// Go back to the relay after the direct link failed
+    async fn drop_direct_link(&mut self, error: &anyhow::Error) -> Result<()> {
+        println!("[CLIENT] P2P link to {} lost ({}), back on relay", self.remote_id, error);
+        self.p2p_link = None;
+        // The host answered these over the link, if at all
+        self.lost_video_replies = self.frames_in_flight;
+        self.set_connection_type(ConnectionType::Relay);
+        self.p2p_retry_delay = P2P_RETRY_INITIAL;
+        self.next_p2p_attempt = Some(std::time::Instant::now() + self.p2p_retry_delay);
+        // The host moves its frames back to the relay
+        self.write_relay(Frame::control(protocol::control::P2P_FAILED, &[])).await
+    }
+
+    fn set_connection_type(&mut self, connection_type: ConnectionType) {
+        if self.connection_type != connection_type {
+            println!("[CLIENT] Connection to {} is now {}", self.remote_id, connection_type);
+            self.connection_type = connection_type;
+            self.connection_type_changed = true;
+        }
+    }
+
+    /// Take the connection type if it changed since the last call (the first call has the initial one)
+    pub fn take_connection_type_change(&mut self) -> Option<ConnectionType> {
+        std::mem::take(&mut self.connection_type_changed).then_some(self.connection_type)
+    }
+
+    /// Ask the relay which device ID an alias belongs to
+    /// Returns `Ok(None)` when the relay predates alias support (it closes the
+    /// connection without answering), so callers can fall back to ID-only
+    pub async fn resolve_alias(
+        relay_address: &str,
+        alias: &str,
+        identity: &Identity,
+    ) -> Result<Option<String>> {
+        let alias = protocol::relay::normalize_alias(alias)?;
+        let mut stream = Self::open_relay_stream(relay_address).await?;
+
+        let my_id = identity.device_id_raw();
+        stream.write_u8(protocol::relay::CLIENT_RESOLVE).await?;
+        stream.write_all(&(my_id.len() as u16).to_be_bytes()).await?;
+        stream.write_all(my_id.as_bytes()).await?;
+        stream.write_all(&(alias.len() as u16).to_be_bytes()).await?;
+        stream.write_all(alias.as_bytes()).await?;
+        stream.flush().await?;
+
+        let reply = match tokio::time::timeout(ALIAS_RESOLVE_TIMEOUT, Self::read_frame_from_stream(&mut stream)).await {
+            Ok(Ok(frame)) => frame,
+            Ok(Err(_)) | Err(_) => {
+                println!("[CLIENT] Relay {} does not support alias lookup", relay_address);
+                return Ok(None);
+            }
         };
 
-        Ok(session)
+        match reply.payload.split_first() {
+            Some((&protocol::relay::REPLY_RESOLVED, id)) if reply.channel == Channel::Control => {
+                Ok(Some(String::from_utf8_lossy(id).to_string()))
+            }
+            Some((&protocol::relay::REPLY_ERROR, message)) if reply.channel == Channel::Control => {
+                anyhow::bail!("Alias lookup failed: {}", String::from_utf8_lossy(message))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Open a TLS connection to a relay ("host:port")
+    pub(crate) async fn open_relay_stream(relay_address: &str) -> Result<tokio_rustls::client::TlsStream<TcpStream>> {
+        // Parse address
+        let (host, port) = relay_address
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Invalid relay address"))?;
+        let port: u16 = port.parse()?;
+
+        // TLS setup: webpki roots plus any configured relay CA
+        let connector = crate::tls::connector();
+
+        // Connect to relay
+        let tcp = crate::proxy::connect(host, port).await?;
+        let server_name = tokio_rustls::rustls::pki_types::ServerName::try_from(host.to_owned())?;
+        Ok(connector.connect(server_name, tcp).await?)
     }
 
-    /// Get the current connection type
+    /// Get the current connection type: P2P only while frames use the direct link
     pub fn connection_type(&self) -> ConnectionType {
         self.connection_type
     }
 
+    /// Capabilities negotiated with the host
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    /// Exchange HELLO with the host and keep the common capability set
+    /// Hosts that predate HELLO never answer; they are treated as `Capabilities::legacy`
+    async fn negotiate_capabilities(&mut self) -> Result<()> {
+        let sent = std::time::Instant::now();
+        self.write_frame(Capabilities::local().hello_frame()).await?;
+
+        let wait = async {
+            let frame = self.read_control(protocol::control::HELLO).await?;
+            Capabilities::decode(&frame.payload[1..])
+        };
+
+        match tokio::time::timeout(HELLO_TIMEOUT, wait).await {
+            Ok(Ok(peer)) => {
+                self.note_connect_rtt(sent.elapsed());
+                self.capabilities = Capabilities::local().negotiate(&peer);
+                println!("[CLIENT] Host HELLO: version {}, negotiated {:?}", peer.version, self.capabilities);
+                if self.capabilities.supports_feature(protocol::feature::FRAGMENTS) {
+                    if let Some(stream) = self.stream.as_mut() {
+                        stream.limit_frames(protocol::MAX_FRAGMENT_FRAME);
+                    }
+                }
+            }
+            Ok(Err(e)) => return Err(e),
+            Err(_) => {
+                println!("[CLIENT] No HELLO from host, using legacy protocol");
+                self.capabilities = Capabilities::legacy();
+            }
+        }
+        Ok(())
+    }
+
+    /// Keep the quickest of the round trips seen while connecting
+    fn note_connect_rtt(&mut self, rtt: std::time::Duration) {
+        self.connect_rtt = Some(self.connect_rtt.map_or(rtt, |best| best.min(rtt)));
+    }
+
+    /// Read frames until a control message of `kind` arrives, handling side frames on the way
+    async fn read_control(&mut self, kind: u8) -> Result<Frame> {
+        loop {
+            let frame = self.read_frame().await?;
+            if frame.channel == Channel::Control && frame.payload.first() == Some(&kind) {
+                return Ok(frame);
+            }
+            self.handle_side_frame(&frame);
+        }
+    }
+
+    /// Check that the host is the device we dialled, then switch to the encrypted channel
+    ///
+    /// The host's public keys must hash to the target device ID - and match
+    /// `expected_key` when pinned, since a 9-digit ID alone is brute-forceable -
+    /// and the Noise_XK handshake then proves the host holds the private key.
+    /// A relay routing us to any other endpoint fails here with "Identity mismatch".
+    async fn verify_identity(&mut self, identity: &Identity, expected_key: Option<[u8; 32]>) -> Result<()> {
+        // A host that cannot prove who it is could be anyone the relay put in
+        // its place, so there is no unencrypted fallback
+        if !self.capabilities.supports_feature(protocol::feature::IDENTITY) {
+            anyhow::bail!(
+                "Identity mismatch: host cannot prove it is device {} - it needs to be updated",
+                self.remote_id
+            );
+        }
+
+        let sent = std::time::Instant::now();
+        self.write_frame(Frame::control(protocol::control::IDENTITY, &[])).await?;
+        let frame = tokio::time::timeout(IDENTITY_TIMEOUT, self.read_control(protocol::control::IDENTITY))
+            .await
+            .map_err(|_| anyhow::anyhow!("Host did not send its identity"))??;
+        self.note_connect_rtt(sent.elapsed());
+        if frame.payload.len() < 65 {
+            anyhow::bail!("Invalid identity from host");
+        }
+        let x25519: [u8; 32] = frame.payload[1..33].try_into()?;
+        let ed25519: [u8; 32] = frame.payload[33..65].try_into()?;
+
+        if crypto::device_id_from_keys(&x25519, &ed25519).replace(' ', "") != self.remote_id {
+            anyhow::bail!("Identity mismatch: host keys do not belong to device {}", self.remote_id);
+        }
+        let fingerprint = crypto::fingerprint_from_keys(&x25519, &ed25519);
+        if expected_key.is_some_and(|key| key != x25519) {
+            anyhow::bail!(
+                "Identity mismatch: device {} presented a different key than the trusted one (fingerprint now {}) - \
+                 verify it with the remote user before trusting it again",
+                self.remote_id,
+                fingerprint,
+            );
+        }
+
+        // Noise_XK: -> e, es  <- e, ee  -> s, se
+        let mut handshake = identity.create_initiator(&x25519)?;
+        let mut buf = vec![0u8; 65535];
+
+        let len = handshake.write_message(&[], &mut buf)?;
+        let mut message = vec![protocol::control::HANDSHAKE];
+        message.extend_from_slice(&buf[..len]);
+        self.write_frame(Frame::new(Channel::Control, message)).await?;
+
+        let reply = tokio::time::timeout(IDENTITY_TIMEOUT, self.read_control(protocol::control::HANDSHAKE))
+            .await
+            .map_err(|_| anyhow::anyhow!("Host did not answer the handshake"))??;
+        handshake.read_message(&reply.payload[1..], &mut buf)
+            .map_err(|_| anyhow::anyhow!("Identity mismatch: host failed the key exchange for device {}", self.remote_id))?;
+
+        // The last message also carries our Ed25519 key, so the host can derive
+        // our device ID from keys the handshake proves (crypto::proven_device_id)
+        let len = handshake.write_message(&identity.verifying_key(), &mut buf)?;
+        let mut message = vec![protocol::control::HANDSHAKE];
+        message.extend_from_slice(&buf[..len]);
+        self.write_frame(Frame::new(Channel::Control, message)).await?;
+
+        if !handshake.is_handshake_finished() || handshake.get_remote_static() != Some(&x25519[..]) {
+            anyhow::bail!("Identity mismatch: handshake did not authenticate device {}", self.remote_id);
+        }
+
+        self.channel = Some(SecureChannel::from_handshake(handshake)?);
+        self.channel_state = ChannelState::Encrypted;
+        self.remote_key = Some(x25519);
+        self.remote_fingerprint = Some(fingerprint);
+        println!("[CLIENT] Verified identity of {}, secure channel established", self.remote_id);
+        Ok(())
+    }
+
+    /// Whether frames go through the end-to-end encrypted channel
+    pub fn is_encrypted(&self) -> bool {
+        self.channel_state.is_encrypted()
+    }
+
+    /// Host X25519 key, if its identity was verified (for pinning trusted devices)
+    pub fn remote_key(&self) -> Option<[u8; 32]> {
+        self.remote_key
+    }
+
+    /// Fingerprint of the host's keys, if its identity was verified
+    pub fn remote_fingerprint(&self) -> Option<&str> {
+        self.remote_fingerprint.as_deref()
+    }
+
+    /// Whether the host runs elevated (None until the host reports it)
+    pub fn host_elevated(&self) -> Option<bool> {
+        self.host_elevated
+    }
+
+    /// Take chat messages received from the host since the last call
+    pub fn take_chat_messages(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.chat_inbox)
+    }
+
+    /// Take upload progress since the last call
+    pub fn take_transfer_updates(&mut self) -> Vec<crate::transfer::Progress> {
+        std::mem::take(&mut self.transfer_updates)
+    }
+
+    /// Take the host's new display size, if it changed since the last call
+    pub fn take_resolution_change(&mut self) -> Option<(u16, u16)> {
+        self.resolution_change.take()
+    }
+
+    /// Take where the host's cursor is, if it moved or changed since the last call
+    pub fn take_cursor_update(&mut self) -> Option<crate::cursor::Message> {
+        self.cursor_update.take()
+    }
+
+    /// Take the host's capture availability if it changed since the last call:
+    /// `Some(Some(reason))` when capture failed, `Some(None)` once it works again
+    pub fn take_capture_status_change(&mut self) -> Option<Option<String>> {
+        std::mem::take(&mut self.capture_status_changed).then(|| self.capture_unavailable.clone())
+    }
+
+    /// Take whether the host uses our input, if it said so since the last call
+    /// (hosts shared by several viewers let one control and the rest watch)
+    pub fn take_input_control_change(&mut self) -> Option<bool> {
+        self.input_control_change.take()
+    }
+
+    /// Privacy state the host last acknowledged (None until it has answered a toggle)
+    pub fn privacy_status(&self) -> Option<PrivacyStatus> {
+        self.privacy_status
+    }
+
+    /// Take the host's privacy state if it changed since the last call
+    pub fn take_privacy_status_change(&mut self) -> Option<PrivacyStatus> {
+        std::mem::take(&mut self.privacy_status_changed).then_some(self.privacy_status).flatten()
+    }
+
+    /// Record state from frames that arrive outside a request/response pair
+    fn handle_side_frame(&mut self, frame: &Frame) {
+        match (frame.channel, frame.payload.first().copied()) {
+            (Channel::Control, Some(protocol::control::SESSION_ACCEPT)) => self.accepted(),
+            (Channel::Control, Some(protocol::control::ELEVATION_STATUS)) => {
+                if let Some(&elevated) = frame.payload.get(1) {
+                    println!("[CLIENT] Host elevation: {}", elevated != 0);
+                    self.host_elevated = Some(elevated != 0);
+                }
+            }
+            (Channel::Control, Some(protocol::control::RESOLUTION_CHANGED)) if frame.payload.len() >= 5 => {
+                let width = u16::from_le_bytes([frame.payload[1], frame.payload[2]]);
+                let height = u16::from_le_bytes([frame.payload[3], frame.payload[4]]);
+                println!("[CLIENT] Host resolution changed: {}x{}", width, height);
+                self.resolution_change = Some((width, height));
+            }
+            // Repeated with every retry on the host; only changes reach the UI
+            (Channel::Control, Some(protocol::control::CAPTURE_UNAVAILABLE)) => {
+                let reason = String::from_utf8_lossy(&frame.payload[1..]).to_string();
+                let unavailable = (!reason.is_empty()).then_some(reason);
+                if unavailable != self.capture_unavailable {
+                    match &unavailable {
+                        Some(reason) => println!("[CLIENT] Host cannot capture: {}", reason),
+                        None => println!("[CLIENT] Host capture available again"),
+                    }
+                    self.capture_unavailable = unavailable;
+                    self.capture_status_changed = true;
+                }
+            }
+            (Channel::Control, Some(protocol::control::FRAME_TIMING)) => {
+                let answer = crate::latency::decode_reply(&frame.payload[1..]);
+                if let (Some((captured_at, host_elapsed)), Some((probed, sent))) = (answer, self.timing_probe) {
+                    if captured_at == probed {
+                        self.timing_probe = None;
+                        let render_ms = crate::latency::decode_echo(&frame.payload[1..]).map_or(0, |(_, ms)| ms);
+                        self.latency.record(host_elapsed, crate::latency::elapsed_ms(sent), render_ms);
+                    }
+                }
+            }
+            (Channel::Control, Some(protocol::control::PONG)) => {
+                if let Some(sent) = frame.payload.get(1..9).and_then(|stamp| stamp.try_into().ok()) {
+                    let rtt_ms = crate::latency::clock_ms().saturating_sub(u64::from_le_bytes(sent));
+                    let rtt_ms = rtt_ms.min(u32::MAX as u64) as u32;
+                    self.ping_rtt.get_or_insert_with(crate::qos::RttTracker::new).add_sample(rtt_ms);
+                    self.record_rtt(rtt_ms);
+                }
+            }
+            (Channel::Control, Some(protocol::control::INPUT_CONTROL)) => {
+                if let Some(&granted) = frame.payload.get(1) {
+                    println!("[CLIENT] Host input control: {}", if granted != 0 { "granted" } else { "view only" });
+                    self.input_control_change = Some(granted != 0);
+                }
+            }
+            // Answer to a privacy toggle; the host reports what is actually in effect
+            (Channel::Privacy, Some(protocol::privacy::STATUS_ACK)) if frame.payload.len() >= 3 => {
+                let status = PrivacyStatus {
+                    black_screen: frame.payload[1] != 0,
+                    input_blocked: frame.payload[2] != 0,
+                };
+                if self.privacy_status != Some(status) {
+                    println!(
+                        "[CLIENT] Host privacy: black screen {}, input blocked {}",
+                        status.black_screen, status.input_blocked
+                    );
+                    self.privacy_status = Some(status);
+                    self.privacy_status_changed = true;
+                }
+            }
+            (Channel::Chat, Some(protocol::chat::MESSAGE)) => {
+                let text = String::from_utf8_lossy(&frame.payload[1..]).to_string();
+                self.chat_inbox.push(text);
+            }
+            (Channel::File, Some(_)) => match crate::transfer::Message::decode(&frame.payload) {
+                Ok(message) => self.handle_upload_answer(message),
+                Err(e) => eprintln!("[CLIENT] Ignoring file message: {}", e),
+            },
+            // Packets still in flight after muting are dropped
+            (Channel::Audio, Some(protocol::audio::PACKET)) => {
+                #[cfg(feature = "audio")]
+                if let Some(playback) = &mut self.audio {
+                    if let Err(e) = playback.push(&frame.payload) {
+                        eprintln!("[CLIENT] Dropping audio packet: {}", e);
+                    }
+                }
+            }
+            (Channel::Input, Some(protocol::input::CURSOR_UPDATE)) => {
+                match crate::cursor::Message::decode(&frame.payload) {
+                    Ok(mut update) => {
+                        if update.image.is_none() {
+                            update.image = self.cursor_update.take().and_then(|earlier| earlier.image);
+                        }
+                        self.cursor_update = Some(update);
+                    }
+                    Err(e) => eprintln!("[CLIENT] Ignoring cursor update: {}", e),
+                }
+            }
+            // Reply to an earlier frame request, read while waiting for something else
+            (Channel::Video, _) => {
+                self.frame_answered(frame.payload.len());
+                if let Some(remote_frame) = self.parse_video_frame(frame) {
+                    self.ready_frame = Some(remote_frame);
+                }
+            }
+            _ => {}
+        }
+    }
+
     /// Helper to write frame to stream
     async fn write_frame_to_stream(
         stream: &mut tokio_rustls::client::TlsStream<TcpStream>,
@@ -160,7 +857,7 @@ impl ClientSession {
     ) -> Result<()> {
         let len = frame.payload.len();
         let header = [
-            frame.channel as u8,
+            frame.channel_byte(),
             (len >> 16) as u8,
             (len >> 8) as u8,
             len as u8,
@@ -189,49 +886,87 @@ impl ClientSession {
         Ok(Frame::new(channel, payload))
     }
 
+    /// Next whole message from the host, reassembled if it came in fragments
     async fn read_frame(&mut self) -> Result<Frame> {
-        let stream = self.stream.as_mut().ok_or_else(|| anyhow::anyhow!("Not connected"))?;
+        loop {
+            if self.lost_video_replies > 0 {
+                self.lost_video_replies -= 1;
+                return Ok(Frame::video(vec![protocol::video::FRAME_UNCHANGED]));
+            }
+            if self.connection_type == ConnectionType::P2P {
+                if let Some((reader, _)) = self.p2p_link.as_mut() {
+                    match reader.read_frame().await {
+                        Ok(message) => {
+                            self.channel_state.check(message.channel)?;
+                            return Ok(message);
+                        }
+                        Err(e) => {
+                            self.drop_direct_link(&e).await?;
+                            continue;
+                        }
+                    }
+                }
+            }
 
-        let mut header = [0u8; 4];
-        stream.read_exact(&mut header).await?;
+            let stream = self.stream.as_mut().ok_or_else(|| anyhow::anyhow!("Not connected"))?;
+            let frame = stream.read_frame().await?;
+            crate::metrics::record_bytes_received(frame.payload.len() + 4);
 
-        let channel = Channel::try_from(header[0])?;
-        let len = ((header[1] as usize) << 16)
-            | ((header[2] as usize) << 8)
-            | (header[3] as usize);
+            self.channel_state.check(frame.channel)?;
 
-        let mut payload = vec![0u8; len];
-        stream.read_exact(&mut payload).await?;
-
-        let decrypted = if let Some(ref mut ch) = self.channel {
-            ch.decrypt(&payload)?
-        } else {
-            payload
-        };
+            let decrypted = if let Some(ref mut ch) = self.channel {
+                ch.decrypt(&frame.payload)?
+            } else {
+                frame.payload
+            };
 
-        Ok(Frame::new(channel, decrypted))
+            let fragment = Frame { payload: decrypted, ..frame };
+            if let Some(message) = self.fragments.push(fragment)? {
+                // The host's last frame on the relay: the rest come over the direct link
+                if message.channel == Channel::Control
+                    && message.payload == [protocol::control::P2P_READY]
+                    && self.p2p_link.is_some()
+                {
+                    self.set_connection_type(ConnectionType::P2P);
+                    continue;
+                }
+                return Ok(message);
+            }
+        }
     }
 
+    /// Send over the direct link while the session is on it, else the relay
     async fn write_frame(&mut self, frame: Frame) -> Result<()> {
-        let stream = self.stream.as_mut().ok_or_else(|| anyhow::anyhow!("Not connected"))?;
+        self.channel_state.check(frame.channel)?;
+        if self.connection_type == ConnectionType::P2P {
+            if let Some((_, writer)) = self.p2p_link.as_mut() {
+                match writer.write_frame(&frame).await {
+                    Ok(()) => return Ok(()),
+                    Err(e) => self.drop_direct_link(&e).await?,
+                }
+            }
+        }
+        self.write_relay(frame).await
+    }
 
-        let payload = if let Some(ref mut ch) = self.channel {
-            ch.encrypt(&frame.payload)?
-        } else {
-            frame.payload
-        };
+    /// Fragments are sealed one by one, so a message is not bound by the
+    /// secure channel's message size either
+    async fn write_relay(&mut self, frame: Frame) -> Result<()> {
+        let stream = self.stream.as_mut().ok_or_else(|| anyhow::anyhow!("Not connected"))?;
 
-        let len = payload.len();
-        let header = [
-            frame.channel as u8,
-            (len >> 16) as u8,
-            (len >> 8) as u8,
-            len as u8,
-        ];
+        let sealed = self.channel.is_some();
+        let fragment_size = self.capabilities.fragment_size()
+            .map_or(usize::MAX, |size| if sealed { size.min(SecureChannel::MAX_PLAINTEXT) } else { size });
+        for fragment in frame.into_fragments(fragment_size) {
+            let payload = if let Some(ref mut ch) = self.channel {
+                ch.encrypt(&fragment.payload)?
+            } else {
+                fragment.payload
+            };
 
-        stream.write_all(&header).await?;
-        stream.write_all(&payload).await?;
-        stream.flush().await?;
+            crate::metrics::record_bytes_sent(payload.len() + 4);
+            stream.write_frame(Frame { payload, ..fragment }).await?;
+        }
         Ok(())
     }
 
@@ -278,15 +1013,26 @@ impl ClientSession {
                 payload.extend(&x.to_le_bytes());
                 payload.extend(&y.to_le_bytes());
             }
-            "scroll" => {
-                payload.push(protocol::input::MOUSE_SCROLL);
+            "scroll" if self.capabilities.supports_feature(protocol::feature::PRECISE_SCROLL) => {
+                // High-resolution deltas, SCROLL_UNITS_PER_NOTCH per wheel notch
+                payload.push(protocol::input::MOUSE_SCROLL_PRECISE);
                 payload.extend(&x.to_le_bytes()); // delta_x
                 payload.extend(&y.to_le_bytes()); // delta_y
             }
+            "scroll" => {
+                // Older hosts only take whole notches
+                let (dx, dy) = self.legacy_scroll.push(x, y);
+                if dx == 0 && dy == 0 {
+                    return Ok(());
+                }
+                payload.push(protocol::input::MOUSE_SCROLL);
+                payload.extend(&dx.to_le_bytes());
+                payload.extend(&dy.to_le_bytes());
+            }
             _ => return Ok(()),
         }
 
-        self.write_frame(Frame::input(payload)).await
+        self.send_input(payload).await
     }
 
     /// Send keyboard event to remote
@@ -301,48 +1047,557 @@ impl ClientSession {
         payload.extend(&key_code.to_le_bytes());
         payload.push(0); // Modifiers
 
+        self.send_input(payload).await
+    }
+
+    /// Hold pointer events back for up to `window` and send them as one frame
+    /// Only used with hosts that replay INPUT_BATCH; zero sends every event at once
+    pub fn set_input_batching(&mut self, window: std::time::Duration) {
+        self.input_batch_window = window;
+    }
+
+    /// Delay after which `flush_input` should run, if a batch was started since the last call
+    pub fn take_batch_flush(&mut self) -> Option<std::time::Duration> {
+        std::mem::take(&mut self.batch_flush_due).then_some(self.input_batch_window)
+    }
+
+    /// Send any held-back pointer events
+    pub async fn flush_input(&mut self) -> Result<()> {
+        let mut events = std::mem::take(&mut self.input_batch);
+        match events.len() {
+            0 => Ok(()),
+            1 => self.write_frame(Frame::input(events.remove(0))).await,
+            _ => self.write_frame(Frame::input(input::encode_batch(&events))).await,
+        }
+    }
+
+    /// Send an input event, batching pointer motion when enabled
+    /// Anything else flushes the batch first so the host sees events in order
+    async fn send_input(&mut self, payload: Vec<u8>) -> Result<()> {
+        let batching = !self.input_batch_window.is_zero()
+            && self.capabilities.supports_feature(protocol::feature::INPUT_BATCH);
+        if batching && input::is_batchable(&payload) {
+            if self.input_batch.is_empty() {
+                self.batch_flush_due = true;
+            }
+            self.input_batch.push(payload);
+            if self.input_batch.len() >= input::MAX_BATCH_EVENTS {
+                self.flush_input().await?;
+            }
+            return Ok(());
+        }
+
+        self.flush_input().await?;
         self.write_frame(Frame::input(payload)).await
     }
 
-    /// Send client viewport resolution to host for adaptive scaling
-    pub async fn send_resolution(&mut self, width: u16, height: u16) -> Result<()> {
+    /// Note the client viewport resolution for the host's adaptive scaling
+    /// It goes out from `flush_resolution` once it stopped changing, so a
+    /// drag-resize sends one update instead of dozens
+    pub fn set_resolution(&mut self, width: u16, height: u16) {
+        if self.resolution.update((width, height), std::time::Instant::now()) {
+            self.resolution_flush_due = true;
+        }
+    }
+
+    /// Delay after which `flush_resolution` should run, if a resize started since the last call
+    pub fn take_resolution_flush(&mut self) -> Option<std::time::Duration> {
+        std::mem::take(&mut self.resolution_flush_due).then_some(RESOLUTION_DEBOUNCE)
+    }
+
+    /// Send the viewport resolution if it settled; otherwise how much longer to wait
+    pub async fn flush_resolution(&mut self) -> Result<Option<std::time::Duration>> {
+        let (width, height) = match self.resolution.poll(std::time::Instant::now()) {
+            crate::debounce::Poll::Idle => return Ok(None),
+            crate::debounce::Poll::Wait(delay) => return Ok(Some(delay)),
+            crate::debounce::Poll::Ready(size) => size,
+        };
         let mut payload = Vec::new();
         payload.extend(&width.to_le_bytes());
         payload.extend(&height.to_le_bytes());
-        self.write_frame(Frame::control(protocol::control::RESOLUTION, &payload)).await
+        self.write_frame(Frame::control(protocol::control::RESOLUTION, &payload)).await?;
+        Ok(None)
+    }
+
+    /// Set the JPEG quality (1-100) the host encodes frames with
+    pub async fn send_quality(&mut self, quality: u8) -> Result<()> {
+        self.write_frame(Frame::control(protocol::control::QUALITY, &[quality])).await?;
+        self.quality_sent = Some(quality);
+        Ok(())
+    }
+
+    /// Follow a quality setting: a fixed level stops adaptation, None ("auto")
+    /// lets the frame round trip steer the JPEG quality from here on
+    pub async fn set_quality_mode(&mut self, level: Option<QualityLevel>) -> Result<()> {
+        self.pending_quality = None;
+        self.quality_level = level;
+        match level {
+            Some(level) => {
+                self.qos = None;
+                self.send_quality_level(level).await
+            }
+            None => {
+                let qos = match self.connect_rtt {
+                    Some(rtt) => {
+                        let rtt_ms = rtt.as_millis().min(u32::MAX as u128) as u32;
+                        let direct = self.connection_type() == ConnectionType::P2P;
+                        let qos = QosManager::with_initial_estimate(rtt_ms, direct);
+                        println!(
+                            "[CLIENT] Connect RTT {}ms over {}, starting at {} FPS, JPEG quality {}",
+                            rtt_ms, self.connection_type(), qos.get_target_fps(), qos.get_jpeg_quality(),
+                        );
+                        qos
+                    }
+                    None => QosManager::new(),
+                };
+                let quality = qos.get_jpeg_quality();
+                self.qos = Some(qos);
+                if self.capabilities.supports_codec(protocol::codec::PNG) {
+                    self.write_frame(Frame::control(protocol::control::LOSSLESS, &[0])).await?;
+                }
+                self.send_quality(quality).await
+            }
+        }
+    }
+
+    /// How long to wait between frame requests: the adapted rate in "auto", ~30 FPS otherwise
+    pub fn frame_interval(&self) -> std::time::Duration {
+        self.qos.as_ref().map_or(DEFAULT_FRAME_INTERVAL, |qos| {
+            std::time::Duration::from_millis(qos.get_frame_interval_ms())
+        })
+    }
+
+    /// A frame is being handed to the UI: now and then echo its capture stamp
+    /// so the host can say how old it is (control::FRAME_TIMING)
+    pub async fn frame_presented(&mut self, frame: &RemoteFrame) -> Result<()> {
+        if frame.captured_at == 0 || !self.capabilities.supports_feature(protocol::feature::FRAME_TIMING) {
+            return Ok(());
+        }
+        // One echo at a time; a lost answer must not stop the measurements for good
+        if self.timing_probe.is_some_and(|(_, sent)| sent.elapsed() < TIMING_PROBE_TIMEOUT) {
+            return Ok(());
+        }
+        if self.last_timing_probe.is_some_and(|sent| sent.elapsed() < TIMING_PROBE_INTERVAL) {
+            return Ok(());
+        }
+
+        let render_ms = crate::latency::elapsed_ms(frame.received_at);
+        let echo = crate::latency::encode_echo(frame.captured_at, render_ms);
+        self.write_frame(Frame::control(protocol::control::FRAME_TIMING, &echo)).await?;
+        let now = std::time::Instant::now();
+        self.timing_probe = Some((frame.captured_at, now));
+        self.last_timing_probe = Some(now);
+        Ok(())
+    }
+
+    /// Ping the host if PING_INTERVAL passed; the answer is read with the video frames
+    pub async fn ping_if_due(&mut self) -> Result<()> {
+        if !self.capabilities.supports_feature(protocol::feature::PING) {
+            return Ok(());
+        }
+        if self.last_ping.is_some_and(|sent| sent.elapsed() < PING_INTERVAL) {
+            return Ok(());
+        }
+        let stamp = crate::latency::clock_ms().to_le_bytes();
+        self.write_frame(Frame::control(protocol::control::PING, &stamp)).await?;
+        self.last_ping = Some(std::time::Instant::now());
+        Ok(())
+    }
+
+    /// Round trip and adaptive quality figures for the UI
+    pub fn connection_stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            rtt_ms: self.ping_rtt.as_ref().filter(|tracker| !tracker.is_stale()).map(|tracker| tracker.get_rtt()),
+            qos: self.qos.as_ref().map(|qos| qos.get_stats()),
+        }
+    }
+
+    /// Latency and round trip figures for the UI
+    pub fn stats(&self) -> SessionStats {
+        SessionStats {
+            connection_type: self.connection_type().to_string(),
+            frame_rtt_ms: self.frame_rtt.get_rtt(),
+            latency: self.latency.stats(),
+        }
+    }
+
+    /// The `connection_quality` value the session follows
+    pub fn quality_setting(&self) -> &'static str {
+        self.quality_level.map_or("auto", |level| level.as_setting())
+    }
+
+    /// Apply a quality level, switching the host to PNG frames for `Lossless`
+    /// Hosts without PNG support get the best JPEG quality instead
+    pub async fn send_quality_level(&mut self, level: QualityLevel) -> Result<()> {
+        let lossless = level == QualityLevel::Lossless;
+        if self.capabilities.supports_codec(protocol::codec::PNG) {
+            self.write_frame(Frame::control(protocol::control::LOSSLESS, &[lossless as u8])).await?;
+        } else if lossless {
+            println!("[CLIENT] Host does not support lossless frames, using best JPEG quality");
+        }
+        self.send_quality(level.jpeg_quality()).await
+    }
+
+    /// Ask the host to relaunch elevated (the host user must approve the prompt)
+    pub async fn request_elevation(&mut self) -> Result<()> {
+        if !self.capabilities.supports_feature(protocol::feature::ELEVATION) {
+            anyhow::bail!("Remote host does not support elevation requests");
+        }
+        self.write_frame(Frame::control(protocol::control::ELEVATION_REQUEST, &[])).await
+    }
+
+    /// Ask the host user for input control (the answer arrives as INPUT_CONTROL)
+    pub async fn request_control(&mut self) -> Result<()> {
+        if !self.capabilities.supports_feature(protocol::feature::CONTROL_REQUEST) {
+            anyhow::bail!("Remote host does not take control requests");
+        }
+        self.write_frame(Frame::control(protocol::control::REQUEST_CONTROL, &[])).await
+    }
+
+    /// Pin keystrokes to the window last clicked on the host, so overlays and
+    /// dialogs that take the foreground don't get our typing
+    pub async fn set_focus_lock(&mut self, enabled: bool) -> Result<()> {
+        if !self.capabilities.supports_feature(protocol::feature::FOCUS_LOCK) {
+            anyhow::bail!("Remote host cannot pin keyboard focus");
+        }
+        self.write_frame(Frame::control(protocol::control::FOCUS_LOCK, &[enabled as u8])).await?;
+        self.focus_lock = enabled;
+        Ok(())
+    }
+
+    /// Whether keystrokes are pinned (None if the host cannot do it)
+    pub fn focus_lock(&self) -> Option<bool> {
+        self.capabilities
+            .supports_feature(protocol::feature::FOCUS_LOCK)
+            .then_some(self.focus_lock)
+    }
+
+    /// Play or mute the host's sound, at the rate negotiated in HELLO
+    #[cfg_attr(not(feature = "audio"), allow(unused_variables))]
+    pub async fn set_audio_enabled(&mut self, enabled: bool) -> Result<()> {
+        let Some(rate) = self.capabilities.audio_rate() else {
+            anyhow::bail!("Remote host does not send audio");
+        };
+        #[cfg(feature = "audio")]
+        {
+            if !enabled {
+                self.audio = None;
+            } else if self.audio.is_none() {
+                self.audio = Some(crate::audio::Playback::start(rate)?);
+            }
+        }
+        self.write_frame(Frame::control(protocol::control::AUDIO, &[enabled as u8])).await?;
+        self.audio_enabled = enabled;
+        Ok(())
+    }
+
+    /// Whether the host's sound plays (None if the host sends none)
+    pub fn audio_enabled(&self) -> Option<bool> {
+        self.capabilities.audio_rate().is_some().then_some(self.audio_enabled)
     }
 
     /// Request video frame
     pub async fn request_frame(&mut self) -> Result<()> {
-        self.write_frame(Frame::new(Channel::Video, vec![0x03])).await
+        self.write_frame(Frame::new(Channel::Video, vec![protocol::video::FRAME_REQUEST])).await
+    }
+
+    /// Keep at most one frame request outstanding (the default)
+    /// When disabled every call sends its own request and waits for its reply
+    pub fn set_frame_coalescing(&mut self, enabled: bool) {
+        self.coalesce_frames = enabled;
     }
 
     /// Request and receive a video frame from remote
-    /// Returns (width, height, jpeg_data) or None if no frame available
-    pub async fn request_and_receive_frame(&mut self) -> Result<Option<(u16, u16, Vec<u8>)>> {
-        // Send frame request
-        self.write_frame(Frame::new(Channel::Video, vec![0x03])).await?;
+    /// Returns None if no frame available
+    pub async fn request_and_receive_frame(&mut self) -> Result<Option<RemoteFrame>> {
+        if self.coalesce_frames {
+            // Already received while waiting for something else: no round trip needed
+            if let Some(frame) = self.ready_frame.take() {
+                return Ok(Some(frame));
+            }
+            // A caller that gave up left its request unanswered - wait for that
+            // reply rather than queue another and fall one frame behind for good
+            if self.frames_in_flight > 0 {
+                return self.receive_video_frame().await;
+            }
+        }
 
-        // Read response frame
-        let frame = self.read_frame().await?;
+        self.send_video_request(protocol::video::FRAME_REQUEST).await?;
+        self.receive_video_frame().await
+    }
 
-        if frame.channel != Channel::Video {
-            // Not a video frame, might be control message
-            return Ok(None);
+    /// Request a single full-quality keyframe from remote (overrides QoS quality)
+    /// Returns None if no frame available
+    pub async fn capture_snapshot(&mut self) -> Result<Option<RemoteFrame>> {
+        // Replies come back in order: collect outstanding frames first so the
+        // snapshot is not mistaken for one of them
+        while self.frames_in_flight > 0 {
+            if let Some(frame) = self.receive_video_frame().await? {
+                self.ready_frame = Some(frame);
+            }
+        }
+
+        self.send_video_request(protocol::video::SNAPSHOT_REQUEST).await?;
+        self.receive_video_frame().await
+    }
+
+    async fn send_video_request(&mut self, kind: u8) -> Result<()> {
+        // An adapted quality goes out first so this very frame uses it
+        if let Some(quality) = self.pending_quality.take() {
+            self.send_quality(quality).await?;
+        }
+        self.write_frame(Frame::new(Channel::Video, vec![kind])).await?;
+        // Only a request sent with nothing else pending is timed, so queueing
+        // behind earlier replies never counts as network delay
+        if self.frames_in_flight == 0 {
+            self.frame_requested_at = Some(std::time::Instant::now());
+        }
+        self.frames_in_flight += 1;
+        Ok(())
+    }
+
+    /// Note the reply to the oldest outstanding video request, `bytes` long
+    fn frame_answered(&mut self, bytes: usize) {
+        self.frames_in_flight = self.frames_in_flight.saturating_sub(1);
+        if let Some(qos) = self.qos.as_mut() {
+            qos.record_frame();
+            qos.record_bytes_sent(bytes);
+        }
+        if let Some(sent) = self.frame_requested_at.take() {
+            let rtt_ms = crate::latency::elapsed_ms(sent);
+            self.frame_rtt.add_sample(rtt_ms);
+            // What the reply took beyond the network round trip is the host's
+            // capture and encode, plus waiting behind frames still being sent
+            let network_ms = self.ping_rtt.as_ref().filter(|tracker| !tracker.is_stale()).map(|tracker| tracker.get_rtt());
+            if let (Some(qos), Some(network_ms)) = (self.qos.as_mut(), network_ms) {
+                qos.record_encode_time(rtt_ms.saturating_sub(network_ms));
+            }
+            // It includes capture and encoding on the host, so pings replace it
+            if !self.capabilities.supports_feature(protocol::feature::PING) {
+                self.record_rtt(rtt_ms);
+            }
+        }
+    }
+
+    /// Feed a round trip to the metrics and to adaptive quality
+    fn record_rtt(&mut self, rtt_ms: u32) {
+        crate::metrics::record_rtt(std::time::Duration::from_millis(rtt_ms as u64));
+        if let Some(qos) = self.qos.as_mut() {
+            qos.record_rtt(rtt_ms);
+            let quality = qos.get_jpeg_quality();
+            if self.quality_sent != Some(quality) {
+                self.pending_quality = Some(quality);
+            }
+        }
+    }
+
+    /// Read a video frame response and split it into its header and image
+    /// None when the host reports the screen unchanged or sends no image - keep showing the last frame
+    async fn receive_video_frame(&mut self) -> Result<Option<RemoteFrame>> {
+        // Read response frame; control messages such as RESOLUTION_CHANGED may
+        // arrive ahead of it, and the host still answers the request afterwards
+        let mut frame = self.read_frame().await?;
+        while frame.channel != Channel::Video {
+            self.handle_side_frame(&frame);
+            frame = self.read_frame().await?;
+        }
+        self.frame_answered(frame.payload.len());
+
+        Ok(self.parse_video_frame(&frame))
+    }
+
+    /// Split a video frame into its header and image
+    /// Tiles are patched into the last frame, which is handed on whole
+    fn parse_video_frame(&mut self, frame: &Frame) -> Option<RemoteFrame> {
+        // Identical to the previous frame: nothing to decode
+        if frame.payload.as_slice() == [protocol::video::FRAME_UNCHANGED] {
+            return None;
+        }
+        if frame.payload.first() == Some(&protocol::video::FRAME_TILES) {
+            return match self.apply_tiles(&frame.payload) {
+                Ok(frame) => Some(frame),
+                Err(e) => {
+                    // Shown again once the host sends a whole frame
+                    eprintln!("[CLIENT] Dropping video tiles: {}", e);
+                    None
+                }
+            };
+        }
+        #[cfg(feature = "codec")]
+        if frame.payload.first() == Some(&protocol::video::FRAME_VPX) {
+            return match self.decode_vpx(&frame.payload) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    // Deltas cannot be decoded until the next keyframe
+                    eprintln!("[CLIENT] Dropping VPX frame: {}", e);
+                    self.vpx_decoder = None;
+                    None
+                }
+            };
         }
 
         // Video frame format:
-        // [keyframe (1 byte)][width (2 bytes LE)][height (2 bytes LE)][timestamp (8 bytes)][data...]
-        if frame.payload.len() < 13 {
-            return Ok(None);
+        // [keyframe (1 byte)][width (2 bytes LE)][height (2 bytes LE)][timestamp (8 bytes)][format (1)][data...]
+        // The format byte is only present when codec::PNG was negotiated
+        let header_len = if self.capabilities.supports_codec(protocol::codec::PNG) { 14 } else { 13 };
+        if frame.payload.len() < header_len {
+            return None;
         }
 
         let width = u16::from_le_bytes([frame.payload[1], frame.payload[2]]);
         let height = u16::from_le_bytes([frame.payload[3], frame.payload[4]]);
-        // Skip timestamp (bytes 5-12)
-        let data = frame.payload[13..].to_vec();
+        let captured_at = u64::from_le_bytes(frame.payload[5..13].try_into().ok()?);
+        let format = if header_len == 14 { frame.payload[13] } else { protocol::video::FORMAT_JPEG };
+        let data = frame.payload[header_len..].to_vec();
+
+        // Header without image bytes (older hosts on capture timeout): keep the current frame
+        if data.is_empty() {
+            return None;
+        }
+
+        if self.capabilities.supports_feature(protocol::feature::DIRTY_TILES) {
+            self.keyframe = Some(data.clone());
+            self.canvas = None;
+        }
+
+        Some(RemoteFrame {
+            width,
+            height,
+            format,
+            data,
+            captured_at,
+            received_at: std::time::Instant::now(),
+        })
+    }
+
+    /// Patch a FRAME_TILES frame into the canvas and encode the result
+    fn apply_tiles(&mut self, payload: &[u8]) -> Result<RemoteFrame> {
+        let tiles = crate::tiles::decode(payload)?;
+        if self.canvas.is_none() {
+            let keyframe = self.keyframe.as_deref()
+                .ok_or_else(|| anyhow::anyhow!("No frame to patch tiles into"))?;
+            self.canvas = Some(crate::tiles::Canvas::from_image(keyframe)?);
+        }
+        let canvas = self.canvas.as_mut().expect("canvas was just set");
+        if let Err(e) = canvas.apply(&tiles) {
+            // Partly patched: wait for the next whole frame
+            self.canvas = None;
+            self.keyframe = None;
+            return Err(e);
+        }
+
+        let (width, height) = canvas.size();
+        Ok(RemoteFrame {
+            width: width as u16,
+            height: height as u16,
+            format: protocol::video::FORMAT_JPEG,
+            data: canvas.encode(CANVAS_QUALITY)?,
+            captured_at: tiles.captured_at,
+            received_at: std::time::Instant::now(),
+        })
+    }
+
+    /// Decode a FRAME_VPX frame and encode the picture for the UI
+    #[cfg(feature = "codec")]
+    fn decode_vpx(&mut self, payload: &[u8]) -> Result<Option<RemoteFrame>> {
+        let (packet, captured_at) = crate::vpx::Packet::parse(payload)?;
+        if self.vpx_decoder.is_none() {
+            if !packet.keyframe {
+                return Ok(None);
+            }
+            let codec = crate::vpx::VpxCodec::negotiated(&self.capabilities)
+                .ok_or_else(|| anyhow::anyhow!("No video codec negotiated"))?;
+            self.vpx_decoder = Some(crate::vpx::Decoder::new(codec)?);
+        }
+        let decoder = self.vpx_decoder.as_mut().expect("decoder was just set");
+        let Some((width, height, rgb)) = decoder.decode(&packet.data)? else {
+            return Ok(None);
+        };
 
-        Ok(Some((width, height, data)))
+        let picture = crate::tiles::Canvas::from_rgb(width, height, rgb)?;
+        Ok(Some(RemoteFrame {
+            width: width as u16,
+            height: height as u16,
+            format: protocol::video::FORMAT_JPEG,
+            data: picture.encode(CANVAS_QUALITY)?,
+            captured_at,
+            received_at: std::time::Instant::now(),
+        }))
+    }
+
+    /// Ask the host which monitors or windows it can capture
+    pub async fn list_remote_sources(&mut self, kind: SourceKind) -> Result<Vec<CaptureSource>> {
+        if !self.capabilities.supports_feature(protocol::feature::CAPTURE_SOURCES) {
+            anyhow::bail!("Remote does not support listing capture sources");
+        }
+
+        self.write_frame(Frame::control(protocol::control::SOURCES_REQUEST, &[kind as u8])).await?;
+        let frame = tokio::time::timeout(SOURCES_TIMEOUT, self.read_control(protocol::control::SOURCES))
+            .await
+            .map_err(|_| anyhow::anyhow!("Remote did not list its capture sources"))??;
+        let (_, listed) = sources::decode(&frame.payload[1..])?;
+        Ok(listed)
+    }
+
+    /// Ask the host to capture another monitor (an index from `list_remote_sources`)
+    /// or all of them; frames at the new size follow once it answered
+    pub async fn set_remote_capture_mode(&mut self, mode: CaptureMode) -> Result<()> {
+        if !self.capabilities.supports_feature(protocol::feature::MONITOR_SELECT) {
+            anyhow::bail!("Remote does not support switching monitors");
+        }
+
+        self.write_frame(Frame::control(protocol::control::SELECT_MONITOR, &mode.to_wire().to_le_bytes())).await?;
+        let frame = tokio::time::timeout(SOURCES_TIMEOUT, self.read_control(protocol::control::SELECT_MONITOR))
+            .await
+            .map_err(|_| anyhow::anyhow!("Remote did not switch monitors"))??;
+        match frame.payload.get(5).copied() {
+            Some(protocol::control::SELECT_MONITOR_OK) => Ok(()),
+            Some(protocol::control::SELECT_MONITOR_DENIED) => {
+                anyhow::bail!("Only the viewer in control can switch monitors")
+            }
+            _ => match mode {
+                CaptureMode::Single(index) => anyhow::bail!("Remote could not capture monitor {}", index),
+                CaptureMode::AllMonitors => anyhow::bail!("Remote could not capture all monitors"),
+            },
+        }
+    }
+
+    /// Whether the host admits viewers only with its access password
+    pub fn password_required(&self) -> bool {
+        self.capabilities.supports_feature(protocol::feature::PASSWORD_REQUIRED)
+    }
+
+    /// Give the host its unattended access password and wait to be let in
+    pub async fn authenticate(&mut self, password: &str) -> Result<()> {
+        if !self.password_required() {
+            anyhow::bail!("Remote host does not ask for a password");
+        }
+        if !self.channel_state.is_encrypted() {
+            anyhow::bail!("Refusing to send the access password unencrypted");
+        }
+        self.write_frame(Frame::control(protocol::control::AUTH_PASSWORD, password.as_bytes())).await?;
+
+        let wait = async {
+            loop {
+                let frame = self.read_frame().await?;
+                match (frame.channel, frame.payload.first().copied()) {
+                    (Channel::Control, Some(protocol::control::SESSION_ACCEPT)) => {
+                        self.accepted();
+                        return Ok(());
+                    }
+                    (Channel::Control, Some(protocol::control::SESSION_END)) => {
+                        if frame.payload.get(1) == Some(&protocol::control::END_REASON_BAD_PASSWORD) {
+                            anyhow::bail!("Wrong access password");
+                        }
+                        anyhow::bail!("Host refused the connection");
+                    }
+                    _ => self.handle_side_frame(&frame),
+                }
+            }
+        };
+        tokio::time::timeout(PASSWORD_TIMEOUT, wait)
+            .await
+            .map_err(|_| anyhow::anyhow!("Timed out waiting for the host to check the password"))?
     }
 
     /// Send clipboard data to remote
@@ -350,15 +1605,122 @@ impl ClientSession {
         self.write_frame(Frame::clipboard(protocol::clipboard::CLIPBOARD_DATA, data)).await
     }
 
+    /// Wait for the host to acknowledge clipboard data sent with `send_clipboard`
+    /// Frames other than the acknowledgement (e.g. stale privacy acks) are skipped
+    pub async fn receive_clipboard_ack(&mut self, timeout: std::time::Duration) -> Result<ClipboardAck> {
+        let wait = async {
+            loop {
+                let frame = self.read_frame().await?;
+                if frame.channel == Channel::Clipboard
+                    && frame.payload.first() == Some(&protocol::clipboard::CLIPBOARD_ACK)
+                {
+                    return ClipboardAck::decode(&frame.payload[1..]);
+                }
+                self.handle_side_frame(&frame);
+            }
+        };
+
+        tokio::time::timeout(timeout, wait)
+            .await
+            .map_err(|_| anyhow::anyhow!("Timed out waiting for clipboard acknowledgement"))?
+    }
+
+    /// Send a chat message to the host
+    pub async fn send_chat(&mut self, text: &str) -> Result<()> {
+        if !self.capabilities.supports_channel(Channel::Chat) {
+            anyhow::bail!("Remote host does not support chat");
+        }
+        let frame = protocol::chat::message_frame(text)?;
+        self.write_frame(frame).await
+    }
+
+    /// Offer a file to the host; chunks follow from `send_file_chunks` once it accepts
+    /// Returns the transfer's id
+    pub async fn send_file(&mut self, path: &std::path::Path) -> Result<u32> {
+        if !self.capabilities.supports_feature(protocol::feature::FILE_TRANSFER) {
+            anyhow::bail!("Remote host does not support file transfer");
+        }
+        let upload = crate::transfer::Upload::open(self.next_upload_id, path)?;
+        self.next_upload_id += 1;
+        self.write_frame(upload.offer().frame()).await?;
+        println!("[CLIENT] Offered {} ({} bytes)", upload.name, upload.size);
+
+        let id = upload.id;
+        self.transfer_updates.push(upload.progress(crate::transfer::State::Waiting, None));
+        self.uploads.push(upload);
+        Ok(id)
+    }
+
+    /// Send what the ACK window allows of every upload; called between frames
+    /// so a large file takes turns with video
+    pub async fn send_file_chunks(&mut self) -> Result<()> {
+        for index in 0..self.uploads.len() {
+            loop {
+                let message = match self.uploads[index].next_message() {
+                    Ok(Some(message)) => message,
+                    Ok(None) => break,
+                    Err(e) => {
+                        // Unreadable now: the host drops what it has, and so do we
+                        let cancel = crate::transfer::Message::Cancel { id: self.uploads[index].id, reason: e.to_string() };
+                        self.write_frame(cancel.frame()).await?;
+                        self.handle_upload_answer(cancel);
+                        return Ok(());
+                    }
+                };
+                self.write_frame(message.frame()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The host's answer to an upload
+    fn handle_upload_answer(&mut self, message: crate::transfer::Message) {
+        use crate::transfer::{Message, State};
+
+        let id = match &message {
+            Message::Accept { id } | Message::Ack { id, .. } | Message::Done { id, .. } => *id,
+            Message::Reject { id, .. } | Message::Cancel { id, .. } => *id,
+            _ => return,
+        };
+        let Some(index) = self.uploads.iter().position(|upload| upload.id == id) else {
+            return;
+        };
+        let upload = &mut self.uploads[index];
+        let update = match message {
+            Message::Accept { .. } => {
+                upload.accepted();
+                upload.progress(State::Transferring, None)
+            }
+            Message::Ack { received, .. } => {
+                upload.acked(received);
+                upload.progress(State::Transferring, None)
+            }
+            Message::Done { .. } => {
+                println!("[CLIENT] Host saved {}", upload.name);
+                let upload = self.uploads.remove(index);
+                upload.progress(State::Completed, None)
+            }
+            Message::Reject { reason, .. } | Message::Cancel { reason, .. } => {
+                eprintln!("[CLIENT] Upload of {} failed: {}", upload.name, reason);
+                let upload = self.uploads.remove(index);
+                upload.progress(State::Failed, Some(reason))
+            }
+            _ => return,
+        };
+        self.transfer_updates.push(update);
+    }
+
     /// Request clipboard from remote
     pub async fn request_clipboard(&mut self) -> Result<()> {
         self.write_frame(Frame::clipboard(protocol::clipboard::CLIPBOARD_REQUEST, &[])).await
     }
 
     /// Disconnect session
-    pub async fn disconnect(mut self) -> Result<()> {
+    /// Later writes fail with "Not connected"
+    pub async fn disconnect(&mut self) -> Result<()> {
         self.write_frame(Frame::control(protocol::control::SESSION_END, &[])).await?;
-        if let Some(mut stream) = self.stream.take() {
+        // A multiplexed stream is released when dropped; the shared connection stays up
+        if let Some(RelayLink::Direct(mut stream, _)) = self.stream.take() {
             let _ = stream.shutdown().await;
         }
         Ok(())
@@ -3,16 +3,55 @@
 
 use anyhow::Result;
 use parking_lot::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-/// Maximum clipboard data size (10 MB)
+/// Maximum clipboard data size (10 MB); keeps a clipboard in one frame for
+/// peers that never fragment
 pub const MAX_CLIPBOARD_SIZE: usize = 10 * 1024 * 1024;
 
+/// Default clipboard size limit (5 MB)
+pub const DEFAULT_MAX_CLIPBOARD_SIZE: usize = 5 * 1024 * 1024;
+
+/// Largest encoded clipboard sent or accepted
+static MAX_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_CLIPBOARD_SIZE);
+
+/// Set the clipboard size limit in MB (at least 1, at most `MAX_CLIPBOARD_SIZE`)
+pub fn set_max_size_mb(mb: u32) {
+    let bytes = (mb.max(1) as usize).saturating_mul(1024 * 1024);
+    MAX_SIZE.store(bytes.min(MAX_CLIPBOARD_SIZE), Ordering::Relaxed);
+}
+
+/// Current clipboard size limit in bytes
+pub fn max_size() -> usize {
+    MAX_SIZE.load(Ordering::Relaxed)
+}
+
 /// Clipboard data types
 #[derive(Debug, Clone, PartialEq)]
 pub enum ClipboardData {
     Text(String),
     Image { width: u32, height: u32, data: Vec<u8> }, // PNG data
     Files(Vec<String>), // File paths
+    /// Formatted text (browser HTML, word processor RTF) with its plain-text form
+    Rich { text: String, html: Option<String>, rtf: Option<String> },
+}
+
+/// Append a length-prefixed (u32 LE) field
+fn push_field(data: &mut Vec<u8>, field: &[u8]) {
+    data.extend(&(field.len() as u32).to_le_bytes());
+    data.extend(field);
+}
+
+/// Read a length-prefixed (u32 LE) field, returning it and the remaining bytes
+fn read_field(payload: &[u8]) -> Result<(&[u8], &[u8])> {
+    if payload.len() < 4 {
+        anyhow::bail!("Invalid rich clipboard data");
+    }
+    let len = u32::from_le_bytes([payload[0], payload[1], payload[2], payload[3]]) as usize;
+    if payload.len() < 4 + len {
+        anyhow::bail!("Incomplete rich clipboard data");
+    }
+    Ok((&payload[4..4 + len], &payload[4 + len..]))
 }
 
 impl ClipboardData {
@@ -45,6 +84,96 @@ impl ClipboardData {
                 data.extend(path_bytes);
                 data
             }
+            ClipboardData::Rich { text, html, rtf } => {
+                let html = html.as_deref().unwrap_or("");
+                let rtf = rtf.as_deref().unwrap_or("");
+                let mut data = Vec::with_capacity(13 + text.len() + html.len() + rtf.len());
+                data.push(crate::protocol::clipboard::DATA_TYPE_RICH);
+                push_field(&mut data, text.as_bytes());
+                push_field(&mut data, html.as_bytes());
+                push_field(&mut data, rtf.as_bytes());
+                data
+            }
+        }
+    }
+
+    /// Length of `encode()`'s output, without encoding
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            ClipboardData::Text(text) => 5 + text.len(),
+            ClipboardData::Image { data, .. } => 13 + data.len(),
+            ClipboardData::Files(paths) => 5 + paths.iter().map(|p| p.len() + 1).sum::<usize>().saturating_sub(1),
+            ClipboardData::Rich { text, html, rtf } => {
+                13 + text.len() + html.as_ref().map_or(0, |h| h.len()) + rtf.as_ref().map_or(0, |r| r.len())
+            }
+        }
+    }
+
+    /// This data within `max_size` encoded bytes: as it is, or for an image
+    /// re-compressed as PNG. None if it cannot be made to fit
+    pub fn fit(&self, max_size: usize) -> Option<ClipboardData> {
+        if self.encoded_len() <= max_size {
+            return Some(self.clone());
+        }
+        let ClipboardData::Image { data, .. } = self else {
+            return None;
+        };
+
+        use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+        use image::ImageEncoder;
+
+        let image = image::load_from_memory(data).ok()?;
+        let mut png = Vec::new();
+        PngEncoder::new_with_quality(&mut png, CompressionType::Best, FilterType::Adaptive)
+            .write_image(image.as_bytes(), image.width(), image.height(), image.color())
+            .ok()?;
+        let compressed = ClipboardData::Image { width: image.width(), height: image.height(), data: png };
+        (compressed.encoded_len() <= max_size).then_some(compressed)
+    }
+
+    /// Deserialize clipboard data, refusing it if the content it declares is
+    /// over `max_size` bytes before anything is copied
+    pub fn decode_within(data: &[u8], max_size: usize) -> Result<Self> {
+        let declared = Self::declared_len(data);
+        if data.len() > max_size || declared > max_size {
+            anyhow::bail!("Clipboard data too large ({} bytes, max {})", data.len().max(declared), max_size);
+        }
+        Self::decode(data)
+    }
+
+    /// Content length the headers claim, 0 where they are missing
+    fn declared_len(data: &[u8]) -> usize {
+        let field = |at: usize| {
+            data.get(at..at + 4)
+                .map_or(0, |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+        };
+        match data.first().copied() {
+            Some(crate::protocol::clipboard::DATA_TYPE_TEXT) | Some(crate::protocol::clipboard::DATA_TYPE_FILES) => field(1),
+            Some(crate::protocol::clipboard::DATA_TYPE_IMAGE) => field(9),
+            Some(crate::protocol::clipboard::DATA_TYPE_RICH) => {
+                let mut at = 1;
+                let mut total = 0usize;
+                for _ in 0..3 {
+                    let len = field(at);
+                    total = total.saturating_add(len);
+                    at = at.saturating_add(4).saturating_add(len);
+                }
+                total
+            }
+            _ => 0,
+        }
+    }
+
+    /// The best representation a peer can accept
+    /// Peers without RICH_CLIPBOARD get the plain-text form of formatted content
+    pub fn for_peer(&self, capabilities: &crate::protocol::Capabilities) -> ClipboardData {
+        match self {
+            ClipboardData::Rich { text, .. }
+                if !capabilities.supports_feature(crate::protocol::feature::RICH_CLIPBOARD) =>
+            {
+                ClipboardData::Text(text.clone())
+            }
+            data => data.clone(),
         }
     }
 
@@ -97,6 +226,19 @@ impl ClipboardData {
                 let paths: Vec<String> = paths_str.lines().map(|s| s.to_string()).collect();
                 Ok(ClipboardData::Files(paths))
             }
+            crate::protocol::clipboard::DATA_TYPE_RICH => {
+                let (text, rest) = read_field(payload)?;
+                let (html, rest) = read_field(rest)?;
+                let (rtf, _) = read_field(rest)?;
+                let optional = |field: &[u8]| {
+                    (!field.is_empty()).then(|| String::from_utf8_lossy(field).to_string())
+                };
+                Ok(ClipboardData::Rich {
+                    text: String::from_utf8_lossy(text).to_string(),
+                    html: optional(html),
+                    rtf: optional(rtf),
+                })
+            }
             _ => anyhow::bail!("Unknown clipboard data type: {}", data_type),
         }
     }
@@ -107,6 +249,57 @@ impl ClipboardData {
             ClipboardData::Text(_) => "text",
             ClipboardData::Image { .. } => "image",
             ClipboardData::Files(_) => "files",
+            ClipboardData::Rich { .. } => "rich",
+        }
+    }
+}
+
+/// Acknowledgement sent back after applying remote clipboard data
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipboardAck {
+    pub success: bool,
+    /// DATA_TYPE_* of the received data (0 if it could not be decoded)
+    pub data_type: u8,
+    /// Size of the encoded clipboard payload
+    pub byte_count: u32,
+    pub error: Option<String>,
+}
+
+impl ClipboardAck {
+    /// Serialize acknowledgement for transmission
+    pub fn encode(&self) -> Vec<u8> {
+        let error = self.error.as_deref().unwrap_or("");
+        let mut data = Vec::with_capacity(6 + error.len());
+        data.push(self.success as u8);
+        data.push(self.data_type);
+        data.extend(&self.byte_count.to_le_bytes());
+        data.extend(error.as_bytes());
+        data
+    }
+
+    /// Deserialize acknowledgement from transmission
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < 6 {
+            anyhow::bail!("Invalid clipboard acknowledgement");
+        }
+
+        let error = String::from_utf8_lossy(&data[6..]).to_string();
+        Ok(Self {
+            success: data[0] != 0,
+            data_type: data[1],
+            byte_count: u32::from_le_bytes([data[2], data[3], data[4], data[5]]),
+            error: if error.is_empty() { None } else { Some(error) },
+        })
+    }
+
+    /// Get data type name for display
+    pub fn type_name(&self) -> &'static str {
+        match self.data_type {
+            crate::protocol::clipboard::DATA_TYPE_TEXT => "text",
+            crate::protocol::clipboard::DATA_TYPE_IMAGE => "image",
+            crate::protocol::clipboard::DATA_TYPE_FILES => "files",
+            crate::protocol::clipboard::DATA_TYPE_RICH => "rich",
+            _ => "unknown",
         }
     }
 }
@@ -148,6 +341,12 @@ impl ClipboardManager {
                 2u8.hash(&mut hasher);
                 files.hash(&mut hasher);
             }
+            ClipboardData::Rich { text, html, rtf } => {
+                3u8.hash(&mut hasher);
+                text.hash(&mut hasher);
+                html.hash(&mut hasher);
+                rtf.hash(&mut hasher);
+            }
         }
         hasher.finish()
     }
@@ -199,3 +398,94 @@ impl Default for ClipboardManager {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clipboard_ack_roundtrip() {
+        let ack = ClipboardAck {
+            success: false,
+            data_type: crate::protocol::clipboard::DATA_TYPE_IMAGE,
+            byte_count: 4096,
+            error: Some("Clipboard is locked".to_string()),
+        };
+        let decoded = ClipboardAck::decode(&ack.encode()).unwrap();
+        assert_eq!(decoded, ack);
+        assert_eq!(decoded.type_name(), "image");
+
+        let ok = ClipboardAck { success: true, data_type: 0, byte_count: 0, error: None };
+        assert_eq!(ClipboardAck::decode(&ok.encode()).unwrap().error, None);
+        assert!(ClipboardAck::decode(&[1, 1]).is_err());
+    }
+
+    #[test]
+    fn test_rich_clipboard_roundtrip_and_fallback() {
+        use crate::protocol::Capabilities;
+
+        let rich = ClipboardData::Rich {
+            text: "Hello".to_string(),
+            html: Some("<b>Hello</b>".to_string()),
+            rtf: None,
+        };
+        assert_eq!(ClipboardData::decode(&rich.encode()).unwrap(), rich);
+        assert!(ClipboardData::decode(&rich.encode()[..6]).is_err());
+
+        assert_eq!(rich.for_peer(&Capabilities::local()), rich);
+        assert_eq!(rich.for_peer(&Capabilities::legacy()), ClipboardData::Text("Hello".to_string()));
+    }
+
+    #[test]
+    fn test_oversized_clipboard_is_rejected() {
+        use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+        use image::{ColorType, ImageEncoder};
+
+        // A 1000x1000 gradient saved fast and unfiltered: megabytes that
+        // proper compression takes down to a few KB
+        let gradient: Vec<u8> = (0..1000 * 1000 * 3).map(|i| (i % 251) as u8).collect();
+        let mut png = Vec::new();
+        PngEncoder::new_with_quality(&mut png, CompressionType::Fast, FilterType::NoFilter)
+            .write_image(&gradient, 1000, 1000, ColorType::Rgb8)
+            .unwrap();
+        let image = ClipboardData::Image { width: 1000, height: 1000, data: png };
+        assert_eq!(image.encoded_len(), image.encode().len());
+
+        let limit = 64 * 1024;
+        assert!(image.encoded_len() > limit);
+        let fitted = image.fit(limit).unwrap();
+        assert!(fitted.encoded_len() <= limit);
+        assert!(matches!(&fitted, ClipboardData::Image { width: 1000, height: 1000, data } if data.starts_with(b"\x89PNG")));
+        assert_eq!(image.fit(image.encoded_len()), Some(image.clone()));
+
+        // Noise does not compress: the sync is skipped rather than sent oversized
+        let mut seed = 1u32;
+        let noise: Vec<u8> = (0..300 * 300 * 3)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png).write_image(&noise, 300, 300, ColorType::Rgb8).unwrap();
+        assert_eq!(ClipboardData::Image { width: 300, height: 300, data: png }.fit(limit), None);
+
+        // Text cannot shrink, and a receiver refuses it without touching the payload
+        let text = ClipboardData::Text("x".repeat(limit));
+        assert_eq!(text.fit(limit), None);
+        let encoded = text.encode();
+        assert!(ClipboardData::decode_within(&encoded, limit).is_err());
+        assert_eq!(ClipboardData::decode_within(&encoded, encoded.len()).unwrap(), text);
+
+        // A header claiming more than the limit fails even when the bytes are short
+        let mut lying = ClipboardData::Rich { text: "a".into(), html: Some("b".into()), rtf: None }.encode();
+        lying[1..5].copy_from_slice(&(limit as u32 + 1).to_le_bytes());
+        let error = ClipboardData::decode_within(&lying, limit).unwrap_err();
+        assert!(error.to_string().contains("too large"));
+
+        let files = ClipboardData::Files(vec!["/a".into(), "/b/c".into()]);
+        assert_eq!(files.encoded_len(), files.encode().len());
+        let rich = ClipboardData::Rich { text: "t".into(), html: None, rtf: Some("r".into()) };
+        assert_eq!(rich.encoded_len(), rich.encode().len());
+    }
+}
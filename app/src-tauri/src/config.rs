@@ -2,9 +2,12 @@
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Shortest unattended access password accepted
+pub const MIN_ACCESS_PASSWORD_LEN: usize = 8;
 
 /// Trusted device information
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +20,52 @@ pub struct TrustedDevice {
     pub trusted_at: u64,
     /// Last connected time
     pub last_connected: Option<u64>,
+    /// Session preferences restored on reconnect
+    #[serde(default)]
+    pub preferences: DevicePreferences,
+    /// Host X25519 key (base64), pinned on the first verified connection
+    #[serde(default)]
+    pub public_key: Option<String>,
+    /// Fingerprint of the host keys, pinned with `public_key` for out-of-band checks
+    #[serde(default)]
+    pub fingerprint: Option<String>,
+}
+
+/// A viewer this host lets in without asking, accepted with "Always"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AllowedViewer {
+    /// Device ID (9-digit) it connected as
+    pub device_id: String,
+    /// When it was allowed
+    pub allowed_at: u64,
+}
+
+/// Per-device session preferences
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DevicePreferences {
+    /// Preferred monitor index on the remote
+    #[serde(default = "default_zero")]
+    pub monitor_index: u32,
+    /// Quality level ("auto", "quality", "balanced", "speed")
+    #[serde(default = "default_quality")]
+    pub quality: String,
+    /// Whether remote audio should be played
+    #[serde(default = "default_false")]
+    pub audio_enabled: bool,
+    /// Watch only - don't send mouse/keyboard input
+    #[serde(default = "default_false")]
+    pub view_only: bool,
+}
+
+impl Default for DevicePreferences {
+    fn default() -> Self {
+        Self {
+            monitor_index: 0,
+            quality: "auto".to_string(),
+            audio_enabled: false,
+            view_only: false,
+        }
+    }
 }
 
 /// Application settings
@@ -35,24 +84,121 @@ pub struct AppSettings {
     pub p2p_enabled: bool,
     #[serde(default = "default_quality")]
     pub connection_quality: String,
+    // Keep one frame request in flight rather than one per UI poll
+    #[serde(default = "default_true")]
+    pub coalesce_frame_requests: bool,
+    // Share one relay connection between sessions (relays without support are skipped)
+    #[serde(default = "default_false")]
+    pub relay_multiplexing: bool,
+    // Frames held back to smooth out bursty links (0 = off, lowest latency)
+    #[serde(default = "default_zero")]
+    pub jitter_buffer_frames: u32,
 
     // Security settings
     #[serde(default = "default_true")]
     pub require_approval: bool,
     #[serde(default = "default_false")]
     pub lock_on_disconnect: bool,
+    // Seconds without input from a viewer before its session is ended (0 = never)
     #[serde(default = "default_zero")]
     pub session_timeout: u32,
+    // Seconds a connection request waits for an answer before it is declined (0 = decline at once)
+    #[serde(default = "default_approval_timeout")]
+    pub approval_timeout_secs: u32,
+    // Accepted viewers watch until they ask for control and the user grants it
+    #[serde(default = "default_false")]
+    pub start_view_only: bool,
 
     // Privacy settings
     #[serde(default = "default_false")]
     pub hide_from_address_book: bool,
+
+    // Snapshot settings (empty = default Pictures/SecureDesk folder)
+    #[serde(default = "default_empty")]
+    pub snapshot_directory: String,
+
+    // Capture settings ("auto", "dxgi" or "gdi"; only Windows has a choice)
+    #[serde(default = "default_capture_backend")]
+    pub capture_backend: String,
+    // How JPEG quality follows screen content ("auto", "text", "photo" or "off")
+    #[serde(default = "default_content_mode")]
+    pub content_mode: String,
+    // Capture rate while the screen changes, and after it has been static for a while
+    #[serde(default = "default_capture_active_fps")]
+    pub capture_active_fps: u32,
+    #[serde(default = "default_capture_idle_fps")]
+    pub capture_idle_fps: u32,
+
+    // Input settings (scroll sensitivity in percent, 100 = unchanged)
+    #[serde(default = "default_scroll_sensitivity")]
+    pub scroll_sensitivity: u32,
+    #[serde(default = "default_false")]
+    pub natural_scroll: bool,
+    // Window in ms for sending pointer moves/scrolls as one frame (0 = off)
+    #[serde(default = "default_zero")]
+    pub input_batch_ms: u32,
+
+    // Clipboard settings (largest clipboard synced, in MB; larger images are
+    // re-compressed first)
+    #[serde(default = "default_max_clipboard_size_mb")]
+    pub max_clipboard_size_mb: u32,
+
+    // Recording settings (empty = default app-data recordings folder)
+    #[serde(default = "default_empty")]
+    pub recordings_dir: String,
+
+    // Proxy for relay connections ("http://host:port" or "socks5://host:port";
+    // empty = HTTPS_PROXY/ALL_PROXY from the environment, "none" = always direct)
+    #[serde(default = "default_empty")]
+    pub proxy_url: String,
+
+    // PEM file of extra CA certificates for self-hosted relays (empty = public roots only)
+    #[serde(default = "default_empty")]
+    pub relay_ca_cert: String,
+    // Skip relay certificate checks entirely - testing only, never for production
+    #[serde(default = "default_false")]
+    pub relay_tls_insecure: bool,
+
+    // Local Prometheus endpoint on 127.0.0.1 (pull-only, nothing is reported out)
+    #[serde(default = "default_false")]
+    pub metrics_enabled: bool,
+    #[serde(default = "default_metrics_port")]
+    pub metrics_port: u32,
+
+    // Local control socket for `securedesk input` (127.0.0.1, token-protected)
+    #[serde(default = "default_false")]
+    pub automation_enabled: bool,
+
+    // Host reconnects after losing the relay: give up after this many attempts
+    // (0 = keep trying), waiting at most this long between them
+    #[serde(default = "default_zero")]
+    pub reconnect_max_attempts: u32,
+    #[serde(default = "default_reconnect_max_backoff")]
+    pub reconnect_max_backoff_secs: u32,
+
+    // License server: portal keys are exchanged for signed licenses here; the
+    // revocation list is always license::REVOCATION_URL
+    #[serde(default = "default_license_activation_url")]
+    pub license_activation_url: String,
 }
 
 fn default_true() -> bool { true }
 fn default_false() -> bool { false }
 fn default_zero() -> u32 { 0 }
 fn default_quality() -> String { "auto".to_string() }
+fn default_empty() -> String { String::new() }
+fn default_capture_backend() -> String { "auto".to_string() }
+fn default_content_mode() -> String { "auto".to_string() }
+fn default_capture_active_fps() -> u32 { 30 }
+fn default_capture_idle_fps() -> u32 { 2 }
+fn default_scroll_sensitivity() -> u32 { 100 }
+fn default_approval_timeout() -> u32 { 30 }
+fn default_max_clipboard_size_mb() -> u32 { (crate::clipboard::DEFAULT_MAX_CLIPBOARD_SIZE / (1024 * 1024)) as u32 }
+fn default_metrics_port() -> u32 { crate::metrics::DEFAULT_METRICS_PORT as u32 }
+fn default_reconnect_max_backoff() -> u32 { 120 }
+fn default_license_activation_url() -> String { DEFAULT_LICENSE_ACTIVATION_URL.to_string() }
+
+const DEFAULT_LICENSE_ACTIVATION_URL: &str = "https://api.securedesk.one/api/license/issue";
 
 impl Default for AppSettings {
     fn default() -> Self {
@@ -62,14 +208,76 @@ impl Default for AppSettings {
             show_notifications: true,
             p2p_enabled: true,
             connection_quality: "auto".to_string(),
+            coalesce_frame_requests: true,
+            relay_multiplexing: false,
+            jitter_buffer_frames: 0,
             require_approval: true,
             lock_on_disconnect: false,
             session_timeout: 0,
+            approval_timeout_secs: 30,
+            start_view_only: false,
             hide_from_address_book: false,
+            snapshot_directory: String::new(),
+            capture_backend: "auto".to_string(),
+            content_mode: "auto".to_string(),
+            capture_active_fps: 30,
+            capture_idle_fps: 2,
+            scroll_sensitivity: 100,
+            natural_scroll: false,
+            input_batch_ms: 0,
+            max_clipboard_size_mb: default_max_clipboard_size_mb(),
+            recordings_dir: String::new(),
+            proxy_url: String::new(),
+            relay_ca_cert: String::new(),
+            relay_tls_insecure: false,
+            metrics_enabled: false,
+            metrics_port: default_metrics_port(),
+            automation_enabled: false,
+            reconnect_max_attempts: 0,
+            reconnect_max_backoff_secs: default_reconnect_max_backoff(),
+            license_activation_url: default_license_activation_url(),
+        }
+    }
+}
+
+impl AppSettings {
+    /// Resolve the folder remote snapshots are saved to
+    pub fn snapshot_dir(&self) -> PathBuf {
+        if !self.snapshot_directory.is_empty() {
+            return PathBuf::from(&self.snapshot_directory);
+        }
+
+        dirs::picture_dir()
+            .or_else(dirs::home_dir)
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("SecureDesk")
+    }
+
+    /// Resolve the folder session recordings are stored in
+    /// Falls back to the default app-data folder when unset or not writable
+    pub fn recordings_path(&self) -> Result<PathBuf> {
+        if !self.recordings_dir.is_empty() {
+            let dir = PathBuf::from(&self.recordings_dir);
+            if is_writable_dir(&dir) {
+                return Ok(dir);
+            }
+            eprintln!("[CONFIG] Recordings folder {:?} is not writable, using default", dir);
         }
+        crate::recording::SessionRecorder::recordings_directory()
     }
 }
 
+/// Check that a directory exists (creating it if needed) and accepts new files
+fn is_writable_dir(dir: &Path) -> bool {
+    if fs::create_dir_all(dir).is_err() {
+        return false;
+    }
+    let probe = dir.join(".securedesk_write_test");
+    let writable = fs::write(&probe, b"").is_ok();
+    let _ = fs::remove_file(&probe);
+    writable
+}
+
 /// Connection configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectionConfig {
@@ -79,10 +287,25 @@ pub struct ConnectionConfig {
     #[serde(default = "default_true")]
     pub p2p_enabled: bool,
 
-    /// Trusted devices that auto-accept connections
+    /// Hosts this device connects to, with their pinned keys and preferences
     #[serde(default)]
     pub trusted_devices: HashMap<String, TrustedDevice>,
 
+    /// Viewers whose connection requests this host accepts without asking,
+    /// by the X25519 key (base64) they prove in the handshake
+    #[serde(default)]
+    pub allowed_viewers: BTreeMap<String, AllowedViewer>,
+
+    /// Blocked devices whose connection requests are refused without asking
+    #[serde(default)]
+    pub blocked_devices: BTreeSet<String>,
+
+    /// Salted argon2 hash of the unattended access password (PHC string)
+    /// While set, viewers that send the password are accepted without asking
+    /// and every other viewer is refused
+    #[serde(default)]
+    pub unattended_password: Option<String>,
+
     /// Application settings
     #[serde(default)]
     pub settings: AppSettings,
@@ -90,6 +313,23 @@ pub struct ConnectionConfig {
     /// Device alias (friendly name)
     #[serde(default)]
     pub alias: Option<String>,
+
+    /// Whether to ask STUN servers for our public address (default: true)
+    /// If false, P2P only offers the LAN address and no third party is contacted
+    #[serde(default = "default_true")]
+    pub stun_enabled: bool,
+
+    /// STUN servers (host:port) queried for public address discovery
+    #[serde(default = "crate::stun::default_servers")]
+    pub stun_servers: Vec<String>,
+
+    /// Per-key overrides of the VK to macOS/X11 key tables used when hosting
+    #[serde(default)]
+    pub keymap: crate::keymap::KeymapOverrides,
+
+    /// Host windows remote viewers may not control, first match wins
+    #[serde(default)]
+    pub control_policy: Vec<crate::policy::PolicyRule>,
 }
 
 impl Default for ConnectionConfig {
@@ -97,8 +337,15 @@ impl Default for ConnectionConfig {
         Self {
             p2p_enabled: true, // P2P enabled by default for faster connections
             trusted_devices: HashMap::new(),
+            allowed_viewers: BTreeMap::new(),
+            blocked_devices: BTreeSet::new(),
+            unattended_password: None,
             settings: AppSettings::default(),
             alias: None,
+            stun_enabled: true,
+            stun_servers: crate::stun::default_servers(),
+            keymap: crate::keymap::KeymapOverrides::default(),
+            control_policy: Vec::new(),
         }
     }
 }
@@ -153,6 +400,110 @@ impl ConnectionConfig {
         self.save()
     }
 
+    /// Apply the STUN settings to the discovery module
+    pub fn apply_stun(&self) {
+        crate::stun::configure(self.stun_enabled, &self.stun_servers);
+    }
+
+    /// Push the proxy setting to the proxy module
+    pub fn apply_proxy(&self) {
+        crate::proxy::configure(&self.settings.proxy_url);
+    }
+
+    /// Push the relay certificate settings to the TLS module
+    pub fn apply_tls(&self) {
+        crate::tls::configure(&self.settings.relay_ca_cert, self.settings.relay_tls_insecure);
+    }
+
+    /// Start or stop the local metrics endpoint to match the settings
+    pub fn apply_metrics(&self) {
+        crate::metrics::configure(self.settings.metrics_enabled, self.settings.metrics_port as u16);
+    }
+
+    /// Start or stop the local automation socket to match the settings
+    pub fn apply_automation(&self) {
+        crate::ipc::configure(self.settings.automation_enabled);
+    }
+
+    /// Push the connection approval timeout to the host module
+    pub fn apply_approval_timeout(&self) {
+        crate::host::set_approval_timeout(self.settings.approval_timeout_secs);
+    }
+
+    /// Push the idle session timeout to the host module
+    pub fn apply_session_timeout(&self) {
+        crate::host::set_session_timeout(self.settings.session_timeout);
+    }
+
+    /// Push whether ending sessions lock the workstation to the host module
+    pub fn apply_lock_on_disconnect(&self) {
+        crate::host::set_lock_on_disconnect(self.settings.lock_on_disconnect);
+    }
+
+    /// Push whether accepted viewers start view-only to the host module
+    pub fn apply_start_view_only(&self) {
+        crate::host::set_start_view_only(self.settings.start_view_only);
+    }
+
+    /// Push the clipboard size limit to the clipboard module
+    pub fn apply_clipboard_limit(&self) {
+        crate::clipboard::set_max_size_mb(self.settings.max_clipboard_size_mb);
+    }
+
+    /// Push the active/idle capture rates to the capture module
+    pub fn apply_capture_rates(&self) {
+        crate::capture::set_frame_rates(self.settings.capture_active_fps, self.settings.capture_idle_fps);
+    }
+
+    /// Push the key remapping overrides to the keymap module
+    pub fn apply_keymap(&self) {
+        crate::keymap::configure(&self.keymap);
+    }
+
+    /// Push the window control policy to the policy module
+    pub fn apply_control_policy(&self) {
+        crate::policy::configure(&self.control_policy);
+    }
+
+    /// Replace the window control policy and save
+    pub fn set_control_policy(&mut self, rules: Vec<crate::policy::PolicyRule>) -> Result<()> {
+        self.control_policy = crate::policy::normalize_rules(rules)?;
+        self.apply_control_policy();
+        self.save()
+    }
+
+    /// Push the quality host sessions start at to the capture module
+    /// "auto" starts balanced; the viewer adapts it from there
+    pub fn apply_connection_quality(&self) {
+        let level = crate::qos::QualityLevel::from_setting(&self.settings.connection_quality)
+            .unwrap_or(crate::qos::QualityLevel::Balanced);
+        crate::capture::set_default_quality(level.jpeg_quality());
+    }
+
+    /// Set STUN enabled and save
+    pub fn set_stun_enabled(&mut self, enabled: bool) -> Result<()> {
+        self.stun_enabled = enabled;
+        self.apply_stun();
+        self.save()
+    }
+
+    /// Replace the STUN server list and save
+    /// Every entry must be a resolvable host:port
+    pub fn set_stun_servers(&mut self, servers: Vec<String>) -> Result<()> {
+        let servers: Vec<String> = servers
+            .into_iter()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        for server in &servers {
+            crate::stun::validate_server(server)?;
+        }
+
+        self.stun_servers = servers;
+        self.apply_stun();
+        self.save()
+    }
+
     /// Get the device alias
     pub fn get_alias(&self) -> Option<&String> {
         self.alias.as_ref()
@@ -164,6 +515,14 @@ impl ConnectionConfig {
         self.save()
     }
 
+    /// Alias to claim on the relay, or None if unset or hidden from the address book
+    pub fn relay_alias(&self) -> Option<String> {
+        if self.settings.hide_from_address_book {
+            return None;
+        }
+        self.alias.clone()
+    }
+
     /// Check if a device is trusted
     pub fn is_trusted(&self, device_id: &str) -> bool {
         let clean_id = device_id.replace(' ', "");
@@ -178,11 +537,17 @@ impl ConnectionConfig {
             .unwrap_or_default()
             .as_secs();
 
+        if self.blocked_devices.remove(&clean_id) {
+            self.apply_blocked_devices();
+        }
         self.trusted_devices.insert(clean_id.clone(), TrustedDevice {
             device_id: clean_id,
             name,
             trusted_at: now,
             last_connected: Some(now),
+            preferences: DevicePreferences::default(),
+            public_key: None,
+            fingerprint: None,
         });
         self.save()
     }
@@ -194,6 +559,97 @@ impl ConnectionConfig {
         self.save()
     }
 
+    /// Let the viewer holding `client_key` in without asking from now on, and save
+    pub fn allow_viewer(&mut self, client_key: &[u8; 32], device_id: &str) -> Result<()> {
+        use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+        let allowed_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.allowed_viewers.insert(STANDARD.encode(client_key), AllowedViewer {
+            device_id: device_id.replace(' ', ""),
+            allowed_at,
+        });
+        self.apply_allowed_viewers();
+        self.save()
+    }
+
+    /// Ask about a device's requests again; false if it was not allowed
+    pub fn remove_allowed_viewer(&mut self, device_id: &str) -> Result<bool> {
+        let clean_id = device_id.replace(' ', "");
+        let before = self.allowed_viewers.len();
+        self.allowed_viewers.retain(|_, viewer| viewer.device_id != clean_id);
+        if self.allowed_viewers.len() == before {
+            return Ok(false);
+        }
+        self.apply_allowed_viewers();
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Push the allowed viewer keys to the host module
+    pub fn apply_allowed_viewers(&self) {
+        use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+        crate::host::set_allowed_viewers(self.allowed_viewers.keys()
+            .filter_map(|key| STANDARD.decode(key).ok()?.try_into().ok())
+            .collect());
+    }
+
+    /// Block a device (it stops being trusted or allowed) and save
+    pub fn block_device(&mut self, device_id: &str) -> Result<()> {
+        let clean_id = device_id.replace(' ', "");
+        if clean_id.is_empty() {
+            anyhow::bail!("Device ID is empty");
+        }
+        self.trusted_devices.remove(&clean_id);
+        self.allowed_viewers.retain(|_, viewer| viewer.device_id != clean_id);
+        self.blocked_devices.insert(clean_id);
+        self.apply_blocked_devices();
+        self.apply_allowed_viewers();
+        self.save()
+    }
+
+    /// Unblock a device and save
+    pub fn unblock_device(&mut self, device_id: &str) -> Result<()> {
+        let clean_id = device_id.replace(' ', "");
+        self.blocked_devices.remove(&clean_id);
+        self.apply_blocked_devices();
+        self.save()
+    }
+
+    /// Get all blocked device IDs
+    pub fn get_blocked_devices(&self) -> Vec<&String> {
+        self.blocked_devices.iter().collect()
+    }
+
+    /// Push the blocked device list to the host module
+    pub fn apply_blocked_devices(&self) {
+        crate::host::set_blocked_devices(self.blocked_devices.iter().cloned().collect());
+    }
+
+    /// Store a new unattended access password (as its hash) and save
+    pub fn set_unattended_password(&mut self, password: &str) -> Result<()> {
+        if password.chars().count() < MIN_ACCESS_PASSWORD_LEN {
+            anyhow::bail!("Access password must be at least {} characters", MIN_ACCESS_PASSWORD_LEN);
+        }
+        self.unattended_password = Some(crate::crypto::hash_access_password(password)?);
+        self.save()
+    }
+
+    /// Turn unattended access off and save
+    pub fn clear_unattended_password(&mut self) -> Result<()> {
+        self.unattended_password = None;
+        self.save()
+    }
+
+    /// Push the unattended access password to the host module; without the
+    /// license every viewer needs approval again
+    pub fn apply_unattended_access(&self, licensed: bool) {
+        crate::host::set_unattended_password(self.unattended_password.clone().filter(|_| licensed));
+    }
+
     /// Update last connected time for a device
     pub fn update_last_connected(&mut self, device_id: &str) -> Result<()> {
         let clean_id = device_id.replace(' ', "");
@@ -209,11 +665,63 @@ impl ConnectionConfig {
         Ok(())
     }
 
+    /// Pinned host key of a trusted device, if one was recorded
+    pub fn trusted_device_key(&self, device_id: &str) -> Option<[u8; 32]> {
+        use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+        let clean_id = device_id.replace(' ', "");
+        let encoded = self.trusted_devices.get(&clean_id)?.public_key.as_ref()?;
+        STANDARD.decode(encoded).ok()?.try_into().ok()
+    }
+
+    /// Pin the host key and fingerprint of a trusted device (first verified connection only) and save
+    pub fn pin_device_key(&mut self, device_id: &str, key: &[u8; 32], fingerprint: Option<&str>) -> Result<()> {
+        use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+        let clean_id = device_id.replace(' ', "");
+        match self.trusted_devices.get_mut(&clean_id) {
+            Some(device) if device.public_key.is_none() => {
+                device.public_key = Some(STANDARD.encode(key));
+                device.fingerprint = fingerprint.map(str::to_string);
+                self.save()
+            }
+            // Pinned before fingerprints were recorded; the key was just verified
+            Some(device) if device.fingerprint.is_none() && fingerprint.is_some() => {
+                device.fingerprint = fingerprint.map(str::to_string);
+                self.save()
+            }
+            _ => Ok(()),
+        }
+    }
+
     /// Get all trusted devices
     pub fn get_trusted_devices(&self) -> Vec<&TrustedDevice> {
         self.trusted_devices.values().collect()
     }
 
+    /// Get saved session preferences for a trusted device
+    pub fn get_device_preferences(&self, device_id: &str) -> Option<&DevicePreferences> {
+        let clean_id = device_id.replace(' ', "");
+        self.trusted_devices.get(&clean_id).map(|d| &d.preferences)
+    }
+
+    /// Quality a session to a device starts with: a fixed level saved for the
+    /// device wins, otherwise `connection_quality`; None means adapt ("auto")
+    pub fn session_quality(&self, device_id: &str) -> Option<crate::qos::QualityLevel> {
+        self.get_device_preferences(device_id)
+            .and_then(|p| crate::qos::QualityLevel::from_setting(&p.quality))
+            .or_else(|| crate::qos::QualityLevel::from_setting(&self.settings.connection_quality))
+    }
+
+    /// Update session preferences for a trusted device and save
+    pub fn set_device_preferences(&mut self, device_id: &str, preferences: DevicePreferences) -> Result<()> {
+        let clean_id = device_id.replace(' ', "");
+        let device = self.trusted_devices.get_mut(&clean_id)
+            .ok_or_else(|| anyhow::anyhow!("Device {} is not trusted", clean_id))?;
+        device.preferences = preferences;
+        self.save()
+    }
+
     /// Get all settings
     pub fn get_settings(&self) -> &AppSettings {
         &self.settings
@@ -248,6 +756,21 @@ impl ConnectionConfig {
                     self.settings.connection_quality = v;
                 }
             }
+            "coalesce_frame_requests" => {
+                if let SettingValue::Bool(v) = value {
+                    self.settings.coalesce_frame_requests = v;
+                }
+            }
+            "relay_multiplexing" => {
+                if let SettingValue::Bool(v) = value {
+                    self.settings.relay_multiplexing = v;
+                }
+            }
+            "jitter_buffer_frames" => {
+                if let SettingValue::Number(v) = value {
+                    self.settings.jitter_buffer_frames = v.min(crate::jitter::MAX_JITTER_BUFFER_FRAMES);
+                }
+            }
             "require_approval" => {
                 if let SettingValue::Bool(v) = value {
                     self.settings.require_approval = v;
@@ -263,11 +786,147 @@ impl ConnectionConfig {
                     self.settings.session_timeout = v;
                 }
             }
+            "approval_timeout_secs" => {
+                if let SettingValue::Number(v) = value {
+                    self.settings.approval_timeout_secs = v.min(crate::host::MAX_APPROVAL_TIMEOUT_SECS);
+                }
+            }
+            "start_view_only" => {
+                if let SettingValue::Bool(v) = value {
+                    self.settings.start_view_only = v;
+                }
+            }
             "hide_from_address_book" => {
                 if let SettingValue::Bool(v) = value {
                     self.settings.hide_from_address_book = v;
                 }
             }
+            "snapshot_directory" => {
+                if let SettingValue::String(v) = value {
+                    self.settings.snapshot_directory = v;
+                }
+            }
+            "recordings_dir" => {
+                if let SettingValue::String(v) = value {
+                    let v = v.trim().to_string();
+                    if !v.is_empty() && !is_writable_dir(Path::new(&v)) {
+                        anyhow::bail!("Recordings folder is not writable: {}", v);
+                    }
+                    self.settings.recordings_dir = v;
+                }
+            }
+            "proxy_url" => {
+                if let SettingValue::String(v) = value {
+                    let v = v.trim().to_string();
+                    crate::proxy::validate_setting(&v)?;
+                    self.settings.proxy_url = v;
+                }
+            }
+            "relay_ca_cert" => {
+                if let SettingValue::String(v) = value {
+                    let v = v.trim().to_string();
+                    crate::tls::validate_ca_setting(&v)?;
+                    self.settings.relay_ca_cert = v;
+                }
+            }
+            "license_activation_url" => {
+                if let SettingValue::String(v) = value {
+                    let v = v.trim().to_string();
+                    if !v.starts_with("https://") {
+                        anyhow::bail!("License activation URL must start with https://");
+                    }
+                    self.settings.license_activation_url = v;
+                }
+            }
+            "relay_tls_insecure" => {
+                if let SettingValue::Bool(v) = value {
+                    self.settings.relay_tls_insecure = v;
+                }
+            }
+            "capture_backend" => {
+                if let SettingValue::String(v) = value {
+                    if crate::capture::CaptureBackend::from_setting(&v).is_none() {
+                        anyhow::bail!("Invalid capture backend: {}", v);
+                    }
+                    self.settings.capture_backend = v;
+                }
+            }
+            "content_mode" => {
+                if let SettingValue::String(v) = value {
+                    if crate::capture::ContentMode::from_setting(&v).is_none() {
+                        anyhow::bail!("Invalid content mode: {}", v);
+                    }
+                    self.settings.content_mode = v;
+                }
+            }
+            "capture_active_fps" => {
+                if let SettingValue::Number(v) = value {
+                    self.settings.capture_active_fps = v.clamp(
+                        crate::capture::MIN_CAPTURE_FPS,
+                        crate::capture::MAX_CAPTURE_FPS,
+                    );
+                }
+            }
+            "capture_idle_fps" => {
+                if let SettingValue::Number(v) = value {
+                    self.settings.capture_idle_fps = v.clamp(
+                        crate::capture::MIN_CAPTURE_FPS,
+                        crate::capture::MAX_CAPTURE_FPS,
+                    );
+                }
+            }
+            "scroll_sensitivity" => {
+                if let SettingValue::Number(v) = value {
+                    self.settings.scroll_sensitivity = v.clamp(
+                        crate::input::MIN_SCROLL_SENSITIVITY,
+                        crate::input::MAX_SCROLL_SENSITIVITY,
+                    );
+                }
+            }
+            "input_batch_ms" => {
+                if let SettingValue::Number(v) = value {
+                    self.settings.input_batch_ms = v.min(crate::input::MAX_INPUT_BATCH_MS);
+                }
+            }
+            "natural_scroll" => {
+                if let SettingValue::Bool(v) = value {
+                    self.settings.natural_scroll = v;
+                }
+            }
+            "metrics_enabled" => {
+                if let SettingValue::Bool(v) = value {
+                    self.settings.metrics_enabled = v;
+                }
+            }
+            "metrics_port" => {
+                if let SettingValue::Number(v) = value {
+                    if v == 0 || v > u16::MAX as u32 {
+                        anyhow::bail!("Invalid metrics port: {}", v);
+                    }
+                    self.settings.metrics_port = v;
+                }
+            }
+            "automation_enabled" => {
+                if let SettingValue::Bool(v) = value {
+                    self.settings.automation_enabled = v;
+                }
+            }
+            "reconnect_max_attempts" => {
+                if let SettingValue::Number(v) = value {
+                    self.settings.reconnect_max_attempts = v;
+                }
+            }
+            "reconnect_max_backoff_secs" => {
+                if let SettingValue::Number(v) = value {
+                    self.settings.reconnect_max_backoff_secs = v.max(1);
+                }
+            }
+            "max_clipboard_size_mb" => {
+                if let SettingValue::Number(v) = value {
+                    let max = (crate::clipboard::MAX_CLIPBOARD_SIZE / (1024 * 1024)) as u32;
+                    self.settings.max_clipboard_size_mb = v.clamp(1, max);
+                }
+            }
             _ => {}
         }
         self.save()
@@ -294,9 +953,92 @@ mod tests {
 
     #[test]
     fn test_serialize_deserialize() {
-        let config = ConnectionConfig { p2p_enabled: false };
+        let config = ConnectionConfig { p2p_enabled: false, ..Default::default() };
         let json = serde_json::to_string(&config).unwrap();
         let loaded: ConnectionConfig = serde_json::from_str(&json).unwrap();
         assert_eq!(config.p2p_enabled, loaded.p2p_enabled);
     }
+
+    #[test]
+    fn test_blocked_devices_round_trip() {
+        let mut config = ConnectionConfig::default();
+        config.blocked_devices.insert("123456789".to_string());
+
+        let json = serde_json::to_string(&config).unwrap();
+        let restored: ConnectionConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.blocked_devices, config.blocked_devices);
+        // Configs saved before blocking existed load with nobody blocked
+        let mut legacy = serde_json::to_value(&config).unwrap();
+        legacy.as_object_mut().unwrap().remove("blocked_devices");
+        assert!(serde_json::from_value::<ConnectionConfig>(legacy).unwrap().blocked_devices.is_empty());
+    }
+
+    #[test]
+    fn test_allowed_viewers_by_key() {
+        use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+        let mut config = ConnectionConfig::default();
+        config.allowed_viewers.insert(STANDARD.encode([7u8; 32]), AllowedViewer {
+            device_id: "123456789".to_string(),
+            allowed_at: 0,
+        });
+        let json = serde_json::to_string(&config).unwrap();
+        let restored: ConnectionConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.allowed_viewers.len(), 1);
+
+        config.apply_allowed_viewers();
+        assert!(crate::host::is_viewer_allowed(&[7u8; 32]));
+        assert!(!crate::host::is_viewer_allowed(&[8u8; 32]));
+        crate::host::set_allowed_viewers(Default::default());
+    }
+
+    #[test]
+    fn test_session_quality_prefers_device_level() {
+        use crate::qos::QualityLevel;
+
+        let mut config = ConnectionConfig::default();
+        assert_eq!(config.session_quality("123456789"), None);
+
+        config.settings.connection_quality = "speed".to_string();
+        assert_eq!(config.session_quality("123456789"), Some(QualityLevel::Low));
+
+        config.trusted_devices.insert("123456789".to_string(), TrustedDevice {
+            device_id: "123456789".to_string(),
+            name: None,
+            trusted_at: 0,
+            last_connected: None,
+            preferences: DevicePreferences { quality: "lossless".to_string(), ..Default::default() },
+            public_key: None,
+            fingerprint: None,
+        });
+        assert_eq!(config.session_quality("123 456 789"), Some(QualityLevel::Lossless));
+    }
+
+    #[test]
+    fn test_snapshot_dir() {
+        let mut settings = AppSettings::default();
+        assert!(settings.snapshot_dir().ends_with("SecureDesk"));
+
+        settings.snapshot_directory = "/tmp/shots".to_string();
+        assert_eq!(settings.snapshot_dir(), PathBuf::from("/tmp/shots"));
+    }
+
+    #[test]
+    fn test_recordings_path_fallback() {
+        let mut settings = AppSettings::default();
+        let default_dir = crate::recording::SessionRecorder::recordings_directory().unwrap();
+        assert_eq!(settings.recordings_path().unwrap(), default_dir);
+
+        let custom = std::env::temp_dir().join("securedesk_recordings_test");
+        settings.recordings_dir = custom.to_string_lossy().to_string();
+        assert_eq!(settings.recordings_path().unwrap(), custom);
+        let _ = fs::remove_dir_all(&custom);
+
+        // A path below a regular file can never be created
+        let blocker = std::env::temp_dir().join("securedesk_recordings_blocker");
+        fs::write(&blocker, b"").unwrap();
+        settings.recordings_dir = blocker.join("sub").to_string_lossy().to_string();
+        assert_eq!(settings.recordings_path().unwrap(), default_dir);
+        let _ = fs::remove_file(&blocker);
+    }
 }
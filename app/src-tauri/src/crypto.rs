@@ -4,16 +4,30 @@
 
 use anyhow::Result;
 use blake3::Hasher;
+use chacha20poly1305::aead::{AeadInPlace, KeyInit};
+use chacha20poly1305::ChaCha20Poly1305;
 use ed25519_dalek::SigningKey;
 use rand::rngs::OsRng;
+use snow::params::{CipherChoice, DHChoice, HashChoice};
+use snow::resolvers::{CryptoResolver, DefaultResolver, FallbackResolver};
+use snow::types::{Cipher, Dh, Hash, Random};
 use snow::{Builder, HandshakeState, TransportState};
 use std::fs;
+use std::io::Write;
 use std::path::PathBuf;
 use x25519_dalek::{PublicKey as X25519Public, StaticSecret as X25519Secret};
+use zeroize::{Zeroize, Zeroizing};
 
 const NOISE_PATTERN: &str = "Noise_XK_25519_ChaChaPoly_BLAKE2s";
 
+/// blake3 key-derivation context for fingerprints, so they never collide with device IDs
+const FINGERPRINT_CONTEXT: &str = "SecureDesk 2024 device fingerprint v1";
+
+/// Noise transport messages, tag included, are at most this long
+const MAX_NOISE_MESSAGE: usize = 65535;
+
 /// Device identity - stored locally, never sent to servers
+/// Both secret keys wipe themselves from memory on drop
 #[derive(Clone)]
 pub struct Identity {
     x25519_secret: X25519Secret,
@@ -48,17 +62,28 @@ impl Identity {
         }
     }
 
+    /// Load from disk without creating one
+    pub fn load_existing() -> Result<Option<Self>> {
+        let path = Self::identity_path()?;
+        if path.exists() {
+            Self::load(&path).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
     fn load(path: &PathBuf) -> Result<Self> {
-        let data = fs::read(path)?;
+        let data = Zeroizing::new(fs::read(path)?);
         if data.len() != 64 {
             anyhow::bail!("Invalid identity file");
         }
 
-        let x25519_bytes: [u8; 32] = data[0..32].try_into()?;
+        let mut x25519_bytes: [u8; 32] = data[0..32].try_into()?;
         let x25519_secret = X25519Secret::from(x25519_bytes);
+        x25519_bytes.zeroize();
         let x25519_public = X25519Public::from(&x25519_secret);
 
-        let ed25519_bytes: [u8; 32] = data[32..64].try_into()?;
+        let ed25519_bytes = Zeroizing::new(<[u8; 32]>::try_from(&data[32..64])?);
         let ed25519_key = SigningKey::from_bytes(&ed25519_bytes);
 
         Ok(Self {
@@ -73,10 +98,28 @@ impl Identity {
             fs::create_dir_all(parent)?;
         }
 
-        let mut data = Vec::with_capacity(64);
+        let mut data = Zeroizing::new(Vec::with_capacity(64));
         data.extend_from_slice(self.x25519_secret.as_bytes());
         data.extend_from_slice(self.ed25519_key.as_bytes());
         fs::write(path, &data)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+        Ok(())
+    }
+
+    /// Overwrite the key file before deleting it, so the old keys are not
+    /// left in freed blocks. Best effort: SSDs and copy-on-write filesystems
+    /// may still keep earlier copies
+    fn shred(path: &PathBuf) -> Result<()> {
+        let len = fs::metadata(path)?.len() as usize;
+        let mut file = fs::OpenOptions::new().write(true).open(path)?;
+        file.write_all(&vec![0u8; len])?;
+        file.sync_all()?;
+        drop(file);
+        fs::remove_file(path)?;
         Ok(())
     }
 
@@ -97,20 +140,7 @@ impl Identity {
     /// Get device ID (shown to user for sharing)
     /// Format: XXX XXX XXX (9 digits)
     pub fn device_id(&self) -> String {
-        let mut hasher = Hasher::new();
-        hasher.update(self.x25519_public.as_bytes());
-        hasher.update(self.ed25519_key.verifying_key().as_bytes());
-        let hash = hasher.finalize();
-
-        // Convert first bytes to digits
-        let bytes = hash.as_bytes();
-        let num = u64::from_le_bytes([
-            bytes[0], bytes[1], bytes[2], bytes[3],
-            bytes[4], bytes[5], bytes[6], bytes[7],
-        ]) % 1_000_000_000;
-
-        let id = format!("{:09}", num);
-        format!("{} {} {}", &id[0..3], &id[3..6], &id[6..9])
+        device_id_from_keys(self.public_key(), &self.verifying_key())
     }
 
     /// Get raw device ID (no spaces)
@@ -118,13 +148,30 @@ impl Identity {
         self.device_id().replace(' ', "")
     }
 
+    /// Fingerprint of this device's public keys, read aloud to verify a
+    /// connection out of band. Format: 8 groups of 4 hex digits
+    pub fn fingerprint(&self) -> String {
+        fingerprint_from_keys(self.public_key(), &self.verifying_key())
+    }
+
+    /// Destroy this device's identity (for decommissioning a machine)
+    /// Returns false if there was none. The next start creates a new one
+    pub fn wipe() -> Result<bool> {
+        let path = Self::identity_path()?;
+        if !path.exists() {
+            return Ok(false);
+        }
+        Self::shred(&path)?;
+        Ok(true)
+    }
+
     /// Regenerate identity (new keys, new device ID)
     pub fn regenerate() -> Result<Self> {
         let path = Self::identity_path()?;
 
-        // Delete existing identity file if it exists
+        // Destroy the old keys rather than just unlinking them
         if path.exists() {
-            fs::remove_file(&path)?;
+            Self::shred(&path)?;
         }
 
         // Generate and save new identity
@@ -138,9 +185,20 @@ impl Identity {
         self.x25519_public.as_bytes()
     }
 
+    /// Key for data this device keeps at rest, derived from its X25519 secret
+    /// under `context`; nobody who only saw the public keys can compute it
+    pub fn storage_key(&self, context: &str) -> Zeroizing<[u8; 32]> {
+        Zeroizing::new(blake3::derive_key(context, self.x25519_secret.as_bytes()))
+    }
+
+    /// Get Ed25519 verifying key bytes
+    pub fn verifying_key(&self) -> [u8; 32] {
+        self.ed25519_key.verifying_key().to_bytes()
+    }
+
     /// Create Noise initiator (client connecting to host)
     pub fn create_initiator(&self, remote_public: &[u8]) -> Result<HandshakeState> {
-        let builder = Builder::new(NOISE_PATTERN.parse()?)
+        let builder = WipingResolver::builder()?
             .local_private_key(self.x25519_secret.as_bytes())
             .remote_public_key(remote_public)
             .build_initiator()?;
@@ -149,35 +207,404 @@ impl Identity {
 
     /// Create Noise responder (host accepting connection)
     pub fn create_responder(&self) -> Result<HandshakeState> {
-        let builder = Builder::new(NOISE_PATTERN.parse()?)
+        let builder = WipingResolver::builder()?
             .local_private_key(self.x25519_secret.as_bytes())
             .build_responder()?;
         Ok(builder)
     }
 }
 
+/// Derive the device ID a pair of public keys belongs to
+/// Lets a client check that the host it reached is the device it dialled
+pub fn device_id_from_keys(x25519_public: &[u8; 32], ed25519_public: &[u8; 32]) -> String {
+    let mut hasher = Hasher::new();
+    hasher.update(x25519_public);
+    hasher.update(ed25519_public);
+    let hash = hasher.finalize();
+
+    // Convert first bytes to digits
+    let bytes = hash.as_bytes();
+    let num = u64::from_le_bytes([
+        bytes[0], bytes[1], bytes[2], bytes[3],
+        bytes[4], bytes[5], bytes[6], bytes[7],
+    ]) % 1_000_000_000;
+
+    let id = format!("{:09}", num);
+    format!("{} {} {}", &id[0..3], &id[3..6], &id[6..9])
+}
+
+/// Raw device ID a viewer proves in the Noise handshake: the static key the
+/// handshake authenticated and the Ed25519 key sent in its final message
+pub fn proven_device_id(static_key: &[u8; 32], payload: &[u8]) -> Option<String> {
+    let ed25519: [u8; 32] = payload.try_into().ok()?;
+    Some(device_id_from_keys(static_key, &ed25519).replace(' ', ""))
+}
+
+/// Derive the fingerprint of a pair of public keys (128 bits of blake3)
+/// Unlike the 9-digit device ID it is long enough that a matching one proves the keys
+pub fn fingerprint_from_keys(x25519_public: &[u8; 32], ed25519_public: &[u8; 32]) -> String {
+    let mut hasher = Hasher::new_derive_key(FINGERPRINT_CONTEXT);
+    hasher.update(x25519_public);
+    hasher.update(ed25519_public);
+    let hash = hasher.finalize();
+
+    hash.as_bytes()[..16]
+        .chunks(2)
+        .map(|pair| format!("{:02X}{:02X}", pair[0], pair[1]))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Salted argon2id hash of an access password, as a PHC string
+/// ("$argon2id$v=19$...") carrying its salt and parameters
+pub fn hash_access_password(password: &str) -> Result<String> {
+    use argon2::password_hash::{rand_core::OsRng, PasswordHasher, SaltString};
+
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = argon2::Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))?;
+    Ok(hash.to_string())
+}
+
+/// Check a password against `hash_access_password` output
+/// The hashes are compared in constant time; a malformed hash matches nothing
+pub fn verify_access_password(hash: &str, password: &[u8]) -> bool {
+    use argon2::password_hash::{PasswordHash, PasswordVerifier};
+
+    let Ok(hash) = PasswordHash::new(hash) else {
+        return false;
+    };
+    argon2::Argon2::default().verify_password(password, &hash).is_ok()
+}
+
 /// Secure transport after Noise handshake completes
 pub struct SecureChannel {
     transport: TransportState,
+    /// A message failed to decrypt: the stream was tampered with or lost frames
+    broken: bool,
 }
 
 impl SecureChannel {
+    /// Longest message one `encrypt` takes (a Noise message less its tag)
+    pub const MAX_PLAINTEXT: usize = MAX_NOISE_MESSAGE - 16;
+
     pub fn from_handshake(handshake: HandshakeState) -> Result<Self> {
-        let transport = handshake.into_transport_mode()?;
-        Ok(Self { transport })
+        if !handshake.is_handshake_finished() {
+            anyhow::bail!("Handshake not finished");
+        }
+        Ok(Self { transport: handshake.into_transport_mode()?, broken: false })
     }
 
     pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>> {
-        let mut ciphertext = vec![0u8; plaintext.len() + 16];
-        let len = self.transport.write_message(plaintext, &mut ciphertext)?;
-        ciphertext.truncate(len);
-        Ok(ciphertext)
+        if plaintext.len() > Self::MAX_PLAINTEXT {
+            anyhow::bail!("Message too long to encrypt");
+        }
+        let mut buf = vec![0u8; plaintext.len() + 16];
+        let len = self.transport.write_message(plaintext, &mut buf)
+            .map_err(|_| anyhow::anyhow!("Encryption failed"))?;
+        buf.truncate(len);
+        Ok(buf)
     }
 
+    /// Messages must arrive in order, each exactly once. The first one that
+    /// does not decrypt ends the channel: a dropped, replayed or forged frame
+    /// means nothing after it can be trusted
     pub fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>> {
-        let mut plaintext = vec![0u8; ciphertext.len()];
-        let len = self.transport.read_message(ciphertext, &mut plaintext)?;
-        plaintext.truncate(len);
-        Ok(plaintext)
+        if self.broken {
+            anyhow::bail!("Secure channel closed after a decryption failure");
+        }
+        if ciphertext.len() > MAX_NOISE_MESSAGE {
+            self.broken = true;
+            anyhow::bail!("Message too long to decrypt");
+        }
+        let mut buf = vec![0u8; ciphertext.len()];
+        let len = self.transport.read_message(ciphertext, &mut buf).map_err(|_| {
+            self.broken = true;
+            anyhow::anyhow!("Decryption failed")
+        })?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+}
+
+/// snow keeps keys in plain arrays it never clears. This resolver hands it
+/// X25519 and ChaChaPoly that wipe theirs on drop, so the static and ephemeral
+/// keys in a HandshakeState and the session keys in a TransportState go with
+/// them; hashing and randomness come from snow's default resolver
+struct WipingResolver;
+
+impl WipingResolver {
+    fn builder<'a>() -> Result<Builder<'a>> {
+        let resolver = FallbackResolver::new(Box::new(WipingResolver), Box::new(DefaultResolver));
+        Ok(Builder::with_resolver(NOISE_PATTERN.parse()?, Box::new(resolver)))
+    }
+}
+
+impl CryptoResolver for WipingResolver {
+    fn resolve_rng(&self) -> Option<Box<dyn Random>> {
+        None
+    }
+
+    fn resolve_dh(&self, choice: &DHChoice) -> Option<Box<dyn Dh>> {
+        match choice {
+            DHChoice::Curve25519 => Some(Box::new(WipingDh25519::default())),
+            _ => None,
+        }
+    }
+
+    fn resolve_hash(&self, _choice: &HashChoice) -> Option<Box<dyn Hash>> {
+        None
+    }
+
+    fn resolve_cipher(&self, choice: &CipherChoice) -> Option<Box<dyn Cipher>> {
+        match choice {
+            CipherChoice::ChaChaPoly => Some(Box::new(WipingChaChaPoly::default())),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct WipingDh25519 {
+    secret: Zeroizing<[u8; 32]>,
+    public: [u8; 32],
+}
+
+impl WipingDh25519 {
+    fn derive_public(&mut self) {
+        self.public = X25519Public::from(&X25519Secret::from(*self.secret)).to_bytes();
+    }
+}
+
+impl Dh for WipingDh25519 {
+    fn name(&self) -> &'static str {
+        "25519"
+    }
+
+    fn pub_len(&self) -> usize {
+        32
+    }
+
+    fn priv_len(&self) -> usize {
+        32
+    }
+
+    fn set(&mut self, privkey: &[u8]) {
+        self.secret.copy_from_slice(&privkey[..32]);
+        self.derive_public();
+    }
+
+    fn generate(&mut self, rng: &mut dyn Random) {
+        rng.fill_bytes(&mut self.secret[..]);
+        self.derive_public();
+    }
+
+    fn pubkey(&self) -> &[u8] {
+        &self.public
+    }
+
+    fn privkey(&self) -> &[u8] {
+        &self.secret[..]
+    }
+
+    fn dh(&self, pubkey: &[u8], out: &mut [u8]) -> std::result::Result<(), snow::Error> {
+        let public: [u8; 32] = pubkey.get(..32)
+            .and_then(|key| key.try_into().ok())
+            .ok_or(snow::Error::Dh)?;
+        let shared = X25519Secret::from(*self.secret).diffie_hellman(&X25519Public::from(public));
+        out[..32].copy_from_slice(shared.as_bytes());
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct WipingChaChaPoly {
+    cipher: Option<ChaCha20Poly1305>,
+}
+
+impl WipingChaChaPoly {
+    /// Noise nonce: 32 zero bits, then the counter little-endian
+    fn nonce(counter: u64) -> chacha20poly1305::Nonce {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&counter.to_le_bytes());
+        nonce.into()
+    }
+}
+
+impl Cipher for WipingChaChaPoly {
+    fn name(&self) -> &'static str {
+        "ChaChaPoly"
+    }
+
+    fn set(&mut self, key: &[u8]) {
+        self.cipher = Some(ChaCha20Poly1305::new(chacha20poly1305::Key::from_slice(&key[..32])));
+    }
+
+    fn encrypt(&self, nonce: u64, authtext: &[u8], plaintext: &[u8], out: &mut [u8]) -> usize {
+        let cipher = self.cipher.as_ref().expect("snow sets the key before encrypting");
+        out[..plaintext.len()].copy_from_slice(plaintext);
+        let tag = cipher.encrypt_in_place_detached(&Self::nonce(nonce), authtext, &mut out[..plaintext.len()])
+            .expect("ChaChaPoly encrypts any Noise message");
+        out[plaintext.len()..plaintext.len() + 16].copy_from_slice(&tag);
+        plaintext.len() + 16
+    }
+
+    fn decrypt(&self, nonce: u64, authtext: &[u8], ciphertext: &[u8], out: &mut [u8]) -> std::result::Result<usize, snow::Error> {
+        let cipher = self.cipher.as_ref().ok_or(snow::Error::Decrypt)?;
+        let len = ciphertext.len().checked_sub(16).ok_or(snow::Error::Decrypt)?;
+        out[..len].copy_from_slice(&ciphertext[..len]);
+        cipher.decrypt_in_place_detached(&Self::nonce(nonce), authtext, &mut out[..len], ciphertext[len..].into())
+            .map_err(|_| snow::Error::Decrypt)?;
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run Noise_XK between `client` dialling `expected` and `host` answering
+    fn handshake(client: &Identity, expected: &Identity, host: &Identity) -> Result<(SecureChannel, SecureChannel)> {
+        let mut initiator = client.create_initiator(expected.public_key())?;
+        let mut responder = host.create_responder()?;
+        let (mut buf, mut out) = (vec![0u8; 1024], vec![0u8; 1024]);
+
+        let len = initiator.write_message(&[], &mut buf)?;
+        responder.read_message(&buf[..len], &mut out)?;
+        let len = responder.write_message(&[], &mut buf)?;
+        initiator.read_message(&buf[..len], &mut out)?;
+        let len = initiator.write_message(&[], &mut buf)?;
+        responder.read_message(&buf[..len], &mut out)?;
+
+        Ok((SecureChannel::from_handshake(initiator)?, SecureChannel::from_handshake(responder)?))
+    }
+
+    #[test]
+    fn test_handshake_binds_host_identity() {
+        let client = Identity::generate();
+        let host = Identity::generate();
+        assert_eq!(device_id_from_keys(host.public_key(), &host.verifying_key()), host.device_id());
+
+        let (mut client_channel, mut host_channel) = handshake(&client, &host, &host).unwrap();
+        let ciphertext = client_channel.encrypt(b"hello").unwrap();
+        assert_eq!(host_channel.decrypt(&ciphertext).unwrap(), b"hello");
+
+        // A different endpoint can't complete a handshake for the dialled key
+        let impostor = Identity::generate();
+        assert!(handshake(&client, &host, &impostor).is_err());
+    }
+
+    #[test]
+    fn test_final_handshake_message_proves_viewer_id() {
+        let client = Identity::generate();
+        let host = Identity::generate();
+        let mut initiator = client.create_initiator(host.public_key()).unwrap();
+        let mut responder = host.create_responder().unwrap();
+        let (mut buf, mut out) = (vec![0u8; 1024], vec![0u8; 1024]);
+
+        let len = initiator.write_message(&[], &mut buf).unwrap();
+        responder.read_message(&buf[..len], &mut out).unwrap();
+        let len = responder.write_message(&[], &mut buf).unwrap();
+        initiator.read_message(&buf[..len], &mut out).unwrap();
+        let len = initiator.write_message(&client.verifying_key(), &mut buf).unwrap();
+        let payload = responder.read_message(&buf[..len], &mut out).unwrap();
+
+        let static_key: [u8; 32] = responder.get_remote_static().unwrap().try_into().unwrap();
+        assert_eq!(proven_device_id(&static_key, &out[..payload]), Some(client.device_id_raw()));
+        // Another device's Ed25519 key makes another ID, and none is no proof
+        assert_ne!(proven_device_id(&static_key, &host.verifying_key()), Some(client.device_id_raw()));
+        assert_eq!(proven_device_id(&static_key, &[]), None);
+    }
+
+    #[test]
+    fn test_wiping_resolver_matches_snow_default() {
+        fn wipes_on_drop<T: zeroize::ZeroizeOnDrop>() {}
+        fn wipes_with_derived_drop<T: Zeroize>() {}
+        // x25519-dalek derives its Drop from Zeroize without the marker trait
+        wipes_with_derived_drop::<X25519Secret>();
+        assert!(std::mem::needs_drop::<X25519Secret>());
+        wipes_on_drop::<SigningKey>();
+        wipes_on_drop::<ChaCha20Poly1305>();
+        wipes_on_drop::<Zeroizing<[u8; 32]>>();
+
+        // Peers on snow's default resolver must interoperate
+        let client = Identity::generate();
+        let host = Identity::generate();
+        let mut initiator = client.create_initiator(host.public_key()).unwrap();
+        let mut responder = Builder::new(NOISE_PATTERN.parse().unwrap())
+            .local_private_key(host.x25519_secret.as_bytes())
+            .build_responder()
+            .unwrap();
+        let (mut buf, mut out) = (vec![0u8; 1024], vec![0u8; 1024]);
+        let len = initiator.write_message(&[], &mut buf).unwrap();
+        responder.read_message(&buf[..len], &mut out).unwrap();
+        let len = responder.write_message(&[], &mut buf).unwrap();
+        initiator.read_message(&buf[..len], &mut out).unwrap();
+        let len = initiator.write_message(&[], &mut buf).unwrap();
+        responder.read_message(&buf[..len], &mut out).unwrap();
+
+        let mut channel = SecureChannel::from_handshake(initiator).unwrap();
+        let mut snow = responder.into_transport_mode().unwrap();
+        for message in [&b"first"[..], b"second", b""] {
+            let len = snow.read_message(&channel.encrypt(message).unwrap(), &mut out).unwrap();
+            assert_eq!(&out[..len], message);
+
+            let len = snow.write_message(message, &mut buf).unwrap();
+            assert_eq!(channel.decrypt(&buf[..len]).unwrap(), message);
+        }
+
+        // A message the relay drops is noticed, not skipped over
+        let len = snow.write_message(b"lost", &mut buf).unwrap();
+        let lost = buf[..len].to_vec();
+        let len = snow.write_message(b"after", &mut buf).unwrap();
+        assert!(channel.decrypt(&buf[..len]).is_err());
+        // and the channel stays closed, even for the message it expected
+        assert!(channel.decrypt(&lost).is_err());
+    }
+
+    #[test]
+    fn test_tampered_message_closes_channel() {
+        let host = Identity::generate();
+        let (mut client_channel, mut host_channel) = handshake(&Identity::generate(), &host, &host).unwrap();
+
+        let mut tampered = client_channel.encrypt(b"tampered").unwrap();
+        tampered[0] ^= 1;
+        assert!(host_channel.decrypt(&tampered).is_err());
+        let next = client_channel.encrypt(b"next").unwrap();
+        assert!(host_channel.decrypt(&next).is_err());
+    }
+
+    #[test]
+    fn test_storage_key_needs_the_secret() {
+        let identity = Identity::generate();
+        let key = identity.storage_key("SecureDesk test storage");
+        assert_eq!(*key, *identity.storage_key("SecureDesk test storage"));
+        assert_ne!(*key, *identity.storage_key("SecureDesk other storage"));
+        assert_ne!(*key, *Identity::generate().storage_key("SecureDesk test storage"));
+        assert_ne!(*key, blake3::derive_key("SecureDesk test storage", identity.public_key()));
+    }
+
+    #[test]
+    fn test_access_password_hash() {
+        let hash = hash_access_password("correct horse").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_access_password(&hash, b"correct horse"));
+        assert!(!verify_access_password(&hash, b"correct hors"));
+        assert!(!verify_access_password("not a hash", b"correct horse"));
+        // Salted: the same password never hashes the same twice
+        assert_ne!(hash_access_password("correct horse").unwrap(), hash);
+    }
+
+    #[test]
+    fn test_fingerprint_format() {
+        let identity = Identity::generate();
+        let fingerprint = identity.fingerprint();
+        assert_eq!(fingerprint, fingerprint_from_keys(identity.public_key(), &identity.verifying_key()));
+
+        let groups: Vec<&str> = fingerprint.split(' ').collect();
+        assert_eq!(groups.len(), 8);
+        assert!(groups.iter().all(|g| g.len() == 4 && g.chars().all(|c| c.is_ascii_hexdigit())));
+        assert_ne!(fingerprint, Identity::generate().fingerprint());
     }
 }
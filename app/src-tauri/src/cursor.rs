@@ -0,0 +1,305 @@
+//! The host's mouse cursor, sent apart from the video as input::CURSOR_UPDATE
+//! so the viewer draws it sharp and moves it without waiting for frames
+
+#![allow(dead_code)]
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+use crate::protocol;
+
+/// Largest cursor image sent, per side
+const MAX_SIZE: u32 = 256;
+
+/// A cursor image as the viewer gets it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    /// Point of the image that clicks land on
+    pub hotspot: (u16, u16),
+    pub png: Vec<u8>,
+}
+
+/// A cursor image in RGBA, as read from the capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shape {
+    pub width: u32,
+    pub height: u32,
+    pub hotspot: (u32, u32),
+    pub rgba: Vec<u8>,
+}
+
+impl Shape {
+    /// From 32-bit BGRA rows with real alpha
+    pub fn from_bgra(width: u32, height: u32, pitch: usize, data: &[u8], hotspot: (u32, u32)) -> Option<Self> {
+        Self::convert(width, height, hotspot, |x, y| {
+            let at = y as usize * pitch + x as usize * 4;
+            let bgra = data.get(at..at + 4)?;
+            Some([bgra[2], bgra[1], bgra[0], bgra[3]])
+        })
+    }
+
+    /// From a 1-bit AND mask followed by a 1-bit XOR mask of `height` rows each
+    /// Pixels that invert the screen are drawn black, so an I-beam stays visible
+    pub fn from_monochrome(width: u32, height: u32, pitch: usize, data: &[u8], hotspot: (u32, u32)) -> Option<Self> {
+        let bit = |x: u32, y: u32| {
+            let byte = data.get(y as usize * pitch + x as usize / 8)?;
+            Some(byte & (0x80 >> (x % 8)) != 0)
+        };
+        Self::convert(width, height, hotspot, |x, y| {
+            Some(match (bit(x, y)?, bit(x, y + height)?) {
+                (true, false) => [0, 0, 0, 0],
+                (false, false) => [0, 0, 0, 255],
+                (false, true) => [255, 255, 255, 255],
+                (true, true) => [0, 0, 0, 255],
+            })
+        })
+    }
+
+    /// From BGRA rows whose alpha byte is a mask: 0 draws the colour, 0xFF XORs
+    /// it onto the screen, which is drawn black unless it leaves the screen as is
+    pub fn from_masked(width: u32, height: u32, pitch: usize, data: &[u8], hotspot: (u32, u32)) -> Option<Self> {
+        Self::convert(width, height, hotspot, |x, y| {
+            let at = y as usize * pitch + x as usize * 4;
+            let bgra = data.get(at..at + 4)?;
+            Some(match (bgra[3], bgra[..3] == [0, 0, 0]) {
+                (0, _) => [bgra[2], bgra[1], bgra[0], 255],
+                (_, true) => [0, 0, 0, 0],
+                (_, false) => [0, 0, 0, 255],
+            })
+        })
+    }
+
+    /// None when the image is empty, too large, or `pixel` runs out of data
+    fn convert(width: u32, height: u32, hotspot: (u32, u32), pixel: impl Fn(u32, u32) -> Option<[u8; 4]>) -> Option<Self> {
+        if width == 0 || height == 0 || width > MAX_SIZE || height > MAX_SIZE {
+            return None;
+        }
+        let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                rgba.extend(pixel(x, y)?);
+            }
+        }
+        let hotspot = (hotspot.0.min(width - 1), hotspot.1.min(height - 1));
+        Some(Self { width, height, hotspot, rgba })
+    }
+
+    pub fn png(&self) -> Result<Vec<u8>> {
+        use image::codecs::png::PngEncoder;
+        use image::{ColorType, ImageEncoder};
+
+        let mut out = Vec::new();
+        PngEncoder::new(&mut out).write_image(&self.rgba, self.width, self.height, ColorType::Rgba8)?;
+        Ok(out)
+    }
+}
+
+/// Where the cursor is, in pixels of the captured frame, and its image if new
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub visible: bool,
+    /// The hotspot's position
+    pub x: i32,
+    pub y: i32,
+    pub image: Option<Arc<Image>>,
+}
+
+impl Message {
+    /// [CURSOR_UPDATE][visible u8][x i32 LE][y i32 LE], then for a new image
+    /// [hotspot x u16 LE][hotspot y u16 LE][PNG]
+    pub fn encode(&self) -> Vec<u8> {
+        let png_len = self.image.as_ref().map_or(0, |image| 4 + image.png.len());
+        let mut payload = Vec::with_capacity(10 + png_len);
+        payload.push(protocol::input::CURSOR_UPDATE);
+        payload.push(self.visible as u8);
+        payload.extend(&self.x.to_le_bytes());
+        payload.extend(&self.y.to_le_bytes());
+        if let Some(image) = &self.image {
+            payload.extend(&image.hotspot.0.to_le_bytes());
+            payload.extend(&image.hotspot.1.to_le_bytes());
+            payload.extend(&image.png);
+        }
+        payload
+    }
+
+    /// Parse an input::CURSOR_UPDATE payload, type byte included
+    pub fn decode(payload: &[u8]) -> Result<Self> {
+        if payload.len() < 10 || payload[0] != protocol::input::CURSOR_UPDATE {
+            anyhow::bail!("Cursor update too short");
+        }
+        let image = match payload.len() {
+            10 => None,
+            len if len > 14 => Some(Arc::new(Image {
+                hotspot: (
+                    u16::from_le_bytes([payload[10], payload[11]]),
+                    u16::from_le_bytes([payload[12], payload[13]]),
+                ),
+                png: payload[14..].to_vec(),
+            })),
+            _ => anyhow::bail!("Cursor image truncated"),
+        };
+        Ok(Self {
+            visible: payload[1] != 0,
+            x: i32::from_le_bytes(payload[2..6].try_into()?),
+            y: i32::from_le_bytes(payload[6..10].try_into()?),
+            image,
+        })
+    }
+}
+
+/// The cursor as the capture last saw it; shared by every viewer
+#[derive(Default)]
+struct Tracker {
+    visible: bool,
+    /// Top-left of the image, as duplication reports it
+    left: i32,
+    top: i32,
+    /// Which part of a stitched frame showed it last; only that one may hide it
+    owner: usize,
+    /// Bumped on every move and every new image
+    moves: u64,
+    image: Option<Arc<Image>>,
+    images: u64,
+}
+
+static CURSOR: Lazy<Mutex<Tracker>> = Lazy::new(|| Mutex::new(Tracker::default()));
+
+/// The capture saw the cursor image's top-left at `left`,`top` of the frame,
+/// or not on its part; `source` tells the outputs of a stitched frame apart
+pub fn moved(source: usize, visible: bool, left: i32, top: i32) {
+    let mut cursor = CURSOR.lock();
+    if !visible && (!cursor.visible || cursor.owner != source) {
+        return;
+    }
+    if (cursor.visible, cursor.left, cursor.top) != (visible, left, top) {
+        cursor.visible = visible;
+        cursor.left = left;
+        cursor.top = top;
+        cursor.moves += 1;
+    }
+    cursor.owner = source;
+}
+
+/// The cursor changed to `shape`
+pub fn reshaped(shape: &Shape) {
+    let png = match shape.png() {
+        Ok(png) => png,
+        Err(e) => {
+            eprintln!("[CAPTURE] Failed to encode cursor: {}", e);
+            return;
+        }
+    };
+    let image = Image { hotspot: (shape.hotspot.0 as u16, shape.hotspot.1 as u16), png };
+    let mut cursor = CURSOR.lock();
+    cursor.image = Some(Arc::new(image));
+    cursor.images += 1;
+    cursor.moves += 1;
+}
+
+/// Position unknown until the next move, e.g. after the captured area changed
+pub fn hide() {
+    let mut cursor = CURSOR.lock();
+    if cursor.visible {
+        cursor.visible = false;
+        cursor.moves += 1;
+    }
+}
+
+/// What a viewer was last sent
+#[derive(Debug, Default)]
+pub struct Sent {
+    moves: u64,
+    images: u64,
+}
+
+/// The update a viewer is due, if the cursor changed since `sent`
+pub fn update_since(sent: &mut Sent) -> Option<Message> {
+    let cursor = CURSOR.lock();
+    if cursor.moves == sent.moves {
+        return None;
+    }
+    let image = (cursor.images != sent.images).then(|| cursor.image.clone()).flatten();
+    let hotspot = cursor.image.as_ref().map_or((0, 0), |image| image.hotspot);
+    sent.moves = cursor.moves;
+    sent.images = cursor.images;
+    Some(Message {
+        visible: cursor.visible && cursor.image.is_some(),
+        x: cursor.left + hotspot.0 as i32,
+        y: cursor.top + hotspot.1 as i32,
+        image,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_monochrome_shape() {
+        // 8x1: AND mask row then XOR mask row
+        let data = [0b1100_0000, 0b0100_0001];
+        let shape = Shape::from_monochrome(8, 1, 1, &data, (20, 0)).unwrap();
+        let pixel = |x: usize| &shape.rgba[x * 4..x * 4 + 4];
+        assert_eq!(pixel(0), [0, 0, 0, 0]); // transparent
+        assert_eq!(pixel(1), [0, 0, 0, 255]); // inverted
+        assert_eq!(pixel(2), [0, 0, 0, 255]); // black
+        assert_eq!(pixel(7), [255, 255, 255, 255]); // white
+        assert_eq!(shape.hotspot, (7, 0));
+
+        assert!(Shape::from_monochrome(8, 2, 1, &data, (0, 0)).is_none());
+        assert!(Shape::from_bgra(MAX_SIZE + 1, 1, 4096, &[0; 4096], (0, 0)).is_none());
+    }
+
+    #[test]
+    fn test_masked_shape() {
+        let data = [10, 20, 30, 0, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+        let shape = Shape::from_masked(3, 1, 12, &data, (0, 0)).unwrap();
+        assert_eq!(shape.rgba, [30, 20, 10, 255, 0, 0, 0, 0, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let message = Message {
+            visible: true,
+            x: -5,
+            y: 1080,
+            image: Some(Arc::new(Image { hotspot: (3, 4), png: vec![0x89, b'P', b'N', b'G'] })),
+        };
+        assert_eq!(Message::decode(&message.encode()).unwrap(), message);
+
+        let moved = Message { image: None, ..message };
+        let encoded = moved.encode();
+        assert_eq!(encoded.len(), 10);
+        assert_eq!(Message::decode(&encoded).unwrap(), moved);
+        assert!(Message::decode(&encoded[..9]).is_err());
+        assert!(Message::decode(&[encoded.as_slice(), &[1, 2]].concat()).is_err());
+    }
+
+    #[test]
+    fn test_update_since() {
+        let mut sent = Sent::default();
+        let shape = Shape::from_bgra(2, 2, 8, &[255; 16], (1, 1)).unwrap();
+        reshaped(&shape);
+        moved(0, true, 100, 50);
+
+        let update = update_since(&mut sent).unwrap();
+        assert!(update.visible);
+        assert_eq!((update.x, update.y), (101, 51));
+        assert_eq!(update.image.unwrap().hotspot, (1, 1));
+        assert!(update_since(&mut sent).is_none());
+
+        // Another output of a stitched frame does not have it: not hidden
+        moved(1, false, 0, 0);
+        assert!(update_since(&mut sent).is_none());
+
+        moved(0, true, 110, 50);
+        let update = update_since(&mut sent).unwrap();
+        assert_eq!(update.x, 111);
+        assert!(update.image.is_none());
+
+        hide();
+        assert!(!update_since(&mut sent).unwrap().visible);
+    }
+}
@@ -0,0 +1,85 @@
+//! Debouncing for values that change in bursts, such as the viewport size
+//! while a window is drag-resized: only the value it settles on is sent
+
+use std::time::{Duration, Instant};
+
+/// What `Debouncer::poll` found
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Poll<T> {
+    /// Nothing new to send
+    Idle,
+    /// The value changed too recently; poll again after this long
+    Wait(Duration),
+    /// The value settled and differs from the last one sent
+    Ready(T),
+}
+
+pub struct Debouncer<T> {
+    quiet: Duration,
+    /// Latest value and when it arrived
+    pending: Option<(T, Instant)>,
+    sent: Option<T>,
+}
+
+impl<T: Copy + PartialEq> Debouncer<T> {
+    /// Values are released once they stayed unchanged for `quiet`
+    pub fn new(quiet: Duration) -> Self {
+        Self { quiet, pending: None, sent: None }
+    }
+
+    /// Note a new value; true when it starts a burst, so a poll should be scheduled
+    pub fn update(&mut self, value: T, now: Instant) -> bool {
+        if self.pending.is_none() && self.sent == Some(value) {
+            return false;
+        }
+        let started = self.pending.is_none();
+        self.pending = Some((value, now));
+        started
+    }
+
+    pub fn poll(&mut self, now: Instant) -> Poll<T> {
+        let Some((value, at)) = self.pending else {
+            return Poll::Idle;
+        };
+        let elapsed = now.saturating_duration_since(at);
+        if elapsed < self.quiet {
+            return Poll::Wait(self.quiet - elapsed);
+        }
+
+        self.pending = None;
+        if self.sent == Some(value) {
+            // Resized and back again
+            return Poll::Idle;
+        }
+        self.sent = Some(value);
+        Poll::Ready(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_burst_sends_last_value() {
+        let quiet = Duration::from_millis(200);
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut debouncer = Debouncer::new(quiet);
+
+        assert_eq!(debouncer.poll(start), Poll::Idle);
+        // Only the first change of a burst asks for a poll
+        assert!(debouncer.update((800, 600), at(0)));
+        assert!(!debouncer.update((810, 600), at(30)));
+        assert!(!debouncer.update((820, 610), at(60)));
+        assert_eq!(debouncer.poll(at(200)), Poll::Wait(Duration::from_millis(60)));
+        assert_eq!(debouncer.poll(at(260)), Poll::Ready((820, 610)));
+        assert_eq!(debouncer.poll(at(300)), Poll::Idle);
+
+        // The size already sent is not sent again
+        assert!(!debouncer.update((820, 610), at(400)));
+        assert!(debouncer.update((900, 700), at(500)));
+        assert!(!debouncer.update((820, 610), at(550)));
+        assert_eq!(debouncer.poll(at(800)), Poll::Idle);
+    }
+}
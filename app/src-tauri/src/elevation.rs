@@ -0,0 +1,179 @@
+//! Host process elevation (run as administrator / root)
+//!
+//! An unelevated host cannot inject input into elevated windows (UAC prompts,
+//! admin tools). These helpers report the current elevation level and relaunch
+//! the host through the platform's elevation prompt.
+
+#![allow(dead_code)]
+
+use anyhow::Result;
+
+/// Quote an argument for a POSIX shell command line
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Arguments to pass to the relaunched process
+fn relaunch_args() -> Vec<String> {
+    std::env::args().skip(1).collect()
+}
+
+#[cfg(windows)]
+mod windows_elevation {
+    use anyhow::Result;
+    use windows::core::{HSTRING, PCWSTR};
+    use windows::Win32::UI::Shell::{IsUserAnAdmin, ShellExecuteW};
+    use windows::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+
+    pub fn is_elevated() -> bool {
+        unsafe { IsUserAnAdmin().as_bool() }
+    }
+
+    /// Quote an argument for a Windows command line
+    fn quote_arg(arg: &str) -> String {
+        if arg.is_empty() || arg.contains(' ') || arg.contains('\t') {
+            format!("\"{}\"", arg.replace('"', "\\\""))
+        } else {
+            arg.to_string()
+        }
+    }
+
+    /// Start an elevated copy of this executable (shows the UAC prompt)
+    pub fn spawn_elevated() -> Result<()> {
+        let exe = std::env::current_exe()?;
+        let params = super::relaunch_args()
+            .iter()
+            .map(|a| quote_arg(a))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let result = unsafe {
+            ShellExecuteW(
+                None,
+                &HSTRING::from("runas"),
+                &HSTRING::from(exe.as_os_str()),
+                &HSTRING::from(params),
+                PCWSTR::null(),
+                SW_SHOWNORMAL,
+            )
+        };
+
+        // Values <= 32 are errors (e.g. the user declined the UAC prompt)
+        if result.0 <= 32 {
+            anyhow::bail!("Elevation was declined or failed (code {})", result.0);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos_elevation {
+    use anyhow::Result;
+    use std::process::Command;
+
+    pub fn is_elevated() -> bool {
+        unsafe { libc::geteuid() == 0 }
+    }
+
+    /// Start a root copy of this executable via the authorization dialog
+    pub fn spawn_elevated() -> Result<()> {
+        let exe = std::env::current_exe()?;
+        let mut command = super::shell_quote(&exe.to_string_lossy());
+        for arg in super::relaunch_args() {
+            command.push(' ');
+            command.push_str(&super::shell_quote(&arg));
+        }
+        command.push_str(" > /dev/null 2>&1 &");
+
+        let script = format!(
+            "do shell script \"{}\" with administrator privileges",
+            command.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+
+        let status = Command::new("osascript").arg("-e").arg(script).status()?;
+        if !status.success() {
+            anyhow::bail!("Authorization was declined or failed");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux_elevation {
+    use anyhow::Result;
+    use std::process::Command;
+
+    pub fn is_elevated() -> bool {
+        unsafe { libc::geteuid() == 0 }
+    }
+
+    /// Start a root copy of this executable via polkit (pkexec)
+    pub fn spawn_elevated() -> Result<()> {
+        let exe = std::env::current_exe()?;
+
+        // pkexec clears the environment, so pass the X session through explicitly.
+        // The shell backgrounds the new instance so pkexec returns once authorized.
+        let status = Command::new("pkexec")
+            .arg("env")
+            .arg(format!("DISPLAY={}", std::env::var("DISPLAY").unwrap_or_default()))
+            .arg(format!("XAUTHORITY={}", std::env::var("XAUTHORITY").unwrap_or_default()))
+            .arg("sh")
+            .arg("-c")
+            .arg("\"$0\" \"$@\" > /dev/null 2>&1 &")
+            .arg(&exe)
+            .args(super::relaunch_args())
+            .status()?;
+
+        if !status.success() {
+            anyhow::bail!("Authorization was declined or failed");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+use windows_elevation as platform;
+#[cfg(target_os = "macos")]
+use macos_elevation as platform;
+#[cfg(target_os = "linux")]
+use linux_elevation as platform;
+
+/// Whether the current process runs with administrator/root rights
+#[cfg(any(windows, target_os = "macos", target_os = "linux"))]
+pub fn is_elevated() -> bool {
+    platform::is_elevated()
+}
+
+/// Relaunch the current executable elevated, then exit this process
+/// Returns an error (and keeps running) if the prompt is declined
+#[cfg(any(windows, target_os = "macos", target_os = "linux"))]
+pub fn relaunch_elevated() -> Result<()> {
+    if is_elevated() {
+        anyhow::bail!("Already running elevated");
+    }
+
+    platform::spawn_elevated()?;
+    println!("[ELEVATION] Elevated instance started, exiting");
+    std::process::exit(0);
+}
+
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+pub fn is_elevated() -> bool {
+    false
+}
+
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+pub fn relaunch_elevated() -> Result<()> {
+    anyhow::bail!("Elevation is not supported on this platform")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("/usr/bin/securedesk"), "'/usr/bin/securedesk'");
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+}
@@ -0,0 +1,207 @@
+//! Errors returned by Tauri commands
+//!
+//! Serialized as `{ "code": "session_not_found", "message": "..." }` so the UI
+//! can branch on the kind of failure (retry, sign in again, ask to upgrade)
+//! while still showing the message. `license_required` also carries the
+//! `feature` that was refused and the `required_tier` to offer, if any.
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppError {
+    /// No session was given and none is active
+    NoActiveSession,
+    /// The session id is unknown, or the session has ended
+    SessionNotFound(String),
+    /// The host listener is not running
+    NoHostSession,
+    /// An argument or setting value was rejected
+    InvalidInput(String),
+    /// Not allowed for this session, e.g. input on a view-only one
+    PermissionDenied(String),
+    /// Needs a license tier that is not active, or exceeds the licensed limits
+    LicenseRequired {
+        message: String,
+        /// `LicenseFeature::key`, or "concurrent_sessions" for the session limit
+        feature: &'static str,
+        /// Lowest tier with the feature; None when a higher tier may not help
+        required_tier: Option<&'static str>,
+    },
+    /// SSO sign-in, token refresh or a host's access password failed
+    Auth(String),
+    /// The remote proved to be a different device than the one dialled
+    IdentityMismatch(String),
+    /// Relay, peer or socket I/O failed; usually worth retrying
+    Network(String),
+    /// Anything else
+    Internal(String),
+}
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NoActiveSession => "no_active_session",
+            Self::SessionNotFound(_) => "session_not_found",
+            Self::NoHostSession => "no_host_session",
+            Self::InvalidInput(_) => "invalid_input",
+            Self::PermissionDenied(_) => "permission_denied",
+            Self::LicenseRequired { .. } => "license_required",
+            Self::Auth(_) => "auth",
+            Self::IdentityMismatch(_) => "identity_mismatch",
+            Self::Network(_) => "network",
+            Self::Internal(_) => "internal",
+        }
+    }
+
+    /// A setter's error: the value was rejected, unless it was saving that failed
+    pub fn invalid_input(error: anyhow::Error) -> Self {
+        match io_error(&error) {
+            Some(_) => Self::Internal(error.to_string()),
+            None => Self::InvalidInput(error.to_string()),
+        }
+    }
+
+    /// A command refused because the license lacks `feature`
+    pub fn license_required(feature: crate::license::LicenseFeature) -> Self {
+        let tier = feature.min_tier().as_str();
+        Self::LicenseRequired {
+            message: format!("{} requires a {} license or higher", feature.display_name(), tier),
+            feature: feature.key(),
+            required_tier: Some(tier),
+        }
+    }
+
+    /// A connection refused because `max` client sessions are already open
+    pub fn session_limit(max: u32) -> Self {
+        let plural = if max == 1 { "" } else { "s" };
+        Self::LicenseRequired {
+            message: format!("Your license allows {} concurrent session{}", max, plural),
+            feature: "concurrent_sessions",
+            required_tier: None,
+        }
+    }
+
+    /// An SSO flow's error; network trouble stays Network so the UI can retry
+    pub fn auth(error: anyhow::Error) -> Self {
+        match Self::from(error) {
+            Self::Internal(message) => Self::Auth(message),
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoActiveSession => f.write_str("No active session"),
+            Self::SessionNotFound(id) => write!(f, "Session {} not found", id),
+            Self::NoHostSession => f.write_str("No host session active"),
+            Self::InvalidInput(message)
+            | Self::PermissionDenied(message)
+            | Self::LicenseRequired { message, .. }
+            | Self::Auth(message)
+            | Self::IdentityMismatch(message)
+            | Self::Network(message)
+            | Self::Internal(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Self::LicenseRequired { feature, required_tier, .. } = self else {
+            let mut error = serializer.serialize_struct("AppError", 2)?;
+            error.serialize_field("code", self.code())?;
+            error.serialize_field("message", &self.to_string())?;
+            return error.end();
+        };
+        let mut error = serializer.serialize_struct("AppError", 4)?;
+        error.serialize_field("code", self.code())?;
+        error.serialize_field("message", &self.to_string())?;
+        error.serialize_field("feature", feature)?;
+        error.serialize_field("required_tier", required_tier)?;
+        error.end()
+    }
+}
+
+/// Network when a socket failed somewhere along the chain, otherwise Internal
+impl From<anyhow::Error> for AppError {
+    fn from(error: anyhow::Error) -> Self {
+        let message = error.to_string();
+        if message.starts_with("Identity mismatch") {
+            return Self::IdentityMismatch(message);
+        }
+        if error.chain().any(|cause| cause.is::<tokio::time::error::Elapsed>()) {
+            return Self::Network(message);
+        }
+        match io_error(&error) {
+            Some(io) if is_network(io.kind()) => Self::Network(message),
+            _ => Self::Internal(message),
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(error: std::io::Error) -> Self {
+        Self::from(anyhow::Error::from(error))
+    }
+}
+
+fn io_error(error: &anyhow::Error) -> Option<&std::io::Error> {
+    error.chain().find_map(|cause| cause.downcast_ref::<std::io::Error>())
+}
+
+fn is_network(kind: std::io::ErrorKind) -> bool {
+    use std::io::ErrorKind::*;
+    matches!(
+        kind,
+        ConnectionRefused
+            | ConnectionReset
+            | ConnectionAborted
+            | NotConnected
+            | AddrNotAvailable
+            | BrokenPipe
+            | TimedOut
+            | UnexpectedEof
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_kinds() {
+        let refused = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        let error = anyhow::Error::from(refused).context("Relay relay.example.com:443 failed");
+        assert_eq!(AppError::from(error).code(), "network");
+
+        let missing = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        assert_eq!(AppError::from(missing).code(), "internal");
+        assert_eq!(AppError::invalid_input(anyhow::anyhow!("Invalid port")).code(), "invalid_input");
+        assert_eq!(AppError::auth(anyhow::anyhow!("Token exchange failed")).code(), "auth");
+        assert_eq!(
+            AppError::from(anyhow::anyhow!("Identity mismatch: host cannot prove it is device 123")).code(),
+            "identity_mismatch",
+        );
+
+        let json = serde_json::to_value(AppError::SessionNotFound("session_3".into())).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "code": "session_not_found",
+            "message": "Session session_3 not found",
+        }));
+
+        let json = serde_json::to_value(AppError::license_required(crate::license::LicenseFeature::SessionRecording)).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "code": "license_required",
+            "message": "Session recording requires a Pro license or higher",
+            "feature": "session_recording",
+            "required_tier": "Pro",
+        }));
+        assert_eq!(AppError::session_limit(1).to_string(), "Your license allows 1 concurrent session");
+        assert_eq!(AppError::session_limit(3).to_string(), "Your license allows 3 concurrent sessions");
+    }
+}
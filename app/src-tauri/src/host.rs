@@ -4,446 +4,2565 @@
 
 use anyhow::Result;
 use tauri::Emitter;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
 use tokio::net::TcpStream;
-use tokio::sync::mpsc;
-use tokio_rustls::rustls::{ClientConfig, RootCertStore};
-use tokio_rustls::TlsConnector;
+use tokio::sync::{mpsc, Notify};
+use tokio::sync::Mutex as AsyncMutex;
+use once_cell::sync::Lazy;
 use parking_lot::Mutex as SyncMutex;
 
-use crate::capture::ScreenCapture;
+use crate::capture::{Delta, ScreenCapture};
 use crate::crypto::{Identity, SecureChannel};
-use crate::input::InputInjector;
+use crate::input::{self, InputClass, InputRateLimiter, SharedInputInjector};
 use crate::p2p::{gather_p2p_info, choose_p2p_port, create_p2p_listener, accept_p2p_connection};
 use crate::privacy::PrivacyMode;
-use crate::protocol::{self, Channel, Frame};
-use crate::transport::{ConnectionType, P2PInfo};
+use crate::protocol::{self, Capabilities, Channel, ChannelState, Frame};
+use crate::sources::{self, SourceKind};
+use crate::transport::{ConnectionType, DirectReader, DirectWriter, P2PInfo, P2PTransport};
 
 /// Callback type for connection request notifications
 pub type ConnectionCallback = Box<dyn Fn(String) + Send + Sync>;
 
-/// Pending connection awaiting user approval
-pub struct PendingConnection {
-    pub remote_id: String,
-    pub response_tx: mpsc::Sender<bool>,
+type RelayStream = tokio_rustls::client::TlsStream<TcpStream>;
+
+/// Maximum queued video requests before the reader applies backpressure
+const VIDEO_QUEUE_DEPTH: usize = 8;
+
+/// Tile frames sent in a row before a whole frame again, so a client whose
+/// canvas went wrong somewhere recovers
+const KEYFRAME_INTERVAL: u32 = 120;
+
+/// Maximum connection requests awaiting approval at once; further requests are refused as busy
+const MAX_PENDING_CONNECTIONS: usize = 4;
+
+/// Viewers served at once on a multiplexed registration; further ones are refused as busy
+const MAX_VIEWERS: usize = 4;
+
+/// Viewer ID of the one client a plain (non-multiplexed) registration serves
+const LEGACY_VIEWER: u16 = 0;
+
+/// Longest a connection request may wait for the user
+pub const MAX_APPROVAL_TIMEOUT_SECS: u32 = 600;
+
+/// How long a connection request waits for the user before it is declined (0 = at once)
+static APPROVAL_TIMEOUT_SECS: AtomicU32 = AtomicU32::new(30);
+
+/// Set the approval timeout for connection requests received from now on
+pub fn set_approval_timeout(secs: u32) {
+    APPROVAL_TIMEOUT_SECS.store(secs.min(MAX_APPROVAL_TIMEOUT_SECS), Ordering::Relaxed);
 }
 
-/// Host session - running on the PC being controlled
-pub struct HostSession {
-    identity: Identity,
-    stream: Option<tokio_rustls::client::TlsStream<TcpStream>>,
-    p2p_stream: Option<TcpStream>,
-    channel: Option<SecureChannel>,
-    capture: ScreenCapture,
-    input: InputInjector,
-    privacy: PrivacyMode,
-    running: bool,
-    pending_connection: Arc<SyncMutex<Option<PendingConnection>>>,
-    connection_type: ConnectionType,
-    p2p_enabled: bool,
-    /// Target resolution from client (for adaptive scaling)
-    target_resolution: Option<(u16, u16)>,
+/// Approval timeout for new connection requests
+pub fn approval_timeout() -> Duration {
+    Duration::from_secs(APPROVAL_TIMEOUT_SECS.load(Ordering::Relaxed) as u64)
 }
 
-impl HostSession {
-    /// Start hosting and connect to relay (with P2P enabled by default)
-    pub async fn start(relay_address: String, identity: Identity) -> Result<Self> {
-        Self::start_with_p2p(relay_address, identity, true).await
+/// Idle time after which an accepted viewer is disconnected (0 = never).
+/// Idle-based: the clock restarts on every input frame the viewer sends, so
+/// a watched-but-busy session stays up while a forgotten one is closed.
+static SESSION_TIMEOUT_SECS: AtomicU32 = AtomicU32::new(0);
+
+/// Set the idle session timeout; applies to running sessions too
+pub fn set_session_timeout(secs: u32) {
+    SESSION_TIMEOUT_SECS.store(secs, Ordering::Relaxed);
+}
+
+/// Idle session timeout, None when disabled
+fn session_timeout() -> Option<Duration> {
+    match SESSION_TIMEOUT_SECS.load(Ordering::Relaxed) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs as u64)),
     }
+}
 
-    /// Start hosting with explicit P2P control
-    pub async fn start_with_p2p(relay_address: String, identity: Identity, p2p_enabled: bool) -> Result<Self> {
-        println!("[HOST] Starting host session, connecting to relay: {}", relay_address);
-        println!("[HOST] P2P enabled: {}", p2p_enabled);
+/// When the first of the given viewers (by last input) runs out of idle time
+fn earliest_idle_deadline(last_inputs: impl Iterator<Item = Instant>, timeout: Option<Duration>) -> Option<Instant> {
+    let timeout = timeout?;
+    last_inputs.min().map(|last| last + timeout)
+}
 
-        // Parse address
-        let (host, port) = relay_address
-            .rsplit_once(':')
-            .ok_or_else(|| anyhow::anyhow!("Invalid relay address"))?;
-        let port: u16 = port.parse()?;
-        println!("[HOST] Parsed address: host={}, port={}", host, port);
+/// Lock the workstation when the last accepted viewer's session ends
+static LOCK_ON_DISCONNECT: AtomicBool = AtomicBool::new(false);
 
-        // TLS setup
-        let mut root_store = RootCertStore::empty();
-        root_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+/// Set whether sessions ending from now on lock the workstation
+pub fn set_lock_on_disconnect(enabled: bool) {
+    LOCK_ON_DISCONNECT.store(enabled, Ordering::Relaxed);
+}
 
-        let config = ClientConfig::builder()
-            .with_root_certificates(root_store)
-            .with_no_client_auth();
+/// Accepted viewers start view-only and ask for control (control::REQUEST_CONTROL)
+static START_VIEW_ONLY: AtomicBool = AtomicBool::new(false);
 
-        let connector = TlsConnector::from(Arc::new(config));
+/// Set whether viewers accepted from now on start view-only
+pub fn set_start_view_only(enabled: bool) {
+    START_VIEW_ONLY.store(enabled, Ordering::Relaxed);
+}
 
-        // Connect to relay
-        let tcp = TcpStream::connect(format!("{}:{}", host, port)).await?;
-        let server_name = tokio_rustls::rustls::pki_types::ServerName::try_from(host.to_owned())?;
-        let mut stream = connector.connect(server_name, tcp).await?;
+fn start_view_only() -> bool {
+    START_VIEW_ONLY.load(Ordering::Relaxed)
+}
 
-        // Register as endpoint with our ID
-        let id = identity.device_id_raw();
-        println!("[HOST] Registering as endpoint with ID: {}", id);
-        stream.write_u8(0x01).await?; // Endpoint type
-        // Use big-endian for protocol compatibility with Go server
-        stream.write_all(&(id.len() as u16).to_be_bytes()).await?;
-        stream.write_all(id.as_bytes()).await?;
-        stream.flush().await?;
-        println!("[HOST] Registration sent, host session initialized");
+/// Device IDs (without spaces) whose connection requests are refused unseen;
+/// matched against the ID a viewer's handshake keys prove
+static BLOCKED_DEVICES: Lazy<SyncMutex<HashSet<String>>> = Lazy::new(|| SyncMutex::new(HashSet::new()));
 
-        // Initialize capture/input
-        let capture = ScreenCapture::new()?;
-        let input = InputInjector::new();
-        let privacy = PrivacyMode::new();
+/// Replace the blocked device list used for new connection requests
+pub fn set_blocked_devices(devices: HashSet<String>) {
+    *BLOCKED_DEVICES.lock() = devices;
+}
 
-        Ok(Self {
-            identity,
-            stream: Some(stream),
-            p2p_stream: None,
-            channel: None,
-            capture,
-            input,
-            privacy,
-            running: true,
-            pending_connection: Arc::new(SyncMutex::new(None)),
-            connection_type: ConnectionType::Relay,
-            p2p_enabled,
-            target_resolution: None,
-        })
+/// Whether connection requests from a device are refused without asking the user
+pub fn is_device_blocked(device_id: &str) -> bool {
+    BLOCKED_DEVICES.lock().contains(&device_id.replace(' ', ""))
+}
+
+/// Static keys of viewers accepted "Always"; their requests skip the prompt
+static ALLOWED_VIEWERS: Lazy<SyncMutex<HashSet<[u8; 32]>>> = Lazy::new(|| SyncMutex::new(HashSet::new()));
+
+/// Replace the allow-list used for new connection requests
+pub fn set_allowed_viewers(keys: HashSet<[u8; 32]>) {
+    *ALLOWED_VIEWERS.lock() = keys;
+}
+
+/// Whether the viewer holding `client_key` is let in without asking the user
+pub fn is_viewer_allowed(client_key: &[u8; 32]) -> bool {
+    ALLOWED_VIEWERS.lock().contains(client_key)
+}
+
+/// Hash of the unattended access password; while set, only viewers that
+/// send it get in and nobody is asked
+static UNATTENDED_PASSWORD: Lazy<SyncMutex<Option<String>>> = Lazy::new(|| SyncMutex::new(None));
+
+/// How long a viewer has to send control::AUTH_PASSWORD under unattended access
+const PASSWORD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Set or clear the unattended access password hash used for new connection requests
+pub fn set_unattended_password(hash: Option<String>) {
+    *UNATTENDED_PASSWORD.lock() = hash;
+}
+
+fn unattended_password() -> Option<String> {
+    UNATTENDED_PASSWORD.lock().clone()
+}
+
+/// Wrong access passwords a device may send within `PASSWORD_FAILURE_WINDOW`
+/// before its requests are refused without checking
+const MAX_PASSWORD_FAILURES: usize = 5;
+
+/// Wrong passwords from all devices together before every request is refused;
+/// the device ID is only the viewer's claim, so this bounds guessing under fresh IDs
+const MAX_TOTAL_PASSWORD_FAILURES: usize = 20;
+
+const PASSWORD_FAILURE_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// Recent wrong access passwords, per device and in all
+#[derive(Default)]
+struct PasswordFailures {
+    /// Device ID (without spaces) to when it failed, oldest first
+    by_device: HashMap<String, VecDeque<Instant>>,
+    all: VecDeque<Instant>,
+}
+
+impl PasswordFailures {
+    fn record(&mut self, device_id: &str, now: Instant) {
+        self.expire(now);
+        self.by_device.entry(device_id.replace(' ', "")).or_default().push_back(now);
+        self.all.push_back(now);
     }
 
-    /// Get the current connection type
-    pub fn connection_type(&self) -> ConnectionType {
-        self.connection_type
+    /// The right password clears the device's count, not the total
+    fn succeeded(&mut self, device_id: &str) {
+        self.by_device.remove(&device_id.replace(' ', ""));
     }
 
-    /// Set P2P enabled state
-    pub fn set_p2p_enabled(&mut self, enabled: bool) {
-        self.p2p_enabled = enabled;
+    fn is_locked_out(&mut self, device_id: &str, now: Instant) -> bool {
+        self.expire(now);
+        self.all.len() >= MAX_TOTAL_PASSWORD_FAILURES
+            || self.by_device.get(&device_id.replace(' ', "")).is_some_and(|f| f.len() >= MAX_PASSWORD_FAILURES)
     }
 
-    /// Get a reference to pending connection for external access
-    pub fn pending_connection(&self) -> Arc<SyncMutex<Option<PendingConnection>>> {
-        self.pending_connection.clone()
+    fn expire(&mut self, now: Instant) {
+        let recent = |at: &Instant| now.duration_since(*at) < PASSWORD_FAILURE_WINDOW;
+        self.all.retain(recent);
+        self.by_device.retain(|_, failures| {
+            failures.retain(recent);
+            !failures.is_empty()
+        });
     }
+}
 
-    /// Main loop - handle incoming requests
-    pub async fn run(&mut self) -> Result<()> {
-        while self.running {
-            self.run_once().await?;
+static PASSWORD_FAILURES: Lazy<SyncMutex<PasswordFailures>> = Lazy::new(|| SyncMutex::new(PasswordFailures::default()));
+
+/// How long accepting a connection request trusts the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrustDuration {
+    /// Just this connection; the next request asks again
+    Once,
+    /// Until the host listener stops (or SecureDesk quits)
+    Session,
+    /// For the next hour
+    Hour,
+    /// Permanently, on the host's allow-list
+    Always,
+}
+
+/// Life of a `TrustDuration::Hour` grant
+pub const TEMPORARY_TRUST_HOUR: Duration = Duration::from_secs(60 * 60);
+
+/// In-memory trust grants, so a device reconnecting shortly after being
+/// accepted is not asked about again; never saved to disk
+/// Grants belong to the static key the viewer proved in the Noise handshake,
+/// not to the device ID it claims, which anyone could send
+#[derive(Default)]
+pub struct TemporaryTrust {
+    /// Viewer key to its device ID (without spaces, for display) and expiry;
+    /// None lasts until the host stops
+    grants: HashMap<[u8; 32], (String, Option<Instant>)>,
+}
+
+impl TemporaryTrust {
+    /// Trust the viewer holding `client_key` until `expires`, or until the host stops if None
+    /// A longer grant already in place is kept.
+    pub fn grant(&mut self, client_key: [u8; 32], device_id: &str, expires: Option<Instant>) {
+        let (id, grant) = self.grants.entry(client_key).or_insert((String::new(), expires));
+        *id = device_id.replace(' ', "");
+        *grant = match (*grant, expires) {
+            (Some(current), Some(new)) => Some(current.max(new)),
+            _ => None,
+        };
+    }
+
+    pub fn is_trusted(&self, client_key: &[u8; 32], now: Instant) -> bool {
+        match self.grants.get(client_key) {
+            Some((_, Some(expires))) => *expires > now,
+            Some((_, None)) => true,
+            None => false,
         }
-        Ok(())
     }
 
-    /// Run one iteration - process a single frame
-    pub async fn run_once(&mut self) -> Result<()> {
-        self.run_once_internal(None::<&tauri::AppHandle>).await
+    /// Remove a device's grants; false if it had none
+    pub fn revoke(&mut self, device_id: &str) -> bool {
+        let device_id = device_id.replace(' ', "");
+        let before = self.grants.len();
+        self.grants.retain(|_, (id, _)| *id != device_id);
+        self.grants.len() != before
     }
 
-    /// Run one iteration with event emission support
-    pub async fn run_once_with_events(&mut self, app_handle: &tauri::AppHandle) -> Result<()> {
-        self.run_once_internal(Some(app_handle)).await
+    /// Drop grants that lasted until the host stopped, and expired ones
+    pub fn end_session(&mut self, now: Instant) {
+        self.grants.retain(|_, (_, expires)| expires.is_some_and(|at| at > now));
     }
 
-    async fn run_once_internal<R: tauri::Runtime>(&mut self, app_handle: Option<&tauri::AppHandle<R>>) -> Result<()> {
-        if !self.running {
-            anyhow::bail!("Session stopped");
-        }
+    /// Grants still in force as (device_id, time left), sorted by device ID
+    /// Time left is None for grants that last until the host stops.
+    pub fn list(&mut self, now: Instant) -> Vec<(String, Option<Duration>)> {
+        self.grants.retain(|_, (_, expires)| expires.is_none_or(|at| at > now));
+        let mut grants: Vec<_> = self.grants.values()
+            .map(|(id, expires)| (id.clone(), expires.map(|at| at - now)))
+            .collect();
+        grants.sort();
+        grants
+    }
+}
 
-        println!("[HOST] Waiting for frame...");
-        let frame = self.read_frame().await?;
-        println!("[HOST] Received frame on channel {:?}, payload len: {}", frame.channel, frame.payload.len());
-        if !frame.payload.is_empty() {
-            println!("[HOST] First payload byte: 0x{:02x}", frame.payload[0]);
-        }
+/// Temporary grants shared by the host session and the UI commands
+pub static TEMPORARY_TRUST: Lazy<SyncMutex<TemporaryTrust>> = Lazy::new(|| SyncMutex::new(TemporaryTrust::default()));
 
-        match frame.channel {
-            Channel::Control => {
-                println!("[HOST] Handling control message");
-                self.handle_control_with_events(&frame, app_handle).await?;
-            }
-            Channel::Input => {
-                println!("[HOST] Handling input");
-                self.handle_input(&frame).await?;
+/// Whether the viewer holding `client_key` is accepted without asking, for now
+pub fn has_temporary_trust(client_key: &[u8; 32]) -> bool {
+    TEMPORARY_TRUST.lock().is_trusted(client_key, Instant::now())
+}
+
+/// How long to wait for the relay to answer a registration (alias claim, multiplexing)
+const REGISTRATION_REPLY_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(5);
+
+/// Remembers the hash of the last encoded frame sent to the client
+#[derive(Default)]
+struct FrameDeduper {
+    last: Option<blake3::Hash>,
+}
+
+impl FrameDeduper {
+    /// Record a frame; true if it is byte-identical to the previous one
+    fn is_repeat(&mut self, data: &[u8]) -> bool {
+        let hash = blake3::hash(data);
+        let repeat = self.last == Some(hash);
+        self.last = Some(hash);
+        repeat
+    }
+
+    fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Unchanged frames in a row before capture drops to the idle rate
+const IDLE_AFTER_UNCHANGED: u32 = 30;
+
+/// Paces captures for the video task: the active rate while the screen changes,
+/// the idle rate once `IDLE_AFTER_UNCHANGED` frames in a row were unchanged
+#[derive(Default)]
+struct CaptureThrottle {
+    unchanged: u32,
+    last_capture: Option<Instant>,
+}
+
+impl CaptureThrottle {
+    fn is_idle(&self) -> bool {
+        self.unchanged >= IDLE_AFTER_UNCHANGED
+    }
+
+    /// Time to wait before the next capture at the given (active, idle) rates
+    fn delay(&self, now: Instant, (active_fps, idle_fps): (u32, u32)) -> Duration {
+        let fps = if self.is_idle() { idle_fps } else { active_fps };
+        let interval = Duration::from_secs(1) / fps.max(1);
+        self.last_capture
+            .map(|last| interval.saturating_sub(now.saturating_duration_since(last)))
+            .unwrap_or_default()
+    }
+
+    /// Record a capture and whether it differed from the previous one
+    fn record(&mut self, now: Instant, changed: bool) {
+        self.last_capture = Some(now);
+        if changed {
+            if self.is_idle() {
+                println!("[HOST] Screen changed, capture back at active rate");
             }
-            Channel::Privacy => {
-                println!("[HOST] Handling privacy");
-                self.handle_privacy(&frame).await?;
+            self.unchanged = 0;
+        } else {
+            self.unchanged = self.unchanged.saturating_add(1);
+            if self.unchanged == IDLE_AFTER_UNCHANGED {
+                println!("[HOST] Screen static, capture at idle rate");
             }
-            Channel::Video => {
-                println!("[HOST] Video request - sending frame");
-                self.send_video_frame().await?;
+        }
+    }
+
+    /// Input arrived, so the screen is about to change - leave idle right away
+    fn wake(&mut self) {
+        self.unchanged = 0;
+    }
+}
+
+/// Failed captures in a row before the capture is closed and reopened
+const CAPTURE_FAILURE_LIMIT: u32 = 3;
+
+/// How often a host without a working capture tries to open it again
+const CAPTURE_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Tracks whether capture works for the video task: when to reopen a failed
+/// capture, and whether the client has been told it is unavailable
+#[derive(Default)]
+struct CaptureHealth {
+    failures: u32,
+    last_attempt: Option<Instant>,
+    /// Set while the client believes capture is unavailable
+    unavailable: bool,
+}
+
+impl CaptureHealth {
+    /// Whether a missing capture should be opened again now
+    fn should_retry(&self, now: Instant) -> bool {
+        self.last_attempt
+            .is_none_or(|last| now.saturating_duration_since(last) >= CAPTURE_RETRY_INTERVAL)
+    }
+
+    /// Record a failed open; retried after `CAPTURE_RETRY_INTERVAL`
+    fn open_failed(&mut self, now: Instant) {
+        self.last_attempt = Some(now);
+        self.unavailable = true;
+    }
+
+    /// Record a failed capture; true once the capture should be closed and reopened
+    fn capture_failed(&mut self, now: Instant) -> bool {
+        self.failures += 1;
+        if self.failures < CAPTURE_FAILURE_LIMIT {
+            return false;
+        }
+        self.failures = 0;
+        self.open_failed(now);
+        true
+    }
+
+    /// Record a captured frame; true if capture was unavailable until now
+    fn captured(&mut self) -> bool {
+        self.failures = 0;
+        std::mem::take(&mut self.unavailable)
+    }
+}
+
+/// The host's sound sent to a viewer that asked for it (`audio` feature)
+#[derive(Default)]
+struct ViewerAudio {
+    /// Asked for before the viewer was accepted; started once it is
+    requested: bool,
+    /// Forwards capture packets; aborted when dropped
+    #[cfg(feature = "audio")]
+    task: Option<AudioTask>,
+}
+
+#[cfg(feature = "audio")]
+struct AudioTask(tokio::task::JoinHandle<()>);
+
+#[cfg(feature = "audio")]
+impl Drop for AudioTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl ViewerAudio {
+    /// Start or stop sending sound, at the rate negotiated in HELLO
+    #[cfg_attr(not(feature = "audio"), allow(unused_variables))]
+    fn set_enabled(&mut self, enabled: bool, capabilities: &Capabilities, link: &ViewerLink) -> Result<()> {
+        let Some(rate) = capabilities.audio_rate() else {
+            anyhow::bail!("Audio not negotiated");
+        };
+        #[cfg(feature = "audio")]
+        {
+            if !enabled {
+                self.task = None;
+            } else if self.task.is_none() {
+                let capture = crate::audio::capture(rate)?;
+                let mut packets = capture.subscribe();
+                let link = link.clone();
+                self.task = Some(AudioTask(tokio::spawn(async move {
+                    let _capture = capture;
+                    loop {
+                        match packets.recv().await {
+                            Ok(packet) => {
+                                if link.write_frame(Frame::new(Channel::Audio, packet.to_vec())).await.is_err() {
+                                    break;
+                                }
+                            }
+                            // Late packets are dropped on the client anyway
+                            Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                        }
+                    }
+                })));
             }
-            Channel::Clipboard => {
-                println!("[HOST] Handling clipboard");
-                self.handle_clipboard_with_events(&frame, app_handle).await?;
+        }
+        Ok(())
+    }
+}
+
+/// A viewer's VP8/VP9 video, once it negotiated a codec (`codec` feature),
+/// and the registration that has frames kept as RGB for it
+#[derive(Default)]
+struct VpxVideo {
+    #[cfg(feature = "codec")]
+    stream: Option<(crate::vpx::Stream, crate::capture::RgbViewer)>,
+}
+
+impl VpxVideo {
+    /// Whether frames go out as VP8/VP9; starts the stream on first use
+    fn active(&mut self, capabilities: &Capabilities) -> bool {
+        #[cfg(feature = "codec")]
+        {
+            if self.stream.is_none() {
+                if let Some(codec) = crate::vpx::VpxCodec::negotiated(capabilities) {
+                    println!("[HOST] Sending video as {:?}", codec);
+                    let stream = crate::vpx::Stream::new(codec, KEYFRAME_INTERVAL);
+                    self.stream = Some((stream, crate::capture::RgbViewer::register()));
+                }
             }
-            _ => {
-                println!("[HOST] Unknown channel");
+            self.stream.is_some()
+        }
+        #[cfg(not(feature = "codec"))]
+        {
+            let _ = capabilities;
+            false
+        }
+    }
+
+    /// Capture and encode the next frame; Unchanged when the screen is
+    #[cfg_attr(not(feature = "codec"), allow(unused_variables))]
+    fn capture(&mut self, capture: &mut ScreenCapture, captured_at: u64) -> Result<Delta> {
+        #[cfg(feature = "codec")]
+        if let Some((stream, _)) = &mut self.stream {
+            let quality = crate::capture::get_quality();
+            // A static screen is not captured again, so a new stream asks for
+            // a fresh frame rather than wait for the screen to change
+            if stream.is_idle() || crate::capture::latest_rgb().is_none() {
+                capture.capture_keyframe(quality)?;
+            } else {
+                capture.capture()?;
             }
+            let Some(frame) = crate::capture::latest_rgb() else {
+                return Ok(Delta::Unchanged);
+            };
+            return Ok(match stream.encode(&frame, quality)? {
+                Some(packet) => Delta::Encoded {
+                    width: packet.width,
+                    height: packet.height,
+                    payload: packet.frame(captured_at),
+                },
+                None => Delta::Unchanged,
+            });
         }
-        Ok(())
+        capture.capture().map(Delta::full)
     }
+}
 
-    async fn read_frame(&mut self) -> Result<Frame> {
-        let stream = self.stream.as_mut().ok_or_else(|| anyhow::anyhow!("Not connected"))?;
+/// Pending connection awaiting user approval
+pub struct PendingConnection {
+    pub request_id: u64,
+    pub remote_id: String,
+    /// Static key the requester proved in the Noise handshake
+    pub client_key: [u8; 32],
+    pub response_tx: mpsc::Sender<bool>,
+}
 
-        let mut header = [0u8; 4];
-        stream.read_exact(&mut header).await?;
+/// Connection requests awaiting user approval, oldest first
+///
+/// Each request has its own response channel and timeout, so a second requester
+/// never replaces (and orphans) the first.
+#[derive(Default)]
+pub struct PendingConnections {
+    next_id: u64,
+    queue: VecDeque<PendingConnection>,
+}
 
-        let channel = Channel::try_from(header[0])?;
-        let len = ((header[1] as usize) << 16)
-            | ((header[2] as usize) << 8)
-            | (header[3] as usize);
+impl PendingConnections {
+    /// Queue a request, returning its id, or None if the queue is full
+    fn push(&mut self, remote_id: String, client_key: [u8; 32], response_tx: mpsc::Sender<bool>) -> Option<u64> {
+        if self.queue.len() >= MAX_PENDING_CONNECTIONS {
+            return None;
+        }
 
-        let mut payload = vec![0u8; len];
-        stream.read_exact(&mut payload).await?;
+        self.next_id += 1;
+        self.queue.push_back(PendingConnection {
+            request_id: self.next_id,
+            remote_id,
+            client_key,
+            response_tx,
+        });
+        Some(self.next_id)
+    }
 
-        // Decrypt if channel established
-        let decrypted = if let Some(ref mut ch) = self.channel {
-            ch.decrypt(&payload)?
-        } else {
-            payload
+    /// Drop a request once it has been answered or timed out
+    fn remove(&mut self, request_id: u64) {
+        self.queue.retain(|p| p.request_id != request_id);
+    }
+
+    /// Answer a request by id, or the oldest one if no id is given
+    /// Returns the requesting device's ID and key.
+    pub fn respond(&mut self, request_id: Option<u64>, accept: bool) -> Result<(String, [u8; 32])> {
+        let index = match request_id {
+            Some(id) => self.queue.iter().position(|p| p.request_id == id),
+            None if self.queue.is_empty() => None,
+            None => Some(0),
         };
 
-        Ok(Frame::new(channel, decrypted))
+        let pending = index
+            .and_then(|i| self.queue.remove(i))
+            .ok_or_else(|| anyhow::anyhow!("No pending connection"))?;
+        let _ = pending.response_tx.try_send(accept);
+        Ok((pending.remote_id, pending.client_key))
     }
 
-    async fn write_frame(&mut self, frame: Frame) -> Result<()> {
-        let stream = self.stream.as_mut().ok_or_else(|| anyhow::anyhow!("Not connected"))?;
+    /// Requests still waiting, as (request_id, remote_id)
+    pub fn list(&self) -> Vec<(u64, String)> {
+        self.queue.iter().map(|p| (p.request_id, p.remote_id.clone())).collect()
+    }
 
-        // Encrypt if channel established
-        let payload = if let Some(ref mut ch) = self.channel {
-            ch.encrypt(&frame.payload)?
-        } else {
-            frame.payload
-        };
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
 
-        let len = payload.len();
-        let header = [
-            frame.channel as u8,
-            (len >> 16) as u8,
-            (len >> 8) as u8,
-            len as u8,
-        ];
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
 
-        stream.write_all(&header).await?;
-        stream.write_all(&payload).await?;
-        stream.flush().await?;
-        Ok(())
+/// Decides whose input reaches this machine while several viewers are connected:
+/// one controller at a time, everyone else view-only
+///
+/// Invariant: there is a controller whenever at least one viewer was accepted,
+/// unless `view_only_start` is set - then control is only ever granted by the
+/// user, and can be revoked.
+#[derive(Default)]
+struct InputControl {
+    /// Viewers the user accepted, in the order they were accepted
+    approved: Vec<u16>,
+    controller: Option<u16>,
+    /// Viewers asking for control, oldest first
+    requests: Vec<u16>,
+    /// Accepted viewers don't get control until the user grants it
+    view_only_start: bool,
+}
+
+impl InputControl {
+    /// Record an accepted viewer; true if it controls input (nobody else did)
+    fn approve(&mut self, id: u16) -> bool {
+        if !self.approved.contains(&id) {
+            self.approved.push(id);
+        }
+        if self.view_only_start {
+            return self.controller == Some(id);
+        }
+        *self.controller.get_or_insert(id) == id
     }
 
-    async fn handle_control(&mut self, frame: &Frame) -> Result<()> {
-        self.handle_control_with_events::<tauri::Wry>(frame, None).await
+    /// Forget a viewer that left
+    /// Returns who control passed to, if it was the controller and an accepted viewer remains
+    fn remove(&mut self, id: u16) -> Option<u16> {
+        self.approved.retain(|&approved| approved != id);
+        self.requests.retain(|&requested| requested != id);
+        if self.controller != Some(id) {
+            return None;
+        }
+        self.controller = if self.view_only_start { None } else { self.approved.first().copied() };
+        self.controller
     }
 
-    async fn handle_control_with_events<R: tauri::Runtime>(&mut self, frame: &Frame, app_handle: Option<&tauri::AppHandle<R>>) -> Result<()> {
-        if frame.payload.is_empty() {
-            println!("[HOST] Control frame has empty payload");
-            return Ok(());
+    /// Hand control to an accepted viewer, returning the previous controller if it changed
+    fn grant(&mut self, id: u16) -> Result<Option<u16>> {
+        if !self.approved.contains(&id) {
+            anyhow::bail!("Viewer {} has not been accepted", id);
         }
+        self.requests.retain(|&requested| requested != id);
+        Ok(self.controller.replace(id).filter(|&previous| previous != id))
+    }
 
-        println!("[HOST] Control message type: 0x{:02x}", frame.payload[0]);
+    /// Take control away from a viewer and drop its pending request
+    /// True if it was the controller; nobody controls input until the next grant
+    fn revoke(&mut self, id: u16) -> bool {
+        self.requests.retain(|&requested| requested != id);
+        if self.controller != Some(id) {
+            return false;
+        }
+        self.controller = None;
+        true
+    }
+
+    /// Note that an accepted, view-only viewer asks for control
+    /// False if it can't ask (not accepted, in control already) or asked before
+    fn request(&mut self, id: u16) -> bool {
+        if !self.approved.contains(&id) || self.controller == Some(id) || self.requests.contains(&id) {
+            return false;
+        }
+        self.requests.push(id);
+        true
+    }
+
+    fn has_requested(&self, id: u16) -> bool {
+        self.requests.contains(&id)
+    }
+
+    fn is_approved(&self, id: u16) -> bool {
+        self.approved.contains(&id)
+    }
+
+    fn is_controller(&self, id: u16) -> bool {
+        self.controller == Some(id)
+    }
+}
+
+/// A connected viewer, as listed by `HostHandle::viewers`
+#[derive(Debug, Clone)]
+pub struct ViewerInfo {
+    pub viewer_id: u16,
+    pub remote_id: String,
+    /// Unix seconds
+    pub connected_at: u64,
+    /// The user accepted the connection request
+    pub accepted: bool,
+    /// This viewer's input is the one being injected
+    pub controlling: bool,
+    /// Waiting for the user to grant it control
+    pub requested_control: bool,
+}
+
+/// A viewer that sent its connection request
+struct ViewerEntry {
+    remote_id: String,
+    connected_at: u64,
+    link: ViewerLink,
+    capabilities: Arc<SyncMutex<Capabilities>>,
+}
+
+/// Viewers of a host session, shared with `HostHandle`
+#[derive(Default)]
+struct ViewerRegistry {
+    /// Oldest first
+    entries: Vec<ViewerEntry>,
+    control: InputControl,
+}
+
+impl ViewerRegistry {
+    fn add(&mut self, entry: ViewerEntry) {
+        self.entries.retain(|e| e.link.id != entry.link.id);
+        self.entries.push(entry);
+    }
+
+    /// Drop a viewer, returning who control passed to (see `InputControl::remove`)
+    fn remove(&mut self, id: u16) -> Option<u16> {
+        self.entries.retain(|e| e.link.id != id);
+        self.control.remove(id)
+    }
+
+    fn get(&self, id: u16) -> Option<&ViewerEntry> {
+        self.entries.iter().find(|e| e.link.id == id)
+    }
+
+    fn list(&self) -> Vec<ViewerInfo> {
+        self.entries.iter()
+            .map(|e| ViewerInfo {
+                viewer_id: e.link.id,
+                remote_id: e.remote_id.clone(),
+                connected_at: e.connected_at,
+                accepted: self.control.is_approved(e.link.id),
+                controlling: self.control.is_controller(e.link.id),
+                requested_control: self.control.has_requested(e.link.id),
+            })
+            .collect()
+    }
+}
+
+/// Tell a viewer whether its input is used, if it understands control::INPUT_CONTROL
+async fn send_input_control(registry: &SyncMutex<ViewerRegistry>, id: u16, granted: bool) {
+    let Some((link, capabilities)) = registry.lock().get(id)
+        .map(|e| (e.link.clone(), e.capabilities.clone()))
+    else {
+        return;
+    };
+    if !capabilities.lock().supports_feature(protocol::feature::INPUT_CONTROL) {
+        return;
+    }
+    let notice = Frame::control(protocol::control::INPUT_CONTROL, &[granted as u8]);
+    if let Err(e) = link.write_frame(notice).await {
+        eprintln!("[HOST] Failed to send input control to viewer {}: {}", id, e);
+    }
+}
+
+/// Tell the local UI who controls input now
+pub fn emit_control_change<R: tauri::Runtime>(app_handle: Option<&tauri::AppHandle<R>>, viewer_id: u16, granted: bool) {
+    if let Some(handle) = app_handle {
+        let event = if granted { "control-granted" } else { "control-revoked" };
+        let _ = handle.emit(event, serde_json::json!({ "viewer_id": viewer_id }));
+    }
+}
+
+/// Control messages a viewer may send before it is accepted: the secure
+/// channel, negotiation and its way in; viewers ask for sound and a direct
+/// link before then too, and get them only once accepted
+fn allowed_before_accept(kind: u8) -> bool {
+    use protocol::control;
+    matches!(
+        kind,
+        control::IDENTITY
+            | control::HANDSHAKE
+            | control::HELLO
+            | control::SESSION_REQUEST
+            | control::SESSION_END
+            | control::AUTH_PASSWORD
+            | control::KEEPALIVE
+            | control::PING
+            | control::FRAME_TIMING
+            | control::RESOLUTION
+            | control::AUDIO
+            | control::P2P_OFFER
+    )
+}
+
+/// Tell the UI how a file a viewer is sending is getting on
+fn emit_transfer_progress<R: tauri::Runtime>(app_handle: Option<&tauri::AppHandle<R>>, progress: &crate::transfer::Progress) {
+    if let Some(handle) = app_handle {
+        let _ = handle.emit("file-transfer-progress", serde_json::json!({
+            "session_id": null,
+            "direction": "incoming",
+            "transfer": progress,
+        }));
+    }
+}
+
+/// Write half of the relay transport
+/// Shared by every viewer and its video task so frames never interleave; on a
+/// multiplexed registration each frame is tagged with the viewer's stream
+struct RelayWriter {
+    stream: WriteHalf<RelayStream>,
+    multiplexed: bool,
+}
+
+impl RelayWriter {
+    /// `channel_byte` is `Frame::channel_byte`, so fragments keep their FRAME_MORE_FLAG
+    async fn write_raw(&mut self, stream_id: u16, channel_byte: u8, payload: &[u8]) -> Result<()> {
+        let prefix: &[u8] = if self.multiplexed { &stream_id.to_be_bytes() } else { &[] };
+        let len = prefix.len() + payload.len();
+        let header = [
+            channel_byte,
+            (len >> 16) as u8,
+            (len >> 8) as u8,
+            len as u8,
+        ];
+
+        self.stream.write_all(&header).await?;
+        self.stream.write_all(prefix).await?;
+        self.stream.write_all(payload).await?;
+        self.stream.flush().await?;
+        crate::metrics::record_bytes_sent(header.len() + len);
+        Ok(())
+    }
+
+    /// Ask the relay to drop a viewer's stream; no-op on a plain registration
+    async fn close_stream(&mut self, stream_id: u16) -> Result<()> {
+        if !self.multiplexed {
+            return Ok(());
+        }
+        let mut payload = vec![protocol::relay::MUX_CLOSE];
+        payload.extend_from_slice(&stream_id.to_be_bytes());
+        self.write_raw(protocol::relay::MUX_CONTROL_STREAM, Channel::Control as u8, &payload).await
+    }
+}
+
+/// One viewer's side of the relay transport: the shared writer and its own channel
+#[derive(Clone)]
+struct ViewerLink {
+    id: u16,
+    writer: Arc<AsyncMutex<RelayWriter>>,
+    channel: Arc<SyncMutex<Option<SecureChannel>>>,
+    /// Longest payload per frame once the viewer takes fragments (feature::FRAGMENTS); 0 sends messages whole
+    fragment_size: Arc<AtomicUsize>,
+    /// Direct link the viewer's frames go over instead, from our control::P2P_READY on
+    direct: Arc<AsyncMutex<Option<DirectWriter>>>,
+}
+
+impl ViewerLink {
+    /// Viewers only get past `Pending` by finishing the handshake
+    fn channel_state(&self) -> ChannelState {
+        if self.channel.lock().is_some() {
+            ChannelState::Encrypted
+        } else {
+            ChannelState::Pending
+        }
+    }
+
+    async fn write_frame(&self, frame: Frame) -> Result<()> {
+        self.channel_state().check(frame.channel)?;
+        // Held while the message goes out, so moving between links never splits one
+        let mut direct = self.direct.lock().await;
+        if let Some(link) = direct.as_mut() {
+            match link.write_frame(&frame).await {
+                Ok(()) => return Ok(()),
+                // The viewer notices the link closing and reads the relay again
+                Err(e) => {
+                    println!("[HOST] Direct link to viewer {} failed ({}), back on relay", self.id, e);
+                    *direct = None;
+                }
+            }
+        }
+        self.write_relay(frame).await
+    }
+
+    /// Send P2P_READY as the last frame on the relay, then move over to `writer`
+    async fn promote(&self, writer: DirectWriter) -> Result<()> {
+        let mut direct = self.direct.lock().await;
+        self.write_relay(Frame::control(protocol::control::P2P_READY, &[])).await?;
+        *direct = Some(writer);
+        Ok(())
+    }
+
+    async fn write_relay(&self, frame: Frame) -> Result<()> {
+        let fragment_size = match self.fragment_size.load(Ordering::Relaxed) {
+            0 => usize::MAX,
+            size if self.channel.lock().is_some() => size.min(SecureChannel::MAX_PLAINTEXT),
+            size => size,
+        };
+        // Encrypt while holding the writer so frames leave in nonce order, and
+        // a message's fragments follow each other on the stream
+        let mut writer = self.writer.lock().await;
+        for fragment in frame.into_fragments(fragment_size) {
+            let channel_byte = fragment.channel_byte();
+            let payload = match self.channel.lock().as_mut() {
+                Some(ch) => ch.encrypt(&fragment.payload)?,
+                None => fragment.payload,
+            };
+            writer.write_raw(self.id, channel_byte, &payload).await?;
+        }
+        Ok(())
+    }
+
+    /// Decrypt if the channel is established
+    fn decrypt(&self, payload: Vec<u8>) -> Result<Vec<u8>> {
+        match self.channel.lock().as_mut() {
+            Some(ch) => ch.decrypt(&payload),
+            None => Ok(payload),
+        }
+    }
+
+    /// Tell a connected viewer the session is over
+    /// Skipped before the handshake, when there is no peer on the other end
+    async fn end_session(&self) -> Result<()> {
+        if self.channel.lock().is_none() {
+            return Ok(());
+        }
+        self.write_frame(Frame::control(protocol::control::SESSION_END, &[])).await
+    }
+}
+
+/// Per-viewer session state; the capture, input injector and privacy mode are shared
+struct Viewer {
+    link: ViewerLink,
+    /// Capabilities negotiated with the viewer (legacy until it sends HELLO)
+    capabilities: Arc<SyncMutex<Capabilities>>,
+    /// Noise responder between the viewer's first and final handshake messages
+    handshake: Option<snow::HandshakeState>,
+    /// Static key the viewer proved in the handshake; a direct link must prove the same
+    client_key: Option<[u8; 32]>,
+    /// Device ID of a connection request that came before the handshake finished
+    request: Option<String>,
+    /// Device ID derived from the keys the viewer proved in the handshake
+    device_id: Option<String>,
+    /// Viewer messages arriving in fragments (feature::FRAGMENTS)
+    fragments: protocol::Reassembler,
+    video_tx: mpsc::Sender<u8>,
+    /// Signalled on accepted input, so the video task leaves its idle rate
+    input_activity: Arc<Notify>,
+    input_limiter: InputRateLimiter,
+    /// Keeps this viewer's keystrokes on one window (control::FOCUS_LOCK)
+    focus_lock: Option<input::FocusLock>,
+    /// The policy rule dropping this viewer's input, while one does
+    policy_block: Option<crate::policy::PolicyHit>,
+    /// Target resolution from the viewer (for adaptive scaling)
+    target_resolution: Option<(u16, u16)>,
+    /// Direct link, only ever held once its own Noise handshake has secured it;
+    /// put to use when the viewer sends control::P2P_READY
+    p2p_stream: Option<P2PTransport>,
+    connection_type: ConnectionType,
+    /// Counts the viewer in the local metrics, from handshake until it leaves
+    metrics: Option<crate::metrics::SessionGuard>,
+    /// Host sound for this viewer (control::AUDIO)
+    audio: ViewerAudio,
+    /// Takes the verdict on control::AUTH_PASSWORD while unattended access waits
+    /// for it, and the device ID the viewer asked to connect as
+    password_answer: Option<(mpsc::Sender<bool>, String)>,
+    /// When the viewer connected
+    started: Instant,
+    /// Last input frame from the viewer, or its acceptance; drives the session timeout
+    last_input: Instant,
+    /// Set by a handler to drop the viewer once its frame is handled
+    ended: bool,
+}
+
+/// Cloneable handle for acting on a host session from outside its loop
+/// (the loop holds the session itself while it waits for frames)
+#[derive(Clone)]
+pub struct HostHandle {
+    pending: Arc<SyncMutex<PendingConnections>>,
+    viewers: Arc<SyncMutex<ViewerRegistry>>,
+    input: SharedInputInjector,
+    multiplexed: bool,
+    shutdown: Arc<Notify>,
+    transfers: Arc<SyncMutex<crate::transfer::IncomingTransfers>>,
+}
+
+impl HostHandle {
+    /// Pending connection queue of the session
+    pub fn pending_connections(&self) -> Arc<SyncMutex<PendingConnections>> {
+        self.pending.clone()
+    }
+
+    /// Viewers that sent a connection request, oldest first
+    pub fn viewers(&self) -> Vec<ViewerInfo> {
+        self.viewers.lock().list()
+    }
+
+    /// Files being received, then the ones finished lately
+    pub fn incoming_transfers(&self) -> Vec<crate::transfer::Progress> {
+        self.transfers.lock().list()
+    }
+
+    /// Give input control to an accepted viewer; the previous controller, if any, becomes view-only
+    /// Returns the previous controller
+    pub async fn set_input_controller(&self, viewer_id: u16) -> Result<Option<u16>> {
+        let (already, previous) = {
+            let mut registry = self.viewers.lock();
+            let already = registry.control.is_controller(viewer_id);
+            (already, registry.control.grant(viewer_id)?)
+        };
+        if already {
+            return Ok(None);
+        }
+        match previous {
+            Some(previous) => {
+                println!("[HOST] Input control moved from viewer {} to {}", previous, viewer_id);
+                self.input.lock().reset();
+                send_input_control(&self.viewers, previous, false).await;
+            }
+            None => println!("[HOST] Input control granted to viewer {}", viewer_id),
+        }
+        send_input_control(&self.viewers, viewer_id, true).await;
+        Ok(previous)
+    }
+
+    /// Make an accepted viewer view-only, or turn down its control request
+    /// Returns true if it had control
+    pub async fn revoke_input_control(&self, viewer_id: u16) -> Result<bool> {
+        let was_controller = {
+            let mut registry = self.viewers.lock();
+            if !registry.control.is_approved(viewer_id) {
+                anyhow::bail!("Viewer {} has not been accepted", viewer_id);
+            }
+            registry.control.revoke(viewer_id)
+        };
+        if was_controller {
+            println!("[HOST] Input control revoked from viewer {}", viewer_id);
+            self.input.lock().reset();
+        }
+        // Also the answer to a declined request
+        send_input_control(&self.viewers, viewer_id, false).await;
+        Ok(was_controller)
+    }
+
+    /// Send a chat message to every connected client that supports chat
+    pub async fn send_chat(&self, text: &str) -> Result<()> {
+        let links: Vec<ViewerLink> = self.viewers.lock().entries.iter()
+            .filter(|e| e.capabilities.lock().supports_channel(Channel::Chat))
+            .filter(|e| e.link.channel_state().is_encrypted())
+            .map(|e| e.link.clone())
+            .collect();
+        if links.is_empty() {
+            anyhow::bail!("No connected client supports chat");
+        }
+        let frame = protocol::chat::message_frame(text)?;
+        for link in links {
+            link.write_frame(frame.clone()).await?;
+        }
+        Ok(())
+    }
+
+    /// End the session for app shutdown: send SESSION_END to every client and
+    /// wake the loop so it disables privacy mode and stops
+    pub async fn shutdown(&self) {
+        let links: Vec<ViewerLink> = self.viewers.lock().entries.iter().map(|e| e.link.clone()).collect();
+        for link in links {
+            if let Err(e) = link.end_session().await {
+                eprintln!("[HOST] Failed to send SESSION_END: {}", e);
+            }
+        }
+        self.shutdown.notify_one();
+    }
+}
+
+/// Host session - running on the PC being controlled
+///
+/// Frames are demultiplexed by channel: the reader loop handles control, input,
+/// privacy and clipboard inline, while video requests are queued to a separate
+/// task per viewer that captures and encodes off the reader's path. All of them
+/// write through the same `RelayWriter`, so a slow encode never delays input injection.
+///
+/// On a multiplexed registration the relay hands over several viewers on one
+/// connection. Each has its own secure channel and video task; all of them see
+/// the screen, but only the first one accepted controls it (`InputControl`).
+pub struct HostSession {
+    identity: Identity,
+    reader: Option<ReadHalf<RelayStream>>,
+    /// Keeps the relay stream's frame boundaries and size limit
+    frames: protocol::FrameReader,
+    /// Messages from the viewer on a direct link; only a plain registration,
+    /// with its one viewer, moves off the relay
+    direct: Option<(u16, DirectReader)>,
+    writer: Arc<AsyncMutex<RelayWriter>>,
+    /// Registered with CLIENT_ENDPOINT_MUX; otherwise the only viewer is `LEGACY_VIEWER`
+    multiplexed: bool,
+    viewers: HashMap<u16, Viewer>,
+    registry: Arc<SyncMutex<ViewerRegistry>>,
+    /// None until the screen can be captured (no display, permission missing)
+    capture: Arc<SyncMutex<Option<ScreenCapture>>>,
+    /// Shared with later sessions; reset rather than recreated on reconnect
+    input: SharedInputInjector,
+    privacy: PrivacyMode,
+    running: bool,
+    pending_connection: Arc<SyncMutex<PendingConnections>>,
+    /// User answers to connection requests, as (viewer, accepted)
+    answers_tx: mpsc::UnboundedSender<(u16, bool)>,
+    answers_rx: mpsc::UnboundedReceiver<(u16, bool)>,
+    p2p_enabled: bool,
+    /// Signalled by `HostHandle::shutdown`
+    shutdown: Arc<Notify>,
+    /// Files viewers are sending us
+    transfers: Arc<SyncMutex<crate::transfer::IncomingTransfers>>,
+    /// A viewer was accepted since the workstation was last locked (lock_on_disconnect)
+    lock_armed: bool,
+}
+
+impl HostSession {
+    /// Start hosting and connect to relay (with P2P enabled by default)
+    pub async fn start(relay_address: String, identity: Identity) -> Result<Self> {
+        Self::start_with_p2p(relay_address, identity, true).await
+    }
+
+    /// Start hosting and claim `alias` on the relay so clients can connect by name
+    /// Pass `None` for devices hidden from the address book
+    pub async fn start_with_alias(relay_address: String, identity: Identity, alias: Option<String>) -> Result<Self> {
+        Self::start_with_options(relay_address, identity, true, alias).await
+    }
+
+    /// Start hosting with explicit P2P control
+    pub async fn start_with_p2p(relay_address: String, identity: Identity, p2p_enabled: bool) -> Result<Self> {
+        Self::start_with_options(relay_address, identity, p2p_enabled, None).await
+    }
+
+    async fn start_with_options(
+        relay_address: String,
+        identity: Identity,
+        p2p_enabled: bool,
+        alias: Option<String>,
+    ) -> Result<Self> {
+        println!("[HOST] Starting host session, connecting to relay: {}", relay_address);
+        println!("[HOST] P2P enabled: {}", p2p_enabled);
+
+        let id = identity.device_id_raw();
+        let alias = match alias.as_deref().map(protocol::relay::normalize_alias) {
+            Some(Ok(alias)) => Some(alias),
+            Some(Err(e)) => {
+                println!("[HOST] Not registering alias: {}", e);
+                None
+            }
+            None => None,
+        };
+
+        // Serve several viewers if the relay can multiplex us, otherwise register
+        // as a plain endpoint with our ID (and alias, if the relay supports it)
+        let (stream, multiplexed) = match Self::register_mux(&relay_address, &id, alias.as_deref()).await {
+            Ok(stream) => (stream, true),
+            Err(e) => {
+                println!("[HOST] Multi-viewer registration unavailable ({}), serving one viewer", e);
+                let stream = match alias {
+                    Some(alias) => match Self::register_with_alias(&relay_address, &id, &alias).await {
+                        Ok(stream) => stream,
+                        Err(e) => {
+                            println!("[HOST] Alias registration unavailable ({}), registering by ID only", e);
+                            Self::register(&relay_address, &id).await?
+                        }
+                    },
+                    None => Self::register(&relay_address, &id).await?,
+                };
+                (stream, false)
+            }
+        };
+        println!("[HOST] Registration sent, host session initialized");
+
+        // Initialize capture/input; the video task keeps retrying a failed capture
+        let capture = match ScreenCapture::new() {
+            Ok(capture) => Some(capture),
+            Err(e) => {
+                println!("[HOST] Screen capture unavailable: {}", e);
+                None
+            }
+        };
+        let capture = Arc::new(SyncMutex::new(capture));
+        let input = input::shared_injector()?;
+        input.lock().reset();
+        let privacy = PrivacyMode::new();
+
+        // Split the transport so video can be written while the reader waits
+        let (reader, write_half) = tokio::io::split(stream);
+        let writer = Arc::new(AsyncMutex::new(RelayWriter {
+            stream: write_half,
+            multiplexed,
+        }));
+        let (answers_tx, answers_rx) = mpsc::unbounded_channel();
+
+        let mut session = Self {
+            identity,
+            reader: Some(reader),
+            frames: Self::frame_reader(),
+            direct: None,
+            writer,
+            multiplexed,
+            viewers: HashMap::new(),
+            registry: Arc::new(SyncMutex::new(ViewerRegistry::default())),
+            capture,
+            input,
+            privacy,
+            running: true,
+            pending_connection: Arc::new(SyncMutex::new(PendingConnections::default())),
+            answers_tx,
+            answers_rx,
+            p2p_enabled,
+            lock_armed: false,
+            shutdown: Arc::new(Notify::new()),
+            transfers: Arc::new(SyncMutex::new(crate::transfer::IncomingTransfers::default())),
+        };
+        // Multiplexed viewers arrive with the relay's MUX_OPEN
+        if !multiplexed {
+            session.add_viewer(LEGACY_VIEWER);
+        }
+        Ok(session)
+    }
+
+    /// Reader for the relay connection
+    /// Viewers send nothing longer than a fragment: data frames wait for the
+    /// secure channel, which seals at most a Noise message per frame
+    fn frame_reader() -> protocol::FrameReader {
+        let mut frames = protocol::FrameReader::new();
+        frames.set_max_frame_size(protocol::MAX_FRAGMENT_FRAME);
+        frames
+    }
+
+    /// Set up state and a video task for a new viewer
+    fn add_viewer(&mut self, id: u16) {
+        let link = ViewerLink {
+            id,
+            writer: self.writer.clone(),
+            channel: Arc::new(SyncMutex::new(None)),
+            fragment_size: Arc::new(AtomicUsize::new(0)),
+            direct: Arc::new(AsyncMutex::new(None)),
+        };
+        let capabilities = Arc::new(SyncMutex::new(Capabilities::legacy()));
+        let (video_tx, video_rx) = mpsc::channel(VIDEO_QUEUE_DEPTH);
+        let input_activity = Arc::new(Notify::new());
+        tokio::spawn(Self::video_task(
+            self.capture.clone(),
+            link.clone(),
+            self.registry.clone(),
+            video_rx,
+            capabilities.clone(),
+            input_activity.clone(),
+        ));
+
+        self.viewers.insert(id, Viewer {
+            link,
+            capabilities,
+            handshake: None,
+            client_key: None,
+            request: None,
+            device_id: None,
+            fragments: protocol::Reassembler::default(),
+            video_tx,
+            input_activity,
+            input_limiter: InputRateLimiter::new(),
+            focus_lock: None,
+            policy_block: None,
+            target_resolution: None,
+            p2p_stream: None,
+            connection_type: ConnectionType::Relay,
+            metrics: None,
+            audio: ViewerAudio::default(),
+            password_answer: None,
+            started: Instant::now(),
+            last_input: Instant::now(),
+            ended: false,
+        });
+    }
+
+    /// Open a TLS connection to a relay ("host:port")
+    async fn open_relay_stream(relay_address: &str) -> Result<RelayStream> {
+        // Parse address
+        let (host, port) = relay_address
+            .rsplit_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Invalid relay address"))?;
+        let port: u16 = port.parse()?;
+        println!("[HOST] Parsed address: host={}, port={}", host, port);
+
+        // TLS setup: webpki roots plus any configured relay CA
+        let connector = crate::tls::connector();
+
+        // Connect to relay
+        let tcp = crate::proxy::connect(host, port).await?;
+        let server_name = tokio_rustls::rustls::pki_types::ServerName::try_from(host.to_owned())?;
+        Ok(connector.connect(server_name, tcp).await?)
+    }
+
+    /// Register with the relay by device ID only
+    async fn register(relay_address: &str, id: &str) -> Result<RelayStream> {
+        let mut stream = Self::open_relay_stream(relay_address).await?;
+
+        println!("[HOST] Registering as endpoint with ID: {}", id);
+        stream.write_u8(protocol::relay::CLIENT_ENDPOINT).await?;
+        // Use big-endian for protocol compatibility with Go server
+        stream.write_all(&(id.len() as u16).to_be_bytes()).await?;
+        stream.write_all(id.as_bytes()).await?;
+        stream.flush().await?;
+        Ok(stream)
+    }
+
+    /// Register with the relay by device ID and claim an alias
+    /// Errors if the relay doesn't understand alias registration (older relays
+    /// close the connection); an alias held by another device only logs a warning,
+    /// since the endpoint is still registered by ID
+    async fn register_with_alias(relay_address: &str, id: &str, alias: &str) -> Result<RelayStream> {
+        let mut stream = Self::open_relay_stream(relay_address).await?;
+
+        println!("[HOST] Registering as endpoint with ID: {} and alias: {}", id, alias);
+        stream.write_u8(protocol::relay::CLIENT_ENDPOINT_ALIAS).await?;
+        stream.write_all(&(id.len() as u16).to_be_bytes()).await?;
+        stream.write_all(id.as_bytes()).await?;
+        stream.write_all(&(alias.len() as u16).to_be_bytes()).await?;
+        stream.write_all(alias.as_bytes()).await?;
+        stream.flush().await?;
+
+        // The relay answers before registering the endpoint, so this is the first frame
+        let payload = Self::read_relay_reply(&mut stream).await?;
+        match payload.split_first() {
+            Some((&protocol::relay::REPLY_ALIAS_REGISTERED, _)) => {
+                println!("[HOST] Alias registered");
+            }
+            Some((&protocol::relay::REPLY_ERROR, message)) => {
+                println!("[HOST] Relay rejected alias: {}", String::from_utf8_lossy(message));
+            }
+            _ => anyhow::bail!("unexpected relay reply"),
+        }
+        Ok(stream)
+    }
+
+    /// Register with the relay as an endpoint that serves several viewers at
+    /// once, claiming `alias` if given
+    /// Errors if the relay doesn't understand endpoint multiplexing (older relays
+    /// close the connection); a rejected alias only logs a warning, as above
+    async fn register_mux(relay_address: &str, id: &str, alias: Option<&str>) -> Result<RelayStream> {
+        let mut stream = Self::open_relay_stream(relay_address).await?;
+
+        println!("[HOST] Registering as multi-viewer endpoint with ID: {}", id);
+        let alias = alias.unwrap_or_default();
+        stream.write_u8(protocol::relay::CLIENT_ENDPOINT_MUX).await?;
+        stream.write_all(&(id.len() as u16).to_be_bytes()).await?;
+        stream.write_all(id.as_bytes()).await?;
+        stream.write_all(&(alias.len() as u16).to_be_bytes()).await?;
+        stream.write_all(alias.as_bytes()).await?;
+        stream.flush().await?;
+
+        // The alias reply, if an alias was sent, comes before MUX_READY
+        loop {
+            let payload = Self::read_relay_reply(&mut stream).await?;
+            match payload.split_first() {
+                Some((&protocol::relay::REPLY_MUX_READY, _)) => return Ok(stream),
+                Some((&protocol::relay::REPLY_ALIAS_REGISTERED, _)) if !alias.is_empty() => {
+                    println!("[HOST] Alias registered");
+                }
+                Some((&protocol::relay::REPLY_ERROR, message)) if !alias.is_empty() => {
+                    println!("[HOST] Relay rejected alias: {}", String::from_utf8_lossy(message));
+                }
+                _ => anyhow::bail!("unexpected relay reply"),
+            }
+        }
+    }
+
+    /// Read one unencrypted reply frame sent during registration
+    async fn read_relay_reply(stream: &mut RelayStream) -> Result<Vec<u8>> {
+        let mut header = [0u8; 4];
+        tokio::time::timeout(REGISTRATION_REPLY_TIMEOUT, stream.read_exact(&mut header))
+            .await
+            .map_err(|_| anyhow::anyhow!("no reply from relay"))??;
+        let len = ((header[1] as usize) << 16) | ((header[2] as usize) << 8) | (header[3] as usize);
+        let mut payload = vec![0u8; len];
+        stream.read_exact(&mut payload).await?;
+        Ok(payload)
+    }
+
+    /// Get the current connection type (P2P if any viewer is connected directly)
+    pub fn connection_type(&self) -> ConnectionType {
+        if self.viewers.values().any(|v| v.connection_type == ConnectionType::P2P) {
+            ConnectionType::P2P
+        } else {
+            ConnectionType::Relay
+        }
+    }
+
+    /// Set P2P enabled state
+    pub fn set_p2p_enabled(&mut self, enabled: bool) {
+        self.p2p_enabled = enabled;
+    }
+
+    /// Get a handle usable while the session loop is running
+    pub fn handle(&self) -> HostHandle {
+        HostHandle {
+            pending: self.pending_connection.clone(),
+            viewers: self.registry.clone(),
+            input: self.input.clone(),
+            multiplexed: self.multiplexed,
+            shutdown: self.shutdown.clone(),
+            transfers: self.transfers.clone(),
+        }
+    }
+
+    /// Main loop - handle incoming requests
+    pub async fn run(&mut self) -> Result<()> {
+        while self.running {
+            self.run_once().await?;
+        }
+        Ok(())
+    }
+
+    /// Run one iteration - process a single frame
+    pub async fn run_once(&mut self) -> Result<()> {
+        self.run_once_internal(None::<&tauri::AppHandle>).await
+    }
+
+    /// Run one iteration with event emission support
+    pub async fn run_once_with_events(&mut self, app_handle: &tauri::AppHandle) -> Result<()> {
+        self.run_once_internal(Some(app_handle)).await
+    }
+
+    async fn run_once_internal<R: tauri::Runtime>(&mut self, app_handle: Option<&tauri::AppHandle<R>>) -> Result<()> {
+        if !self.running {
+            anyhow::bail!("Session stopped");
+        }
+
+        println!("[HOST] Waiting for frame...");
+        let shutdown = self.shutdown.clone();
+        let idle_deadline = self.idle_deadline();
+        let reader = self.reader.as_mut().ok_or_else(|| anyhow::anyhow!("Not connected"))?;
+        let (viewer_id, frame) = tokio::select! {
+            raw = Self::read_raw(reader, &mut self.frames, self.multiplexed) => raw?,
+            (viewer_id, message) = Self::read_direct(&mut self.direct) => {
+                return self.handle_direct(viewer_id, message, app_handle).await;
+            }
+            Some((viewer_id, accepted)) = self.answers_rx.recv() => {
+                return self.apply_answer(viewer_id, accepted, app_handle).await;
+            }
+            _ = async {
+                match idle_deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+                    None => std::future::pending().await,
+                }
+            } => {
+                return self.expire_idle_viewers(app_handle).await;
+            }
+            _ = shutdown.notified() => {
+                self.running = false;
+                self.privacy.disable_all()?;
+                anyhow::bail!("Session stopped");
+            }
+        };
+
+        if self.multiplexed && viewer_id == protocol::relay::MUX_CONTROL_STREAM {
+            return self.handle_mux_control(&frame.payload, app_handle).await;
+        }
+
+        // Taken out while its frame is handled, so handlers can borrow it alongside the session
+        let Some(mut viewer) = self.viewers.remove(&viewer_id) else {
+            return Ok(()); // Late frame from a viewer that already left
+        };
+        let message = viewer.link.decrypt(frame.payload)
+            .and_then(|payload| viewer.fragments.push(Frame { payload, ..frame }));
+        self.dispatch(viewer, message, app_handle).await
+    }
+
+    /// Next message from a viewer on its direct link; pending while there is none
+    async fn read_direct(direct: &mut Option<(u16, DirectReader)>) -> (u16, Result<Frame>) {
+        match direct {
+            Some((viewer_id, reader)) => (*viewer_id, reader.read_frame().await),
+            None => std::future::pending().await,
+        }
+    }
+
+    async fn handle_direct<R: tauri::Runtime>(
+        &mut self,
+        viewer_id: u16,
+        message: Result<Frame>,
+        app_handle: Option<&tauri::AppHandle<R>>,
+    ) -> Result<()> {
+        let Some(mut viewer) = self.viewers.remove(&viewer_id) else {
+            self.direct = None;
+            return Ok(());
+        };
+        match message {
+            Ok(message) => self.dispatch(viewer, Ok(Some(message)), app_handle).await,
+            Err(e) => {
+                println!("[HOST] Direct link to viewer {} closed ({}), back on relay", viewer_id, e);
+                self.leave_direct_link(&mut viewer, app_handle).await;
+                self.viewers.insert(viewer_id, viewer);
+                Ok(())
+            }
+        }
+    }
+
+    /// Handle a viewer's next message, if `message` completed one, and put the viewer back
+    async fn dispatch<R: tauri::Runtime>(
+        &mut self,
+        mut viewer: Viewer,
+        message: Result<Option<Frame>>,
+        app_handle: Option<&tauri::AppHandle<R>>,
+    ) -> Result<()> {
+        let viewer_id = viewer.link.id;
+        let result = match message {
+            Ok(Some(message)) => self.handle_frame(&mut viewer, message, app_handle).await,
+            Ok(None) => Ok(()),
+            Err(e) => Err(e),
+        };
+        let ended = viewer.ended;
+        self.viewers.insert(viewer_id, viewer);
+
+        match result {
+            // One misbehaving viewer must not take the others down with the relay connection
+            Err(e) if self.multiplexed => {
+                eprintln!("[HOST] Dropping viewer {}: {}", viewer_id, e);
+                self.remove_viewer(viewer_id, false, app_handle).await
+            }
+            Err(e) => Err(e),
+            Ok(()) if ended => self.remove_viewer(viewer_id, false, app_handle).await,
+            Ok(()) => Ok(()),
+        }
+    }
+
+    async fn handle_frame<R: tauri::Runtime>(
+        &mut self,
+        viewer: &mut Viewer,
+        frame: Frame,
+        app_handle: Option<&tauri::AppHandle<R>>,
+    ) -> Result<()> {
+        println!("[HOST] Received frame on channel {:?}, payload len: {}", frame.channel, frame.payload.len());
+        if !frame.payload.is_empty() {
+            println!("[HOST] First payload byte: 0x{:02x}", frame.payload[0]);
+        }
+
+        // Nothing but control messages until the viewer finished the handshake
+        if let Err(e) = viewer.link.channel_state().check(frame.channel) {
+            println!("[HOST] Viewer {}: {}", viewer.link.id, e);
+            return Ok(());
+        }
+
+        // Nor anything of the session until it is accepted: video requests are
+        // answered without a frame, so the viewer's requests and replies still pair up
+        if !self.is_accepted(viewer) {
+            match frame.channel {
+                Channel::Video => {
+                    return viewer.link.write_frame(Frame::video(vec![protocol::video::FRAME_UNCHANGED])).await;
+                }
+                Channel::Control if frame.payload.first().is_some_and(|&kind| allowed_before_accept(kind)) => {}
+                _ => {
+                    println!("[HOST] Ignoring {:?} from viewer {}: not accepted", frame.channel, viewer.link.id);
+                    return Ok(());
+                }
+            }
+        }
+
+        match frame.channel {
+            Channel::Control => {
+                println!("[HOST] Handling control message");
+                self.handle_control_with_events(viewer, &frame, app_handle).await?;
+            }
+            Channel::Input => {
+                viewer.last_input = Instant::now();
+                if self.controls_input(viewer) {
+                    println!("[HOST] Handling input");
+                    let was_blocked = viewer.policy_block.is_some();
+                    self.handle_input(viewer, &frame).await?;
+                    if let (false, Some(hit), Some(handle)) = (was_blocked, &viewer.policy_block, app_handle) {
+                        let _ = handle.emit("control-blocked-by-policy", serde_json::json!({
+                            "viewer_id": viewer.link.id,
+                            "pattern": hit.rule.pattern,
+                            "match_on": hit.rule.match_on,
+                            "title": hit.window.title,
+                            "process": hit.window.process,
+                        }));
+                    }
+                }
+            }
+            Channel::Privacy => {
+                println!("[HOST] Handling privacy");
+                self.handle_privacy(viewer, &frame).await?;
+            }
+            Channel::Video => {
+                // Hand off to the video task so encoding doesn't block input
+                let kind = frame.payload.first().copied().unwrap_or(protocol::video::FRAME_REQUEST);
+                if viewer.video_tx.send(kind).await.is_err() {
+                    anyhow::bail!("Video task stopped");
+                }
+            }
+            Channel::Clipboard => {
+                // The host clipboard is for the controller only
+                if self.controls_input(viewer) {
+                    println!("[HOST] Handling clipboard");
+                    self.handle_clipboard_with_events(viewer, &frame, app_handle).await?;
+                }
+            }
+            Channel::File => {
+                self.handle_file(viewer, &frame, app_handle).await?;
+            }
+            Channel::Chat => {
+                if frame.payload.first() == Some(&protocol::chat::MESSAGE) {
+                    let text = String::from_utf8_lossy(&frame.payload[1..]).to_string();
+                    println!("[HOST] Chat message from viewer {} ({} bytes)", viewer.link.id, text.len());
+                    if let Some(handle) = app_handle {
+                        let _ = handle.emit("chat-message", serde_json::json!({
+                            "session_id": null,
+                            "from": "remote",
+                            "text": text,
+                        }));
+                    }
+                }
+            }
+            // Only the host sends sound
+            Channel::Audio => {}
+        }
+        Ok(())
+    }
+
+    /// Read one frame off the relay: (viewer, frame as received)
+    /// Only multiplexed registrations carry a stream ID; otherwise it is `LEGACY_VIEWER`
+    async fn read_raw(
+        reader: &mut ReadHalf<RelayStream>,
+        frames: &mut protocol::FrameReader,
+        multiplexed: bool,
+    ) -> Result<(u16, Frame)> {
+        let mut frame = frames.read(reader).await?;
+        crate::metrics::record_bytes_received(4 + frame.payload.len());
+
+        if !multiplexed {
+            return Ok((LEGACY_VIEWER, frame));
+        }
+        if frame.payload.len() < 2 {
+            anyhow::bail!("Frame without stream ID");
+        }
+        let stream_id = u16::from_be_bytes([frame.payload[0], frame.payload[1]]);
+        frame.payload.drain(..2);
+        Ok((stream_id, frame))
+    }
+
+    /// Read the next frame and decrypt it for `link`
+    async fn read_frame(&mut self, link: &ViewerLink) -> Result<Frame> {
+        let reader = self.reader.as_mut().ok_or_else(|| anyhow::anyhow!("Not connected"))?;
+        let (_, frame) = Self::read_raw(reader, &mut self.frames, self.multiplexed).await?;
+        Ok(Frame::new(frame.channel, link.decrypt(frame.payload)?))
+    }
+
+    /// Whether a viewer's input, privacy and clipboard requests are honoured
+    /// A plain registration has one viewer and it always is in control
+    fn controls_input(&self, viewer: &Viewer) -> bool {
+        self.registry.lock().control.is_controller(viewer.link.id)
+    }
+
+    /// Whether the user (or the access password) let the viewer in
+    fn is_accepted(&self, viewer: &Viewer) -> bool {
+        self.registry.lock().control.is_approved(viewer.link.id)
+    }
+
+    /// Viewers joining and leaving a multiplexed registration
+    async fn handle_mux_control<R: tauri::Runtime>(
+        &mut self,
+        payload: &[u8],
+        app_handle: Option<&tauri::AppHandle<R>>,
+    ) -> Result<()> {
+        let (Some(&op), Some(&[high, low])) = (payload.first(), payload.get(1..3)) else {
+            return Ok(());
+        };
+        let viewer_id = u16::from_be_bytes([high, low]);
+
+        match op {
+            protocol::relay::MUX_OPEN => {
+                self.remove_viewer(viewer_id, true, app_handle).await?;
+                if self.viewers.len() >= MAX_VIEWERS {
+                    println!("[HOST] Already serving {} viewers - refusing another", MAX_VIEWERS);
+                    let mut writer = self.writer.lock().await;
+                    writer.write_raw(viewer_id, Channel::Control as u8, &[
+                        protocol::control::SESSION_END,
+                        protocol::control::END_REASON_BUSY,
+                    ]).await?;
+                    return writer.close_stream(viewer_id).await;
+                }
+
+                println!("[HOST] Viewer {} joined", viewer_id);
+                self.add_viewer(viewer_id);
+
+                // The relay's open stands in for the SESSION_REQUEST of a plain registration
+                let mut request = vec![protocol::control::SESSION_REQUEST];
+                request.extend_from_slice(&payload[3..]);
+                let Some(mut viewer) = self.viewers.remove(&viewer_id) else {
+                    return Ok(());
+                };
+                let result = self.handle_control_with_events(&mut viewer, &Frame::new(Channel::Control, request), app_handle).await;
+                let ended = viewer.ended;
+                self.viewers.insert(viewer_id, viewer);
+                if ended || result.is_err() {
+                    self.remove_viewer(viewer_id, false, app_handle).await?;
+                }
+                result
+            }
+            protocol::relay::MUX_CLOSE => self.remove_viewer(viewer_id, true, app_handle).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Act on the user's answer to a viewer's connection request: the first
+    /// viewer accepted controls input, a declined one is dropped from a
+    /// multiplexed registration
+    async fn apply_answer<R: tauri::Runtime>(
+        &mut self,
+        viewer_id: u16,
+        accepted: bool,
+        app_handle: Option<&tauri::AppHandle<R>>,
+    ) -> Result<()> {
+        let Some(viewer) = self.viewers.get_mut(&viewer_id) else {
+            return Ok(()); // Left while the request was waiting
+        };
+        if !accepted {
+            if self.multiplexed {
+                self.remove_viewer(viewer_id, false, app_handle).await?;
+            }
+            return Ok(());
+        }
+        // Time spent waiting for an answer doesn't count as idle
+        viewer.last_input = Instant::now();
+        self.lock_armed = true;
+
+        let granted = {
+            let mut registry = self.registry.lock();
+            registry.control.view_only_start = start_view_only();
+            registry.control.approve(viewer_id)
+        };
+        // A plain registration's only viewer needs telling when it starts view-only
+        if self.multiplexed || !granted {
+            println!("[HOST] Viewer {} accepted, {}", viewer_id, if granted { "in control" } else { "view only" });
+            send_input_control(&self.registry, viewer_id, granted).await;
+        }
+        if granted {
+            emit_control_change(app_handle, viewer_id, true);
+        }
+
+        // Sound asked for while the request waited starts now
+        if let Some(viewer) = self.viewers.get_mut(&viewer_id).filter(|v| v.audio.requested) {
+            let capabilities = *viewer.capabilities.lock();
+            if let Err(e) = viewer.audio.set_enabled(true, &capabilities, &viewer.link) {
+                eprintln!("[HOST] Cannot send audio to viewer {}: {}", viewer_id, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// When the next accepted viewer runs out of idle time, if the session timeout is on
+    fn idle_deadline(&self) -> Option<Instant> {
+        let registry = self.registry.lock();
+        earliest_idle_deadline(
+            self.viewers
+                .iter()
+                .filter(|(id, _)| registry.control.is_approved(**id))
+                .map(|(_, viewer)| viewer.last_input),
+            session_timeout(),
+        )
+    }
+
+    /// End the sessions of accepted viewers that sent no input for the session timeout
+    async fn expire_idle_viewers<R: tauri::Runtime>(&mut self, app_handle: Option<&tauri::AppHandle<R>>) -> Result<()> {
+        let Some(timeout) = session_timeout() else {
+            return Ok(());
+        };
+        let expired: Vec<(u16, Duration, Duration)> = {
+            let registry = self.registry.lock();
+            self.viewers
+                .iter()
+                .filter(|(id, viewer)| registry.control.is_approved(**id) && viewer.last_input.elapsed() >= timeout)
+                .map(|(id, viewer)| (*id, viewer.last_input.elapsed(), viewer.started.elapsed()))
+                .collect()
+        };
+
+        for (viewer_id, idle, duration) in expired {
+            println!("[HOST] Viewer {} idle for {}s - ending session", viewer_id, idle.as_secs());
+            let remote_id = self.registry.lock().get(viewer_id).map(|e| e.remote_id.clone()).unwrap_or_default();
+            if let Some(viewer) = self.viewers.get(&viewer_id) {
+                let end = Frame::control(protocol::control::SESSION_END, &[protocol::control::END_REASON_TIMEOUT]);
+                if let Err(e) = viewer.link.write_frame(end).await {
+                    eprintln!("[HOST] Failed to tell viewer {} about the timeout: {}", viewer_id, e);
+                }
+            }
+            if let Some(handle) = app_handle {
+                let _ = handle.emit("session-timed-out", serde_json::json!({
+                    "viewer_id": viewer_id,
+                    "remote_id": remote_id,
+                    "idle_secs": idle.as_secs(),
+                    "session_secs": duration.as_secs(),
+                }));
+            }
+            self.remove_viewer(viewer_id, false, app_handle).await?;
+        }
+        Ok(())
+    }
+
+    /// Move a viewer's frames back to the relay, if they went over a direct link
+    async fn leave_direct_link<R: tauri::Runtime>(&mut self, viewer: &mut Viewer, app_handle: Option<&tauri::AppHandle<R>>) {
+        if self.direct.as_ref().is_some_and(|(id, _)| *id == viewer.link.id) {
+            self.direct = None;
+        }
+        viewer.p2p_stream = None;
+        viewer.link.direct.lock().await.take();
+        if viewer.connection_type == ConnectionType::P2P {
+            viewer.connection_type = ConnectionType::Relay;
+            if let Some(handle) = app_handle {
+                let _ = handle.emit("connection-type-changed", serde_json::json!({
+                    "type": self.connection_type().to_string()
+                }));
+            }
+        }
+    }
+
+    /// Forget a viewer that left or was dropped; `closed_by_relay` when the
+    /// relay already closed its stream. The session of a plain registration
+    /// ends with its viewer, and privacy mode goes off with the last one.
+    async fn remove_viewer<R: tauri::Runtime>(
+        &mut self,
+        viewer_id: u16,
+        closed_by_relay: bool,
+        app_handle: Option<&tauri::AppHandle<R>>,
+    ) -> Result<()> {
+        let Some(viewer) = self.viewers.remove(&viewer_id) else {
+            return Ok(());
+        };
+        if self.direct.as_ref().is_some_and(|(id, _)| *id == viewer_id) {
+            self.direct = None;
+        }
+        if !closed_by_relay {
+            self.writer.lock().await.close_stream(viewer_id).await?;
+        }
+        // Stops its video task and metrics count
+        drop(viewer);
+        for progress in self.transfers.lock().viewer_left(viewer_id) {
+            emit_transfer_progress(app_handle, &progress);
+        }
+
+        let (was_controller, next) = {
+            let mut registry = self.registry.lock();
+            (registry.control.is_controller(viewer_id), registry.remove(viewer_id))
+        };
+        if !self.multiplexed {
+            self.running = false;
+        }
+        if was_controller || !self.multiplexed {
+            self.input.lock().reset();
+        }
+        if let Some(next) = next {
+            println!("[HOST] Input control passed to viewer {}", next);
+            send_input_control(&self.registry, next, true).await;
+            emit_control_change(app_handle, next, true);
+        }
+        if self.viewers.is_empty() {
+            self.privacy.disable_all()?;
+        }
+        let accepted_left = {
+            let registry = self.registry.lock();
+            self.viewers.keys().any(|id| registry.control.is_approved(*id))
+        };
+        if !accepted_left {
+            self.lock_if_armed();
+        }
+        println!("[HOST] Viewer {} left, {} still connected", viewer_id, self.viewers.len());
+
+        if let Some(handle) = app_handle {
+            let _ = handle.emit("connection-ended", serde_json::json!({
+                "viewer_id": viewer_id,
+                "remaining": self.viewers.len(),
+            }));
+        }
+        Ok(())
+    }
+
+    async fn handle_control(&mut self, viewer: &mut Viewer, frame: &Frame) -> Result<()> {
+        self.handle_control_with_events::<tauri::Wry>(viewer, frame, None).await
+    }
+
+    async fn handle_control_with_events<R: tauri::Runtime>(
+        &mut self,
+        viewer: &mut Viewer,
+        frame: &Frame,
+        app_handle: Option<&tauri::AppHandle<R>>,
+    ) -> Result<()> {
+        if frame.payload.is_empty() {
+            println!("[HOST] Control frame has empty payload");
+            return Ok(());
+        }
+
+        println!("[HOST] Control message type: 0x{:02x}", frame.payload[0]);
+
+        // These change the host for everyone watching, so only the controller may send them
+        let view_only = !self.controls_input(viewer);
+
+        match frame.payload[0] {
+            protocol::control::QUALITY
+            | protocol::control::LOSSLESS
+            | protocol::control::ELEVATION_REQUEST if view_only => {
+                println!("[HOST] Ignoring 0x{:02x} from view-only viewer {}", frame.payload[0], viewer.link.id);
+            }
+            protocol::control::IDENTITY => {
+                // Public keys only - the handshake proves we hold the private half
+                let mut keys = vec![protocol::control::IDENTITY];
+                keys.extend_from_slice(self.identity.public_key());
+                keys.extend_from_slice(&self.identity.verifying_key());
+                viewer.link.write_frame(Frame::new(Channel::Control, keys)).await?;
+            }
+            protocol::control::HANDSHAKE => {
+                println!("[HOST] Received HANDSHAKE");
+                // Noise_XK from client: -> e, es  <- e, ee  -> s, se
+                let mut buf = vec![0u8; 65535];
+                match viewer.handshake.take() {
+                    None => {
+                        let mut responder = self.identity.create_responder()?;
+                        responder.read_message(&frame.payload[1..], &mut buf)?;
+
+                        let len = responder.write_message(&[], &mut buf)?;
+                        let mut response = vec![protocol::control::HANDSHAKE];
+                        response.extend_from_slice(&buf[..len]);
+                        viewer.link.write_frame(Frame::new(Channel::Control, response)).await?;
+                        viewer.handshake = Some(responder);
+                    }
+                    Some(mut responder) => {
+                        let len = responder.read_message(&frame.payload[1..], &mut buf)?;
+                        if responder.is_handshake_finished() {
+                            viewer.client_key = responder.get_remote_static().and_then(|key| key.try_into().ok());
+                            viewer.device_id = viewer.client_key
+                                .and_then(|key| crate::crypto::proven_device_id(&key, &buf[..len]));
+                            *viewer.link.channel.lock() = Some(SecureChannel::from_handshake(responder)?);
+                            viewer.metrics = Some(crate::metrics::SessionGuard::new(crate::metrics::SessionRole::Host));
+                            println!("[HOST] Secure channel established");
+                            if let Some(remote_id) = viewer.request.take() {
+                                self.handle_session_request(viewer, remote_id, app_handle).await?;
+                            }
+                        }
+                    }
+                }
+            }
+            protocol::control::HELLO => {
+                let peer = Capabilities::decode(&frame.payload[1..])?;
+                let negotiated = Capabilities::local().negotiate(&peer);
+                println!("[HOST] Client HELLO: version {}, negotiated {:?}", peer.version, negotiated);
+                viewer.link.fragment_size.store(negotiated.fragment_size().unwrap_or(0), Ordering::Relaxed);
+                *viewer.capabilities.lock() = negotiated;
+                // A new client starts lossy at the configured quality, whatever the
+                // previous one chose; one joining others already watching leaves
+                // their choice alone
+                if self.viewers.is_empty() {
+                    crate::capture::set_lossless(false);
+                    crate::capture::reset_quality();
+                }
+                let mut hello = Capabilities::local();
+                if unattended_password().is_none() {
+                    hello.features &= !protocol::feature::PASSWORD_REQUIRED;
+                }
+                viewer.link.write_frame(hello.hello_frame()).await?;
+            }
+            protocol::control::SESSION_REQUEST => {
+                // Extract remote ID from payload (bytes after the message type)
+                let remote_id = if frame.payload.len() > 1 {
+                    String::from_utf8_lossy(&frame.payload[1..]).to_string()
+                } else {
+                    "Unknown".to_string()
+                };
+
+                println!("[HOST] Received SESSION_REQUEST from: {}", remote_id);
+
+                // Trust belongs to the key the handshake proves, so nothing is
+                // decided on the claimed ID alone; the relay speaks for the viewer
+                // before its handshake starts
+                if viewer.client_key.is_none() {
+                    println!("[HOST] Holding the request from {} until its link is secured", remote_id);
+                    viewer.request = Some(remote_id);
+                    return Ok(());
+                }
+                self.handle_session_request(viewer, remote_id, app_handle).await?;
+            }
+            protocol::control::SESSION_END => {
+                // Privacy mode, input and the connection-ended event are handled
+                // as the viewer is removed
+                viewer.ended = true;
+            }
+            protocol::control::KEEPALIVE => {
+                viewer.link.write_frame(Frame::control(protocol::control::KEEPALIVE, &[])).await?;
+            }
+            protocol::control::P2P_OFFER => {
+                println!("[HOST] Received P2P_OFFER");
+                // Parse remote P2P info
+                if let Ok(remote_info) = P2PInfo::decode(&frame.payload[1..]) {
+                    println!("[HOST] Remote P2P info: {:?}", remote_info);
+
+                    // Gather our P2P info. A multiplexed registration keeps every
+                    // viewer on the one relay connection, and waiting for a direct
+                    // link here would hold up the others' frames. A viewer that has
+                    // not finished the handshake can't secure a direct link either
+                    let client_key = viewer.client_key;
+                    let my_id = self.identity.device_id_raw();
+                    let p2p_port = choose_p2p_port(&my_id);
+                    // Nor does one not yet accepted learn our addresses; it offers again once in.
+                    // A viewer that can't take frames over the link would gain nothing
+                    let direct_frames = viewer.capabilities.lock().supports_feature(protocol::feature::DIRECT_FRAMES);
+                    let local_info = gather_p2p_info(
+                        self.p2p_enabled && !self.multiplexed && client_key.is_some() && direct_frames && self.is_accepted(viewer),
+                        p2p_port,
+                    ).await;
+
+                    // Send P2P answer
+                    let answer_data = local_info.encode();
+                    viewer.link.write_frame(Frame::control(protocol::control::P2P_ANSWER, &answer_data)).await?;
+                    println!("[HOST] Sent P2P_ANSWER");
+
+                    // If either side has P2P enabled, prepare for P2P connection
+                    let client_key = client_key.filter(|_| !self.multiplexed && (remote_info.p2p_enabled || local_info.p2p_enabled));
+                    if let Some(client_key) = client_key {
+                        // Start P2P listener
+                        if let Ok(listener) = create_p2p_listener(p2p_port).await {
+                            let identity = self.identity.clone();
+                            // Wait for P2P connection or P2P_FAILED message; a link
+                            // failing the handshake leaves the viewer on the relay
+                            tokio::select! {
+                                p2p_result = accept_p2p_connection(&listener, remote_info.public_addr, &identity, &client_key) => {
+                                    if let Ok(Some(transport)) = p2p_result {
+                                        println!("[HOST] P2P connection accepted and secured");
+                                        viewer.p2p_stream = Some(transport);
+                                    }
+                                }
+                                // Also check for relay messages (P2P_FAILED)
+                                relay_frame = self.read_frame(&viewer.link) => {
+                                    if let Ok(f) = relay_frame {
+                                        if f.channel == Channel::Control
+                                            && !f.payload.is_empty()
+                                            && f.payload[0] == protocol::control::P2P_FAILED
+                                        {
+                                            println!("[HOST] Client reported P2P failed, staying on relay");
+                                        } else if f.payload[0] == protocol::control::P2P_READY {
+                                            println!("[HOST] Client reported P2P ready");
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            protocol::control::P2P_READY => {
+                // The viewer's last frame on the relay; ours follow it over the link
+                match viewer.p2p_stream.take() {
+                    Some(transport) => {
+                        let (reader, writer) = transport.into_direct()?;
+                        viewer.link.promote(writer).await?;
+                        self.direct = Some((viewer.link.id, reader));
+                        viewer.connection_type = ConnectionType::P2P;
+                        println!("[HOST] Viewer {} is now on the direct link", viewer.link.id);
+                        if let Some(handle) = app_handle {
+                            let _ = handle.emit("connection-type-changed", serde_json::json!({
+                                "type": "P2P"
+                            }));
+                        }
+                    }
+                    None => println!("[HOST] Received P2P_READY without a secured link, staying on relay"),
+                }
+            }
+            protocol::control::P2P_FAILED => {
+                println!("[HOST] Received P2P_FAILED - using relay");
+                // Also sent mid-session when the viewer's direct link dropped
+                self.leave_direct_link(viewer, app_handle).await;
+            }
+            protocol::control::RESOLUTION => {
+                // Client sends target viewport resolution
+                if frame.payload.len() >= 5 {
+                    let width = u16::from_le_bytes([frame.payload[1], frame.payload[2]]);
+                    let height = u16::from_le_bytes([frame.payload[3], frame.payload[4]]);
+                    // Older clients send one per resize event: repeats and
+                    // minimised (zero) sizes change nothing
+                    if width > 0 && height > 0 && viewer.target_resolution != Some((width, height)) {
+                        println!("[HOST] Client resolution: {}x{}", width, height);
+                        viewer.target_resolution = Some((width, height));
+                    }
+                }
+            }
+            protocol::control::QUALITY => {
+                if let Some(&quality) = frame.payload.get(1) {
+                    println!("[HOST] Client requested quality: {}", quality);
+                    crate::capture::set_quality(quality);
+                }
+            }
+            protocol::control::LOSSLESS => {
+                let enabled = frame.payload.get(1).is_some_and(|&v| v != 0);
+                if !viewer.capabilities.lock().supports_codec(protocol::codec::PNG) {
+                    println!("[HOST] Ignoring lossless request: PNG not negotiated");
+                } else {
+                    if enabled {
+                        println!("[HOST] Client enabled lossless frames - expect much higher bandwidth");
+                    }
+                    crate::capture::set_lossless(enabled);
+                }
+            }
+            protocol::control::REQUEST_CONTROL => {
+                let id = viewer.link.id;
+                let (requested, remote_id) = {
+                    let mut registry = self.registry.lock();
+                    let remote_id = registry.get(id).map(|e| e.remote_id.clone()).unwrap_or_default();
+                    (registry.control.request(id), remote_id)
+                };
+                if !view_only {
+                    // Already in control; say so again
+                    send_input_control(&self.registry, id, true).await;
+                } else if requested {
+                    println!("[HOST] Viewer {} ({}) asks for input control", id, remote_id);
+                    if let Some(handle) = app_handle {
+                        let _ = handle.emit("control-requested", serde_json::json!({
+                            "viewer_id": id,
+                            "remote_id": remote_id,
+                        }));
+                    }
+                }
+            }
+            protocol::control::AUTH_PASSWORD => {
+                // One try per request: a viewer that got it wrong is sent away
+                let Some((answer, remote_id)) = viewer.password_answer.take() else {
+                    println!("[HOST] Ignoring access password from viewer {}: none asked for", viewer.link.id);
+                    return Ok(());
+                };
+                // Never accepted in the clear, where the relay could read it
+                let verified = match unattended_password() {
+                    Some(hash) if viewer.link.channel_state().is_encrypted() => {
+                        let password = frame.payload[1..].to_vec();
+                        tokio::task::spawn_blocking(move || crate::crypto::verify_access_password(&hash, &password)).await?
+                    }
+                    _ => false,
+                };
+                if verified {
+                    PASSWORD_FAILURES.lock().succeeded(&remote_id);
+                    // Listed only once it proved the password
+                    self.register_viewer(viewer, remote_id);
+                } else {
+                    PASSWORD_FAILURES.lock().record(&remote_id, Instant::now());
+                }
+                let _ = answer.try_send(verified);
+            }
+            // Answered at once: any delay here would count as network time
+            protocol::control::PING => {
+                viewer.link.write_frame(Frame::control(protocol::control::PONG, &frame.payload[1..])).await?;
+            }
+            protocol::control::FRAME_TIMING => {
+                if let Some(reply) = crate::latency::reply_to_echo(&frame.payload[1..]) {
+                    viewer.link.write_frame(Frame::control(protocol::control::FRAME_TIMING, &reply)).await?;
+                }
+            }
+            protocol::control::AUDIO => {
+                let enabled = frame.payload.get(1).is_some_and(|&v| v != 0);
+                viewer.audio.requested = enabled;
+                if !self.is_accepted(viewer) {
+                    println!("[HOST] Viewer {} asked for audio before it was accepted", viewer.link.id);
+                    return Ok(());
+                }
+                let capabilities = *viewer.capabilities.lock();
+                match viewer.audio.set_enabled(enabled, &capabilities, &viewer.link) {
+                    Ok(()) => println!("[HOST] Viewer {} {} audio", viewer.link.id, if enabled { "started" } else { "stopped" }),
+                    Err(e) => eprintln!("[HOST] Cannot send audio to viewer {}: {}", viewer.link.id, e),
+                }
+            }
+            protocol::control::FOCUS_LOCK => {
+                let enabled = frame.payload.get(1).is_some_and(|&v| v != 0);
+                println!("[HOST] Viewer {} {} keyboard focus", viewer.link.id, if enabled { "pinned" } else { "released" });
+                viewer.focus_lock = enabled.then(input::FocusLock::new);
+            }
+            protocol::control::SOURCES_REQUEST => {
+                let kind = frame.payload.get(1).copied().and_then(SourceKind::from_u8)
+                    .unwrap_or(SourceKind::Monitor);
+                let listed = tokio::task::spawn_blocking(move || sources::list(kind)).await?;
+                println!("[HOST] Client listed {:?} sources ({} found)", kind, listed.len());
+                viewer.link.write_frame(Frame::control(
+                    protocol::control::SOURCES,
+                    &sources::encode(kind, &listed),
+                )).await?;
+            }
+            protocol::control::SELECT_MONITOR if frame.payload.len() >= 5 => {
+                let index = u32::from_le_bytes([frame.payload[1], frame.payload[2], frame.payload[3], frame.payload[4]]);
+                let status = if view_only {
+                    protocol::control::SELECT_MONITOR_DENIED
+                } else {
+                    // Shared by every viewer: they all see the same screen
+                    let capture = self.capture.clone();
+                    let mode = crate::capture::CaptureMode::from_wire(index);
+                    let result = tokio::task::spawn_blocking(move || match capture.lock().as_mut() {
+                        Some(capture) => capture.set_capture_mode(mode),
+                        None => Err(anyhow::anyhow!("Screen capture unavailable")),
+                    }).await?;
+                    match result {
+                        Ok(_) => {
+                            // Pointer positions are relative to the new monitor
+                            self.input.lock().reset();
+                            protocol::control::SELECT_MONITOR_OK
+                        }
+                        Err(e) => {
+                            println!("[HOST] Viewer {} could not switch to {:?}: {}", viewer.link.id, mode, e);
+                            protocol::control::SELECT_MONITOR_FAILED
+                        }
+                    }
+                };
+                let mut reply = index.to_le_bytes().to_vec();
+                reply.push(status);
+                viewer.link.write_frame(Frame::control(protocol::control::SELECT_MONITOR, &reply)).await?;
+            }
+            protocol::control::ELEVATION_REQUEST => {
+                println!("[HOST] Client requested elevation");
+                if crate::elevation::is_elevated() {
+                    viewer.link.write_frame(Self::elevation_status_frame()).await?;
+                    return Ok(());
+                }
+
+                if let Some(handle) = app_handle {
+                    let _ = handle.emit("elevation-requested", serde_json::json!({}));
+                }
+
+                // Blocks on the OS prompt; on success this process exits and the
+                // elevated instance takes over the relay registration
+                let result = tokio::task::spawn_blocking(crate::elevation::relaunch_elevated).await?;
+                if let Err(e) = result {
+                    eprintln!("[HOST] Elevation failed: {}", e);
+                    if let Some(handle) = app_handle {
+                        let _ = handle.emit("elevation-failed", serde_json::json!({
+                            "error": e.to_string()
+                        }));
+                    }
+                }
+                viewer.link.write_frame(Self::elevation_status_frame()).await?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Answer a connection request once the viewer's link is secured: refused,
+    /// sent to the password check, let in on a grant for its key, or queued
+    /// for the user
+    async fn handle_session_request<R: tauri::Runtime>(
+        &mut self,
+        viewer: &mut Viewer,
+        remote_id: String,
+        app_handle: Option<&tauri::AppHandle<R>>,
+    ) -> Result<()> {
+        let Some(client_key) = viewer.client_key else {
+            anyhow::bail!("Connection request on a link that is not secured");
+        };
+
+        // The ID the relay was given is only a claim; the block list and the
+        // user see the one the viewer's keys prove
+        let proven = viewer.device_id.clone().filter(|id| *id == remote_id.replace(' ', ""));
+        let Some(remote_id) = proven else {
+            match &viewer.device_id {
+                Some(id) => println!("[HOST] Refusing {}: its keys belong to device {}", remote_id, id),
+                None => println!("[HOST] Refusing {}: it cannot prove its device ID - it needs to be updated", remote_id),
+            }
+            viewer.link.write_frame(Frame::control(
+                protocol::control::SESSION_END,
+                &[protocol::control::END_REASON_DECLINED],
+            )).await?;
+            viewer.ended = self.multiplexed;
+            return Ok(());
+        };
+
+        // Blocked devices get a plain decline and the user never hears of them
+        if is_device_blocked(&remote_id) {
+            println!("[HOST] Refusing blocked device {}", remote_id);
+            viewer.link.write_frame(Frame::control(
+                protocol::control::SESSION_END,
+                &[protocol::control::END_REASON_DECLINED],
+            )).await?;
+            viewer.ended = self.multiplexed;
+            return Ok(());
+        }
+
+        // With unattended access on the password decides: the user is not
+        // asked and the request never joins the approval queue
+        if unattended_password().is_some() {
+            // Each guess costs an argon2 hash, and enough of them could find the password
+            if PASSWORD_FAILURES.lock().is_locked_out(&remote_id, Instant::now()) {
+                println!("[HOST] Refusing {}: too many wrong access passwords", remote_id);
+                viewer.link.write_frame(Frame::control(
+                    protocol::control::SESSION_END,
+                    &[protocol::control::END_REASON_BAD_PASSWORD],
+                )).await?;
+                viewer.ended = self.multiplexed;
+                return Ok(());
+            }
+            let (tx, rx) = mpsc::channel::<bool>(1);
+            viewer.password_answer = Some((tx, remote_id.clone()));
+            println!("[HOST] Waiting for {} to send the access password", remote_id);
+            tokio::spawn(Self::await_password(
+                remote_id,
+                rx,
+                viewer.link.clone(),
+                self.answers_tx.clone(),
+                app_handle.cloned(),
+            ));
+            return Ok(());
+        }
 
-        match frame.payload[0] {
-            protocol::control::HANDSHAKE => {
-                println!("[HOST] Received HANDSHAKE");
-                // Noise handshake from client
-                let mut responder = self.identity.create_responder()?;
-                let mut buf = vec![0u8; 65535];
+        // Queue the request - each one is answered independently
+        let (tx, rx) = mpsc::channel::<bool>(1);
+        let queued = {
+            let mut pending = self.pending_connection.lock();
+            pending.push(remote_id.clone(), client_key, tx).map(|id| (id, pending.len()))
+        };
 
-                // Read message
-                responder.read_message(&frame.payload[1..], &mut buf)?;
+        let Some((request_id, queue_position)) = queued else {
+            println!("[HOST] Too many pending requests - refusing {}", remote_id);
+            viewer.link.write_frame(Frame::control(
+                protocol::control::SESSION_END,
+                &[protocol::control::END_REASON_BUSY],
+            )).await?;
+            viewer.ended = self.multiplexed;
+            return Ok(());
+        };
 
-                // Send response
-                let len = responder.write_message(&[], &mut buf)?;
-                let mut response = vec![protocol::control::HANDSHAKE];
-                response.extend_from_slice(&buf[..len]);
-                self.write_frame(Frame::new(Channel::Control, response)).await?;
+        self.register_viewer(viewer, remote_id.clone());
+
+        // A viewer accepted "always", "for this session" or "for an hour"
+        // earlier is let straight in if it holds the same key; everyone else
+        // goes to the approval dialog
+        if is_viewer_allowed(&client_key) {
+            println!("[HOST] {} is always allowed - accepting without asking", remote_id);
+            self.pending_connection.lock().respond(Some(request_id), true)?;
+        } else if has_temporary_trust(&client_key) {
+            println!("[HOST] {} has temporary trust - accepting without asking", remote_id);
+            self.pending_connection.lock().respond(Some(request_id), true)?;
+        } else if let Some(handle) = app_handle {
+            let _ = handle.emit("connection-request", serde_json::json!({
+                "request_id": request_id,
+                "remote_id": remote_id.clone(),
+                "queue_position": queue_position,
+                "viewer_id": viewer.link.id,
+            }));
+            println!("[HOST] Emitted connection-request {} for: {}", request_id, remote_id);
+        }
 
-                // Complete handshake
-                if responder.is_handshake_finished() {
-                    self.channel = Some(SecureChannel::from_handshake(responder)?);
-                }
-            }
-            protocol::control::SESSION_REQUEST => {
-                // Extract remote ID from payload (bytes after the message type)
-                let remote_id = if frame.payload.len() > 1 {
-                    String::from_utf8_lossy(&frame.payload[1..]).to_string()
-                } else {
-                    "Unknown".to_string()
-                };
+        // Wait for the answer off the reader loop so further requests
+        // (and respond_to_connection) are not blocked behind this one
+        tokio::spawn(Self::await_approval(
+            request_id,
+            remote_id,
+            rx,
+            self.pending_connection.clone(),
+            viewer.link.clone(),
+            self.answers_tx.clone(),
+            app_handle.cloned(),
+        ));
 
-                println!("[HOST] Received SESSION_REQUEST from: {}", remote_id);
+        Ok(())
+    }
 
-                // Create channel for user response
-                let (tx, mut rx) = mpsc::channel::<bool>(1);
+    /// List a viewer that asked to connect, before it is answered
+    fn register_viewer(&self, viewer: &Viewer, remote_id: String) {
+        let connected_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.registry.lock().add(ViewerEntry {
+            remote_id,
+            connected_at,
+            link: viewer.link.clone(),
+            capabilities: viewer.capabilities.clone(),
+        });
+    }
 
-                // Store pending connection
-                {
-                    let mut pending = self.pending_connection.lock();
-                    *pending = Some(PendingConnection {
-                        remote_id: remote_id.clone(),
-                        response_tx: tx,
-                    });
-                }
+    /// Let an accepted viewer in
+    async fn send_accept(link: &ViewerLink) -> Result<()> {
+        link.write_frame(Frame::control(protocol::control::SESSION_ACCEPT, &[0x01])).await?;
+        link.write_frame(Self::elevation_status_frame()).await
+    }
 
-                // Emit event to frontend to show approval dialog
-                if let Some(handle) = app_handle {
-                    let _ = handle.emit("connection-request", serde_json::json!({
-                        "remote_id": remote_id.clone()
-                    }));
-                    println!("[HOST] Emitted connection-request event for: {}", remote_id);
-                }
+    /// Tells the client whether input can reach elevated windows
+    fn elevation_status_frame() -> Frame {
+        let elevated = crate::elevation::is_elevated();
+        Frame::control(protocol::control::ELEVATION_STATUS, &[elevated as u8])
+    }
 
-                // Wait for user response (with timeout)
-                let accepted = tokio::time::timeout(
-                    tokio::time::Duration::from_secs(30),
-                    rx.recv()
-                ).await.unwrap_or(None).unwrap_or(false);
+    /// Wait for the user's answer to one connection request and reply to the requester
+    async fn await_approval<R: tauri::Runtime>(
+        request_id: u64,
+        remote_id: String,
+        mut rx: mpsc::Receiver<bool>,
+        pending: Arc<SyncMutex<PendingConnections>>,
+        link: ViewerLink,
+        answered: mpsc::UnboundedSender<(u16, bool)>,
+        app_handle: Option<tauri::AppHandle<R>>,
+    ) {
+        let answer = tokio::time::timeout(approval_timeout(), rx.recv()).await;
+        let timed_out = answer.is_err();
+        let accepted = answer.unwrap_or(None).unwrap_or(false);
+
+        // No-op if respond() already removed it; clears timed-out requests
+        pending.lock().remove(request_id);
+
+        let result = if accepted {
+            Self::send_accept(&link).await
+        } else {
+            link.write_frame(Frame::control(
+                protocol::control::SESSION_END,
+                &[protocol::control::END_REASON_DECLINED],
+            )).await
+        };
 
-                // Clear pending connection
-                {
-                    let mut pending = self.pending_connection.lock();
-                    *pending = None;
-                }
+        if let Err(e) = result {
+            eprintln!("[HOST] Failed to answer connection request {}: {}", request_id, e);
+            return;
+        }
+        // The session loop hands out input control and drops declined viewers
+        let _ = answered.send((link.id, accepted));
 
-                if accepted {
-                    // User accepted - send SESSION_ACCEPT
-                    self.write_frame(Frame::control(protocol::control::SESSION_ACCEPT, &[0x01])).await?;
-                    println!("[HOST] User accepted - sent SESSION_ACCEPT");
+        if accepted {
+            println!("[HOST] User accepted {} - sent SESSION_ACCEPT", remote_id);
+        } else if timed_out {
+            println!("[HOST] Request from {} timed out - sent SESSION_END", remote_id);
+        } else {
+            println!("[HOST] User declined {} - sent SESSION_END", remote_id);
+        }
 
-                    // Emit connected event
-                    if let Some(handle) = app_handle {
-                        let _ = handle.emit("connection-accepted", serde_json::json!({
-                            "remote_id": remote_id
-                        }));
-                    }
-                } else {
-                    // User declined or timeout - send SESSION_REJECT
-                    self.write_frame(Frame::control(protocol::control::SESSION_END, &[0x00])).await?;
-                    println!("[HOST] User declined - sent SESSION_END");
-                }
+        if let Some(handle) = app_handle {
+            // Lets the UI dismiss the dialog the user never answered
+            if timed_out {
+                let _ = handle.emit("connection-request-timeout", serde_json::json!({
+                    "request_id": request_id,
+                    "remote_id": remote_id.clone(),
+                }));
             }
-            protocol::control::SESSION_END => {
-                self.running = false;
-                self.privacy.disable_all()?;
 
-                // Emit disconnected event
-                if let Some(handle) = app_handle {
-                    let _ = handle.emit("connection-ended", serde_json::json!({}));
-                }
-            }
-            protocol::control::KEEPALIVE => {
-                self.write_frame(Frame::control(protocol::control::KEEPALIVE, &[])).await?;
+            let _ = handle.emit("connection-request-closed", serde_json::json!({
+                "request_id": request_id,
+                "remote_id": remote_id.clone(),
+                "accepted": accepted,
+            }));
+
+            // Emit connected event
+            if accepted {
+                let _ = handle.emit("connection-accepted", serde_json::json!({
+                    "request_id": request_id,
+                    "remote_id": remote_id,
+                    "viewer_id": link.id,
+                }));
             }
-            protocol::control::P2P_OFFER => {
-                println!("[HOST] Received P2P_OFFER");
-                // Parse remote P2P info
-                if let Ok(remote_info) = P2PInfo::decode(&frame.payload[1..]) {
-                    println!("[HOST] Remote P2P info: {:?}", remote_info);
+        }
+    }
 
-                    // Gather our P2P info
-                    let my_id = self.identity.device_id_raw();
-                    let p2p_port = choose_p2p_port(&my_id);
-                    let local_info = gather_p2p_info(self.p2p_enabled, p2p_port).await;
+    /// Answer a viewer under unattended access once its password is checked;
+    /// one that sends none within PASSWORD_TIMEOUT is refused like a wrong one
+    async fn await_password<R: tauri::Runtime>(
+        remote_id: String,
+        mut rx: mpsc::Receiver<bool>,
+        link: ViewerLink,
+        answered: mpsc::UnboundedSender<(u16, bool)>,
+        app_handle: Option<tauri::AppHandle<R>>,
+    ) {
+        let accepted = tokio::time::timeout(PASSWORD_TIMEOUT, rx.recv()).await.ok().flatten().unwrap_or(false);
+        let result = if accepted {
+            Self::send_accept(&link).await
+        } else {
+            link.write_frame(Frame::control(
+                protocol::control::SESSION_END,
+                &[protocol::control::END_REASON_BAD_PASSWORD],
+            )).await
+        };
+        if let Err(e) = result {
+            eprintln!("[HOST] Failed to answer {}: {}", remote_id, e);
+            return;
+        }
+        let _ = answered.send((link.id, accepted));
 
-                    // Send P2P answer
-                    let answer_data = local_info.encode();
-                    self.write_frame(Frame::control(protocol::control::P2P_ANSWER, &answer_data)).await?;
-                    println!("[HOST] Sent P2P_ANSWER");
+        if !accepted {
+            println!("[HOST] {} did not give the access password - sent SESSION_END", remote_id);
+            return;
+        }
+        println!("[HOST] {} gave the access password - sent SESSION_ACCEPT", remote_id);
+        if let Some(handle) = app_handle {
+            let _ = handle.emit("connection-accepted", serde_json::json!({
+                "request_id": null,
+                "remote_id": remote_id,
+                "viewer_id": link.id,
+                "unattended": true,
+            }));
+        }
+    }
 
-                    // If either side has P2P enabled, prepare for P2P connection
-                    if remote_info.p2p_enabled || local_info.p2p_enabled {
-                        // Start P2P listener
-                        if let Ok(listener) = create_p2p_listener(p2p_port).await {
-                            // Wait for P2P connection or P2P_FAILED message
-                            tokio::select! {
-                                p2p_result = accept_p2p_connection(&listener, remote_info.public_addr) => {
-                                    if let Ok(Some(transport)) = p2p_result {
-                                        println!("[HOST] P2P connection accepted!");
-                                        self.p2p_stream = Some(transport.stream);
-                                        self.connection_type = ConnectionType::P2P;
-
-                                        // Emit connection type change event
-                                        if let Some(handle) = app_handle {
-                                            let _ = handle.emit("connection-type-changed", serde_json::json!({
-                                                "type": "P2P"
-                                            }));
-                                        }
-                                    }
-                                }
-                                // Also check for relay messages (P2P_FAILED)
-                                relay_frame = self.read_frame() => {
-                                    if let Ok(f) = relay_frame {
-                                        if f.channel == Channel::Control
-                                            && !f.payload.is_empty()
-                                            && f.payload[0] == protocol::control::P2P_FAILED
-                                        {
-                                            println!("[HOST] Client reported P2P failed, staying on relay");
-                                        } else if f.payload[0] == protocol::control::P2P_READY {
-                                            println!("[HOST] Client reported P2P ready");
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            protocol::control::P2P_READY => {
-                println!("[HOST] Received P2P_READY - P2P connection confirmed");
-            }
-            protocol::control::P2P_FAILED => {
-                println!("[HOST] Received P2P_FAILED - using relay");
-                self.connection_type = ConnectionType::Relay;
-            }
-            protocol::control::RESOLUTION => {
-                // Client sends target viewport resolution
-                if frame.payload.len() >= 5 {
-                    let width = u16::from_le_bytes([frame.payload[1], frame.payload[2]]);
-                    let height = u16::from_le_bytes([frame.payload[3], frame.payload[4]]);
-                    println!("[HOST] Client resolution: {}x{}", width, height);
-                    self.target_resolution = Some((width, height));
-                }
+    async fn handle_input(&mut self, viewer: &mut Viewer, frame: &Frame) -> Result<()> {
+        // Batched pointer events replay in the order they were sent
+        if frame.payload.first() == Some(&protocol::input::INPUT_BATCH) {
+            for event in input::decode_batch(&frame.payload) {
+                self.handle_input_event(viewer, event)?;
             }
-            _ => {}
+            return Ok(());
         }
-        Ok(())
+        self.handle_input_event(viewer, &frame.payload)
     }
 
-    async fn handle_input(&mut self, frame: &Frame) -> Result<()> {
-        if frame.payload.is_empty() {
+    fn handle_input_event(&mut self, viewer: &mut Viewer, payload: &[u8]) -> Result<()> {
+        if payload.is_empty() {
             return Ok(());
         }
 
-        match frame.payload[0] {
+        // Classify for rate limiting - releases always pass so nothing gets stuck
+        let class = match payload[0] {
+            protocol::input::MOUSE_MOVE
+            | protocol::input::MOUSE_SCROLL
+            | protocol::input::MOUSE_SCROLL_PRECISE => InputClass::Pointer,
+            protocol::input::MOUSE_BUTTON if payload.get(2) == Some(&0) => InputClass::Release,
+            protocol::input::KEY_UP => InputClass::Release,
+            _ => InputClass::Press,
+        };
+        if !viewer.input_limiter.allow(class) {
+            return Ok(());
+        }
+        // Protected windows get no presses or movement; releases still pass
+        if class != InputClass::Release {
+            let hit = crate::policy::foreground_hit();
+            if let Some(hit) = &hit {
+                if viewer.policy_block.is_none() {
+                    println!("[HOST] Input from viewer {} blocked by policy ({})", viewer.link.id, hit.rule.pattern);
+                }
+            }
+            viewer.policy_block = hit;
+            if viewer.policy_block.is_some() {
+                return Ok(());
+            }
+        }
+        // The screen is about to change for everyone watching
+        viewer.input_activity.notify_one();
+        for other in self.viewers.values() {
+            other.input_activity.notify_one();
+        }
+
+        let mut injector = self.input.lock();
+        let (screen_w, screen_h) = injector.screen_size();
+
+        match payload[0] {
             protocol::input::MOUSE_MOVE => {
-                if frame.payload.len() >= 9 {
-                    let x = i32::from_le_bytes(frame.payload[1..5].try_into()?);
-                    let y = i32::from_le_bytes(frame.payload[5..9].try_into()?);
-                    self.input.move_mouse(x, y)?;
+                if payload.len() >= 9 {
+                    let x = i32::from_le_bytes(payload[1..5].try_into()?);
+                    let y = i32::from_le_bytes(payload[5..9].try_into()?);
+                    let (x, y) = input::clamp_to_screen(x, y, screen_w, screen_h);
+                    injector.move_mouse(x, y)?;
                 }
             }
             protocol::input::MOUSE_BUTTON => {
-                if frame.payload.len() >= 11 {
-                    let button = frame.payload[1];
-                    let pressed = frame.payload[2] != 0;
-                    let x = i32::from_le_bytes(frame.payload[3..7].try_into()?);
-                    let y = i32::from_le_bytes(frame.payload[7..11].try_into()?);
-                    self.input.mouse_button(button, pressed, x, y)?;
+                if payload.len() >= 11 {
+                    let button = payload[1];
+                    let pressed = payload[2] != 0;
+                    let x = i32::from_le_bytes(payload[3..7].try_into()?);
+                    let y = i32::from_le_bytes(payload[7..11].try_into()?);
+                    let (x, y) = input::clamp_to_screen(x, y, screen_w, screen_h);
+                    injector.mouse_button(button, pressed, x, y)?;
+                    if let Some(lock) = viewer.focus_lock.as_mut() {
+                        lock.clicked();
+                    }
                 }
             }
             protocol::input::MOUSE_SCROLL => {
-                if frame.payload.len() >= 9 {
-                    let dx = i32::from_le_bytes(frame.payload[1..5].try_into()?);
-                    let dy = i32::from_le_bytes(frame.payload[5..9].try_into()?);
-                    self.input.mouse_scroll(dx, dy)?;
+                if payload.len() >= 9 {
+                    let dx = i32::from_le_bytes(payload[1..5].try_into()?);
+                    let dy = i32::from_le_bytes(payload[5..9].try_into()?);
+                    // Legacy clients send whole notches
+                    let (dx, dy) = input::clamp_scroll(dx, dy);
+                    injector.mouse_scroll(
+                        dx * input::SCROLL_UNITS_PER_NOTCH,
+                        dy * input::SCROLL_UNITS_PER_NOTCH,
+                    )?;
+                }
+            }
+            protocol::input::MOUSE_SCROLL_PRECISE => {
+                if payload.len() >= 9 {
+                    let dx = i32::from_le_bytes(payload[1..5].try_into()?);
+                    let dy = i32::from_le_bytes(payload[5..9].try_into()?);
+                    let (dx, dy) = input::clamp_scroll_units(dx, dy);
+                    injector.mouse_scroll(dx, dy)?;
                 }
             }
             protocol::input::KEY_DOWN | protocol::input::KEY_UP => {
-                if frame.payload.len() >= 4 {
-                    let key = u16::from_le_bytes(frame.payload[1..3].try_into()?);
-                    let pressed = frame.payload[0] == protocol::input::KEY_DOWN;
-                    self.input.key_event(key, pressed)?;
+                if payload.len() >= 4 {
+                    let key = u16::from_le_bytes(payload[1..3].try_into()?);
+                    let pressed = payload[0] == protocol::input::KEY_DOWN;
+                    if let Some(lock) = viewer.focus_lock.as_mut() {
+                        lock.before_key();
+                    }
+                    injector.key_event(key, pressed)?;
                 }
             }
             _ => {}
@@ -451,25 +2570,34 @@ impl HostSession {
         Ok(())
     }
 
-    async fn handle_privacy(&mut self, frame: &Frame) -> Result<()> {
+    async fn handle_privacy(&mut self, viewer: &mut Viewer, frame: &Frame) -> Result<()> {
         if frame.payload.is_empty() {
             return Ok(());
         }
 
-        match frame.payload[0] {
-            protocol::privacy::BLACK_SCREEN_ON => {
-                self.privacy.enable_black_screen()?;
-            }
-            protocol::privacy::BLACK_SCREEN_OFF => {
-                self.privacy.disable_black_screen()?;
-            }
-            protocol::privacy::INPUT_BLOCK_ON => {
-                self.privacy.block_input()?;
-            }
-            protocol::privacy::INPUT_BLOCK_OFF => {
-                self.privacy.unblock_input()?;
+        // A view-only viewer only gets the current state back
+        if self.controls_input(viewer) {
+            match frame.payload[0] {
+                protocol::privacy::BLACK_SCREEN_ON => {
+                    // e.g. no X11 display; the ack below tells the viewer it is off
+                    if let Err(e) = self.privacy.enable_black_screen() {
+                        eprintln!("[HOST] Black screen unavailable: {}", e);
+                    }
+                }
+                protocol::privacy::BLACK_SCREEN_OFF => {
+                    self.privacy.disable_black_screen()?;
+                }
+                protocol::privacy::INPUT_BLOCK_ON => {
+                    // e.g. no Accessibility permission; the ack tells the viewer
+                    if let Err(e) = self.privacy.block_input() {
+                        eprintln!("[HOST] Input blocking unavailable: {}", e);
+                    }
+                }
+                protocol::privacy::INPUT_BLOCK_OFF => {
+                    self.privacy.unblock_input()?;
+                }
+                _ => {}
             }
-            _ => {}
         }
 
         // Send acknowledgment
@@ -478,29 +2606,283 @@ impl HostSession {
             self.privacy.is_black_screen_active() as u8,
             self.privacy.is_input_blocked() as u8,
         ];
-        self.write_frame(Frame::new(Channel::Privacy, status)).await?;
+        viewer.link.write_frame(Frame::new(Channel::Privacy, status)).await?;
         Ok(())
     }
 
-    async fn send_video_frame(&mut self) -> Result<()> {
-        let (width, height, data) = self.capture.capture()?;
+    /// Video task - captures and sends a frame for each queued request
+    /// Capture and JPEG encoding run on the blocking pool; exits when the session is dropped.
+    /// A frame identical to the previous one is answered with FRAME_UNCHANGED instead,
+    /// if the client negotiated that feature. While the screen cannot be captured,
+    /// requests get the empty marker, the capture is reopened every
+    /// `CAPTURE_RETRY_INTERVAL` and each failed attempt is reported with
+    /// control::CAPTURE_UNAVAILABLE. Clients that negotiated DIRTY_TILES get only
+    /// the areas changed since the last frame sent, with a whole frame at least
+    /// every `KEYFRAME_INTERVAL`. The cursor is sent apart, as input::CURSOR_UPDATE.
+    async fn video_task(
+        capture: Arc<SyncMutex<Option<ScreenCapture>>>,
+        link: ViewerLink,
+        registry: Arc<SyncMutex<ViewerRegistry>>,
+        mut requests: mpsc::Receiver<u8>,
+        capabilities: Arc<SyncMutex<Capabilities>>,
+        input_activity: Arc<Notify>,
+    ) {
+        let mut deduper = FrameDeduper::default();
+        let mut throttle = CaptureThrottle::default();
+        let mut health = CaptureHealth::default();
+        let mut display_size: Option<(u32, u32)> = None;
+        let mut lossless = false;
+        // The damage log entry the client's canvas shows, and tile frames since a whole one
+        let mut baseline: Option<u64> = None;
+        let mut tiles_sent = 0u32;
+        let mut cursor_sent = crate::cursor::Sent::default();
+        let vpx = Arc::new(SyncMutex::new(VpxVideo::default()));
+
+        while let Some(kind) = requests.recv().await {
+            // handle_frame forwards no requests from viewers not accepted; nothing is captured for one either
+            if !registry.lock().control.is_approved(link.id) {
+                if link.write_frame(Frame::video(vec![protocol::video::FRAME_UNCHANGED])).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+
+            // Snapshots are taken on demand; everything else follows the capture rate
+            if kind != protocol::video::SNAPSHOT_REQUEST {
+                Self::pace_capture(&mut throttle, &input_activity).await;
+            }
+            let started = Instant::now();
+
+            // After switching format, re-encode now rather than keep serving a
+            // cached frame in the old format on a static screen
+            let format_switched = crate::capture::is_lossless() != lossless;
+            lossless = crate::capture::is_lossless();
+
+            // Permission may have been granted or a display attached since the last try
+            let mut unavailable_reason = None;
+            if capture.lock().is_none() && health.should_retry(Instant::now()) {
+                match tokio::task::spawn_blocking(ScreenCapture::new).await {
+                    Ok(Ok(opened)) => {
+                        println!("[HOST] Screen capture available");
+                        *capture.lock() = Some(opened);
+                    }
+                    Ok(Err(e)) => {
+                        health.open_failed(Instant::now());
+                        unavailable_reason = Some(e.to_string());
+                    }
+                    Err(e) => {
+                        eprintln!("[HOST] Capture task panicked: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            let streamed = kind == protocol::video::FRAME_REQUEST
+                && !format_switched
+                && !lossless
+                && vpx.lock().active(&capabilities.lock());
+            let tiled = kind == protocol::video::FRAME_REQUEST
+                && !streamed
+                && !format_switched
+                && !lossless
+                && capabilities.lock().supports_feature(protocol::feature::DIRTY_TILES);
+            let since = if tiles_sent >= KEYFRAME_INTERVAL { None } else { baseline };
+
+            let capture_slot = capture.clone();
+            let vpx_slot = vpx.clone();
+            let captured_at = crate::latency::clock_ms();
+            let captured = tokio::task::spawn_blocking(move || {
+                let mut slot = capture_slot.lock();
+                let Some(capture) = slot.as_mut() else {
+                    return Ok(None);
+                };
+                if kind == protocol::video::SNAPSHOT_REQUEST {
+                    capture.capture_keyframe(protocol::video::SNAPSHOT_QUALITY).map(Delta::full)
+                } else if format_switched {
+                    capture.capture_keyframe(crate::capture::get_quality()).map(Delta::full)
+                } else if streamed {
+                    vpx_slot.lock().capture(capture, captured_at)
+                } else if tiled {
+                    capture.capture_delta(since)
+                } else {
+                    capture.capture().map(Delta::full)
+                }
+                .map(Some)
+            }).await;
+
+            let delta = match captured {
+                Ok(Ok(Some(delta))) => {
+                    if health.captured() {
+                        Self::send_capture_status(&link, &capabilities, "").await;
+                    }
+                    delta
+                }
+                Ok(Ok(None)) => Delta::Unchanged,
+                Ok(Err(e)) => {
+                    eprintln!("[HOST] Capture failed: {}", e);
+                    if health.capture_failed(Instant::now()) {
+                        println!("[HOST] Capture keeps failing, reopening it");
+                        *capture.lock() = None;
+                        unavailable_reason = Some(e.to_string());
+                    }
+                    Delta::Unchanged
+                }
+                Err(e) => {
+                    eprintln!("[HOST] Capture task panicked: {}", e);
+                    break;
+                }
+            };
+
+            if let Some(reason) = unavailable_reason {
+                println!("[HOST] Screen capture unavailable: {}", reason);
+                Self::send_capture_status(&link, &capabilities, &reason).await;
+            }
+
+            // The cursor goes ahead of the frame, which does not show it
+            if capabilities.lock().supports_feature(protocol::feature::CURSOR_UPDATE) {
+                if let Some(update) = crate::cursor::update_since(&mut cursor_sent) {
+                    if let Err(e) = link.write_frame(Frame::new(Channel::Input, update.encode())).await {
+                        eprintln!("[HOST] Failed to send cursor: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            // Display resolution changed (mode switch, monitor unplugged): tell the
+            // client ahead of the frame so it reflows its viewport
+            if let Some((width, height)) = delta.size().filter(|&size| display_size != Some(size)) {
+                if let Some((old_width, old_height)) = display_size {
+                    println!("[HOST] Display resolution changed: {}x{} -> {}x{}", old_width, old_height, width, height);
+                    deduper.reset();
+                    if capabilities.lock().supports_feature(protocol::feature::RESOLUTION_CHANGED) {
+                        let mut payload = Vec::with_capacity(4);
+                        payload.extend(&(width as u16).to_le_bytes());
+                        payload.extend(&(height as u16).to_le_bytes());
+                        let notice = Frame::control(protocol::control::RESOLUTION_CHANGED, &payload);
+                        if let Err(e) = link.write_frame(notice).await {
+                            eprintln!("[HOST] Failed to send resolution change: {}", e);
+                            break;
+                        }
+                    }
+                }
+                display_size = Some((width, height));
+            }
+
+            let (width, height, data) = match delta {
+                Delta::Encoded { payload, .. } => {
+                    throttle.record(started, true);
+                    if let Err(e) = link.write_frame(Frame::video(payload)).await {
+                        eprintln!("[HOST] Failed to send video frame: {}", e);
+                        break;
+                    }
+                    continue;
+                }
+                Delta::Tiles { width, height, seq, tiles } => {
+                    throttle.record(started, true);
+                    // The client no longer shows the last whole frame sent
+                    deduper.reset();
+                    baseline = Some(seq);
+                    tiles_sent += 1;
+                    let frame = Frame::video(crate::tiles::encode(width, height, captured_at, &tiles));
+                    if let Err(e) = link.write_frame(frame).await {
+                        eprintln!("[HOST] Failed to send video tiles: {}", e);
+                        break;
+                    }
+                    continue;
+                }
+                Delta::Full { width, height, seq, data } => {
+                    // Snapshots and re-encodes do not say which frame they are
+                    baseline = seq;
+                    tiles_sent = 0;
+                    (width, height, data)
+                }
+                Delta::Unchanged => (0, 0, Vec::new()),
+            };
+
+            // Snapshots are always sent in full. An empty capture (timeout or failure)
+            // is also answered with the marker - pre-HELLO clients read the short frame
+            // as "no frame" too - and resets the baseline for the next real frame.
+            let dedupe = kind != protocol::video::SNAPSHOT_REQUEST
+                && capabilities.lock().supports_feature(protocol::feature::FRAME_UNCHANGED);
+            let repeat = !data.is_empty() && deduper.is_repeat(&data);
+            throttle.record(started, !data.is_empty() && !repeat);
+            let frame = if data.is_empty() {
+                deduper.reset();
+                Frame::video(vec![protocol::video::FRAME_UNCHANGED])
+            } else if dedupe && repeat {
+                Frame::video(vec![protocol::video::FRAME_UNCHANGED])
+            } else {
+                let with_format = capabilities.lock().supports_codec(protocol::codec::PNG);
+                Self::video_frame(width, height, captured_at, &data, with_format)
+            };
+
+            if let Err(e) = link.write_frame(frame).await {
+                eprintln!("[HOST] Failed to send video frame: {}", e);
+                break;
+            }
+        }
+        println!("[HOST] Video task stopped");
+    }
+
+    /// Tell the client why it gets no frames; an empty reason means capture works again
+    async fn send_capture_status(
+        link: &ViewerLink,
+        capabilities: &SyncMutex<Capabilities>,
+        reason: &str,
+    ) {
+        if !capabilities.lock().supports_feature(protocol::feature::CAPTURE_STATUS) {
+            return;
+        }
+        let notice = Frame::control(protocol::control::CAPTURE_UNAVAILABLE, reason.as_bytes());
+        if let Err(e) = link.write_frame(notice).await {
+            eprintln!("[HOST] Failed to send capture status: {}", e);
+        }
+    }
+
+    /// Wait until the next capture is due; while idle, input cuts the wait short
+    async fn pace_capture(throttle: &mut CaptureThrottle, input_activity: &Notify) {
+        let delay = throttle.delay(Instant::now(), crate::capture::frame_rates());
+        if delay.is_zero() {
+            return;
+        }
+        if !throttle.is_idle() {
+            tokio::time::sleep(delay).await;
+            return;
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = input_activity.notified() => {
+                throttle.wake();
+                let delay = throttle.delay(Instant::now(), crate::capture::frame_rates());
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
 
-        let mut payload = Vec::with_capacity(13 + data.len());
+    /// Build a video frame: [keyframe][width LE][height LE][timestamp LE][format][image]
+    /// The timestamp is the capture time on `latency::clock_ms`, only meaningful to this host.
+    /// The format byte is only sent to clients that negotiated codec::PNG
+    fn video_frame(width: u32, height: u32, captured_at: u64, data: &[u8], with_format: bool) -> Frame {
+        let mut payload = Vec::with_capacity(14 + data.len());
         payload.push(0x01); // Keyframe
         payload.extend(&(width as u16).to_le_bytes());
         payload.extend(&(height as u16).to_le_bytes());
-        payload.extend(&0u64.to_le_bytes()); // Timestamp
-        payload.extend(&data);
-
-        self.write_frame(Frame::video(payload)).await
+        payload.extend(&captured_at.to_le_bytes());
+        if with_format {
+            payload.push(crate::capture::frame_format(data));
+        }
+        payload.extend(data);
+        Frame::video(payload)
     }
 
     async fn handle_clipboard_with_events<R: tauri::Runtime>(
         &mut self,
+        viewer: &mut Viewer,
         frame: &Frame,
         app_handle: Option<&tauri::AppHandle<R>>,
     ) -> Result<()> {
-        use crate::clipboard::{ClipboardManager, ClipboardData};
+        use crate::clipboard::{ClipboardAck, ClipboardManager, ClipboardData};
 
         if frame.payload.is_empty() {
             return Ok(());
@@ -512,35 +2894,66 @@ impl HostSession {
                 // Get local clipboard and send it
                 let clipboard = ClipboardManager::new();
                 if let Ok(Some(data)) = clipboard.get_clipboard() {
-                    let encoded = data.encode();
-                    self.write_frame(Frame::clipboard(protocol::clipboard::CLIPBOARD_DATA, &encoded)).await?;
-                    println!("[HOST] Sent clipboard data ({} bytes)", encoded.len());
+                    let data = data.for_peer(&viewer.capabilities.lock());
+                    let max_size = crate::clipboard::max_size();
+                    match data.fit(max_size) {
+                        Some(data) => {
+                            let encoded = data.encode();
+                            viewer.link.write_frame(Frame::clipboard(protocol::clipboard::CLIPBOARD_DATA, &encoded)).await?;
+                            println!("[HOST] Sent clipboard data ({} bytes)", encoded.len());
+                        }
+                        None => {
+                            println!("[HOST] Clipboard too large to send ({} bytes, limit {})", data.encoded_len(), max_size);
+                            if let Some(handle) = app_handle {
+                                let _ = handle.emit("clipboard-too-large", serde_json::json!({
+                                    "session_id": null,
+                                    "data_type": data.type_name(),
+                                    "byte_count": data.encoded_len(),
+                                    "max_size": max_size,
+                                }));
+                            }
+                        }
+                    }
                 }
             }
             protocol::clipboard::CLIPBOARD_DATA => {
                 println!("[HOST] Received clipboard data from remote");
-                // Decode and set local clipboard
-                if frame.payload.len() > 1 {
-                    if let Ok(data) = ClipboardData::decode(&frame.payload[1..]) {
+                // Decode and set local clipboard, then report the outcome back
+                let encoded = &frame.payload[1..];
+                let mut ack = ClipboardAck {
+                    success: false,
+                    data_type: encoded.first().copied().unwrap_or(0),
+                    byte_count: encoded.len() as u32,
+                    error: None,
+                };
+
+                // Checked against the limit before anything is copied out of the frame
+                match ClipboardData::decode_within(encoded, crate::clipboard::max_size()) {
+                    Ok(data) => {
                         let clipboard = ClipboardManager::new();
                         clipboard.update_hash(&data);
                         if let Err(e) = clipboard.set_clipboard(&data) {
                             eprintln!("[HOST] Failed to set clipboard: {}", e);
+                            ack.error = Some(e.to_string());
                         } else {
                             println!("[HOST] Clipboard updated from remote");
+                            ack.success = true;
                             // Notify frontend
                             if let Some(handle) = app_handle {
                                 let _ = handle.emit("clipboard-received", serde_json::json!({
-                                    "type": match &data {
-                                        ClipboardData::Text(_) => "text",
-                                        ClipboardData::Image { .. } => "image",
-                                        ClipboardData::Files(_) => "files",
-                                    }
+                                    "type": data.type_name()
                                 }));
                             }
                         }
                     }
+                    Err(e) => {
+                        eprintln!("[HOST] Invalid clipboard data: {}", e);
+                        ack.data_type = 0;
+                        ack.error = Some(e.to_string());
+                    }
                 }
+
+                viewer.link.write_frame(Frame::clipboard(protocol::clipboard::CLIPBOARD_ACK, &ack.encode())).await?;
             }
             protocol::clipboard::CLIPBOARD_CHANGED => {
                 println!("[HOST] Remote clipboard changed notification");
@@ -551,13 +2964,299 @@ impl HostSession {
         Ok(())
     }
 
+    /// Files sent to the Downloads folder; only the viewer in control may offer one
+    async fn handle_file<R: tauri::Runtime>(
+        &mut self,
+        viewer: &mut Viewer,
+        frame: &Frame,
+        app_handle: Option<&tauri::AppHandle<R>>,
+    ) -> Result<()> {
+        use crate::transfer::Message;
+
+        let message = match Message::decode(&frame.payload) {
+            Ok(message) => message,
+            Err(e) => {
+                eprintln!("[HOST] Ignoring file message from viewer {}: {}", viewer.link.id, e);
+                return Ok(());
+            }
+        };
+        if let Message::Offer { id, .. } = &message {
+            if !self.controls_input(viewer) {
+                let reason = "Only the viewer in control can send files".to_string();
+                viewer.link.write_frame(Message::Reject { id: *id, reason }.frame()).await?;
+                return Ok(());
+            }
+        }
+
+        let (answer, progress) = self.transfers.lock().handle(&crate::transfer::downloads_dir(), viewer.link.id, message);
+        if let Some(answer) = answer {
+            viewer.link.write_frame(answer.frame()).await?;
+        }
+        if let Some(progress) = progress {
+            emit_transfer_progress(app_handle, &progress);
+        }
+        Ok(())
+    }
+
+    /// Lock the workstation once per accepted session, when lock_on_disconnect is set.
+    /// Called whenever a session may have ended, so a viewer's SESSION_END followed
+    /// by a relay error or shutdown still locks once
+    fn lock_if_armed(&mut self) {
+        if !std::mem::take(&mut self.lock_armed) || !LOCK_ON_DISCONNECT.load(Ordering::Relaxed) {
+            return;
+        }
+        println!("[HOST] Session ended - locking the workstation");
+        // Off the session loop: the lock commands may take a moment to return
+        std::thread::spawn(|| {
+            if let Err(e) = crate::privacy::lock_workstation() {
+                eprintln!("[HOST] Failed to lock the workstation: {}", e);
+            }
+        });
+    }
+
     /// Stop hosting
     pub async fn stop(mut self) -> Result<()> {
         self.running = false;
         self.privacy.disable_all()?;
-        if let Some(mut stream) = self.stream.take() {
-            let _ = stream.shutdown().await;
+        self.reader = None;
+        for viewer in self.viewers.values() {
+            let _ = viewer.link.end_session().await;
         }
+        let _ = self.writer.lock().await.stream.shutdown().await;
         Ok(())
     }
 }
+
+impl Drop for HostSession {
+    /// Relay errors and shutdown drop the session without removing its viewers
+    fn drop(&mut self) {
+        self.lock_if_armed();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_deduper() {
+        let mut deduper = FrameDeduper::default();
+        assert!(!deduper.is_repeat(b"frame-a"));
+        assert!(deduper.is_repeat(b"frame-a"));
+        assert!(!deduper.is_repeat(b"frame-b"));
+
+        deduper.reset();
+        assert!(!deduper.is_repeat(b"frame-b"));
+    }
+
+    #[test]
+    fn test_capture_throttle_idles_and_wakes() {
+        let rates = (30, 2);
+        let start = Instant::now();
+        let mut throttle = CaptureThrottle::default();
+        assert_eq!(throttle.delay(start, rates), Duration::ZERO);
+
+        throttle.record(start, true);
+        assert_eq!(throttle.delay(start, rates), Duration::from_secs(1) / 30);
+
+        for _ in 0..IDLE_AFTER_UNCHANGED {
+            throttle.record(start, false);
+        }
+        assert!(throttle.is_idle());
+        assert_eq!(throttle.delay(start, rates), Duration::from_millis(500));
+        assert_eq!(throttle.delay(start + Duration::from_secs(1), rates), Duration::ZERO);
+
+        // First change ramps back up
+        throttle.record(start, true);
+        assert!(!throttle.is_idle());
+
+        for _ in 0..IDLE_AFTER_UNCHANGED {
+            throttle.record(start, false);
+        }
+        throttle.wake();
+        assert_eq!(throttle.delay(start, rates), Duration::from_secs(1) / 30);
+    }
+
+    #[test]
+    fn test_capture_health_retries_and_recovers() {
+        let start = Instant::now();
+        let mut health = CaptureHealth::default();
+        assert!(health.should_retry(start));
+
+        health.open_failed(start);
+        assert!(!health.should_retry(start + Duration::from_secs(1)));
+        assert!(health.should_retry(start + CAPTURE_RETRY_INTERVAL));
+
+        // The first good frame after a failure clears it exactly once
+        assert!(health.captured());
+        assert!(!health.captured());
+
+        for _ in 1..CAPTURE_FAILURE_LIMIT {
+            assert!(!health.capture_failed(start));
+        }
+        assert!(health.capture_failed(start));
+        assert!(!health.should_retry(start));
+    }
+
+    #[test]
+    fn test_input_control_one_controller() {
+        let mut control = InputControl::default();
+        assert!(control.grant(1).is_err());
+
+        // First accepted viewer controls; later ones watch
+        assert!(control.approve(1));
+        assert!(!control.approve(2));
+        assert!(!control.approve(3));
+        assert!(control.is_controller(1));
+
+        // A view-only viewer leaving changes nothing
+        assert_eq!(control.remove(3), None);
+        assert!(control.grant(3).is_err());
+
+        // Handing over reports who lost control; granting again is a no-op
+        assert_eq!(control.grant(2).unwrap(), Some(1));
+        assert_eq!(control.grant(2).unwrap(), None);
+
+        // The controller leaving passes control to the oldest accepted viewer
+        assert_eq!(control.remove(2), Some(1));
+        assert_eq!(control.remove(1), None);
+        assert!(!control.is_controller(1));
+        assert!(control.approve(4));
+    }
+
+    #[test]
+    fn test_input_control_view_only_start() {
+        let mut control = InputControl { view_only_start: true, ..Default::default() };
+
+        // Accepted viewers watch until the user grants control
+        assert!(!control.approve(1));
+        assert!(!control.approve(2));
+        assert!(!control.request(3));
+        assert!(control.request(2));
+        assert!(!control.request(2));
+        assert!(control.has_requested(2));
+
+        // Granting answers the request
+        assert_eq!(control.grant(2).unwrap(), None);
+        assert!(control.is_controller(2) && !control.has_requested(2));
+        assert!(!control.request(2));
+
+        // Revoking leaves nobody in control; declining a request only drops it
+        assert!(control.request(1));
+        assert!(!control.revoke(1));
+        assert!(!control.has_requested(1));
+        assert!(control.revoke(2));
+        assert!(!control.is_controller(2));
+
+        // The controller leaving doesn't hand control to anyone
+        control.grant(1).unwrap();
+        assert_eq!(control.remove(1), None);
+        assert!(!control.is_controller(2));
+    }
+
+    #[test]
+    fn test_only_the_way_in_before_accept() {
+        use protocol::control;
+        for kind in [control::HANDSHAKE, control::HELLO, control::SESSION_REQUEST, control::AUTH_PASSWORD] {
+            assert!(allowed_before_accept(kind));
+        }
+        for kind in [control::SOURCES_REQUEST, control::SELECT_MONITOR, control::FOCUS_LOCK, control::REQUEST_CONTROL, control::P2P_READY] {
+            assert!(!allowed_before_accept(kind));
+        }
+    }
+
+    #[test]
+    fn test_password_failures_lock_out() {
+        let now = Instant::now();
+        let mut failures = PasswordFailures::default();
+
+        for _ in 0..MAX_PASSWORD_FAILURES {
+            assert!(!failures.is_locked_out("111 222 333", now));
+            failures.record("111222333", now);
+        }
+        assert!(failures.is_locked_out("111222333", now));
+        assert!(!failures.is_locked_out("444555666", now));
+        assert!(!failures.is_locked_out("111222333", now + PASSWORD_FAILURE_WINDOW));
+
+        // Fresh device IDs don't buy more guesses
+        for i in 0..MAX_TOTAL_PASSWORD_FAILURES {
+            failures.record(&format!("device{}", i), now);
+        }
+        assert!(failures.is_locked_out("444555666", now));
+
+        failures.succeeded("111222333");
+        assert!(!failures.is_locked_out("111222333", now + PASSWORD_FAILURE_WINDOW));
+    }
+
+    #[test]
+    fn test_temporary_trust_expires() {
+        let now = Instant::now();
+        let mut trust = TemporaryTrust::default();
+
+        let (first, second) = ([1u8; 32], [2u8; 32]);
+
+        trust.grant(first, "111 222 333", Some(now + TEMPORARY_TRUST_HOUR));
+        trust.grant(second, "444555666", None);
+        assert!(trust.is_trusted(&first, now));
+        assert!(!trust.is_trusted(&first, now + TEMPORARY_TRUST_HOUR));
+        // Claiming a trusted device's ID is not enough without its key
+        assert!(!trust.is_trusted(&[3u8; 32], now));
+
+        // A shorter grant does not cut a longer one short
+        trust.grant(first, "111222333", Some(now + Duration::from_secs(60)));
+        assert!(trust.is_trusted(&first, now + Duration::from_secs(120)));
+
+        let later = now + Duration::from_secs(600);
+        assert_eq!(trust.list(later), vec![
+            ("111222333".to_string(), Some(TEMPORARY_TRUST_HOUR - Duration::from_secs(600))),
+            ("444555666".to_string(), None),
+        ]);
+
+        // Stopping the host ends session grants but not timed ones
+        trust.end_session(later);
+        assert!(!trust.is_trusted(&second, later));
+        assert!(trust.revoke("111222333"));
+        assert!(!trust.revoke("111222333"));
+        assert!(trust.list(later).is_empty());
+    }
+
+    #[test]
+    fn test_idle_deadline_follows_quietest_viewer() {
+        let now = Instant::now();
+        let timeout = Duration::from_secs(300);
+        let inputs = [now, now - Duration::from_secs(100), now - Duration::from_secs(50)];
+
+        assert_eq!(earliest_idle_deadline(inputs.into_iter(), Some(timeout)), Some(now + Duration::from_secs(200)));
+        assert_eq!(earliest_idle_deadline(inputs.into_iter(), None), None);
+        assert_eq!(earliest_idle_deadline(std::iter::empty(), Some(timeout)), None);
+    }
+
+    #[test]
+    fn test_pending_connections_queue() {
+        let mut pending = PendingConnections::default();
+        let (tx1, mut rx1) = mpsc::channel(1);
+        let (tx2, mut rx2) = mpsc::channel(1);
+
+        let first = pending.push("111".to_string(), [1; 32], tx1).unwrap();
+        let second = pending.push("222".to_string(), [2; 32], tx2).unwrap();
+        assert_ne!(first, second);
+
+        // Answering the second request leaves the first one waiting
+        assert_eq!(pending.respond(Some(second), true).unwrap(), ("222".to_string(), [2; 32]));
+        assert!(rx2.try_recv().unwrap());
+        assert_eq!(pending.list(), vec![(first, "111".to_string())]);
+
+        // No id answers the oldest
+        pending.respond(None, false).unwrap();
+        assert!(!rx1.try_recv().unwrap());
+        assert!(pending.respond(None, true).is_err());
+
+        // Queue is bounded
+        for _ in 0..MAX_PENDING_CONNECTIONS {
+            let (tx, _rx) = mpsc::channel(1);
+            assert!(pending.push("333".to_string(), [3; 32], tx).is_some());
+        }
+        let (tx, _rx) = mpsc::channel(1);
+        assert!(pending.push("444".to_string(), [4; 32], tx).is_none());
+    }
+}
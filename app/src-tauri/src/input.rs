@@ -4,6 +4,279 @@
 #![allow(unused_imports)]
 
 use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Maximum pointer events (moves/scrolls) accepted per second
+pub const MAX_POINTER_EVENTS_PER_SEC: u32 = 240;
+
+/// Maximum key/button presses accepted per second
+pub const MAX_PRESS_EVENTS_PER_SEC: u32 = 120;
+
+/// Maximum scroll delta (in wheel notches) accepted in a single event
+pub const MAX_SCROLL_DELTA: i32 = 10;
+
+/// High-resolution scroll units per wheel notch (same as Windows WHEEL_DELTA)
+pub const SCROLL_UNITS_PER_NOTCH: i32 = 120;
+
+/// Scroll sensitivity bounds, in percent (100 = unchanged)
+pub const MIN_SCROLL_SENSITIVITY: u32 = 10;
+pub const MAX_SCROLL_SENSITIVITY: u32 = 500;
+
+/// Longest input batching window, in milliseconds (0 = no batching)
+pub const MAX_INPUT_BATCH_MS: u32 = 50;
+
+/// Most events packed into one INPUT_BATCH frame
+pub const MAX_BATCH_EVENTS: usize = 64;
+
+/// Input event classes for rate limiting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputClass {
+    /// Mouse move or scroll
+    Pointer,
+    /// Key down or mouse button down
+    Press,
+    /// Key up or mouse button up (never dropped, to avoid stuck keys)
+    Release,
+}
+
+/// Per-session input rate limiter using a fixed one-second window
+pub struct InputRateLimiter {
+    window_start: Instant,
+    pointer_events: u32,
+    press_events: u32,
+    dropped: u64,
+}
+
+impl InputRateLimiter {
+    pub fn new() -> Self {
+        Self {
+            window_start: Instant::now(),
+            pointer_events: 0,
+            press_events: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Check whether an event of the given class may be injected now
+    pub fn allow(&mut self, class: InputClass) -> bool {
+        if self.window_start.elapsed() >= Duration::from_secs(1) {
+            self.window_start = Instant::now();
+            self.pointer_events = 0;
+            self.press_events = 0;
+        }
+
+        let (count, limit) = match class {
+            InputClass::Release => return true,
+            InputClass::Pointer => (&mut self.pointer_events, MAX_POINTER_EVENTS_PER_SEC),
+            InputClass::Press => (&mut self.press_events, MAX_PRESS_EVENTS_PER_SEC),
+        };
+
+        if *count < limit {
+            *count += 1;
+            true
+        } else {
+            self.dropped += 1;
+            false
+        }
+    }
+
+    /// Total number of events dropped since the session started
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped
+    }
+}
+
+impl Default for InputRateLimiter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Clamp a pointer position to the screen bounds
+pub fn clamp_to_screen(x: i32, y: i32, width: i32, height: i32) -> (i32, i32) {
+    (
+        x.clamp(0, (width - 1).max(0)),
+        y.clamp(0, (height - 1).max(0)),
+    )
+}
+
+/// Clamp scroll deltas to a sane number of wheel notches
+pub fn clamp_scroll(dx: i32, dy: i32) -> (i32, i32) {
+    (
+        dx.clamp(-MAX_SCROLL_DELTA, MAX_SCROLL_DELTA),
+        dy.clamp(-MAX_SCROLL_DELTA, MAX_SCROLL_DELTA),
+    )
+}
+
+/// Clamp high-resolution scroll deltas to a sane number of wheel notches
+pub fn clamp_scroll_units(dx: i32, dy: i32) -> (i32, i32) {
+    let max = MAX_SCROLL_DELTA * SCROLL_UNITS_PER_NOTCH;
+    (dx.clamp(-max, max), dy.clamp(-max, max))
+}
+
+/// Apply the user's scroll preferences to a high-resolution delta
+/// `sensitivity` is a percentage; `natural` inverts the direction (content follows fingers)
+pub fn apply_scroll_preferences(dx: i32, dy: i32, sensitivity: u32, natural: bool) -> (i32, i32) {
+    let factor = sensitivity.clamp(MIN_SCROLL_SENSITIVITY, MAX_SCROLL_SENSITIVITY) as i64;
+    let sign = if natural { -1 } else { 1 };
+    let scale = |d: i32| {
+        (d as i64 * factor / 100 * sign).clamp(i32::MIN as i64, i32::MAX as i64) as i32
+    };
+    (scale(dx), scale(dy))
+}
+
+/// Whether an input event may wait for a batch - pointer motion only, so
+/// button and key events are never delayed
+pub fn is_batchable(event: &[u8]) -> bool {
+    matches!(
+        event.first().copied(),
+        Some(crate::protocol::input::MOUSE_MOVE)
+            | Some(crate::protocol::input::MOUSE_SCROLL)
+            | Some(crate::protocol::input::MOUSE_SCROLL_PRECISE)
+    )
+}
+
+/// Pack input events into one INPUT_BATCH payload: [INPUT_BATCH]([len u8][event])*
+pub fn encode_batch(events: &[Vec<u8>]) -> Vec<u8> {
+    let mut payload = vec![crate::protocol::input::INPUT_BATCH];
+    for event in events {
+        payload.push(event.len() as u8);
+        payload.extend(event);
+    }
+    payload
+}
+
+/// Events of an INPUT_BATCH payload, in order; a truncated entry ends the batch
+pub fn decode_batch(payload: &[u8]) -> Vec<&[u8]> {
+    let mut events = Vec::new();
+    let mut rest = payload.get(1..).unwrap_or_default();
+    while let Some((&len, tail)) = rest.split_first() {
+        if tail.len() < len as usize || events.len() == MAX_BATCH_EVENTS {
+            break;
+        }
+        let (event, tail) = tail.split_at(len as usize);
+        events.push(event);
+        rest = tail;
+    }
+    events
+}
+
+/// Converts high-resolution deltas into whole wheel notches, carrying the remainder
+/// to the next event so slow trackpad scrolling is not lost or rounded up
+#[derive(Debug, Default)]
+pub struct ScrollAccumulator {
+    x: i32,
+    y: i32,
+}
+
+impl ScrollAccumulator {
+    /// Add a delta (in scroll units), returning the whole notches now due
+    pub fn push(&mut self, dx: i32, dy: i32) -> (i32, i32) {
+        fn step(acc: &mut i32, delta: i32) -> i32 {
+            // Drop a leftover from the opposite direction so reversing feels immediate
+            if delta != 0 && acc.signum() == -delta.signum() {
+                *acc = 0;
+            }
+            *acc += delta;
+            let notches = *acc / SCROLL_UNITS_PER_NOTCH;
+            *acc %= SCROLL_UNITS_PER_NOTCH;
+            notches
+        }
+
+        (step(&mut self.x, dx), step(&mut self.y, dy))
+    }
+}
+
+/// Range of SendInput absolute coordinates (0..=65535 across the target area)
+pub const ABSOLUTE_COORD_MAX: i64 = 65535;
+
+/// One display in virtual-desktop coordinates, in physical pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MonitorLayout {
+    pub left: i32,
+    pub top: i32,
+    pub width: i32,
+    pub height: i32,
+    /// Effective DPI (96 = 100% scaling)
+    pub dpi: u32,
+    pub primary: bool,
+}
+
+impl MonitorLayout {
+    /// Display scaling in percent
+    pub fn scale_percent(&self) -> u32 {
+        self.dpi * 100 / 96
+    }
+}
+
+/// The virtual desktop (bounding box of all monitors) and the captured monitor within it
+/// Frames show the captured monitor, so pointer positions are relative to its top-left
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DesktopLayout {
+    pub left: i32,
+    pub top: i32,
+    pub width: i32,
+    pub height: i32,
+    /// Bounds of the captured monitor
+    pub capture: MonitorLayout,
+}
+
+impl DesktopLayout {
+    /// Build the layout from a monitor list; the monitor at `captured` (its top-left
+    /// corner) is captured, or if there is none there the primary (or the first)
+    pub fn from_monitors(monitors: &[MonitorLayout], captured: Option<(i32, i32)>) -> Option<Self> {
+        let capture = *captured
+            .and_then(|(left, top)| monitors.iter().find(|m| (m.left, m.top) == (left, top)))
+            .or_else(|| monitors.iter().find(|m| m.primary))
+            .or_else(|| monitors.first())?;
+        let left = monitors.iter().map(|m| m.left).min()?;
+        let top = monitors.iter().map(|m| m.top).min()?;
+        let right = monitors.iter().map(|m| m.left + m.width).max()?;
+        let bottom = monitors.iter().map(|m| m.top + m.height).max()?;
+        Some(Self {
+            left,
+            top,
+            width: right - left,
+            height: bottom - top,
+            capture,
+        })
+    }
+
+    /// Layout for frames stitched from every monitor: the whole virtual
+    /// desktop is captured (at the primary monitor's DPI)
+    pub fn spanning(monitors: &[MonitorLayout]) -> Option<Self> {
+        let mut layout = Self::from_monitors(monitors, None)?;
+        layout.capture = MonitorLayout {
+            left: layout.left,
+            top: layout.top,
+            width: layout.width,
+            height: layout.height,
+            ..layout.capture
+        };
+        Some(layout)
+    }
+
+    /// Size of the captured monitor, for coordinate validation
+    pub fn capture_size(&self) -> (i32, i32) {
+        (self.capture.width, self.capture.height)
+    }
+
+    /// Map a pixel in the captured frame to absolute coordinates spanning the
+    /// whole virtual desktop (SendInput with MOUSEEVENTF_VIRTUALDESK)
+    pub fn absolute_coords(&self, x: i32, y: i32) -> (i32, i32) {
+        let (x, y) = clamp_to_screen(x, y, self.capture.width, self.capture.height);
+        let normalize = |offset: i32, extent: i32| {
+            (offset as i64 * ABSOLUTE_COORD_MAX / (extent as i64 - 1).max(1)) as i32
+        };
+        (
+            normalize(self.capture.left + x - self.left, self.width),
+            normalize(self.capture.top + y - self.top, self.height),
+        )
+    }
+}
 
 /// Lock key states for synchronization
 #[derive(Debug, Clone, Copy, Default)]
@@ -13,10 +286,115 @@ pub struct LockStates {
     pub scroll_lock: bool,
 }
 
+/// What a focus lock does before a keystroke
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FocusStep {
+    /// The target has the foreground already
+    Keep,
+    /// Make the foreground window the new target
+    Adopt,
+    /// Pull the target back to the foreground
+    Restore,
+}
+
+/// `foreground`/`target` are window handles (0 = none); `own` means the foreground
+/// window belongs to this process (the black-screen overlay)
+fn focus_step(foreground: isize, target: isize, clicked: bool, own: bool) -> FocusStep {
+    if foreground == target {
+        FocusStep::Keep
+    } else if (clicked || target == 0) && foreground != 0 && !own {
+        FocusStep::Adopt
+    } else if target == 0 {
+        FocusStep::Keep
+    } else {
+        FocusStep::Restore
+    }
+}
+
+/// Pins one viewer's keystrokes to the window it last clicked
+/// Overlays and dialogs that grab the foreground in between lose it again
+/// before the next key is injected. Only Windows reports windows; elsewhere
+/// keys go to whatever has focus, as without the lock.
+pub struct FocusLock {
+    target: isize,
+    clicked: bool,
+    /// The last restore failed (logged once until one succeeds)
+    failing: bool,
+}
+
+impl FocusLock {
+    /// Pin to the window in the foreground now
+    pub fn new() -> Self {
+        let foreground = foreground_window();
+        Self {
+            target: if is_own_window(foreground) { 0 } else { foreground },
+            clicked: false,
+            failing: false,
+        }
+    }
+
+    /// A click may have moved focus on purpose; the window holding the
+    /// foreground at the next keystroke becomes the target
+    pub fn clicked(&mut self) {
+        self.clicked = true;
+    }
+
+    /// Make sure the target has the foreground before a keystroke
+    /// Returns false if it could not be brought back (closed, or a secure desktop such as UAC is up)
+    pub fn before_key(&mut self) -> bool {
+        let foreground = foreground_window();
+        let step = focus_step(foreground, self.target, self.clicked, is_own_window(foreground));
+        self.clicked = false;
+        match step {
+            FocusStep::Keep => true,
+            FocusStep::Adopt => {
+                self.target = foreground;
+                true
+            }
+            FocusStep::Restore => {
+                let restored = focus_window(self.target);
+                if !restored && !self.failing {
+                    println!("[INPUT] Could not return focus to the pinned window");
+                }
+                self.failing = !restored;
+                restored
+            }
+        }
+    }
+}
+
+impl Default for FocusLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(not(windows))]
+fn foreground_window() -> isize {
+    0
+}
+
+#[cfg(not(windows))]
+fn is_own_window(_hwnd: isize) -> bool {
+    false
+}
+
+#[cfg(not(windows))]
+fn focus_window(_hwnd: isize) -> bool {
+    false
+}
+
 #[cfg(windows)]
 mod windows_input {
     use super::*;
     use anyhow::Result;
+    use windows::Win32::Foundation::{BOOL, FALSE, HWND, LPARAM, RECT, TRUE};
+    use windows::Win32::Graphics::Gdi::{EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFO};
+    use windows::Win32::UI::HiDpi::{
+        GetDpiForMonitor, SetProcessDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2,
+        MDT_EFFECTIVE_DPI,
+    };
+    use windows::Win32::System::Threading::{AttachThreadInput, GetCurrentProcessId, GetCurrentThreadId};
     use windows::Win32::UI::Input::KeyboardAndMouse::*;
     use windows::Win32::UI::WindowsAndMessaging::*;
 
@@ -25,24 +403,158 @@ mod windows_input {
     const VK_NUMLOCK: u16 = 0x90;
     const VK_SCROLL: u16 = 0x91;
 
+    /// How often the monitor layout is re-read (monitors can be plugged or rescaled mid-session)
+    const LAYOUT_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub(super) fn foreground_window() -> isize {
+        unsafe { GetForegroundWindow().0 }
+    }
+
+    /// Whether the window belongs to this process (the black-screen overlay)
+    pub(super) fn is_own_window(hwnd: isize) -> bool {
+        if hwnd == 0 {
+            return false;
+        }
+        let mut pid = 0u32;
+        unsafe {
+            GetWindowThreadProcessId(HWND(hwnd), Some(&mut pid));
+            pid == GetCurrentProcessId()
+        }
+    }
+
+    /// Bring a window to the foreground
+    /// Windows only lets the foreground thread's input queue hand focus over,
+    /// so attach to it for the duration of the switch
+    pub(super) fn focus_window(hwnd: isize) -> bool {
+        let hwnd = HWND(hwnd);
+        unsafe {
+            if !IsWindow(hwnd).as_bool() {
+                return false;
+            }
+            let foreground_thread = GetWindowThreadProcessId(GetForegroundWindow(), None);
+            let own_thread = GetCurrentThreadId();
+            let attached = foreground_thread != 0
+                && foreground_thread != own_thread
+                && AttachThreadInput(own_thread, foreground_thread, TRUE).as_bool();
+
+            if IsIconic(hwnd).as_bool() {
+                let _ = ShowWindow(hwnd, SW_RESTORE);
+            }
+            let _ = BringWindowToTop(hwnd);
+            let focused = SetForegroundWindow(hwnd).as_bool();
+
+            if attached {
+                let _ = AttachThreadInput(own_thread, foreground_thread, FALSE);
+            }
+            focused
+        }
+    }
+
+    /// Opt the process into per-monitor DPI awareness so monitor rects, capture sizes and
+    /// input coordinates are all physical pixels rather than per-monitor virtualized values
+    /// Fails harmlessly if awareness was already set (by the manifest or the webview)
+    pub fn enable_dpi_awareness() {
+        static ONCE: std::sync::Once = std::sync::Once::new();
+        ONCE.call_once(|| unsafe {
+            if let Err(e) = SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) {
+                println!("[INPUT] DPI awareness not changed: {}", e);
+            }
+        });
+    }
+
+    /// Enumerate monitors with their physical bounds and effective DPI
+    fn enumerate_monitors() -> Vec<MonitorLayout> {
+        unsafe extern "system" fn collect(monitor: HMONITOR, _dc: HDC, _clip: *mut RECT, data: LPARAM) -> BOOL {
+            let monitors = &mut *(data.0 as *mut Vec<MonitorLayout>);
+            let mut info = MONITORINFO {
+                cbSize: std::mem::size_of::<MONITORINFO>() as u32,
+                ..Default::default()
+            };
+            if GetMonitorInfoW(monitor, &mut info).as_bool() {
+                let (mut dpi_x, mut dpi_y) = (96, 96);
+                let _ = GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y);
+                let rect = info.rcMonitor;
+                monitors.push(MonitorLayout {
+                    left: rect.left,
+                    top: rect.top,
+                    width: rect.right - rect.left,
+                    height: rect.bottom - rect.top,
+                    dpi: dpi_x,
+                    primary: info.dwFlags & MONITORINFOF_PRIMARY != 0,
+                });
+            }
+            TRUE
+        }
+
+        let mut monitors: Vec<MonitorLayout> = Vec::new();
+        unsafe {
+            let _ = EnumDisplayMonitors(None, None, Some(collect), LPARAM(&mut monitors as *mut _ as isize));
+        }
+        monitors
+    }
+
+    /// Current desktop layout, falling back to the primary screen metrics
+    fn desktop_layout() -> DesktopLayout {
+        let monitors = enumerate_monitors();
+        let layout = if crate::capture::captures_all_monitors() {
+            DesktopLayout::spanning(&monitors)
+        } else {
+            let captured = crate::capture::selected_monitor().map(|m| (m.left, m.top));
+            DesktopLayout::from_monitors(&monitors, captured)
+        };
+        layout.unwrap_or_else(|| {
+            let (width, height) = unsafe {
+                (GetSystemMetrics(SM_CXSCREEN), GetSystemMetrics(SM_CYSCREEN))
+            };
+            let primary = MonitorLayout { left: 0, top: 0, width, height, dpi: 96, primary: true };
+            DesktopLayout { left: 0, top: 0, width, height, capture: primary }
+        })
+    }
+
     pub struct InputInjector {
-        screen_width: i32,
-        screen_height: i32,
+        layout: DesktopLayout,
+        layout_checked: Instant,
         last_mouse_x: i32,
         last_mouse_y: i32,
     }
 
     impl InputInjector {
-        pub fn new() -> Self {
-            let (w, h) = unsafe {
-                (GetSystemMetrics(SM_CXSCREEN), GetSystemMetrics(SM_CYSCREEN))
-            };
-            Self {
-                screen_width: w,
-                screen_height: h,
+        pub fn new() -> Result<Self> {
+            enable_dpi_awareness();
+            let layout = desktop_layout();
+            println!(
+                "[INPUT] Virtual desktop {}x{} at ({}, {}), captured monitor {}x{} at {}%",
+                layout.width, layout.height, layout.left, layout.top,
+                layout.capture.width, layout.capture.height, layout.capture.scale_percent()
+            );
+            Ok(Self {
+                layout,
+                layout_checked: Instant::now(),
                 last_mouse_x: 0,
                 last_mouse_y: 0,
+            })
+        }
+
+        /// Forget per-session state; the monitor layout is re-read on the next event
+        pub fn reset(&mut self) {
+            self.layout = desktop_layout();
+            self.layout_checked = Instant::now();
+            self.last_mouse_x = 0;
+            self.last_mouse_y = 0;
+        }
+
+        /// Screen size used for coordinate validation
+        pub fn screen_size(&self) -> (i32, i32) {
+            self.layout.capture_size()
+        }
+
+        /// Absolute SendInput coordinates for a frame pixel, re-reading the layout if stale
+        fn absolute(&mut self, x: i32, y: i32) -> (i32, i32) {
+            if self.layout_checked.elapsed() >= LAYOUT_REFRESH_INTERVAL {
+                self.layout = desktop_layout();
+                self.layout_checked = Instant::now();
             }
+            self.layout.absolute_coords(x, y)
         }
 
         /// Get current lock key states
@@ -96,8 +608,7 @@ mod windows_input {
             self.last_mouse_x = x;
             self.last_mouse_y = y;
 
-            let norm_x = (x * 65535) / self.screen_width;
-            let norm_y = (y * 65535) / self.screen_height;
+            let (norm_x, norm_y) = self.absolute(x, y);
 
             let input = INPUT {
                 r#type: INPUT_MOUSE,
@@ -106,7 +617,7 @@ mod windows_input {
                         dx: norm_x,
                         dy: norm_y,
                         mouseData: 0,
-                        dwFlags: MOUSEEVENTF_MOVE | MOUSEEVENTF_ABSOLUTE,
+                        dwFlags: MOUSEEVENTF_MOVE | MOUSEEVENTF_ABSOLUTE | MOUSEEVENTF_VIRTUALDESK,
                         time: 0,
                         dwExtraInfo: 0,
                     },
@@ -124,8 +635,7 @@ mod windows_input {
             self.last_mouse_x = x;
             self.last_mouse_y = y;
 
-            let norm_x = (x * 65535) / self.screen_width;
-            let norm_y = (y * 65535) / self.screen_height;
+            let (norm_x, norm_y) = self.absolute(x, y);
 
             let flags = match (button, pressed) {
                 (0, true) => MOUSEEVENTF_LEFTDOWN,
@@ -156,7 +666,7 @@ mod windows_input {
                         dx: norm_x,
                         dy: norm_y,
                         mouseData: mouse_data,
-                        dwFlags: flags | MOUSEEVENTF_MOVE | MOUSEEVENTF_ABSOLUTE,
+                        dwFlags: flags | MOUSEEVENTF_MOVE | MOUSEEVENTF_ABSOLUTE | MOUSEEVENTF_VIRTUALDESK,
                         time: 0,
                         dwExtraInfo: 0,
                     },
@@ -169,7 +679,9 @@ mod windows_input {
             Ok(())
         }
 
-        pub fn mouse_scroll(&self, dx: i32, dy: i32) -> Result<()> {
+        /// Scroll by high-resolution deltas (SCROLL_UNITS_PER_NOTCH per notch)
+        /// Windows accepts partial WHEEL_DELTA values for smooth scrolling
+        pub fn mouse_scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
             // Vertical scroll
             if dy != 0 {
                 let input = INPUT {
@@ -178,7 +690,7 @@ mod windows_input {
                         mi: MOUSEINPUT {
                             dx: 0,
                             dy: 0,
-                            mouseData: (dy * WHEEL_DELTA as i32) as u32,
+                            mouseData: dy as u32,
                             dwFlags: MOUSEEVENTF_WHEEL,
                             time: 0,
                             dwExtraInfo: 0,
@@ -199,7 +711,7 @@ mod windows_input {
                         mi: MOUSEINPUT {
                             dx: 0,
                             dy: 0,
-                            mouseData: (dx * WHEEL_DELTA as i32) as u32,
+                            mouseData: dx as u32,
                             dwFlags: MOUSEEVENTF_HWHEEL,
                             time: 0,
                             dwExtraInfo: 0,
@@ -311,7 +823,9 @@ mod windows_input {
 }
 
 #[cfg(windows)]
-pub use windows_input::InputInjector;
+pub use windows_input::{enable_dpi_awareness, InputInjector};
+#[cfg(windows)]
+use windows_input::{focus_window, foreground_window, is_own_window};
 
 #[cfg(target_os = "macos")]
 mod macos_input {
@@ -323,7 +837,77 @@ mod macos_input {
     };
     use core_graphics::event_source::{CGEventSource, CGEventSourceStateID};
 
+    /// Pixels scrolled per wheel notch (roughly one notch of a classic mouse)
+    const MAC_PIXELS_PER_NOTCH: i32 = 40;
+
+    /// kIOHIDParamConnectType
+    const HID_PARAM_CONNECT_TYPE: u32 = 1;
+    /// kIOHIDCapsLockState
+    const HID_CAPS_LOCK_STATE: i32 = 1;
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IOServiceMatching(name: *const libc::c_char) -> *mut libc::c_void;
+        fn IOServiceGetMatchingService(main_port: u32, matching: *mut libc::c_void) -> u32;
+        fn IOServiceOpen(service: u32, owning_task: u32, connect_type: u32, connect: *mut u32) -> i32;
+        fn IOServiceClose(connect: u32) -> i32;
+        fn IOObjectRelease(object: u32) -> i32;
+        fn IOHIDGetModifierLockState(connect: u32, selector: i32, state: *mut bool) -> i32;
+        fn IOHIDSetModifierLockState(connect: u32, selector: i32, state: bool) -> i32;
+    }
+
+    extern "C" {
+        static mach_task_self_: u32;
+    }
+
+    /// A connection to the IOHIDSystem service, which owns the CapsLock state
+    /// (and its LED); a synthetic CapsLock keypress does not toggle it
+    struct HidSystem(u32);
+
+    impl HidSystem {
+        fn open() -> Result<Self> {
+            unsafe {
+                let service = IOServiceGetMatchingService(0, IOServiceMatching(c"IOHIDSystem".as_ptr()));
+                if service == 0 {
+                    anyhow::bail!("IOHIDSystem service not found");
+                }
+                let mut connect = 0;
+                let result = IOServiceOpen(service, mach_task_self_, HID_PARAM_CONNECT_TYPE, &mut connect);
+                IOObjectRelease(service);
+                if result != 0 {
+                    anyhow::bail!("Failed to open IOHIDSystem: {:#x}", result);
+                }
+                Ok(Self(connect))
+            }
+        }
+
+        fn caps_lock(&self) -> Result<bool> {
+            let mut state = false;
+            let result = unsafe { IOHIDGetModifierLockState(self.0, HID_CAPS_LOCK_STATE, &mut state) };
+            if result != 0 {
+                anyhow::bail!("Failed to read CapsLock state: {:#x}", result);
+            }
+            Ok(state)
+        }
+
+        fn set_caps_lock(&self, on: bool) -> Result<()> {
+            let result = unsafe { IOHIDSetModifierLockState(self.0, HID_CAPS_LOCK_STATE, on) };
+            if result != 0 {
+                anyhow::bail!("Failed to set CapsLock state: {:#x}", result);
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for HidSystem {
+        fn drop(&mut self) {
+            unsafe { IOServiceClose(self.0) };
+        }
+    }
+
     pub struct InputInjector {
+        /// Global position of the captured display's top-left corner
+        origin: (i32, i32),
         screen_width: i32,
         screen_height: i32,
         last_mouse_x: i32,
@@ -331,33 +915,75 @@ mod macos_input {
         event_source: CGEventSource,
     }
 
+    /// Position and size of what is captured: the selected display, all of
+    /// them stitched, or the main display
+    fn captured_display() -> (i32, i32, i32, i32) {
+        if let Some((left, top, width, height)) = crate::capture::captured_bounds() {
+            return (left, top, width as i32, height as i32);
+        }
+        let display = CGDisplay::new(unsafe { CGMainDisplayID() });
+        (0, 0, display.pixels_wide() as i32, display.pixels_high() as i32)
+    }
+
+    // The event source is only used behind the shared injector's mutex
+    unsafe impl Send for InputInjector {}
+
     impl InputInjector {
-        pub fn new() -> Self {
-            let display_id = unsafe { CGMainDisplayID() };
-            let display = CGDisplay::new(display_id);
-            let w = display.pixels_wide() as i32;
-            let h = display.pixels_high() as i32;
+        pub fn new() -> Result<Self> {
+            let (left, top, w, h) = captured_display();
 
             let event_source = CGEventSource::new(CGEventSourceStateID::HIDSystemState)
-                .expect("Failed to create event source");
+                .map_err(|_| anyhow::anyhow!("Failed to create event source"))?;
 
-            Self {
+            Ok(Self {
+                origin: (left, top),
                 screen_width: w,
                 screen_height: h,
                 last_mouse_x: 0,
                 last_mouse_y: 0,
                 event_source,
-            }
+            })
+        }
+
+        /// Forget per-session state, picking up display changes since the last session
+        pub fn reset(&mut self) {
+            let (left, top, width, height) = captured_display();
+            self.origin = (left, top);
+            self.screen_width = width;
+            self.screen_height = height;
+            self.last_mouse_x = 0;
+            self.last_mouse_y = 0;
+        }
+
+        /// Screen size used for coordinate validation
+        pub fn screen_size(&self) -> (i32, i32) {
+            (self.screen_width, self.screen_height)
         }
 
+        /// Global position of a pixel in the captured frame
+        fn point(&self, x: i32, y: i32) -> core_graphics::geometry::CGPoint {
+            core_graphics::geometry::CGPoint::new((self.origin.0 + x) as f64, (self.origin.1 + y) as f64)
+        }
+
+        /// Get current lock key states
+        /// Macs have no NumLock or ScrollLock, so those are always off
         pub fn get_lock_states(&self) -> LockStates {
-            // macOS doesn't have NumLock/ScrollLock in the same way
-            // CapsLock state can be detected but requires IOKit
-            LockStates::default()
+            let caps_lock = match HidSystem::open().and_then(|hid| hid.caps_lock()) {
+                Ok(on) => on,
+                Err(e) => {
+                    eprintln!("[INPUT] {}", e);
+                    false
+                }
+            };
+            LockStates { caps_lock, ..LockStates::default() }
         }
 
-        pub fn sync_lock_states(&self, _remote_states: LockStates) -> Result<()> {
-            // Not implemented for macOS
+        /// Synchronize CapsLock with remote
+        pub fn sync_lock_states(&self, remote_states: LockStates) -> Result<()> {
+            let hid = HidSystem::open()?;
+            if hid.caps_lock()? != remote_states.caps_lock {
+                hid.set_caps_lock(remote_states.caps_lock)?;
+            }
             Ok(())
         }
 
@@ -371,7 +997,7 @@ mod macos_input {
             self.last_mouse_x = x;
             self.last_mouse_y = y;
 
-            let point = core_graphics::geometry::CGPoint::new(x as f64, y as f64);
+            let point = self.point(x, y);
 
             if let Ok(event) = CGEvent::new_mouse_event(
                 self.event_source.clone(),
@@ -389,7 +1015,7 @@ mod macos_input {
             self.last_mouse_x = x;
             self.last_mouse_y = y;
 
-            let point = core_graphics::geometry::CGPoint::new(x as f64, y as f64);
+            let point = self.point(x, y);
 
             let (event_type, mouse_button) = match (button, pressed) {
                 (0, true) => (CGEventType::LeftMouseDown, CGMouseButton::Left),
@@ -413,13 +1039,16 @@ mod macos_input {
             Ok(())
         }
 
-        pub fn mouse_scroll(&self, dx: i32, dy: i32) -> Result<()> {
+        /// Scroll by high-resolution deltas (SCROLL_UNITS_PER_NOTCH per notch)
+        /// Pixel scroll events give smooth scrolling in every Cocoa view
+        pub fn mouse_scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+            let to_pixels = |d: i32| d * MAC_PIXELS_PER_NOTCH / SCROLL_UNITS_PER_NOTCH;
             if let Ok(event) = CGEvent::new_scroll_event(
                 self.event_source.clone(),
                 ScrollEventUnit::PIXEL,
                 2, // wheel_count
-                dy,
-                dx,
+                to_pixels(dy),
+                to_pixels(dx),
                 0,
             ) {
                 event.post(CGEventTapLocation::HID);
@@ -429,7 +1058,7 @@ mod macos_input {
 
         pub fn key_event(&self, key_code: u16, pressed: bool) -> Result<()> {
             // Convert Windows virtual key to macOS key code
-            let mac_keycode = self.windows_vk_to_mac(key_code);
+            let mac_keycode = crate::keymap::mac_keycode(key_code);
 
             if let Ok(event) = CGEvent::new_keyboard_event(
                 self.event_source.clone(),
@@ -467,92 +1096,6 @@ mod macos_input {
             }
             Ok(())
         }
-
-        // Convert Windows virtual key codes to macOS key codes
-        fn windows_vk_to_mac(&self, vk: u16) -> u16 {
-            match vk {
-                // Letters A-Z (0x41-0x5A)
-                0x41 => 0x00, // A
-                0x42 => 0x0B, // B
-                0x43 => 0x08, // C
-                0x44 => 0x02, // D
-                0x45 => 0x0E, // E
-                0x46 => 0x03, // F
-                0x47 => 0x05, // G
-                0x48 => 0x04, // H
-                0x49 => 0x22, // I
-                0x4A => 0x26, // J
-                0x4B => 0x28, // K
-                0x4C => 0x25, // L
-                0x4D => 0x2E, // M
-                0x4E => 0x2D, // N
-                0x4F => 0x1F, // O
-                0x50 => 0x23, // P
-                0x51 => 0x0C, // Q
-                0x52 => 0x0F, // R
-                0x53 => 0x01, // S
-                0x54 => 0x11, // T
-                0x55 => 0x20, // U
-                0x56 => 0x09, // V
-                0x57 => 0x0D, // W
-                0x58 => 0x07, // X
-                0x59 => 0x10, // Y
-                0x5A => 0x06, // Z
-
-                // Numbers 0-9 (0x30-0x39)
-                0x30 => 0x1D, // 0
-                0x31 => 0x12, // 1
-                0x32 => 0x13, // 2
-                0x33 => 0x14, // 3
-                0x34 => 0x15, // 4
-                0x35 => 0x17, // 5
-                0x36 => 0x16, // 6
-                0x37 => 0x1A, // 7
-                0x38 => 0x1C, // 8
-                0x39 => 0x19, // 9
-
-                // Function keys
-                0x70 => 0x7A, // F1
-                0x71 => 0x78, // F2
-                0x72 => 0x63, // F3
-                0x73 => 0x76, // F4
-                0x74 => 0x60, // F5
-                0x75 => 0x61, // F6
-                0x76 => 0x62, // F7
-                0x77 => 0x64, // F8
-                0x78 => 0x65, // F9
-                0x79 => 0x6D, // F10
-                0x7A => 0x67, // F11
-                0x7B => 0x6F, // F12
-
-                // Special keys
-                0x08 => 0x33, // Backspace
-                0x09 => 0x30, // Tab
-                0x0D => 0x24, // Enter
-                0x10 => 0x38, // Shift
-                0x11 => 0x3B, // Control
-                0x12 => 0x3A, // Alt/Option
-                0x14 => 0x39, // CapsLock
-                0x1B => 0x35, // Escape
-                0x20 => 0x31, // Space
-
-                // Arrow keys
-                0x25 => 0x7B, // Left
-                0x26 => 0x7E, // Up
-                0x27 => 0x7C, // Right
-                0x28 => 0x7D, // Down
-
-                // Other keys
-                0x2E => 0x75, // Delete
-                0x24 => 0x73, // Home
-                0x23 => 0x77, // End
-                0x21 => 0x74, // PageUp
-                0x22 => 0x79, // PageDown
-
-                // Default: pass through
-                _ => vk,
-            }
-        }
     }
 }
 
@@ -569,40 +1112,68 @@ mod linux_input {
 
     pub struct InputInjector {
         display: *mut Display,
+        /// Root window position of the captured monitor's top-left corner
+        origin: (i32, i32),
         screen_width: i32,
         screen_height: i32,
         last_mouse_x: i32,
         last_mouse_y: i32,
+        scroll: ScrollAccumulator,
     }
 
     // Display pointer is thread-safe for our use case
     unsafe impl Send for InputInjector {}
     unsafe impl Sync for InputInjector {}
 
+    /// Position and size of the selected monitor, all of them, or the whole screen
+    unsafe fn captured_area(display: *mut Display) -> (i32, i32, i32, i32) {
+        if let Some((left, top, width, height)) = crate::capture::captured_bounds() {
+            return (left, top, width as i32, height as i32);
+        }
+        let screen = XDefaultScreen(display);
+        (0, 0, XDisplayWidth(display, screen), XDisplayHeight(display, screen))
+    }
+
     impl InputInjector {
-        pub fn new() -> Self {
+        pub fn new() -> Result<Self> {
             unsafe {
                 let display = XOpenDisplay(ptr::null());
                 if display.is_null() {
-                    panic!("Failed to open X11 display for input injection");
+                    anyhow::bail!("Failed to open X11 display for input injection");
                 }
 
-                let screen = XDefaultScreen(display);
-                let w = XDisplayWidth(display, screen);
-                let h = XDisplayHeight(display, screen);
+                let (left, top, w, h) = captured_area(display);
 
                 println!("[INPUT] Linux X11 input ready: {}x{}", w, h);
 
-                Self {
+                Ok(Self {
                     display,
+                    origin: (left, top),
                     screen_width: w,
                     screen_height: h,
                     last_mouse_x: 0,
                     last_mouse_y: 0,
-                }
+                    scroll: ScrollAccumulator::default(),
+                })
             }
         }
 
+        /// Forget per-session state, picking up a screen resize since the last session
+        pub fn reset(&mut self) {
+            let (left, top, width, height) = unsafe { captured_area(self.display) };
+            self.origin = (left, top);
+            self.screen_width = width;
+            self.screen_height = height;
+            self.last_mouse_x = 0;
+            self.last_mouse_y = 0;
+            self.scroll = ScrollAccumulator::default();
+        }
+
+        /// Screen size used for coordinate validation
+        pub fn screen_size(&self) -> (i32, i32) {
+            (self.screen_width, self.screen_height)
+        }
+
         pub fn get_lock_states(&self) -> LockStates {
             unsafe {
                 let mut state: XKeyboardState = std::mem::zeroed();
@@ -660,7 +1231,7 @@ mod linux_input {
             self.last_mouse_y = y;
 
             unsafe {
-                XTestFakeMotionEvent(self.display, -1, x, y, 0);
+                XTestFakeMotionEvent(self.display, -1, self.origin.0 + x, self.origin.1 + y, 0);
                 XFlush(self.display);
             }
             Ok(())
@@ -672,7 +1243,7 @@ mod linux_input {
 
             // First move to position
             unsafe {
-                XTestFakeMotionEvent(self.display, -1, x, y, 0);
+                XTestFakeMotionEvent(self.display, -1, self.origin.0 + x, self.origin.1 + y, 0);
             }
 
             // X11 button mapping:
@@ -694,7 +1265,16 @@ mod linux_input {
             Ok(())
         }
 
-        pub fn mouse_scroll(&self, dx: i32, dy: i32) -> Result<()> {
+        /// Scroll by high-resolution deltas (SCROLL_UNITS_PER_NOTCH per notch)
+        ///
+        /// XTest can only fake wheel button clicks (no XI2 smooth-scroll valuators),
+        /// so partial notches are accumulated and emitted as clicks once complete.
+        pub fn mouse_scroll(&mut self, dx: i32, dy: i32) -> Result<()> {
+            let (dx, dy) = self.scroll.push(dx, dy);
+            if dx == 0 && dy == 0 {
+                return Ok(());
+            }
+
             unsafe {
                 // Vertical scroll
                 if dy != 0 {
@@ -723,11 +1303,14 @@ mod linux_input {
 
         pub fn key_event(&self, key_code: u16, pressed: bool) -> Result<()> {
             unsafe {
-                // Convert Windows VK to X11 keysym, then to keycode
-                let keysym = self.windows_vk_to_x11_keysym(key_code);
-                let keycode = XKeysymToKeycode(self.display, keysym);
-
-                if keycode != 0 {
+                // Convert Windows VK to X11 keysym, then to keycode; the first
+                // keysym the current layout can type wins (dead keys as fallback)
+                let keycode = crate::keymap::x11_keysyms(key_code)
+                    .into_iter()
+                    .map(|keysym| XKeysymToKeycode(self.display, keysym as KeySym))
+                    .find(|&keycode| keycode != 0);
+
+                if let Some(keycode) = keycode {
                     XTestFakeKeyEvent(self.display, keycode as u32, if pressed { 1 } else { 0 }, 0);
                     XFlush(self.display);
                 }
@@ -759,102 +1342,6 @@ mod linux_input {
             }
             Ok(())
         }
-
-        // Convert Windows virtual key codes to X11 keysyms
-        fn windows_vk_to_x11_keysym(&self, vk: u16) -> u64 {
-            match vk {
-                // Letters A-Z (0x41-0x5A) - lowercase keysyms
-                0x41..=0x5A => (vk as u64) + 0x20, // 'a' = 0x61
-
-                // Numbers 0-9 (0x30-0x39)
-                0x30..=0x39 => vk as u64,
-
-                // Function keys F1-F12
-                0x70 => 0xFFBE, // F1
-                0x71 => 0xFFBF, // F2
-                0x72 => 0xFFC0, // F3
-                0x73 => 0xFFC1, // F4
-                0x74 => 0xFFC2, // F5
-                0x75 => 0xFFC3, // F6
-                0x76 => 0xFFC4, // F7
-                0x77 => 0xFFC5, // F8
-                0x78 => 0xFFC6, // F9
-                0x79 => 0xFFC7, // F10
-                0x7A => 0xFFC8, // F11
-                0x7B => 0xFFC9, // F12
-
-                // Special keys
-                0x08 => 0xFF08, // Backspace
-                0x09 => 0xFF09, // Tab
-                0x0D => 0xFF0D, // Enter/Return
-                0x10 => 0xFFE1, // Shift_L
-                0x11 => 0xFFE3, // Control_L
-                0x12 => 0xFFE9, // Alt_L
-                0x14 => 0xFFE5, // CapsLock
-                0x1B => 0xFF1B, // Escape
-                0x20 => 0x0020, // Space
-
-                // Arrow keys
-                0x25 => 0xFF51, // Left
-                0x26 => 0xFF52, // Up
-                0x27 => 0xFF53, // Right
-                0x28 => 0xFF54, // Down
-
-                // Navigation keys
-                0x2E => 0xFFFF, // Delete
-                0x2D => 0xFF63, // Insert
-                0x24 => 0xFF50, // Home
-                0x23 => 0xFF57, // End
-                0x21 => 0xFF55, // PageUp
-                0x22 => 0xFF56, // PageDown
-
-                // Numpad
-                0x60 => 0xFFB0, // Numpad 0
-                0x61 => 0xFFB1, // Numpad 1
-                0x62 => 0xFFB2, // Numpad 2
-                0x63 => 0xFFB3, // Numpad 3
-                0x64 => 0xFFB4, // Numpad 4
-                0x65 => 0xFFB5, // Numpad 5
-                0x66 => 0xFFB6, // Numpad 6
-                0x67 => 0xFFB7, // Numpad 7
-                0x68 => 0xFFB8, // Numpad 8
-                0x69 => 0xFFB9, // Numpad 9
-                0x6A => 0xFFAA, // Multiply
-                0x6B => 0xFFAB, // Add
-                0x6D => 0xFFAD, // Subtract
-                0x6E => 0xFFAE, // Decimal
-                0x6F => 0xFFAF, // Divide
-
-                // Windows keys
-                0x5B => 0xFFEB, // Left Windows/Super
-                0x5C => 0xFFEC, // Right Windows/Super
-                0x5D => 0xFF67, // Menu
-
-                // Lock keys
-                0x90 => 0xFF7F, // NumLock
-                0x91 => 0xFF14, // ScrollLock
-
-                // Punctuation
-                0xBA => 0x003B, // Semicolon
-                0xBB => 0x003D, // Equals
-                0xBC => 0x002C, // Comma
-                0xBD => 0x002D, // Minus
-                0xBE => 0x002E, // Period
-                0xBF => 0x002F, // Slash
-                0xC0 => 0x0060, // Backtick
-                0xDB => 0x005B, // Left bracket
-                0xDC => 0x005C, // Backslash
-                0xDD => 0x005D, // Right bracket
-                0xDE => 0x0027, // Quote
-
-                // Print Screen, Pause
-                0x2C => 0xFF61, // PrintScreen
-                0x13 => 0xFF13, // Pause
-
-                // Default: pass through as-is
-                _ => vk as u64,
-            }
-        }
     }
 
     impl Drop for InputInjector {
@@ -877,8 +1364,14 @@ pub struct InputInjector;
 
 #[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
 impl InputInjector {
-    pub fn new() -> Self {
-        Self
+    pub fn new() -> Result<Self> {
+        Ok(Self)
+    }
+
+    pub fn reset(&mut self) {}
+
+    pub fn screen_size(&self) -> (i32, i32) {
+        (1920, 1080)
     }
 
     pub fn get_lock_states(&self) -> LockStates {
@@ -897,7 +1390,7 @@ impl InputInjector {
         Ok(())
     }
 
-    pub fn mouse_scroll(&self, _dx: i32, _dy: i32) -> Result<()> {
+    pub fn mouse_scroll(&mut self, _dx: i32, _dy: i32) -> Result<()> {
         Ok(())
     }
 
@@ -913,3 +1406,145 @@ impl InputInjector {
         Ok(())
     }
 }
+
+/// Input injector shared by every host session in the process
+pub type SharedInputInjector = Arc<Mutex<InputInjector>>;
+
+static SHARED_INJECTOR: Lazy<Mutex<Option<SharedInputInjector>>> = Lazy::new(|| Mutex::new(None));
+
+/// The process-wide injector, created on first use
+/// Opening the display / event source is expensive and leaks if repeated on
+/// every reconnect, so sessions share one and call `reset()` instead.
+/// A failed open is not cached - the next session tries again.
+pub fn shared_injector() -> Result<SharedInputInjector> {
+    let mut shared = SHARED_INJECTOR.lock();
+    if let Some(injector) = shared.as_ref() {
+        return Ok(injector.clone());
+    }
+    let injector = Arc::new(Mutex::new(InputInjector::new()?));
+    *shared = Some(injector.clone());
+    Ok(injector)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_drops_floods() {
+        let mut limiter = InputRateLimiter::new();
+
+        // Flood with mouse moves - only the per-second budget gets through
+        let allowed = (0..10_000)
+            .filter(|_| limiter.allow(InputClass::Pointer))
+            .count() as u32;
+        assert_eq!(allowed, MAX_POINTER_EVENTS_PER_SEC);
+        assert_eq!(limiter.dropped_count(), (10_000 - MAX_POINTER_EVENTS_PER_SEC) as u64);
+
+        // Presses have a separate budget, releases are never dropped
+        assert!(limiter.allow(InputClass::Press));
+        for _ in 0..1000 {
+            assert!(limiter.allow(InputClass::Release));
+        }
+    }
+
+    #[test]
+    fn test_input_batch_round_trip() {
+        use crate::protocol::input::{KEY_DOWN, MOUSE_MOVE};
+
+        let events = vec![vec![MOUSE_MOVE, 1, 0, 0, 0, 2, 0, 0, 0], vec![MOUSE_MOVE, 3, 0, 0, 0, 4, 0, 0, 0]];
+        let payload = encode_batch(&events);
+        assert_eq!(decode_batch(&payload), vec![&events[0][..], &events[1][..]]);
+        assert!(is_batchable(&events[0]));
+        assert!(!is_batchable(&[KEY_DOWN, 0x41, 0, 0]));
+
+        // A truncated trailing entry is dropped, the rest still replays
+        assert_eq!(decode_batch(&payload[..payload.len() - 3]), vec![&events[0][..]]);
+    }
+
+    #[test]
+    fn test_out_of_range_coordinates() {
+        assert_eq!(clamp_to_screen(-500, 99_999, 1920, 1080), (0, 1079));
+        assert_eq!(clamp_to_screen(i32::MAX, i32::MIN, 1920, 1080), (1919, 0));
+        assert_eq!(clamp_to_screen(100, 200, 1920, 1080), (100, 200));
+
+        assert_eq!(clamp_scroll(i32::MAX, -1), (MAX_SCROLL_DELTA, -1));
+        assert_eq!(clamp_scroll(0, i32::MIN), (0, -MAX_SCROLL_DELTA));
+    }
+
+    #[test]
+    fn test_scroll_preferences() {
+        assert_eq!(apply_scroll_preferences(120, -60, 100, false), (120, -60));
+        assert_eq!(apply_scroll_preferences(120, -60, 200, true), (-240, 120));
+        // Sensitivity is bounded
+        assert_eq!(apply_scroll_preferences(0, 120, 0, false), (0, 12));
+        assert_eq!(apply_scroll_preferences(0, i32::MAX, 500, false), (0, i32::MAX));
+    }
+
+    #[test]
+    fn test_scroll_accumulator() {
+        let mut acc = ScrollAccumulator::default();
+
+        // Small trackpad deltas add up to whole notches
+        assert_eq!(acc.push(0, 50), (0, 0));
+        assert_eq!(acc.push(0, 50), (0, 0));
+        assert_eq!(acc.push(0, 50), (0, 1));
+        assert_eq!(acc.push(0, 360), (0, 3));
+
+        // Reversing direction discards the leftover
+        assert_eq!(acc.push(0, 60), (0, 0));
+        assert_eq!(acc.push(0, -120), (0, -1));
+        assert_eq!(acc.push(-240, 0), (-2, 0));
+    }
+
+    #[test]
+    fn test_focus_lock_steps() {
+        // Something else took the foreground: bring the target back
+        assert_eq!(focus_step(7, 5, false, false), FocusStep::Restore);
+        assert_eq!(focus_step(9, 5, true, true), FocusStep::Restore);
+        // A click moved focus on purpose, or nothing was pinned yet
+        assert_eq!(focus_step(7, 5, true, false), FocusStep::Adopt);
+        assert_eq!(focus_step(7, 0, false, false), FocusStep::Adopt);
+        // Never pin to our own overlay, or to no window at all
+        assert_eq!(focus_step(9, 0, true, true), FocusStep::Keep);
+        assert_eq!(focus_step(0, 0, true, false), FocusStep::Keep);
+        assert_eq!(focus_step(5, 5, true, false), FocusStep::Keep);
+    }
+
+    #[test]
+    fn test_mixed_dpi_desktop_layout() {
+        // 4K primary at 150%, with a 100% 1080p monitor to its left, lower down
+        let monitors = [
+            MonitorLayout { left: -1920, top: 540, width: 1920, height: 1080, dpi: 96, primary: false },
+            MonitorLayout { left: 0, top: 0, width: 3840, height: 2160, dpi: 144, primary: true },
+        ];
+        let layout = DesktopLayout::from_monitors(&monitors, None).unwrap();
+        assert_eq!((layout.left, layout.top, layout.width, layout.height), (-1920, 0, 5760, 2160));
+        assert_eq!(layout.capture_size(), (3840, 2160));
+        assert_eq!(layout.capture.scale_percent(), 150);
+
+        // Frame pixels are offset into the virtual desktop, not scaled to the primary alone
+        assert_eq!(layout.absolute_coords(0, 0), (21848, 0));
+        assert_eq!(layout.absolute_coords(3839, 2159), (65535, 65535));
+        // Out-of-frame positions stay on the captured monitor
+        assert_eq!(layout.absolute_coords(-100, 5000), (21848, 65535));
+
+        // Single monitor: the captured frame spans the whole absolute range
+        let single = DesktopLayout::from_monitors(&monitors[1..], None).unwrap();
+        assert_eq!(single.absolute_coords(1920, 1080), (32776, 32782));
+        assert!(DesktopLayout::from_monitors(&[], None).is_none());
+
+        // The viewer switched to the left monitor; an unplugged one falls back to the primary
+        let left = DesktopLayout::from_monitors(&monitors, Some((-1920, 540))).unwrap();
+        assert_eq!(left.capture_size(), (1920, 1080));
+        assert_eq!(left.absolute_coords(0, 0), (0, 16391));
+        let gone = DesktopLayout::from_monitors(&monitors, Some((3840, 0))).unwrap();
+        assert_eq!(gone.capture, monitors[1]);
+
+        // All monitors stitched: the frame is the whole virtual desktop
+        let all = DesktopLayout::spanning(&monitors).unwrap();
+        assert_eq!(all.capture_size(), (5760, 2160));
+        assert_eq!(all.absolute_coords(0, 0), (0, 0));
+        assert_eq!(all.absolute_coords(5759, 2159), (65535, 65535));
+    }
+}
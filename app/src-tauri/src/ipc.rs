@@ -0,0 +1,282 @@
+//! Local control socket - lets `securedesk input ...` drive a running instance
+//!
+//! Off by default. When enabled the GUI process listens on 127.0.0.1 on a
+//! random port and writes the port and a fresh random token to `ipc.json`
+//! in the config directory, readable by the current user only. Every
+//! request must carry that token. One JSON request line per connection,
+//! answered with one JSON response line; see shared/proto/automation.md.
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::future::Future;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Longest request line accepted
+const MAX_REQUEST_LEN: u64 = 64 * 1024;
+
+/// How long a connection may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the CLI waits for the running instance
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One request: the token from `ipc.json` and what to do
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    pub token: String,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    /// List the client sessions of the running instance
+    Sessions,
+    /// Send one input action to a session (None = the active session)
+    Input {
+        #[serde(default)]
+        session: Option<String>,
+        action: InputAction,
+    },
+}
+
+/// Input actions, in remote screen coordinates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputAction {
+    Move { x: i32, y: i32 },
+    /// Move, press and release; button 0 = left, 1 = right, 2 = middle
+    Click { x: i32, y: i32, #[serde(default)] button: u8 },
+    /// Deltas in wheel notches
+    Scroll { dx: i32, dy: i32 },
+    /// Press and release a Windows virtual key
+    Key { code: u16 },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<serde_json::Value>,
+}
+
+impl Response {
+    fn from_result(result: std::result::Result<serde_json::Value, String>) -> Self {
+        match result {
+            Ok(value) => Self { ok: true, error: None, result: (!value.is_null()).then_some(value) },
+            Err(error) => Self { ok: false, error: Some(error), result: None },
+        }
+    }
+}
+
+/// Where a running instance can be reached
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Endpoint {
+    port: u16,
+    token: String,
+}
+
+type HandlerFuture = Pin<Box<dyn Future<Output = std::result::Result<serde_json::Value, String>> + Send>>;
+
+/// Runs commands against the app state; set once by the GUI process
+pub type Handler = Arc<dyn Fn(Command) -> HandlerFuture + Send + Sync>;
+
+static HANDLER: Lazy<Mutex<Option<Handler>>> = Lazy::new(|| Mutex::new(None));
+
+static SERVER: Lazy<Mutex<Option<tauri::async_runtime::JoinHandle<()>>>> = Lazy::new(|| Mutex::new(None));
+
+/// Install what answers commands; the socket only starts once there is one
+pub fn set_handler<F>(handler: F)
+where
+    F: Fn(Command) -> HandlerFuture + Send + Sync + 'static,
+{
+    *HANDLER.lock() = Some(Arc::new(handler));
+}
+
+/// Start or stop the control socket to match the setting
+pub fn configure(enabled: bool) {
+    let mut server = SERVER.lock();
+    if enabled == server.is_some() {
+        return;
+    }
+
+    if let Some(handle) = server.take() {
+        handle.abort();
+        if let Ok(path) = endpoint_path() {
+            let _ = fs::remove_file(path);
+        }
+        println!("[IPC] Control socket stopped");
+        return;
+    }
+
+    let Some(handler) = HANDLER.lock().clone() else {
+        return;
+    };
+    *server = Some(tauri::async_runtime::spawn(serve(handler)));
+}
+
+async fn serve(handler: Handler) {
+    let listener = match TcpListener::bind(("127.0.0.1", 0)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("[IPC] Failed to open control socket: {}", e);
+            return;
+        }
+    };
+    let endpoint = match listener.local_addr().map_err(anyhow::Error::from).and_then(|addr| publish(addr.port())) {
+        Ok(endpoint) => Arc::new(endpoint),
+        Err(e) => {
+            eprintln!("[IPC] Failed to publish control socket: {}", e);
+            return;
+        }
+    };
+    println!("[IPC] Control socket on 127.0.0.1:{}", endpoint.port);
+
+    loop {
+        let Ok((stream, _)) = listener.accept().await else { continue };
+        let handler = handler.clone();
+        let endpoint = endpoint.clone();
+        tokio::spawn(async move {
+            if let Err(e) = answer(stream, &endpoint.token, handler).await {
+                eprintln!("[IPC] Request failed: {}", e);
+            }
+        });
+    }
+}
+
+/// Answer the one request of a connection
+async fn answer(mut stream: TcpStream, token: &str, handler: Handler) -> Result<()> {
+    let (read, mut write) = stream.split();
+    let mut line = String::new();
+    let mut reader = tokio::io::BufReader::new(read.take(MAX_REQUEST_LEN));
+    tokio::time::timeout(REQUEST_TIMEOUT, reader.read_line(&mut line))
+        .await
+        .map_err(|_| anyhow!("timed out"))??;
+
+    let response = match serde_json::from_str::<Request>(&line) {
+        Err(e) => Response::from_result(Err(format!("Invalid request: {}", e))),
+        Ok(request) if !token_matches(&request.token, token) => {
+            Response::from_result(Err("Invalid token".to_string()))
+        }
+        Ok(request) => Response::from_result(handler(request.command).await),
+    };
+
+    let mut out = serde_json::to_vec(&response)?;
+    out.push(b'\n');
+    write.write_all(&out).await?;
+    write.shutdown().await?;
+    Ok(())
+}
+
+/// Compare without stopping at the first differing byte
+fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given.bytes().zip(expected.bytes()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Write a fresh token and the port for the CLI to find
+fn publish(port: u16) -> Result<Endpoint> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes)?;
+    let endpoint = Endpoint {
+        port,
+        token: bytes.iter().map(|b| format!("{:02x}", b)).collect(),
+    };
+
+    let path = endpoint_path()?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Created private beside it and moved into place: the token is never
+    // readable by others, not even while it is written, and a file left by an
+    // older version is replaced rather than kept with its permissions
+    let temp = path.with_extension("json.tmp");
+    let _ = fs::remove_file(&temp);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&temp)?;
+    file.write_all(&serde_json::to_vec(&endpoint)?)?;
+    drop(file);
+    fs::rename(&temp, &path)?;
+    Ok(endpoint)
+}
+
+fn endpoint_path() -> Result<PathBuf> {
+    #[cfg(windows)]
+    let base = std::env::var("LOCALAPPDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("."));
+
+    #[cfg(not(windows))]
+    let base = std::env::var("HOME")
+        .map(|h| PathBuf::from(h).join(".config"))
+        .unwrap_or_else(|_| PathBuf::from("."));
+
+    Ok(base.join("SecureDesk").join("ipc.json"))
+}
+
+/// Send one command to the running instance and return its result (CLI side)
+pub fn send(command: Command) -> Result<Option<serde_json::Value>> {
+    let not_running = || anyhow!("SecureDesk is not running, or its automation socket is off (`config set automation_enabled true`)");
+    let data = fs::read(endpoint_path()?).map_err(|_| not_running())?;
+    let endpoint: Endpoint = serde_json::from_slice(&data)?;
+
+    let addr = std::net::SocketAddr::from(([127, 0, 0, 1], endpoint.port));
+    let mut stream = std::net::TcpStream::connect_timeout(&addr, CLIENT_TIMEOUT).map_err(|_| not_running())?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut request = serde_json::to_vec(&Request { token: endpoint.token, command })?;
+    request.push(b'\n');
+    stream.write_all(&request)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    let response: Response = serde_json::from_str(&line)
+        .map_err(|_| anyhow!("No valid response from SecureDesk"))?;
+    if !response.ok {
+        anyhow::bail!(response.error.unwrap_or_else(|| "Request failed".to_string()));
+    }
+    Ok(response.result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_wire_format() {
+        let line = r#"{"token":"ab12","command":"input","session":"session_3","action":{"type":"click","x":100,"y":200}}"#;
+        let request: Request = serde_json::from_str(line).unwrap();
+        assert_eq!(request.command, Command::Input {
+            session: Some("session_3".to_string()),
+            action: InputAction::Click { x: 100, y: 200, button: 0 },
+        });
+
+        let sessions: Request = serde_json::from_str(r#"{"token":"ab12","command":"sessions"}"#).unwrap();
+        assert_eq!(sessions.command, Command::Sessions);
+
+        assert!(token_matches("ab12", "ab12"));
+        assert!(!token_matches("ab13", "ab12"));
+        assert!(!token_matches("ab1", "ab12"));
+
+        let failed = serde_json::to_string(&Response::from_result(Err("Session not found".into()))).unwrap();
+        assert_eq!(failed, r#"{"ok":false,"error":"Session not found"}"#);
+    }
+}
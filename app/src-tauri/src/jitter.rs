@@ -0,0 +1,146 @@
+//! Client-side jitter buffer - smooths frame presentation over bursty links
+//!
+//! Frames are held until `depth` are buffered, then released one at a time at
+//! the average interval they arrived at, so a burst followed by a gap plays
+//! back evenly. Depth 0 passes frames straight through for the lowest latency.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Most frames the buffer may be set to hold
+pub const MAX_JITTER_BUFFER_FRAMES: u32 = 5;
+
+/// Slowest release cadence, however long the gaps between frames get
+const MAX_RELEASE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Weight of each new arrival interval in the running average (1/8)
+const INTERVAL_SMOOTHING: u32 = 8;
+
+pub struct JitterBuffer<T> {
+    depth: usize,
+    frames: VecDeque<T>,
+    /// True once `depth` frames were buffered; cleared when the buffer runs dry
+    primed: bool,
+    /// Average time between arrivals, once two frames were fetched back to back
+    interval: Option<Duration>,
+    last_arrival: Option<Instant>,
+    last_release: Option<Instant>,
+}
+
+impl<T> JitterBuffer<T> {
+    pub fn new(depth: u32) -> Self {
+        Self {
+            depth: depth.min(MAX_JITTER_BUFFER_FRAMES) as usize,
+            frames: VecDeque::new(),
+            primed: false,
+            interval: None,
+            last_arrival: None,
+            last_release: None,
+        }
+    }
+
+    /// Change the depth; buffered frames are dropped so the new depth starts clean
+    pub fn set_depth(&mut self, depth: u32) {
+        let depth = depth.min(MAX_JITTER_BUFFER_FRAMES) as usize;
+        if depth != self.depth {
+            *self = Self::new(depth as u32);
+        }
+    }
+
+    /// Whether another frame should be fetched, or the buffer already holds enough
+    pub fn wants_frame(&self) -> bool {
+        self.depth == 0 || self.frames.len() < self.depth
+    }
+
+    /// Note that no frame was fetched this time, so the next arrival is not
+    /// counted against the one before
+    pub fn skip_fetch(&mut self) {
+        self.last_arrival = None;
+    }
+
+    /// Add a newly arrived frame
+    pub fn push(&mut self, frame: T, now: Instant) {
+        if let Some(last) = self.last_arrival {
+            let sample = now.saturating_duration_since(last).min(MAX_RELEASE_INTERVAL);
+            self.interval = Some(match self.interval {
+                Some(avg) => (avg * (INTERVAL_SMOOTHING - 1) + sample) / INTERVAL_SMOOTHING,
+                None => sample,
+            });
+        }
+        self.last_arrival = Some(now);
+
+        self.frames.push_back(frame);
+        // Never fall further behind than the configured depth
+        while self.frames.len() > self.depth.max(1) {
+            self.frames.pop_front();
+        }
+    }
+
+    /// Next frame to present, if one is due
+    pub fn pop(&mut self, now: Instant) -> Option<T> {
+        if self.depth == 0 {
+            return self.frames.pop_front();
+        }
+
+        if !self.primed {
+            if self.frames.len() < self.depth {
+                return None;
+            }
+            self.primed = true;
+        }
+
+        let interval = self.interval.unwrap_or_default();
+        if let Some(last) = self.last_release {
+            if now.saturating_duration_since(last) < interval {
+                return None;
+            }
+        }
+
+        let frame = self.frames.pop_front()?;
+        self.last_release = Some(now);
+        if self.frames.is_empty() {
+            // Ran dry - build the cushion up again before the next release
+            self.primed = false;
+        }
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_depth_zero_passes_through() {
+        let now = Instant::now();
+        let mut buffer = JitterBuffer::new(0);
+        assert!(buffer.wants_frame());
+        buffer.push(1, now);
+        assert_eq!(buffer.pop(now), Some(1));
+        assert_eq!(buffer.pop(now), None);
+    }
+
+    #[test]
+    fn test_burst_is_released_at_arrival_cadence() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut buffer = JitterBuffer::new(2);
+
+        // Frames arrive every 40ms; nothing is shown until two are buffered
+        buffer.push(1, at(0));
+        assert_eq!(buffer.pop(at(0)), None);
+        buffer.push(2, at(40));
+        assert!(!buffer.wants_frame());
+        assert_eq!(buffer.pop(at(40)), Some(1));
+
+        // A burst arrives right away, but is still released at the measured cadence
+        buffer.push(3, at(41));
+        assert_eq!(buffer.pop(at(50)), None);
+        assert_eq!(buffer.pop(at(80)), Some(2));
+        assert_eq!(buffer.pop(at(120)), Some(3));
+
+        // Running dry re-primes before showing more
+        buffer.push(4, at(130));
+        assert_eq!(buffer.pop(at(200)), None);
+    }
+}
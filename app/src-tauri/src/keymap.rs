@@ -0,0 +1,380 @@
+//! Key remapping - Windows virtual keys to macOS key codes and X11 keysyms
+//!
+//! Viewers always send Windows VK codes, so macOS and Linux hosts translate
+//! every key through these tables. The defaults follow a US layout and any
+//! key can be overridden from config. An X11 entry may list several keysyms:
+//! the first one the host layout can type wins, which is how a quote still
+//! lands on an international layout where that key only has `dead_acute`.
+//! macOS key codes are positional, so dead keys need nothing extra there.
+
+#![allow(dead_code)]
+
+use anyhow::{anyhow, Result};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+
+/// One key: VK, display name, macOS key code, X11 keysyms in preference order
+type KeyRow = (u16, &'static str, Option<u16>, &'static [u32]);
+
+const DEFAULT_KEYS: &[KeyRow] = &[
+    // Letters - X11 uses the lowercase keysym
+    (0x41, "A", Some(0x00), &[0x61]),
+    (0x42, "B", Some(0x0B), &[0x62]),
+    (0x43, "C", Some(0x08), &[0x63]),
+    (0x44, "D", Some(0x02), &[0x64]),
+    (0x45, "E", Some(0x0E), &[0x65]),
+    (0x46, "F", Some(0x03), &[0x66]),
+    (0x47, "G", Some(0x05), &[0x67]),
+    (0x48, "H", Some(0x04), &[0x68]),
+    (0x49, "I", Some(0x22), &[0x69]),
+    (0x4A, "J", Some(0x26), &[0x6A]),
+    (0x4B, "K", Some(0x28), &[0x6B]),
+    (0x4C, "L", Some(0x25), &[0x6C]),
+    (0x4D, "M", Some(0x2E), &[0x6D]),
+    (0x4E, "N", Some(0x2D), &[0x6E]),
+    (0x4F, "O", Some(0x1F), &[0x6F]),
+    (0x50, "P", Some(0x23), &[0x70]),
+    (0x51, "Q", Some(0x0C), &[0x71]),
+    (0x52, "R", Some(0x0F), &[0x72]),
+    (0x53, "S", Some(0x01), &[0x73]),
+    (0x54, "T", Some(0x11), &[0x74]),
+    (0x55, "U", Some(0x20), &[0x75]),
+    (0x56, "V", Some(0x09), &[0x76]),
+    (0x57, "W", Some(0x0D), &[0x77]),
+    (0x58, "X", Some(0x07), &[0x78]),
+    (0x59, "Y", Some(0x10), &[0x79]),
+    (0x5A, "Z", Some(0x06), &[0x7A]),
+
+    // Digits
+    (0x30, "0", Some(0x1D), &[0x30]),
+    (0x31, "1", Some(0x12), &[0x31]),
+    (0x32, "2", Some(0x13), &[0x32]),
+    (0x33, "3", Some(0x14), &[0x33]),
+    (0x34, "4", Some(0x15), &[0x34]),
+    (0x35, "5", Some(0x17), &[0x35]),
+    (0x36, "6", Some(0x16), &[0x36]),
+    (0x37, "7", Some(0x1A), &[0x37]),
+    (0x38, "8", Some(0x1C), &[0x38]),
+    (0x39, "9", Some(0x19), &[0x39]),
+
+    // Function keys
+    (0x70, "F1", Some(0x7A), &[0xFFBE]),
+    (0x71, "F2", Some(0x78), &[0xFFBF]),
+    (0x72, "F3", Some(0x63), &[0xFFC0]),
+    (0x73, "F4", Some(0x76), &[0xFFC1]),
+    (0x74, "F5", Some(0x60), &[0xFFC2]),
+    (0x75, "F6", Some(0x61), &[0xFFC3]),
+    (0x76, "F7", Some(0x62), &[0xFFC4]),
+    (0x77, "F8", Some(0x64), &[0xFFC5]),
+    (0x78, "F9", Some(0x65), &[0xFFC6]),
+    (0x79, "F10", Some(0x6D), &[0xFFC7]),
+    (0x7A, "F11", Some(0x67), &[0xFFC8]),
+    (0x7B, "F12", Some(0x6F), &[0xFFC9]),
+    (0x7C, "F13", Some(0x69), &[0xFFCA]),
+    (0x7D, "F14", Some(0x6B), &[0xFFCB]),
+    (0x7E, "F15", Some(0x71), &[0xFFCC]),
+    (0x7F, "F16", Some(0x6A), &[0xFFCD]),
+    (0x80, "F17", Some(0x40), &[0xFFCE]),
+    (0x81, "F18", Some(0x4F), &[0xFFCF]),
+    (0x82, "F19", Some(0x50), &[0xFFD0]),
+    (0x83, "F20", Some(0x5A), &[0xFFD1]),
+    (0x84, "F21", None, &[0xFFD2]),
+    (0x85, "F22", None, &[0xFFD3]),
+    (0x86, "F23", None, &[0xFFD4]),
+    (0x87, "F24", None, &[0xFFD5]),
+
+    // Editing and modifiers
+    (0x08, "Backspace", Some(0x33), &[0xFF08]),
+    (0x09, "Tab", Some(0x30), &[0xFF09]),
+    (0x0C, "Clear", Some(0x47), &[0xFF0B]),
+    (0x0D, "Enter", Some(0x24), &[0xFF0D]),
+    (0x10, "Shift", Some(0x38), &[0xFFE1]),
+    (0x11, "Control", Some(0x3B), &[0xFFE3]),
+    (0x12, "Alt", Some(0x3A), &[0xFFE9]),
+    (0x13, "Pause", None, &[0xFF13]),
+    (0x14, "CapsLock", Some(0x39), &[0xFFE5]),
+    (0x1B, "Escape", Some(0x35), &[0xFF1B]),
+    (0x20, "Space", Some(0x31), &[0x0020]),
+    (0xA0, "LeftShift", Some(0x38), &[0xFFE1]),
+    (0xA1, "RightShift", Some(0x3C), &[0xFFE2]),
+    (0xA2, "LeftControl", Some(0x3B), &[0xFFE3]),
+    (0xA3, "RightControl", Some(0x3E), &[0xFFE4]),
+    (0xA4, "LeftAlt", Some(0x3A), &[0xFFE9]),
+    // AltGr layouts have ISO_Level3_Shift where others have Alt_R
+    (0xA5, "RightAlt", Some(0x3D), &[0xFFEA, 0xFE03]),
+    (0x5B, "LeftWindows", Some(0x37), &[0xFFEB]),
+    (0x5C, "RightWindows", Some(0x36), &[0xFFEC]),
+    (0x5D, "Menu", None, &[0xFF67]),
+
+    // Navigation
+    (0x21, "PageUp", Some(0x74), &[0xFF55]),
+    (0x22, "PageDown", Some(0x79), &[0xFF56]),
+    (0x23, "End", Some(0x77), &[0xFF57]),
+    (0x24, "Home", Some(0x73), &[0xFF50]),
+    (0x25, "Left", Some(0x7B), &[0xFF51]),
+    (0x26, "Up", Some(0x7E), &[0xFF52]),
+    (0x27, "Right", Some(0x7C), &[0xFF53]),
+    (0x28, "Down", Some(0x7D), &[0xFF54]),
+    (0x2C, "PrintScreen", None, &[0xFF61]),
+    // Help sits where Insert is on Apple extended keyboards
+    (0x2D, "Insert", Some(0x72), &[0xFF63]),
+    (0x2E, "Delete", Some(0x75), &[0xFFFF]),
+
+    // Numpad
+    (0x60, "Numpad0", Some(0x52), &[0xFFB0]),
+    (0x61, "Numpad1", Some(0x53), &[0xFFB1]),
+    (0x62, "Numpad2", Some(0x54), &[0xFFB2]),
+    (0x63, "Numpad3", Some(0x55), &[0xFFB3]),
+    (0x64, "Numpad4", Some(0x56), &[0xFFB4]),
+    (0x65, "Numpad5", Some(0x57), &[0xFFB5]),
+    (0x66, "Numpad6", Some(0x58), &[0xFFB6]),
+    (0x67, "Numpad7", Some(0x59), &[0xFFB7]),
+    (0x68, "Numpad8", Some(0x5B), &[0xFFB8]),
+    (0x69, "Numpad9", Some(0x5C), &[0xFFB9]),
+    (0x6A, "NumpadMultiply", Some(0x43), &[0xFFAA]),
+    (0x6B, "NumpadAdd", Some(0x45), &[0xFFAB]),
+    (0x6D, "NumpadSubtract", Some(0x4E), &[0xFFAD]),
+    (0x6E, "NumpadDecimal", Some(0x41), &[0xFFAE]),
+    (0x6F, "NumpadDivide", Some(0x4B), &[0xFFAF]),
+    // Macs have no Num Lock; the key in its place is keypad Clear
+    (0x90, "NumLock", Some(0x47), &[0xFF7F]),
+    (0x91, "ScrollLock", None, &[0xFF14]),
+
+    // Punctuation - the dead variant is tried when the layout lacks the plain one
+    (0xBA, "Semicolon", Some(0x29), &[0x003B]),
+    (0xBB, "Equals", Some(0x18), &[0x003D]),
+    (0xBC, "Comma", Some(0x2B), &[0x002C]),
+    (0xBD, "Minus", Some(0x1B), &[0x002D]),
+    (0xBE, "Period", Some(0x2F), &[0x002E]),
+    (0xBF, "Slash", Some(0x2C), &[0x002F]),
+    (0xC0, "Backtick", Some(0x32), &[0x0060, 0xFE50]),         // dead_grave
+    (0xDB, "LeftBracket", Some(0x21), &[0x005B]),
+    (0xDC, "Backslash", Some(0x2A), &[0x005C, 0xFE52]),        // dead_circumflex
+    (0xDD, "RightBracket", Some(0x1E), &[0x005D, 0xFE52]),     // dead_circumflex
+    (0xDE, "Quote", Some(0x27), &[0x0027, 0xFE51, 0xFE57]),    // dead_acute, dead_diaeresis
+    (0xE2, "IntlBackslash", Some(0x0A), &[0x003C]),
+
+    // Media and browser keys - macOS only has the volume keys as key codes
+    (0xA6, "BrowserBack", None, &[0x1008FF26]),
+    (0xA7, "BrowserForward", None, &[0x1008FF27]),
+    (0xA8, "BrowserRefresh", None, &[0x1008FF29]),
+    (0xA9, "BrowserStop", None, &[0x1008FF28]),
+    (0xAA, "BrowserSearch", None, &[0x1008FF1B]),
+    (0xAB, "BrowserFavorites", None, &[0x1008FF30]),
+    (0xAC, "BrowserHome", None, &[0x1008FF18]),
+    (0xAD, "VolumeMute", Some(0x4A), &[0x1008FF12]),
+    (0xAE, "VolumeDown", Some(0x49), &[0x1008FF11]),
+    (0xAF, "VolumeUp", Some(0x48), &[0x1008FF13]),
+    (0xB0, "MediaNext", None, &[0x1008FF17]),
+    (0xB1, "MediaPrevious", None, &[0x1008FF16]),
+    (0xB2, "MediaStop", None, &[0x1008FF15]),
+    (0xB3, "MediaPlayPause", None, &[0x1008FF14]),
+    (0xB4, "LaunchMail", None, &[0x1008FF19]),
+    (0xB5, "LaunchMedia", None, &[0x1008FF32]),
+    (0xB6, "LaunchApp1", None, &[0x1008FF33]),
+    (0xB7, "LaunchApp2", None, &[0x1008FF1D]),
+    (0x5F, "Sleep", None, &[0x1008FF2F]),
+];
+
+/// Per-key overrides from config, keyed by VK
+/// Keys and codes are hex ("0xDE") or decimal. X11 values may list several
+/// keysyms separated by commas, tried in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeymapOverrides {
+    #[serde(default)]
+    pub macos: BTreeMap<String, String>,
+    #[serde(default)]
+    pub x11: BTreeMap<String, String>,
+}
+
+impl KeymapOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.macos.is_empty() && self.x11.is_empty()
+    }
+}
+
+/// Resolved tables: defaults with the overrides laid over them
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    mac: HashMap<u16, u16>,
+    x11: HashMap<u16, Vec<u32>>,
+}
+
+impl Keymap {
+    fn defaults() -> Self {
+        let mut mac = HashMap::new();
+        let mut x11 = HashMap::new();
+        for &(vk, _, mac_code, keysyms) in DEFAULT_KEYS {
+            if let Some(code) = mac_code {
+                mac.insert(vk, code);
+            }
+            if !keysyms.is_empty() {
+                x11.insert(vk, keysyms.to_vec());
+            }
+        }
+        Self { mac, x11 }
+    }
+
+    /// Defaults plus overrides; invalid entries are skipped and reported
+    pub fn with_overrides(overrides: &KeymapOverrides) -> (Self, Vec<String>) {
+        let mut keymap = Self::defaults();
+        let mut errors = Vec::new();
+
+        for (key, value) in &overrides.macos {
+            let parsed = parse_code(key).and_then(vk_from).and_then(|vk| {
+                let code = parse_code(value)?;
+                let code = u16::try_from(code).map_err(|_| anyhow!("key code out of range"))?;
+                Ok((vk, code))
+            });
+            match parsed {
+                Ok((vk, code)) => { keymap.mac.insert(vk, code); }
+                Err(e) => errors.push(format!("macos {} = {:?}: {}", key, value, e)),
+            }
+        }
+
+        for (key, value) in &overrides.x11 {
+            let parsed = parse_code(key).and_then(vk_from).and_then(|vk| {
+                let keysyms = value.split(',').map(parse_code).collect::<Result<Vec<_>>>()?;
+                Ok((vk, keysyms))
+            });
+            match parsed {
+                Ok((vk, keysyms)) => { keymap.x11.insert(vk, keysyms); }
+                Err(e) => errors.push(format!("x11 {} = {:?}: {}", key, value, e)),
+            }
+        }
+
+        (keymap, errors)
+    }
+
+    /// macOS key code for a VK; unmapped keys pass through unchanged
+    pub fn mac_keycode(&self, vk: u16) -> u16 {
+        self.mac.get(&vk).copied().unwrap_or(vk)
+    }
+
+    /// X11 keysyms for a VK in preference order; unmapped keys pass through
+    pub fn x11_keysyms(&self, vk: u16) -> Vec<u32> {
+        self.x11.get(&vk).cloned().unwrap_or_else(|| vec![vk as u32])
+    }
+
+    /// Both tables as text, one key per line
+    pub fn dump(&self) -> String {
+        let mut vks: Vec<u16> = self.mac.keys().chain(self.x11.keys()).copied().collect();
+        vks.sort_unstable();
+        vks.dedup();
+
+        let mut out = String::new();
+        let _ = writeln!(out, "# {:<6} {:<18} {:<8} X11 keysyms", "VK", "Name", "macOS");
+        for vk in vks {
+            let mac = self.mac.get(&vk).map(|code| format!("0x{:02X}", code)).unwrap_or_else(|| "-".into());
+            let x11 = self.x11.get(&vk)
+                .map(|keysyms| keysyms.iter().map(|k| format!("0x{:04X}", k)).collect::<Vec<_>>().join(","))
+                .unwrap_or_else(|| "-".into());
+            let _ = writeln!(out, "0x{:02X}     {:<18} {:<8} {}", vk, key_name(vk), mac, x11);
+        }
+        out
+    }
+}
+
+/// Active tables used by the input injector
+static ACTIVE: Lazy<RwLock<Keymap>> = Lazy::new(|| RwLock::new(Keymap::defaults()));
+
+/// Rebuild the active tables from config overrides
+pub fn configure(overrides: &KeymapOverrides) {
+    let (keymap, errors) = Keymap::with_overrides(overrides);
+    for error in &errors {
+        eprintln!("[KEYMAP] Ignoring override {}", error);
+    }
+    if !overrides.is_empty() {
+        println!(
+            "[KEYMAP] {} override(s) applied",
+            overrides.macos.len() + overrides.x11.len() - errors.len()
+        );
+    }
+    *ACTIVE.write() = keymap;
+}
+
+pub fn mac_keycode(vk: u16) -> u16 {
+    ACTIVE.read().mac_keycode(vk)
+}
+
+pub fn x11_keysyms(vk: u16) -> Vec<u32> {
+    ACTIVE.read().x11_keysyms(vk)
+}
+
+/// The active tables as text, for `--dump-keymap`
+pub fn dump() -> String {
+    ACTIVE.read().dump()
+}
+
+fn key_name(vk: u16) -> &'static str {
+    DEFAULT_KEYS.iter()
+        .find(|(key, ..)| *key == vk)
+        .map(|(_, name, ..)| *name)
+        .unwrap_or("-")
+}
+
+/// Parse a hex ("0x27") or decimal code
+pub fn parse_code(s: &str) -> Result<u32> {
+    let s = s.trim();
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| anyhow!("{:?} is not a number", s))
+}
+
+fn vk_from(code: u32) -> Result<u16> {
+    match u16::try_from(code) {
+        Ok(vk) if vk <= 0xFF => Ok(vk),
+        _ => Err(anyhow!("virtual key out of range")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_mappings_round_trip() {
+        let keymap = Keymap::defaults();
+        // (VK, macOS key code, X11 keysym)
+        let known = [(0x41, 0x00, 0x61), (0xDE, 0x27, 0x27), (0x60, 0x52, 0xFFB0), (0xBF, 0x2C, 0x2F)];
+
+        for (vk, mac, keysym) in known {
+            assert_eq!(keymap.mac_keycode(vk), mac);
+            assert_eq!(keymap.x11_keysyms(vk)[0], keysym);
+
+            // Each of these codes belongs to exactly that VK, so the way back is unambiguous
+            let mac_back: Vec<u16> = keymap.mac.iter().filter(|(_, &c)| c == mac).map(|(&v, _)| v).collect();
+            let x11_back: Vec<u16> = keymap.x11.iter().filter(|(_, k)| k[0] == keysym).map(|(&v, _)| v).collect();
+            assert_eq!(mac_back, vec![vk]);
+            assert_eq!(x11_back, vec![vk]);
+        }
+
+        // Quote falls back to the dead key on layouts without a plain apostrophe
+        assert_eq!(keymap.x11_keysyms(0xDE), vec![0x27, 0xFE51, 0xFE57]);
+        // Unmapped keys pass through as before
+        assert_eq!(keymap.mac_keycode(0xE5), 0xE5);
+        assert_eq!(keymap.x11_keysyms(0xE5), vec![0xE5]);
+    }
+
+    #[test]
+    fn test_overrides_replace_defaults() {
+        let mut overrides = KeymapOverrides::default();
+        overrides.macos.insert("0xE2".into(), "0x32".into());
+        overrides.x11.insert("222".into(), "0xFE51, 0x27".into());
+        overrides.macos.insert("0x1FF".into(), "0x00".into());
+        overrides.x11.insert("0xC0".into(), "grave".into());
+
+        let (keymap, errors) = Keymap::with_overrides(&overrides);
+        assert_eq!(keymap.mac_keycode(0xE2), 0x32);
+        assert_eq!(keymap.x11_keysyms(0xDE), vec![0xFE51, 0x27]);
+        // Bad entries are reported and leave the default in place
+        assert_eq!(errors.len(), 2);
+        assert_eq!(keymap.x11_keysyms(0xC0), vec![0x60, 0xFE50]);
+        assert!(keymap.dump().contains("0xDE     Quote"));
+    }
+}
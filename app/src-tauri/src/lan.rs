@@ -0,0 +1,539 @@
+//! LAN discovery over mDNS / DNS-SD (RFC 6762, RFC 6763)
+//!
+//! While the host listener runs, this device answers queries for
+//! `_securedesk._tcp.local` with its device ID, alias and machine name, unless
+//! it is hidden from the address book. `discover` sends one query from an
+//! ephemeral port - responders answer such "legacy unicast" queries straight
+//! back - and collects the answers for a moment.
+//!
+//! Only finding peers happens here. Connecting to one still sets the session
+//! up through the relay (handshake and approval); the P2P upgrade then dials
+//! the address `discover` last found the peer at before any it is told, and
+//! once the link is secured the session's frames leave the relay.
+
+use anyhow::{Context, Result};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+use tokio::net::UdpSocket;
+
+/// Service type hosts advertise
+pub const SERVICE: &str = "_securedesk._tcp.local";
+
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+
+/// Record TTL in multicast answers; legacy unicast answers use LEGACY_TTL (RFC 6762 6.7)
+const TTL: u32 = 120;
+const LEGACY_TTL: u32 = 10;
+
+/// How long `discover` waits for answers
+pub const BROWSE_TIMEOUT: Duration = Duration::from_millis(1500);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Set on records only this host answers for, so caches replace rather than add
+const CACHE_FLUSH: u16 = 0x8000;
+/// Response, authoritative
+const FLAGS_RESPONSE: u16 = 0x8400;
+
+/// What a host announces about itself
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Advert {
+    /// Raw device ID (no spaces)
+    pub device_id: String,
+    pub alias: Option<String>,
+    pub name: String,
+    /// Port of the P2P listener
+    pub port: u16,
+}
+
+impl Advert {
+    fn instance(&self) -> String {
+        format!("{}.{}", self.device_id, SERVICE)
+    }
+
+    fn target(&self) -> String {
+        format!("securedesk-{}.local", self.device_id)
+    }
+}
+
+/// A host on the local network, for `discover_lan_peers`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LanPeer {
+    pub device_id: String,
+    pub alias: Option<String>,
+    pub name: String,
+    /// LAN address of its P2P listener
+    pub address: SocketAddr,
+}
+
+struct Responder {
+    advert: Advert,
+    task: tauri::async_runtime::JoinHandle<()>,
+}
+
+static RESPONDER: Lazy<Mutex<Option<Responder>>> = Lazy::new(|| Mutex::new(None));
+
+/// Where the last `discover` found each peer, by raw device ID
+static LAST_SEEN: Lazy<Mutex<HashMap<String, SocketAddr>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Answer queries for this host, or update what is announced if already running
+pub fn advertise(advert: Advert) {
+    let mut responder = RESPONDER.lock();
+    match responder.as_mut() {
+        Some(running) if running.advert == advert => {}
+        Some(running) => {
+            running.advert = advert;
+            println!("[LAN] Updated announcement");
+        }
+        None => {
+            println!("[LAN] Announcing device {} on the local network", advert.device_id);
+            let task = tauri::async_runtime::spawn(respond(advert.clone()));
+            *responder = Some(Responder { advert, task });
+        }
+    }
+}
+
+/// Stop answering, telling caches to drop our records
+pub fn withdraw() {
+    let Some(responder) = RESPONDER.lock().take() else {
+        return;
+    };
+    responder.task.abort();
+    println!("[LAN] Stopped announcing");
+
+    tauri::async_runtime::spawn(async move {
+        let goodbye = response(&responder.advert, local_ipv4().await, 0, None);
+        if let Ok(socket) = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await {
+            let _ = socket.send_to(&goodbye, SocketAddrV4::new(MDNS_GROUP, MDNS_PORT)).await;
+        }
+    });
+}
+
+/// LAN address of `device_id`'s P2P listener, if the last `discover` found it
+/// Only a hint: the link to it must still prove the host's identity
+pub fn last_seen(device_id: &str) -> Option<SocketAddr> {
+    LAST_SEEN.lock().get(&device_id.replace(' ', "")).copied()
+}
+
+/// This machine's name, for the announcement
+pub fn machine_name() -> String {
+    ["COMPUTERNAME", "HOSTNAME"]
+        .iter()
+        .filter_map(|key| std::env::var(key).ok())
+        .chain(std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .find(|name| !name.is_empty())
+        .unwrap_or_else(|| "SecureDesk".to_string())
+}
+
+/// Ask the local network for SecureDesk hosts, leaving out `own_id`
+pub async fn discover(own_id: &str, wait: Duration) -> Result<Vec<LanPeer>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.send_to(&query(), SocketAddrV4::new(MDNS_GROUP, MDNS_PORT)).await
+        .context("Failed to send mDNS query")?;
+
+    let mut peers: HashMap<String, LanPeer> = HashMap::new();
+    let mut buf = vec![0u8; 9000];
+    let deadline = tokio::time::Instant::now() + wait;
+    while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let Ok((len, from)) = received else {
+            break;
+        };
+        for peer in parse_response(&buf[..len], from) {
+            if peer.device_id != own_id {
+                peers.insert(peer.device_id.clone(), peer);
+            }
+        }
+    }
+
+    *LAST_SEEN.lock() = peers.iter().map(|(id, peer)| (id.clone(), peer.address)).collect();
+    let mut peers: Vec<LanPeer> = peers.into_values().collect();
+    peers.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.device_id.cmp(&b.device_id)));
+    Ok(peers)
+}
+
+async fn respond(advert: Advert) {
+    let socket = match bind_mdns() {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!("[LAN] Cannot listen for mDNS queries: {:#}", e);
+            return;
+        }
+    };
+    let group = SocketAddr::V4(SocketAddrV4::new(MDNS_GROUP, MDNS_PORT));
+
+    // Unsolicited announcement, repeated once as RFC 6762 8.3 asks
+    for _ in 0..2 {
+        let _ = socket.send_to(&response(&advert, local_ipv4().await, TTL, None), group).await;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+
+    let mut buf = vec![0u8; 9000];
+    loop {
+        let (len, from) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                eprintln!("[LAN] mDNS receive failed: {}", e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let Some(asked) = parse_query(&buf[..len]) else {
+            continue;
+        };
+        // The advert may have changed since the task started
+        let Some(advert) = RESPONDER.lock().as_ref().map(|r| r.advert.clone()) else {
+            return;
+        };
+        if !asked.questions.iter().any(|(name, qtype)| asks_for(&advert, name, *qtype)) {
+            continue;
+        }
+
+        let ip = local_ipv4().await;
+        let sent = if from.port() == MDNS_PORT {
+            socket.send_to(&response(&advert, ip, TTL, None), group).await
+        } else {
+            socket.send_to(&response(&advert, ip, LEGACY_TTL, Some(&asked)), from).await
+        };
+        if let Err(e) = sent {
+            eprintln!("[LAN] Failed to answer mDNS query: {}", e);
+        }
+    }
+}
+
+/// The mDNS port, shared with any other responder on this machine
+fn bind_mdns() -> Result<UdpSocket> {
+    use socket2::{Domain, Protocol, Socket, Type};
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())
+        .context("Failed to bind the mDNS port")?;
+    socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)?;
+    socket.set_nonblocking(true)?;
+    Ok(UdpSocket::from_std(socket.into())?)
+}
+
+async fn local_ipv4() -> Option<Ipv4Addr> {
+    match crate::stun::get_local_address_async().await {
+        Ok(Some(SocketAddr::V4(addr))) => Some(*addr.ip()),
+        _ => None,
+    }
+}
+
+fn asks_for(advert: &Advert, name: &str, qtype: u16) -> bool {
+    (name.eq_ignore_ascii_case(SERVICE) && matches!(qtype, TYPE_PTR | TYPE_ANY))
+        || (name.eq_ignore_ascii_case(&advert.instance()) && matches!(qtype, TYPE_SRV | TYPE_TXT | TYPE_ANY))
+}
+
+/// A query's id and questions
+struct Query {
+    id: u16,
+    questions: Vec<(String, u16)>,
+}
+
+/// PTR query for the service
+fn query() -> Vec<u8> {
+    let mut packet = header(0, 0, 1, 0, 0);
+    write_name(&mut packet, SERVICE);
+    packet.extend(&TYPE_PTR.to_be_bytes());
+    packet.extend(&CLASS_IN.to_be_bytes());
+    packet
+}
+
+/// PTR answer with SRV, TXT and (when known) A records alongside
+/// A legacy unicast answer echoes the query's id and questions and leaves the
+/// cache-flush bit off
+fn response(advert: &Advert, ip: Option<Ipv4Addr>, ttl: u32, legacy: Option<&Query>) -> Vec<u8> {
+    let unique = if legacy.is_some() { CLASS_IN } else { CLASS_IN | CACHE_FLUSH };
+    let questions = legacy.map_or(&[][..], |q| &q.questions[..]);
+    let additional = 2 + ip.is_some() as u16;
+    let mut packet = header(legacy.map_or(0, |q| q.id), FLAGS_RESPONSE, questions.len() as u16, 1, additional);
+    for (name, qtype) in questions {
+        write_name(&mut packet, name);
+        packet.extend(&qtype.to_be_bytes());
+        packet.extend(&CLASS_IN.to_be_bytes());
+    }
+
+    let instance = advert.instance();
+    let mut ptr = Vec::new();
+    write_name(&mut ptr, &instance);
+    write_record(&mut packet, SERVICE, TYPE_PTR, CLASS_IN, ttl, &ptr);
+
+    let mut srv = Vec::new();
+    srv.extend(&0u16.to_be_bytes()); // priority
+    srv.extend(&0u16.to_be_bytes()); // weight
+    srv.extend(&advert.port.to_be_bytes());
+    write_name(&mut srv, &advert.target());
+    write_record(&mut packet, &instance, TYPE_SRV, unique, ttl, &srv);
+
+    let mut txt = Vec::new();
+    let mut entries = vec![
+        "txtvers=1".to_string(),
+        format!("id={}", advert.device_id),
+        format!("name={}", advert.name),
+    ];
+    if let Some(alias) = &advert.alias {
+        entries.push(format!("alias={}", alias));
+    }
+    for entry in entries {
+        let entry = &entry.as_bytes()[..entry.len().min(255)];
+        txt.push(entry.len() as u8);
+        txt.extend(entry);
+    }
+    write_record(&mut packet, &instance, TYPE_TXT, unique, ttl, &txt);
+
+    if let Some(ip) = ip {
+        write_record(&mut packet, &advert.target(), TYPE_A, unique, ttl, &ip.octets());
+    }
+    packet
+}
+
+fn header(id: u16, flags: u16, questions: u16, answers: u16, additional: u16) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(512);
+    for field in [id, flags, questions, answers, 0, additional] {
+        packet.extend(&field.to_be_bytes());
+    }
+    packet
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|l| !l.is_empty()) {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        out.push(label.len() as u8);
+        out.extend(label);
+    }
+    out.push(0);
+}
+
+fn write_record(out: &mut Vec<u8>, name: &str, rtype: u16, class: u16, ttl: u32, data: &[u8]) {
+    write_name(out, name);
+    out.extend(&rtype.to_be_bytes());
+    out.extend(&class.to_be_bytes());
+    out.extend(&ttl.to_be_bytes());
+    out.extend(&(data.len() as u16).to_be_bytes());
+    out.extend(data);
+}
+
+/// A name at `pos`, following compression pointers, and where the name ends
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut end = None;
+    // Every pointer must go back, so a loop can't last longer than the packet
+    for _ in 0..packet.len() {
+        let len = *packet.get(pos)? as usize;
+        match len {
+            0 => {
+                return Some((labels.join("."), end.unwrap_or(pos + 1)));
+            }
+            l if l & 0xC0 == 0xC0 => {
+                let target = ((l & 0x3F) << 8) | *packet.get(pos + 1)? as usize;
+                if target >= pos {
+                    return None;
+                }
+                end.get_or_insert(pos + 2);
+                pos = target;
+            }
+            l if l < 64 => {
+                let label = packet.get(pos + 1..pos + 1 + l)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + l;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+fn read_u16(packet: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(packet.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+/// Questions of a query, None for responses and malformed packets
+fn parse_query(packet: &[u8]) -> Option<Query> {
+    let id = read_u16(packet, 0)?;
+    if read_u16(packet, 2)? & 0x8000 != 0 {
+        return None;
+    }
+    let count = read_u16(packet, 4)?;
+    let mut pos = 12;
+    let mut questions = Vec::new();
+    for _ in 0..count {
+        let (name, next) = read_name(packet, pos)?;
+        questions.push((name, read_u16(packet, next)?));
+        pos = next + 4;
+    }
+    Some(Query { id, questions })
+}
+
+struct Record {
+    name: String,
+    rtype: u16,
+    /// Offset of the record data in the packet (names in it may point back)
+    data: usize,
+    len: usize,
+}
+
+/// SecureDesk hosts in a response; `from` stands in for a missing A record
+fn parse_response(packet: &[u8], from: SocketAddr) -> Vec<LanPeer> {
+    parse_records(packet).map_or_else(Vec::new, |records| peers_in(packet, &records, from))
+}
+
+fn parse_records(packet: &[u8]) -> Option<Vec<Record>> {
+    if read_u16(packet, 2)? & 0x8000 == 0 {
+        return None;
+    }
+    let questions = read_u16(packet, 4)?;
+    let records = read_u16(packet, 6)? as usize + read_u16(packet, 8)? as usize + read_u16(packet, 10)? as usize;
+
+    let mut pos = 12;
+    for _ in 0..questions {
+        pos = read_name(packet, pos)?.1 + 4;
+    }
+    let mut parsed = Vec::with_capacity(records);
+    for _ in 0..records {
+        let (name, next) = read_name(packet, pos)?;
+        let rtype = read_u16(packet, next)?;
+        let len = read_u16(packet, next + 8)? as usize;
+        let data = next + 10;
+        packet.get(data..data + len)?;
+        parsed.push(Record { name, rtype, data, len });
+        pos = data + len;
+    }
+    Some(parsed)
+}
+
+fn peers_in(packet: &[u8], records: &[Record], from: SocketAddr) -> Vec<LanPeer> {
+    let find = |name: &str, rtype: u16| {
+        records.iter().find(|r| r.rtype == rtype && r.name.eq_ignore_ascii_case(name))
+    };
+
+    let mut peers = Vec::new();
+    for ptr in records.iter().filter(|r| r.rtype == TYPE_PTR && r.name.eq_ignore_ascii_case(SERVICE)) {
+        let Some((instance, _)) = read_name(packet, ptr.data) else {
+            continue;
+        };
+        let (Some(srv), Some(txt)) = (find(&instance, TYPE_SRV), find(&instance, TYPE_TXT)) else {
+            continue;
+        };
+        let Some(port) = read_u16(packet, srv.data + 4) else {
+            continue;
+        };
+
+        let mut fields = HashMap::new();
+        let mut entries = &packet[txt.data..txt.data + txt.len];
+        while let Some((&len, rest)) = entries.split_first() {
+            let Some(entry) = rest.get(..len as usize) else {
+                break;
+            };
+            if let Some((key, value)) = String::from_utf8_lossy(entry).split_once('=') {
+                fields.insert(key.to_ascii_lowercase(), value.to_string());
+            }
+            entries = &rest[len as usize..];
+        }
+        let Some(device_id) = fields.remove("id").filter(|id| !id.is_empty()) else {
+            continue;
+        };
+
+        let ip = read_name(packet, srv.data + 6)
+            .and_then(|(target, _)| find(&target, TYPE_A))
+            .and_then(|a| <[u8; 4]>::try_from(&packet[a.data..a.data + a.len]).ok())
+            .map(std::net::IpAddr::from)
+            .unwrap_or(from.ip());
+
+        peers.push(LanPeer {
+            name: fields.remove("name").unwrap_or_else(|| device_id.clone()),
+            alias: fields.remove("alias").filter(|alias| !alias.is_empty()),
+            device_id,
+            address: SocketAddr::new(ip, port),
+        });
+    }
+    peers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn advert() -> Advert {
+        Advert {
+            device_id: "123456789".to_string(),
+            alias: Some("front-desk".to_string()),
+            name: "RECEPTION-PC".to_string(),
+            port: 50123,
+        }
+    }
+
+    #[test]
+    fn test_query_matching() {
+        let asked = parse_query(&query()).unwrap();
+        assert_eq!(asked.questions, vec![(SERVICE.to_string(), TYPE_PTR)]);
+        assert!(asks_for(&advert(), "_SecureDesk._tcp.local", TYPE_PTR));
+        assert!(asks_for(&advert(), "123456789._securedesk._tcp.local", TYPE_SRV));
+        assert!(!asks_for(&advert(), "_airplay._tcp.local", TYPE_PTR));
+        assert!(!asks_for(&advert(), SERVICE, TYPE_A));
+        // Our own answers are not questions
+        assert!(parse_query(&response(&advert(), None, TTL, None)).is_none());
+    }
+
+    #[test]
+    fn test_response_round_trip() {
+        let from: SocketAddr = "192.168.1.40:5353".parse().unwrap();
+        let ip = Ipv4Addr::new(192, 168, 1, 20);
+        let peers = parse_response(&response(&advert(), Some(ip), TTL, None), from);
+        assert_eq!(peers, vec![LanPeer {
+            device_id: "123456789".to_string(),
+            alias: Some("front-desk".to_string()),
+            name: "RECEPTION-PC".to_string(),
+            address: "192.168.1.20:50123".parse().unwrap(),
+        }]);
+
+        // Legacy unicast: the question comes back and the sender's address stands in for A
+        let asked = parse_query(&query()).unwrap();
+        let packet = response(&Advert { alias: None, ..advert() }, None, LEGACY_TTL, Some(&asked));
+        let peers = parse_response(&packet, from);
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].alias, None);
+        assert_eq!(peers[0].address, "192.168.1.40:50123".parse().unwrap());
+
+        // Compressed names, as other responders send them: "_securedesk._tcp.local"
+        // once at offset 12, then pointed to
+        let mut packet = header(0, FLAGS_RESPONSE, 0, 3, 0);
+        write_name(&mut packet, SERVICE);
+        packet.extend(&TYPE_PTR.to_be_bytes());
+        packet.extend(&CLASS_IN.to_be_bytes());
+        packet.extend(&TTL.to_be_bytes());
+        packet.extend(&12u16.to_be_bytes()); // rdlength: 10-byte label + pointer
+        let instance = packet.len();
+        packet.extend(b"\x09987654321\xC0\x0C");
+        for (rtype, data) in [
+            (TYPE_SRV, b"\x00\x00\x00\x00\xC3\x50\x00".to_vec()),
+            (TYPE_TXT, b"\x0Cid=987654321\x0Aname=LAB-7".to_vec()),
+        ] {
+            packet.extend(&[0xC0, instance as u8]);
+            packet.extend(&rtype.to_be_bytes());
+            packet.extend(&CLASS_IN.to_be_bytes());
+            packet.extend(&TTL.to_be_bytes());
+            packet.extend(&(data.len() as u16).to_be_bytes());
+            packet.extend(&data);
+        }
+        let peers = parse_response(&packet, from);
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].device_id, "987654321");
+        assert_eq!(peers[0].name, "LAB-7");
+        assert_eq!(peers[0].address, "192.168.1.40:50000".parse().unwrap());
+
+        // A pointer to itself is rejected rather than followed forever
+        assert!(read_name(b"\xC0\x00", 0).is_none());
+    }
+}
@@ -0,0 +1,188 @@
+//! End-to-end frame latency - from capture on the host to presentation here
+//!
+//! The host stamps each frame with its capture time on a monotonic clock of its
+//! own. When a frame is handed to the UI the client echoes that stamp back with
+//! how long the frame waited locally (control::FRAME_TIMING), and the host
+//! answers with how long ago it captured it. Neither clock is ever compared
+//! with the other: the client times the echo round trip on its own clock and
+//! takes half of it off the host's figure for the trip back.
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+/// Samples kept for the averages
+const WINDOW: usize = 120;
+
+/// Length of the echo the client sends: [capture_ms u64 LE][render_ms u32 LE]
+pub const ECHO_LEN: usize = 12;
+
+/// Length of the host's answer: the echo followed by [host_elapsed_ms u32 LE]
+pub const REPLY_LEN: usize = ECHO_LEN + 4;
+
+static EPOCH: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// Milliseconds on this process's monotonic clock, never 0 (0 marks an unstamped frame)
+pub fn clock_ms() -> u64 {
+    EPOCH.elapsed().as_millis() as u64 + 1
+}
+
+/// Milliseconds since an `Instant`, saturating at u32::MAX
+pub fn elapsed_ms(since: Instant) -> u32 {
+    since.elapsed().as_millis().min(u32::MAX as u128) as u32
+}
+
+/// Payload of the client's echo, control id excluded
+pub fn encode_echo(capture_ms: u64, render_ms: u32) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(REPLY_LEN);
+    payload.extend(&capture_ms.to_le_bytes());
+    payload.extend(&render_ms.to_le_bytes());
+    payload
+}
+
+/// Capture time and local wait from an echo (or the start of a reply)
+pub fn decode_echo(payload: &[u8]) -> Option<(u64, u32)> {
+    if payload.len() < ECHO_LEN {
+        return None;
+    }
+    let capture_ms = u64::from_le_bytes(payload[..8].try_into().ok()?);
+    let render_ms = u32::from_le_bytes(payload[8..12].try_into().ok()?);
+    Some((capture_ms, render_ms))
+}
+
+/// Host side: the echo with the time since capture appended, on the host's clock
+pub fn reply_to_echo(echo: &[u8]) -> Option<Vec<u8>> {
+    let (capture_ms, render_ms) = decode_echo(echo)?;
+    let host_elapsed = clock_ms().saturating_sub(capture_ms).min(u32::MAX as u64) as u32;
+    let mut reply = encode_echo(capture_ms, render_ms);
+    reply.extend(&host_elapsed.to_le_bytes());
+    Some(reply)
+}
+
+/// Client side: time since capture from the host's answer
+pub fn decode_reply(payload: &[u8]) -> Option<(u64, u32)> {
+    if payload.len() < REPLY_LEN {
+        return None;
+    }
+    let (capture_ms, _) = decode_echo(payload)?;
+    let host_elapsed = u32::from_le_bytes(payload[12..16].try_into().ok()?);
+    Some((capture_ms, host_elapsed))
+}
+
+/// One measured frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Sample {
+    /// Capture on the host to presentation here
+    total_ms: u32,
+    /// Echo round trip, on our clock
+    network_rtt_ms: u32,
+    /// Arrival here to presentation (jitter buffer and IPC wait)
+    render_ms: u32,
+}
+
+/// Latency figures for `get_session_stats`; all zero until the first sample
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LatencyStats {
+    pub samples: usize,
+    pub last_ms: u32,
+    pub avg_ms: u32,
+    pub p95_ms: u32,
+    pub max_ms: u32,
+    /// Average network round trip of the echoes
+    pub network_rtt_ms: u32,
+    /// Average time frames waited here before being shown
+    pub render_ms: u32,
+}
+
+#[derive(Default)]
+pub struct LatencyTracker {
+    samples: VecDeque<Sample>,
+}
+
+impl LatencyTracker {
+    /// Add a measurement. `host_elapsed_ms` runs from capture until the echo
+    /// reached the host, so half the echo round trip comes off it for the way back
+    pub fn record(&mut self, host_elapsed_ms: u32, network_rtt_ms: u32, render_ms: u32) -> u32 {
+        let total_ms = host_elapsed_ms.saturating_sub(network_rtt_ms / 2).max(render_ms);
+        if self.samples.len() >= WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample { total_ms, network_rtt_ms, render_ms });
+        total_ms
+    }
+
+    pub fn stats(&self) -> LatencyStats {
+        let count = self.samples.len();
+        if count == 0 {
+            return LatencyStats::default();
+        }
+        let average = |field: fn(&Sample) -> u32| {
+            (self.samples.iter().map(|s| field(s) as u64).sum::<u64>() / count as u64) as u32
+        };
+
+        let mut totals: Vec<u32> = self.samples.iter().map(|s| s.total_ms).collect();
+        totals.sort_unstable();
+        let p95 = totals[(count * 95).div_ceil(100).saturating_sub(1)];
+
+        LatencyStats {
+            samples: count,
+            last_ms: self.samples.back().map_or(0, |s| s.total_ms),
+            avg_ms: average(|s| s.total_ms),
+            p95_ms: p95,
+            max_ms: totals[count - 1],
+            network_rtt_ms: average(|s| s.network_rtt_ms),
+            render_ms: average(|s| s.render_ms),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_echo_round_trip() {
+        let echo = encode_echo(1234, 7);
+        assert_eq!(echo.len(), ECHO_LEN);
+        assert_eq!(decode_echo(&echo), Some((1234, 7)));
+        assert!(decode_echo(&echo[..11]).is_none());
+
+        let stamp = clock_ms();
+        assert!(stamp > 0);
+        let reply = reply_to_echo(&encode_echo(stamp, 3)).unwrap();
+        let (capture_ms, host_elapsed) = decode_reply(&reply).unwrap();
+        assert_eq!(capture_ms, stamp);
+        assert!(host_elapsed < 1000);
+        // An echo is not a reply
+        assert!(decode_reply(&echo).is_none());
+    }
+
+    #[test]
+    fn test_tracker_stats() {
+        let mut tracker = LatencyTracker::default();
+        assert_eq!(tracker.stats(), LatencyStats::default());
+
+        // 100ms from capture to the echo reaching the host, 40ms of it the way back
+        assert_eq!(tracker.record(100, 80, 10), 60);
+        // Never below the time the frame is known to have waited here
+        assert_eq!(tracker.record(20, 80, 15), 15);
+        for _ in 0..18 {
+            tracker.record(50, 20, 5);
+        }
+        tracker.record(500, 20, 5);
+
+        let stats = tracker.stats();
+        assert_eq!(stats.samples, 21);
+        assert_eq!(stats.last_ms, 490);
+        assert_eq!(stats.max_ms, 490);
+        assert_eq!(stats.p95_ms, 60);
+        assert_eq!(stats.network_rtt_ms, (80 * 2 + 20 * 19) / 21);
+
+        for _ in 0..WINDOW {
+            tracker.record(30, 0, 0);
+        }
+        assert_eq!(tracker.stats().samples, WINDOW);
+        assert_eq!(tracker.stats().max_ms, 30);
+    }
+}
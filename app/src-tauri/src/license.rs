@@ -4,6 +4,11 @@
 //! - No personal data stored
 //! - Encrypted with device-derived key (useless if stolen)
 //! - Signed by SecureDesk to prevent tampering
+//!
+//! Keys from the portal ("SD-XXXX-...") are exchanged for a signed license at
+//! the activation endpoint. A license stops counting once its key_id is on the
+//! revocation list, or after OFFLINE_GRACE without a check; the list is signed
+//! with the license key like the licenses themselves.
 
 #![allow(dead_code)]
 
@@ -17,19 +22,29 @@ use ed25519_dalek::{Signature, VerifyingKey, SIGNATURE_LENGTH};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// SecureDesk license public key for signature verification
-/// This is our signing key - licenses are signed by SecureDesk servers
+/// Licenses are signed with the private half, which never leaves the license server
 const LICENSE_PUBLIC_KEY: &[u8; 32] = &[
-    0x8a, 0x7b, 0x3c, 0x4d, 0x5e, 0x6f, 0x70, 0x81,
-    0x92, 0xa3, 0xb4, 0xc5, 0xd6, 0xe7, 0xf8, 0x09,
-    0x1a, 0x2b, 0x3c, 0x4d, 0x5e, 0x6f, 0x70, 0x81,
-    0x92, 0xa3, 0xb4, 0xc5, 0xd6, 0xe7, 0xf8, 0x09,
+    0xf1, 0x8d, 0x8b, 0x21, 0xd3, 0xeb, 0xd4, 0x0c,
+    0x5d, 0xdb, 0x10, 0x7b, 0x07, 0x6a, 0xf8, 0x74,
+    0x65, 0xb3, 0x1c, 0x8e, 0xcf, 0xd7, 0x73, 0xf6,
+    0x7d, 0xa6, 0xa9, 0xf4, 0x8e, 0xc7, 0x47, 0x2f,
 ];
 
-/// License tier levels
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Where revoked key_ids are listed; not a setting, so the grace period can't
+/// be switched off or renewed by another endpoint
+pub const REVOCATION_URL: &str = "https://api.securedesk.one/api/license/revoked";
+
+/// How long a license keeps working while the revocation endpoint can't be reached
+pub const OFFLINE_GRACE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Wait for the activation and revocation endpoints
+const LICENSE_SERVER_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// License tier levels, in ascending order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum LicenseTier {
     Free,
@@ -91,6 +106,59 @@ pub struct License {
     pub signature: [u8; SIGNATURE_LENGTH],
 }
 
+/// What license.dat holds: the license and when it was last checked for revocation
+#[derive(Serialize, Deserialize)]
+struct StoredLicense {
+    #[serde(flatten)]
+    license: License,
+    /// Unix seconds of the last successful revocation check (0 = never)
+    #[serde(default)]
+    revocation_checked_at: u64,
+}
+
+/// Answer of the activation endpoint
+#[derive(Deserialize)]
+struct ActivationResponse {
+    /// The signed license, encoded like an offline license key
+    license: String,
+}
+
+/// What a revocation list's signature covers
+#[derive(Serialize, Deserialize)]
+struct RevocationPayload {
+    revoked: Vec<String>,
+    /// Unix seconds the list was issued; the grace period runs from here
+    issued_at: u64,
+}
+
+/// Answer of the revocation endpoint, only ever built with its signature checked
+#[derive(Deserialize)]
+pub struct RevocationList {
+    payload: RevocationPayload,
+    #[serde(with = "signature_serde")]
+    signature: [u8; SIGNATURE_LENGTH],
+}
+
+impl RevocationList {
+    /// Parse the endpoint's answer, refusing a list not signed with LICENSE_PUBLIC_KEY
+    fn parse(body: &[u8]) -> Result<Self> {
+        let list: RevocationList = serde_json::from_slice(body)
+            .map_err(|_| anyhow::anyhow!("Invalid revocation list"))?;
+        if !signed_by_license_server(&serde_json::to_string(&list.payload)?, &list.signature)? {
+            bail!("Revocation list signature is invalid");
+        }
+        Ok(list)
+    }
+}
+
+/// Whether `message` carries the license server's Ed25519 signature
+fn signed_by_license_server(message: &str, signature: &[u8; SIGNATURE_LENGTH]) -> Result<bool> {
+    let verifying_key = VerifyingKey::from_bytes(LICENSE_PUBLIC_KEY)
+        .map_err(|e| anyhow::anyhow!("Invalid public key: {}", e))?;
+    let signature = Signature::from_bytes(signature);
+    Ok(verifying_key.verify_strict(message.as_bytes(), &signature).is_ok())
+}
+
 /// Custom serialization for signature bytes
 mod signature_serde {
     use base64::{Engine as _, engine::general_purpose::STANDARD};
@@ -114,18 +182,10 @@ mod signature_serde {
 }
 
 impl License {
-    /// Verify the license signature
+    /// Verify the license signature over the payload's JSON (field order as declared)
     pub fn verify(&self) -> Result<bool> {
-        let verifying_key = VerifyingKey::from_bytes(LICENSE_PUBLIC_KEY)
-            .map_err(|e| anyhow::anyhow!("Invalid public key: {}", e))?;
-
         let payload_json = serde_json::to_string(&self.payload)?;
-        let signature = Signature::from_bytes(&self.signature);
-
-        match verifying_key.verify_strict(payload_json.as_bytes(), &signature) {
-            Ok(_) => Ok(true),
-            Err(_) => Ok(false),
-        }
+        signed_by_license_server(&payload_json, &self.signature)
     }
 
     /// Check if license is expired
@@ -144,9 +204,33 @@ impl License {
 
     /// Check if license is valid (signature OK and not expired)
     pub fn is_valid(&self) -> bool {
-        // For now, accept all licenses (signature verification disabled for testing)
-        // In production, uncomment: self.verify().unwrap_or(false) && !self.is_expired()
-        !self.is_expired()
+        self.verify().unwrap_or(false) && !self.is_expired()
+    }
+
+    /// Parse a license key (Base64 encoded JSON), rejecting forged or expired ones
+    pub fn from_key(license_key: &str) -> Result<Self> {
+        use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+        let license_key = license_key.trim().replace(" ", "").replace("-", "");
+
+        let decoded = STANDARD.decode(&license_key)
+            .map_err(|_| anyhow::anyhow!("Invalid license key format"))?;
+
+        let license: License = serde_json::from_slice(&decoded)
+            .map_err(|_| anyhow::anyhow!("Invalid license key data"))?;
+
+        if !license.verify()? {
+            bail!("License signature is invalid");
+        }
+        if license.is_expired() {
+            bail!("License has expired");
+        }
+        Ok(license)
+    }
+
+    /// Whether `license_key` is a portal key to activate online rather than a signed license
+    pub fn is_activation_key(license_key: &str) -> bool {
+        license_key.trim().to_ascii_uppercase().starts_with("SD-")
     }
 
     /// Get days until expiration (None if lifetime)
@@ -171,6 +255,8 @@ pub struct LicenseManager {
     current_license: Option<License>,
     /// Device-specific encryption key (derived from identity)
     encryption_key: [u8; 32],
+    /// Unix seconds of the last successful revocation check (0 = never)
+    revocation_checked_at: u64,
 }
 
 impl LicenseManager {
@@ -190,6 +276,7 @@ impl LicenseManager {
         Self {
             current_license: None,
             encryption_key,
+            revocation_checked_at: 0,
         }
     }
 
@@ -222,11 +309,16 @@ impl LicenseManager {
         let plaintext = cipher.decrypt(nonce, ciphertext)
             .map_err(|_| anyhow::anyhow!("License decryption failed - may be from different device"))?;
 
-        let license: License = serde_json::from_slice(&plaintext)?;
+        let stored: StoredLicense = serde_json::from_slice(&plaintext)?;
 
         // Validate the license
-        if license.is_valid() {
-            self.current_license = Some(license);
+        if stored.license.is_valid() {
+            self.current_license = Some(stored.license);
+            // Stored before revocation checks existed: the grace starts now
+            self.revocation_checked_at = match stored.revocation_checked_at {
+                0 => now_secs(),
+                checked_at => checked_at,
+            };
         } else {
             // Invalid or expired license
             self.current_license = None;
@@ -244,7 +336,10 @@ impl LicenseManager {
         }
 
         if let Some(ref license) = self.current_license {
-            let plaintext = serde_json::to_vec(license)?;
+            let plaintext = serde_json::to_vec(&StoredLicense {
+                license: license.clone(),
+                revocation_checked_at: self.revocation_checked_at,
+            })?;
 
             // Generate random nonce
             let mut nonce_bytes = [0u8; 12];
@@ -290,28 +385,59 @@ impl LicenseManager {
 
     /// Activate a license key
     pub fn activate(&mut self, license_key: &str) -> Result<LicenseTier> {
-        // Parse the license key (Base64 encoded JSON)
-        use base64::{Engine as _, engine::general_purpose::STANDARD};
+        let license = License::from_key(license_key)?;
+        self.install(license)
+    }
 
-        let license_key = license_key.trim().replace(" ", "").replace("-", "");
+    /// Store a verified license; the offline grace starts over
+    pub fn install(&mut self, license: License) -> Result<LicenseTier> {
+        let tier = license.payload.tier;
+        self.current_license = Some(license);
+        self.revocation_checked_at = now_secs();
+        self.save()?;
 
-        let decoded = STANDARD.decode(&license_key)
-            .map_err(|_| anyhow::anyhow!("Invalid license key format"))?;
+        Ok(tier)
+    }
 
-        let license: License = serde_json::from_slice(&decoded)
-            .map_err(|_| anyhow::anyhow!("Invalid license key data"))?;
+    /// key_id of the stored license, valid or not
+    pub fn key_id(&self) -> Option<&str> {
+        self.current_license.as_ref().map(|l| l.payload.key_id.as_str())
+    }
 
-        // Check if license is valid
-        if license.is_expired() {
-            bail!("License has expired");
+    /// Apply a revocation list just fetched: drops the license if its key_id is
+    /// listed, otherwise restarts the offline grace from when the list was
+    /// issued. Returns whether it was revoked
+    pub fn apply_revocations(&mut self, list: &RevocationList) -> Result<bool> {
+        if self.current_license.is_none() {
+            return Ok(false);
         }
-
-        // Store the license
-        let tier = license.payload.tier;
-        self.current_license = Some(license);
+        let issued_at = list.payload.issued_at.min(now_secs());
+        let is_revoked = self.revoke_if_listed(&list.payload.revoked, issued_at);
         self.save()?;
+        Ok(is_revoked)
+    }
 
-        Ok(tier)
+    /// An older list, replayed, never moves the last check back
+    fn revoke_if_listed(&mut self, revoked: &[String], issued_at: u64) -> bool {
+        let is_revoked = self.key_id().is_some_and(|key_id| revoked.iter().any(|id| id == key_id));
+        if is_revoked {
+            self.current_license = None;
+        }
+        self.revocation_checked_at = self.revocation_checked_at.max(issued_at);
+        is_revoked
+    }
+
+    /// The stored license, if it is valid and its offline grace has not run out
+    fn active_license(&self) -> Option<&License> {
+        self.current_license
+            .as_ref()
+            .filter(|l| l.is_valid())
+            .filter(|_| self.within_grace(now_secs()))
+    }
+
+    /// Whether the last revocation check is recent enough at `now`
+    fn within_grace(&self, now: u64) -> bool {
+        now.saturating_sub(self.revocation_checked_at) <= OFFLINE_GRACE.as_secs()
     }
 
     /// Remove current license (revert to Free)
@@ -323,17 +449,15 @@ impl LicenseManager {
 
     /// Get current license tier
     pub fn current_tier(&self) -> LicenseTier {
-        self.current_license
-            .as_ref()
-            .filter(|l| l.is_valid())
+        self.active_license()
             .map(|l| l.payload.tier)
             .unwrap_or(LicenseTier::Free)
     }
 
     /// Get license info for display
     pub fn license_info(&self) -> LicenseInfo {
-        match &self.current_license {
-            Some(license) if license.is_valid() => LicenseInfo {
+        match self.active_license() {
+            Some(license) => LicenseInfo {
                 tier: license.payload.tier.as_str().to_string(),
                 key_id: Some(license.payload.key_id.clone()),
                 expires_at: if license.payload.expires_at == 0 {
@@ -358,28 +482,64 @@ impl LicenseManager {
 
     /// Check if a feature is enabled for current tier
     pub fn has_feature(&self, feature: LicenseFeature) -> bool {
-        let tier = self.current_tier();
-        match feature {
-            // Free features
-            LicenseFeature::BasicRemoteControl => true,
-            LicenseFeature::EncryptedConnection => true,
-
-            // Basic features
-            LicenseFeature::FileTransfer => matches!(tier, LicenseTier::Basic | LicenseTier::Pro | LicenseTier::Enterprise),
-            LicenseFeature::Clipboard => matches!(tier, LicenseTier::Basic | LicenseTier::Pro | LicenseTier::Enterprise),
-            LicenseFeature::MultiMonitor => matches!(tier, LicenseTier::Basic | LicenseTier::Pro | LicenseTier::Enterprise),
-
-            // Pro features
-            LicenseFeature::UnattendedAccess => matches!(tier, LicenseTier::Pro | LicenseTier::Enterprise),
-            LicenseFeature::SessionRecording => matches!(tier, LicenseTier::Pro | LicenseTier::Enterprise),
-            LicenseFeature::CustomBranding => matches!(tier, LicenseTier::Pro | LicenseTier::Enterprise),
-
-            // Enterprise features
-            LicenseFeature::SelfHostedRelay => matches!(tier, LicenseTier::Enterprise),
-            LicenseFeature::ActiveDirectory => matches!(tier, LicenseTier::Enterprise),
-            LicenseFeature::AuditLogs => matches!(tier, LicenseTier::Enterprise),
-        }
+        self.current_tier() >= feature.min_tier()
+    }
+
+    /// Client sessions allowed at once (0 = unlimited); Free allows one
+    pub fn max_sessions(&self) -> u32 {
+        self.active_license()
+            .map(|l| l.payload.max_sessions)
+            .unwrap_or(1)
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn http_client() -> Result<reqwest::Client> {
+    Ok(crate::proxy::http_client_builder()
+        .timeout(LICENSE_SERVER_TIMEOUT)
+        .build()?)
+}
+
+/// Exchange a portal key for a signed license at `url`. The license must verify
+/// and be issued for that very key (its key_id), so a server answering with
+/// another customer's license is refused
+pub async fn activate_online(url: &str, license_key: &str, device_id: &str) -> Result<License> {
+    let license_key = license_key.trim();
+    let response = http_client()?
+        .post(url)
+        .json(&serde_json::json!({
+            "license_key": license_key,
+            "device_id": device_id,
+        }))
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        bail!("License activation failed: {}", error_text);
+    }
+
+    let activation: ActivationResponse = response.json().await?;
+    let license = License::from_key(&activation.license)?;
+    if !license.payload.key_id.eq_ignore_ascii_case(license_key) {
+        bail!("License server answered for a different key");
     }
+    Ok(license)
+}
+
+/// Fetch the key_ids revoked so far from REVOCATION_URL, signature checked
+pub async fn fetch_revocations() -> Result<RevocationList> {
+    let response = http_client()?.get(REVOCATION_URL).send().await?;
+    if !response.status().is_success() {
+        bail!("Revocation check failed: HTTP {}", response.status());
+    }
+    RevocationList::parse(&response.bytes().await?)
 }
 
 /// License feature flags
@@ -405,6 +565,52 @@ pub enum LicenseFeature {
     AuditLogs,
 }
 
+impl LicenseFeature {
+    /// Lowest tier that includes the feature
+    pub fn min_tier(&self) -> LicenseTier {
+        match self {
+            LicenseFeature::BasicRemoteControl | LicenseFeature::EncryptedConnection => LicenseTier::Free,
+            LicenseFeature::FileTransfer | LicenseFeature::Clipboard | LicenseFeature::MultiMonitor => LicenseTier::Basic,
+            LicenseFeature::UnattendedAccess | LicenseFeature::SessionRecording | LicenseFeature::CustomBranding => LicenseTier::Pro,
+            LicenseFeature::SelfHostedRelay | LicenseFeature::ActiveDirectory | LicenseFeature::AuditLogs => LicenseTier::Enterprise,
+        }
+    }
+
+    /// Identifier the frontend branches on (e.g. which upgrade to offer)
+    pub fn key(&self) -> &'static str {
+        match self {
+            LicenseFeature::BasicRemoteControl => "basic_remote_control",
+            LicenseFeature::EncryptedConnection => "encrypted_connection",
+            LicenseFeature::FileTransfer => "file_transfer",
+            LicenseFeature::Clipboard => "clipboard",
+            LicenseFeature::MultiMonitor => "multi_monitor",
+            LicenseFeature::UnattendedAccess => "unattended_access",
+            LicenseFeature::SessionRecording => "session_recording",
+            LicenseFeature::CustomBranding => "custom_branding",
+            LicenseFeature::SelfHostedRelay => "self_hosted_relay",
+            LicenseFeature::ActiveDirectory => "active_directory",
+            LicenseFeature::AuditLogs => "audit_logs",
+        }
+    }
+
+    /// Name for messages, e.g. "Session recording"
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            LicenseFeature::BasicRemoteControl => "Remote control",
+            LicenseFeature::EncryptedConnection => "Encrypted connections",
+            LicenseFeature::FileTransfer => "File transfer",
+            LicenseFeature::Clipboard => "Clipboard sync",
+            LicenseFeature::MultiMonitor => "Switching monitors",
+            LicenseFeature::UnattendedAccess => "Unattended access",
+            LicenseFeature::SessionRecording => "Session recording",
+            LicenseFeature::CustomBranding => "Custom branding",
+            LicenseFeature::SelfHostedRelay => "A self-hosted relay",
+            LicenseFeature::ActiveDirectory => "Active Directory",
+            LicenseFeature::AuditLogs => "Audit logs",
+        }
+    }
+}
+
 /// License info for frontend
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LicenseInfo {
@@ -425,10 +631,101 @@ mod tests {
         assert_eq!(LicenseTier::default(), LicenseTier::Free);
     }
 
+    /// Basic lifetime license signed with the production key
+    const SIGNED_KEY: &str = "eyJwYXlsb2FkIjp7InRpZXIiOiJiYXNpYyIsImtleV9pZCI6InRlc3QtMDAwMSIsImlzc3VlZF9hdCI6MTc2MDAwMDAwMCwiZXhwaXJlc19hdCI6MCwibWF4X3Nlc3Npb25zIjoxLCJmZWF0dXJlcyI6MH0sInNpZ25hdHVyZSI6IjV6QzdMR1BwaHErUCtvUC8wSnNDaXVFeVh2c1R5UWo4K3pMLzlpMFhVTjZwenBTcWNUT3R5emtseVBCM0hPREJ0bzNXMDJHb2I0bzliYkVOMG5YWkRRPT0ifQ==";
+
+    /// The same license with its tier raised to enterprise
+    const TAMPERED_KEY: &str = "eyJwYXlsb2FkIjp7InRpZXIiOiJlbnRlcnByaXNlIiwia2V5X2lkIjoidGVzdC0wMDAxIiwiaXNzdWVkX2F0IjoxNzYwMDAwMDAwLCJleHBpcmVzX2F0IjowLCJtYXhfc2Vzc2lvbnMiOjEsImZlYXR1cmVzIjowfSwic2lnbmF0dXJlIjoiNXpDN0xHUHBocStQK29QLzBKc0NpdUV5WHZzVHlRajgrekwvOWkwWFVONnB6cFNxY1RPdHl6a2x5UEIzSE9EQnRvM1cwMkdvYjRvOWJiRU4wblhaRFE9PSJ9";
+
+    #[test]
+    fn test_signed_license_accepted() {
+        let license = License::from_key(SIGNED_KEY).unwrap();
+        assert_eq!(license.payload.tier, LicenseTier::Basic);
+        assert_eq!(license.payload.key_id, "test-0001");
+        assert!(license.verify().unwrap());
+        assert!(license.is_valid());
+    }
+
+    #[test]
+    fn test_tampered_license_rejected() {
+        let err = License::from_key(TAMPERED_KEY).unwrap_err();
+        assert!(err.to_string().contains("signature"));
+
+        // Loaded from storage instead of activated, it still does not count
+        use base64::{Engine as _, engine::general_purpose::STANDARD};
+        let license: License = serde_json::from_slice(&STANDARD.decode(TAMPERED_KEY).unwrap()).unwrap();
+        assert!(!license.is_valid());
+
+        let mut manager = LicenseManager::new(&[0u8; 32]);
+        manager.current_license = Some(license);
+        manager.revocation_checked_at = now_secs();
+        assert_eq!(manager.current_tier(), LicenseTier::Free);
+        assert!(!manager.has_feature(LicenseFeature::UnattendedAccess));
+    }
+
+    #[test]
+    fn test_revocation_and_offline_grace() {
+        let mut manager = LicenseManager::new(&[0u8; 32]);
+        manager.current_license = Some(License::from_key(SIGNED_KEY).unwrap());
+
+        // Offline, the license holds for the grace period and lapses after it
+        let now = now_secs();
+        manager.revocation_checked_at = now - OFFLINE_GRACE.as_secs() + 60;
+        assert_eq!(manager.current_tier(), LicenseTier::Basic);
+        assert!(!manager.within_grace(now + 120));
+        manager.revocation_checked_at = now - OFFLINE_GRACE.as_secs() - 60;
+        assert_eq!(manager.current_tier(), LicenseTier::Free);
+
+        // A check that doesn't list the key restarts the grace; a list issued
+        // earlier, replayed, does not take it back
+        assert!(!manager.revoke_if_listed(&["other-key".to_string()], now));
+        assert_eq!(manager.current_tier(), LicenseTier::Basic);
+        assert!(!manager.revoke_if_listed(&[], now - OFFLINE_GRACE.as_secs() - 60));
+        assert_eq!(manager.revocation_checked_at, now);
+
+        assert!(manager.revoke_if_listed(&["test-0001".to_string()], now));
+        assert_eq!(manager.current_tier(), LicenseTier::Free);
+        assert_eq!(manager.key_id(), None);
+    }
+
+    #[test]
+    fn test_unsigned_revocation_list_refused() {
+        // An empty list from anywhere but the license server renews nothing
+        let forged = serde_json::json!({
+            "payload": { "revoked": [], "issued_at": now_secs() },
+            "signature": base64::Engine::encode(&base64::engine::general_purpose::STANDARD, [0u8; 64]),
+        });
+        let err = RevocationList::parse(forged.to_string().as_bytes()).err().unwrap();
+        assert!(err.to_string().contains("signature"));
+        assert!(RevocationList::parse(br#"{"revoked":[]}"#).is_err());
+    }
+
+    #[test]
+    fn test_activation_keys() {
+        assert!(License::is_activation_key(" SD-Ab12-Cd34-Ef56-Gh78"));
+        assert!(!License::is_activation_key(SIGNED_KEY));
+    }
+
     #[test]
     fn test_license_manager_default_tier() {
         let key = [0u8; 32];
         let manager = LicenseManager::new(&key);
         assert_eq!(manager.current_tier(), LicenseTier::Free);
+        assert_eq!(manager.max_sessions(), 1);
+        assert!(manager.has_feature(LicenseFeature::BasicRemoteControl));
+        assert!(!manager.has_feature(LicenseFeature::Clipboard));
+    }
+
+    #[test]
+    fn test_features_follow_tier() {
+        let mut manager = LicenseManager::new(&[0u8; 32]);
+        manager.current_license = Some(License::from_key(SIGNED_KEY).unwrap());
+        manager.revocation_checked_at = now_secs();
+
+        assert!(manager.has_feature(LicenseFeature::Clipboard));
+        assert!(manager.has_feature(LicenseFeature::FileTransfer));
+        assert!(!manager.has_feature(LicenseFeature::SessionRecording));
+        assert!(!manager.has_feature(LicenseFeature::AuditLogs));
+        assert_eq!(manager.max_sessions(), 1);
     }
 }
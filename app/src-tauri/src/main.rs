@@ -28,18 +28,45 @@ mod clipboard;
 mod recording;
 mod cli;
 mod sso;
-
+mod elevation;
+mod session_events;
+mod mux;
+mod sources;
+mod jitter;
+mod proxy;
+mod metrics;
+mod keymap;
+mod ipc;
+mod backoff;
+mod transcript;
+mod policy;
+mod latency;
+mod tls;
+mod debounce;
+mod error;
+mod lan;
+mod tiles;
+mod cursor;
+mod transfer;
+mod audio;
+#[cfg(feature = "codec")]
+mod vpx;
+
+use error::AppError;
 use parking_lot::Mutex as SyncMutex;
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use tauri::{
-    Manager, WindowEvent,
+    Emitter, Manager, WindowEvent,
     menu::{Menu, MenuItem},
     tray::{TrayIconBuilder, TrayIconEvent, MouseButton, MouseButtonState},
 };
 use tokio::sync::Mutex as AsyncMutex;
 
+/// How long to wait for the host to acknowledge pushed clipboard data
+const CLIPBOARD_ACK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
 /// Relay server addresses (multiple for failover and load balancing)
 const RELAY_SERVERS: &[&str] = &[
     "relay.securedesk.one:8443",
@@ -47,19 +74,144 @@ const RELAY_SERVERS: &[&str] = &[
 ];
 
 /// Client session with metadata
+/// The connection has a lock of its own, held across network I/O, so a slow
+/// frame only holds up its own session; the rest stays readable meanwhile
 pub struct ClientSessionEntry {
-    session: client::ClientSession,
     remote_id: String,
     remote_name: String,
     connected_at: u64,
+    preferences: SyncMutex<config::DevicePreferences>,
+    /// Last time the user interacted with this session (unix ms)
+    last_activity: AtomicU64,
+    /// What `list_sessions` shows, as of the last time the connection was unlocked
+    status: SyncMutex<SessionStatus>,
+    link: AsyncMutex<SessionLink>,
+}
+
+/// The part of a session that waits on the network
+struct SessionLink {
+    session: client::ClientSession,
+    /// Frames held back to smooth presentation, see `jitter_buffer_frames`
+    jitter: jitter::JitterBuffer<client::RemoteFrame>,
+}
+
+/// Session state reported by `list_sessions`
+#[derive(Clone)]
+struct SessionStatus {
+    connection_type: String,
+    host_elevated: Option<bool>,
+    focus_lock: Option<bool>,
+    audio: Option<bool>,
+    quality: &'static str,
+}
+
+impl SessionStatus {
+    fn of(session: &client::ClientSession) -> Self {
+        Self {
+            connection_type: session.connection_type().to_string(),
+            host_elevated: session.host_elevated(),
+            focus_lock: session.focus_lock(),
+            audio: session.audio_enabled(),
+            quality: session.quality_setting(),
+        }
+    }
+}
+
+impl ClientSessionEntry {
+    fn new(
+        session: client::ClientSession,
+        remote_id: String,
+        remote_name: String,
+        connected_at: u64,
+        preferences: config::DevicePreferences,
+    ) -> Self {
+        Self {
+            remote_id,
+            remote_name,
+            connected_at,
+            preferences: SyncMutex::new(preferences),
+            last_activity: AtomicU64::new(now_millis()),
+            status: SyncMutex::new(SessionStatus::of(&session)),
+            link: AsyncMutex::new(SessionLink { session, jitter: jitter::JitterBuffer::new(0) }),
+        }
+    }
+
+    /// Wait for the connection; the status refreshes when it is unlocked again
+    async fn lock(&self) -> LinkGuard<'_> {
+        LinkGuard { entry: self, link: self.link.lock().await }
+    }
+
+    /// Mark the session as just used
+    fn touch(&self) {
+        self.last_activity.store(now_millis(), Ordering::Relaxed);
+    }
+
+    fn last_activity(&self) -> u64 {
+        self.last_activity.load(Ordering::Relaxed)
+    }
+
+    fn view_only(&self) -> bool {
+        self.preferences.lock().view_only
+    }
+}
+
+/// A locked session connection
+struct LinkGuard<'a> {
+    entry: &'a ClientSessionEntry,
+    link: tokio::sync::MutexGuard<'a, SessionLink>,
+}
+
+impl std::ops::Deref for LinkGuard<'_> {
+    type Target = SessionLink;
+
+    fn deref(&self) -> &SessionLink {
+        &self.link
+    }
+}
+
+impl std::ops::DerefMut for LinkGuard<'_> {
+    fn deref_mut(&mut self) -> &mut SessionLink {
+        &mut self.link
+    }
+}
+
+impl Drop for LinkGuard<'_> {
+    fn drop(&mut self) {
+        *self.entry.status.lock() = SessionStatus::of(&self.link.session);
+    }
+}
+
+/// Current unix time in milliseconds
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Session ids ordered most recently used first (ties broken by id so the order is stable)
+fn sessions_by_recent_use(sessions: &HashMap<String, Arc<ClientSessionEntry>>) -> Vec<String> {
+    let mut ids: Vec<(&String, u64)> = sessions
+        .iter()
+        .map(|(id, entry)| (id, entry.last_activity()))
+        .collect();
+    ids.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    ids.into_iter().map(|(id, _)| id.clone()).collect()
 }
 
 /// Global application state
 struct AppState {
     identity: SyncMutex<crypto::Identity>,
     host_session: AsyncMutex<Option<host::HostSession>>,
+    /// Handle to the current host session (approval queue, chat) - kept outside
+    /// `host_session` because the host loop holds that lock while waiting for frames
+    host_handle: SyncMutex<Option<host::HostHandle>>,
+    /// Background task running (and reconnecting) the host session
+    /// Held while the listener starts or stops, so overlapping calls never run two
+    host_task: AsyncMutex<Option<tokio::task::JoinHandle<()>>>,
     /// Multiple client sessions - key is session_id (auto-generated)
-    client_sessions: AsyncMutex<HashMap<String, ClientSessionEntry>>,
+    /// Only held to look a session up, never across I/O - see `ClientSessionEntry`
+    client_sessions: SyncMutex<HashMap<String, Arc<ClientSessionEntry>>>,
     /// Currently active session ID for commands without explicit session_id
     active_session_id: SyncMutex<Option<String>>,
     /// Counter for generating session IDs
@@ -69,7 +221,71 @@ struct AppState {
     license_manager: SyncMutex<license::LicenseManager>,
     clipboard_manager: clipboard::ClipboardManager,
     recording_manager: recording::RecordingManager,
+    /// Stops the recording being played back, if one is
+    playback_stop: SyncMutex<Option<Arc<AtomicBool>>>,
     sso_manager: AsyncMutex<sso::SsoManager>,
+    /// Per-session event timeline shown in the UI and exported as transcripts
+    /// (kept for a few sessions after they end)
+    session_events: SyncMutex<session_events::SessionEventLog>,
+    /// Shared relay connections by relay address (None = relay can't multiplex)
+    relay_muxes: AsyncMutex<HashMap<String, Option<Arc<mux::RelayMux>>>>,
+}
+
+impl AppState {
+    /// A client session by id
+    fn client_session(&self, session_id: &str) -> Option<Arc<ClientSessionEntry>> {
+        self.client_sessions.lock().get(session_id).cloned()
+    }
+
+    /// Sessions connected to `remote_id` (spaces ignored), most recently used first
+    fn session_ids_for_remote(&self, remote_id: &str) -> Vec<String> {
+        let clean_id = remote_id.replace(' ', "");
+        let sessions = self.client_sessions.lock();
+        sessions_by_recent_use(&sessions)
+            .into_iter()
+            .filter(|id| sessions.get(id).is_some_and(|e| e.remote_id.replace(' ', "") == clean_id))
+            .collect()
+    }
+
+    /// The session to use for `remote_id` - the most recently used one if there are several
+    fn get_session_id_for_remote(&self, remote_id: &str) -> Option<String> {
+        self.session_ids_for_remote(remote_id).into_iter().next()
+    }
+
+    /// Disconnect one session; false if no such session exists
+    async fn disconnect(&self, session_id: &str) -> anyhow::Result<bool> {
+        let entry = {
+            let mut sessions = self.client_sessions.lock();
+            let Some(entry) = sessions.remove(session_id) else {
+                return Ok(false);
+            };
+
+            // If this was the active session, fall back to the most recently used one (or None)
+            let mut active_id = self.active_session_id.lock();
+            if active_id.as_deref() == Some(session_id) {
+                *active_id = sessions_by_recent_use(&sessions).into_iter().next();
+            }
+            entry
+        };
+
+        println!("[MAIN] Disconnecting session {}", session_id);
+        self.session_events.lock().end(session_id);
+
+        // Waits out a frame request still in flight on this session
+        entry.lock().await.session.disconnect().await?;
+        Ok(true)
+    }
+
+    /// Disconnect every session to `remote_id`; returns how many were closed
+    async fn disconnect_by_remote_id(&self, remote_id: &str) -> anyhow::Result<usize> {
+        let mut count = 0;
+        for session_id in self.session_ids_for_remote(remote_id) {
+            if self.disconnect(&session_id).await? {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
 }
 
 // ============================================================================
@@ -82,11 +298,32 @@ fn get_device_id(state: tauri::State<Arc<AppState>>) -> String {
     state.identity.lock().device_id()
 }
 
+/// Get this device's key fingerprint, for reading out to the other side
+#[tauri::command]
+fn get_device_fingerprint(state: tauri::State<Arc<AppState>>) -> String {
+    state.identity.lock().fingerprint()
+}
+
+/// Get the verified key fingerprint of a connected device
+/// None for hosts that predate identity verification
+#[tauri::command]
+async fn get_remote_fingerprint(
+    state: tauri::State<'_, Arc<AppState>>,
+    session_id: Option<String>,
+) -> Result<Option<String>, AppError> {
+    let target_id = session_id
+        .or_else(|| state.active_session_id.lock().clone())
+        .ok_or(AppError::NoActiveSession)?;
+
+    let entry = state.client_session(&target_id).ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+    let link = entry.lock().await;
+    Ok(link.session.remote_fingerprint().map(str::to_string))
+}
+
 /// Regenerate device ID (creates new identity)
 #[tauri::command]
-fn regenerate_device_id(state: tauri::State<Arc<AppState>>) -> Result<String, String> {
-    let new_identity = crypto::Identity::regenerate()
-        .map_err(|e| e.to_string())?;
+fn regenerate_device_id(state: tauri::State<Arc<AppState>>) -> Result<String, AppError> {
+    let new_identity = crypto::Identity::regenerate()?;
     let new_id = new_identity.device_id();
     *state.identity.lock() = new_identity;
     Ok(new_id)
@@ -105,29 +342,38 @@ fn set_relay_address(state: tauri::State<Arc<AppState>>, address: String) {
 }
 
 /// Start listening for incoming connections (host mode)
-/// Tries each relay server until one works
+/// Tries each relay server until one works. Does nothing while the listener
+/// runs; `stop_host_listener` first to restart it (e.g. with a new identity)
 #[tauri::command]
 async fn start_host_listener(
     state: tauri::State<'_, Arc<AppState>>,
     app_handle: tauri::AppHandle,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
+    let mut host_task = state.host_task.lock().await;
+    if host_task.as_ref().is_some_and(|task| !task.is_finished()) {
+        println!("[MAIN] Host listener already running");
+        return Ok(());
+    }
+
     let relays = state.relay_addresses.lock().clone();
     let identity = state.identity.lock().clone();
+    let alias = state.connection_config.lock().relay_alias();
 
     let mut last_error = String::from("No relay servers configured");
 
     for relay in relays {
         println!("[MAIN] Trying to connect to relay: {}", relay);
-        match host::HostSession::start(relay.clone(), identity.clone()).await {
+        match host::HostSession::start_with_alias(relay.clone(), identity.clone(), alias.clone()).await {
             Ok(session) => {
                 println!("[MAIN] Connected to relay: {}", relay);
+                *state.host_handle.lock() = Some(session.handle());
                 *state.host_session.lock().await = Some(session);
 
                 // Spawn background task to run the host session
                 let state_clone = state.inner().clone();
                 let app_handle_clone = app_handle.clone();
                 println!("[MAIN] Spawning background host session task");
-                tokio::spawn(async move {
+                *host_task = Some(tokio::spawn(async move {
                     println!("[MAIN-TASK] Host session background task started");
                     loop {
                         // Take the session to run it
@@ -142,19 +388,40 @@ async fn start_host_listener(
                                     *session_opt = None;
                                     drop(session_opt);
 
-                                    // Try to reconnect after a delay
-                                    println!("[MAIN-TASK] Reconnecting in 5 seconds...");
-                                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
-
-                                    // Attempt reconnection
-                                    let relays = state_clone.relay_addresses.lock().clone();
-                                    let identity = state_clone.identity.lock().clone();
-                                    for relay in relays {
-                                        println!("[MAIN-TASK] Trying relay: {}", relay);
-                                        if let Ok(new_session) = host::HostSession::start(relay, identity.clone()).await {
-                                            println!("[MAIN-TASK] Reconnected successfully");
-                                            *state_clone.host_session.lock().await = Some(new_session);
-                                            break;
+                                    // Back off between rounds over the relays, with jitter so
+                                    // hosts that lost the relay together don't return in lockstep.
+                                    // Each outage starts from the shortest wait
+                                    let mut backoff = {
+                                        let settings = &state_clone.connection_config.lock().settings;
+                                        backoff::Backoff::new(
+                                            std::time::Duration::from_secs(settings.reconnect_max_backoff_secs as u64),
+                                            settings.reconnect_max_attempts,
+                                        )
+                                    };
+                                    'reconnect: loop {
+                                        let Some(delay) = backoff.next_delay() else {
+                                            eprintln!("[MAIN-TASK] Giving up after {} reconnect attempts", backoff.attempts());
+                                            lan::withdraw();
+                                            let _ = app_handle_clone.emit("host-reconnect-failed", serde_json::json!({
+                                                "attempts": backoff.attempts(),
+                                            }));
+                                            return;
+                                        };
+                                        println!("[MAIN-TASK] Reconnecting in {:.1}s (attempt {})...", delay.as_secs_f64(), backoff.attempts());
+                                        tokio::time::sleep(delay).await;
+
+                                        let relays = state_clone.relay_addresses.lock().clone();
+                                        let identity = state_clone.identity.lock().clone();
+                                        let alias = state_clone.connection_config.lock().relay_alias();
+                                        for relay in relays {
+                                            println!("[MAIN-TASK] Trying relay: {}", relay);
+                                            if let Ok(new_session) = host::HostSession::start_with_alias(relay, identity.clone(), alias.clone()).await {
+                                                println!("[MAIN-TASK] Reconnected successfully");
+                                                metrics::record_relay_reconnect();
+                                                *state_clone.host_handle.lock() = Some(new_session.handle());
+                                                *state_clone.host_session.lock().await = Some(new_session);
+                                                break 'reconnect;
+                                            }
                                         }
                                     }
                                     continue;
@@ -166,8 +433,9 @@ async fn start_host_listener(
                             tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
                         }
                     }
-                });
+                }));
 
+                update_lan_advert(&state.connection_config.lock(), &identity);
                 return Ok(());
             }
             Err(e) => {
@@ -178,7 +446,65 @@ async fn start_host_listener(
         }
     }
 
-    Err(last_error)
+    Err(AppError::Network(last_error))
+}
+
+/// Stop hosting: viewers get SESSION_END and the relay registration is dropped
+#[tauri::command]
+async fn stop_host_listener(state: tauri::State<'_, Arc<AppState>>) -> Result<(), AppError> {
+    stop_host(&state).await;
+    Ok(())
+}
+
+/// Stop the host listener's task first, so it cannot reconnect behind our
+/// back, then end the session, which also disables privacy mode
+async fn stop_host(state: &AppState) {
+    let mut host_task = state.host_task.lock().await;
+    if let Some(task) = host_task.take() {
+        task.abort();
+        let _ = task.await;
+    }
+    lan::withdraw();
+
+    let handle = state.host_handle.lock().take();
+    if let Some(handle) = handle {
+        handle.shutdown().await;
+    }
+    match tokio::time::timeout(HOST_STOP_TIMEOUT, state.host_session.lock()).await {
+        Ok(mut session) => {
+            if let Some(session) = session.take() {
+                let _ = session.stop().await;
+                println!("[MAIN] Host listener stopped");
+            }
+        }
+        Err(_) => eprintln!("[MAIN] Host session did not stop in time"),
+    }
+    // "For this session" grants end with the host
+    host::TEMPORARY_TRUST.lock().end_session(std::time::Instant::now());
+}
+
+/// Announce this host on the local network, or stop when it is hidden from the address book
+fn update_lan_advert(config: &config::ConnectionConfig, identity: &crypto::Identity) {
+    if config.settings.hide_from_address_book {
+        lan::withdraw();
+        return;
+    }
+    let device_id = identity.device_id_raw();
+    lan::advertise(lan::Advert {
+        port: p2p::choose_p2p_port(&device_id),
+        device_id,
+        alias: config.get_alias().cloned(),
+        name: lan::machine_name(),
+    });
+}
+
+/// SecureDesk hosts announcing themselves on the local network
+/// Connect with `connect_to_remote` and the device ID: the session is set up
+/// through the relay, then its P2P upgrade dials the address found here first
+#[tauri::command]
+async fn discover_lan_peers(state: tauri::State<'_, Arc<AppState>>) -> Result<Vec<lan::LanPeer>, AppError> {
+    let own_id = state.identity.lock().device_id_raw();
+    Ok(lan::discover(&own_id, lan::BROWSE_TIMEOUT).await?)
 }
 
 /// Session info for frontend display
@@ -190,25 +516,174 @@ pub struct SessionInfo {
     pub connected_at: u64,
     pub is_active: bool,
     pub connection_type: String,
+    pub view_only: bool,
+    /// Whether the host runs elevated (None until reported)
+    pub host_elevated: Option<bool>,
+    /// Whether keystrokes are pinned to one window (None if the host cannot do it)
+    pub focus_lock: Option<bool>,
+    /// Whether the host's sound plays (None if the host sends none)
+    pub audio: Option<bool>,
+    /// Quality level the session follows ("auto" adapts to the network)
+    pub quality: String,
+    /// Last user interaction (unix ms); list_sessions is sorted by this, newest first
+    pub last_activity: u64,
 }
 
 /// Connect to a remote device (client mode)
+/// Accepts a device ID, or an alias the relay resolves to one
 /// Tries each relay server until one works
 /// Returns the session_id for multi-session management
+/// `password` is the host's unattended access password, if it needs one
 #[tauri::command]
 async fn connect_to_remote(
     state: tauri::State<'_, Arc<AppState>>,
     remote_id: String,
     remote_name: Option<String>,
-) -> Result<String, String> {
+    password: Option<String>,
+) -> Result<String, AppError> {
+    let by_alias = !protocol::relay::is_device_id(&remote_id);
+    connect_via_relays(&state, remote_id, by_alias, remote_name, password).await
+}
+
+/// Connect to a remote device by alias (friendly name)
+/// The alias is resolved on each relay before connecting; relays without alias
+/// support fall back to treating it as a device ID
+#[tauri::command]
+async fn connect_by_alias(
+    state: tauri::State<'_, Arc<AppState>>,
+    alias: String,
+    remote_name: Option<String>,
+    password: Option<String>,
+) -> Result<String, AppError> {
+    connect_via_relays(&state, alias, true, remote_name, password).await
+}
+
+/// The shared connection to `relay`, opened on first use
+/// None when multiplexing is turned off or the relay doesn't support it -
+/// sessions then get a connection each
+async fn multiplexed_relay(
+    state: &Arc<AppState>,
+    relay: &str,
+    identity: &crypto::Identity,
+) -> Option<Arc<mux::RelayMux>> {
+    if !state.connection_config.lock().get_settings().relay_multiplexing {
+        return None;
+    }
+
+    let mut muxes = state.relay_muxes.lock().await;
+    match muxes.get(relay) {
+        Some(Some(mux)) if mux.is_alive() => return Some(mux.clone()),
+        // Not re-probed until restart
+        Some(None) => return None,
+        _ => {}
+    }
+
+    match mux::RelayMux::connect(relay, identity).await {
+        Ok(mux) => {
+            muxes.insert(relay.to_string(), mux.clone());
+            mux
+        }
+        Err(e) => {
+            println!("[MAIN] Shared connection to {} failed ({}), connecting directly", relay, e);
+            None
+        }
+    }
+}
+
+/// Refuse another client session once the licensed concurrent count is open
+fn ensure_session_slot(state: &AppState) -> Result<(), AppError> {
+    let max = state.license_manager.lock().max_sessions();
+    if max != 0 && state.client_sessions.lock().len() >= max as usize {
+        return Err(AppError::session_limit(max));
+    }
+    Ok(())
+}
+
+async fn connect_via_relays(
+    state: &Arc<AppState>,
+    remote: String,
+    by_alias: bool,
+    remote_name: Option<String>,
+    password: Option<String>,
+) -> Result<String, AppError> {
+    ensure_session_slot(state)?;
     let relays = state.relay_addresses.lock().clone();
     let identity = state.identity.lock().clone();
 
     let mut last_error = String::from("No relay servers configured");
 
     for relay in relays {
-        match client::ClientSession::connect(relay.clone(), remote_id.clone(), identity.clone()).await {
-            Ok(session) => {
+        // Aliases are registered per relay, so resolve on the relay we connect through
+        let remote_id = if by_alias {
+            match client::ClientSession::resolve_alias(&relay, &remote, &identity).await {
+                Ok(Some(device_id)) => {
+                    println!("[MAIN] Alias {} resolved to {}", remote, device_id);
+                    device_id
+                }
+                Ok(None) => remote.clone(),
+                Err(e) => {
+                    last_error = format!("Relay {} failed: {}", relay, e);
+                    continue;
+                }
+            }
+        } else {
+            remote.clone()
+        };
+
+        let expected_key = state.connection_config.lock().trusted_device_key(&remote_id);
+        let connected = match multiplexed_relay(state, &relay, &identity).await {
+            Some(mux) => client::ClientSession::connect_multiplexed(&mux, remote_id.clone(), identity.clone(), expected_key).await,
+            None => client::ClientSession::connect(relay.clone(), remote_id.clone(), identity.clone(), expected_key).await,
+        };
+        match connected {
+            Ok(mut session) => {
+                // An unattended host lets nobody in without its password
+                if session.password_required() {
+                    let Some(password) = password.as_deref() else {
+                        return Err(AppError::Auth("The host needs its access password".into()));
+                    };
+                    session.authenticate(password).await.map_err(|e| AppError::Auth(e.to_string()))?;
+                }
+
+                if let Some(key) = session.remote_key() {
+                    let pinned = state.connection_config.lock()
+                        .pin_device_key(&remote_id, &key, session.remote_fingerprint());
+                    if let Err(e) = pinned {
+                        eprintln!("[MAIN] Failed to pin device key: {}", e);
+                    }
+                }
+
+                // Restore saved preferences for trusted devices; the quality
+                // falls back to the connection_quality setting
+                let (saved, quality) = {
+                    let config = state.connection_config.lock();
+                    (config.get_device_preferences(&remote_id).cloned(), config.session_quality(&remote_id))
+                };
+                if let Err(e) = session.set_quality_mode(quality).await {
+                    eprintln!("[MAIN] Failed to apply quality: {}", e);
+                }
+                // Sound plays from the start unless the device's preferences mute
+                // it; a missing output device is not fatal
+                let audio = saved.as_ref().is_none_or(|p| p.audio_enabled);
+                if session.audio_enabled().is_some() {
+                    if let Err(e) = session.set_audio_enabled(audio).await {
+                        eprintln!("[MAIN] No host audio: {}", e);
+                    }
+                }
+                let preferences = saved.unwrap_or_default();
+                if preferences.monitor_index != 0 && state.license_manager.lock().has_feature(license::LicenseFeature::MultiMonitor) {
+                    let mode = capture::CaptureMode::Single(preferences.monitor_index);
+                    if let Err(e) = session.set_remote_capture_mode(mode).await {
+                        eprintln!("[MAIN] Failed to select monitor {}: {}", preferences.monitor_index, e);
+                    }
+                }
+
+                // Another connect may have taken the last slot meanwhile
+                if let Err(e) = ensure_session_slot(state) {
+                    let _ = session.disconnect().await;
+                    return Err(e);
+                }
+
                 // Generate a unique session ID
                 let counter = state.session_counter.fetch_add(1, Ordering::SeqCst);
                 let session_id = format!("session_{}", counter);
@@ -219,22 +694,37 @@ async fn connect_to_remote(
                     .unwrap_or_default()
                     .as_secs();
 
-                let entry = ClientSessionEntry {
+                let connection_type = session.connection_type().to_string();
+                let entry = ClientSessionEntry::new(
                     session,
-                    remote_id: remote_id.clone(),
-                    remote_name: remote_name.clone().unwrap_or_else(|| remote_id.clone()),
+                    remote_id.clone(),
+                    remote_name.clone().unwrap_or_else(|| remote.clone()),
                     connected_at,
-                };
+                    preferences,
+                );
 
                 // Add to sessions map
-                state.client_sessions.lock().await.insert(session_id.clone(), entry);
+                state.client_sessions.lock().insert(session_id.clone(), Arc::new(entry));
 
                 // Set as active session
                 *state.active_session_id.lock() = Some(session_id.clone());
 
+                state.session_events.lock().start(&session_id, session_events::SessionMeta {
+                    remote_id: remote_id.clone(),
+                    remote_name: remote_name.clone().unwrap_or_else(|| remote.clone()),
+                    connection_type,
+                    connected_at: now_millis(),
+                    disconnected_at: None,
+                });
+
                 println!("[MAIN] Connected to {} as session {}", remote_id, session_id);
                 return Ok(session_id);
             }
+            // Reached a device that is not the one dialled - don't paper over it with another relay
+            Err(e) if e.to_string().starts_with("Identity mismatch") => {
+                eprintln!("[MAIN] {} (via relay {})", e, relay);
+                return Err(AppError::IdentityMismatch(e.to_string()));
+            }
             Err(e) => {
                 last_error = format!("Relay {} failed: {}", relay, e);
                 continue;
@@ -242,7 +732,7 @@ async fn connect_to_remote(
         }
     }
 
-    Err(last_error)
+    Err(AppError::Network(last_error))
 }
 
 /// Disconnect a session by ID, or the active session if no ID provided
@@ -250,57 +740,80 @@ async fn connect_to_remote(
 async fn disconnect_session(
     state: tauri::State<'_, Arc<AppState>>,
     session_id: Option<String>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let target_id = session_id
         .or_else(|| state.active_session_id.lock().clone())
-        .ok_or("No active session")?;
-
-    let mut sessions = state.client_sessions.lock().await;
-    if let Some(entry) = sessions.remove(&target_id) {
-        println!("[MAIN] Disconnecting session {}", target_id);
-        entry.session.disconnect().await.map_err(|e| e.to_string())?;
+        .ok_or(AppError::NoActiveSession)?;
 
-        // If this was the active session, set another one as active (or None)
-        let mut active_id = state.active_session_id.lock();
-        if active_id.as_ref() == Some(&target_id) {
-            *active_id = sessions.keys().next().cloned();
-        }
-    }
+    state.disconnect(&target_id).await?;
     Ok(())
 }
 
+/// Disconnect all sessions to a remote device; returns how many were closed
+#[tauri::command]
+async fn disconnect_by_remote_id(
+    state: tauri::State<'_, Arc<AppState>>,
+    remote_id: String,
+) -> Result<usize, AppError> {
+    state.disconnect_by_remote_id(&remote_id).await.map_err(AppError::from)
+}
+
+/// Get the session ID connected to a remote device (the most recently used
+/// one if there are several), or None if not connected
+#[tauri::command]
+async fn get_session_id_for_remote(
+    state: tauri::State<'_, Arc<AppState>>,
+    remote_id: String,
+) -> Result<Option<String>, AppError> {
+    Ok(state.get_session_id_for_remote(&remote_id))
+}
+
 /// Disconnect all sessions
 #[tauri::command]
-async fn disconnect_all_sessions(state: tauri::State<'_, Arc<AppState>>) -> Result<(), String> {
-    let mut sessions = state.client_sessions.lock().await;
-    let session_ids: Vec<String> = sessions.keys().cloned().collect();
+async fn disconnect_all_sessions(state: tauri::State<'_, Arc<AppState>>) -> Result<(), AppError> {
+    disconnect_all(&state).await;
+    Ok(())
+}
 
-    for session_id in session_ids {
-        if let Some(entry) = sessions.remove(&session_id) {
-            println!("[MAIN] Disconnecting session {}", session_id);
-            let _ = entry.session.disconnect().await;
-        }
+/// Send SESSION_END on every client session and drop them
+async fn disconnect_all(state: &AppState) {
+    let sessions: Vec<_> = state.client_sessions.lock().drain().collect();
+
+    for (session_id, entry) in sessions {
+        println!("[MAIN] Disconnecting session {}", session_id);
+        let _ = entry.lock().await.session.disconnect().await;
     }
 
     *state.active_session_id.lock() = None;
-    Ok(())
+    state.session_events.lock().end_all();
 }
 
-/// List all active sessions
+/// List all active sessions, most recently used first
 #[tauri::command]
-async fn list_sessions(state: tauri::State<'_, Arc<AppState>>) -> Result<Vec<SessionInfo>, String> {
-    let sessions = state.client_sessions.lock().await;
+async fn list_sessions(state: tauri::State<'_, Arc<AppState>>) -> Result<Vec<SessionInfo>, AppError> {
+    // Reads each session's status as last unlocked, so a frame in flight never holds this up
+    let sessions = state.client_sessions.lock();
     let active_id = state.active_session_id.lock().clone();
 
-    Ok(sessions
-        .iter()
-        .map(|(id, entry)| SessionInfo {
-            session_id: id.clone(),
-            remote_id: entry.remote_id.clone(),
-            remote_name: entry.remote_name.clone(),
-            connected_at: entry.connected_at,
-            is_active: active_id.as_ref() == Some(id),
-            connection_type: entry.session.connection_type().to_string(),
+    Ok(sessions_by_recent_use(&sessions)
+        .into_iter()
+        .filter_map(|id| sessions.get(&id).map(|entry| (id, entry)))
+        .map(|(id, entry)| {
+            let status = entry.status.lock().clone();
+            SessionInfo {
+                is_active: active_id.as_ref() == Some(&id),
+                session_id: id,
+                remote_id: entry.remote_id.clone(),
+                remote_name: entry.remote_name.clone(),
+                connected_at: entry.connected_at,
+                connection_type: status.connection_type,
+                view_only: entry.view_only(),
+                host_elevated: status.host_elevated,
+                focus_lock: status.focus_lock,
+                audio: status.audio,
+                quality: status.quality.to_string(),
+                last_activity: entry.last_activity(),
+            }
         })
         .collect())
 }
@@ -310,13 +823,13 @@ async fn list_sessions(state: tauri::State<'_, Arc<AppState>>) -> Result<Vec<Ses
 async fn set_active_session(
     state: tauri::State<'_, Arc<AppState>>,
     session_id: String,
-) -> Result<(), String> {
-    let sessions = state.client_sessions.lock().await;
-    if sessions.contains_key(&session_id) {
+) -> Result<(), AppError> {
+    if let Some(entry) = state.client_session(&session_id) {
+        entry.touch();
         *state.active_session_id.lock() = Some(session_id);
         Ok(())
     } else {
-        Err(format!("Session {} not found", session_id))
+        Err(AppError::SessionNotFound(session_id))
     }
 }
 
@@ -328,8 +841,48 @@ fn get_active_session(state: tauri::State<Arc<AppState>>) -> Option<String> {
 
 /// Get session count
 #[tauri::command]
-async fn get_session_count(state: tauri::State<'_, Arc<AppState>>) -> Result<usize, String> {
-    Ok(state.client_sessions.lock().await.len())
+async fn get_session_count(state: tauri::State<'_, Arc<AppState>>) -> Result<usize, AppError> {
+    Ok(state.client_sessions.lock().len())
+}
+
+/// Get the event timeline of a session (or the active session), oldest first
+#[tauri::command]
+fn get_session_events(
+    state: tauri::State<Arc<AppState>>,
+    session_id: Option<String>,
+) -> Result<Vec<session_events::SessionEvent>, AppError> {
+    let target_id = session_id
+        .or_else(|| state.active_session_id.lock().clone())
+        .ok_or(AppError::NoActiveSession)?;
+
+    Ok(state.session_events.lock().events(&target_id))
+}
+
+/// Write a transcript of a session (or the active session) to `path` for a
+/// support ticket: HTML for .html/.htm, JSON otherwise
+/// Sessions that ended recently can still be exported by id
+#[tauri::command]
+fn export_session_transcript(
+    state: tauri::State<Arc<AppState>>,
+    session_id: Option<String>,
+    path: String,
+) -> Result<String, AppError> {
+    let target_id = session_id
+        .or_else(|| state.active_session_id.lock().clone())
+        .ok_or(AppError::NoActiveSession)?;
+
+    let (meta, events) = {
+        let log = state.session_events.lock();
+        let meta = log.meta(&target_id)
+            .ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+        (meta, log.events(&target_id))
+    };
+    let local_id = state.identity.lock().device_id();
+
+    let transcript = transcript::Transcript::new(&target_id, local_id, meta, events, now_millis());
+    transcript.write(std::path::Path::new(&path))?;
+    println!("[MAIN] Exported transcript of session {} to {}", target_id, path);
+    Ok(path)
 }
 
 /// Toggle black screen on remote (when in client mode)
@@ -338,14 +891,15 @@ async fn set_black_screen(
     state: tauri::State<'_, Arc<AppState>>,
     enabled: bool,
     session_id: Option<String>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let target_id = session_id
         .or_else(|| state.active_session_id.lock().clone())
-        .ok_or("No active session")?;
+        .ok_or(AppError::NoActiveSession)?;
 
-    let mut sessions = state.client_sessions.lock().await;
-    if let Some(entry) = sessions.get_mut(&target_id) {
-        entry.session.set_black_screen(enabled).await.map_err(|e| e.to_string())?;
+    if let Some(entry) = state.client_session(&target_id) {
+        entry.lock().await.session.set_black_screen(enabled).await?;
+        let kind = if enabled { "black_screen_on" } else { "black_screen_off" };
+        state.session_events.lock().record(&target_id, kind, None);
     }
     Ok(())
 }
@@ -356,18 +910,35 @@ async fn set_input_block(
     state: tauri::State<'_, Arc<AppState>>,
     enabled: bool,
     session_id: Option<String>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let target_id = session_id
         .or_else(|| state.active_session_id.lock().clone())
-        .ok_or("No active session")?;
+        .ok_or(AppError::NoActiveSession)?;
 
-    let mut sessions = state.client_sessions.lock().await;
-    if let Some(entry) = sessions.get_mut(&target_id) {
-        entry.session.set_input_block(enabled).await.map_err(|e| e.to_string())?;
+    if let Some(entry) = state.client_session(&target_id) {
+        entry.lock().await.session.set_input_block(enabled).await?;
+        let kind = if enabled { "input_block_on" } else { "input_block_off" };
+        state.session_events.lock().record(&target_id, kind, None);
     }
     Ok(())
 }
 
+/// Black screen and input block as the host last acknowledged them
+/// (None until the host has answered a toggle)
+#[tauri::command]
+async fn get_remote_privacy_status(
+    state: tauri::State<'_, Arc<AppState>>,
+    session_id: Option<String>,
+) -> Result<Option<client::PrivacyStatus>, AppError> {
+    let target_id = session_id
+        .or_else(|| state.active_session_id.lock().clone())
+        .ok_or(AppError::NoActiveSession)?;
+
+    let entry = state.client_session(&target_id).ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+    let link = entry.lock().await;
+    Ok(link.session.privacy_status())
+}
+
 /// Send mouse event to remote
 #[tauri::command]
 async fn send_mouse(
@@ -377,14 +948,51 @@ async fn send_mouse(
     event_type: String,
     button: Option<u8>,
     session_id: Option<String>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
+    deliver_mouse(state.inner(), session_id, x, y, event_type, button).await
+}
+
+/// Mouse input for `send_mouse` and the automation socket
+async fn deliver_mouse(
+    state: &Arc<AppState>,
+    session_id: Option<String>,
+    x: i32,
+    y: i32,
+    event_type: String,
+    button: Option<u8>,
+) -> Result<(), AppError> {
     let target_id = session_id
         .or_else(|| state.active_session_id.lock().clone())
-        .ok_or("No active session")?;
+        .ok_or(AppError::NoActiveSession)?;
 
-    let mut sessions = state.client_sessions.lock().await;
-    if let Some(entry) = sessions.get_mut(&target_id) {
-        entry.session.send_mouse(x, y, &event_type, button).await.map_err(|e| e.to_string())?;
+    if let Some(entry) = state.client_session(&target_id) {
+        if entry.view_only() {
+            return Ok(());
+        }
+        entry.touch();
+
+        // Scroll deltas arrive in high-resolution units; apply local scroll preferences
+        let (x, y) = if event_type == "scroll" {
+            let config = state.connection_config.lock();
+            input::apply_scroll_preferences(
+                x,
+                y,
+                config.settings.scroll_sensitivity,
+                config.settings.natural_scroll,
+            )
+        } else {
+            (x, y)
+        };
+
+        let batch_ms = state.connection_config.lock().settings.input_batch_ms;
+        let mut link = entry.lock().await;
+        link.session.set_input_batching(std::time::Duration::from_millis(batch_ms as u64));
+        link.session.send_mouse(x, y, &event_type, button).await?;
+        if let Some(delay) = link.session.take_batch_flush() {
+            schedule_input_flush(state.clone(), target_id.clone(), delay);
+        }
+        drop(link);
+        record_input(state, recording::InputEvent::Mouse { x, y, event_type, button });
     }
     Ok(())
 }
@@ -396,18 +1004,67 @@ async fn send_key(
     key_code: u16,
     pressed: bool,
     session_id: Option<String>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
+    deliver_key(state.inner(), session_id, key_code, pressed).await
+}
+
+/// Key input for `send_key` and the automation socket
+async fn deliver_key(
+    state: &Arc<AppState>,
+    session_id: Option<String>,
+    key_code: u16,
+    pressed: bool,
+) -> Result<(), AppError> {
     let target_id = session_id
         .or_else(|| state.active_session_id.lock().clone())
-        .ok_or("No active session")?;
+        .ok_or(AppError::NoActiveSession)?;
 
-    let mut sessions = state.client_sessions.lock().await;
-    if let Some(entry) = sessions.get_mut(&target_id) {
-        entry.session.send_key(key_code, pressed).await.map_err(|e| e.to_string())?;
+    if let Some(entry) = state.client_session(&target_id) {
+        if entry.view_only() {
+            return Ok(());
+        }
+        entry.touch();
+        entry.lock().await.session.send_key(key_code, pressed).await?;
+        record_input(state, recording::InputEvent::Key { key_code, pressed });
     }
     Ok(())
 }
 
+/// Send a session's batched pointer events once the batching window has passed
+/// Button and key events flush the batch earlier; then this finds nothing to send
+fn schedule_input_flush(state: Arc<AppState>, session_id: String, delay: std::time::Duration) {
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        if let Some(entry) = state.client_session(&session_id) {
+            if let Err(e) = entry.lock().await.session.flush_input().await {
+                eprintln!("[MAIN] Failed to send batched input: {}", e);
+            }
+        }
+    });
+}
+
+/// Refuse a command the active license does not cover
+fn require_feature(state: &AppState, feature: license::LicenseFeature) -> Result<(), AppError> {
+    if state.license_manager.lock().has_feature(feature) {
+        Ok(())
+    } else {
+        Err(AppError::license_required(feature))
+    }
+}
+
+/// Write an operator input event to the active recording
+/// Input capture is part of the session-recording license feature
+fn record_input(state: &AppState, event: recording::InputEvent) {
+    if !state.recording_manager.is_recording()
+        || !state.license_manager.lock().has_feature(license::LicenseFeature::SessionRecording)
+    {
+        return;
+    }
+    if let Err(e) = state.recording_manager.write_input(&event) {
+        eprintln!("[RECORDING] Failed to write input event: {}", e);
+    }
+}
+
 /// Send client viewport resolution to host for adaptive scaling
 #[tauri::command]
 async fn send_resolution(
@@ -415,15 +1072,79 @@ async fn send_resolution(
     width: u16,
     height: u16,
     session_id: Option<String>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
+    let target_id = session_id
+        .or_else(|| state.active_session_id.lock().clone())
+        .ok_or(AppError::NoActiveSession)?;
+
+    if let Some(entry) = state.client_session(&target_id) {
+        let mut link = entry.lock().await;
+        link.session.set_resolution(width, height);
+        if let Some(delay) = link.session.take_resolution_flush() {
+            schedule_resolution_flush(state.inner().clone(), target_id, delay);
+        }
+    }
+    Ok(())
+}
+
+/// Send a session's viewport resolution once resizing has settled
+/// Every change during a drag pushes the send back; one task covers the whole drag
+fn schedule_resolution_flush(state: Arc<AppState>, session_id: String, mut delay: std::time::Duration) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(delay).await;
+            let Some(entry) = state.client_session(&session_id) else {
+                return;
+            };
+            let flushed = entry.lock().await.session.flush_resolution().await;
+            match flushed {
+                Ok(Some(wait)) => delay = wait,
+                Ok(None) => return,
+                Err(e) => {
+                    eprintln!("[MAIN] Failed to send resolution: {}", e);
+                    return;
+                }
+            }
+        }
+    });
+}
+
+/// Whether a session (or the active session) is end-to-end encrypted
+/// False only for hosts that predate identity verification
+#[tauri::command]
+async fn is_encrypted(
+    state: tauri::State<'_, Arc<AppState>>,
+    session_id: Option<String>,
+) -> Result<bool, AppError> {
     let target_id = session_id
         .or_else(|| state.active_session_id.lock().clone())
-        .ok_or("No active session")?;
+        .ok_or(AppError::NoActiveSession)?;
+
+    let entry = state.client_session(&target_id).ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+    let link = entry.lock().await;
+    Ok(link.session.is_encrypted())
+}
 
-    let mut sessions = state.client_sessions.lock().await;
-    if let Some(entry) = sessions.get_mut(&target_id) {
-        entry.session.send_resolution(width, height).await.map_err(|e| e.to_string())?;
+/// Change the quality of a session (or the active session) while it runs
+/// `level` is a `connection_quality` value; "auto" adapts to the network,
+/// anything else is fixed. The host uses it from the next frame on.
+#[tauri::command]
+async fn set_session_quality(
+    state: tauri::State<'_, Arc<AppState>>,
+    session_id: Option<String>,
+    level: String,
+) -> Result<(), AppError> {
+    let quality = qos::QualityLevel::from_setting(&level);
+    if quality.is_none() && level != "auto" {
+        return Err(AppError::InvalidInput(format!("Unknown quality level: {}", level)));
     }
+    let target_id = session_id
+        .or_else(|| state.active_session_id.lock().clone())
+        .ok_or(AppError::NoActiveSession)?;
+
+    let entry = state.client_session(&target_id).ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+    entry.lock().await.session.set_quality_mode(quality).await?;
+    state.session_events.lock().record(&target_id, "quality_changed", Some(level));
     Ok(())
 }
 
@@ -432,37 +1153,235 @@ async fn send_resolution(
 struct VideoFrame {
     width: u16,
     height: u16,
-    data: String, // Base64 encoded JPEG
+    data: String, // Base64 encoded image
+    format: &'static str, // "jpeg" or "png" (lossless mode)
+    interval_ms: u64, // When to ask for the next frame
 }
 
-/// Request and receive a video frame from remote
+/// Bytes before the image in a `request_video_frame_binary` response:
+/// [format u8][reserved u8][width u16 LE][height u16 LE][interval_ms u16 LE]
+const BINARY_FRAME_HEADER: usize = 8;
+
+/// Request and receive a video frame from remote, base64 encoded in JSON
+/// Kept for compatibility; the session view uses `request_video_frame_binary`
 #[tauri::command]
 async fn request_video_frame(
     state: tauri::State<'_, Arc<AppState>>,
+    app_handle: tauri::AppHandle,
     session_id: Option<String>,
-) -> Result<Option<VideoFrame>, String> {
+) -> Result<Option<VideoFrame>, AppError> {
+    let Some((frame, interval_ms)) = next_video_frame(&state, &app_handle, session_id).await? else {
+        return Ok(None);
+    };
+    let client::RemoteFrame { width, height, format, data, .. } = frame;
+
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
+    let encoded = STANDARD.encode(&data);
+    let format = if format == protocol::video::FORMAT_PNG { "png" } else { "jpeg" };
+    Ok(Some(VideoFrame { width, height, data: encoded, format, interval_ms }))
+}
+
+/// Request and receive a video frame as raw bytes (an ArrayBuffer in the webview)
+/// Skips base64 and its third more bytes and string churn per frame: the body is
+/// BINARY_FRAME_HEADER followed by the JPEG or PNG, or empty when there is no new frame
+#[tauri::command]
+async fn request_video_frame_binary(
+    state: tauri::State<'_, Arc<AppState>>,
+    app_handle: tauri::AppHandle,
+    session_id: Option<String>,
+) -> Result<tauri::ipc::Response, AppError> {
+    let body = match next_video_frame(&state, &app_handle, session_id).await? {
+        Some((frame, interval_ms)) => {
+            let mut body = Vec::with_capacity(BINARY_FRAME_HEADER + frame.data.len());
+            body.push(frame.format);
+            body.push(0);
+            body.extend_from_slice(&frame.width.to_le_bytes());
+            body.extend_from_slice(&frame.height.to_le_bytes());
+            body.extend_from_slice(&(interval_ms.min(u16::MAX as u64) as u16).to_le_bytes());
+            body.extend_from_slice(&frame.data);
+            body
+        }
+        None => Vec::new(),
+    };
+    Ok(tauri::ipc::Response::new(body))
+}
+
+/// Fetch the next frame to show for a session, with the pacing for the one after
+/// Also hands the UI whatever the host reported in between (chat, resolution,
+/// capture and control changes) and feeds an active recording
+async fn next_video_frame(
+    state: &AppState,
+    app_handle: &tauri::AppHandle,
+    session_id: Option<String>,
+) -> Result<Option<(client::RemoteFrame, u64)>, AppError> {
     let target_id = match session_id.or_else(|| state.active_session_id.lock().clone()) {
         Some(id) => id,
         None => return Ok(None),
     };
 
-    let mut sessions = state.client_sessions.lock().await;
-    if let Some(entry) = sessions.get_mut(&target_id) {
-        match entry.session.request_and_receive_frame().await {
-            Ok(Some((width, height, data))) => {
+    let (coalesce, jitter_depth) = {
+        let config = state.connection_config.lock();
+        let settings = config.get_settings();
+        (settings.coalesce_frame_requests, settings.jitter_buffer_frames)
+    };
+    if let Some(entry) = state.client_session(&target_id) {
+        let mut link = entry.lock().await;
+        link.session.set_frame_coalescing(coalesce);
+        link.jitter.set_depth(jitter_depth);
+
+        // Move a relayed session to P2P when a retry is due
+        if let Err(e) = link.session.maintain_p2p().await {
+            eprintln!("[MAIN] P2P upgrade for {} failed: {}", target_id, e);
+        }
+
+        // The pong is read along with the frame below
+        if let Err(e) = link.session.ping_if_due().await {
+            eprintln!("[MAIN] Failed to ping host: {}", e);
+        }
+        // Uploads take turns with video: a window of chunks per frame
+        if let Err(e) = link.session.send_file_chunks().await {
+            eprintln!("[MAIN] Failed to send file data: {}", e);
+        }
+
+        // With enough frames buffered, present from the buffer without waiting on the host
+        let result = if link.jitter.wants_frame() {
+            link.session.request_and_receive_frame().await
+        } else {
+            link.jitter.skip_fetch();
+            Ok(None)
+        };
+
+        // Chat messages arrive in between frames; hand them to the UI here
+        for text in link.session.take_chat_messages() {
+            state.session_events.lock().record(&target_id, "chat_received", Some(text.clone()));
+            let _ = app_handle.emit("chat-message", serde_json::json!({
+                "session_id": target_id,
+                "from": "remote",
+                "text": text,
+            }));
+        }
+
+        for progress in link.session.take_transfer_updates() {
+            let kind = match progress.state {
+                transfer::State::Completed => Some("file_sent"),
+                transfer::State::Failed => Some("file_failed"),
+                _ => None,
+            };
+            if let Some(kind) = kind {
+                state.session_events.lock().record(&target_id, kind, Some(progress.name.clone()));
+            }
+            let _ = app_handle.emit("file-transfer-progress", serde_json::json!({
+                "session_id": target_id,
+                "direction": "outgoing",
+                "transfer": progress,
+            }));
+        }
+
+        if let Some((width, height)) = link.session.take_resolution_change() {
+            state.session_events.lock().record(
+                &target_id,
+                "resolution_changed",
+                Some(format!("{}x{}", width, height)),
+            );
+            let _ = app_handle.emit("resolution-changed", serde_json::json!({
+                "session_id": target_id,
+                "width": width,
+                "height": height,
+            }));
+        }
+
+        // The host reports why frames stopped (no display, permission missing)
+        match link.session.take_capture_status_change() {
+            Some(Some(reason)) => {
+                state.session_events.lock().record(&target_id, "capture_unavailable", Some(reason.clone()));
+                let _ = app_handle.emit("capture-unavailable", serde_json::json!({
+                    "session_id": target_id,
+                    "reason": reason,
+                }));
+            }
+            Some(None) => {
+                state.session_events.lock().record(&target_id, "capture_available", None);
+                let _ = app_handle.emit("capture-available", serde_json::json!({
+                    "session_id": target_id,
+                }));
+            }
+            None => {}
+        }
+
+        // The host user granted or revoked control, or another viewer of the same host took it
+        if let Some(granted) = link.session.take_input_control_change() {
+            state.session_events.lock().record(
+                &target_id,
+                if granted { "input_control_granted" } else { "view_only" },
+                None,
+            );
+            let event = if granted { "control-granted" } else { "control-revoked" };
+            let _ = app_handle.emit(event, serde_json::json!({
+                "session_id": target_id,
+            }));
+        }
+
+        // The host acknowledged a privacy toggle; the UI shows what is really in effect
+        if let Some(status) = link.session.take_privacy_status_change() {
+            let _ = app_handle.emit("privacy-status", serde_json::json!({
+                "session_id": target_id,
+                "black_screen": status.black_screen,
+                "input_blocked": status.input_blocked,
+            }));
+        }
+
+        // The cursor skips the jitter buffer: it is drawn over whatever frame shows
+        if let Some(cursor) = link.session.take_cursor_update() {
+            use base64::{Engine as _, engine::general_purpose::STANDARD};
+            let image = cursor.image.map(|image| serde_json::json!({
+                "hotspot_x": image.hotspot.0,
+                "hotspot_y": image.hotspot.1,
+                "png": STANDARD.encode(&image.png),
+            }));
+            let _ = app_handle.emit("remote-cursor", serde_json::json!({
+                "session_id": target_id,
+                "visible": cursor.visible,
+                "x": cursor.x,
+                "y": cursor.y,
+                "image": image,
+            }));
+        }
+
+        if let Some(connection_type) = link.session.take_connection_type_change() {
+            state.session_events.lock().record(
+                &target_id,
+                "connection_type_changed",
+                Some(connection_type.to_string()),
+            );
+            let _ = app_handle.emit("connection-type-changed", serde_json::json!({
+                "session_id": target_id,
+                "type": connection_type.to_string(),
+            }));
+        }
+
+        let result = result.map(|frame| {
+            if let Some(frame) = frame {
+                link.jitter.push(frame, std::time::Instant::now());
+            }
+            link.jitter.pop(std::time::Instant::now())
+        });
+
+        let interval_ms = link.session.frame_interval().as_millis() as u64;
+        match result {
+            Ok(Some(frame)) => {
+                // Echo its capture stamp now and then for the latency figures
+                if let Err(e) = link.session.frame_presented(&frame).await {
+                    eprintln!("[MAIN] Failed to send frame timing: {}", e);
+                }
                 // Write frame to recording if recording is active
-                if let Err(e) = state.recording_manager.write_frame(width, height, &data) {
+                if let Err(e) = state.recording_manager.write_frame(frame.width, frame.height, &frame.data) {
                     // Log but don't fail the frame request
                     eprintln!("[RECORDING] Failed to write frame: {}", e);
                 }
-
-                // Encode frame data as base64 for transfer to frontend
-                use base64::{Engine as _, engine::general_purpose::STANDARD};
-                let encoded = STANDARD.encode(&data);
-                Ok(Some(VideoFrame { width, height, data: encoded }))
+                Ok(Some((frame, interval_ms)))
             }
             Ok(None) => Ok(None),
-            Err(e) => Err(e.to_string()),
+            Err(e) => Err(e.into()),
         }
     } else {
         Ok(None)
@@ -470,26 +1389,391 @@ async fn request_video_frame(
 }
 
 /// Respond to pending connection request (accept or decline)
+/// Without a request_id the oldest pending request is answered. An accepted
+/// device can be remembered so its next requests skip the prompt.
 #[tauri::command]
 async fn respond_to_connection(
     state: tauri::State<'_, Arc<AppState>>,
     accept: bool,
-) -> Result<(), String> {
-    let session_opt = state.host_session.lock().await;
-    if let Some(ref session) = *session_opt {
-        let pending = session.pending_connection();
-        let mut pending_lock = pending.lock();
-        if let Some(pending_conn) = pending_lock.take() {
-            // Send response to the waiting host session
-            let _ = pending_conn.response_tx.try_send(accept);
-            println!("[MAIN] Sent connection response: accept={}", accept);
-            Ok(())
-        } else {
-            Err("No pending connection".to_string())
+    request_id: Option<u64>,
+    remember: Option<host::TrustDuration>,
+) -> Result<(), AppError> {
+    let pending = state.host_handle.lock().as_ref()
+        .map(|handle| handle.pending_connections())
+        .ok_or(AppError::NoHostSession)?;
+
+    let (remote_id, client_key) = pending.lock().respond(request_id, accept)?;
+    println!("[MAIN] Sent connection response: request={:?} accept={}", request_id, accept);
+
+    // Remembered by the key the viewer proved, so another device claiming its ID is still asked
+    if accept {
+        let now = std::time::Instant::now();
+        match remember.unwrap_or(host::TrustDuration::Once) {
+            host::TrustDuration::Once => {}
+            host::TrustDuration::Session => host::TEMPORARY_TRUST.lock().grant(client_key, &remote_id, None),
+            host::TrustDuration::Hour => {
+                host::TEMPORARY_TRUST.lock().grant(client_key, &remote_id, Some(now + host::TEMPORARY_TRUST_HOUR));
+            }
+            host::TrustDuration::Always => {
+                state.connection_config.lock().allow_viewer(&client_key, &remote_id)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Device let in without a prompt until its grant runs out, for the frontend
+#[derive(serde::Serialize)]
+struct TemporaryTrustInfo {
+    device_id: String,
+    /// Seconds left; None until the host listener stops
+    expires_in_secs: Option<u64>,
+}
+
+/// List temporary trust grants still in force
+#[tauri::command]
+fn list_temporary_trust() -> Vec<TemporaryTrustInfo> {
+    host::TEMPORARY_TRUST.lock().list(std::time::Instant::now())
+        .into_iter()
+        .map(|(device_id, left)| TemporaryTrustInfo {
+            device_id,
+            expires_in_secs: left.map(|left| left.as_secs()),
+        })
+        .collect()
+}
+
+/// Revoke a device's temporary trust; its next request asks again
+#[tauri::command]
+fn revoke_temporary_trust(device_id: String) -> Result<(), AppError> {
+    if !host::TEMPORARY_TRUST.lock().revoke(&device_id) {
+        return Err(AppError::InvalidInput(format!("Device {} has no temporary trust", device_id)));
+    }
+    println!("[MAIN] Revoked temporary trust for {}", device_id);
+    Ok(())
+}
+
+/// Viewer this host lets in without asking, for the frontend
+#[derive(serde::Serialize)]
+struct AllowedViewerInfo {
+    device_id: String,
+    allowed_at: u64,
+}
+
+/// List viewers accepted "Always"
+#[tauri::command]
+fn list_allowed_viewers(state: tauri::State<Arc<AppState>>) -> Vec<AllowedViewerInfo> {
+    let config = state.connection_config.lock();
+    let mut viewers: Vec<_> = config.allowed_viewers.values()
+        .map(|viewer| AllowedViewerInfo {
+            device_id: viewer.device_id.clone(),
+            allowed_at: viewer.allowed_at,
+        })
+        .collect();
+    viewers.sort_by(|a, b| a.device_id.cmp(&b.device_id));
+    viewers
+}
+
+/// Stop letting a device in without asking
+#[tauri::command]
+fn remove_allowed_viewer(state: tauri::State<Arc<AppState>>, device_id: String) -> Result<(), AppError> {
+    if !state.connection_config.lock().remove_allowed_viewer(&device_id)? {
+        return Err(AppError::InvalidInput(format!("Device {} is not always allowed", device_id)));
+    }
+    println!("[MAIN] Device {} needs approval again", device_id);
+    Ok(())
+}
+
+/// Pending connection request info for frontend
+#[derive(serde::Serialize)]
+struct PendingConnectionInfo {
+    request_id: u64,
+    remote_id: String,
+}
+
+/// List connection requests still awaiting approval, oldest first
+#[tauri::command]
+fn list_pending_connections(state: tauri::State<Arc<AppState>>) -> Vec<PendingConnectionInfo> {
+    match state.host_handle.lock().as_ref() {
+        Some(handle) => handle.pending_connections().lock().list()
+            .into_iter()
+            .map(|(request_id, remote_id)| PendingConnectionInfo { request_id, remote_id })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Viewer connected to our host session, for the frontend
+#[derive(serde::Serialize)]
+struct HostViewerInfo {
+    viewer_id: u16,
+    remote_id: String,
+    connected_at: u64,
+    accepted: bool,
+    controlling: bool,
+    requested_control: bool,
+}
+
+/// List clients viewing this device, oldest first
+/// Only one of them (`controlling`) has its input used; the rest are view-only
+#[tauri::command]
+fn list_host_viewers(state: tauri::State<Arc<AppState>>) -> Vec<HostViewerInfo> {
+    match state.host_handle.lock().as_ref() {
+        Some(handle) => handle.viewers()
+            .into_iter()
+            .map(|v| HostViewerInfo {
+                viewer_id: v.viewer_id,
+                remote_id: v.remote_id,
+                connected_at: v.connected_at,
+                accepted: v.accepted,
+                controlling: v.controlling,
+                requested_control: v.requested_control,
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Hand input control to an accepted viewer (answering its request, if it made one)
+/// Whoever had control becomes view-only
+#[tauri::command]
+async fn grant_control(
+    state: tauri::State<'_, Arc<AppState>>,
+    app_handle: tauri::AppHandle,
+    viewer_id: u16,
+) -> Result<(), AppError> {
+    let handle = state.host_handle.lock().clone()
+        .ok_or(AppError::NoHostSession)?;
+    let previous = handle.set_input_controller(viewer_id).await?;
+    if let Some(previous) = previous {
+        host::emit_control_change(Some(&app_handle), previous, false);
+    }
+    host::emit_control_change(Some(&app_handle), viewer_id, true);
+    println!("[MAIN] Input control given to viewer {}", viewer_id);
+    Ok(())
+}
+
+/// Make a viewer view-only, or decline its control request
+#[tauri::command]
+async fn revoke_control(
+    state: tauri::State<'_, Arc<AppState>>,
+    app_handle: tauri::AppHandle,
+    viewer_id: u16,
+) -> Result<(), AppError> {
+    let handle = state.host_handle.lock().clone()
+        .ok_or(AppError::NoHostSession)?;
+    if handle.revoke_input_control(viewer_id).await? {
+        host::emit_control_change(Some(&app_handle), viewer_id, false);
+        println!("[MAIN] Input control taken back from viewer {}", viewer_id);
+    }
+    Ok(())
+}
+
+// ============================================================================
+// Chat Commands
+// ============================================================================
+
+/// Send a chat message to the other end of a session
+/// Goes to the given (or active) client session; falls back to the clients
+/// viewing our host session when we are not viewing anyone
+#[tauri::command]
+async fn send_chat(
+    state: tauri::State<'_, Arc<AppState>>,
+    session_id: Option<String>,
+    text: String,
+) -> Result<(), AppError> {
+    let target_id = session_id.or_else(|| state.active_session_id.lock().clone());
+
+    if let Some(id) = target_id {
+        if let Some(entry) = state.client_session(&id) {
+            entry.lock().await.session.send_chat(&text).await?;
+            state.session_events.lock().record(&id, "chat_sent", Some(text.clone()));
+            println!("[MAIN] Sent chat message to session {}", id);
+            return Ok(());
         }
-    } else {
-        Err("No host session active".to_string())
     }
+
+    let handle = state.host_handle.lock().clone()
+        .ok_or(AppError::NoActiveSession)?;
+    handle.send_chat(&text).await?;
+    println!("[MAIN] Sent chat message to connected clients");
+    Ok(())
+}
+
+/// Send a chat message to the clients viewing our host session, even while
+/// we are viewing someone else (the host view's chat panel)
+#[tauri::command]
+async fn send_host_chat(
+    state: tauri::State<'_, Arc<AppState>>,
+    text: String,
+) -> Result<(), AppError> {
+    let handle = state.host_handle.lock().clone()
+        .ok_or(AppError::NoActiveSession)?;
+    handle.send_chat(&text).await?;
+    println!("[MAIN] Sent chat message to connected clients");
+    Ok(())
+}
+
+// ============================================================================
+// File Transfer Commands
+// ============================================================================
+
+/// Send a file to the host of a client session (or the active session); it lands
+/// in the host's Downloads folder. Progress arrives as `file-transfer-progress`
+/// events. Returns the transfer's id
+#[tauri::command]
+async fn start_file_upload(
+    state: tauri::State<'_, Arc<AppState>>,
+    path: String,
+    session_id: Option<String>,
+) -> Result<u32, AppError> {
+    require_feature(&state, license::LicenseFeature::FileTransfer)?;
+    let target_id = session_id
+        .or_else(|| state.active_session_id.lock().clone())
+        .ok_or(AppError::NoActiveSession)?;
+
+    let entry = state.client_session(&target_id).ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+    let id = entry.lock().await.session.send_file(std::path::Path::new(&path)).await?;
+    println!("[MAIN] Offered {} to session {}", path, target_id);
+    Ok(id)
+}
+
+/// Files viewers of our host session are sending, then the ones finished lately
+#[tauri::command]
+fn list_incoming_transfers(state: tauri::State<Arc<AppState>>) -> Vec<transfer::Progress> {
+    match state.host_handle.lock().as_ref() {
+        Some(handle) => handle.incoming_transfers(),
+        None => Vec::new(),
+    }
+}
+
+// ============================================================================
+// Diagnostics Commands
+// ============================================================================
+
+/// Get host-side capture/encode timing (avg/max encode ms, effective capture FPS)
+/// High encode times with low FPS point at a CPU-bound session rather than the network
+#[tauri::command]
+fn get_capture_stats() -> capture::EncodeStats {
+    capture::get_encode_stats()
+}
+
+/// Get latency figures for a client session (or the active session): capture on
+/// the host to presentation here, and the frame round trip it includes
+#[tauri::command]
+async fn get_session_stats(
+    state: tauri::State<'_, Arc<AppState>>,
+    session_id: Option<String>,
+) -> Result<client::SessionStats, AppError> {
+    let target_id = session_id
+        .or_else(|| state.active_session_id.lock().clone())
+        .ok_or(AppError::NoActiveSession)?;
+
+    let entry = state.client_session(&target_id).ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+    let link = entry.lock().await;
+    Ok(link.session.stats())
+}
+
+/// Get the ping round trip to the host of a client session (or the active session)
+/// and, while quality adapts to it, the adaptive quality state
+#[tauri::command]
+async fn get_connection_stats(
+    state: tauri::State<'_, Arc<AppState>>,
+    session_id: Option<String>,
+) -> Result<client::ConnectionStats, AppError> {
+    let target_id = session_id
+        .or_else(|| state.active_session_id.lock().clone())
+        .ok_or(AppError::NoActiveSession)?;
+
+    let entry = state.client_session(&target_id).ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+    let link = entry.lock().await;
+    Ok(link.session.connection_stats())
+}
+
+// ============================================================================
+// Elevation Commands
+// ============================================================================
+
+/// Whether this device runs elevated (input can reach UAC/admin windows)
+#[tauri::command]
+fn get_elevation_status() -> bool {
+    elevation::is_elevated()
+}
+
+/// Relaunch this app elevated; exits on success
+#[tauri::command]
+async fn relaunch_elevated() -> Result<(), AppError> {
+    tokio::task::spawn_blocking(elevation::relaunch_elevated)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .map_err(AppError::from)
+}
+
+/// Ask the remote host to relaunch elevated
+#[tauri::command]
+async fn request_host_elevation(
+    state: tauri::State<'_, Arc<AppState>>,
+    session_id: Option<String>,
+) -> Result<(), AppError> {
+    let target_id = session_id
+        .or_else(|| state.active_session_id.lock().clone())
+        .ok_or(AppError::NoActiveSession)?;
+
+    let entry = state.client_session(&target_id).ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+    entry.lock().await.session.request_elevation().await?;
+    state.session_events.lock().record(&target_id, "elevation_requested", None);
+    Ok(())
+}
+
+/// Ask the host user for input control while the session is view-only
+#[tauri::command]
+async fn request_control(
+    state: tauri::State<'_, Arc<AppState>>,
+    session_id: Option<String>,
+) -> Result<(), AppError> {
+    let target_id = session_id
+        .or_else(|| state.active_session_id.lock().clone())
+        .ok_or(AppError::NoActiveSession)?;
+
+    let entry = state.client_session(&target_id).ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+    entry.lock().await.session.request_control().await?;
+    state.session_events.lock().record(&target_id, "control_requested", None);
+    Ok(())
+}
+
+/// Pin keystrokes to the window last clicked on the remote, or release them
+#[tauri::command]
+async fn set_focus_lock(
+    state: tauri::State<'_, Arc<AppState>>,
+    enabled: bool,
+    session_id: Option<String>,
+) -> Result<(), AppError> {
+    let target_id = session_id
+        .or_else(|| state.active_session_id.lock().clone())
+        .ok_or(AppError::NoActiveSession)?;
+
+    let entry = state.client_session(&target_id).ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+    entry.lock().await.session.set_focus_lock(enabled).await?;
+    let kind = if enabled { "focus_lock_on" } else { "focus_lock_off" };
+    state.session_events.lock().record(&target_id, kind, None);
+    Ok(())
+}
+
+/// Play or mute the host's sound
+#[tauri::command]
+async fn set_audio_enabled(
+    state: tauri::State<'_, Arc<AppState>>,
+    enabled: bool,
+    session_id: Option<String>,
+) -> Result<(), AppError> {
+    let target_id = session_id
+        .or_else(|| state.active_session_id.lock().clone())
+        .ok_or(AppError::NoActiveSession)?;
+
+    let entry = state.client_session(&target_id).ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+    entry.lock().await.session.set_audio_enabled(enabled).await?;
+    let kind = if enabled { "audio_on" } else { "audio_off" };
+    state.session_events.lock().record(&target_id, kind, None);
+    Ok(())
 }
 
 // ============================================================================
@@ -504,9 +1788,56 @@ fn get_p2p_enabled(state: tauri::State<Arc<AppState>>) -> bool {
 
 /// Set P2P enabled state
 #[tauri::command]
-fn set_p2p_enabled(state: tauri::State<Arc<AppState>>, enabled: bool) -> Result<(), String> {
+fn set_p2p_enabled(state: tauri::State<Arc<AppState>>, enabled: bool) -> Result<(), AppError> {
+    let mut config = state.connection_config.lock();
+    config.set_p2p_enabled(enabled)?;
+    Ok(())
+}
+
+/// STUN configuration for frontend
+#[derive(serde::Serialize)]
+struct StunConfigInfo {
+    enabled: bool,
+    servers: Vec<String>,
+}
+
+/// Get STUN enabled state and server list
+#[tauri::command]
+fn get_stun_config(state: tauri::State<Arc<AppState>>) -> StunConfigInfo {
+    let config = state.connection_config.lock();
+    StunConfigInfo {
+        enabled: config.stun_enabled,
+        servers: config.stun_servers.clone(),
+    }
+}
+
+/// Enable or disable STUN discovery (disabled = no third-party lookups)
+#[tauri::command]
+fn set_stun_enabled(state: tauri::State<Arc<AppState>>, enabled: bool) -> Result<(), AppError> {
+    let mut config = state.connection_config.lock();
+    config.set_stun_enabled(enabled)?;
+    Ok(())
+}
+
+/// Replace the STUN server list (entries must resolve)
+#[tauri::command]
+fn set_stun_servers(state: tauri::State<Arc<AppState>>, servers: Vec<String>) -> Result<(), AppError> {
+    let mut config = state.connection_config.lock();
+    config.set_stun_servers(servers).map_err(AppError::invalid_input)?;
+    Ok(())
+}
+
+/// Get the host window control policy
+#[tauri::command]
+fn get_control_policy(state: tauri::State<Arc<AppState>>) -> Vec<policy::PolicyRule> {
+    state.connection_config.lock().control_policy.clone()
+}
+
+/// Replace the host window control policy (patterns must not be empty)
+#[tauri::command]
+fn set_control_policy(state: tauri::State<Arc<AppState>>, rules: Vec<policy::PolicyRule>) -> Result<(), AppError> {
     let mut config = state.connection_config.lock();
-    config.set_p2p_enabled(enabled).map_err(|e| e.to_string())?;
+    config.set_control_policy(rules).map_err(AppError::invalid_input)?;
     Ok(())
 }
 
@@ -515,14 +1846,13 @@ fn set_p2p_enabled(state: tauri::State<Arc<AppState>>, enabled: bool) -> Result<
 async fn get_connection_type(
     state: tauri::State<'_, Arc<AppState>>,
     session_id: Option<String>,
-) -> Result<String, String> {
+) -> Result<String, AppError> {
     // Check specified or active client session first
     let target_id = session_id.or_else(|| state.active_session_id.lock().clone());
 
     if let Some(id) = target_id {
-        let sessions = state.client_sessions.lock().await;
-        if let Some(entry) = sessions.get(&id) {
-            return Ok(entry.session.connection_type().to_string());
+        if let Some(entry) = state.client_session(&id) {
+            return Ok(entry.status.lock().connection_type.clone());
         }
     }
 
@@ -551,9 +1881,9 @@ fn add_trusted_device(
     state: tauri::State<Arc<AppState>>,
     device_id: String,
     name: Option<String>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let mut config = state.connection_config.lock();
-    config.add_trusted_device(&device_id, name).map_err(|e| e.to_string())?;
+    config.add_trusted_device(&device_id, name).map_err(AppError::invalid_input)?;
     Ok(())
 }
 
@@ -562,9 +1892,9 @@ fn add_trusted_device(
 fn remove_trusted_device(
     state: tauri::State<Arc<AppState>>,
     device_id: String,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let mut config = state.connection_config.lock();
-    config.remove_trusted_device(&device_id).map_err(|e| e.to_string())?;
+    config.remove_trusted_device(&device_id)?;
     Ok(())
 }
 
@@ -575,6 +1905,7 @@ struct TrustedDeviceInfo {
     name: Option<String>,
     trusted_at: u64,
     last_connected: Option<u64>,
+    fingerprint: Option<String>,
 }
 
 /// Get list of trusted devices
@@ -589,10 +1920,113 @@ fn get_trusted_devices(state: tauri::State<Arc<AppState>>) -> Vec<TrustedDeviceI
             name: d.name.clone(),
             trusted_at: d.trusted_at,
             last_connected: d.last_connected,
+            fingerprint: d.fingerprint.clone(),
         })
         .collect()
 }
 
+/// Block a device - its connection requests are refused without a prompt
+#[tauri::command]
+fn block_device(
+    state: tauri::State<Arc<AppState>>,
+    device_id: String,
+) -> Result<(), AppError> {
+    let mut config = state.connection_config.lock();
+    config.block_device(&device_id)?;
+    host::TEMPORARY_TRUST.lock().revoke(&device_id);
+    println!("[MAIN] Blocked device {}", device_id);
+    Ok(())
+}
+
+/// Unblock a device
+#[tauri::command]
+fn unblock_device(
+    state: tauri::State<Arc<AppState>>,
+    device_id: String,
+) -> Result<(), AppError> {
+    let mut config = state.connection_config.lock();
+    config.unblock_device(&device_id)?;
+    Ok(())
+}
+
+/// Let viewers that give this password in without asking (unattended access)
+/// Everyone else is refused while it is set
+#[tauri::command]
+fn set_unattended_password(
+    state: tauri::State<Arc<AppState>>,
+    password: String,
+) -> Result<(), AppError> {
+    let licensed = state.license_manager.lock().has_feature(license::LicenseFeature::UnattendedAccess);
+    if !licensed {
+        return Err(AppError::license_required(license::LicenseFeature::UnattendedAccess));
+    }
+    let mut config = state.connection_config.lock();
+    config.set_unattended_password(&password).map_err(AppError::invalid_input)?;
+    config.apply_unattended_access(licensed);
+    println!("[MAIN] Unattended access enabled");
+    Ok(())
+}
+
+/// Turn unattended access off; connection requests need approval again
+#[tauri::command]
+fn clear_unattended_password(state: tauri::State<Arc<AppState>>) -> Result<(), AppError> {
+    let mut config = state.connection_config.lock();
+    config.clear_unattended_password()?;
+    config.apply_unattended_access(false);
+    println!("[MAIN] Unattended access disabled");
+    Ok(())
+}
+
+/// Whether an unattended access password is set
+#[tauri::command]
+fn has_unattended_password(state: tauri::State<Arc<AppState>>) -> bool {
+    state.connection_config.lock().unattended_password.is_some()
+}
+
+/// Get list of blocked device IDs
+#[tauri::command]
+fn get_blocked_devices(state: tauri::State<Arc<AppState>>) -> Vec<String> {
+    let config = state.connection_config.lock();
+    config.get_blocked_devices().into_iter().cloned().collect()
+}
+
+/// Get saved session preferences for a trusted device
+#[tauri::command]
+fn get_device_preferences(
+    state: tauri::State<Arc<AppState>>,
+    device_id: String,
+) -> Option<config::DevicePreferences> {
+    state.connection_config.lock().get_device_preferences(&device_id).cloned()
+}
+
+/// Update saved session preferences for a trusted device
+/// Live sessions to that device pick up the new preferences immediately
+#[tauri::command]
+async fn set_device_preferences(
+    state: tauri::State<'_, Arc<AppState>>,
+    device_id: String,
+    preferences: config::DevicePreferences,
+) -> Result<(), AppError> {
+    let quality = {
+        let mut config = state.connection_config.lock();
+        config.set_device_preferences(&device_id, preferences.clone())
+            .map_err(AppError::invalid_input)?;
+        config.session_quality(&device_id)
+    };
+
+    let clean_id = device_id.replace(' ', "");
+    let entries: Vec<_> = state.client_sessions.lock()
+        .values()
+        .filter(|e| e.remote_id.replace(' ', "") == clean_id)
+        .cloned()
+        .collect();
+    for entry in entries {
+        entry.lock().await.session.set_quality_mode(quality).await?;
+        *entry.preferences.lock() = preferences.clone();
+    }
+    Ok(())
+}
+
 // ============================================================================
 // Settings Commands
 // ============================================================================
@@ -605,10 +2039,34 @@ struct SettingsInfo {
     show_notifications: bool,
     p2p_enabled: bool,
     connection_quality: String,
+    coalesce_frame_requests: bool,
+    relay_multiplexing: bool,
+    jitter_buffer_frames: u32,
     require_approval: bool,
     lock_on_disconnect: bool,
     session_timeout: u32,
+    approval_timeout_secs: u32,
+    start_view_only: bool,
     hide_from_address_book: bool,
+    snapshot_directory: String,
+    capture_backend: String,
+    content_mode: String,
+    capture_active_fps: u32,
+    capture_idle_fps: u32,
+    scroll_sensitivity: u32,
+    natural_scroll: bool,
+    input_batch_ms: u32,
+    max_clipboard_size_mb: u32,
+    recordings_dir: String,
+    proxy_url: String,
+    relay_ca_cert: String,
+    relay_tls_insecure: bool,
+    metrics_enabled: bool,
+    metrics_port: u32,
+    automation_enabled: bool,
+    reconnect_max_attempts: u32,
+    reconnect_max_backoff_secs: u32,
+    license_activation_url: String,
 }
 
 /// Get all settings
@@ -622,10 +2080,36 @@ fn get_settings(state: tauri::State<Arc<AppState>>) -> SettingsInfo {
         show_notifications: settings.show_notifications,
         p2p_enabled: settings.p2p_enabled,
         connection_quality: settings.connection_quality.clone(),
+        coalesce_frame_requests: settings.coalesce_frame_requests,
+        relay_multiplexing: settings.relay_multiplexing,
+        jitter_buffer_frames: settings.jitter_buffer_frames,
         require_approval: settings.require_approval,
         lock_on_disconnect: settings.lock_on_disconnect,
         session_timeout: settings.session_timeout,
+        approval_timeout_secs: settings.approval_timeout_secs,
+        start_view_only: settings.start_view_only,
         hide_from_address_book: settings.hide_from_address_book,
+        snapshot_directory: settings.snapshot_dir().to_string_lossy().to_string(),
+        capture_backend: settings.capture_backend.clone(),
+        content_mode: settings.content_mode.clone(),
+        capture_active_fps: settings.capture_active_fps,
+        capture_idle_fps: settings.capture_idle_fps,
+        scroll_sensitivity: settings.scroll_sensitivity,
+        natural_scroll: settings.natural_scroll,
+        input_batch_ms: settings.input_batch_ms,
+        max_clipboard_size_mb: settings.max_clipboard_size_mb,
+        recordings_dir: settings.recordings_path()
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        proxy_url: settings.proxy_url.clone(),
+        relay_ca_cert: settings.relay_ca_cert.clone(),
+        relay_tls_insecure: settings.relay_tls_insecure,
+        metrics_enabled: settings.metrics_enabled,
+        metrics_port: settings.metrics_port,
+        automation_enabled: settings.automation_enabled,
+        reconnect_max_attempts: settings.reconnect_max_attempts,
+        reconnect_max_backoff_secs: settings.reconnect_max_backoff_secs,
+        license_activation_url: settings.license_activation_url.clone(),
     }
 }
 
@@ -635,10 +2119,32 @@ fn set_setting_bool(
     state: tauri::State<Arc<AppState>>,
     key: String,
     value: bool,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let mut config = state.connection_config.lock();
     config.update_setting(&key, config::SettingValue::Bool(value))
-        .map_err(|e| e.to_string())
+        .map_err(AppError::invalid_input)?;
+
+    if key == "metrics_enabled" {
+        config.apply_metrics();
+    }
+    if key == "automation_enabled" {
+        config.apply_automation();
+    }
+    if key == "relay_tls_insecure" {
+        config.apply_tls();
+    }
+    // Viewers already accepted keep the control they have
+    if key == "start_view_only" {
+        config.apply_start_view_only();
+    }
+    if key == "lock_on_disconnect" {
+        config.apply_lock_on_disconnect();
+    }
+    // Only hosts announce themselves
+    if key == "hide_from_address_book" && state.host_handle.lock().is_some() {
+        update_lan_advert(&config, &state.identity.lock());
+    }
+    Ok(())
 }
 
 /// Update a string setting
@@ -647,10 +2153,36 @@ fn set_setting_string(
     state: tauri::State<Arc<AppState>>,
     key: String,
     value: String,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let mut config = state.connection_config.lock();
-    config.update_setting(&key, config::SettingValue::String(value))
-        .map_err(|e| e.to_string())
+    config.update_setting(&key, config::SettingValue::String(value.clone()))
+        .map_err(AppError::invalid_input)?;
+
+    // Backend preference takes effect on the next host session
+    if key == "capture_backend" {
+        if let Some(backend) = capture::CaptureBackend::from_setting(&value) {
+            capture::set_backend(backend);
+        }
+    }
+    // Content mode applies from the next frame
+    if key == "content_mode" {
+        if let Some(mode) = capture::ContentMode::from_setting(&value) {
+            capture::set_content_mode(mode);
+        }
+    }
+    // New relay connections pick the proxy up right away
+    if key == "proxy_url" {
+        config.apply_proxy();
+    }
+    // Likewise the relay CA; connections already open keep the certificate they checked
+    if key == "relay_ca_cert" {
+        config.apply_tls();
+    }
+    // Host sessions start at the new quality; running ones keep theirs
+    if key == "connection_quality" {
+        config.apply_connection_quality();
+    }
+    Ok(())
 }
 
 /// Update a number setting
@@ -659,10 +2191,31 @@ fn set_setting_number(
     state: tauri::State<Arc<AppState>>,
     key: String,
     value: u32,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let mut config = state.connection_config.lock();
     config.update_setting(&key, config::SettingValue::Number(value))
-        .map_err(|e| e.to_string())
+        .map_err(AppError::invalid_input)?;
+
+    // Capture rates apply to running sessions from the next frame
+    if key == "capture_active_fps" || key == "capture_idle_fps" {
+        config.apply_capture_rates();
+    }
+    // Requests already waiting keep the timeout they started with
+    if key == "approval_timeout_secs" {
+        config.apply_approval_timeout();
+    }
+    // Running sessions pick it up with their next frame
+    if key == "session_timeout" {
+        config.apply_session_timeout();
+    }
+    if key == "max_clipboard_size_mb" {
+        config.apply_clipboard_limit();
+    }
+    // Moves the metrics endpoint to the new port if it is running
+    if key == "metrics_port" {
+        config.apply_metrics();
+    }
+    Ok(())
 }
 
 // ============================================================================
@@ -672,15 +2225,19 @@ fn set_setting_number(
 /// Clipboard content info for frontend
 #[derive(serde::Serialize, serde::Deserialize)]
 struct ClipboardContent {
-    data_type: String, // "text", "image", "files"
+    data_type: String, // "text", "image", "files", "rich"
     text: Option<String>,
     image_data: Option<String>, // Base64 encoded
     files: Option<Vec<String>>,
+    #[serde(default)]
+    html: Option<String>,
+    #[serde(default)]
+    rtf: Option<String>,
 }
 
 /// Get local clipboard content
 #[tauri::command]
-fn get_local_clipboard(state: tauri::State<Arc<AppState>>) -> Result<Option<ClipboardContent>, String> {
+fn get_local_clipboard(state: tauri::State<Arc<AppState>>) -> Result<Option<ClipboardContent>, AppError> {
     match state.clipboard_manager.get_clipboard() {
         Ok(Some(data)) => {
             let content = match data {
@@ -689,6 +2246,8 @@ fn get_local_clipboard(state: tauri::State<Arc<AppState>>) -> Result<Option<Clip
                     text: Some(text),
                     image_data: None,
                     files: None,
+                    html: None,
+                    rtf: None,
                 },
                 clipboard::ClipboardData::Image { data, .. } => {
                     use base64::{Engine as _, engine::general_purpose::STANDARD};
@@ -697,6 +2256,8 @@ fn get_local_clipboard(state: tauri::State<Arc<AppState>>) -> Result<Option<Clip
                         text: None,
                         image_data: Some(STANDARD.encode(&data)),
                         files: None,
+                        html: None,
+                        rtf: None,
                     }
                 }
                 clipboard::ClipboardData::Files(files) => ClipboardContent {
@@ -704,12 +2265,22 @@ fn get_local_clipboard(state: tauri::State<Arc<AppState>>) -> Result<Option<Clip
                     text: None,
                     image_data: None,
                     files: Some(files),
+                    html: None,
+                    rtf: None,
+                },
+                clipboard::ClipboardData::Rich { text, html, rtf } => ClipboardContent {
+                    data_type: "rich".to_string(),
+                    text: Some(text),
+                    image_data: None,
+                    files: None,
+                    html,
+                    rtf,
                 },
             };
             Ok(Some(content))
         }
         Ok(None) => Ok(None),
-        Err(e) => Err(e.to_string()),
+        Err(e) => Err(e.into()),
     }
 }
 
@@ -718,56 +2289,110 @@ fn get_local_clipboard(state: tauri::State<Arc<AppState>>) -> Result<Option<Clip
 fn set_local_clipboard(
     state: tauri::State<Arc<AppState>>,
     content: ClipboardContent,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let data = match content.data_type.as_str() {
         "text" => {
-            let text = content.text.ok_or("Missing text content")?;
+            let text = content.text.ok_or_else(|| AppError::InvalidInput("Missing text content".into()))?;
             clipboard::ClipboardData::Text(text)
         }
         "image" => {
             use base64::{Engine as _, engine::general_purpose::STANDARD};
-            let encoded = content.image_data.ok_or("Missing image data")?;
-            let data = STANDARD.decode(&encoded).map_err(|e| e.to_string())?;
+            let encoded = content.image_data.ok_or_else(|| AppError::InvalidInput("Missing image data".into()))?;
+            let data = STANDARD.decode(&encoded).map_err(|e| AppError::InvalidInput(e.to_string()))?;
             clipboard::ClipboardData::Image { width: 0, height: 0, data }
         }
         "files" => {
-            let files = content.files.ok_or("Missing files")?;
+            let files = content.files.ok_or_else(|| AppError::InvalidInput("Missing files".into()))?;
             clipboard::ClipboardData::Files(files)
         }
-        _ => return Err("Unknown clipboard data type".to_string()),
+        "rich" => {
+            let text = content.text.ok_or_else(|| AppError::InvalidInput("Missing text content".into()))?;
+            if content.html.is_none() && content.rtf.is_none() {
+                return Err(AppError::InvalidInput("Missing rich content".into()));
+            }
+            clipboard::ClipboardData::Rich { text, html: content.html, rtf: content.rtf }
+        }
+        _ => return Err(AppError::InvalidInput("Unknown clipboard data type".into())),
     };
 
     state.clipboard_manager.update_hash(&data);
-    state.clipboard_manager.set_clipboard(&data).map_err(|e| e.to_string())
+    state.clipboard_manager.set_clipboard(&data).map_err(AppError::from)
 }
 
 /// Send clipboard to remote device
+/// The host's result is reported through the `clipboard-sync-result` event
 #[tauri::command]
 async fn send_clipboard_to_remote(
     state: tauri::State<'_, Arc<AppState>>,
+    app_handle: tauri::AppHandle,
     session_id: Option<String>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
+    require_feature(&state, license::LicenseFeature::Clipboard)?;
     let target_id = session_id
         .or_else(|| state.active_session_id.lock().clone())
-        .ok_or("No active session")?;
+        .ok_or(AppError::NoActiveSession)?;
 
     // Get local clipboard
     let data = match state.clipboard_manager.get_clipboard() {
         Ok(Some(d)) => d,
-        Ok(None) => return Err("Clipboard is empty".to_string()),
-        Err(e) => return Err(e.to_string()),
+        Ok(None) => return Err(AppError::InvalidInput("Clipboard is empty".into())),
+        Err(e) => return Err(e.into()),
     };
 
+    // Send via client session, in the richest format the host understands
+    let entry = state.client_session(&target_id).ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+    let mut link = entry.lock().await;
+    let data = data.for_peer(&link.session.capabilities());
+    // Too large even after compression: skip it rather than stall the video behind it
+    let max_size = clipboard::max_size();
+    let Some(data) = data.fit(max_size) else {
+        drop(link);
+        println!("[CLIPBOARD] Not sending {} clipboard: {} bytes, limit {}", data.type_name(), data.encoded_len(), max_size);
+        let _ = app_handle.emit("clipboard-too-large", serde_json::json!({
+            "session_id": target_id,
+            "data_type": data.type_name(),
+            "byte_count": data.encoded_len(),
+            "max_size": max_size,
+        }));
+        return Ok(());
+    };
     let encoded = data.encode();
+    link.session.send_clipboard(&encoded).await?;
 
-    // Send via client session
-    let mut sessions = state.client_sessions.lock().await;
-    if let Some(entry) = sessions.get_mut(&target_id) {
-        entry.session.send_clipboard(&encoded).await.map_err(|e| e.to_string())?;
-        Ok(())
+    // Hosts without CLIPBOARD_ACK never confirm, so don't wait for them
+    let acked = link.session.capabilities().supports_feature(protocol::feature::CLIPBOARD_ACK);
+    let (success, error) = if !acked {
+        println!("[CLIPBOARD] Host does not acknowledge clipboard pushes");
+        (true, None)
     } else {
-        Err("Session not found".to_string())
+        match link.session.receive_clipboard_ack(CLIPBOARD_ACK_TIMEOUT).await {
+            Ok(ack) => {
+                println!("[CLIPBOARD] Remote ack: {} ({} bytes), success={}", ack.type_name(), ack.byte_count, ack.success);
+                (ack.success, ack.error)
+            }
+            Err(e) => (false, Some(e.to_string())),
+        }
+    };
+    drop(link);
+
+    if !success {
+        eprintln!("[CLIPBOARD] Remote failed to apply clipboard: {}", error.as_deref().unwrap_or("unknown error"));
     }
+
+    let detail = match &error {
+        Some(e) if !success => format!("{} ({} bytes) failed: {}", data.type_name(), encoded.len(), e),
+        _ => format!("{} ({} bytes)", data.type_name(), encoded.len()),
+    };
+    state.session_events.lock().record(&target_id, "clipboard_sent", Some(detail));
+
+    let _ = app_handle.emit("clipboard-sync-result", serde_json::json!({
+        "session_id": target_id,
+        "success": success,
+        "error": error,
+        "data_type": data.type_name(),
+        "byte_count": encoded.len(),
+    }));
+    Ok(())
 }
 
 /// Request clipboard from remote device
@@ -775,30 +2400,154 @@ async fn send_clipboard_to_remote(
 async fn request_remote_clipboard(
     state: tauri::State<'_, Arc<AppState>>,
     session_id: Option<String>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
+    require_feature(&state, license::LicenseFeature::Clipboard)?;
     let target_id = session_id
         .or_else(|| state.active_session_id.lock().clone())
-        .ok_or("No active session")?;
+        .ok_or(AppError::NoActiveSession)?;
 
-    let mut sessions = state.client_sessions.lock().await;
-    if let Some(entry) = sessions.get_mut(&target_id) {
-        entry.session.request_clipboard().await.map_err(|e| e.to_string())?;
+    if let Some(entry) = state.client_session(&target_id) {
+        entry.lock().await.session.request_clipboard().await?;
+        state.session_events.lock().record(&target_id, "clipboard_requested", None);
         Ok(())
     } else {
-        Err("Session not found".to_string())
+        Err(AppError::SessionNotFound(target_id))
     }
 }
 
-/// Get clipboard sync enabled state
+/// Get clipboard sync enabled state (always off without a Clipboard license)
 #[tauri::command]
 fn get_clipboard_sync_enabled(state: tauri::State<Arc<AppState>>) -> bool {
     state.clipboard_manager.is_sync_enabled()
+        && state.license_manager.lock().has_feature(license::LicenseFeature::Clipboard)
 }
 
-/// Set clipboard sync enabled state
+/// Set clipboard sync enabled state; turning it on needs the Clipboard feature
 #[tauri::command]
-fn set_clipboard_sync_enabled(state: tauri::State<Arc<AppState>>, enabled: bool) {
+fn set_clipboard_sync_enabled(state: tauri::State<Arc<AppState>>, enabled: bool) -> Result<(), AppError> {
+    if enabled {
+        require_feature(&state, license::LicenseFeature::Clipboard)?;
+    }
     state.clipboard_manager.set_sync_enabled(enabled);
+    Ok(())
+}
+
+// ============================================================================
+// Snapshot Commands
+// ============================================================================
+
+/// Grab a full-quality still of the remote screen and save it to the snapshot folder
+/// Returns the path of the saved JPEG
+#[tauri::command]
+async fn take_remote_snapshot(
+    state: tauri::State<'_, Arc<AppState>>,
+    session_id: Option<String>,
+) -> Result<String, AppError> {
+    let target_id = session_id
+        .or_else(|| state.active_session_id.lock().clone())
+        .ok_or(AppError::NoActiveSession)?;
+
+    let (remote_id, frame) = {
+        let entry = state.client_session(&target_id).ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+        let snapshot = entry.lock().await.session.capture_snapshot().await?;
+        let frame = snapshot.ok_or_else(|| AppError::Network("Remote did not return a frame".into()))?;
+        (entry.remote_id.clone(), frame)
+    };
+
+    let dir = state.connection_config.lock().get_settings().snapshot_dir();
+    std::fs::create_dir_all(&dir)?;
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let extension = if frame.format == protocol::video::FORMAT_PNG { "png" } else { "jpg" };
+    let path = dir.join(format!("snapshot_{}_{}.{}", remote_id.replace(' ', ""), timestamp, extension));
+
+    std::fs::write(&path, &frame.data)?;
+    println!("[MAIN] Saved remote snapshot to {:?}", path);
+    state.session_events.lock().record(
+        &target_id,
+        "snapshot_saved",
+        Some(path.to_string_lossy().to_string()),
+    );
+
+    Ok(path.to_string_lossy().to_string())
+}
+
+// ============================================================================
+// Capture Sources
+// ============================================================================
+
+/// Monitors that can be captured - the remote host's when a session is given,
+/// otherwise this machine's (host mode)
+#[tauri::command]
+async fn get_host_monitors(
+    state: tauri::State<'_, Arc<AppState>>,
+    session_id: Option<String>,
+) -> Result<Vec<sources::CaptureSource>, AppError> {
+    list_capture_sources(&state, session_id, sources::SourceKind::Monitor).await
+}
+
+/// Top-level windows that can be captured, remote or local as for `get_host_monitors`
+#[tauri::command]
+async fn get_host_windows(
+    state: tauri::State<'_, Arc<AppState>>,
+    session_id: Option<String>,
+) -> Result<Vec<sources::CaptureSource>, AppError> {
+    list_capture_sources(&state, session_id, sources::SourceKind::Window).await
+}
+
+/// Switch the remote host to capturing another monitor (an index from `get_host_monitors`)
+#[tauri::command]
+async fn select_monitor(
+    state: tauri::State<'_, Arc<AppState>>,
+    index: u32,
+    session_id: Option<String>,
+) -> Result<(), AppError> {
+    set_capture_mode(state, capture::CaptureMode::Single(index), session_id).await
+}
+
+/// Switch the remote host between one monitor and all of them stitched together
+/// `mode` is `{ kind: "single", index }` or `{ kind: "all_monitors" }`
+#[tauri::command]
+async fn set_capture_mode(
+    state: tauri::State<'_, Arc<AppState>>,
+    mode: capture::CaptureMode,
+    session_id: Option<String>,
+) -> Result<(), AppError> {
+    require_feature(&state, license::LicenseFeature::MultiMonitor)?;
+    let target_id = session_id
+        .or_else(|| state.active_session_id.lock().clone())
+        .ok_or(AppError::NoActiveSession)?;
+
+    let entry = state.client_session(&target_id).ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+    if entry.view_only() {
+        return Err(AppError::PermissionDenied("Session is view only".into()));
+    }
+    entry.lock().await.session.set_remote_capture_mode(mode).await?;
+    let detail = match mode {
+        capture::CaptureMode::Single(index) => index.to_string(),
+        capture::CaptureMode::AllMonitors => "all".to_string(),
+    };
+    state.session_events.lock().record(&target_id, "monitor_selected", Some(detail));
+    Ok(())
+}
+
+async fn list_capture_sources(
+    state: &Arc<AppState>,
+    session_id: Option<String>,
+    kind: sources::SourceKind,
+) -> Result<Vec<sources::CaptureSource>, AppError> {
+    let Some(session_id) = session_id else {
+        return tokio::task::spawn_blocking(move || sources::list(kind))
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()));
+    };
+
+    let entry = state.client_session(&session_id).ok_or_else(|| AppError::SessionNotFound(session_id.clone()))?;
+    let mut link = entry.lock().await;
+    link.session.list_remote_sources(kind).await.map_err(AppError::from)
 }
 
 // ============================================================================
@@ -811,19 +2560,30 @@ fn start_recording(
     state: tauri::State<Arc<AppState>>,
     remote_device_id: String,
     remote_device_name: String,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
+    require_feature(&state, license::LicenseFeature::SessionRecording)?;
+    let dir = state.connection_config.lock().get_settings()
+        .recordings_path()?;
     state.recording_manager
-        .start_recording(&remote_device_id, &remote_device_name)
-        .map_err(|e| e.to_string())
+        .start_recording(&dir, &remote_device_id, &remote_device_name)?;
+
+    if let Some(id) = state.active_session_id.lock().clone() {
+        state.session_events.lock().record(&id, "recording_started", None);
+    }
+    Ok(())
 }
 
 /// Stop recording the session
 #[tauri::command]
-fn stop_recording(state: tauri::State<Arc<AppState>>) -> Result<String, String> {
-    state.recording_manager
+fn stop_recording(state: tauri::State<Arc<AppState>>) -> Result<String, AppError> {
+    let path = state.recording_manager
         .stop_recording()
-        .map(|p| p.to_string_lossy().to_string())
-        .map_err(|e| e.to_string())
+        .map(|p| p.to_string_lossy().to_string())?;
+
+    if let Some(id) = state.active_session_id.lock().clone() {
+        state.session_events.lock().record(&id, "recording_stopped", Some(path.clone()));
+    }
+    Ok(path)
 }
 
 /// Check if currently recording
@@ -840,44 +2600,139 @@ fn get_recording_status(state: tauri::State<Arc<AppState>>) -> Option<recording:
 
 /// List all recordings
 #[tauri::command]
-fn list_recordings() -> Result<Vec<recording::RecordingInfo>, String> {
-    recording::list_recordings().map_err(|e| e.to_string())
+fn list_recordings(state: tauri::State<Arc<AppState>>) -> Result<Vec<recording::RecordingInfo>, AppError> {
+    let dir = state.connection_config.lock().get_settings()
+        .recordings_path()?;
+    recording::list_recordings(&dir).map_err(AppError::from)
 }
 
 /// Delete a recording
 #[tauri::command]
-fn delete_recording(path: String) -> Result<(), String> {
-    recording::delete_recording(&path).map_err(|e| e.to_string())
+fn delete_recording(state: tauri::State<Arc<AppState>>, path: String) -> Result<(), AppError> {
+    let dir = state.connection_config.lock().get_settings()
+        .recordings_path()?;
+    recording::delete_recording(&dir, &path).map_err(AppError::from)
+}
+
+/// Get the click markers and key log recorded with a recording, for playback
+#[tauri::command]
+fn get_recording_input_overlay(
+    state: tauri::State<Arc<AppState>>,
+    path: String,
+) -> Result<recording::InputOverlay, AppError> {
+    require_feature(&state, license::LicenseFeature::SessionRecording)?;
+    let dir = state.connection_config.lock().get_settings()
+        .recordings_path()?;
+    recording::read_input_overlay(&dir, &path).map_err(AppError::from)
+}
+
+/// What a player UI needs to lay out its timeline
+#[derive(serde::Serialize)]
+struct PlaybackInfo {
+    duration_ms: u64,
+    frame_count: usize,
+    width: u16,
+    height: u16,
+    /// Timestamp of the frame playback starts from
+    position_ms: u64,
+}
+
+/// Play a recording from `position_ms` (seeking = starting again elsewhere).
+/// Frames arrive as `recording-playback-frame` events carrying base64 JPEGs,
+/// paced like the session at `speed`; `recording-playback-ended` follows the
+/// last one or a stop. Any playback already running is stopped
+#[tauri::command]
+fn start_recording_playback(
+    state: tauri::State<Arc<AppState>>,
+    app_handle: tauri::AppHandle,
+    path: String,
+    position_ms: Option<u64>,
+    speed: Option<f32>,
+) -> Result<PlaybackInfo, AppError> {
+    require_feature(&state, license::LicenseFeature::SessionRecording)?;
+    let dir = state.connection_config.lock().get_settings()
+        .recordings_path()?;
+    let mut player = recording::open_player(&dir, &path)?;
+    let position_ms = player.seek(position_ms.unwrap_or(0));
+    let info = PlaybackInfo {
+        duration_ms: player.duration_ms(),
+        frame_count: player.frame_count(),
+        width: player.metadata().width,
+        height: player.metadata().height,
+        position_ms,
+    };
+
+    let stop = Arc::new(AtomicBool::new(false));
+    if let Some(previous) = state.playback_stop.lock().replace(stop.clone()) {
+        previous.store(true, Ordering::Relaxed);
+    }
+    // File reads and pacing sleeps stay off the async runtime
+    std::thread::spawn(move || {
+        use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+        let result = player.play(speed.unwrap_or(1.0), &stop, |frame| {
+            let _ = app_handle.emit("recording-playback-frame", serde_json::json!({
+                "timestamp_ms": frame.timestamp_ms,
+                "width": frame.width,
+                "height": frame.height,
+                "jpeg": STANDARD.encode(&frame.data),
+            }));
+        });
+        let (reached, error) = match result {
+            Ok(reached) => (reached, None),
+            Err(e) => {
+                eprintln!("[RECORDING] Playback failed: {}", e);
+                (0, Some(e.to_string()))
+            }
+        };
+        let _ = app_handle.emit("recording-playback-ended", serde_json::json!({
+            "path": path,
+            "position_ms": reached,
+            "stopped": stop.load(Ordering::Relaxed),
+            "error": error,
+        }));
+    });
+    Ok(info)
+}
+
+/// Stop the recording being played back
+#[tauri::command]
+fn stop_recording_playback(state: tauri::State<Arc<AppState>>) {
+    if let Some(stop) = state.playback_stop.lock().take() {
+        stop.store(true, Ordering::Relaxed);
+    }
 }
 
 /// Open recordings folder
 #[tauri::command]
-fn open_recordings_folder() -> Result<(), String> {
-    let dir = recording::SessionRecorder::recordings_directory()
-        .map_err(|e| e.to_string())?;
+fn open_recordings_folder(state: tauri::State<Arc<AppState>>) -> Result<(), AppError> {
+    let dir = state.connection_config.lock().get_settings()
+        .recordings_path()?;
+    open_with_system(&dir)?;
+    Ok(())
+}
 
+/// Open a folder or URL with the system's default handler
+fn open_with_system(target: impl AsRef<std::ffi::OsStr>) -> std::io::Result<()> {
     #[cfg(target_os = "windows")]
     {
         std::process::Command::new("explorer")
-            .arg(&dir)
-            .spawn()
-            .map_err(|e| e.to_string())?;
+            .arg(target.as_ref())
+            .spawn()?;
     }
 
     #[cfg(target_os = "macos")]
     {
         std::process::Command::new("open")
-            .arg(&dir)
-            .spawn()
-            .map_err(|e| e.to_string())?;
+            .arg(target.as_ref())
+            .spawn()?;
     }
 
     #[cfg(target_os = "linux")]
     {
         std::process::Command::new("xdg-open")
-            .arg(&dir)
-            .spawn()
-            .map_err(|e| e.to_string())?;
+            .arg(target.as_ref())
+            .spawn()?;
     }
 
     Ok(())
@@ -888,17 +2743,20 @@ fn open_recordings_folder() -> Result<(), String> {
 // ============================================================================
 
 /// Get SSO status and info
+/// A session about to expire is refreshed first (see `refresh_sso_if_needed`)
 #[tauri::command]
-async fn get_sso_info(state: tauri::State<'_, Arc<AppState>>) -> Result<sso::SsoInfo, String> {
-    let manager = state.sso_manager.lock().await;
-    Ok(sso::SsoInfo::from_manager(&manager))
+async fn get_sso_info(
+    app_handle: tauri::AppHandle,
+    state: tauri::State<'_, Arc<AppState>>,
+) -> Result<sso::SsoInfo, AppError> {
+    Ok(refresh_sso_if_needed(&app_handle, state.inner()).await)
 }
 
 /// List configured SSO providers
 #[tauri::command]
 async fn list_sso_providers(
     state: tauri::State<'_, Arc<AppState>>,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<String>, AppError> {
     let manager = state.sso_manager.lock().await;
     Ok(manager.list_providers().iter().map(|p| p.name.clone()).collect())
 }
@@ -920,31 +2778,31 @@ struct SsoProviderConfig {
 async fn add_sso_provider(
     state: tauri::State<'_, Arc<AppState>>,
     config: SsoProviderConfig,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let provider = match config.provider_type.as_str() {
         "azure" => {
-            let tenant_id = config.tenant_id.ok_or("Missing tenant_id for Azure AD")?;
+            let tenant_id = config.tenant_id.ok_or_else(|| AppError::InvalidInput("Missing tenant_id for Azure AD".into()))?;
             sso::OidcProvider::azure_ad(&tenant_id, &config.client_id)
         }
         "okta" => {
-            let domain = config.domain.ok_or("Missing domain for Okta")?;
+            let domain = config.domain.ok_or_else(|| AppError::InvalidInput("Missing domain for Okta".into()))?;
             sso::OidcProvider::okta(&domain, &config.client_id)
         }
         "google" => {
-            let client_secret = config.client_secret.ok_or("Missing client_secret for Google")?;
+            let client_secret = config.client_secret.ok_or_else(|| AppError::InvalidInput("Missing client_secret for Google".into()))?;
             sso::OidcProvider::google(&config.client_id, &client_secret)
         }
         "custom" => {
-            let discovery_url = config.discovery_url.ok_or("Missing discovery_url for custom OIDC")?;
+            let discovery_url = config.discovery_url.ok_or_else(|| AppError::InvalidInput("Missing discovery_url for custom OIDC".into()))?;
             sso::OidcProvider::from_discovery(&discovery_url, &config.client_id)
                 .await
-                .map_err(|e| e.to_string())?
+                .map_err(AppError::auth)?
         }
-        _ => return Err(format!("Unknown provider type: {}", config.provider_type)),
+        _ => return Err(AppError::InvalidInput(format!("Unknown provider type: {}", config.provider_type))),
     };
 
     let mut manager = state.sso_manager.lock().await;
-    manager.add_provider(provider).map_err(|e| e.to_string())
+    manager.add_provider(provider).map_err(AppError::invalid_input)
 }
 
 /// Remove an SSO provider
@@ -952,9 +2810,9 @@ async fn add_sso_provider(
 async fn remove_sso_provider(
     state: tauri::State<'_, Arc<AppState>>,
     name: String,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let mut manager = state.sso_manager.lock().await;
-    manager.remove_provider(&name).map_err(|e| e.to_string())
+    manager.remove_provider(&name).map_err(AppError::from)
 }
 
 /// SSO login response
@@ -962,6 +2820,8 @@ async fn remove_sso_provider(
 struct SsoLoginResponse {
     auth_url: String,
     redirect_uri: String,
+    /// Passed back to complete_sso_login as `expected_state`
+    state: String,
 }
 
 /// Start SSO login flow - returns URL to open in browser
@@ -969,19 +2829,20 @@ struct SsoLoginResponse {
 async fn start_sso_login(
     state: tauri::State<'_, Arc<AppState>>,
     provider_name: String,
-) -> Result<SsoLoginResponse, String> {
-    let manager = state.sso_manager.lock().await;
+) -> Result<SsoLoginResponse, AppError> {
+    let mut manager = state.sso_manager.lock().await;
     let provider = manager
         .config()
         .get_provider(&provider_name)
-        .ok_or(format!("Provider {} not found", provider_name))?
+        .ok_or_else(|| AppError::InvalidInput(format!("Provider {} not found", provider_name)))?
         .clone();
 
-    let (auth_url, redirect_uri, _pkce) = manager
+    // The callback listener stays bound in the manager until complete_sso_login
+    let (auth_url, redirect_uri, login_state) = manager
         .start_login(&provider)
-        .map_err(|e| e.to_string())?;
+        .map_err(AppError::auth)?;
 
-    Ok(SsoLoginResponse { auth_url, redirect_uri })
+    Ok(SsoLoginResponse { auth_url, redirect_uri, state: login_state })
 }
 
 /// Complete SSO login - waits for callback and exchanges code for tokens
@@ -991,30 +2852,22 @@ async fn complete_sso_login(
     provider_name: String,
     redirect_uri: String,
     expected_state: String,
-) -> Result<sso::SsoInfo, String> {
+) -> Result<sso::SsoInfo, AppError> {
     let provider = {
         let manager = state.sso_manager.lock().await;
         manager
             .config()
             .get_provider(&provider_name)
-            .ok_or(format!("Provider {} not found", provider_name))?
+            .ok_or_else(|| AppError::InvalidInput(format!("Provider {} not found", provider_name)))?
             .clone()
     };
 
-    // Generate new PKCE for the callback
-    let pkce = if provider.use_pkce {
-        // Note: In a real implementation, we'd need to store and retrieve the PKCE
-        // from the start_sso_login call. For now we use fresh PKCE.
-        None
-    } else {
-        None
-    };
-
+    // The PKCE verifier was kept with the login by start_sso_login
     let mut manager = state.sso_manager.lock().await;
     manager
-        .wait_for_callback(&provider, &redirect_uri, &expected_state, pkce)
+        .wait_for_callback(&provider, &redirect_uri, &expected_state)
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(AppError::auth)?;
 
     Ok(sso::SsoInfo::from_manager(&manager))
 }
@@ -1023,22 +2876,33 @@ async fn complete_sso_login(
 #[tauri::command]
 async fn refresh_sso_session(
     state: tauri::State<'_, Arc<AppState>>,
-) -> Result<sso::SsoInfo, String> {
+) -> Result<sso::SsoInfo, AppError> {
     let mut manager = state.sso_manager.lock().await;
-    manager.refresh_session().await.map_err(|e| e.to_string())?;
+    manager.refresh_session().await.map_err(AppError::auth)?;
     Ok(sso::SsoInfo::from_manager(&manager))
 }
 
 /// Logout from SSO
+/// Also ends the provider's browser session when it has an end-session
+/// endpoint, so the next login on a shared machine asks for credentials
 #[tauri::command]
-async fn sso_logout(state: tauri::State<'_, Arc<AppState>>) -> Result<(), String> {
+async fn sso_logout(state: tauri::State<'_, Arc<AppState>>) -> Result<(), AppError> {
     let mut manager = state.sso_manager.lock().await;
-    manager.logout().map_err(|e| e.to_string())
+    let Some((logout_url, redirect_uri, logout_state)) = manager.logout().map_err(AppError::from)? else {
+        return Ok(());
+    };
+
+    open_with_system(&logout_url)?;
+    // The local session is already gone; the provider redirecting back is best effort
+    if let Err(e) = manager.wait_for_logout(&redirect_uri, &logout_state).await {
+        eprintln!("[SSO] Provider logout did not complete: {}", e);
+    }
+    Ok(())
 }
 
 /// Check if SSO is required for connections
 #[tauri::command]
-async fn is_sso_required(state: tauri::State<'_, Arc<AppState>>) -> Result<bool, String> {
+async fn is_sso_required(state: tauri::State<'_, Arc<AppState>>) -> Result<bool, AppError> {
     let manager = state.sso_manager.lock().await;
     Ok(manager.config().require_sso)
 }
@@ -1048,9 +2912,9 @@ async fn is_sso_required(state: tauri::State<'_, Arc<AppState>>) -> Result<bool,
 async fn set_sso_required(
     state: tauri::State<'_, Arc<AppState>>,
     required: bool,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let mut manager = state.sso_manager.lock().await;
-    manager.set_require_sso(required).map_err(|e| e.to_string())
+    manager.set_require_sso(required).map_err(AppError::invalid_input)
 }
 
 /// Set allowed email domains for SSO
@@ -1058,20 +2922,105 @@ async fn set_sso_required(
 async fn set_sso_allowed_domains(
     state: tauri::State<'_, Arc<AppState>>,
     domains: Vec<String>,
-) -> Result<(), String> {
+) -> Result<(), AppError> {
     let mut manager = state.sso_manager.lock().await;
-    manager.set_allowed_domains(domains).map_err(|e| e.to_string())
+    manager.set_allowed_domains(domains).map_err(AppError::invalid_input)
 }
 
 /// Get allowed email domains for SSO
 #[tauri::command]
 async fn get_sso_allowed_domains(
     state: tauri::State<'_, Arc<AppState>>,
-) -> Result<Vec<String>, String> {
+) -> Result<Vec<String>, AppError> {
     let manager = state.sso_manager.lock().await;
     Ok(manager.config().allowed_domains.clone())
 }
 
+/// How often the SSO session is checked for an upcoming expiry
+/// Well inside the 5-minute `needs_refresh` window, so a failed attempt is retried
+const SSO_REFRESH_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Keep the SSO session fresh for the app's lifetime
+async fn sso_refresh_loop(app_handle: tauri::AppHandle) {
+    let mut interval = tokio::time::interval(SSO_REFRESH_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let state = app_handle.state::<Arc<AppState>>().inner().clone();
+        refresh_sso_if_needed(&app_handle, &state).await;
+    }
+}
+
+/// Refresh the SSO session if it is about to expire and tell the UI
+/// Emits `sso-session-refreshed` with the new session info, or
+/// `sso-session-expired` once the session is gone; with `require_sso` set,
+/// client sessions are dropped too. Callers are serialized by the manager's
+/// mutex, so when two checks race only the first refreshes
+async fn refresh_sso_if_needed(app_handle: &tauri::AppHandle, state: &Arc<AppState>) -> sso::SsoInfo {
+    let (outcome, info) = {
+        let mut manager = state.sso_manager.lock().await;
+        let outcome = manager.refresh_if_needed().await;
+        (outcome, sso::SsoInfo::from_manager(&manager))
+    };
+
+    match outcome {
+        sso::RefreshOutcome::Refreshed => {
+            println!("[SSO] Session refreshed");
+            let _ = app_handle.emit("sso-session-refreshed", &info);
+        }
+        sso::RefreshOutcome::Expired => {
+            println!("[SSO] Session expired");
+            let disconnect = info.require_sso;
+            if disconnect {
+                println!("[SSO] SSO is required, dropping active connections");
+                disconnect_all(state).await;
+            }
+            let _ = app_handle.emit("sso-session-expired", serde_json::json!({
+                "disconnected": disconnect,
+            }));
+        }
+        sso::RefreshOutcome::NotNeeded | sso::RefreshOutcome::Retry => {}
+    }
+    info
+}
+
+/// How often a running app checks its license against the revocation list
+/// Well inside license::OFFLINE_GRACE, so a few failed checks don't lapse it
+const LICENSE_REVOCATION_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// Check the license for revocation at startup and daily after. A failed check
+/// leaves the license alone; it only lapses once the offline grace runs out
+async fn license_revocation_loop(app_handle: tauri::AppHandle) {
+    let mut interval = tokio::time::interval(LICENSE_REVOCATION_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let state = app_handle.state::<Arc<AppState>>().inner().clone();
+
+        let key_id = state.license_manager.lock().key_id().map(str::to_string);
+        let Some(key_id) = key_id else {
+            continue;
+        };
+
+        let list = match license::fetch_revocations().await {
+            Ok(list) => list,
+            Err(e) => {
+                eprintln!("[LICENSE] Revocation check failed: {}", e);
+                continue;
+            }
+        };
+        let mut manager = state.license_manager.lock();
+        match manager.apply_revocations(&list) {
+            Ok(true) => {
+                println!("[LICENSE] License {} was revoked - back to Free", key_id);
+                let licensed = manager.has_feature(license::LicenseFeature::UnattendedAccess);
+                state.connection_config.lock().apply_unattended_access(licensed);
+                let _ = app_handle.emit("license-revoked", serde_json::json!({ "key_id": key_id }));
+            }
+            Ok(false) => {}
+            Err(e) => eprintln!("[LICENSE] Failed to save revocation check: {}", e),
+        }
+    }
+}
+
 // ============================================================================
 // License Commands
 // ============================================================================
@@ -1082,24 +3031,45 @@ fn get_license_info(state: tauri::State<Arc<AppState>>) -> license::LicenseInfo
     state.license_manager.lock().license_info()
 }
 
-/// Activate a license key
+/// Activate a license key: a signed license as is, a portal key ("SD-...")
+/// through the activation endpoint
 #[tauri::command]
-fn activate_license(
-    state: tauri::State<Arc<AppState>>,
+async fn activate_license(
+    state: tauri::State<'_, Arc<AppState>>,
     license_key: String,
-) -> Result<String, String> {
+) -> Result<String, AppError> {
+    let online = if license::License::is_activation_key(&license_key) {
+        let url = state.connection_config.lock().settings.license_activation_url.clone();
+        let device_id = state.identity.lock().device_id_raw();
+        let license = license::activate_online(&url, &license_key, &device_id).await
+            .map_err(|e| match AppError::from(e) {
+                AppError::Internal(message) => AppError::InvalidInput(message),
+                other => other,
+            })?;
+        Some(license)
+    } else {
+        None
+    };
+
     let mut manager = state.license_manager.lock();
-    match manager.activate(&license_key) {
-        Ok(tier) => Ok(tier.as_str().to_string()),
-        Err(e) => Err(e.to_string()),
-    }
+    let activated = match online {
+        Some(license) => manager.install(license).map_err(AppError::from),
+        None => manager.activate(&license_key).map_err(AppError::invalid_input),
+    };
+    let tier = activated?;
+    let licensed = manager.has_feature(license::LicenseFeature::UnattendedAccess);
+    state.connection_config.lock().apply_unattended_access(licensed);
+    Ok(tier.as_str().to_string())
 }
 
 /// Deactivate current license (revert to Free)
 #[tauri::command]
-fn deactivate_license(state: tauri::State<Arc<AppState>>) -> Result<(), String> {
+fn deactivate_license(state: tauri::State<Arc<AppState>>) -> Result<(), AppError> {
     let mut manager = state.license_manager.lock();
-    manager.deactivate().map_err(|e| e.to_string())
+    manager.deactivate()?;
+    let licensed = manager.has_feature(license::LicenseFeature::UnattendedAccess);
+    state.connection_config.lock().apply_unattended_access(licensed);
+    Ok(())
 }
 
 /// Get current license tier
@@ -1108,6 +3078,94 @@ fn get_license_tier(state: tauri::State<Arc<AppState>>) -> String {
     state.license_manager.lock().current_tier().as_str().to_string()
 }
 
+// ============================================================================
+// Automation
+// ============================================================================
+
+/// Run a command from the automation socket (see ipc.rs)
+async fn handle_ipc_command(state: &Arc<AppState>, command: ipc::Command) -> Result<serde_json::Value, AppError> {
+    match command {
+        ipc::Command::Sessions => {
+            let sessions = state.client_sessions.lock();
+            let active_id = state.active_session_id.lock().clone();
+            Ok(sessions_by_recent_use(&sessions)
+                .into_iter()
+                .filter_map(|id| sessions.get(&id).map(|entry| serde_json::json!({
+                    "session_id": id,
+                    "remote_id": entry.remote_id,
+                    "active": active_id.as_ref() == Some(&id),
+                })))
+                .collect())
+        }
+        ipc::Command::Input { session, action } => {
+            let target_id = session
+                .or_else(|| state.active_session_id.lock().clone())
+                .ok_or(AppError::NoActiveSession)?;
+            {
+                let entry = state.client_session(&target_id).ok_or_else(|| AppError::SessionNotFound(target_id.clone()))?;
+                if entry.view_only() {
+                    return Err(AppError::PermissionDenied("Session is view only".into()));
+                }
+            }
+
+            let id = Some(target_id);
+            match action {
+                ipc::InputAction::Move { x, y } => {
+                    deliver_mouse(state, id, x, y, "move".into(), None).await?;
+                }
+                ipc::InputAction::Click { x, y, button } => {
+                    deliver_mouse(state, id.clone(), x, y, "move".into(), None).await?;
+                    deliver_mouse(state, id.clone(), x, y, "down".into(), Some(button)).await?;
+                    deliver_mouse(state, id, x, y, "up".into(), Some(button)).await?;
+                }
+                ipc::InputAction::Scroll { dx, dy } => {
+                    let units = input::SCROLL_UNITS_PER_NOTCH;
+                    deliver_mouse(state, id, dx * units, dy * units, "scroll".into(), None).await?;
+                }
+                ipc::InputAction::Key { code } => {
+                    deliver_key(state, id.clone(), code, true).await?;
+                    deliver_key(state, id, code, false).await?;
+                }
+            }
+            Ok(serde_json::Value::Null)
+        }
+    }
+}
+
+// ============================================================================
+// Shutdown
+// ============================================================================
+
+/// How long to wait for the host session to be released before giving up on it
+const HOST_STOP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// End everything in order before the process exits, so remote peers see
+/// SESSION_END instead of a dropped connection and no overlay or input hook
+/// is left behind
+async fn shutdown(state: &AppState) {
+    println!("[MAIN] Shutting down");
+    disconnect_all(state).await;
+    ipc::configure(false);
+
+    stop_host(state).await;
+
+    if state.recording_manager.is_recording() {
+        if let Err(e) = state.recording_manager.stop_recording() {
+            eprintln!("[RECORDING] Failed to stop recording: {}", e);
+        }
+    }
+}
+
+/// Run `shutdown` off the UI thread, then exit the app
+fn shutdown_and_exit(app: &tauri::AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let state = app.state::<Arc<AppState>>().inner().clone();
+        shutdown(&state).await;
+        app.exit(0);
+    });
+}
+
 // ============================================================================
 // Main
 // ============================================================================
@@ -1146,11 +3204,34 @@ fn main() {
     let connection_config = config::ConnectionConfig::load_or_create()
         .unwrap_or_default();
 
+    if let Some(backend) = capture::CaptureBackend::from_setting(&connection_config.settings.capture_backend) {
+        capture::set_backend(backend);
+    }
+    if let Some(mode) = capture::ContentMode::from_setting(&connection_config.settings.content_mode) {
+        capture::set_content_mode(mode);
+    }
+    connection_config.apply_proxy();
+    connection_config.apply_tls();
+    connection_config.apply_metrics();
+    connection_config.apply_stun();
+    connection_config.apply_capture_rates();
+    connection_config.apply_approval_timeout();
+    connection_config.apply_session_timeout();
+    connection_config.apply_clipboard_limit();
+    connection_config.apply_start_view_only();
+    connection_config.apply_lock_on_disconnect();
+    connection_config.apply_blocked_devices();
+    connection_config.apply_allowed_viewers();
+    connection_config.apply_connection_quality();
+    connection_config.apply_keymap();
+    connection_config.apply_control_policy();
+
     // Initialize license manager with device key for encryption
     let mut license_manager = license::LicenseManager::new(identity.public_key());
     if let Err(e) = license_manager.load() {
         eprintln!("[LICENSE] Failed to load license: {}", e);
     }
+    connection_config.apply_unattended_access(license_manager.has_feature(license::LicenseFeature::UnattendedAccess));
 
     // Use relay from CLI if provided
     let relay_addresses = if let Some(ref relay) = cli_args.relay {
@@ -1160,13 +3241,15 @@ fn main() {
     };
 
     // Initialize SSO manager
-    let sso_manager = sso::SsoManager::new()
+    let sso_manager = sso::SsoManager::new(&identity)
         .expect("Failed to initialize SSO manager");
 
     let app_state = Arc::new(AppState {
         identity: SyncMutex::new(identity),
         host_session: AsyncMutex::new(None),
-        client_sessions: AsyncMutex::new(HashMap::new()),
+        host_handle: SyncMutex::new(None),
+        host_task: AsyncMutex::new(None),
+        client_sessions: SyncMutex::new(HashMap::new()),
         active_session_id: SyncMutex::new(None),
         session_counter: AtomicU64::new(0),
         relay_addresses: SyncMutex::new(relay_addresses),
@@ -1174,9 +3257,20 @@ fn main() {
         license_manager: SyncMutex::new(license_manager),
         clipboard_manager: clipboard::ClipboardManager::new(),
         recording_manager: recording::RecordingManager::new(),
+        playback_stop: SyncMutex::new(None),
         sso_manager: AsyncMutex::new(sso_manager),
+        session_events: SyncMutex::new(session_events::SessionEventLog::default()),
+        relay_muxes: AsyncMutex::new(HashMap::new()),
     });
 
+    // `securedesk input` drives these sessions through the automation socket
+    let ipc_state = app_state.clone();
+    ipc::set_handler(move |command| {
+        let state = ipc_state.clone();
+        Box::pin(async move { handle_ipc_command(&state, command).await.map_err(|e| e.to_string()) })
+    });
+    app_state.connection_config.lock().apply_automation();
+
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .manage(app_state)
@@ -1199,7 +3293,7 @@ fn main() {
                                 let _ = window.set_focus();
                             }
                         }
-                        "quit" => std::process::exit(0),
+                        "quit" => shutdown_and_exit(app),
                         _ => {}
                     }
                 })
@@ -1214,43 +3308,102 @@ fn main() {
                 })
                 .build(app)?;
 
+            tauri::async_runtime::spawn(sso_refresh_loop(app.handle().clone()));
+            tauri::async_runtime::spawn(license_revocation_loop(app.handle().clone()));
+
             Ok(())
         })
         .on_window_event(|window, event| {
             if let WindowEvent::CloseRequested { api, .. } = event {
-                // Minimize to tray instead of closing
-                let _ = window.hide();
                 api.prevent_close();
+                let state = window.state::<Arc<AppState>>();
+                let minimize_to_tray = state.connection_config.lock().settings.minimize_to_tray;
+                if minimize_to_tray {
+                    // Minimize to tray instead of closing
+                    let _ = window.hide();
+                } else {
+                    shutdown_and_exit(window.app_handle());
+                }
             }
         })
         .invoke_handler(tauri::generate_handler![
             get_device_id,
+            get_device_fingerprint,
+            get_remote_fingerprint,
             regenerate_device_id,
             set_relay_address,
             start_host_listener,
+            stop_host_listener,
+            discover_lan_peers,
             connect_to_remote,
+            connect_by_alias,
             disconnect_session,
+            disconnect_by_remote_id,
+            get_session_id_for_remote,
             disconnect_all_sessions,
             set_black_screen,
             set_input_block,
+            get_remote_privacy_status,
+            set_focus_lock,
+            set_audio_enabled,
             send_mouse,
             send_key,
             send_resolution,
+            set_session_quality,
+            is_encrypted,
             request_video_frame,
+            request_video_frame_binary,
             respond_to_connection,
+            list_temporary_trust,
+            list_allowed_viewers,
+            remove_allowed_viewer,
+            revoke_temporary_trust,
+            list_pending_connections,
+            list_host_viewers,
+            grant_control,
+            revoke_control,
+            request_control,
+            // Chat commands
+            send_chat,
+            send_host_chat,
+            start_file_upload,
+            list_incoming_transfers,
             // Multi-session commands
             list_sessions,
             set_active_session,
             get_active_session,
             get_session_count,
+            get_session_events,
+            export_session_transcript,
+            // Diagnostics commands
+            get_capture_stats,
+            get_session_stats,
+            get_connection_stats,
+            // Elevation commands
+            get_elevation_status,
+            relaunch_elevated,
+            request_host_elevation,
             // P2P commands
             get_p2p_enabled,
             set_p2p_enabled,
+            get_stun_config,
+            set_stun_enabled,
+            set_stun_servers,
+            get_control_policy,
+            set_control_policy,
             get_connection_type,
             is_device_trusted,
             add_trusted_device,
             remove_trusted_device,
             get_trusted_devices,
+            block_device,
+            unblock_device,
+            set_unattended_password,
+            clear_unattended_password,
+            has_unattended_password,
+            get_blocked_devices,
+            get_device_preferences,
+            set_device_preferences,
             get_license_info,
             activate_license,
             deactivate_license,
@@ -1266,6 +3419,13 @@ fn main() {
             request_remote_clipboard,
             get_clipboard_sync_enabled,
             set_clipboard_sync_enabled,
+            // Snapshot commands
+            take_remote_snapshot,
+            // Capture source commands
+            get_host_monitors,
+            select_monitor,
+            set_capture_mode,
+            get_host_windows,
             // Recording commands
             start_recording,
             stop_recording,
@@ -1273,6 +3433,9 @@ fn main() {
             get_recording_status,
             list_recordings,
             delete_recording,
+            get_recording_input_overlay,
+            start_recording_playback,
+            stop_recording_playback,
             open_recordings_folder,
             // SSO/OIDC commands
             get_sso_info,
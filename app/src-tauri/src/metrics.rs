@@ -0,0 +1,299 @@
+//! Local metrics endpoint - Prometheus text format for self-hosted monitoring
+//!
+//! Off by default. When enabled it listens on 127.0.0.1 only and answers
+//! `GET /metrics`; nothing is ever pushed anywhere, a scraper has to ask.
+//! Counters are process-wide and bumped where the data flows.
+
+use crate::qos::RttTracker;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Port used when none is configured
+pub const DEFAULT_METRICS_PORT: u16 = 9464;
+
+/// Longest request header read from a scraper
+const MAX_REQUEST_LEN: usize = 4096;
+
+/// How long a scraper may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+static BYTES_SENT: AtomicU64 = AtomicU64::new(0);
+static BYTES_RECEIVED: AtomicU64 = AtomicU64::new(0);
+static RELAY_RECONNECTS: AtomicU64 = AtomicU64::new(0);
+static P2P_ATTEMPTS: AtomicU64 = AtomicU64::new(0);
+static P2P_SUCCESSES: AtomicU64 = AtomicU64::new(0);
+static CLIENT_SESSIONS: AtomicU64 = AtomicU64::new(0);
+static HOST_SESSIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Round trips of client sessions to their hosts; None until the first sample
+static RTT: Lazy<Mutex<Option<RttTracker>>> = Lazy::new(|| Mutex::new(None));
+
+/// Port of the running endpoint and the task serving it
+type RunningServer = (u16, tauri::async_runtime::JoinHandle<()>);
+
+static SERVER: Lazy<Mutex<Option<RunningServer>>> = Lazy::new(|| Mutex::new(None));
+
+/// Bytes written to a relay or P2P link, framing included
+pub fn record_bytes_sent(bytes: usize) {
+    BYTES_SENT.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+/// Bytes read from a relay or P2P link, framing included
+pub fn record_bytes_received(bytes: usize) {
+    BYTES_RECEIVED.fetch_add(bytes as u64, Ordering::Relaxed);
+}
+
+/// The host reconnected to the relay after losing it
+pub fn record_relay_reconnect() {
+    RELAY_RECONNECTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A P2P connection was attempted
+pub fn record_p2p_attempt(succeeded: bool) {
+    P2P_ATTEMPTS.fetch_add(1, Ordering::Relaxed);
+    if succeeded {
+        P2P_SUCCESSES.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A ping's round trip, or from a frame request to its reply for hosts without PING
+pub fn record_rtt(rtt: Duration) {
+    let ms = rtt.as_millis().min(u32::MAX as u128) as u32;
+    RTT.lock().get_or_insert_with(RttTracker::new).add_sample(ms);
+}
+
+/// Which side of a session a `SessionGuard` counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionRole {
+    /// Controlling a remote device
+    Client,
+    /// Being viewed by a remote client
+    Host,
+}
+
+impl SessionRole {
+    fn gauge(self) -> &'static AtomicU64 {
+        match self {
+            SessionRole::Client => &CLIENT_SESSIONS,
+            SessionRole::Host => &HOST_SESSIONS,
+        }
+    }
+}
+
+/// Counts one active session for as long as it is alive
+pub struct SessionGuard {
+    role: SessionRole,
+}
+
+impl SessionGuard {
+    pub fn new(role: SessionRole) -> Self {
+        role.gauge().fetch_add(1, Ordering::Relaxed);
+        Self { role }
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.role.gauge().fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Point-in-time copy of every metric
+struct Snapshot {
+    client_sessions: u64,
+    host_sessions: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    frames_encoded: u64,
+    relay_reconnects: u64,
+    p2p_attempts: u64,
+    p2p_successes: u64,
+    /// None before the first sample, or once samples stopped coming
+    rtt_ms: Option<u32>,
+}
+
+impl Snapshot {
+    fn take() -> Self {
+        let rtt_ms = RTT.lock()
+            .as_ref()
+            .filter(|tracker| !tracker.is_stale())
+            .map(|tracker| tracker.get_rtt());
+
+        Self {
+            client_sessions: CLIENT_SESSIONS.load(Ordering::Relaxed),
+            host_sessions: HOST_SESSIONS.load(Ordering::Relaxed),
+            bytes_sent: BYTES_SENT.load(Ordering::Relaxed),
+            bytes_received: BYTES_RECEIVED.load(Ordering::Relaxed),
+            frames_encoded: crate::capture::get_encode_stats().frame_count as u64,
+            relay_reconnects: RELAY_RECONNECTS.load(Ordering::Relaxed),
+            p2p_attempts: P2P_ATTEMPTS.load(Ordering::Relaxed),
+            p2p_successes: P2P_SUCCESSES.load(Ordering::Relaxed),
+            rtt_ms,
+        }
+    }
+
+    /// Prometheus text exposition format (version 0.0.4)
+    fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, String)]| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "{}{} {}", name, labels, value);
+            }
+        };
+
+        metric("securedesk_active_sessions", "gauge", "Sessions currently connected", &[
+            ("{role=\"client\"}", self.client_sessions.to_string()),
+            ("{role=\"host\"}", self.host_sessions.to_string()),
+        ]);
+        metric("securedesk_bytes_sent_total", "counter", "Bytes sent to relay and P2P links", &[
+            ("", self.bytes_sent.to_string()),
+        ]);
+        metric("securedesk_bytes_received_total", "counter", "Bytes received from relay and P2P links", &[
+            ("", self.bytes_received.to_string()),
+        ]);
+        metric("securedesk_frames_encoded_total", "counter", "Screen frames captured and encoded", &[
+            ("", self.frames_encoded.to_string()),
+        ]);
+        metric("securedesk_relay_reconnects_total", "counter", "Times the host reconnected to the relay", &[
+            ("", self.relay_reconnects.to_string()),
+        ]);
+        metric("securedesk_p2p_attempts_total", "counter", "P2P connections attempted", &[
+            ("", self.p2p_attempts.to_string()),
+        ]);
+        metric("securedesk_p2p_successes_total", "counter", "P2P connections established", &[
+            ("", self.p2p_successes.to_string()),
+        ]);
+        if self.p2p_attempts > 0 {
+            let ratio = self.p2p_successes as f64 / self.p2p_attempts as f64;
+            metric("securedesk_p2p_success_ratio", "gauge", "Share of P2P attempts that succeeded", &[
+                ("", format!("{:.3}", ratio)),
+            ]);
+        }
+        if let Some(rtt) = self.rtt_ms {
+            metric("securedesk_rtt_milliseconds", "gauge", "Smoothed round trip to the host", &[
+                ("", rtt.to_string()),
+            ]);
+        }
+        out
+    }
+}
+
+/// Start, move or stop the endpoint to match the settings
+pub fn configure(enabled: bool, port: u16) {
+    let mut server = SERVER.lock();
+    if let Some((current_port, handle)) = server.as_ref() {
+        if enabled && *current_port == port {
+            return;
+        }
+        handle.abort();
+        println!("[METRICS] Endpoint on port {} stopped", current_port);
+        *server = None;
+    }
+
+    if enabled {
+        let handle = tauri::async_runtime::spawn(serve(port));
+        *server = Some((port, handle));
+    }
+}
+
+async fn serve(port: u16) {
+    let listener = match TcpListener::bind(("127.0.0.1", port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("[METRICS] Failed to listen on 127.0.0.1:{}: {}", port, e);
+            return;
+        }
+    };
+    println!("[METRICS] Serving http://127.0.0.1:{}/metrics", port);
+
+    loop {
+        let Ok((stream, _)) = listener.accept().await else { continue };
+        tokio::spawn(async move {
+            let _ = tokio::time::timeout(REQUEST_TIMEOUT, answer(stream)).await;
+        });
+    }
+}
+
+/// Answer one scrape and close the connection
+async fn answer(mut stream: TcpStream) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 512];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        if request.len() >= MAX_REQUEST_LEN {
+            break;
+        }
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let response = response_for(request.lines().next().unwrap_or_default());
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// The full HTTP response for a request line
+fn response_for(request_line: &str) -> String {
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            Snapshot::take().render(),
+        ),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "Method not allowed\n".to_string()),
+    };
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prometheus_text() {
+        let snapshot = Snapshot {
+            client_sessions: 2,
+            host_sessions: 0,
+            bytes_sent: 1024,
+            bytes_received: 4096,
+            frames_encoded: 30,
+            relay_reconnects: 1,
+            p2p_attempts: 4,
+            p2p_successes: 3,
+            rtt_ms: None,
+        };
+        let text = snapshot.render();
+
+        assert!(text.contains("# TYPE securedesk_active_sessions gauge\n"));
+        assert!(text.contains("securedesk_active_sessions{role=\"client\"} 2\n"));
+        assert!(text.contains("securedesk_bytes_received_total 4096\n"));
+        assert!(text.contains("securedesk_p2p_success_ratio 0.750\n"));
+        // No round trip measured yet: the gauge is left out rather than reported as 0
+        assert!(!text.contains("securedesk_rtt_milliseconds"));
+    }
+
+    #[test]
+    fn test_only_metrics_path_is_served() {
+        assert!(response_for("GET /metrics HTTP/1.1").starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response_for("GET / HTTP/1.1").starts_with("HTTP/1.1 404"));
+        assert!(response_for("POST /metrics HTTP/1.1").starts_with("HTTP/1.1 405"));
+    }
+}
@@ -0,0 +1,333 @@
+//! Relay multiplexing - several client sessions sharing one relay connection
+//!
+//! A multiplexed connection registers once with `relay::CLIENT_TECHNICIAN_MUX`.
+//! Every frame on it carries a stream ID ahead of its payload:
+//! `[channel (1)][length (3)][stream (2, BE)][payload]`
+//! Stream 0 is reserved for opening and closing the others.
+
+use anyhow::Result;
+use parking_lot::Mutex as SyncMutex;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot, Mutex as AsyncMutex};
+use tokio_rustls::client::TlsStream;
+
+use crate::crypto::Identity;
+use crate::protocol::{relay, Channel, Frame, FrameReader};
+
+/// How long to wait for the relay to confirm multiplexing before falling back
+const MUX_READY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long to wait for the relay to connect a new stream to its target
+const MUX_OPEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+type RelayStream = TlsStream<TcpStream>;
+
+/// Where incoming frames go, by stream ID
+#[derive(Default)]
+struct Routes {
+    streams: HashMap<u16, mpsc::UnboundedSender<Frame>>,
+    /// Streams waiting for the relay to answer MUX_OPEN
+    opening: HashMap<u16, oneshot::Sender<std::result::Result<(), String>>>,
+    /// Set once the shared connection is gone
+    closed: bool,
+}
+
+/// One relay connection carrying any number of client sessions
+pub struct RelayMux {
+    relay_address: String,
+    writer: AsyncMutex<WriteHalf<RelayStream>>,
+    routes: Arc<SyncMutex<Routes>>,
+    next_stream: AtomicU16,
+}
+
+impl RelayMux {
+    /// Register a multiplexed connection with the relay
+    /// Returns `Ok(None)` when the relay predates multiplexing (it closes the
+    /// connection without confirming), so callers can connect per session instead
+    pub async fn connect(relay_address: &str, identity: &Identity) -> Result<Option<Arc<Self>>> {
+        let mut stream = crate::client::ClientSession::open_relay_stream(relay_address).await?;
+
+        let my_id = identity.device_id_raw();
+        stream.write_u8(relay::CLIENT_TECHNICIAN_MUX).await?;
+        stream.write_all(&(my_id.len() as u16).to_be_bytes()).await?;
+        stream.write_all(my_id.as_bytes()).await?;
+        stream.flush().await?;
+
+        let ready = match tokio::time::timeout(MUX_READY_TIMEOUT, read_raw_frame(&mut stream)).await {
+            Ok(Ok(frame)) => frame.channel == Channel::Control
+                && frame.payload.as_slice() == [relay::REPLY_MUX_READY],
+            Ok(Err(_)) | Err(_) => false,
+        };
+        if !ready {
+            println!("[MUX] Relay {} does not support multiplexing", relay_address);
+            return Ok(None);
+        }
+        println!("[MUX] Multiplexed connection to {} ready", relay_address);
+
+        let (reader, writer) = tokio::io::split(stream);
+        let routes = Arc::new(SyncMutex::new(Routes::default()));
+        tokio::spawn(Self::read_task(reader, routes.clone(), relay_address.to_string()));
+
+        Ok(Some(Arc::new(Self {
+            relay_address: relay_address.to_string(),
+            writer: AsyncMutex::new(writer),
+            routes,
+            next_stream: AtomicU16::new(1),
+        })))
+    }
+
+    /// Relay this connection goes through
+    pub fn relay_address(&self) -> &str {
+        &self.relay_address
+    }
+
+    /// False once the shared connection has dropped; open a new one
+    pub fn is_alive(&self) -> bool {
+        !self.routes.lock().closed
+    }
+
+    /// Open a session to `target_id` on this connection
+    pub async fn open_stream(self: &Arc<Self>, target_id: &str) -> Result<MuxStream> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let (open_tx, open_rx) = oneshot::channel();
+        let id = {
+            let mut routes = self.routes.lock();
+            if routes.closed {
+                anyhow::bail!("Relay connection closed");
+            }
+            let id = self.allocate_stream_id(&routes)?;
+            routes.streams.insert(id, tx);
+            routes.opening.insert(id, open_tx);
+            id
+        };
+
+        if let Err(e) = self.send_control(relay::MUX_OPEN, id, target_id.as_bytes()).await {
+            self.forget(id);
+            return Err(e);
+        }
+
+        match tokio::time::timeout(MUX_OPEN_TIMEOUT, open_rx).await {
+            Ok(Ok(Ok(()))) => Ok(MuxStream { id, mux: self.clone(), rx }),
+            Ok(Ok(Err(message))) => {
+                self.forget(id);
+                anyhow::bail!("Connection failed: {}", message)
+            }
+            Ok(Err(_)) => anyhow::bail!("Relay connection closed"),
+            Err(_) => {
+                self.forget(id);
+                anyhow::bail!("Timed out opening relay stream")
+            }
+        }
+    }
+
+    /// Next free stream ID, skipping the control stream and IDs still in use
+    fn allocate_stream_id(&self, routes: &Routes) -> Result<u16> {
+        for _ in 0..u16::MAX {
+            let id = self.next_stream.fetch_add(1, Ordering::Relaxed);
+            if id != relay::MUX_CONTROL_STREAM && !routes.streams.contains_key(&id) {
+                return Ok(id);
+            }
+        }
+        anyhow::bail!("No free relay streams")
+    }
+
+    fn forget(&self, id: u16) {
+        let mut routes = self.routes.lock();
+        routes.streams.remove(&id);
+        routes.opening.remove(&id);
+    }
+
+    async fn send_control(&self, op: u8, stream: u16, data: &[u8]) -> Result<()> {
+        self.write(relay::MUX_CONTROL_STREAM, Frame::control(op, &control_body(stream, data))).await
+    }
+
+    async fn write(&self, stream: u16, frame: Frame) -> Result<()> {
+        let mut writer = self.writer.lock().await;
+        write_raw_frame(&mut *writer, frame.channel_byte(), &encode_payload(stream, &frame.payload)).await
+    }
+
+    /// Route incoming frames to their streams until the connection drops
+    async fn read_task(mut reader: ReadHalf<RelayStream>, routes: Arc<SyncMutex<Routes>>, relay_address: String) {
+        let mut frames = FrameReader::new();
+        loop {
+            let frame = match frames.read(&mut reader).await {
+                Ok(frame) => frame,
+                Err(e) => {
+                    println!("[MUX] Connection to {} closed: {}", relay_address, e);
+                    break;
+                }
+            };
+            let Some((stream, payload)) = decode_payload(&frame.payload) else {
+                println!("[MUX] Dropping frame without a stream ID");
+                continue;
+            };
+
+            let mut routes = routes.lock();
+            if stream == relay::MUX_CONTROL_STREAM {
+                Self::handle_control(&mut routes, payload);
+            } else if let Some(tx) = routes.streams.get(&stream) {
+                let routed = Frame { channel: frame.channel, payload: payload.to_vec(), more: frame.more };
+                if tx.send(routed).is_err() {
+                    routes.streams.remove(&stream);
+                }
+            }
+        }
+
+        // Dropping the senders ends every stream's reads
+        let mut routes = routes.lock();
+        routes.closed = true;
+        routes.streams.clear();
+        routes.opening.clear();
+    }
+
+    fn handle_control(routes: &mut Routes, payload: &[u8]) {
+        let Some((&op, body)) = payload.split_first() else {
+            return;
+        };
+        let Some((stream, data)) = decode_payload(body) else {
+            return;
+        };
+
+        match op {
+            relay::MUX_OPEN => {
+                if let Some(opened) = routes.opening.remove(&stream) {
+                    let _ = opened.send(Ok(()));
+                }
+            }
+            relay::REPLY_ERROR => {
+                let message = String::from_utf8_lossy(data).to_string();
+                match routes.opening.remove(&stream) {
+                    Some(opened) => {
+                        let _ = opened.send(Err(message));
+                    }
+                    None => {
+                        routes.streams.remove(&stream);
+                    }
+                }
+            }
+            // The host side went away; the session sees its reads end
+            relay::MUX_CLOSE => {
+                routes.streams.remove(&stream);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// One client session on a shared relay connection
+pub struct MuxStream {
+    id: u16,
+    mux: Arc<RelayMux>,
+    rx: mpsc::UnboundedReceiver<Frame>,
+}
+
+impl MuxStream {
+    pub async fn read_frame(&mut self) -> Result<Frame> {
+        self.rx.recv().await.ok_or_else(|| anyhow::anyhow!("Relay stream closed"))
+    }
+
+    pub async fn write_frame(&mut self, frame: Frame) -> Result<()> {
+        self.mux.write(self.id, frame).await
+    }
+}
+
+impl Drop for MuxStream {
+    /// Release the stream on the relay; the shared connection stays open
+    fn drop(&mut self) {
+        self.mux.forget(self.id);
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let mux = self.mux.clone();
+            let id = self.id;
+            runtime.spawn(async move {
+                let _ = mux.send_control(relay::MUX_CLOSE, id, &[]).await;
+            });
+        }
+    }
+}
+
+/// Prefix a payload with its stream ID
+fn encode_payload(stream: u16, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(2 + payload.len());
+    out.extend(&stream.to_be_bytes());
+    out.extend(payload);
+    out
+}
+
+/// Split a payload into its stream ID and the rest
+fn decode_payload(payload: &[u8]) -> Option<(u16, &[u8])> {
+    if payload.len() < 2 {
+        return None;
+    }
+    Some((u16::from_be_bytes([payload[0], payload[1]]), &payload[2..]))
+}
+
+/// Control stream body after the op byte: [stream (2, BE)][data]
+fn control_body(stream: u16, data: &[u8]) -> Vec<u8> {
+    encode_payload(stream, data)
+}
+
+async fn read_raw_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Frame> {
+    let mut header = [0u8; 4];
+    reader.read_exact(&mut header).await?;
+
+    let channel = Channel::try_from(header[0])?;
+    let len = ((header[1] as usize) << 16)
+        | ((header[2] as usize) << 8)
+        | (header[3] as usize);
+
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(Frame::new(channel, payload))
+}
+
+async fn write_raw_frame<W: AsyncWrite + Unpin>(writer: &mut W, channel_byte: u8, payload: &[u8]) -> Result<()> {
+    let len = payload.len();
+    let header = [
+        channel_byte,
+        (len >> 16) as u8,
+        (len >> 8) as u8,
+        len as u8,
+    ];
+    writer.write_all(&header).await?;
+    writer.write_all(payload).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stream_id_round_trip() {
+        let payload = encode_payload(0x1234, b"frame");
+        assert_eq!(&payload[..2], &[0x12, 0x34]);
+        assert_eq!(decode_payload(&payload), Some((0x1234, &b"frame"[..])));
+        assert_eq!(decode_payload(&[0x01]), None);
+    }
+
+    #[test]
+    fn test_control_replies_reach_streams() {
+        let mut routes = Routes::default();
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let (open_tx, mut open_rx) = oneshot::channel();
+        routes.streams.insert(7, tx);
+        routes.opening.insert(7, open_tx);
+
+        let mut opened = vec![relay::MUX_OPEN];
+        opened.extend(control_body(7, &[]));
+        RelayMux::handle_control(&mut routes, &opened);
+        assert_eq!(open_rx.try_recv().unwrap(), Ok(()));
+        assert!(routes.streams.contains_key(&7));
+
+        let mut closed = vec![relay::MUX_CLOSE];
+        closed.extend(control_body(7, &[]));
+        RelayMux::handle_control(&mut routes, &closed);
+        assert!(routes.streams.is_empty());
+    }
+}
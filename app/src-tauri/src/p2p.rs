@@ -2,6 +2,9 @@
 //!
 //! Handles direct peer-to-peer connections with UDP hole punching
 //! and automatic fallback to relay on failure.
+//!
+//! A direct link is only used once the same Noise_XK handshake as the relay
+//! session has run over it; one that fails the handshake is dropped.
 
 use anyhow::Result;
 use std::net::SocketAddr;
@@ -9,22 +12,30 @@ use std::time::Duration;
 use tokio::net::TcpStream;
 use tokio::time::timeout;
 
+use crate::crypto::Identity;
 use crate::stun::{discover_public_address_async, get_local_address_async};
 use crate::transport::{P2PInfo, P2PTransport};
 
 /// P2P connection timeout (5 seconds)
 const P2P_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// Time allowed for the Noise handshake on a new direct link
+const P2P_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// P2P handshake port offset from STUN-discovered port
 /// Reserved for future UDP hole punching implementation
 #[allow(dead_code)]
 const P2P_PORT_OFFSET: u16 = 1000;
 
-/// Attempt to establish a P2P connection to the remote peer
+/// Attempt to establish a secured P2P connection to the remote peer, the host
+/// holding `host_key`; `lan_addr` is where LAN discovery last saw it
 /// Returns None if P2P fails (fallback to relay should be used)
 pub async fn attempt_p2p_connection(
     remote_info: &P2PInfo,
     local_info: &P2PInfo,
+    identity: &Identity,
+    host_key: &[u8; 32],
+    lan_addr: Option<SocketAddr>,
 ) -> Result<Option<P2PTransport>> {
     // Check if either side has P2P disabled
     if !remote_info.p2p_enabled && !local_info.p2p_enabled {
@@ -37,14 +48,24 @@ pub async fn attempt_p2p_connection(
     println!("[P2P] Local: public={:?}, local={:?}", local_info.public_addr, local_info.local_addr);
 
     // Try connection strategies in order of preference:
+    // 0. Where mDNS found the host (it answered from that address)
     // 1. Same LAN (local addresses match network)
     // 2. Direct public IP connection
     // 3. UDP hole punching (more complex, future enhancement)
 
+    // Strategy 0: Try the discovered address
+    if let Some(lan_addr) = lan_addr {
+        println!("[P2P] Trying discovered LAN address: {}", lan_addr);
+        if let Some(transport) = try_connect(lan_addr, identity, host_key).await {
+            println!("[P2P] Connected via discovered LAN address!");
+            return Ok(Some(transport));
+        }
+    }
+
     // Strategy 1: Try local address (same LAN)
-    if let Some(local_addr) = remote_info.local_addr {
+    if let Some(local_addr) = remote_info.local_addr.filter(|addr| Some(*addr) != lan_addr) {
         println!("[P2P] Trying local address: {}", local_addr);
-        if let Some(transport) = try_connect(local_addr).await {
+        if let Some(transport) = try_connect(local_addr, identity, host_key).await {
             println!("[P2P] Connected via local address!");
             return Ok(Some(transport));
         }
@@ -53,7 +74,7 @@ pub async fn attempt_p2p_connection(
     // Strategy 2: Try public address (direct connection)
     if let Some(public_addr) = remote_info.public_addr {
         println!("[P2P] Trying public address: {}", public_addr);
-        if let Some(transport) = try_connect(public_addr).await {
+        if let Some(transport) = try_connect(public_addr, identity, host_key).await {
             println!("[P2P] Connected via public address!");
             return Ok(Some(transport));
         }
@@ -63,12 +84,23 @@ pub async fn attempt_p2p_connection(
     Ok(None)
 }
 
-/// Try to connect to an address with timeout
-async fn try_connect(addr: SocketAddr) -> Option<P2PTransport> {
+/// Try to connect to an address with timeout, then secure the link
+async fn try_connect(addr: SocketAddr, identity: &Identity, host_key: &[u8; 32]) -> Option<P2PTransport> {
     match timeout(P2P_CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
         Ok(Ok(stream)) => {
             println!("[P2P] TCP connection established to {}", addr);
-            Some(P2PTransport::new(stream, addr))
+            let mut transport = P2PTransport::new(stream, addr);
+            match timeout(P2P_HANDSHAKE_TIMEOUT, transport.secure_initiator(identity, host_key)).await {
+                Ok(Ok(())) => Some(transport),
+                Ok(Err(e)) => {
+                    println!("[P2P] Handshake with {} failed: {}", addr, e);
+                    None
+                }
+                Err(_) => {
+                    println!("[P2P] Handshake with {} timed out", addr);
+                    None
+                }
+            }
         }
         Ok(Err(e)) => {
             println!("[P2P] Connection to {} failed: {}", addr, e);
@@ -89,10 +121,13 @@ pub async fn create_p2p_listener(local_port: u16) -> Result<tokio::net::TcpListe
     Ok(listener)
 }
 
-/// Accept a P2P connection with timeout
+/// Accept a P2P connection with timeout and secure it; the peer must prove
+/// `client_key`, the key of the viewer on the relay
 pub async fn accept_p2p_connection(
     listener: &tokio::net::TcpListener,
     expected_addr: Option<SocketAddr>,
+    identity: &Identity,
+    client_key: &[u8; 32],
 ) -> Result<Option<P2PTransport>> {
     match timeout(P2P_CONNECT_TIMEOUT, listener.accept()).await {
         Ok(Ok((stream, peer_addr))) => {
@@ -106,7 +141,18 @@ pub async fn accept_p2p_connection(
                 }
             }
 
-            Ok(Some(P2PTransport::new(stream, peer_addr)))
+            let mut transport = P2PTransport::new(stream, peer_addr);
+            match timeout(P2P_HANDSHAKE_TIMEOUT, transport.secure_responder(identity, client_key)).await {
+                Ok(Ok(())) => Ok(Some(transport)),
+                Ok(Err(e)) => {
+                    println!("[P2P] Handshake with {} failed: {}", peer_addr, e);
+                    Ok(None)
+                }
+                Err(_) => {
+                    println!("[P2P] Handshake with {} timed out", peer_addr);
+                    Ok(None)
+                }
+            }
         }
         Ok(Err(e)) => {
             println!("[P2P] Accept failed: {}", e);
@@ -120,6 +166,8 @@ pub async fn accept_p2p_connection(
 }
 
 /// Gather P2P connection info for this peer
+/// The public address comes from the configured STUN servers (cached, and
+/// skipped entirely when STUN is disabled)
 pub async fn gather_p2p_info(p2p_enabled: bool, listen_port: u16) -> P2PInfo {
     let public_addr = if p2p_enabled {
         match discover_public_address_async().await {
@@ -192,4 +240,82 @@ mod tests {
         assert!(port1 >= 49152);
         assert!(port1 <= 65535);
     }
+
+    #[test]
+    fn test_direct_link_is_secured() {
+        use crate::protocol::{Channel, Frame};
+        use crate::transport::Transport;
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let host = Identity::generate();
+            let client = Identity::generate();
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+
+            // The viewer proves the key it used on the relay; frames then pass encrypted
+            let (accepted, dialled) = tokio::join!(
+                accept_p2p_connection(&listener, None, &host, client.public_key()),
+                try_connect(addr, &client, host.public_key()),
+            );
+            let (mut host_link, mut client_link) = (accepted.unwrap().unwrap(), dialled.unwrap());
+            assert!(host_link.is_secured() && client_link.is_secured());
+            client_link.write_frame(Frame::new(Channel::Input, b"click".to_vec())).await.unwrap();
+            assert_eq!(host_link.read_frame().await.unwrap().payload, b"click");
+
+            // Anyone else reaching the port is turned away, and so is a wrong host
+            let impostor = Identity::generate();
+            let (accepted, dialled) = tokio::join!(
+                accept_p2p_connection(&listener, None, &host, client.public_key()),
+                try_connect(addr, &impostor, host.public_key()),
+            );
+            assert!(accepted.unwrap().is_none());
+            assert!(dialled.is_none());
+            let (_, dialled) = tokio::join!(
+                accept_p2p_connection(&listener, None, &host, client.public_key()),
+                try_connect(addr, &client, impostor.public_key()),
+            );
+            assert!(dialled.is_none());
+
+            // An unsecured link never sends plaintext
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let mut raw = P2PTransport::new(stream, addr);
+            assert!(raw.write_frame(Frame::new(Channel::Input, b"click".to_vec())).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_direct_link_carries_session_frames() {
+        use crate::crypto::SecureChannel;
+        use crate::protocol::{self, Channel, Frame};
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let host = Identity::generate();
+            let client = Identity::generate();
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let (accepted, dialled) = tokio::join!(
+                accept_p2p_connection(&listener, None, &host, client.public_key()),
+                try_connect(addr, &client, host.public_key()),
+            );
+            let (mut host_reader, mut host_writer) = accepted.unwrap().unwrap().into_direct().unwrap();
+            let (mut client_reader, mut client_writer) = dialled.unwrap().into_direct().unwrap();
+
+            // A video frame longer than a Noise message arrives whole
+            let image: Vec<u8> = (0..SecureChannel::MAX_PLAINTEXT * 2 + 7).map(|i| i as u8).collect();
+            host_writer.write_frame(&Frame::video(image.clone())).await.unwrap();
+            host_writer.write_frame(&Frame::control(protocol::control::KEEPALIVE, &[])).await.unwrap();
+            let frame = client_reader.read_frame().await.unwrap();
+            assert_eq!((frame.channel, frame.payload), (Channel::Video, image));
+            assert_eq!(client_reader.read_frame().await.unwrap().payload, [protocol::control::KEEPALIVE]);
+
+            client_writer.write_frame(&Frame::new(Channel::Input, Vec::new())).await.unwrap();
+            assert!(host_reader.read_frame().await.unwrap().payload.is_empty());
+
+            // The other side closing ends the reader rather than leaving it waiting
+            drop((host_reader, host_writer));
+            assert!(client_reader.read_frame().await.is_err());
+        });
+    }
 }
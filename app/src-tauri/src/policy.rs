@@ -0,0 +1,284 @@
+//! Control policy - host windows the remote side may not control
+//!
+//! Rules match the window in the foreground on the host by title or process.
+//! While one matches, injected input is dropped and, if the rule asks for it,
+//! the window is blacked out in captured frames. Only Windows reports the
+//! foreground window; elsewhere rules are kept but never match.
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// How long a foreground check is reused (pointer input arrives far faster)
+const RECHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Longest pattern accepted, in bytes
+pub const MAX_PATTERN_LEN: usize = 256;
+
+/// What a rule's pattern is compared with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatchOn {
+    /// The window title
+    #[default]
+    Title,
+    /// The executable file name, e.g. "keepass.exe"
+    Process,
+}
+
+/// One policy entry, stored in the config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyRule {
+    /// Case-insensitive, matched against the whole value; `*` matches any run of characters
+    pub pattern: String,
+    #[serde(default)]
+    pub match_on: MatchOn,
+    /// Also black out the window in captured frames
+    #[serde(default)]
+    pub blackout: bool,
+}
+
+impl PolicyRule {
+    fn matches(&self, window: &ForegroundWindow) -> bool {
+        let value = match self.match_on {
+            MatchOn::Title => &window.title,
+            MatchOn::Process => &window.process,
+        };
+        glob_match(&self.pattern, value)
+    }
+}
+
+/// The window in the foreground on the host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ForegroundWindow {
+    pub title: String,
+    /// Executable file name, without the directory
+    pub process: String,
+    /// Desktop coordinates: left, top, right, bottom
+    pub rect: (i32, i32, i32, i32),
+}
+
+/// A rule matching the foreground window
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PolicyHit {
+    pub rule: PolicyRule,
+    pub window: ForegroundWindow,
+}
+
+static RULES: Lazy<Mutex<Vec<PolicyRule>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// When the foreground window was last checked, and the rule it matched
+type LastCheck = Option<(Instant, Option<PolicyHit>)>;
+
+static LAST_CHECK: Lazy<Mutex<LastCheck>> = Lazy::new(|| Mutex::new(None));
+
+/// Replace the active rules
+pub fn configure(rules: &[PolicyRule]) {
+    *RULES.lock() = rules.to_vec();
+    *LAST_CHECK.lock() = None;
+    if !rules.is_empty() && !cfg!(windows) {
+        println!("[POLICY] {} rule(s) loaded; window policy is only enforced on Windows hosts", rules.len());
+    }
+}
+
+/// Trim patterns and refuse empty or oversized ones
+pub fn normalize_rules(rules: Vec<PolicyRule>) -> Result<Vec<PolicyRule>> {
+    rules
+        .into_iter()
+        .map(|mut rule| {
+            rule.pattern = rule.pattern.trim().to_string();
+            if rule.pattern.is_empty() {
+                anyhow::bail!("Policy pattern is empty");
+            }
+            if rule.pattern.len() > MAX_PATTERN_LEN {
+                anyhow::bail!("Policy pattern is longer than {} bytes", MAX_PATTERN_LEN);
+            }
+            Ok(rule)
+        })
+        .collect()
+}
+
+/// The first rule matching the foreground window, if any
+pub fn foreground_hit() -> Option<PolicyHit> {
+    let rules = RULES.lock().clone();
+    if rules.is_empty() {
+        return None;
+    }
+
+    let mut last = LAST_CHECK.lock();
+    if let Some((at, hit)) = last.as_ref() {
+        if at.elapsed() < RECHECK_INTERVAL {
+            return hit.clone();
+        }
+    }
+    let hit = platform::foreground_window().and_then(|window| first_hit(&rules, window));
+    *last = Some((Instant::now(), hit.clone()));
+    hit
+}
+
+fn first_hit(rules: &[PolicyRule], window: ForegroundWindow) -> Option<PolicyHit> {
+    let rule = rules.iter().find(|rule| rule.matches(&window))?;
+    Some(PolicyHit { rule: rule.clone(), window })
+}
+
+/// A copy of a packed RGB frame with the foreground window blacked out, if a
+/// blackout rule matches it. The frame shows the monitor at the desktop origin
+pub fn masked_frame(rgb: &[u8], width: u32, height: u32) -> Option<Vec<u8>> {
+    let rect = blackout_rect()?;
+    let mut masked = rgb.to_vec();
+    blank_rect(&mut masked, width as usize, height as usize, rect);
+    Some(masked)
+}
+
+/// The area `masked_frame` blacks out (left, top, right, bottom), if any
+pub fn blackout_rect() -> Option<(i32, i32, i32, i32)> {
+    foreground_hit().filter(|hit| hit.rule.blackout).map(|hit| hit.window.rect)
+}
+
+/// Fill `rect` (clipped to the frame) with black
+pub fn blank_rect(rgb: &mut [u8], width: usize, height: usize, rect: (i32, i32, i32, i32)) {
+    let clip = |value: i32, max: usize| value.clamp(0, max as i32) as usize;
+    let (left, right) = (clip(rect.0, width), clip(rect.2, width));
+    let (top, bottom) = (clip(rect.1, height), clip(rect.3, height));
+    if left >= right {
+        return;
+    }
+    for row in rgb.chunks_exact_mut(width * 3).take(bottom).skip(top) {
+        row[left * 3..right * 3].fill(0);
+    }
+}
+
+/// Case-insensitive match of the whole of `text`, `*` matching any run of characters
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    // Greedy scan, backtracking to the last star on a mismatch
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::ForegroundWindow;
+    use windows::core::PWSTR;
+    use windows::Win32::Foundation::{CloseHandle, RECT};
+    use windows::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+    use windows::Win32::UI::WindowsAndMessaging::{
+        GetForegroundWindow, GetWindowRect, GetWindowTextW, GetWindowThreadProcessId,
+    };
+
+    pub fn foreground_window() -> Option<ForegroundWindow> {
+        unsafe {
+            let hwnd = GetForegroundWindow();
+            if hwnd.0 == 0 {
+                return None;
+            }
+
+            let mut title = [0u16; 512];
+            let copied = GetWindowTextW(hwnd, &mut title).max(0) as usize;
+            let mut rect = RECT::default();
+            GetWindowRect(hwnd, &mut rect).ok()?;
+            let mut pid = 0u32;
+            GetWindowThreadProcessId(hwnd, Some(&mut pid));
+
+            Some(ForegroundWindow {
+                title: String::from_utf16_lossy(&title[..copied]),
+                process: process_name(pid).unwrap_or_default(),
+                rect: (rect.left, rect.top, rect.right, rect.bottom),
+            })
+        }
+    }
+
+    /// Executable file name of a process (elevated processes may refuse the query)
+    unsafe fn process_name(pid: u32) -> Option<String> {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+        let mut path = [0u16; 1024];
+        let mut len = path.len() as u32;
+        let queried = QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, PWSTR(path.as_mut_ptr()), &mut len);
+        let _ = CloseHandle(process);
+        queried.ok()?;
+
+        let path = String::from_utf16_lossy(&path[..len as usize]);
+        path.rsplit('\\').next().map(str::to_string)
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use super::ForegroundWindow;
+
+    pub fn foreground_window() -> Option<ForegroundWindow> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, match_on: MatchOn) -> PolicyRule {
+        PolicyRule { pattern: pattern.to_string(), match_on, blackout: false }
+    }
+
+    #[test]
+    fn test_rules_match_foreground_window() {
+        assert!(glob_match("*bank*", "My Bank - Online Banking"));
+        assert!(glob_match("keepass.exe", "KeePass.exe"));
+        assert!(glob_match("*.kdbx - keepass", "Passwords.kdbx - KeePass"));
+        assert!(!glob_match("keepass", "KeePassXC"));
+        assert!(!glob_match("*bank", "Bank statement"));
+        assert!(glob_match("*", ""));
+
+        let window = ForegroundWindow {
+            title: "Passwords.kdbx - KeePass".to_string(),
+            process: "KeePass.exe".to_string(),
+            rect: (0, 0, 100, 100),
+        };
+        let rules = vec![rule("KeePass*", MatchOn::Title), rule("keepass.exe", MatchOn::Process)];
+        let hit = first_hit(&rules, window.clone()).unwrap();
+        assert_eq!(hit.rule.match_on, MatchOn::Process);
+        assert!(first_hit(&rules[..1], window).is_none());
+
+        assert!(normalize_rules(vec![rule("  ", MatchOn::Title)]).is_err());
+        assert_eq!(normalize_rules(vec![rule(" *bank* ", MatchOn::Title)]).unwrap()[0].pattern, "*bank*");
+    }
+
+    #[test]
+    fn test_blank_rect_clips_to_frame() {
+        let (width, height) = (4, 3);
+        let mut rgb = vec![0xFF; width * height * 3];
+        // Hangs off the top-left corner
+        blank_rect(&mut rgb, width, height, (-10, -10, 2, 2));
+        for (i, pixel) in rgb.chunks_exact(3).enumerate() {
+            let (x, y) = (i % width, i / width);
+            let blank = x < 2 && y < 2;
+            assert_eq!(pixel == [0, 0, 0], blank, "pixel {},{}", x, y);
+        }
+
+        // Entirely off screen
+        let mut rgb = vec![0xFF; width * height * 3];
+        blank_rect(&mut rgb, width, height, (10, 0, 20, 3));
+        assert!(rgb.iter().all(|&b| b == 0xFF));
+    }
+}
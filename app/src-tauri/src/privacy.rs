@@ -1,4 +1,15 @@
 //! Privacy mode implementation (black screen, input blocking)
+//!
+//! The black screen is a topmost overlay reading "Remote Support Session
+//! Active": a popup window on Windows, screen-saver level windows on macOS and
+//! an override-redirect window on X11 (Wayland sessions through XWayland).
+//!
+//! Input blocking swallows the local keyboard and mouse while the viewer's
+//! injected input still gets through: a low-level hook on Windows, a CGEventTap
+//! on macOS (needs the Accessibility permission) and exclusive evdev grabs on
+//! Linux (needs read access to /dev/input, e.g. the `input` group). Ctrl+Shift+Esc
+//! always gets through on Windows and lifts the block on macOS and Linux, so the
+//! person at the machine is never locked out.
 
 #![allow(dead_code)]
 #![allow(unused_imports)]
@@ -193,10 +204,515 @@ mod windows_privacy {
 #[cfg(windows)]
 pub use windows_privacy::PrivacyMode;
 
+/// Text shown on the black screen overlay
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+const OVERLAY_TEXT: &str = "Remote Support Session Active";
+
+#[cfg(target_os = "linux")]
+mod overlay {
+    use super::OVERLAY_TEXT;
+    use anyhow::Result;
+    use std::ffi::CString;
+    use std::ptr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread::JoinHandle;
+    use std::time::{Duration, Instant};
+    use x11::xlib::*;
+
+    /// How often the overlay is raised back over windows mapped since
+    const RAISE_INTERVAL: Duration = Duration::from_secs(1);
+
+    /// An override-redirect black window over the whole root window, so every
+    /// monitor; it runs on its own thread and display connection
+    pub struct Overlay {
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl Overlay {
+        pub fn show() -> Result<Self> {
+            let stop = Arc::new(AtomicBool::new(false));
+            let (ready_tx, ready_rx) = mpsc::channel();
+            let thread_stop = stop.clone();
+            let thread = std::thread::Builder::new()
+                .name("privacy-overlay".to_string())
+                .spawn(move || unsafe { run(thread_stop, ready_tx) })?;
+
+            match ready_rx.recv() {
+                Ok(Ok(())) => Ok(Self { stop, thread: Some(thread) }),
+                Ok(Err(e)) => {
+                    let _ = thread.join();
+                    Err(e)
+                }
+                Err(_) => anyhow::bail!("Overlay thread exited"),
+            }
+        }
+    }
+
+    impl Drop for Overlay {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::SeqCst);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    unsafe fn run(stop: Arc<AtomicBool>, ready: mpsc::Sender<Result<()>>) {
+        let display = XOpenDisplay(ptr::null());
+        if display.is_null() {
+            let _ = ready.send(Err(anyhow::anyhow!("No display available - the black screen needs X11 or XWayland")));
+            return;
+        }
+
+        let screen = XDefaultScreen(display);
+        let root = XRootWindow(display, screen);
+        let width = XDisplayWidth(display, screen) as u32;
+        let height = XDisplayHeight(display, screen) as u32;
+
+        // Override-redirect keeps the window manager from decorating or moving it
+        let mut attributes: XSetWindowAttributes = std::mem::zeroed();
+        attributes.override_redirect = True;
+        attributes.background_pixel = XBlackPixel(display, screen);
+        attributes.event_mask = ExposureMask;
+        let window = XCreateWindow(
+            display, root, 0, 0, width, height, 0,
+            CopyFromParent, InputOutput as u32, ptr::null_mut(),
+            CWOverrideRedirect | CWBackPixel | CWEventMask, &mut attributes,
+        );
+        let title = CString::new("Remote Session").unwrap_or_default();
+        XStoreName(display, window, title.as_ptr());
+        XMapRaised(display, window);
+
+        let gc = XCreateGC(display, window, 0, ptr::null_mut());
+        XSetForeground(display, gc, XWhitePixel(display, screen));
+        let mut font = ptr::null_mut();
+        for name in ["-*-helvetica-bold-r-normal--34-*-*-*-*-*-*-*", "fixed"] {
+            let name = CString::new(name).unwrap_or_default();
+            font = XLoadQueryFont(display, name.as_ptr());
+            if !font.is_null() {
+                XSetFont(display, gc, (*font).fid);
+                break;
+            }
+        }
+        XFlush(display);
+        let _ = ready.send(Ok(()));
+        println!("[PRIVACY] Black screen overlay shown ({}x{})", width, height);
+
+        // The text goes in the middle of each monitor
+        let mut monitors: Vec<(i32, i32, u32, u32)> = crate::capture::ScreenCapture::list_monitors()
+            .iter()
+            .map(|m| (m.left, m.top, m.width, m.height))
+            .collect();
+        if monitors.is_empty() {
+            monitors.push((0, 0, width, height));
+        }
+
+        let text = OVERLAY_TEXT.as_bytes();
+        let mut raised = Instant::now();
+        while !stop.load(Ordering::SeqCst) {
+            while XPending(display) > 0 {
+                let mut event: XEvent = std::mem::zeroed();
+                XNextEvent(display, &mut event);
+                if event.get_type() == Expose {
+                    for &(left, top, w, h) in &monitors {
+                        let text_width = if font.is_null() {
+                            text.len() as i32 * 6
+                        } else {
+                            XTextWidth(font, text.as_ptr() as *const _, text.len() as i32)
+                        };
+                        let x = left + (w as i32 - text_width) / 2;
+                        let y = top + h as i32 / 2;
+                        XDrawString(display, window, gc, x, y, text.as_ptr() as *const _, text.len() as i32);
+                    }
+                }
+            }
+            if raised.elapsed() >= RAISE_INTERVAL {
+                XRaiseWindow(display, window);
+                XFlush(display);
+                raised = Instant::now();
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+
+        if !font.is_null() {
+            XFreeFont(display, font);
+        }
+        XFreeGC(display, gc);
+        XDestroyWindow(display, window);
+        XCloseDisplay(display);
+        println!("[PRIVACY] Black screen overlay removed");
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod overlay {
+    use super::OVERLAY_TEXT;
+    use anyhow::Result;
+    use cocoa::appkit::{NSBackingStoreType, NSColor, NSScreen, NSView, NSWindow, NSWindowCollectionBehavior, NSWindowStyleMask};
+    use cocoa::base::{id, nil, NO, YES};
+    use cocoa::foundation::{NSArray, NSPoint, NSString};
+    use objc::{class, msg_send, sel, sel_impl};
+    use std::sync::{Arc, Mutex};
+
+    /// kCGScreenSaverWindowLevel: above the menu bar, the Dock and full-screen apps
+    const SCREEN_SAVER_LEVEL: i64 = 1000;
+
+    /// A borderless black window at screen-saver level on each screen
+    /// AppKit windows belong to the main thread, so showing and removing are
+    /// both queued there and run in order on the app's run loop
+    pub struct Overlay {
+        /// The windows, as `id`s, once the main thread has made them
+        windows: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl Overlay {
+        pub fn show() -> Result<Self> {
+            let windows = Arc::new(Mutex::new(Vec::new()));
+            let created = windows.clone();
+            dispatch::Queue::main().exec_async(move || unsafe {
+                let screens = NSScreen::screens(nil);
+                for i in 0..screens.count() {
+                    let window = cover(screens.objectAtIndex(i));
+                    created.lock().unwrap_or_else(|e| e.into_inner()).push(window as usize);
+                }
+                println!("[PRIVACY] Black screen overlay shown on {} screen(s)", screens.count());
+            });
+            Ok(Self { windows })
+        }
+    }
+
+    impl Drop for Overlay {
+        fn drop(&mut self) {
+            let windows = self.windows.clone();
+            dispatch::Queue::main().exec_async(move || unsafe {
+                for window in windows.lock().unwrap_or_else(|e| e.into_inner()).drain(..) {
+                    let window = window as id;
+                    window.close();
+                    let _: () = msg_send![window, release];
+                }
+                println!("[PRIVACY] Black screen overlay removed");
+            });
+        }
+    }
+
+    /// Black out one screen; main thread only
+    unsafe fn cover(screen: id) -> id {
+        let frame = NSScreen::frame(screen);
+        let window = NSWindow::alloc(nil).initWithContentRect_styleMask_backing_defer_(
+            frame,
+            NSWindowStyleMask::NSBorderlessWindowMask,
+            NSBackingStoreType::NSBackingStoreBuffered,
+            NO,
+        );
+        window.setReleasedWhenClosed_(NO);
+        window.setLevel_(SCREEN_SAVER_LEVEL);
+        window.setOpaque_(YES);
+        window.setBackgroundColor_(NSColor::colorWithRed_green_blue_alpha_(nil, 0.0, 0.0, 0.0, 1.0));
+        window.setCollectionBehavior_(
+            NSWindowCollectionBehavior::NSWindowCollectionBehaviorCanJoinAllSpaces
+                | NSWindowCollectionBehavior::NSWindowCollectionBehaviorFullScreenAuxiliary,
+        );
+        window.setFrame_display_(frame, YES);
+
+        let text = NSString::alloc(nil).init_str(OVERLAY_TEXT);
+        let label: id = msg_send![class!(NSTextField), labelWithString: text];
+        let font: id = msg_send![class!(NSFont), boldSystemFontOfSize: 28.0f64];
+        let _: () = msg_send![label, setFont: font];
+        let _: () = msg_send![label, setTextColor: NSColor::colorWithRed_green_blue_alpha_(nil, 1.0, 1.0, 1.0, 1.0)];
+        let _: () = msg_send![label, sizeToFit];
+        let size = NSView::frame(label).size;
+        label.setFrameOrigin(NSPoint::new(
+            (frame.size.width - size.width) / 2.0,
+            (frame.size.height - size.height) / 2.0,
+        ));
+        let _: () = msg_send![window.contentView(), addSubview: label];
+
+        window.orderFrontRegardless();
+        window
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod input_block {
+    use anyhow::Result;
+    use std::collections::HashSet;
+    use std::fs::{self, File, OpenOptions};
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::JoinHandle;
+
+    /// _IOW('E', 0x90, int): take (1) or release (0) a device exclusively
+    const EVIOCGRAB: libc::c_ulong = 0x4004_4590;
+
+    const EV_KEY: u32 = 0x01;
+    const EV_REL: u32 = 0x02;
+    const EV_ABS: u32 = 0x03;
+    const KEY_ESC: u16 = 1;
+    const KEY_LEFTCTRL: u16 = 29;
+    const KEY_RIGHTCTRL: u16 = 97;
+    const KEY_LEFTSHIFT: u16 = 42;
+    const KEY_RIGHTSHIFT: u16 = 54;
+    const KEY_A: usize = 30;
+    const BTN_LEFT: usize = 0x110;
+    const BTN_TOUCH: usize = 0x14a;
+
+    /// _IOC(_IOC_READ, 'E', 0x20 + ev, len): the event codes a device reports
+    fn eviocgbit(ev: u32, len: usize) -> libc::c_ulong {
+        (2 << 30) | ((len as libc::c_ulong) << 16) | (0x45 << 8) | (0x20 + ev as libc::c_ulong)
+    }
+
+    fn event_bits(device: &File, ev: u32, len: usize) -> Vec<u8> {
+        let mut bits = vec![0u8; len];
+        let read = unsafe { libc::ioctl(device.as_raw_fd(), eviocgbit(ev, len) as _, bits.as_mut_ptr()) };
+        if read < 0 {
+            bits.clear();
+        }
+        bits
+    }
+
+    fn has_bit(bits: &[u8], bit: usize) -> bool {
+        bits.get(bit / 8).is_some_and(|byte| byte & (1 << (bit % 8)) != 0)
+    }
+
+    /// Keyboards, mice and touchpads; not power buttons, lid switches and the like
+    fn is_keyboard_or_pointer(device: &File) -> bool {
+        let types = event_bits(device, 0, 4);
+        if has_bit(&types, EV_REL as usize) {
+            return true;
+        }
+        let keys = event_bits(device, EV_KEY, 96);
+        has_bit(&keys, KEY_A) || (has_bit(&types, EV_ABS as usize) && (has_bit(&keys, BTN_LEFT) || has_bit(&keys, BTN_TOUCH)))
+    }
+
+    /// Exclusive grabs on every keyboard and pointer device, so their events
+    /// reach neither X11 nor Wayland; the viewer's XTest input is not affected
+    /// Devices plugged in while blocked are not grabbed
+    pub struct InputBlock {
+        active: Arc<AtomicBool>,
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl InputBlock {
+        pub fn start() -> Result<Self> {
+            let mut grabbed = Vec::new();
+            for entry in fs::read_dir("/dev/input")?.flatten() {
+                if !entry.file_name().to_string_lossy().starts_with("event") {
+                    continue;
+                }
+                let Ok(device) = OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(entry.path()) else {
+                    continue;
+                };
+                if is_keyboard_or_pointer(&device) && unsafe { libc::ioctl(device.as_raw_fd(), EVIOCGRAB as _, 1) } == 0 {
+                    grabbed.push(device);
+                }
+            }
+            if grabbed.is_empty() {
+                anyhow::bail!("No keyboard or mouse could be grabbed - input blocking needs read access to /dev/input");
+            }
+            println!("[PRIVACY] Local input blocked on {} device(s)", grabbed.len());
+
+            let active = Arc::new(AtomicBool::new(true));
+            let stop = Arc::new(AtomicBool::new(false));
+            let (thread_active, thread_stop) = (active.clone(), stop.clone());
+            let thread = std::thread::Builder::new()
+                .name("input-block".to_string())
+                .spawn(move || run(grabbed, thread_active, thread_stop))?;
+            Ok(Self { active, stop, thread: Some(thread) })
+        }
+
+        /// False once the local user lifted the block with Ctrl+Shift+Esc
+        pub fn is_active(&self) -> bool {
+            self.active.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Drop for InputBlock {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::SeqCst);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    /// Read and drop the grabbed devices' events, watching for Ctrl+Shift+Esc
+    fn run(devices: Vec<File>, active: Arc<AtomicBool>, stop: Arc<AtomicBool>) {
+        let mut pressed = HashSet::new();
+        let mut fds: Vec<libc::pollfd> = devices
+            .iter()
+            .map(|d| libc::pollfd { fd: d.as_raw_fd(), events: libc::POLLIN, revents: 0 })
+            .collect();
+        let mut event: libc::input_event = unsafe { std::mem::zeroed() };
+        let size = std::mem::size_of::<libc::input_event>();
+
+        'block: while !stop.load(Ordering::SeqCst) {
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as _, 100) } <= 0 {
+                continue;
+            }
+            for pollfd in fds.iter_mut().filter(|p| p.revents != 0) {
+                pollfd.revents = 0;
+                while unsafe { libc::read(pollfd.fd, &mut event as *mut _ as *mut libc::c_void, size) } == size as isize {
+                    if event.type_ as u32 != EV_KEY {
+                        continue;
+                    }
+                    match event.value {
+                        0 => { pressed.remove(&event.code); }
+                        _ => { pressed.insert(event.code); }
+                    }
+                    let ctrl = pressed.contains(&KEY_LEFTCTRL) || pressed.contains(&KEY_RIGHTCTRL);
+                    let shift = pressed.contains(&KEY_LEFTSHIFT) || pressed.contains(&KEY_RIGHTSHIFT);
+                    if event.code == KEY_ESC && event.value == 1 && ctrl && shift {
+                        println!("[PRIVACY] Ctrl+Shift+Esc pressed - local input unblocked");
+                        active.store(false, Ordering::SeqCst);
+                        break 'block;
+                    }
+                }
+            }
+        }
+
+        for device in &devices {
+            unsafe { libc::ioctl(device.as_raw_fd(), EVIOCGRAB as _, 0) };
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod input_block {
+    use anyhow::Result;
+    use core_foundation::runloop::{kCFRunLoopCommonModes, kCFRunLoopDefaultMode, CFRunLoop};
+    use core_graphics::event::{
+        CGEventFlags, CGEventTap, CGEventTapLocation, CGEventTapOptions, CGEventTapPlacement, CGEventType, EventField,
+    };
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+
+    /// kVK_Escape
+    const KEY_ESCAPE: i64 = 53;
+
+    /// A CGEventTap that drops local keyboard and mouse events; those posted
+    /// for the viewer carry our process ID and pass
+    /// Without the Accessibility permission (System Settings > Privacy &
+    /// Security > Accessibility) the tap cannot be created
+    pub struct InputBlock {
+        active: Arc<AtomicBool>,
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl InputBlock {
+        pub fn start() -> Result<Self> {
+            let active = Arc::new(AtomicBool::new(true));
+            let stop = Arc::new(AtomicBool::new(false));
+            let (ready_tx, ready_rx) = mpsc::channel();
+            let (thread_active, thread_stop) = (active.clone(), stop.clone());
+            let thread = std::thread::Builder::new()
+                .name("input-block".to_string())
+                .spawn(move || run(thread_active, thread_stop, ready_tx))?;
+
+            match ready_rx.recv() {
+                Ok(Ok(())) => Ok(Self { active, stop, thread: Some(thread) }),
+                Ok(Err(e)) => {
+                    let _ = thread.join();
+                    Err(e)
+                }
+                Err(_) => anyhow::bail!("Input block thread exited"),
+            }
+        }
+
+        /// False once the local user lifted the block with Ctrl+Shift+Esc
+        pub fn is_active(&self) -> bool {
+            self.active.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Drop for InputBlock {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::SeqCst);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    fn run(active: Arc<AtomicBool>, stop: Arc<AtomicBool>, ready: mpsc::Sender<Result<()>>) {
+        let own_pid = std::process::id() as i64;
+        let tap_active = active.clone();
+        let tap = CGEventTap::new(
+            CGEventTapLocation::HID,
+            CGEventTapPlacement::HeadInsertEventTap,
+            CGEventTapOptions::Default,
+            vec![
+                CGEventType::KeyDown,
+                CGEventType::KeyUp,
+                CGEventType::FlagsChanged,
+                CGEventType::LeftMouseDown,
+                CGEventType::LeftMouseUp,
+                CGEventType::RightMouseDown,
+                CGEventType::RightMouseUp,
+                CGEventType::OtherMouseDown,
+                CGEventType::OtherMouseUp,
+                CGEventType::MouseMoved,
+                CGEventType::LeftMouseDragged,
+                CGEventType::RightMouseDragged,
+                CGEventType::OtherMouseDragged,
+                CGEventType::ScrollWheel,
+            ],
+            move |_, kind, event| {
+                if !tap_active.load(Ordering::SeqCst)
+                    || event.get_integer_value_field(EventField::EVENT_SOURCE_UNIX_PROCESS_ID) == own_pid
+                {
+                    return Some(event.clone());
+                }
+                if matches!(kind, CGEventType::KeyDown)
+                    && event.get_integer_value_field(EventField::KEYBOARD_EVENT_KEYCODE) == KEY_ESCAPE
+                    && event.get_flags().contains(CGEventFlags::CGEventFlagControl | CGEventFlags::CGEventFlagShift)
+                {
+                    println!("[PRIVACY] Ctrl+Shift+Esc pressed - local input unblocked");
+                    tap_active.store(false, Ordering::SeqCst);
+                    return Some(event.clone());
+                }
+                None
+            },
+        );
+        let Ok(tap) = tap else {
+            let _ = ready.send(Err(anyhow::anyhow!(
+                "Input blocking needs the Accessibility permission (System Settings > Privacy & Security > Accessibility)"
+            )));
+            return;
+        };
+        let Ok(source) = tap.mach_port.create_runloop_source(0) else {
+            let _ = ready.send(Err(anyhow::anyhow!("Failed to attach the input event tap")));
+            return;
+        };
+        let run_loop = CFRunLoop::get_current();
+        unsafe { run_loop.add_source(&source, kCFRunLoopCommonModes) };
+        tap.enable();
+        let _ = ready.send(Ok(()));
+        println!("[PRIVACY] Local input blocked");
+
+        while !stop.load(Ordering::SeqCst) && active.load(Ordering::SeqCst) {
+            CFRunLoop::run_in_mode(unsafe { kCFRunLoopDefaultMode }, Duration::from_millis(100), false);
+            // The system disables a tap it judges too slow; keep it on
+            tap.enable();
+        }
+    }
+}
+
 #[cfg(not(windows))]
 pub struct PrivacyMode {
     black_screen: AtomicBool,
     input_blocked: AtomicBool,
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    overlay: Option<overlay::Overlay>,
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    input_block: Option<input_block::InputBlock>,
 }
 
 #[cfg(not(windows))]
@@ -205,26 +721,61 @@ impl PrivacyMode {
         Self {
             black_screen: AtomicBool::new(false),
             input_blocked: AtomicBool::new(false),
+            #[cfg(any(target_os = "macos", target_os = "linux"))]
+            overlay: None,
+            #[cfg(any(target_os = "macos", target_os = "linux"))]
+            input_block: None,
         }
     }
 
     pub fn enable_black_screen(&mut self) -> Result<()> {
+        if self.black_screen.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        #[cfg(any(target_os = "macos", target_os = "linux"))]
+        {
+            self.overlay = Some(overlay::Overlay::show()?);
+        }
         self.black_screen.store(true, Ordering::SeqCst);
         Ok(())
     }
 
     pub fn disable_black_screen(&mut self) -> Result<()> {
+        if !self.black_screen.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
         self.black_screen.store(false, Ordering::SeqCst);
+        #[cfg(any(target_os = "macos", target_os = "linux"))]
+        {
+            // Dropping the overlay takes its windows down
+            self.overlay = None;
+        }
         Ok(())
     }
 
     pub fn block_input(&mut self) -> Result<()> {
+        if self.is_input_blocked() {
+            return Ok(());
+        }
+
+        #[cfg(any(target_os = "macos", target_os = "linux"))]
+        {
+            // A block lifted with Ctrl+Shift+Esc is released before the new one
+            self.input_block = None;
+            self.input_block = Some(input_block::InputBlock::start()?);
+        }
         self.input_blocked.store(true, Ordering::SeqCst);
         Ok(())
     }
 
     pub fn unblock_input(&mut self) -> Result<()> {
         self.input_blocked.store(false, Ordering::SeqCst);
+        #[cfg(any(target_os = "macos", target_os = "linux"))]
+        {
+            self.input_block = None;
+        }
         Ok(())
     }
 
@@ -239,6 +790,53 @@ impl PrivacyMode {
     }
 
     pub fn is_input_blocked(&self) -> bool {
+        #[cfg(any(target_os = "macos", target_os = "linux"))]
+        if self.input_block.as_ref().is_some_and(|block| !block.is_active()) {
+            return false;
+        }
         self.input_blocked.load(Ordering::SeqCst)
     }
 }
+
+/// Lock the local screen so the next person at the desk has to sign in
+#[cfg(windows)]
+pub fn lock_workstation() -> Result<()> {
+    use windows::Win32::System::Shutdown::LockWorkStation;
+    unsafe { LockWorkStation()? };
+    Ok(())
+}
+
+/// Lock the local screen so the next person at the desk has to sign in
+#[cfg(target_os = "macos")]
+pub fn lock_workstation() -> Result<()> {
+    // CGSession is gone since macOS 11; sleeping the display locks when a
+    // password is required after sleep, which is the default
+    run_first(&[
+        ("/System/Library/CoreServices/Menu Extras/User.menu/Contents/Resources/CGSession", &["-suspend"]),
+        ("pmset", &["displaysleepnow"]),
+    ])
+}
+
+/// Lock the local screen so the next person at the desk has to sign in
+#[cfg(target_os = "linux")]
+pub fn lock_workstation() -> Result<()> {
+    // logind covers most desktops; xdg-screensaver the sessions it doesn't manage
+    run_first(&[
+        ("loginctl", &["lock-session"]),
+        ("xdg-screensaver", &["lock"]),
+    ])
+}
+
+/// Run the first of the commands that succeeds
+#[cfg(any(target_os = "macos", target_os = "linux"))]
+fn run_first(commands: &[(&str, &[&str])]) -> Result<()> {
+    let mut last_error = anyhow::anyhow!("No lock command available");
+    for (program, args) in commands {
+        match std::process::Command::new(program).args(*args).status() {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(status) => last_error = anyhow::anyhow!("{} exited with {}", program, status),
+            Err(e) => last_error = anyhow::anyhow!("{}: {}", program, e),
+        }
+    }
+    Err(last_error)
+}
@@ -3,10 +3,33 @@
 #![allow(dead_code)]
 
 use anyhow::Result;
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 /// Maximum frame size (16 MB)
 pub const MAX_FRAME_SIZE: usize = 16 * 1024 * 1024;
 
+/// Largest frame payload advertised in HELLO; peers with feature::FRAGMENTS
+/// split longer messages across frames
+pub const MAX_FRAGMENT_SIZE: usize = 256 * 1024;
+
+/// Longest frame payload from a peer that fragments: a fragment plus the
+/// stream ID and encryption tag around it
+pub const MAX_FRAGMENT_FRAME: usize = MAX_FRAGMENT_SIZE + 64;
+
+/// Smallest fragment a sender goes down to, whatever the peer advertises
+pub const MIN_FRAGMENT_SIZE: usize = 1024;
+
+/// Largest message a reader reassembles from fragments
+pub const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Set on the channel byte of a fragment whose message continues in the next
+/// frame on the same stream (feature::FRAGMENTS); relays pass it through
+pub const FRAME_MORE_FLAG: u8 = 0x40;
+
+/// Protocol version advertised in HELLO
+/// Peers that never send HELLO are treated as version 0 (see `Capabilities::legacy`)
+pub const PROTOCOL_VERSION: u16 = 1;
+
 /// Protocol channels
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -17,6 +40,8 @@ pub enum Channel {
     Clipboard = 0x03,
     File = 0x04,
     Privacy = 0x05,
+    Chat = 0x06,
+    Audio = 0x07,
 }
 
 impl TryFrom<u8> for Channel {
@@ -30,21 +55,71 @@ impl TryFrom<u8> for Channel {
             0x03 => Ok(Self::Clipboard),
             0x04 => Ok(Self::File),
             0x05 => Ok(Self::Privacy),
+            0x06 => Ok(Self::Chat),
+            0x07 => Ok(Self::Audio),
             _ => anyhow::bail!("Invalid channel: {}", value),
         }
     }
 }
 
+/// How far a session is with end-to-end encryption
+/// Until the Noise handshake settles it only control messages (HELLO,
+/// IDENTITY, HANDSHAKE, ...) may pass; video, input, clipboard and every
+/// other data channel wait for the SecureChannel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelState {
+    /// Handshake not finished
+    Pending,
+    /// Every frame goes through the SecureChannel
+    Encrypted,
+}
+
+impl ChannelState {
+    /// Refuse a data frame while the handshake is pending
+    pub fn check(self, channel: Channel) -> Result<()> {
+        if self == ChannelState::Pending && channel != Channel::Control {
+            anyhow::bail!("Refusing {:?} frame before the secure channel is established", channel);
+        }
+        Ok(())
+    }
+
+    pub fn is_encrypted(self) -> bool {
+        self == ChannelState::Encrypted
+    }
+}
+
 /// Protocol frame
 #[derive(Debug, Clone)]
 pub struct Frame {
     pub channel: Channel,
     pub payload: Vec<u8>,
+    /// A fragment the next frame on the stream continues (FRAME_MORE_FLAG)
+    pub more: bool,
 }
 
 impl Frame {
     pub fn new(channel: Channel, payload: Vec<u8>) -> Self {
-        Self { channel, payload }
+        Self { channel, payload, more: false }
+    }
+
+    /// Channel byte of the frame header, without the sync flag
+    pub fn channel_byte(&self) -> u8 {
+        self.channel as u8 | if self.more { FRAME_MORE_FLAG } else { 0 }
+    }
+
+    /// Split into frames of at most `max_len` payload bytes, all but the last
+    /// carrying `more`; a frame that fits comes back as it is
+    pub fn into_fragments(self, max_len: usize) -> Vec<Frame> {
+        let max_len = max_len.max(1);
+        if self.payload.len() <= max_len {
+            return vec![self];
+        }
+        let count = self.payload.len().div_ceil(max_len);
+        self.payload
+            .chunks(max_len)
+            .enumerate()
+            .map(|(i, chunk)| Frame { channel: self.channel, payload: chunk.to_vec(), more: i + 1 < count })
+            .collect()
     }
 
     pub fn control(msg_type: u8, data: &[u8]) -> Self {
@@ -77,11 +152,17 @@ impl Frame {
         Self::new(Channel::File, payload)
     }
 
+    pub fn chat(msg_type: u8, data: &[u8]) -> Self {
+        let mut payload = vec![msg_type];
+        payload.extend_from_slice(data);
+        Self::new(Channel::Chat, payload)
+    }
+
     /// Serialize to bytes
     pub fn to_bytes(&self) -> Vec<u8> {
         let len = self.payload.len();
         let mut bytes = Vec::with_capacity(4 + len);
-        bytes.push(self.channel as u8);
+        bytes.push(self.channel_byte());
         bytes.push((len >> 16) as u8);
         bytes.push((len >> 8) as u8);
         bytes.push(len as u8);
@@ -95,7 +176,7 @@ impl Frame {
             anyhow::bail!("Frame too short");
         }
 
-        let channel = Channel::try_from(data[0])?;
+        let channel = Channel::try_from(data[0] & !FRAME_MORE_FLAG)?;
         let len = ((data[1] as usize) << 16)
             | ((data[2] as usize) << 8)
             | (data[3] as usize);
@@ -111,10 +192,115 @@ impl Frame {
         Ok(Self {
             channel,
             payload: data[4..4 + len].to_vec(),
+            more: data[0] & FRAME_MORE_FLAG != 0,
         })
     }
 }
 
+/// Reads frames off one connection
+pub struct FrameReader {
+    /// Longest payload accepted; a longer header is refused before anything is allocated
+    max_len: usize,
+}
+
+impl Default for FrameReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameReader {
+    pub fn new() -> Self {
+        Self { max_len: MAX_FRAME_SIZE }
+    }
+
+    /// Refuse frames whose payload (stream ID included) is longer than
+    /// `max_len`; for peers known to fragment anything longer
+    pub fn set_max_frame_size(&mut self, max_len: usize) {
+        self.max_len = max_len.min(MAX_FRAME_SIZE);
+    }
+
+    /// Next frame (on multiplexed connections the stream ID stays in the payload)
+    pub async fn read<R: AsyncRead + Unpin>(&mut self, reader: &mut R) -> Result<Frame> {
+        let mut header = [0u8; 4];
+        reader.read_exact(&mut header).await?;
+
+        let channel = Channel::try_from(header[0] & !FRAME_MORE_FLAG)?;
+        let len = ((header[1] as usize) << 16)
+            | ((header[2] as usize) << 8)
+            | (header[3] as usize);
+        if len > self.max_len {
+            anyhow::bail!("Frame too large: {} bytes (limit {})", len, self.max_len);
+        }
+
+        let mut payload = vec![0u8; len];
+        reader.read_exact(&mut payload).await?;
+        Ok(Frame { channel, payload, more: header[0] & FRAME_MORE_FLAG != 0 })
+    }
+}
+
+/// Joins one stream's fragmented messages (feature::FRAGMENTS) back together
+/// Fragments arrive decrypted and in order; a message cut short by a frame on
+/// another channel is dropped along with the rest
+/// of its fragments. A message over its channel's limit stops growing there and
+/// comes out cut short, for its handler to refuse by the size it declares
+#[derive(Default)]
+pub struct Reassembler {
+    partial: Option<Frame>,
+    /// Channel of a dropped message whose remaining fragments are skipped
+    discarding: Option<Channel>,
+}
+
+impl Reassembler {
+    /// Add a frame; the whole message, once its last fragment is in
+    pub fn push(&mut self, frame: Frame) -> Result<Option<Frame>> {
+        if self.discarding == Some(frame.channel) {
+            if !frame.more {
+                self.discarding = None;
+            }
+            return Ok(None);
+        }
+
+        let mut message = match self.partial.take() {
+            Some(mut partial) if partial.channel == frame.channel => {
+                if partial.payload.len() + frame.payload.len() > MAX_MESSAGE_SIZE {
+                    anyhow::bail!("Fragmented {:?} message over {} bytes", frame.channel, MAX_MESSAGE_SIZE);
+                }
+                let limit = Self::limit(frame.channel);
+                let room = limit.saturating_sub(partial.payload.len());
+                if partial.payload.len() <= limit && frame.payload.len() > room {
+                    println!("[PROTO] {:?} message over {} bytes, keeping only its start", frame.channel, limit);
+                }
+                partial.payload.extend_from_slice(&frame.payload[..frame.payload.len().min(room)]);
+                partial.more = frame.more;
+                partial
+            }
+            Some(partial) => {
+                println!("[PROTO] Dropping unfinished {:?} message of {} bytes", partial.channel, partial.payload.len());
+                self.discarding = Some(partial.channel);
+                frame
+            }
+            None => frame,
+        };
+
+        if message.more {
+            self.partial = Some(message);
+            return Ok(None);
+        }
+        message.more = false;
+        Ok(Some(message))
+    }
+
+    /// Longest message kept for a channel
+    fn limit(channel: Channel) -> usize {
+        match channel {
+            // CLIPBOARD_DATA and the data encoded within the receiver's limit
+            Channel::Clipboard => 1 + crate::clipboard::max_size(),
+            _ => MAX_MESSAGE_SIZE,
+        }
+    }
+}
+
 /// Control message types
 pub mod control {
     pub const HANDSHAKE: u8 = 0x01;
@@ -123,14 +309,48 @@ pub mod control {
     pub const SESSION_END: u8 = 0x04;
     pub const KEEPALIVE: u8 = 0x05;
     pub const RESOLUTION: u8 = 0x06;    // Client sends viewport resolution
+    pub const QUALITY: u8 = 0x07;       // Client sets JPEG quality [quality u8]
+    pub const ELEVATION_REQUEST: u8 = 0x08; // Client asks host to relaunch elevated
+    pub const ELEVATION_STATUS: u8 = 0x09;  // Host reports elevation [elevated u8]
+    pub const HELLO: u8 = 0x0A;             // Capability exchange, see `Capabilities`
+    pub const RESOLUTION_CHANGED: u8 = 0x0B; // Host display size changed [width u16 LE][height u16 LE]
+    pub const IDENTITY: u8 = 0x0C;      // Client asks for host keys; host answers [x25519 (32)][ed25519 (32)]
+    pub const LOSSLESS: u8 = 0x0D;      // Client toggles PNG frames [enabled u8] (needs codec::PNG)
+    pub const SOURCES_REQUEST: u8 = 0x0E; // Client asks for capturable sources [kind u8]
+    pub const SOURCES: u8 = 0x0F;       // Host lists sources, see `sources::encode`
 
     // P2P negotiation messages
     pub const P2P_OFFER: u8 = 0x10;     // Client offers P2P with public addr
     pub const P2P_ANSWER: u8 = 0x11;    // Host responds with public addr
-    pub const P2P_READY: u8 = 0x12;     // Both ready, attempt P2P
+    pub const P2P_READY: u8 = 0x12;     // Sender's frames move to the direct link after this one
     pub const P2P_FAILED: u8 = 0x13;    // P2P failed, use relay
 
+    pub const CAPTURE_UNAVAILABLE: u8 = 0x14; // Host cannot capture [reason utf8]; empty reason = capturing again
+    pub const INPUT_CONTROL: u8 = 0x15; // Host tells a viewer whether its input is used [granted u8]
+    pub const FOCUS_LOCK: u8 = 0x16;    // Client pins keystrokes to the window it last clicked [enabled u8]
+    pub const REQUEST_CONTROL: u8 = 0x17; // View-only client asks the host user for input control
+    pub const FRAME_TIMING: u8 = 0x18;  // Client echoes a frame's capture stamp, host answers with its age (see `latency`)
+    pub const SELECT_MONITOR: u8 = 0x19; // Client picks the captured monitor [index u32 LE]; host answers [index u32 LE][status u8]
+    pub const PING: u8 = 0x1A;          // Client measures the round trip [sent ms u64 LE, its own clock]
+    pub const PONG: u8 = 0x1B;          // Host echoes a PING's payload at once
+    pub const AUDIO: u8 = 0x1C;         // Client starts or stops the host's sound [enabled u8] (needs Channel::Audio)
+    pub const AUTH_PASSWORD: u8 = 0x1D; // Client gives the unattended access password [utf8], encrypted only
+
     pub const ERROR: u8 = 0xFF;
+
+    // SESSION_END reasons [reason u8]
+    pub const END_REASON_DECLINED: u8 = 0x00;
+    pub const END_REASON_BUSY: u8 = 0x02;   // Too many requests awaiting approval
+    pub const END_REASON_BAD_PASSWORD: u8 = 0x03; // Unattended access password missing or wrong
+    pub const END_REASON_TIMEOUT: u8 = 0x04; // No input for the host's session timeout
+
+    // SELECT_MONITOR index for every monitor stitched into one frame
+    pub const SELECT_ALL_MONITORS: u32 = u32::MAX;
+
+    // SELECT_MONITOR statuses
+    pub const SELECT_MONITOR_OK: u8 = 0x00;
+    pub const SELECT_MONITOR_FAILED: u8 = 0x01; // No such monitor, or it cannot be captured
+    pub const SELECT_MONITOR_DENIED: u8 = 0x02; // Only the viewer in control may switch
 }
 
 /// Input message types
@@ -140,6 +360,37 @@ pub mod input {
     pub const MOUSE_SCROLL: u8 = 0x03;
     pub const KEY_DOWN: u8 = 0x04;
     pub const KEY_UP: u8 = 0x05;
+    /// High-resolution scroll [dx i32 LE][dy i32 LE], 120 units per wheel notch
+    pub const MOUSE_SCROLL_PRECISE: u8 = 0x06;
+    /// Several events replayed in order ([len u8][event])*, see `input::encode_batch`
+    pub const INPUT_BATCH: u8 = 0x07;
+    /// Host -> client: where its cursor is and, when it changed, its image,
+    /// see `cursor::Message::encode`
+    pub const CURSOR_UPDATE: u8 = 0x08;
+}
+
+/// Video request types (client -> host)
+pub mod video {
+    /// Request the next frame at the current QoS quality
+    pub const FRAME_REQUEST: u8 = 0x03;
+    /// Request a single full-quality keyframe (snapshot)
+    pub const SNAPSHOT_REQUEST: u8 = 0x04;
+    /// Host reply when the frame is identical to the last one sent [marker only]
+    /// Takes the place of the keyframe flag byte, so it must not be 0x00/0x01
+    pub const FRAME_UNCHANGED: u8 = 0x05;
+    /// Host reply with only the areas that changed since the last frame sent,
+    /// see `tiles::encode`; also in place of the keyframe flag byte
+    pub const FRAME_TILES: u8 = 0x06;
+    /// Host reply with a VP8/VP9 packet, see `vpx::Packet::frame`; also in
+    /// place of the keyframe flag byte
+    pub const FRAME_VPX: u8 = 0x07;
+
+    /// JPEG quality used for snapshots, regardless of QoS setting
+    pub const SNAPSHOT_QUALITY: u8 = 100;
+
+    /// Image format byte, present after the timestamp when codec::PNG is negotiated
+    pub const FORMAT_JPEG: u8 = 0x00;
+    pub const FORMAT_PNG: u8 = 0x01;
 }
 
 /// Privacy message types
@@ -161,14 +412,20 @@ pub mod clipboard {
     pub const CLIPBOARD_CHANGED: u8 = 0x03;
     /// Clipboard sync enabled/disabled notification
     pub const CLIPBOARD_SYNC_STATUS: u8 = 0x04;
+    /// Result of applying received clipboard data
+    /// [success u8][data_type u8][byte_count u32 LE][error utf8...]
+    pub const CLIPBOARD_ACK: u8 = 0x05;
 
     /// Clipboard data types
     pub const DATA_TYPE_TEXT: u8 = 0x01;
     pub const DATA_TYPE_IMAGE: u8 = 0x02;
     pub const DATA_TYPE_FILES: u8 = 0x03;
+    /// Formatted text: plain text plus optional HTML and RTF representations
+    /// [text len u32 LE][text][html len u32 LE][html][rtf len u32 LE][rtf] (len 0 = absent)
+    pub const DATA_TYPE_RICH: u8 = 0x04;
 }
 
-/// File transfer message types
+/// File transfer message types, see `transfer::Message` for the layouts
 pub mod file {
     /// Request to start file transfer
     pub const FILE_OFFER: u8 = 0x01;
@@ -178,10 +435,477 @@ pub mod file {
     pub const FILE_REJECT: u8 = 0x03;
     /// File data chunk
     pub const FILE_CHUNK: u8 = 0x04;
-    /// File transfer complete
-    pub const FILE_COMPLETE: u8 = 0x05;
+    /// All chunks sent, with the file's hash; echoed once the file is kept
+    pub const FILE_DONE: u8 = 0x05;
     /// Cancel file transfer
     pub const FILE_CANCEL: u8 = 0x06;
-    /// File transfer progress
-    pub const FILE_PROGRESS: u8 = 0x07;
+    /// Bytes received so far; the sender waits for one every `transfer::ACK_EVERY` chunks
+    pub const FILE_ACK: u8 = 0x07;
+}
+
+/// Chat message types (either direction)
+pub mod chat {
+    /// Text message [utf8 text...]
+    pub const MESSAGE: u8 = 0x01;
+
+    /// Maximum message length in bytes
+    pub const MAX_MESSAGE_LEN: usize = 4096;
+
+    /// Build a chat message frame, rejecting empty or oversized text
+    pub fn message_frame(text: &str) -> anyhow::Result<super::Frame> {
+        if text.trim().is_empty() {
+            anyhow::bail!("Message is empty");
+        }
+        if text.len() > MAX_MESSAGE_LEN {
+            anyhow::bail!("Message too long ({} bytes, max {})", text.len(), MAX_MESSAGE_LEN);
+        }
+        Ok(super::Frame::chat(MESSAGE, text.as_bytes()))
+    }
+}
+
+/// Audio channel messages (host to client), see `audio`
+pub mod audio {
+    /// Opus packet [captured_at ms u64 LE, host clock][opus...]
+    pub const PACKET: u8 = 0x01;
+
+    /// Opus sample rates (bitmask in HELLO)
+    pub const RATE_8K: u8 = 1 << 0;
+    pub const RATE_12K: u8 = 1 << 1;
+    pub const RATE_16K: u8 = 1 << 2;
+    pub const RATE_24K: u8 = 1 << 3;
+    pub const RATE_48K: u8 = 1 << 4;
+
+    /// Each rate bit with its rate in Hz, best first
+    pub const RATES: [(u8, u32); 5] = [
+        (RATE_48K, 48000),
+        (RATE_24K, 24000),
+        (RATE_16K, 16000),
+        (RATE_12K, 12000),
+        (RATE_8K, 8000),
+    ];
+}
+
+/// Video codecs (bitmask in HELLO)
+pub mod codec {
+    pub const JPEG: u16 = 1 << 0;
+    /// Lossless frames; also means video frames carry a format byte
+    pub const PNG: u16 = 1 << 1;
+    /// Inter-frame video through libvpx, sent as video::FRAME_VPX (`codec` feature)
+    pub const VP8: u16 = 1 << 2;
+    pub const VP9: u16 = 1 << 3;
+}
+
+/// Optional behaviours (bitmask in HELLO)
+pub mod feature {
+    /// Host may answer a frame request with video::FRAME_UNCHANGED
+    pub const FRAME_UNCHANGED: u32 = 1 << 0;
+    /// Host understands input::MOUSE_SCROLL_PRECISE
+    pub const PRECISE_SCROLL: u32 = 1 << 1;
+    /// Host acknowledges clipboard pushes with clipboard::CLIPBOARD_ACK
+    pub const CLIPBOARD_ACK: u32 = 1 << 2;
+    /// Host reports and can change its elevation level
+    pub const ELEVATION: u32 = 1 << 3;
+    /// Host announces display size changes with control::RESOLUTION_CHANGED
+    pub const RESOLUTION_CHANGED: u32 = 1 << 4;
+    /// Peer understands clipboard::DATA_TYPE_RICH
+    pub const RICH_CLIPBOARD: u32 = 1 << 5;
+    /// Host answers control::IDENTITY and completes a Noise_XK handshake
+    pub const IDENTITY: u32 = 1 << 6;
+    /// Host answers control::SOURCES_REQUEST with its monitors or windows
+    pub const CAPTURE_SOURCES: u32 = 1 << 7;
+    /// Host reports capture failures with control::CAPTURE_UNAVAILABLE
+    pub const CAPTURE_STATUS: u32 = 1 << 8;
+    /// Host replays input::INPUT_BATCH frames
+    pub const INPUT_BATCH: u32 = 1 << 9;
+    /// Host reports input control with control::INPUT_CONTROL when several viewers share it
+    pub const INPUT_CONTROL: u32 = 1 << 10;
+    /// Host can pin a viewer's keystrokes to one window with control::FOCUS_LOCK
+    pub const FOCUS_LOCK: u32 = 1 << 11;
+    /// Host passes control::REQUEST_CONTROL to its user, who answers with control::INPUT_CONTROL
+    pub const CONTROL_REQUEST: u32 = 1 << 12;
+    // 1 << 13 was FRAME_SYNC (resync after a lost frame boundary), dropped as
+    // an encrypted session cannot carry on past a lost frame; not reused
+    /// Video frames carry a capture stamp and control::FRAME_TIMING is answered
+    pub const FRAME_TIMING: u32 = 1 << 14;
+    /// Host switches the captured monitor on control::SELECT_MONITOR
+    pub const MONITOR_SELECT: u32 = 1 << 15;
+    /// Peer sends or patches video::FRAME_TILES frames
+    pub const DIRTY_TILES: u32 = 1 << 16;
+    /// Host sends input::CURSOR_UPDATE and the client draws it over the frames
+    pub const CURSOR_UPDATE: u32 = 1 << 17;
+    /// Host answers control::PING with control::PONG
+    pub const PING: u32 = 1 << 18;
+    /// Host takes files offered on the file channel
+    pub const FILE_TRANSFER: u32 = 1 << 19;
+    /// Host admits viewers only with control::AUTH_PASSWORD (unattended access);
+    /// its HELLO carries the bit only while a password is set
+    pub const PASSWORD_REQUIRED: u32 = 1 << 20;
+    /// Messages longer than the peer's max_frame_size are split across frames
+    /// with FRAME_MORE_FLAG; the receiver refuses longer single frames
+    pub const FRAGMENTS: u32 = 1 << 21;
+    /// Session frames move to a secured direct link after control::P2P_READY
+    pub const DIRECT_FRAMES: u32 = 1 << 22;
+}
+
+/// Relay registration (sent in the clear before any frames)
+/// Registration: [client type u8][id len u16 BE][id][second field len u16 BE][second field]
+/// Endpoints send no second field; technicians send the target ID; the alias types send the alias
+pub mod relay {
+    /// Host registering by device ID
+    pub const CLIENT_ENDPOINT: u8 = 0x01;
+    /// Client connecting to a target device ID
+    pub const CLIENT_TECHNICIAN: u8 = 0x02;
+    /// Host registering by device ID and claiming an alias
+    pub const CLIENT_ENDPOINT_ALIAS: u8 = 0x03;
+    /// One-shot alias lookup; the relay answers and closes the connection
+    pub const CLIENT_RESOLVE: u8 = 0x04;
+    /// Client carrying several sessions on one connection (no second field)
+    pub const CLIENT_TECHNICIAN_MUX: u8 = 0x05;
+    /// Host serving several clients on one connection (alias, or empty, as second field)
+    pub const CLIENT_ENDPOINT_MUX: u8 = 0x06;
+
+    /// Relay reply (control frame): session established
+    pub const REPLY_SUCCESS: u8 = 0x01;
+    /// Relay reply: alias claimed
+    pub const REPLY_ALIAS_REGISTERED: u8 = 0x03;
+    /// Relay reply: alias resolved [device id...]
+    pub const REPLY_RESOLVED: u8 = 0x04;
+    /// Relay reply: multiplexed connection accepted
+    pub const REPLY_MUX_READY: u8 = 0x05;
+    /// Relay reply: error [utf8 message...]
+    pub const REPLY_ERROR: u8 = 0xFF;
+
+    /// Stream carrying open/close requests on a multiplexed connection
+    pub const MUX_CONTROL_STREAM: u16 = 0;
+    /// Mux control: open [stream][target id...]; echoed back once connected
+    /// On a multiplexed endpoint the relay opens streams: [stream][client key hash...]
+    pub const MUX_OPEN: u8 = 0x01;
+    /// Mux control: stream closed [stream] (either direction)
+    pub const MUX_CLOSE: u8 = 0x02;
+    // Opening failures come back as REPLY_ERROR [stream][utf8 message...]
+
+    /// Maximum alias length in bytes
+    pub const MAX_ALIAS_LEN: usize = 64;
+
+    /// Normalize an alias the way the relay compares them (trimmed, lowercase)
+    pub fn normalize_alias(alias: &str) -> anyhow::Result<String> {
+        let alias = alias.trim().to_lowercase();
+        if alias.is_empty() {
+            anyhow::bail!("Alias is empty");
+        }
+        if alias.len() > MAX_ALIAS_LEN {
+            anyhow::bail!("Alias too long ({} bytes, max {})", alias.len(), MAX_ALIAS_LEN);
+        }
+        Ok(alias)
+    }
+
+    /// Whether `input` looks like a device ID (digits, optionally space-separated)
+    pub fn is_device_id(input: &str) -> bool {
+        let digits: String = input.chars().filter(|c| !c.is_whitespace()).collect();
+        !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+    }
+}
+
+/// What a peer supports, exchanged in control::HELLO at session start
+/// HELLO payload: [version u16 LE][codecs u16 LE][channels u16 LE][features u32 LE][max_frame_size u32 LE]
+/// then [audio_rates u8], absent from peers that predate audio
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capabilities {
+    pub version: u16,
+    /// Bitmask of `codec` values
+    pub codecs: u16,
+    /// Bitmask of channel ids (bit n = channel n)
+    pub channels: u16,
+    /// Bitmask of `feature` values
+    pub features: u32,
+    pub max_frame_size: u32,
+    /// Bitmask of `audio` rates
+    pub audio_rates: u8,
+}
+
+impl Capabilities {
+    /// Everything this build supports
+    pub fn local() -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            codecs: codec::JPEG
+                | codec::PNG
+                | if cfg!(feature = "codec") { codec::VP8 | codec::VP9 } else { 0 },
+            channels: Self::channel_mask(&[
+                Channel::Control,
+                Channel::Video,
+                Channel::Input,
+                Channel::Clipboard,
+                Channel::File,
+                Channel::Privacy,
+                Channel::Chat,
+            ]) | if cfg!(feature = "audio") { Self::channel_mask(&[Channel::Audio]) } else { 0 },
+            features: feature::FRAME_UNCHANGED
+                | feature::PRECISE_SCROLL
+                | feature::CLIPBOARD_ACK
+                | feature::ELEVATION
+                | feature::RESOLUTION_CHANGED
+                | feature::RICH_CLIPBOARD
+                | feature::IDENTITY
+                | feature::CAPTURE_SOURCES
+                | feature::CAPTURE_STATUS
+                | feature::INPUT_BATCH
+                | feature::INPUT_CONTROL
+                | feature::CONTROL_REQUEST
+                | feature::FRAME_TIMING
+                | feature::MONITOR_SELECT
+                | feature::DIRTY_TILES
+                | feature::CURSOR_UPDATE
+                | feature::PING
+                | feature::FILE_TRANSFER
+                | feature::PASSWORD_REQUIRED
+                | feature::FRAGMENTS
+                | feature::DIRECT_FRAMES
+                | if cfg!(windows) { feature::FOCUS_LOCK } else { 0 },
+            max_frame_size: MAX_FRAGMENT_SIZE as u32,
+            audio_rates: if cfg!(feature = "audio") {
+                audio::RATES.iter().fold(0, |mask, (bit, _)| mask | bit)
+            } else {
+                0
+            },
+        }
+    }
+
+    /// Assumed capabilities of a peer that predates HELLO
+    pub fn legacy() -> Self {
+        Self {
+            version: 0,
+            codecs: codec::JPEG,
+            channels: Self::channel_mask(&[
+                Channel::Control,
+                Channel::Video,
+                Channel::Input,
+                Channel::Clipboard,
+                Channel::File,
+                Channel::Privacy,
+            ]),
+            features: 0,
+            max_frame_size: MAX_FRAME_SIZE as u32,
+            audio_rates: 0,
+        }
+    }
+
+    fn channel_mask(channels: &[Channel]) -> u16 {
+        channels.iter().fold(0, |mask, &c| mask | (1 << c as u8))
+    }
+
+    /// Common capability set both peers can operate at
+    pub fn negotiate(&self, peer: &Self) -> Self {
+        Self {
+            version: self.version.min(peer.version),
+            codecs: self.codecs & peer.codecs,
+            channels: self.channels & peer.channels,
+            features: self.features & peer.features,
+            max_frame_size: self.max_frame_size.min(peer.max_frame_size),
+            audio_rates: self.audio_rates & peer.audio_rates,
+        }
+    }
+
+    /// Highest audio sample rate in Hz both peers take, None when there is
+    /// no audio to send
+    pub fn audio_rate(&self) -> Option<u32> {
+        if !self.supports_channel(Channel::Audio) {
+            return None;
+        }
+        audio::RATES.iter().find(|(bit, _)| self.audio_rates & bit != 0).map(|&(_, rate)| rate)
+    }
+
+    pub fn supports_channel(&self, channel: Channel) -> bool {
+        self.channels & (1 << channel as u8) != 0
+    }
+
+    pub fn supports_feature(&self, feature: u32) -> bool {
+        self.features & feature == feature
+    }
+
+    pub fn supports_codec(&self, codec: u16) -> bool {
+        self.codecs & codec == codec
+    }
+
+    /// Longest payload to put in one frame for this peer: its max_frame_size
+    /// once both sides fragment, otherwise None (messages go whole)
+    pub fn fragment_size(&self) -> Option<usize> {
+        self.supports_feature(feature::FRAGMENTS)
+            .then(|| (self.max_frame_size as usize).clamp(MIN_FRAGMENT_SIZE, MAX_FRAGMENT_SIZE))
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(15);
+        data.extend(&self.version.to_le_bytes());
+        data.extend(&self.codecs.to_le_bytes());
+        data.extend(&self.channels.to_le_bytes());
+        data.extend(&self.features.to_le_bytes());
+        data.extend(&self.max_frame_size.to_le_bytes());
+        data.push(self.audio_rates);
+        data
+    }
+
+    /// Decode a HELLO payload; trailing bytes from newer versions are ignored
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < 14 {
+            anyhow::bail!("HELLO too short: {} bytes", data.len());
+        }
+        Ok(Self {
+            version: u16::from_le_bytes([data[0], data[1]]),
+            codecs: u16::from_le_bytes([data[2], data[3]]),
+            channels: u16::from_le_bytes([data[4], data[5]]),
+            features: u32::from_le_bytes([data[6], data[7], data[8], data[9]]),
+            max_frame_size: u32::from_le_bytes([data[10], data[11], data[12], data[13]]),
+            audio_rates: data.get(14).copied().unwrap_or(0),
+        })
+    }
+
+    /// HELLO frame advertising these capabilities
+    pub fn hello_frame(&self) -> Frame {
+        Frame::control(control::HELLO, &self.encode())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities_roundtrip() {
+        let caps = Capabilities::local();
+        let decoded = Capabilities::decode(&caps.encode()).unwrap();
+        assert_eq!(decoded, caps);
+        assert!(Capabilities::decode(&[0u8; 4]).is_err());
+    }
+
+    #[test]
+    fn test_capabilities_negotiate_with_legacy() {
+        let negotiated = Capabilities::local().negotiate(&Capabilities::legacy());
+        assert_eq!(negotiated.version, 0);
+        assert!(negotiated.supports_channel(Channel::Video));
+        assert!(!negotiated.supports_channel(Channel::Chat));
+        assert!(!negotiated.supports_feature(feature::FRAME_UNCHANGED));
+        assert_eq!(negotiated.codecs, codec::JPEG);
+        assert_eq!(negotiated.audio_rate(), None);
+    }
+
+    #[test]
+    fn test_audio_rate_negotiation() {
+        let mut host = Capabilities::local();
+        host.channels |= 1 << Channel::Audio as u8;
+        host.audio_rates = audio::RATE_16K | audio::RATE_48K;
+        let mut client = host;
+        client.audio_rates = audio::RATE_16K | audio::RATE_24K;
+        assert_eq!(host.negotiate(&client).audio_rate(), Some(16000));
+        assert_eq!(host.audio_rate(), Some(48000));
+
+        // A HELLO from before audio has no rate byte
+        let old = Capabilities::decode(&host.encode()[..14]).unwrap();
+        assert_eq!(old.audio_rates, 0);
+        assert_eq!(host.negotiate(&old).audio_rate(), None);
+
+        client.channels &= !(1 << Channel::Audio as u8);
+        assert_eq!(host.negotiate(&client).audio_rate(), None);
+    }
+
+    #[test]
+    fn test_data_frames_wait_for_secure_channel() {
+        let video_request = Frame::video(vec![video::FRAME_REQUEST]);
+        assert!(ChannelState::Pending.check(video_request.channel).is_err());
+        assert!(ChannelState::Pending.check(Channel::Input).is_err());
+        // The handshake itself has to get through
+        assert!(ChannelState::Pending.check(Channel::Control).is_ok());
+
+        assert!(ChannelState::Encrypted.check(video_request.channel).is_ok());
+        assert!(!ChannelState::Pending.is_encrypted());
+    }
+
+    #[test]
+    fn test_relay_alias_helpers() {
+        assert_eq!(relay::normalize_alias("  Front Desk ").unwrap(), "front desk");
+        assert!(relay::normalize_alias("   ").is_err());
+        assert!(relay::normalize_alias(&"a".repeat(relay::MAX_ALIAS_LEN + 1)).is_err());
+        assert!(relay::is_device_id("123 456 789"));
+        assert!(!relay::is_device_id("front-desk"));
+        assert!(!relay::is_device_id(""));
+    }
+
+    #[test]
+    fn test_fragments_reassemble() {
+        let caps = Capabilities::local().negotiate(&Capabilities::local());
+        assert_eq!(caps.fragment_size(), Some(MAX_FRAGMENT_SIZE));
+        assert_eq!(Capabilities::local().negotiate(&Capabilities::legacy()).fragment_size(), None);
+
+        let message: Vec<u8> = (0..MAX_FRAGMENT_SIZE * 2 + 100).map(|i| (i % 251) as u8).collect();
+        let fragments = Frame::new(Channel::Clipboard, message.clone()).into_fragments(MAX_FRAGMENT_SIZE);
+        assert_eq!(fragments.len(), 3);
+        assert!(fragments[0].more && fragments[1].more && !fragments[2].more);
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        runtime.block_on(async {
+            let mut wire = Vec::new();
+            for (i, fragment) in fragments.iter().enumerate() {
+                wire.extend(fragment.to_bytes());
+                // Another channel in between cuts the message short
+                if i == 0 {
+                    wire.extend(Frame::new(Channel::Chat, b"hi".to_vec()).to_bytes());
+                }
+            }
+            for fragment in &fragments {
+                wire.extend(fragment.to_bytes());
+            }
+
+            let mut reader = FrameReader::new();
+            reader.set_max_frame_size(MAX_FRAGMENT_SIZE + 64);
+            let mut reassembler = Reassembler::default();
+            let mut stream = &wire[..];
+            let mut messages = Vec::new();
+            while !stream.is_empty() {
+                if let Some(message) = reassembler.push(reader.read(&mut stream).await.unwrap()).unwrap() {
+                    messages.push(message);
+                }
+            }
+            // The cut message is dropped whole; the intact copy comes through
+            assert_eq!(messages.len(), 2);
+            assert_eq!((messages[0].channel, &messages[0].payload[..]), (Channel::Chat, &b"hi"[..]));
+            assert_eq!((messages[1].channel, &messages[1].payload), (Channel::Clipboard, &message));
+            assert!(!messages[1].more);
+
+            // A header over the limit is refused before its payload is read
+            let mut reader = FrameReader::new();
+            reader.set_max_frame_size(MAX_FRAGMENT_SIZE);
+            let big = Frame::new(Channel::Video, vec![0; MAX_FRAGMENT_SIZE + 1]).to_bytes();
+            let mut stream = &big[..4];
+            assert!(reader.read(&mut stream).await.is_err());
+        });
+    }
+
+    #[test]
+    fn test_clipboard_fragments_stop_at_limit() {
+        use crate::clipboard::ClipboardData;
+
+        let limit = crate::clipboard::max_size();
+        let text = ClipboardData::Text("x".repeat(limit + 4096));
+        let message = Frame::clipboard(clipboard::CLIPBOARD_DATA, &text.encode());
+
+        let mut reassembler = Reassembler::default();
+        let mut whole = None;
+        for fragment in message.clone().into_fragments(MAX_FRAGMENT_SIZE) {
+            whole = reassembler.push(fragment).unwrap();
+        }
+        // Kept no further than the limit, and still refused by its header
+        let whole = whole.unwrap();
+        assert_eq!(whole.payload.len(), 1 + limit);
+        assert_eq!(whole.payload[..], message.payload[..1 + limit]);
+        assert!(ClipboardData::decode_within(&whole.payload[1..], limit).is_err());
+
+        // Other channels take the whole message
+        let mut reassembler = Reassembler::default();
+        let video = Frame::new(Channel::Video, vec![7; limit + 4096]);
+        let mut whole = None;
+        for fragment in video.clone().into_fragments(MAX_FRAGMENT_SIZE) {
+            whole = reassembler.push(fragment).unwrap();
+        }
+        assert_eq!(whole.unwrap().payload, video.payload);
+    }
 }
@@ -17,12 +17,22 @@ pub const MAX_FPS: u32 = 60;
 /// RTT sample window size
 const RTT_WINDOW_SIZE: usize = 60;
 
+/// Span over which bytes sent are summed into a rate
+const BANDWIDTH_WINDOW: Duration = Duration::from_secs(1);
+
+/// Frames needed in the window before the rate is trusted
+const BANDWIDTH_MIN_FRAMES: usize = 5;
+
+/// Share of the target FPS's bytes that must get through for the link to count as keeping up
+const BANDWIDTH_HEADROOM: f32 = 0.85;
+
 /// Quality levels
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum QualityLevel {
     Low,      // 50% quality, higher FPS
     Balanced, // 75% quality, balanced
     Best,     // 100% quality, may reduce FPS
+    Lossless, // PNG frames - LAN/P2P only, several times the bandwidth
 }
 
 impl QualityLevel {
@@ -31,6 +41,29 @@ impl QualityLevel {
             QualityLevel::Low => 50,
             QualityLevel::Balanced => 75,
             QualityLevel::Best => 95,
+            QualityLevel::Lossless => 100, // Used if the host can't do PNG
+        }
+    }
+
+    /// Map a `connection_quality` setting value to a fixed level
+    /// Returns None for "auto" (adaptive) or unknown values
+    pub fn from_setting(value: &str) -> Option<Self> {
+        match value {
+            "quality" | "best" => Some(QualityLevel::Best),
+            "balanced" => Some(QualityLevel::Balanced),
+            "speed" | "low" => Some(QualityLevel::Low),
+            "lossless" => Some(QualityLevel::Lossless),
+            _ => None,
+        }
+    }
+
+    /// The `connection_quality` value for this level
+    pub fn as_setting(&self) -> &'static str {
+        match self {
+            QualityLevel::Low => "speed",
+            QualityLevel::Balanced => "balanced",
+            QualityLevel::Best => "quality",
+            QualityLevel::Lossless => "lossless",
         }
     }
 
@@ -39,6 +72,7 @@ impl QualityLevel {
             QualityLevel::Low => 12,
             QualityLevel::Balanced => 10,
             QualityLevel::Best => 8,
+            QualityLevel::Lossless => 2,
         }
     }
 }
@@ -53,16 +87,28 @@ pub struct RttTracker {
 
 impl RttTracker {
     pub fn new() -> Self {
+        Self::with_estimate(50) // Default 50ms
+    }
+
+    /// Start from an estimate; real samples replace it as they arrive
+    pub fn with_estimate(rtt_ms: u32) -> Self {
         Self {
             samples: VecDeque::with_capacity(RTT_WINDOW_SIZE),
-            smoothed_rtt: 50, // Default 50ms
+            smoothed_rtt: rtt_ms,
             min_rtt: u32::MAX,
             last_update: Instant::now(),
         }
     }
 
     /// Add a new RTT sample
+    /// After a gap of is_stale() the old samples are dropped first: the route may
+    /// have changed, and an old minimum would hide a slower link
     pub fn add_sample(&mut self, rtt_ms: u32) {
+        if self.is_stale() {
+            self.samples.clear();
+            self.min_rtt = u32::MAX;
+        }
+
         // Add to window
         if self.samples.len() >= RTT_WINDOW_SIZE {
             self.samples.pop_front();
@@ -100,6 +146,10 @@ pub struct QosManager {
     target_quality: QualityLevel,
     quality_ratio: f32, // 0.0 - 1.0, multiplier for quality
     frame_times: VecDeque<Instant>,
+    /// Frame sizes over the last BANDWIDTH_WINDOW
+    bytes_sent: VecDeque<(Instant, usize)>,
+    /// Smoothed time a frame takes before it is on its way, in ms
+    encode_ms: Option<u32>,
     last_adjustment: Instant,
 }
 
@@ -111,10 +161,38 @@ impl QosManager {
             target_quality: QualityLevel::Balanced,
             quality_ratio: 1.0,
             frame_times: VecDeque::with_capacity(60),
+            bytes_sent: VecDeque::with_capacity(MAX_FPS as usize),
+            encode_ms: None,
             last_adjustment: Instant::now(),
         }
     }
 
+    /// Start from the round trip measured while connecting instead of the
+    /// conservative defaults, so fast links don't ramp up from INIT_FPS and
+    /// slow ones don't stutter first. `direct` is a P2P link, which has no
+    /// relay hop sharing its bandwidth
+    pub fn with_initial_estimate(rtt_ms: u32, direct: bool) -> Self {
+        let (fps, target_quality, quality_ratio) = if rtt_ms < 50 && direct {
+            (30, QualityLevel::Best, 1.0)
+        } else if rtt_ms < 50 {
+            (24, QualityLevel::Balanced, 1.0)
+        } else if rtt_ms < 100 {
+            (INIT_FPS, QualityLevel::Balanced, 0.9)
+        } else if rtt_ms < 200 {
+            (10, QualityLevel::Balanced, 0.75)
+        } else {
+            (MIN_FPS, QualityLevel::Balanced, 0.6)
+        };
+
+        Self {
+            rtt_tracker: RttTracker::with_estimate(rtt_ms),
+            current_fps: fps,
+            target_quality,
+            quality_ratio,
+            ..Self::new()
+        }
+    }
+
     /// Set target quality level
     pub fn set_quality(&mut self, quality: QualityLevel) {
         self.target_quality = quality;
@@ -152,11 +230,70 @@ impl QosManager {
         self.frame_times.len() as u32
     }
 
+    /// Record the size of a frame that went out
+    pub fn record_bytes_sent(&mut self, len: usize) {
+        let now = Instant::now();
+        while self.bytes_sent.front().is_some_and(|&(at, _)| now.duration_since(at) > BANDWIDTH_WINDOW) {
+            self.bytes_sent.pop_front();
+        }
+        self.bytes_sent.push_back((now, len));
+    }
+
+    /// Record how long a frame took to capture and encode
+    pub fn record_encode_time(&mut self, ms: u32) {
+        // Smoothed over ~8 frames so one slow frame does not drop quality
+        self.encode_ms = Some(match self.encode_ms {
+            Some(smoothed) => (smoothed * 7 + ms) / 8,
+            None => ms,
+        });
+    }
+
+    /// Bytes per second that got through over the last BANDWIDTH_WINDOW; None until
+    /// enough frames went out to tell
+    pub fn get_bandwidth(&self) -> Option<u32> {
+        let (first, _) = *self.bytes_sent.front()?;
+        if self.bytes_sent.len() < BANDWIDTH_MIN_FRAMES || first.elapsed() < BANDWIDTH_WINDOW / 2 {
+            return None;
+        }
+        let bytes: usize = self.bytes_sent.iter().map(|&(_, len)| len).sum();
+        Some((bytes as f32 / first.elapsed().as_secs_f32().max(BANDWIDTH_WINDOW.as_secs_f32())) as u32)
+    }
+
+    /// Share of the current FPS the link and encoder keep up with, below 1.0 once
+    /// frames queue
+    /// Compares the bytes that got through to what the FPS needs at the recent
+    /// frame size, and the encode time to the frame interval
+    fn throughput_ratio(&self) -> f32 {
+        let mut ratio = 1.0f32;
+        if let Some(bandwidth) = self.get_bandwidth() {
+            let frame_bytes = self.bytes_sent.iter().map(|&(_, len)| len).sum::<usize>() / self.bytes_sent.len();
+            let needed = frame_bytes as f32 * self.current_fps as f32;
+            if needed > 0.0 {
+                ratio = ratio.min(bandwidth as f32 / needed);
+            }
+        }
+        if let Some(encode_ms) = self.encode_ms.filter(|&ms| ms > 0) {
+            ratio = ratio.min(self.get_frame_interval_ms() as f32 / encode_ms as f32);
+        }
+        ratio
+    }
+
     /// Adjust FPS and quality based on network conditions
     fn adjust_parameters(&mut self) {
         let rtt = self.rtt_tracker.get_rtt();
         let min_fps = self.target_quality.min_fps();
 
+        // A saturated link can keep a low RTT while frames queue: step quality
+        // down with the shortfall, at most 20% at a time, and hold the FPS
+        let throughput = self.throughput_ratio();
+        if throughput < BANDWIDTH_HEADROOM {
+            self.quality_ratio = (self.quality_ratio * throughput.clamp(0.8, 0.95)).max(0.3);
+            if rtt >= 150 {
+                self.current_fps = (self.current_fps.saturating_sub(2)).max(min_fps);
+            }
+            return;
+        }
+
         // FPS adjustment based on RTT
         if rtt < 50 {
             // Excellent network - increase FPS aggressively
@@ -226,6 +363,8 @@ impl QosManager {
             quality_ratio: self.quality_ratio,
             jpeg_quality: self.get_jpeg_quality(),
             network_quality: self.get_network_quality(),
+            bandwidth: self.get_bandwidth(),
+            encode_ms: self.encode_ms,
         }
     }
 }
@@ -237,7 +376,7 @@ impl Default for QosManager {
 }
 
 /// QoS statistics for debugging/display
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct QosStats {
     pub rtt_ms: u32,
     pub target_fps: u32,
@@ -245,6 +384,9 @@ pub struct QosStats {
     pub quality_ratio: f32,
     pub jpeg_quality: u8,
     pub network_quality: &'static str,
+    /// Bytes per second getting through; None until measured
+    pub bandwidth: Option<u32>,
+    pub encode_ms: Option<u32>,
 }
 
 #[cfg(test)]
@@ -265,6 +407,82 @@ mod tests {
         assert!(rtt >= 48 && rtt <= 55);
     }
 
+    #[test]
+    fn test_stale_rtt_resets() {
+        let mut tracker = RttTracker::new();
+        tracker.add_sample(10);
+        tracker.last_update = Instant::now() - Duration::from_secs(6);
+        assert!(tracker.is_stale());
+
+        tracker.add_sample(120);
+        assert_eq!(tracker.get_rtt(), 120);
+        assert!(!tracker.is_stale());
+    }
+
+    #[test]
+    fn test_quality_from_setting() {
+        assert_eq!(QualityLevel::from_setting("quality"), Some(QualityLevel::Best));
+        assert_eq!(QualityLevel::from_setting("balanced"), Some(QualityLevel::Balanced));
+        assert_eq!(QualityLevel::from_setting("speed"), Some(QualityLevel::Low));
+        assert_eq!(QualityLevel::from_setting("lossless"), Some(QualityLevel::Lossless));
+        assert_eq!(QualityLevel::from_setting("auto"), None);
+        for level in [QualityLevel::Low, QualityLevel::Balanced, QualityLevel::Best, QualityLevel::Lossless] {
+            assert_eq!(QualityLevel::from_setting(level.as_setting()), Some(level));
+        }
+    }
+
+    #[test]
+    fn test_initial_estimate() {
+        let lan = QosManager::with_initial_estimate(2, true);
+        assert!(lan.get_target_fps() > INIT_FPS);
+        assert_eq!(lan.get_jpeg_quality(), QualityLevel::Best.jpeg_quality());
+        assert_eq!(lan.get_network_quality(), "Excellent");
+
+        // The same round trip through a relay starts a little lower
+        let relayed = QosManager::with_initial_estimate(2, false);
+        assert!(relayed.get_target_fps() < lan.get_target_fps());
+        assert_eq!(relayed.get_jpeg_quality(), QosManager::new().get_jpeg_quality());
+
+        let slow = QosManager::with_initial_estimate(300, false);
+        assert_eq!(slow.get_target_fps(), MIN_FPS);
+        assert!(slow.get_jpeg_quality() < QosManager::new().get_jpeg_quality());
+        assert_eq!(slow.get_network_quality(), "Bad");
+    }
+
+    #[test]
+    fn test_saturated_link_lowers_quality() {
+        let mut qos = QosManager::with_initial_estimate(20, true);
+        let fps = qos.get_target_fps();
+        let start = Instant::now() - Duration::from_millis(900);
+        // A third of the frames the FPS asks for get through
+        for i in 0..(fps / 3) {
+            qos.bytes_sent.push_back((start + Duration::from_millis(i as u64 * 90), 100_000));
+        }
+        assert!(qos.throughput_ratio() < 0.5);
+
+        qos.adjust_parameters();
+        assert!(qos.quality_ratio < 1.0 && qos.quality_ratio >= 0.8);
+        assert_eq!(qos.get_target_fps(), fps);
+        assert!(qos.get_jpeg_quality() < QualityLevel::Best.jpeg_quality());
+
+        // Degrades step by step, not all at once
+        for _ in 0..20 {
+            qos.adjust_parameters();
+        }
+        assert!((qos.quality_ratio - 0.3).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_slow_encode_lowers_quality() {
+        let mut qos = QosManager::with_initial_estimate(20, true);
+        assert!(qos.throughput_ratio() >= 1.0);
+        for _ in 0..10 {
+            qos.record_encode_time(qos.get_frame_interval_ms() as u32 * 2);
+        }
+        qos.adjust_parameters();
+        assert!(qos.quality_ratio < 1.0);
+    }
+
     #[test]
     fn test_qos_adjustment() {
         let mut qos = QosManager::new();
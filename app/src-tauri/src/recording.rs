@@ -3,11 +3,17 @@
 
 use anyhow::Result;
 use std::fs::{self, File};
-use std::io::{BufWriter, Write, Read, BufReader};
-use std::path::PathBuf;
-use std::time::{SystemTime, UNIX_EPOCH, Duration, Instant};
+use std::io::{BufWriter, Write, Read, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{SystemTime, UNIX_EPOCH, Instant};
 use parking_lot::Mutex;
 
+use crate::protocol::MAX_FRAME_SIZE;
+
 /// Recording file format version
 const RECORDING_VERSION: u8 = 1;
 
@@ -17,6 +23,22 @@ const MAGIC: &[u8; 4] = b"SDRC"; // SecureDesk Recording
 /// Maximum recording size (2 GB)
 const MAX_RECORDING_SIZE: u64 = 2 * 1024 * 1024 * 1024;
 
+/// Space reserved for the metadata JSON so it can be rewritten in place on stop
+/// (the JSON is padded with trailing spaces, which JSON readers ignore)
+const METADATA_RESERVED: usize = 1024;
+
+/// Thumbnail bounding box and JPEG quality
+const THUMBNAIL_WIDTH: u32 = 320;
+const THUMBNAIL_HEIGHT: u32 = 180;
+const THUMBNAIL_QUALITY: u8 = 70;
+
+/// Keep every Nth frame as the thumbnail source (~10s at 30 FPS), so the
+/// preview shows the session in progress rather than its first frame
+const THUMBNAIL_SAMPLE_INTERVAL: u64 = 300;
+
+/// Records queued for the writer thread before new ones are dropped (~2s of video at 30 FPS)
+const WRITE_QUEUE_LEN: usize = 64;
+
 /// Recording frame types
 #[repr(u8)]
 #[derive(Debug, Clone, Copy)]
@@ -27,6 +49,22 @@ pub enum FrameType {
     Metadata = 0x04,
 }
 
+impl FrameType {
+    /// None for types written by a newer version, which readers skip
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x01 => Some(Self::Video),
+            0x02 => Some(Self::Audio),
+            0x03 => Some(Self::Input),
+            0x04 => Some(Self::Metadata),
+            _ => None,
+        }
+    }
+}
+
+/// Size of a record header: [type (1)][timestamp_ms (8)][width (2)][height (2)][data_len (4)]
+const RECORD_HEADER_LEN: u64 = 17;
+
 /// Recording metadata
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct RecordingMetadata {
@@ -38,6 +76,35 @@ pub struct RecordingMetadata {
     pub frame_count: u64,
     pub width: u16,
     pub height: u16,
+    /// Input records in the file (absent in recordings made before input capture)
+    #[serde(default)]
+    pub input_event_count: u64,
+}
+
+/// An operator input event, stored as JSON in `FrameType::Input` records
+/// Coordinates are in remote screen pixels, as sent to the host
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum InputEvent {
+    Mouse {
+        x: i32,
+        y: i32,
+        event_type: String,
+        button: Option<u8>,
+    },
+    Key {
+        key_code: u16,
+        pressed: bool,
+    },
+}
+
+/// Pad metadata JSON with spaces to exactly `size` bytes
+fn pad_metadata(mut json: Vec<u8>, size: usize) -> Result<Vec<u8>> {
+    if json.len() > size {
+        anyhow::bail!("Recording metadata too large");
+    }
+    json.resize(size, b' ');
+    Ok(json)
 }
 
 /// Session recorder
@@ -46,16 +113,22 @@ pub struct SessionRecorder {
     path: PathBuf,
     start_time: Instant,
     frame_count: u64,
+    input_event_count: u64,
     bytes_written: u64,
     metadata: RecordingMetadata,
     is_recording: bool,
+    /// Most recently sampled frame, turned into the thumbnail on stop
+    thumbnail_source: Option<Vec<u8>>,
+    /// Size of the metadata slot written by `start`
+    metadata_reserved: usize,
+    /// Size of the last video frame, stored with input records for the overlay
+    last_size: (u16, u16),
 }
 
 impl SessionRecorder {
-    /// Create a new session recorder
-    pub fn new(remote_device_id: &str, remote_device_name: &str) -> Result<Self> {
-        let recordings_dir = Self::recordings_directory()?;
-        fs::create_dir_all(&recordings_dir)?;
+    /// Create a new session recorder writing into `recordings_dir`
+    pub fn new(recordings_dir: &Path, remote_device_id: &str, remote_device_name: &str) -> Result<Self> {
+        fs::create_dir_all(recordings_dir)?;
 
         // Generate unique filename with timestamp
         let timestamp = SystemTime::now()
@@ -78,6 +151,7 @@ impl SessionRecorder {
             frame_count: 0,
             width: 0,
             height: 0,
+            input_event_count: 0,
         };
 
         Ok(Self {
@@ -85,13 +159,17 @@ impl SessionRecorder {
             path,
             start_time: Instant::now(),
             frame_count: 0,
+            input_event_count: 0,
             bytes_written: 0,
             metadata,
             is_recording: false,
+            thumbnail_source: None,
+            metadata_reserved: METADATA_RESERVED,
+            last_size: (0, 0),
         })
     }
 
-    /// Get recordings directory
+    /// Get the default recordings directory (used unless `recordings_dir` is configured)
     pub fn recordings_directory() -> Result<PathBuf> {
         // Use environment variables for cross-platform data directory
         #[cfg(windows)]
@@ -135,6 +213,8 @@ impl SessionRecorder {
         // Reserve space for metadata (will be updated on stop)
         // Write placeholder metadata length (4 bytes) and metadata
         let metadata_json = serde_json::to_vec(&self.metadata)?;
+        self.metadata_reserved = METADATA_RESERVED.max(metadata_json.len() + 64);
+        let metadata_json = pad_metadata(metadata_json, self.metadata_reserved)?;
         writer.write_all(&(metadata_json.len() as u32).to_le_bytes())?;
         writer.write_all(&metadata_json)?;
 
@@ -158,6 +238,7 @@ impl SessionRecorder {
         // Update metadata with final values
         self.metadata.duration_ms = self.start_time.elapsed().as_millis() as u64;
         self.metadata.frame_count = self.frame_count;
+        self.metadata.input_event_count = self.input_event_count;
 
         // Close file
         if let Some(mut writer) = self.file.take() {
@@ -167,6 +248,13 @@ impl SessionRecorder {
         // Re-open and update metadata at the beginning
         self.update_metadata_in_file()?;
 
+        // A missing thumbnail is regenerated on demand when listing, so don't fail the stop
+        if let Some(source) = self.thumbnail_source.take() {
+            if let Err(e) = write_thumbnail(&self.path, &source) {
+                eprintln!("[RECORDING] Failed to write thumbnail: {}", e);
+            }
+        }
+
         println!("[RECORDING] Stopped recording. Frames: {}, Duration: {}ms, Size: {} bytes",
             self.frame_count, self.metadata.duration_ms, self.bytes_written);
 
@@ -175,8 +263,6 @@ impl SessionRecorder {
 
     /// Update metadata in the recording file
     fn update_metadata_in_file(&self) -> Result<()> {
-        use std::io::{Seek, SeekFrom};
-
         let mut file = fs::OpenOptions::new()
             .write(true)
             .open(&self.path)?;
@@ -184,16 +270,16 @@ impl SessionRecorder {
         // Skip magic and version
         file.seek(SeekFrom::Start(5))?;
 
-        // Write updated metadata
-        let metadata_json = serde_json::to_vec(&self.metadata)?;
+        // Write updated metadata into the same slot, so frame data is not overwritten
+        let metadata_json = pad_metadata(serde_json::to_vec(&self.metadata)?, self.metadata_reserved)?;
         file.write_all(&(metadata_json.len() as u32).to_le_bytes())?;
         file.write_all(&metadata_json)?;
 
         Ok(())
     }
 
-    /// Write a video frame to the recording
-    pub fn write_video_frame(&mut self, width: u16, height: u16, jpeg_data: &[u8]) -> Result<()> {
+    /// Write a video frame taken `timestamp_ms` into the recording
+    pub fn write_video_frame(&mut self, timestamp_ms: u64, width: u16, height: u16, jpeg_data: &[u8]) -> Result<()> {
         if !self.is_recording {
             return Ok(());
         }
@@ -214,8 +300,6 @@ impl SessionRecorder {
 
         // Write frame header
         // [type (1)][timestamp_ms (8)][width (2)][height (2)][data_len (4)][data...]
-        let timestamp_ms = self.start_time.elapsed().as_millis() as u64;
-
         writer.write_all(&[FrameType::Video as u8])?;
         writer.write_all(&timestamp_ms.to_le_bytes())?;
         writer.write_all(&width.to_le_bytes())?;
@@ -223,6 +307,11 @@ impl SessionRecorder {
         writer.write_all(&(jpeg_data.len() as u32).to_le_bytes())?;
         writer.write_all(jpeg_data)?;
 
+        self.last_size = (width, height);
+        if self.frame_count.is_multiple_of(THUMBNAIL_SAMPLE_INTERVAL) {
+            self.thumbnail_source = Some(jpeg_data.to_vec());
+        }
+
         self.frame_count += 1;
         self.bytes_written += 1 + 8 + 2 + 2 + 4 + jpeg_data.len() as u64;
 
@@ -234,20 +323,39 @@ impl SessionRecorder {
         Ok(())
     }
 
+    /// Write an operator input event made `timestamp_ms` into the recording
+    /// The record carries the current frame size so playback can place markers
+    pub fn write_input_event(&mut self, timestamp_ms: u64, event: &InputEvent) -> Result<()> {
+        if !self.is_recording {
+            return Ok(());
+        }
+
+        let data = serde_json::to_vec(event)?;
+        if self.bytes_written + data.len() as u64 > MAX_RECORDING_SIZE {
+            self.stop()?;
+            anyhow::bail!("Recording size limit reached");
+        }
+
+        let writer = self.file.as_mut().ok_or_else(|| anyhow::anyhow!("No file"))?;
+        let (width, height) = self.last_size;
+
+        writer.write_all(&[FrameType::Input as u8])?;
+        writer.write_all(&timestamp_ms.to_le_bytes())?;
+        writer.write_all(&width.to_le_bytes())?;
+        writer.write_all(&height.to_le_bytes())?;
+        writer.write_all(&(data.len() as u32).to_le_bytes())?;
+        writer.write_all(&data)?;
+
+        self.input_event_count += 1;
+        self.bytes_written += 1 + 8 + 2 + 2 + 4 + data.len() as u64;
+        Ok(())
+    }
+
     /// Check if currently recording
     pub fn is_recording(&self) -> bool {
         self.is_recording
     }
 
-    /// Get current recording duration
-    pub fn duration(&self) -> Duration {
-        if self.is_recording {
-            self.start_time.elapsed()
-        } else {
-            Duration::from_millis(self.metadata.duration_ms)
-        }
-    }
-
     /// Get frame count
     pub fn frame_count(&self) -> u64 {
         self.frame_count
@@ -259,6 +367,362 @@ impl SessionRecorder {
     }
 }
 
+/// A frame read back from a recording file
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub struct RecordedFrame {
+    pub frame_type: u8,
+    pub timestamp_ms: u64,
+    pub width: u16,
+    pub height: u16,
+    pub data: Vec<u8>,
+}
+
+/// Sequential reader for `.sdrec` files
+pub struct RecordingReader {
+    reader: BufReader<File>,
+    metadata: RecordingMetadata,
+}
+
+impl RecordingReader {
+    /// Open a recording and read its header and metadata
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        let mut reader = BufReader::new(file);
+
+        // Read and verify header
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            anyhow::bail!("Invalid recording file");
+        }
+
+        let mut version = [0u8; 1];
+        reader.read_exact(&mut version)?;
+        if version[0] != RECORDING_VERSION {
+            anyhow::bail!("Unsupported recording version");
+        }
+
+        // Read metadata length and metadata
+        let mut len_buf = [0u8; 4];
+        reader.read_exact(&mut len_buf)?;
+        let metadata_len = u32::from_le_bytes(len_buf) as usize;
+
+        let mut metadata_buf = vec![0u8; metadata_len];
+        reader.read_exact(&mut metadata_buf)?;
+
+        let metadata: RecordingMetadata = serde_json::from_slice(&metadata_buf)?;
+        Ok(Self { reader, metadata })
+    }
+
+    pub fn metadata(&self) -> &RecordingMetadata {
+        &self.metadata
+    }
+
+    /// Read the next frame; None at end of file (or after a truncated final frame)
+    /// Layout: [type (1)][timestamp_ms (8)][width (2)][height (2)][data_len (4)][data...]
+    pub fn next_frame(&mut self) -> Result<Option<RecordedFrame>> {
+        let mut header = [0u8; 17];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let data_len = u32::from_le_bytes([header[13], header[14], header[15], header[16]]) as u64;
+        if data_len > MAX_FRAME_SIZE as u64 {
+            anyhow::bail!("Corrupt frame length: {}", data_len);
+        }
+        let file_len = self.reader.get_ref().metadata()?.len();
+        if data_len > file_len.saturating_sub(self.reader.stream_position()?) {
+            return Ok(None);
+        }
+
+        let mut data = vec![0u8; data_len as usize];
+        match self.reader.read_exact(&mut data) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(Some(RecordedFrame {
+            frame_type: header[0],
+            timestamp_ms: u64::from_le_bytes(header[1..9].try_into()?),
+            width: u16::from_le_bytes([header[9], header[10]]),
+            height: u16::from_le_bytes([header[11], header[12]]),
+            data,
+        }))
+    }
+
+    /// First video frame in the recording, if any
+    pub fn first_video_frame(&mut self) -> Result<Option<RecordedFrame>> {
+        while let Some(frame) = self.next_frame()? {
+            if frame.frame_type == FrameType::Video as u8 {
+                return Ok(Some(frame));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Collect the input records into a playback overlay
+    /// Records that fail to parse are skipped rather than failing the whole file
+    pub fn input_overlay(&mut self) -> Result<InputOverlay> {
+        let mut overlay = InputOverlay::default();
+        while let Some(frame) = self.next_frame()? {
+            if frame.frame_type != FrameType::Input as u8 {
+                continue;
+            }
+            let Ok(event) = serde_json::from_slice::<InputEvent>(&frame.data) else {
+                continue;
+            };
+            match event {
+                InputEvent::Mouse { x, y, event_type, button } if event_type == "down" => {
+                    overlay.clicks.push(ClickMarker {
+                        timestamp_ms: frame.timestamp_ms,
+                        x,
+                        y,
+                        button: button.unwrap_or(0),
+                        frame_width: frame.width,
+                        frame_height: frame.height,
+                    });
+                }
+                InputEvent::Key { key_code, pressed: true } => {
+                    overlay.keys.push(KeyLogEntry {
+                        timestamp_ms: frame.timestamp_ms,
+                        key_code,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(overlay)
+    }
+}
+
+/// Video frames of a recording by timestamp, for playback and seeking
+/// Opening indexes the file once; a recording cut short by a crash plays up to
+/// its last complete frame (its metadata then still says 0 frames and 0 ms)
+pub struct RecordingPlayer {
+    reader: BufReader<File>,
+    metadata: RecordingMetadata,
+    /// (timestamp_ms, file offset) of each complete video record
+    index: Vec<(u64, u64)>,
+    /// Index entry `next_frame` reads
+    cursor: usize,
+    /// File size when indexed; no record reaches past it
+    file_len: u64,
+}
+
+impl RecordingPlayer {
+    /// Open a recording, validate its header and index its video frames
+    pub fn open(path: &Path) -> Result<Self> {
+        let RecordingReader { mut reader, metadata } = RecordingReader::open(path)?;
+        let file_len = reader.get_ref().metadata()?.len();
+        let mut offset = reader.stream_position()?;
+        let mut index = Vec::new();
+
+        let mut header = [0u8; RECORD_HEADER_LEN as usize];
+        while offset + RECORD_HEADER_LEN <= file_len {
+            reader.read_exact(&mut header)?;
+            let data_len = u32::from_le_bytes([header[13], header[14], header[15], header[16]]) as u64;
+            let end = offset + RECORD_HEADER_LEN + data_len;
+            if end > file_len {
+                break; // Interrupted while writing this record
+            }
+            // Audio, input and metadata records, and types this version doesn't know
+            if let Some(FrameType::Video) = FrameType::from_u8(header[0]) {
+                index.push((u64::from_le_bytes(header[1..9].try_into()?), offset));
+            }
+            reader.seek_relative(data_len as i64)?;
+            offset = end;
+        }
+
+        Ok(Self { reader, metadata, index, cursor: 0, file_len })
+    }
+
+    pub fn metadata(&self) -> &RecordingMetadata {
+        &self.metadata
+    }
+
+    /// Complete video frames in the file
+    pub fn frame_count(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Length of the recording, from the frames when its metadata was never finalized
+    pub fn duration_ms(&self) -> u64 {
+        let last = self.index.last().map(|(timestamp, _)| *timestamp).unwrap_or(0);
+        self.metadata.duration_ms.max(last)
+    }
+
+    /// Continue from the frame on screen at `position_ms`; returns that frame's timestamp
+    pub fn seek(&mut self, position_ms: u64) -> u64 {
+        self.cursor = self.index.partition_point(|(timestamp, _)| *timestamp <= position_ms).saturating_sub(1);
+        self.index.get(self.cursor).map(|(timestamp, _)| *timestamp).unwrap_or(0)
+    }
+
+    /// The next video frame (JPEG data), None after the last one
+    pub fn next_frame(&mut self) -> Result<Option<RecordedFrame>> {
+        let Some(&(_, offset)) = self.index.get(self.cursor) else {
+            return Ok(None);
+        };
+        self.cursor += 1;
+
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let mut header = [0u8; RECORD_HEADER_LEN as usize];
+        self.reader.read_exact(&mut header)?;
+        let data_len = u32::from_le_bytes([header[13], header[14], header[15], header[16]]) as u64;
+        if data_len > MAX_FRAME_SIZE as u64 || data_len > self.file_len.saturating_sub(offset + RECORD_HEADER_LEN) {
+            anyhow::bail!("Corrupt frame length: {}", data_len);
+        }
+        let mut data = vec![0u8; data_len as usize];
+        self.reader.read_exact(&mut data)?;
+
+        Ok(Some(RecordedFrame {
+            frame_type: header[0],
+            timestamp_ms: u64::from_le_bytes(header[1..9].try_into()?),
+            width: u16::from_le_bytes([header[9], header[10]]),
+            height: u16::from_le_bytes([header[11], header[12]]),
+            data,
+        }))
+    }
+
+    /// Show the frames from the current position in real time (`speed` 2.0 = twice
+    /// as fast), until the last one or until `stop` is set. Returns the timestamp
+    /// reached
+    pub fn play(&mut self, speed: f32, stop: &AtomicBool, mut show: impl FnMut(&RecordedFrame)) -> Result<u64> {
+        let speed = if speed.is_finite() && speed > 0.0 { speed } else { 1.0 };
+        let started = Instant::now();
+        let mut first = None;
+        let mut position = 0;
+
+        while let Some(frame) = self.next_frame()? {
+            let base = *first.get_or_insert(frame.timestamp_ms);
+            let due = started + std::time::Duration::from_secs_f64(
+                frame.timestamp_ms.saturating_sub(base) as f64 / 1000.0 / speed as f64,
+            );
+            // Short sleeps, so stopping doesn't wait out a long pause in the session
+            loop {
+                if stop.load(Ordering::Relaxed) {
+                    return Ok(position);
+                }
+                let now = Instant::now();
+                if now >= due {
+                    break;
+                }
+                std::thread::sleep((due - now).min(std::time::Duration::from_millis(100)));
+            }
+            show(&frame);
+            position = frame.timestamp_ms;
+        }
+        Ok(position)
+    }
+}
+
+impl Iterator for RecordingPlayer {
+    type Item = Result<RecordedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+/// Open a recording for playback
+/// The path must resolve to a file inside `recordings_dir`
+pub fn open_player(recordings_dir: &Path, path: &str) -> Result<RecordingPlayer> {
+    let path = resolve_recording_path(recordings_dir, path)?;
+    RecordingPlayer::open(&path)
+}
+
+/// A mouse press shown as a marker during playback
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ClickMarker {
+    pub timestamp_ms: u64,
+    pub x: i32,
+    pub y: i32,
+    pub button: u8,
+    /// Frame size the coordinates refer to (0x0 if no frame had arrived yet)
+    pub frame_width: u16,
+    pub frame_height: u16,
+}
+
+/// A key press shown in the playback key log
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct KeyLogEntry {
+    pub timestamp_ms: u64,
+    pub key_code: u16,
+}
+
+/// Operator input from a recording, for overlaying on playback
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct InputOverlay {
+    pub clicks: Vec<ClickMarker>,
+    pub keys: Vec<KeyLogEntry>,
+}
+
+/// Read the input overlay of a recording
+/// The path must resolve to a file inside `recordings_dir`
+pub fn read_input_overlay(recordings_dir: &Path, path: &str) -> Result<InputOverlay> {
+    let path = resolve_recording_path(recordings_dir, path)?;
+    RecordingReader::open(&path)?.input_overlay()
+}
+
+/// Canonicalize a frontend-supplied path and verify it is a `.sdrec` file inside
+/// `recordings_dir` (canonicalized so ".." and links can't escape)
+fn resolve_recording_path(recordings_dir: &Path, path: &str) -> Result<PathBuf> {
+    let path = fs::canonicalize(path)?;
+    let recordings_dir = fs::canonicalize(recordings_dir)?;
+    if !path.starts_with(&recordings_dir) || path.extension().and_then(|s| s.to_str()) != Some("sdrec") {
+        anyhow::bail!("Invalid recording path");
+    }
+    Ok(path)
+}
+
+/// Path of the thumbnail stored next to a recording
+fn thumbnail_path(recording: &Path) -> PathBuf {
+    recording.with_extension("thumb.jpg")
+}
+
+/// Scale a video frame down to a small JPEG preview
+fn make_thumbnail(frame: &[u8]) -> Result<Vec<u8>> {
+    use image::codecs::jpeg::JpegEncoder;
+
+    // Frames are JPEG, or PNG when the session ran in lossless mode
+    let image = image::load_from_memory(frame)?;
+    let thumbnail = image.thumbnail(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT).to_rgb8();
+
+    let mut out = Vec::new();
+    JpegEncoder::new_with_quality(&mut out, THUMBNAIL_QUALITY).encode(
+        thumbnail.as_raw(),
+        thumbnail.width(),
+        thumbnail.height(),
+        image::ColorType::Rgb8,
+    )?;
+    Ok(out)
+}
+
+/// Create the thumbnail file for a recording from one of its frames
+fn write_thumbnail(recording: &Path, frame: &[u8]) -> Result<Vec<u8>> {
+    let thumbnail = make_thumbnail(frame)?;
+    fs::write(thumbnail_path(recording), &thumbnail)?;
+    Ok(thumbnail)
+}
+
+/// Load a recording's thumbnail, generating it from the first frame for
+/// recordings made before thumbnails existed
+fn load_thumbnail(recording: &Path, reader: &mut RecordingReader) -> Option<Vec<u8>> {
+    if let Ok(data) = fs::read(thumbnail_path(recording)) {
+        return Some(data);
+    }
+
+    let frame = reader.first_video_frame().ok()??;
+    match write_thumbnail(recording, &frame.data) {
+        Ok(thumbnail) => Some(thumbnail),
+        // Read-only folder: still show the preview, just don't cache it
+        Err(_) => make_thumbnail(&frame.data).ok(),
+    }
+}
+
 /// Recording info for listing
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct RecordingInfo {
@@ -271,19 +735,19 @@ pub struct RecordingInfo {
     pub size_bytes: u64,
     pub frame_count: u64,
     pub resolution: String,
+    /// Base64 JPEG preview (None if the recording has no decodable frame)
+    pub thumbnail: Option<String>,
 }
 
-/// List all recordings
-pub fn list_recordings() -> Result<Vec<RecordingInfo>> {
-    let recordings_dir = SessionRecorder::recordings_directory()?;
-
+/// List all recordings in a recordings directory
+pub fn list_recordings(recordings_dir: &Path) -> Result<Vec<RecordingInfo>> {
     if !recordings_dir.exists() {
         return Ok(Vec::new());
     }
 
     let mut recordings = Vec::new();
 
-    for entry in fs::read_dir(&recordings_dir)? {
+    for entry in fs::read_dir(recordings_dir)? {
         let entry = entry?;
         let path = entry.path();
 
@@ -304,32 +768,12 @@ pub fn list_recordings() -> Result<Vec<RecordingInfo>> {
 
 /// Read recording info from file
 fn read_recording_info(path: &PathBuf) -> Result<RecordingInfo> {
-    let file = File::open(path)?;
-    let mut reader = BufReader::new(file);
-
-    // Read and verify header
-    let mut magic = [0u8; 4];
-    reader.read_exact(&mut magic)?;
-    if &magic != MAGIC {
-        anyhow::bail!("Invalid recording file");
-    }
-
-    let mut version = [0u8; 1];
-    reader.read_exact(&mut version)?;
-    if version[0] != RECORDING_VERSION {
-        anyhow::bail!("Unsupported recording version");
-    }
-
-    // Read metadata length and metadata
-    let mut len_buf = [0u8; 4];
-    reader.read_exact(&mut len_buf)?;
-    let metadata_len = u32::from_le_bytes(len_buf) as usize;
-
-    let mut metadata_buf = vec![0u8; metadata_len];
-    reader.read_exact(&mut metadata_buf)?;
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
 
-    let metadata: RecordingMetadata = serde_json::from_slice(&metadata_buf)?;
+    let mut reader = RecordingReader::open(path)?;
+    let metadata = reader.metadata().clone();
     let file_size = fs::metadata(path)?.len();
+    let thumbnail = load_thumbnail(path, &mut reader).map(|data| STANDARD.encode(data));
 
     Ok(RecordingInfo {
         path: path.to_string_lossy().to_string(),
@@ -344,94 +788,184 @@ fn read_recording_info(path: &PathBuf) -> Result<RecordingInfo> {
         size_bytes: file_size,
         frame_count: metadata.frame_count,
         resolution: format!("{}x{}", metadata.width, metadata.height),
+        thumbnail,
     })
 }
 
 /// Delete a recording
-pub fn delete_recording(path: &str) -> Result<()> {
-    let path = PathBuf::from(path);
-
-    // Verify it's in the recordings directory
-    let recordings_dir = SessionRecorder::recordings_directory()?;
-    if !path.starts_with(&recordings_dir) {
-        anyhow::bail!("Invalid recording path");
-    }
-
+/// The path must resolve to a `.sdrec` file inside `recordings_dir`
+pub fn delete_recording(recordings_dir: &Path, path: &str) -> Result<()> {
+    let path = resolve_recording_path(recordings_dir, path)?;
     fs::remove_file(&path)?;
+    let _ = fs::remove_file(thumbnail_path(&path));
     println!("[RECORDING] Deleted recording: {:?}", path);
     Ok(())
 }
 
+/// A record on its way to the writer thread, stamped when it was queued
+enum WriteJob {
+    Video { timestamp_ms: u64, width: u16, height: u16, data: Vec<u8> },
+    Input { timestamp_ms: u64, event: InputEvent },
+}
+
+/// Counters shared between a recording's writer thread and the manager
+struct RecordingProgress {
+    started: Instant,
+    path: PathBuf,
+    frame_count: AtomicU64,
+    dropped_frames: AtomicU64,
+    /// The writer stopped (size limit or write error) and takes no more records
+    finished: AtomicBool,
+}
+
+/// A recording in progress: the queue to its writer thread and the thread itself
+struct ActiveRecording {
+    jobs: SyncSender<WriteJob>,
+    writer: JoinHandle<Result<PathBuf>>,
+    progress: Arc<RecordingProgress>,
+}
+
+impl ActiveRecording {
+    fn start(recordings_dir: &Path, remote_device_id: &str, remote_device_name: &str) -> Result<Self> {
+        let mut recorder = SessionRecorder::new(recordings_dir, remote_device_id, remote_device_name)?;
+        recorder.start()?;
+
+        let progress = Arc::new(RecordingProgress {
+            started: recorder.start_time,
+            path: recorder.path().clone(),
+            frame_count: AtomicU64::new(0),
+            dropped_frames: AtomicU64::new(0),
+            finished: AtomicBool::new(false),
+        });
+        let (jobs, queue) = mpsc::sync_channel(WRITE_QUEUE_LEN);
+        let writer_progress = progress.clone();
+        let writer = std::thread::Builder::new()
+            .name("recording-writer".to_string())
+            .spawn(move || Self::write_loop(recorder, queue, writer_progress))?;
+
+        Ok(Self { jobs, writer, progress })
+    }
+
+    /// Queue a record without waiting; it is dropped if the writer is behind
+    fn queue(&self, job: WriteJob) -> Result<()> {
+        match self.jobs.try_send(job) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                let dropped = self.progress.dropped_frames.fetch_add(1, Ordering::Relaxed) + 1;
+                if dropped == 1 || dropped.is_multiple_of(100) {
+                    println!("[RECORDING] Disk is falling behind, {} record(s) dropped", dropped);
+                }
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(_)) => anyhow::bail!("Recording stopped"),
+        }
+    }
+
+    /// Write queued records until the queue closes, then finalize the file
+    fn write_loop(mut recorder: SessionRecorder, queue: Receiver<WriteJob>, progress: Arc<RecordingProgress>) -> Result<PathBuf> {
+        for job in queue.iter() {
+            let written = match job {
+                WriteJob::Video { timestamp_ms, width, height, data } => {
+                    recorder.write_video_frame(timestamp_ms, width, height, &data)
+                }
+                WriteJob::Input { timestamp_ms, event } => recorder.write_input_event(timestamp_ms, &event),
+            };
+            if let Err(e) = written {
+                eprintln!("[RECORDING] {}, recording stopped", e);
+                break;
+            }
+            progress.frame_count.store(recorder.frame_count(), Ordering::Relaxed);
+        }
+        progress.finished.store(true, Ordering::Relaxed);
+
+        if recorder.is_recording() {
+            recorder.stop()
+        } else {
+            Ok(recorder.path().clone())
+        }
+    }
+
+    /// Close the queue and wait for the writer to flush what is left
+    fn finish(self) -> Result<PathBuf> {
+        drop(self.jobs);
+        self.writer.join().map_err(|_| anyhow::anyhow!("Recording writer panicked"))?
+    }
+
+    fn is_recording(&self) -> bool {
+        !self.progress.finished.load(Ordering::Relaxed)
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.progress.started.elapsed().as_millis() as u64
+    }
+}
+
 /// Recording manager for use in AppState
+/// Records are written by a thread of their own, so a slow disk never holds up a session
 pub struct RecordingManager {
-    recorder: Mutex<Option<SessionRecorder>>,
+    active: Mutex<Option<ActiveRecording>>,
 }
 
 impl RecordingManager {
     pub fn new() -> Self {
         Self {
-            recorder: Mutex::new(None),
+            active: Mutex::new(None),
         }
     }
 
-    /// Start a new recording
-    pub fn start_recording(&self, remote_device_id: &str, remote_device_name: &str) -> Result<()> {
-        let mut recorder_lock = self.recorder.lock();
-
-        // Stop existing recording if any
-        if let Some(ref mut existing) = *recorder_lock {
-            if existing.is_recording() {
-                let _ = existing.stop();
-            }
+    /// Start a new recording in `recordings_dir`
+    pub fn start_recording(&self, recordings_dir: &Path, remote_device_id: &str, remote_device_name: &str) -> Result<()> {
+        // Stop existing recording if any, outside the lock so sessions keep queueing
+        let existing = self.active.lock().take();
+        if let Some(existing) = existing {
+            let _ = existing.finish();
         }
 
-        let mut recorder = SessionRecorder::new(remote_device_id, remote_device_name)?;
-        recorder.start()?;
-        *recorder_lock = Some(recorder);
+        let recording = ActiveRecording::start(recordings_dir, remote_device_id, remote_device_name)?;
+        *self.active.lock() = Some(recording);
         Ok(())
     }
 
-    /// Stop current recording
+    /// Stop current recording, once the records still queued are on disk
     pub fn stop_recording(&self) -> Result<PathBuf> {
-        let mut recorder_lock = self.recorder.lock();
-
-        if let Some(ref mut recorder) = *recorder_lock {
-            let path = recorder.stop()?;
-            *recorder_lock = None;
-            Ok(path)
-        } else {
-            anyhow::bail!("No active recording")
+        let recording = self.active.lock().take();
+        match recording {
+            Some(recording) => recording.finish(),
+            None => anyhow::bail!("No active recording"),
         }
     }
 
-    /// Write a video frame (called from host session)
+    /// Queue a video frame (called from host session)
     pub fn write_frame(&self, width: u16, height: u16, data: &[u8]) -> Result<()> {
-        let mut recorder_lock = self.recorder.lock();
+        if let Some(ref recording) = *self.active.lock() {
+            let timestamp_ms = recording.elapsed_ms();
+            recording.queue(WriteJob::Video { timestamp_ms, width, height, data: data.to_vec() })?;
+        }
+        Ok(())
+    }
 
-        if let Some(ref mut recorder) = *recorder_lock {
-            recorder.write_video_frame(width, height, data)?;
+    /// Queue an operator input event (called from the input commands)
+    pub fn write_input(&self, event: &InputEvent) -> Result<()> {
+        if let Some(ref recording) = *self.active.lock() {
+            let timestamp_ms = recording.elapsed_ms();
+            recording.queue(WriteJob::Input { timestamp_ms, event: event.clone() })?;
         }
         Ok(())
     }
 
     /// Check if currently recording
     pub fn is_recording(&self) -> bool {
-        self.recorder.lock().as_ref().map(|r| r.is_recording()).unwrap_or(false)
+        self.active.lock().as_ref().map(|r| r.is_recording()).unwrap_or(false)
     }
 
     /// Get recording status
     pub fn status(&self) -> Option<RecordingStatus> {
-        let recorder_lock = self.recorder.lock();
-        recorder_lock.as_ref().and_then(|r| {
-            if r.is_recording() {
-                Some(RecordingStatus {
-                    duration_ms: r.duration().as_millis() as u64,
-                    frame_count: r.frame_count(),
-                    path: r.path().to_string_lossy().to_string(),
-                })
-            } else {
-                None
-            }
+        let active = self.active.lock();
+        active.as_ref().filter(|r| r.is_recording()).map(|r| RecordingStatus {
+            duration_ms: r.elapsed_ms(),
+            frame_count: r.progress.frame_count.load(Ordering::Relaxed),
+            dropped_frames: r.progress.dropped_frames.load(Ordering::Relaxed),
+            path: r.progress.path.to_string_lossy().to_string(),
         })
     }
 }
@@ -446,6 +980,254 @@ impl Default for RecordingManager {
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct RecordingStatus {
     pub duration_ms: u64,
+    /// Video frames on disk so far
     pub frame_count: u64,
+    /// Frames and input events dropped because the disk fell behind
+    pub dropped_frames: u64,
     pub path: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_jpeg(width: u32, height: u32) -> Vec<u8> {
+        let rgb = vec![128u8; (width * height * 3) as usize];
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 80)
+            .encode(&rgb, width, height, image::ColorType::Rgb8)
+            .unwrap();
+        jpeg
+    }
+
+    #[test]
+    fn test_recording_reader_and_thumbnail() {
+        let dir = std::env::temp_dir().join("securedesk_recording_test");
+        let _ = fs::remove_dir_all(&dir);
+
+        let jpeg = test_jpeg(640, 360);
+        let mut recorder = SessionRecorder::new(&dir, "123456789", "Test").unwrap();
+        recorder.start().unwrap();
+        recorder.write_video_frame(0, 640, 360, &jpeg).unwrap();
+        recorder.write_video_frame(0, 640, 360, &jpeg).unwrap();
+        let path = recorder.stop().unwrap();
+
+        let mut reader = RecordingReader::open(&path).unwrap();
+        assert_eq!(reader.metadata().frame_count, 2);
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert_eq!((frame.width, frame.height), (640, 360));
+        assert_eq!(frame.data, jpeg);
+        assert!(reader.next_frame().unwrap().is_some());
+        assert!(reader.next_frame().unwrap().is_none());
+
+        // Written on stop, and regenerated when missing
+        assert!(thumbnail_path(&path).exists());
+        fs::remove_file(thumbnail_path(&path)).unwrap();
+        let info = read_recording_info(&path).unwrap();
+        assert!(info.thumbnail.is_some());
+        assert!(thumbnail_path(&path).exists());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_input_records_and_overlay() {
+        let dir = std::env::temp_dir().join("securedesk_recording_input_test");
+        let _ = fs::remove_dir_all(&dir);
+
+        let jpeg = test_jpeg(64, 36);
+        let mouse = |event_type: &str| InputEvent::Mouse {
+            x: 10,
+            y: 20,
+            event_type: event_type.to_string(),
+            button: Some(0),
+        };
+        let mut recorder = SessionRecorder::new(&dir, "123456789", "Test").unwrap();
+        recorder.start().unwrap();
+        recorder.write_video_frame(0, 64, 36, &jpeg).unwrap();
+        recorder.write_input_event(10, &mouse("move")).unwrap();
+        recorder.write_input_event(10, &mouse("down")).unwrap();
+        recorder.write_input_event(10, &mouse("up")).unwrap();
+        recorder.write_input_event(10, &InputEvent::Key { key_code: 0x41, pressed: true }).unwrap();
+        recorder.write_input_event(10, &InputEvent::Key { key_code: 0x41, pressed: false }).unwrap();
+        let path = recorder.stop().unwrap();
+
+        let mut reader = RecordingReader::open(&path).unwrap();
+        assert_eq!(reader.metadata().frame_count, 1);
+        assert_eq!(reader.metadata().input_event_count, 5);
+        assert!(reader.first_video_frame().unwrap().is_some());
+        let frame = reader.next_frame().unwrap().unwrap();
+        assert_eq!(frame.frame_type, FrameType::Input as u8);
+        assert_eq!(serde_json::from_slice::<InputEvent>(&frame.data).unwrap(), mouse("move"));
+
+        // Only presses reach the overlay
+        let overlay = read_input_overlay(&dir, &path.to_string_lossy()).unwrap();
+        assert_eq!(overlay.clicks.len(), 1);
+        assert_eq!((overlay.clicks[0].x, overlay.clicks[0].y), (10, 20));
+        assert_eq!((overlay.clicks[0].frame_width, overlay.clicks[0].frame_height), (64, 36));
+        assert_eq!(overlay.keys.len(), 1);
+        assert_eq!(overlay.keys[0].key_code, 0x41);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_delete_only_recordings() {
+        let dir = std::env::temp_dir().join("securedesk_recording_delete_test");
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut recorder = SessionRecorder::new(&dir, "123456789", "Test").unwrap();
+        recorder.start().unwrap();
+        let path = recorder.stop().unwrap();
+
+        // Another file in the directory, and a recording outside it
+        let other = dir.join("notes.txt");
+        fs::write(&other, b"keep").unwrap();
+        let outside = std::env::temp_dir().join("securedesk_recording_delete_test.sdrec");
+        fs::write(&outside, b"keep").unwrap();
+        let escape = dir.join("..").join("securedesk_recording_delete_test.sdrec");
+
+        for refused in [&other, &outside, &escape, &dir] {
+            assert!(delete_recording(&dir, &refused.to_string_lossy()).is_err(), "{:?}", refused);
+        }
+        assert!(other.exists() && outside.exists());
+
+        delete_recording(&dir, &path.to_string_lossy()).unwrap();
+        assert!(!path.exists());
+
+        let _ = fs::remove_file(&outside);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_corrupt_frame_length_refused() {
+        let dir = std::env::temp_dir().join("securedesk_recording_corrupt_len_test");
+        let _ = fs::remove_dir_all(&dir);
+
+        let jpeg = test_jpeg(64, 36);
+        let mut recorder = SessionRecorder::new(&dir, "123456789", "Test").unwrap();
+        recorder.start().unwrap();
+        recorder.write_video_frame(0, 64, 36, &jpeg).unwrap();
+        recorder.write_video_frame(100, 64, 36, &jpeg).unwrap();
+        let path = recorder.stop().unwrap();
+
+        let mut player = RecordingPlayer::open(&path).unwrap();
+        let second = player.index[1].1;
+        let set_len = |len: u32| {
+            let mut file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+            file.seek(SeekFrom::Start(second + 13)).unwrap();
+            file.write_all(&len.to_le_bytes()).unwrap();
+        };
+
+        // Longer than any frame, then longer than what is left of the file
+        set_len(u32::MAX);
+        let mut reader = RecordingReader::open(&path).unwrap();
+        assert!(reader.next_frame().unwrap().is_some());
+        assert!(reader.next_frame().is_err());
+        assert!(player.next_frame().unwrap().is_some());
+        assert!(player.next_frame().is_err());
+
+        set_len(jpeg.len() as u32 + 1);
+        player.seek(100);
+        assert!(player.next_frame().is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_player_seeks_and_survives_truncation() {
+        let dir = std::env::temp_dir().join("securedesk_recording_player_test");
+        let _ = fs::remove_dir_all(&dir);
+
+        let jpeg = test_jpeg(64, 36);
+        let mut recorder = SessionRecorder::new(&dir, "123456789", "Test").unwrap();
+        recorder.start().unwrap();
+        recorder.write_video_frame(0, 64, 36, &jpeg).unwrap();
+        recorder.write_input_event(50, &InputEvent::Key { key_code: 0x41, pressed: true }).unwrap();
+        recorder.write_video_frame(100, 64, 36, &jpeg).unwrap();
+        let path = recorder.stop().unwrap();
+
+        // A record type from a newer version, another frame, then a crash mid-frame
+        let record = |kind: u8, timestamp: u64, data: &[u8]| {
+            let mut record = vec![kind];
+            record.extend_from_slice(&timestamp.to_le_bytes());
+            record.extend_from_slice(&64u16.to_le_bytes());
+            record.extend_from_slice(&36u16.to_le_bytes());
+            record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            record.extend_from_slice(data);
+            record
+        };
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&record(0x7F, 150, b"future")).unwrap();
+        file.write_all(&record(FrameType::Video as u8, 200, &jpeg)).unwrap();
+        let cut = record(FrameType::Video as u8, 300, &jpeg);
+        file.write_all(&cut[..cut.len() / 2]).unwrap();
+        drop(file);
+
+        let mut player = open_player(&dir, &path.to_string_lossy()).unwrap();
+        assert_eq!(player.frame_count(), 3);
+        assert_eq!(player.duration_ms(), 200);
+
+        let frame = player.next_frame().unwrap().unwrap();
+        assert_eq!((frame.timestamp_ms, frame.width, frame.height), (0, 64, 36));
+        assert_eq!(frame.data, jpeg);
+
+        // Seeking lands on the frame on screen at that moment
+        assert_eq!(player.seek(150), 100);
+        let timestamps: Vec<u64> = player.by_ref().map(|frame| frame.unwrap().timestamp_ms).collect();
+        assert_eq!(timestamps, vec![100, 200]);
+        assert!(player.next_frame().unwrap().is_none());
+
+        player.seek(0);
+        let mut shown = Vec::new();
+        let reached = player.play(100.0, &AtomicBool::new(false), |frame| shown.push(frame.timestamp_ms)).unwrap();
+        assert_eq!((shown, reached), (vec![0, 100, 200], 200));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_manager_writes_in_background() {
+        let dir = std::env::temp_dir().join("securedesk_recording_manager_test");
+        let _ = fs::remove_dir_all(&dir);
+
+        let jpeg = test_jpeg(64, 36);
+        let manager = RecordingManager::new();
+        manager.start_recording(&dir, "123456789", "Test").unwrap();
+        for _ in 0..3 {
+            manager.write_frame(64, 36, &jpeg).unwrap();
+        }
+        manager.write_input(&InputEvent::Key { key_code: 0x41, pressed: true }).unwrap();
+        assert!(manager.status().is_some());
+        let path = manager.stop_recording().unwrap();
+        assert!(!manager.is_recording());
+
+        // Everything queued before the stop reaches the file
+        let reader = RecordingReader::open(&path).unwrap();
+        assert_eq!(reader.metadata().frame_count, 3);
+        assert_eq!(reader.metadata().input_event_count, 1);
+
+        // A full queue drops records instead of waiting for the disk
+        let (jobs, queue) = mpsc::sync_channel(WRITE_QUEUE_LEN);
+        let recording = ActiveRecording {
+            jobs,
+            writer: std::thread::spawn(|| Ok(PathBuf::new())),
+            progress: Arc::new(RecordingProgress {
+                started: Instant::now(),
+                path: PathBuf::new(),
+                frame_count: AtomicU64::new(0),
+                dropped_frames: AtomicU64::new(0),
+                finished: AtomicBool::new(false),
+            }),
+        };
+        let frame = || WriteJob::Video { timestamp_ms: 0, width: 64, height: 36, data: jpeg.clone() };
+        for _ in 0..WRITE_QUEUE_LEN + 3 {
+            recording.queue(frame()).unwrap();
+        }
+        assert_eq!(recording.progress.dropped_frames.load(Ordering::Relaxed), 3);
+        drop(queue);
+        assert!(recording.queue(frame()).is_err());
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,175 @@
+//! Per-session event timeline for the UI and session transcripts
+//! In-memory only and bounded - the audit log remains the tamper-evident record
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Events kept per session; older entries are dropped first
+pub const MAX_EVENTS_PER_SESSION: usize = 200;
+
+/// Disconnected sessions whose timeline is kept for export; oldest dropped first
+pub const MAX_ENDED_SESSIONS: usize = 10;
+
+/// A single timeline entry
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionEvent {
+    /// Unix time in milliseconds
+    pub timestamp: u64,
+    /// Short machine-readable kind (e.g. "connected", "black_screen_on")
+    pub kind: String,
+    /// Optional human-readable detail
+    pub detail: Option<String>,
+}
+
+/// Who a session was with and when, for transcripts
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionMeta {
+    pub remote_id: String,
+    pub remote_name: String,
+    /// Connection type when the session was established
+    pub connection_type: String,
+    /// Unix time in milliseconds
+    pub connected_at: u64,
+    pub disconnected_at: Option<u64>,
+}
+
+/// Ring buffers of events, keyed by session_id
+#[derive(Default)]
+pub struct SessionEventLog {
+    sessions: HashMap<String, VecDeque<SessionEvent>>,
+    meta: HashMap<String, SessionMeta>,
+    /// Disconnected sessions still held, oldest first
+    ended: VecDeque<String>,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl SessionEventLog {
+    /// Begin a session's timeline with its "connected" event
+    pub fn start(&mut self, session_id: &str, meta: SessionMeta) {
+        let detail = format!("{} via {}", meta.remote_id, meta.connection_type);
+        self.meta.insert(session_id.to_string(), meta);
+        self.record(session_id, "connected", Some(detail));
+    }
+
+    /// Close a session's timeline with a "disconnected" event; it stays
+    /// readable until `MAX_ENDED_SESSIONS` later sessions have ended
+    pub fn end(&mut self, session_id: &str) {
+        if self.ended.iter().any(|id| id == session_id) {
+            return;
+        }
+        self.record(session_id, "disconnected", None);
+        if let Some(meta) = self.meta.get_mut(session_id) {
+            meta.disconnected_at = Some(now_millis());
+        }
+
+        self.ended.push_back(session_id.to_string());
+        while self.ended.len() > MAX_ENDED_SESSIONS {
+            if let Some(oldest) = self.ended.front().cloned() {
+                self.remove(&oldest);
+            }
+        }
+    }
+
+    /// End every session still open
+    pub fn end_all(&mut self) {
+        let open: Vec<String> = self.sessions.keys()
+            .filter(|id| !self.ended.contains(id))
+            .cloned()
+            .collect();
+        for session_id in open {
+            self.end(&session_id);
+        }
+    }
+
+    /// Append an event to a session's timeline
+    pub fn record(&mut self, session_id: &str, kind: &str, detail: Option<String>) {
+        let timestamp = now_millis();
+
+        let events = self.sessions.entry(session_id.to_string()).or_default();
+        if events.len() >= MAX_EVENTS_PER_SESSION {
+            events.pop_front();
+        }
+        events.push_back(SessionEvent {
+            timestamp,
+            kind: kind.to_string(),
+            detail,
+        });
+    }
+
+    /// Events for a session, oldest first
+    pub fn events(&self, session_id: &str) -> Vec<SessionEvent> {
+        self.sessions
+            .get(session_id)
+            .map(|events| events.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn meta(&self, session_id: &str) -> Option<SessionMeta> {
+        self.meta.get(session_id).cloned()
+    }
+
+    /// Drop a session's timeline
+    pub fn remove(&mut self, session_id: &str) {
+        self.sessions.remove(session_id);
+        self.meta.remove(session_id);
+        self.ended.retain(|id| id != session_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_log_ring_buffer() {
+        let mut log = SessionEventLog::default();
+        log.record("session_0", "connected", None);
+        for i in 0..MAX_EVENTS_PER_SESSION {
+            log.record("session_0", "chat_sent", Some(i.to_string()));
+        }
+
+        let events = log.events("session_0");
+        assert_eq!(events.len(), MAX_EVENTS_PER_SESSION);
+        assert_eq!(events[0].detail.as_deref(), Some("0")); // "connected" was dropped
+        assert!(log.events("session_1").is_empty());
+
+        log.remove("session_0");
+        assert!(log.events("session_0").is_empty());
+    }
+
+    #[test]
+    fn test_ended_sessions_kept_for_export() {
+        let meta = |remote_id: &str| SessionMeta {
+            remote_id: remote_id.to_string(),
+            remote_name: "Office PC".to_string(),
+            connection_type: "Relay".to_string(),
+            connected_at: now_millis(),
+            disconnected_at: None,
+        };
+
+        let mut log = SessionEventLog::default();
+        log.start("session_0", meta("123456789"));
+        log.end("session_0");
+        log.end("session_0");
+
+        let kinds: Vec<String> = log.events("session_0").into_iter().map(|e| e.kind).collect();
+        assert_eq!(kinds, ["connected", "disconnected"]);
+        assert!(log.meta("session_0").unwrap().disconnected_at.is_some());
+
+        // Only the most recent ended sessions are held
+        for i in 1..=MAX_ENDED_SESSIONS {
+            log.start(&format!("session_{}", i), meta("987654321"));
+        }
+        log.end_all();
+        assert!(log.events("session_0").is_empty());
+        assert!(log.meta("session_0").is_none());
+        assert_eq!(log.events("session_1").len(), 2);
+    }
+}
@@ -0,0 +1,322 @@
+//! Capture sources - the monitors and windows a host can stream
+
+use anyhow::Result;
+
+use crate::capture::{MonitorInfo, ScreenCapture};
+
+/// Longest source name sent over the wire, in bytes
+pub const MAX_NAME_LEN: usize = 256;
+
+/// Most sources listed in one control::SOURCES reply
+pub const MAX_SOURCES: usize = 512;
+
+/// What kind of source a list holds
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    Monitor = 0x00,
+    Window = 0x01,
+}
+
+impl SourceKind {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x00 => Some(SourceKind::Monitor),
+            0x01 => Some(SourceKind::Window),
+            _ => None,
+        }
+    }
+}
+
+/// A monitor or window that can be captured
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct CaptureSource {
+    /// Monitor index (as for control::SELECT_MONITOR), or the platform window handle
+    pub id: u64,
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    /// Primary monitor (always false for windows)
+    pub primary: bool,
+}
+
+impl From<MonitorInfo> for CaptureSource {
+    fn from(monitor: MonitorInfo) -> Self {
+        Self {
+            id: monitor.index as u64,
+            name: monitor.name,
+            width: monitor.width,
+            height: monitor.height,
+            primary: monitor.primary,
+        }
+    }
+}
+
+/// Sources of `kind` on this machine
+pub fn list(kind: SourceKind) -> Vec<CaptureSource> {
+    match kind {
+        SourceKind::Monitor => ScreenCapture::list_monitors().into_iter().map(CaptureSource::from).collect(),
+        SourceKind::Window => platform::windows(),
+    }
+}
+
+/// Encode a source list for control::SOURCES:
+/// [kind u8][count u16 LE] then per source
+/// [id u64 LE][width u32 LE][height u32 LE][primary u8][name len u16 LE][name utf8]
+pub fn encode(kind: SourceKind, sources: &[CaptureSource]) -> Vec<u8> {
+    let sources = &sources[..sources.len().min(MAX_SOURCES)];
+    let mut data = vec![kind as u8];
+    data.extend(&(sources.len() as u16).to_le_bytes());
+    for source in sources {
+        let name = truncate_name(&source.name);
+        data.extend(&source.id.to_le_bytes());
+        data.extend(&source.width.to_le_bytes());
+        data.extend(&source.height.to_le_bytes());
+        data.push(source.primary as u8);
+        data.extend(&(name.len() as u16).to_le_bytes());
+        data.extend(name.as_bytes());
+    }
+    data
+}
+
+/// Decode a control::SOURCES payload (after the message type)
+pub fn decode(data: &[u8]) -> Result<(SourceKind, Vec<CaptureSource>)> {
+    if data.len() < 3 {
+        anyhow::bail!("Source list too short");
+    }
+    let kind = SourceKind::from_u8(data[0])
+        .ok_or_else(|| anyhow::anyhow!("Unknown source kind {}", data[0]))?;
+    let count = u16::from_le_bytes([data[1], data[2]]) as usize;
+
+    let mut sources = Vec::with_capacity(count.min(MAX_SOURCES));
+    let mut pos = 3;
+    for _ in 0..count {
+        let header = data.get(pos..pos + 19)
+            .ok_or_else(|| anyhow::anyhow!("Source list truncated"))?;
+        let id = u64::from_le_bytes(header[0..8].try_into()?);
+        let width = u32::from_le_bytes(header[8..12].try_into()?);
+        let height = u32::from_le_bytes(header[12..16].try_into()?);
+        let primary = header[16] != 0;
+        let name_len = u16::from_le_bytes([header[17], header[18]]) as usize;
+        pos += 19;
+
+        let name = data.get(pos..pos + name_len)
+            .ok_or_else(|| anyhow::anyhow!("Source list truncated"))?;
+        pos += name_len;
+
+        sources.push(CaptureSource {
+            id,
+            name: String::from_utf8_lossy(name).to_string(),
+            width,
+            height,
+            primary,
+        });
+    }
+    Ok((kind, sources))
+}
+
+/// Cut a name to MAX_NAME_LEN bytes without splitting a character
+fn truncate_name(name: &str) -> &str {
+    if name.len() <= MAX_NAME_LEN {
+        return name;
+    }
+    let mut end = MAX_NAME_LEN;
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[..end]
+}
+
+#[cfg(windows)]
+mod platform {
+    use super::*;
+    use windows::Win32::Foundation::{BOOL, HWND, LPARAM, RECT, TRUE};
+    use windows::Win32::UI::WindowsAndMessaging::{
+        EnumWindows, GetWindowRect, GetWindowTextLengthW, GetWindowTextW, IsIconic, IsWindowVisible,
+    };
+
+    /// Visible, titled, non-minimized top-level windows
+    pub fn windows() -> Vec<CaptureSource> {
+        unsafe extern "system" fn collect(hwnd: HWND, data: LPARAM) -> BOOL {
+            let sources = &mut *(data.0 as *mut Vec<CaptureSource>);
+            if !IsWindowVisible(hwnd).as_bool() || IsIconic(hwnd).as_bool() {
+                return TRUE;
+            }
+
+            let len = GetWindowTextLengthW(hwnd);
+            if len <= 0 {
+                return TRUE;
+            }
+            let mut title = vec![0u16; len as usize + 1];
+            let copied = GetWindowTextW(hwnd, &mut title).max(0) as usize;
+
+            let mut rect = RECT::default();
+            if GetWindowRect(hwnd, &mut rect).is_err() {
+                return TRUE;
+            }
+            let (width, height) = (rect.right - rect.left, rect.bottom - rect.top);
+            if width <= 0 || height <= 0 {
+                return TRUE;
+            }
+
+            sources.push(CaptureSource {
+                id: hwnd.0 as u64,
+                name: String::from_utf16_lossy(&title[..copied]),
+                width: width as u32,
+                height: height as u32,
+                primary: false,
+            });
+            TRUE
+        }
+
+        let mut sources: Vec<CaptureSource> = Vec::new();
+        unsafe {
+            let _ = EnumWindows(Some(collect), LPARAM(&mut sources as *mut _ as isize));
+        }
+        sources
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use super::*;
+
+    /// Window titles need the Screen Recording permission; not listed yet
+    pub fn windows() -> Vec<CaptureSource> {
+        Vec::new()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::*;
+    use std::ffi::{CStr, CString};
+    use std::os::raw::{c_int, c_uchar, c_ulong};
+    use std::ptr;
+    use x11::xlib::*;
+
+    /// Run `f` with a short-lived display connection (empty result without X11)
+    fn with_display<T: Default>(f: impl FnOnce(*mut Display) -> T) -> T {
+        unsafe {
+            let display = XOpenDisplay(ptr::null());
+            if display.is_null() {
+                return T::default();
+            }
+            let result = f(display);
+            XCloseDisplay(display);
+            result
+        }
+    }
+
+    unsafe fn intern(display: *mut Display, name: &str) -> Atom {
+        let name = CString::new(name).unwrap_or_default();
+        XInternAtom(display, name.as_ptr(), False)
+    }
+
+    /// Read a window property; returns (items, format) with the data XFree'd
+    unsafe fn property(display: *mut Display, window: Window, name: Atom, kind: Atom) -> Option<(Vec<u8>, c_ulong, c_int)> {
+        let mut actual_type: Atom = 0;
+        let mut format: c_int = 0;
+        let mut items: c_ulong = 0;
+        let mut remaining: c_ulong = 0;
+        let mut data: *mut c_uchar = ptr::null_mut();
+        let status = XGetWindowProperty(
+            display, window, name, 0, 4096, False, kind,
+            &mut actual_type, &mut format, &mut items, &mut remaining, &mut data,
+        );
+        if status != Success as c_int || data.is_null() {
+            return None;
+        }
+        // Format-32 items are stored as C longs
+        let item_size = match format {
+            8 => 1,
+            16 => 2,
+            _ => std::mem::size_of::<std::os::raw::c_long>(),
+        };
+        let bytes = std::slice::from_raw_parts(data, items as usize * item_size).to_vec();
+        XFree(data as *mut _);
+        Some((bytes, items, format))
+    }
+
+    /// Mapped top-level windows from the window manager's client list
+    pub fn windows() -> Vec<CaptureSource> {
+        with_display(|display| unsafe {
+            let root = XDefaultRootWindow(display);
+            let client_list = intern(display, "_NET_CLIENT_LIST");
+            let Some((bytes, items, 32)) = property(display, root, client_list, XA_WINDOW) else {
+                return Vec::new();
+            };
+            let windows = std::slice::from_raw_parts(bytes.as_ptr() as *const Window, items as usize).to_vec();
+            windows.into_iter().filter_map(|window| window_source(display, window)).collect()
+        })
+    }
+
+    // Window is a c_ulong, which is not u64 on every target
+    #[allow(clippy::unnecessary_cast)]
+    unsafe fn window_source(display: *mut Display, window: Window) -> Option<CaptureSource> {
+        let mut attributes: XWindowAttributes = std::mem::zeroed();
+        if XGetWindowAttributes(display, window, &mut attributes) == 0 || attributes.map_state != IsViewable {
+            return None;
+        }
+        let name = window_title(display, window)?;
+        Some(CaptureSource {
+            id: window as u64,
+            name,
+            width: attributes.width.max(0) as u32,
+            height: attributes.height.max(0) as u32,
+            primary: false,
+        })
+    }
+
+    /// The UTF-8 EWMH title, falling back to the legacy WM_NAME
+    unsafe fn window_title(display: *mut Display, window: Window) -> Option<String> {
+        let net_wm_name = intern(display, "_NET_WM_NAME");
+        let utf8 = intern(display, "UTF8_STRING");
+        if let Some((bytes, _, 8)) = property(display, window, net_wm_name, utf8) {
+            if !bytes.is_empty() {
+                return Some(String::from_utf8_lossy(&bytes).to_string());
+            }
+        }
+
+        let mut name: *mut std::os::raw::c_char = ptr::null_mut();
+        if XFetchName(display, window, &mut name) == 0 || name.is_null() {
+            return None;
+        }
+        let title = CStr::from_ptr(name).to_string_lossy().into_owned();
+        XFree(name as *mut _);
+        (!title.is_empty()).then_some(title)
+    }
+}
+
+// Stub for unsupported platforms
+#[cfg(not(any(windows, target_os = "macos", target_os = "linux")))]
+mod platform {
+    use super::*;
+
+    pub fn windows() -> Vec<CaptureSource> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_list_round_trip() {
+        let sources = vec![
+            CaptureSource { id: 0, name: "DISPLAY1".to_string(), width: 2560, height: 1440, primary: true },
+            CaptureSource { id: 0x1234_5678, name: "Terminal — ~".to_string(), width: 800, height: 600, primary: false },
+        ];
+        let (kind, decoded) = decode(&encode(SourceKind::Window, &sources)).unwrap();
+        assert_eq!(kind, SourceKind::Window);
+        assert_eq!(decoded, sources);
+
+        // Long names are cut on a character boundary
+        let long = CaptureSource { name: "é".repeat(MAX_NAME_LEN), ..sources[0].clone() };
+        let (_, decoded) = decode(&encode(SourceKind::Monitor, &[long])).unwrap();
+        assert!(decoded[0].name.len() <= MAX_NAME_LEN);
+
+        assert!(decode(&[SourceKind::Monitor as u8, 1, 0]).is_err());
+    }
+}
@@ -9,22 +9,42 @@
 //! 3. User authenticates with identity provider
 //! 4. IdP redirects to local callback server
 //! 5. Application exchanges code for tokens
-//! 6. User identity is verified and session established
+//! 6. The id_token is verified against the provider's JWKS (signature, issuer,
+//!    audience, lifetime and nonce) and the session established
+//!
+//! Hosts without a browser use the device authorization grant (RFC 8628)
+//! instead: the user approves a code on another device while we poll.
+//!
+//! Provider settings stay readable in `sso.json`; the active session and its
+//! tokens are stored encrypted (AES-256-GCM) with a key derived from the
+//! device's secret X25519 key; the public keys viewers receive do not open it.
 
 #![allow(dead_code)]
 
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
 use anyhow::{Context, Result};
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs;
+use std::io::Write;
 use std::net::TcpListener;
 use std::path::PathBuf;
 #[allow(unused_imports)]
 use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::net::TcpListener as AsyncTcpListener;
+use zeroize::Zeroizing;
+
+use crate::crypto::Identity;
+
+/// blake3 context for the key that seals the stored session
+const STORAGE_KEY_CONTEXT: &str = "SecureDesk 2024-01 SSO session storage v2";
 
 /// OIDC Provider configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,6 +68,12 @@ pub struct OidcProvider {
     pub jwks_uri: Option<String>,
     /// Issuer URL for token validation
     pub issuer: String,
+    /// RP-initiated logout endpoint (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_session_endpoint: Option<String>,
+    /// Device authorization endpoint for headless logins (optional)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_authorization_endpoint: Option<String>,
     /// Scopes to request
     #[serde(default = "default_scopes")]
     pub scopes: Vec<String>,
@@ -86,6 +112,14 @@ impl OidcProvider {
                 tenant_id
             )),
             issuer: format!("https://login.microsoftonline.com/{}/v2.0", tenant_id),
+            end_session_endpoint: Some(format!(
+                "https://login.microsoftonline.com/{}/oauth2/v2.0/logout",
+                tenant_id
+            )),
+            device_authorization_endpoint: Some(format!(
+                "https://login.microsoftonline.com/{}/oauth2/v2.0/devicecode",
+                tenant_id
+            )),
             scopes: vec![
                 "openid".to_string(),
                 "profile".to_string(),
@@ -106,6 +140,8 @@ impl OidcProvider {
             userinfo_endpoint: Some(format!("https://{}/oauth2/v1/userinfo", domain)),
             jwks_uri: Some(format!("https://{}/oauth2/v1/keys", domain)),
             issuer: format!("https://{}", domain),
+            end_session_endpoint: Some(format!("https://{}/oauth2/v1/logout", domain)),
+            device_authorization_endpoint: Some(format!("https://{}/oauth2/v1/device/authorize", domain)),
             scopes: vec![
                 "openid".to_string(),
                 "profile".to_string(),
@@ -126,6 +162,8 @@ impl OidcProvider {
             userinfo_endpoint: Some("https://openidconnect.googleapis.com/v1/userinfo".to_string()),
             jwks_uri: Some("https://www.googleapis.com/oauth2/v3/certs".to_string()),
             issuer: "https://accounts.google.com".to_string(),
+            end_session_endpoint: None,
+            device_authorization_endpoint: Some("https://oauth2.googleapis.com/device/code".to_string()),
             scopes: vec![
                 "openid".to_string(),
                 "profile".to_string(),
@@ -138,7 +176,7 @@ impl OidcProvider {
     /// Generic OIDC provider from discovery URL
     pub async fn from_discovery(discovery_url: &str, client_id: &str) -> Result<Self> {
         // Fetch OpenID Connect discovery document
-        let client = reqwest::Client::new();
+        let client = crate::proxy::http_client_builder().build()?;
         let response = client.get(discovery_url).send().await?;
         let discovery: OidcDiscovery = response.json().await?;
 
@@ -151,6 +189,8 @@ impl OidcProvider {
             userinfo_endpoint: discovery.userinfo_endpoint,
             jwks_uri: discovery.jwks_uri,
             issuer: discovery.issuer,
+            end_session_endpoint: discovery.end_session_endpoint,
+            device_authorization_endpoint: discovery.device_authorization_endpoint,
             scopes: vec![
                 "openid".to_string(),
                 "profile".to_string(),
@@ -169,6 +209,157 @@ struct OidcDiscovery {
     token_endpoint: String,
     userinfo_endpoint: Option<String>,
     jwks_uri: Option<String>,
+    end_session_endpoint: Option<String>,
+    device_authorization_endpoint: Option<String>,
+}
+
+/// JSON Web Key Set published at a provider's `jwks_uri`
+#[derive(Debug, Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+/// A public key from a JWKS (RSA or P-256)
+#[derive(Debug, Clone, Deserialize)]
+struct Jwk {
+    #[serde(default)]
+    kid: Option<String>,
+    kty: String,
+    #[serde(rename = "use", default)]
+    key_use: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+    #[serde(default)]
+    crv: Option<String>,
+    #[serde(default)]
+    x: Option<String>,
+    #[serde(default)]
+    y: Option<String>,
+}
+
+/// JOSE header of an id_token
+#[derive(Debug, Deserialize)]
+struct JwtHeader {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+/// `aud` is either a single client ID or a list of them
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Audience::One(aud) => aud == client_id,
+            Audience::Many(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+/// Claims of a verified id_token
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    iss: String,
+    aud: Audience,
+    exp: u64,
+    #[serde(default)]
+    nbf: Option<u64>,
+    #[serde(default)]
+    nonce: Option<String>,
+    #[serde(flatten)]
+    user: UserInfo,
+}
+
+/// Clock skew tolerated on `exp` and `nbf` (seconds)
+const ID_TOKEN_LEEWAY: u64 = 60;
+
+fn decode_jwt_part(part: &str) -> Result<Vec<u8>> {
+    URL_SAFE_NO_PAD.decode(part).context("Malformed id_token")
+}
+
+fn jwt_header(token: &str) -> Result<JwtHeader> {
+    let header = token.split('.').next().unwrap_or_default();
+    serde_json::from_slice(&decode_jwt_part(header)?).context("Malformed id_token header")
+}
+
+/// Check an id_token's signature with `key`, then its claims against the
+/// provider, the expected nonce and the current time
+fn verify_jwt(token: &str, key: &Jwk, provider: &OidcProvider, nonce: Option<&str>, now: u64) -> Result<IdTokenClaims> {
+    use ring::signature;
+
+    let parts: Vec<&str> = token.split('.').collect();
+    if parts.len() != 3 {
+        anyhow::bail!("Malformed id_token");
+    }
+    let header = jwt_header(token)?;
+    let signing_input = &token[..parts[0].len() + 1 + parts[1].len()];
+    let sig = decode_jwt_part(parts[2])?;
+    let field = |value: &Option<String>| -> Result<Vec<u8>> {
+        decode_jwt_part(value.as_deref().context("Incomplete JWKS key")?)
+    };
+
+    let verified = match (header.alg.as_str(), key.kty.as_str()) {
+        ("RS256", "RSA") => signature::RsaPublicKeyComponents { n: field(&key.n)?, e: field(&key.e)? }
+            .verify(&signature::RSA_PKCS1_2048_8192_SHA256, signing_input.as_bytes(), &sig)
+            .is_ok(),
+        ("ES256", "EC") if key.crv.as_deref() == Some("P-256") => {
+            let mut point = vec![0x04];
+            point.extend(field(&key.x)?);
+            point.extend(field(&key.y)?);
+            signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                .verify(signing_input.as_bytes(), &sig)
+                .is_ok()
+        }
+        (alg, kty) => anyhow::bail!("Unsupported id_token algorithm {} for a {} key", alg, kty),
+    };
+    if !verified {
+        anyhow::bail!("id_token signature is invalid");
+    }
+
+    let claims: IdTokenClaims = serde_json::from_slice(&decode_jwt_part(parts[1])?).context("Malformed id_token claims")?;
+    if claims.iss != provider.issuer {
+        anyhow::bail!("id_token issuer {} is not {}", claims.iss, provider.issuer);
+    }
+    if !claims.aud.contains(&provider.client_id) {
+        anyhow::bail!("id_token was not issued for this client");
+    }
+    if claims.exp.saturating_add(ID_TOKEN_LEEWAY) <= now {
+        anyhow::bail!("id_token has expired");
+    }
+    if claims.nbf.is_some_and(|nbf| nbf > now.saturating_add(ID_TOKEN_LEEWAY)) {
+        anyhow::bail!("id_token is not valid yet");
+    }
+    if let Some(expected) = nonce {
+        if claims.nonce.as_deref() != Some(expected) {
+            anyhow::bail!("id_token nonce does not match the login");
+        }
+    }
+    Ok(claims)
+}
+
+/// RP-initiated logout URL for a session, returning to `redirect_uri`
+/// None when the provider has no end-session endpoint
+fn end_session_url(provider: &OidcProvider, session: &SsoSession, redirect_uri: &str, state: &str) -> Option<String> {
+    let endpoint = provider.end_session_endpoint.as_ref()?;
+    let mut url = format!(
+        "{}?client_id={}&post_logout_redirect_uri={}&state={}",
+        endpoint,
+        urlencoding::encode(&provider.client_id),
+        urlencoding::encode(redirect_uri),
+        urlencoding::encode(state),
+    );
+    if let Some(ref id_token) = session.id_token {
+        url.push_str(&format!("&id_token_hint={}", urlencoding::encode(id_token)));
+    }
+    Some(url)
 }
 
 /// PKCE (Proof Key for Code Exchange) challenge
@@ -185,9 +376,9 @@ impl PkceChallenge {
         getrandom::getrandom(&mut verifier_bytes).expect("Failed to generate random bytes");
         let code_verifier = URL_SAFE_NO_PAD.encode(verifier_bytes);
 
-        // Create SHA256 hash of verifier for challenge
-        let challenge_hash = blake3::hash(code_verifier.as_bytes());
-        let code_challenge = URL_SAFE_NO_PAD.encode(challenge_hash.as_bytes());
+        // S256 (RFC 7636): the challenge is the SHA-256 of the verifier
+        let challenge_hash = Sha256::digest(code_verifier.as_bytes());
+        let code_challenge = URL_SAFE_NO_PAD.encode(challenge_hash);
 
         Self {
             code_verifier,
@@ -212,6 +403,47 @@ pub struct TokenResponse {
     pub scope: Option<String>,
 }
 
+/// Device authorization response (RFC 8628 §3.2)
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeviceAuthorization {
+    pub device_code: String,
+    /// Code the user enters at `verification_uri`
+    pub user_code: String,
+    #[serde(alias = "verification_url")]
+    pub verification_uri: String,
+    /// Verification URI with the code already filled in
+    #[serde(default)]
+    pub verification_uri_complete: Option<String>,
+    /// Lifetime of the device code (seconds)
+    pub expires_in: u64,
+    /// Minimum time between token polls (seconds)
+    #[serde(default = "default_device_interval")]
+    pub interval: u64,
+}
+
+fn default_device_interval() -> u64 {
+    5
+}
+
+/// Added to the poll interval on each `slow_down` (RFC 8628 §3.5)
+const DEVICE_SLOW_DOWN: Duration = Duration::from_secs(5);
+
+/// One poll of the token endpoint during a device login
+#[derive(Debug)]
+enum DevicePoll {
+    Tokens(TokenResponse),
+    Pending,
+    SlowDown,
+}
+
+/// OAuth2 error response
+#[derive(Debug, Deserialize)]
+struct OAuthError {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
 /// User info from OIDC provider
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserInfo {
@@ -281,15 +513,21 @@ impl SsoSession {
 pub struct SsoConfig {
     /// Configured OIDC providers
     pub providers: Vec<OidcProvider>,
-    /// Currently active session
-    #[serde(skip_serializing_if = "Option::is_none")]
+    /// Currently active session; read from older plaintext files but never written
+    #[serde(default, skip_serializing)]
     pub active_session: Option<SsoSession>,
+    /// The active session as BASE64(NONCE + ENCRYPTED(JSON))
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sealed_session: Option<String>,
     /// Require SSO for all connections
     #[serde(default)]
     pub require_sso: bool,
     /// Allowed email domains (empty = all allowed)
     #[serde(default)]
     pub allowed_domains: Vec<String>,
+    /// Device-derived key for the session (None = the session is not persisted)
+    #[serde(skip)]
+    storage_key: Option<Zeroizing<[u8; 32]>>,
 }
 
 impl Default for SsoConfig {
@@ -297,8 +535,10 @@ impl Default for SsoConfig {
         Self {
             providers: Vec::new(),
             active_session: None,
+            sealed_session: None,
             require_sso: false,
             allowed_domains: Vec::new(),
+            storage_key: None,
         }
     }
 }
@@ -313,22 +553,132 @@ impl SsoConfig {
         Ok(config_dir.join("sso.json"))
     }
 
+    /// Session storage key of a device, from its secret key
+    /// A file copied to another machine cannot be decrypted there
+    fn storage_key(identity: &Identity) -> Zeroizing<[u8; 32]> {
+        identity.storage_key(STORAGE_KEY_CONTEXT)
+    }
+
+    /// The key sessions were sealed with before it came from the secret key;
+    /// anyone with the public key could derive it, so it only opens old files
+    fn legacy_storage_key(device_public_key: &[u8; 32]) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(b"SecureDesk-SSO-Key-v1");
+        hasher.update(device_public_key);
+        hasher.update(b"encrypted-sso-session");
+        *hasher.finalize().as_bytes()
+    }
+
     /// Load SSO configuration from disk
-    pub fn load() -> Result<Self> {
+    pub fn load(identity: &Identity) -> Result<Self> {
         let path = Self::config_path()?;
-        if path.exists() {
-            let data = fs::read_to_string(&path)?;
-            Ok(serde_json::from_str(&data)?)
-        } else {
-            Ok(Self::default())
+        if !path.exists() {
+            return Ok(Self { storage_key: Some(Self::storage_key(identity)), ..Self::default() });
+        }
+
+        let data = fs::read_to_string(&path)?;
+        let legacy_key = Self::legacy_storage_key(identity.public_key());
+        let (config, rewrite) = Self::from_stored(&data, Self::storage_key(identity), Some(&legacy_key))?;
+        if rewrite {
+            // Encrypts tokens from older plaintext files, drops undecryptable ones
+            config.save()?;
+        }
+        Ok(config)
+    }
+
+    /// Parse the stored form and open the sealed session, with `legacy_key`
+    /// too if given; returns whether the file needs rewriting
+    fn from_stored(data: &str, storage_key: Zeroizing<[u8; 32]>, legacy_key: Option<&[u8; 32]>) -> Result<(Self, bool)> {
+        let mut config: Self = serde_json::from_str(data)?;
+        let Some(sealed) = config.sealed_session.take() else {
+            config.storage_key = Some(storage_key);
+            if config.active_session.is_some() {
+                println!("[SSO] Migrating plaintext session tokens to encrypted storage");
+                return Ok((config, true));
+            }
+            return Ok((config, false));
+        };
+
+        let opened = Self::open_session(&sealed, &storage_key);
+        config.storage_key = Some(storage_key);
+        match opened {
+            Ok(session) => {
+                config.active_session = Some(session);
+                Ok((config, false))
+            }
+            Err(e) => {
+                config.active_session = match legacy_key.map(|key| Self::open_session(&sealed, key)) {
+                    Some(Ok(session)) => {
+                        println!("[SSO] Sealing the stored session with the new storage key");
+                        Some(session)
+                    }
+                    _ => {
+                        println!("[SSO] Stored session could not be restored ({}), sign in again", e);
+                        None
+                    }
+                };
+                Ok((config, true))
+            }
         }
     }
 
+    /// Encrypt the session for storage
+    fn seal_session(&self, session: &SsoSession) -> Result<String> {
+        let key = self.storage_key.as_deref().context("No storage key for SSO session")?;
+        let plaintext = serde_json::to_vec(session)?;
+
+        let mut nonce_bytes = [0u8; 12];
+        getrandom::getrandom(&mut nonce_bytes)?;
+
+        let cipher = Aes256Gcm::new_from_slice(key)
+            .map_err(|e| anyhow::anyhow!("Cipher init failed: {}", e))?;
+        let ciphertext = cipher.encrypt(Nonce::from_slice(&nonce_bytes), plaintext.as_ref())
+            .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+
+        let mut output = Vec::with_capacity(12 + ciphertext.len());
+        output.extend_from_slice(&nonce_bytes);
+        output.extend_from_slice(&ciphertext);
+        Ok(URL_SAFE_NO_PAD.encode(output))
+    }
+
+    /// Decrypt a stored session
+    fn open_session(sealed: &str, key: &[u8; 32]) -> Result<SsoSession> {
+        let data = URL_SAFE_NO_PAD.decode(sealed)?;
+        if data.len() < 12 + 16 {
+            anyhow::bail!("Stored session is truncated");
+        }
+
+        let cipher = Aes256Gcm::new_from_slice(key)
+            .map_err(|e| anyhow::anyhow!("Cipher init failed: {}", e))?;
+        let plaintext = cipher.decrypt(Nonce::from_slice(&data[0..12]), &data[12..])
+            .map_err(|_| anyhow::anyhow!("decryption failed - may be from a different device"))?;
+        Ok(serde_json::from_slice(&plaintext)?)
+    }
+
     /// Save SSO configuration to disk
     pub fn save(&self) -> Result<()> {
         let path = Self::config_path()?;
-        let data = serde_json::to_string_pretty(self)?;
-        fs::write(path, data)?;
+        let mut stored = self.clone();
+        stored.sealed_session = match (&self.active_session, &self.storage_key) {
+            (Some(session), Some(_)) => Some(self.seal_session(session)?),
+            _ => None,
+        };
+        let data = serde_json::to_string_pretty(&stored)?;
+
+        // Created private beside it and moved into place, as ipc.json is
+        let temp = path.with_extension("json.tmp");
+        let _ = fs::remove_file(&temp);
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&temp)?;
+        file.write_all(data.as_bytes())?;
+        drop(file);
+        fs::rename(&temp, &path)?;
         Ok(())
     }
 
@@ -376,21 +726,56 @@ impl SsoConfig {
     }
 }
 
+/// Outcome of `SsoManager::refresh_if_needed`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshOutcome {
+    /// No session, or it is not close to expiring yet
+    NotNeeded,
+    /// New tokens were issued
+    Refreshed,
+    /// Refresh failed but the session is still valid; try again on the next check
+    Retry,
+    /// The session expired or was rejected and has been cleared
+    Expired,
+}
+
+/// How long the browser has to come back to the callback server
+const CALLBACK_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// A login started by `start_login` (or a logout), waiting for the browser to return
+/// The callback listener stays bound from the start, so no other process
+/// can take its port before `wait_for_callback` runs
+struct PendingLogin {
+    listener: TcpListener,
+    state: String,
+    pkce: Option<PkceChallenge>,
+    /// Sent in the auth request; the id_token must carry it back
+    nonce: String,
+    started: Instant,
+}
+
 /// SSO Manager handles authentication flow
 pub struct SsoManager {
     config: SsoConfig,
     http_client: reqwest::Client,
+    /// Logins in progress, by redirect URI
+    pending_logins: HashMap<String, PendingLogin>,
+    /// Provider signing keys, by JWKS URI then key ID
+    jwks: HashMap<String, HashMap<String, Jwk>>,
 }
 
 impl SsoManager {
-    /// Create a new SSO manager
-    pub fn new() -> Result<Self> {
-        let config = SsoConfig::load().unwrap_or_default();
-        let http_client = reqwest::Client::builder()
+    /// Create a new SSO manager; the device's secret key protects the stored session
+    pub fn new(identity: &Identity) -> Result<Self> {
+        let config = SsoConfig::load(identity).unwrap_or_else(|e| {
+            eprintln!("[SSO] Failed to load SSO config: {}", e);
+            SsoConfig { storage_key: Some(SsoConfig::storage_key(identity)), ..SsoConfig::default() }
+        });
+        let http_client = crate::proxy::http_client_builder()
             .timeout(Duration::from_secs(30))
             .build()?;
 
-        Ok(Self { config, http_client })
+        Ok(Self { config, http_client, pending_logins: HashMap::new(), jwks: HashMap::new() })
     }
 
     /// Get current configuration
@@ -414,27 +799,24 @@ impl SsoManager {
     }
 
     /// Start SSO login flow
-    /// Returns the authorization URL to open in browser
-    pub fn start_login(&self, provider: &OidcProvider) -> Result<(String, String, Option<PkceChallenge>)> {
-        // Find an available port for the callback server
-        let listener = TcpListener::bind("127.0.0.1:0")?;
-        let port = listener.local_addr()?.port();
-        drop(listener);
+    /// Returns the authorization URL to open in browser, the redirect URI and
+    /// the state to hand back to `wait_for_callback`
+    pub fn start_login(&mut self, provider: &OidcProvider) -> Result<(String, String, String)> {
+        let (listener, redirect_uri, state) = self.bind_callback()?;
 
-        let redirect_uri = format!("http://127.0.0.1:{}/callback", port);
-
-        // Generate state for CSRF protection
-        let mut state_bytes = [0u8; 16];
-        getrandom::getrandom(&mut state_bytes)?;
-        let state = URL_SAFE_NO_PAD.encode(state_bytes);
+        // The nonce ties the id_token to this login (replay protection)
+        let mut nonce_bytes = [0u8; 16];
+        getrandom::getrandom(&mut nonce_bytes)?;
+        let nonce = URL_SAFE_NO_PAD.encode(nonce_bytes);
 
         // Build authorization URL
         let mut auth_url = format!(
-            "{}?client_id={}&redirect_uri={}&response_type=code&state={}&scope={}",
+            "{}?client_id={}&redirect_uri={}&response_type=code&state={}&nonce={}&scope={}",
             provider.authorization_endpoint,
             urlencoding::encode(&provider.client_id),
             urlencoding::encode(&redirect_uri),
             urlencoding::encode(&state),
+            urlencoding::encode(&nonce),
             urlencoding::encode(&provider.scopes.join(" ")),
         );
 
@@ -450,7 +832,35 @@ impl SsoManager {
             None
         };
 
-        Ok((auth_url, redirect_uri, pkce))
+        self.pending_logins.insert(redirect_uri.clone(), PendingLogin {
+            listener,
+            state: state.clone(),
+            pkce,
+            nonce,
+            started: Instant::now(),
+        });
+        Ok((auth_url, redirect_uri, state))
+    }
+
+    /// Bind a callback server for the browser to come back to
+    /// Returns the listener, its redirect URI and a state for CSRF protection
+    fn bind_callback(&mut self) -> Result<(TcpListener, String, String)> {
+        // Logins the browser never came back from
+        self.pending_logins.retain(|_, login| login.started.elapsed() < CALLBACK_TIMEOUT);
+
+        // Bind the callback server now and keep it until the callback arrives
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+
+        let redirect_uri = format!("http://127.0.0.1:{}/callback", port);
+
+        // Generate state for CSRF protection
+        let mut state_bytes = [0u8; 16];
+        getrandom::getrandom(&mut state_bytes)?;
+        let state = URL_SAFE_NO_PAD.encode(state_bytes);
+
+        Ok((listener, redirect_uri, state))
     }
 
     /// Wait for OAuth callback and exchange code for tokens
@@ -459,18 +869,178 @@ impl SsoManager {
         provider: &OidcProvider,
         redirect_uri: &str,
         expected_state: &str,
-        pkce: Option<PkceChallenge>,
     ) -> Result<SsoSession> {
-        // Parse port from redirect URI
-        let port: u16 = redirect_uri
-            .split(':')
-            .last()
-            .and_then(|s| s.split('/').next())
-            .and_then(|s| s.parse().ok())
-            .context("Invalid redirect URI")?;
+        let (code, pkce, nonce) = self.receive_callback(redirect_uri, expected_state).await?;
+
+        // Exchange code for tokens
+        let tokens = self.exchange_code(provider, &code, redirect_uri, pkce).await?;
+
+        self.establish_session(provider, tokens, Some(&nonce)).await
+    }
+
+    /// Start a device login for a host without a browser
+    /// The user opens `verification_uri` on another device and enters `user_code`;
+    /// then `wait_for_device_login` completes it
+    pub async fn start_device_login(&self, provider: &OidcProvider) -> Result<DeviceAuthorization> {
+        let endpoint = provider
+            .device_authorization_endpoint
+            .as_ref()
+            .context("Provider does not support device login")?;
 
-        // Start callback server
-        let listener = AsyncTcpListener::bind(format!("127.0.0.1:{}", port)).await?;
+        let mut params = vec![
+            ("client_id", provider.client_id.clone()),
+            ("scope", provider.scopes.join(" ")),
+        ];
+        if let Some(ref secret) = provider.client_secret {
+            params.push(("client_secret", secret.clone()));
+        }
+
+        let response = self.http_client.post(endpoint).form(&params).send().await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            anyhow::bail!("Device authorization failed: {}", error_text);
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Poll the token endpoint until the device login is approved, denied or expired
+    pub async fn wait_for_device_login(
+        &mut self,
+        provider: &OidcProvider,
+        authorization: &DeviceAuthorization,
+    ) -> Result<SsoSession> {
+        let deadline = Instant::now() + Duration::from_secs(authorization.expires_in);
+        let mut interval = Duration::from_secs(authorization.interval);
+
+        loop {
+            tokio::time::sleep(interval).await;
+            if Instant::now() >= deadline {
+                anyhow::bail!("Device code expired before the login was approved");
+            }
+            match self.poll_device_token(provider, &authorization.device_code).await? {
+                DevicePoll::Tokens(tokens) => return self.establish_session(provider, tokens, None).await,
+                DevicePoll::Pending => {}
+                DevicePoll::SlowDown => interval += DEVICE_SLOW_DOWN,
+            }
+        }
+    }
+
+    /// Ask the token endpoint once whether a device login was approved
+    async fn poll_device_token(&self, provider: &OidcProvider, device_code: &str) -> Result<DevicePoll> {
+        let mut params = vec![
+            ("grant_type", "urn:ietf:params:oauth:grant-type:device_code".to_string()),
+            ("device_code", device_code.to_string()),
+            ("client_id", provider.client_id.clone()),
+        ];
+        if let Some(ref secret) = provider.client_secret {
+            params.push(("client_secret", secret.clone()));
+        }
+
+        let response = self
+            .http_client
+            .post(&provider.token_endpoint)
+            .form(&params)
+            .send()
+            .await?;
+
+        if response.status().is_success() {
+            return Ok(DevicePoll::Tokens(response.json().await?));
+        }
+
+        let error_text = response.text().await.unwrap_or_default();
+        let error: OAuthError = serde_json::from_str(&error_text)
+            .with_context(|| format!("Token request failed: {}", error_text))?;
+        match error.error.as_str() {
+            "authorization_pending" => Ok(DevicePoll::Pending),
+            "slow_down" => Ok(DevicePoll::SlowDown),
+            "access_denied" => anyhow::bail!("Device login was denied"),
+            "expired_token" => anyhow::bail!("Device code expired before the login was approved"),
+            other => anyhow::bail!("OAuth error: {} - {}", other, error.error_description.unwrap_or_default()),
+        }
+    }
+
+    /// Verify the tokens of a finished login and make them the active session
+    /// `nonce` is the one sent in the auth request, if the flow had one
+    async fn establish_session(
+        &mut self,
+        provider: &OidcProvider,
+        tokens: TokenResponse,
+        nonce: Option<&str>,
+    ) -> Result<SsoSession> {
+        // The signed id_token, not userinfo, says who logged in
+        let id_token = tokens.id_token.as_deref().context("Provider returned no id_token")?;
+        let claims = self.verify_id_token(provider, id_token, nonce).await?;
+
+        // Get user info, which must describe the same user
+        let user = if provider.userinfo_endpoint.is_some() {
+            let user = self.get_user_info(provider, &tokens.access_token).await?;
+            if user.sub != claims.user.sub {
+                anyhow::bail!("Userinfo is for a different user than the id_token");
+            }
+            user
+        } else {
+            claims.user
+        };
+
+        // Check domain restriction
+        if let Some(ref email) = user.email {
+            if !self.config.is_domain_allowed(email) {
+                anyhow::bail!("Email domain not allowed: {}", email);
+            }
+        }
+
+        // Calculate expiration
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let expires_at = now + tokens.expires_in.unwrap_or(3600);
+
+        // Create session
+        let session = SsoSession {
+            user,
+            access_token: tokens.access_token,
+            expires_at,
+            refresh_token: tokens.refresh_token,
+            id_token: tokens.id_token,
+            provider: provider.name.clone(),
+        };
+
+        // Save session
+        self.config.set_session(session.clone())?;
+
+        Ok(session)
+    }
+
+    /// Serve the callback of a login from `start_login` on its listener
+    /// Returns the authorization code, the login's PKCE verifier and its nonce
+    async fn receive_callback(
+        &mut self,
+        redirect_uri: &str,
+        expected_state: &str,
+    ) -> Result<(String, Option<PkceChallenge>, String)> {
+        let (params, pkce, nonce) = self.serve_callback(redirect_uri, expected_state, "Authentication Successful").await?;
+
+        // Get authorization code
+        let code = params.get("code").context("Missing authorization code")?;
+        Ok((code.clone(), pkce, nonce))
+    }
+
+    /// Serve one browser return on the listener of a pending login or logout
+    /// and check its state; the page shown says `heading`
+    async fn serve_callback(
+        &mut self,
+        redirect_uri: &str,
+        expected_state: &str,
+        heading: &str,
+    ) -> Result<(HashMap<String, String>, Option<PkceChallenge>, String)> {
+        if self.pending_logins.get(redirect_uri).is_some_and(|login| login.state != expected_state) {
+            anyhow::bail!("State does not match the SSO login in progress");
+        }
+        let login = self.pending_logins.remove(redirect_uri).context("No SSO login in progress for this redirect URI")?;
+        let listener = AsyncTcpListener::from_std(login.listener)?;
 
         // Wait for callback with timeout
         let callback_task = async {
@@ -505,15 +1075,15 @@ impl SsoManager {
                 .collect();
 
             // Send response to browser
-            let response_body = r#"<!DOCTYPE html>
+            let response_body = format!(r#"<!DOCTYPE html>
 <html>
 <head><title>SecureDesk SSO</title></head>
 <body style="font-family: system-ui; text-align: center; padding: 50px;">
-<h1>Authentication Successful</h1>
+<h1>{}</h1>
 <p>You can close this window and return to SecureDesk.</p>
 <script>window.close();</script>
 </body>
-</html>"#;
+</html>"#, heading);
 
             let response = format!(
                 "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
@@ -530,7 +1100,7 @@ impl SsoManager {
         };
 
         // Run callback with timeout
-        let params = tokio::time::timeout(Duration::from_secs(120), callback_task)
+        let params = tokio::time::timeout(CALLBACK_TIMEOUT, callback_task)
             .await
             .context("SSO callback timeout")??;
 
@@ -546,43 +1116,7 @@ impl SsoManager {
             anyhow::bail!("OAuth error: {} - {}", error, desc);
         }
 
-        // Get authorization code
-        let code = params.get("code").context("Missing authorization code")?;
-
-        // Exchange code for tokens
-        let tokens = self.exchange_code(provider, code, redirect_uri, pkce).await?;
-
-        // Get user info
-        let user = self.get_user_info(provider, &tokens.access_token).await?;
-
-        // Check domain restriction
-        if let Some(ref email) = user.email {
-            if !self.config.is_domain_allowed(email) {
-                anyhow::bail!("Email domain not allowed: {}", email);
-            }
-        }
-
-        // Calculate expiration
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        let expires_at = now + tokens.expires_in.unwrap_or(3600);
-
-        // Create session
-        let session = SsoSession {
-            user,
-            access_token: tokens.access_token,
-            expires_at,
-            refresh_token: tokens.refresh_token,
-            id_token: tokens.id_token,
-            provider: provider.name.clone(),
-        };
-
-        // Save session
-        self.config.set_session(session.clone())?;
-
-        Ok(session)
+        Ok((params, login.pkce, login.nonce))
     }
 
     /// Exchange authorization code for tokens
@@ -625,6 +1159,48 @@ impl SsoManager {
         Ok(response.json().await?)
     }
 
+    /// Verify an id_token against the provider's JWKS
+    /// Keys are cached; an unknown key ID refetches the set, since providers
+    /// rotate their signing keys
+    async fn verify_id_token(&mut self, provider: &OidcProvider, id_token: &str, nonce: Option<&str>) -> Result<IdTokenClaims> {
+        let jwks_uri = provider.jwks_uri.as_ref().context("Provider has no JWKS URI to verify the id_token")?;
+        let kid = jwt_header(id_token)?.kid.unwrap_or_default();
+
+        if !self.jwks.get(jwks_uri).is_some_and(|keys| keys.contains_key(&kid)) {
+            let keys = self.fetch_jwks(jwks_uri).await?;
+            self.jwks.insert(jwks_uri.clone(), keys);
+        }
+        let key = self
+            .jwks
+            .get(jwks_uri)
+            .and_then(|keys| keys.get(&kid))
+            .context("id_token is signed with a key the provider does not publish")?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        verify_jwt(id_token, key, provider, nonce, now)
+    }
+
+    /// Fetch a provider's signing keys, by key ID
+    async fn fetch_jwks(&self, jwks_uri: &str) -> Result<HashMap<String, Jwk>> {
+        let response = self.http_client.get(jwks_uri).send().await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await.unwrap_or_default();
+            anyhow::bail!("Failed to fetch JWKS: {}", error_text);
+        }
+
+        let set: JwkSet = response.json().await?;
+        Ok(set
+            .keys
+            .into_iter()
+            .filter(|key| key.key_use.as_deref() != Some("enc"))
+            .map(|key| (key.kid.clone().unwrap_or_default(), key))
+            .collect())
+    }
+
     /// Get user info from identity provider
     async fn get_user_info(&self, provider: &OidcProvider, access_token: &str) -> Result<UserInfo> {
         let userinfo_url = provider
@@ -713,9 +1289,71 @@ impl SsoManager {
         Ok(new_session)
     }
 
+    /// Refresh the session if it is about to expire (see `SsoSession::needs_refresh`)
+    /// A network error before expiry is retried later; a rejected refresh, or a
+    /// session that ran out without a refresh token, clears the session
+    pub async fn refresh_if_needed(&mut self) -> RefreshOutcome {
+        let Some(session) = self.config.active_session.as_ref() else {
+            return RefreshOutcome::NotNeeded;
+        };
+        if !session.needs_refresh() {
+            return RefreshOutcome::NotNeeded;
+        }
+
+        if session.refresh_token.is_some() {
+            match self.refresh_session().await {
+                Ok(_) => return RefreshOutcome::Refreshed,
+                Err(e) => eprintln!("[SSO] Session refresh failed: {}", e),
+            }
+        }
+
+        // refresh_session already cleared the session if the provider rejected it
+        if self.current_session().is_some() {
+            return RefreshOutcome::Retry;
+        }
+        if let Err(e) = self.config.clear_session() {
+            eprintln!("[SSO] Failed to clear expired session: {}", e);
+        }
+        RefreshOutcome::Expired
+    }
+
     /// Logout and clear session
-    pub fn logout(&mut self) -> Result<()> {
-        self.config.clear_session()
+    /// The local session is always cleared; when its provider has an
+    /// end-session endpoint, returns the logout URL to open in the browser,
+    /// the redirect URI and the state to hand to `wait_for_logout`, so the
+    /// provider's own session ends too
+    pub fn logout(&mut self) -> Result<Option<(String, String, String)>> {
+        let session = self.config.active_session.clone();
+        self.config.clear_session()?;
+
+        let Some(session) = session else {
+            return Ok(None);
+        };
+        let Some(provider) = self.config.get_provider(&session.provider).cloned() else {
+            return Ok(None);
+        };
+        if provider.end_session_endpoint.is_none() {
+            return Ok(None);
+        }
+
+        let (listener, redirect_uri, state) = self.bind_callback()?;
+        let Some(logout_url) = end_session_url(&provider, &session, &redirect_uri, &state) else {
+            return Ok(None);
+        };
+        self.pending_logins.insert(redirect_uri.clone(), PendingLogin {
+            listener,
+            state: state.clone(),
+            pkce: None,
+            nonce: String::new(),
+            started: Instant::now(),
+        });
+        Ok(Some((logout_url, redirect_uri, state)))
+    }
+
+    /// Wait for the browser to come back from the provider's logout page
+    pub async fn wait_for_logout(&mut self, redirect_uri: &str, expected_state: &str) -> Result<()> {
+        self.serve_callback(redirect_uri, expected_state, "Signed Out").await?;
+        Ok(())
     }
 
     /// Configure a new provider
@@ -854,6 +1492,388 @@ mod tests {
         assert!(config.is_domain_allowed("user@any.com"));
     }
 
+    #[test]
+    fn test_session_stored_encrypted() {
+        let session = SsoSession {
+            user: serde_json::from_str(r#"{"sub":"user-1","email":"user@example.com"}"#).unwrap(),
+            access_token: "secret-access".to_string(),
+            expires_at: 4_000_000_000,
+            refresh_token: Some("secret-refresh".to_string()),
+            id_token: None,
+            provider: "Okta".to_string(),
+        };
+        let identity = Identity::generate();
+        let key = SsoConfig::storage_key(&identity);
+
+        // Older plaintext files load and are flagged for rewriting
+        let plaintext = r#"{"providers":[],"active_session":"#.to_string()
+            + &serde_json::to_string(&session).unwrap() + "}";
+        let (migrated, rewrite) = SsoConfig::from_stored(&plaintext, key.clone(), None).unwrap();
+        assert!(rewrite);
+        assert_eq!(migrated.active_session.as_ref().unwrap().access_token, "secret-access");
+
+        // What gets written carries no tokens in the clear
+        let mut stored = migrated.clone();
+        stored.sealed_session = Some(migrated.seal_session(&session).unwrap());
+        let written = serde_json::to_string(&stored).unwrap();
+        assert!(!written.contains("secret-access"));
+        assert!(!written.contains("secret-refresh"));
+
+        let (restored, rewrite) = SsoConfig::from_stored(&written, key.clone(), None).unwrap();
+        assert!(!rewrite);
+        assert_eq!(restored.active_session.unwrap().refresh_token.as_deref(), Some("secret-refresh"));
+
+        // The public key alone opens nothing
+        let public_key = SsoConfig::legacy_storage_key(identity.public_key());
+        assert!(SsoConfig::open_session(stored.sealed_session.as_ref().unwrap(), &public_key).is_err());
+
+        // On another device the session is dropped, the providers are kept
+        let other = SsoConfig::storage_key(&Identity::generate());
+        let (copied, rewrite) = SsoConfig::from_stored(&written, other, None).unwrap();
+        assert!(rewrite);
+        assert!(copied.active_session.is_none());
+
+        // A session sealed under the old public-key derivation is re-sealed
+        let legacy = SsoConfig { storage_key: Some(Zeroizing::new(public_key)), ..migrated.clone() };
+        let mut old_file = migrated.clone();
+        old_file.sealed_session = Some(legacy.seal_session(&session).unwrap());
+        let old_file = serde_json::to_string(&old_file).unwrap();
+        let (upgraded, rewrite) = SsoConfig::from_stored(&old_file, key, Some(&public_key)).unwrap();
+        assert!(rewrite);
+        assert_eq!(upgraded.active_session.unwrap().access_token, "secret-access");
+    }
+
+    #[test]
+    fn test_back_to_back_logins_keep_their_ports() {
+        let mut manager = SsoManager {
+            config: SsoConfig::default(),
+            http_client: reqwest::Client::new(),
+            pending_logins: HashMap::new(),
+            jwks: HashMap::new(),
+        };
+        let provider = OidcProvider::okta("dev-12345.okta.com", "test-client");
+        let (first_url, first_uri, first_state) = manager.start_login(&provider).unwrap();
+        let (_, second_uri, second_state) = manager.start_login(&provider).unwrap();
+        assert_ne!(first_uri, second_uri);
+        assert!(first_url.contains(&urlencoding::encode(&first_state)));
+        assert!(first_url.contains("code_challenge="));
+
+        // Both ports stay taken while their logins are pending
+        for uri in [&first_uri, &second_uri] {
+            let port = uri.trim_start_matches("http://127.0.0.1:").trim_end_matches("/callback");
+            assert!(TcpListener::bind(format!("127.0.0.1:{}", port)).is_err());
+        }
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            // A wrong state leaves the login in place
+            assert!(manager.receive_callback(&first_uri, "forged").await.is_err());
+
+            // The second login completes first, then the first
+            for (uri, state, code) in [(&second_uri, &second_state, "code-2"), (&first_uri, &first_state, "code-1")] {
+                let address = uri.trim_start_matches("http://").trim_end_matches("/callback").to_string();
+                let request = format!("GET /callback?code={}&state={} HTTP/1.1\r\n\r\n", code, urlencoding::encode(state));
+                let browser = tokio::spawn(async move {
+                    let mut socket = tokio::net::TcpStream::connect(address).await.unwrap();
+                    socket.write_all(request.as_bytes()).await.unwrap();
+                    let mut response = String::new();
+                    tokio::io::AsyncReadExt::read_to_string(&mut socket, &mut response).await.unwrap();
+                    response
+                });
+
+                let (received, pkce, _) = manager.receive_callback(uri, state).await.unwrap();
+                assert_eq!(received, code);
+                assert!(pkce.is_some());
+                assert!(browser.await.unwrap().starts_with("HTTP/1.1 200 OK"));
+            }
+        });
+        assert!(manager.pending_logins.is_empty());
+    }
+
+    /// Answer one HTTP request with `reply` as JSON; returns the request body
+    async fn serve_json_once(listener: AsyncTcpListener, reply: String) -> String {
+        serve_once(listener, "200 OK", reply).await
+    }
+
+    async fn serve_once(listener: AsyncTcpListener, status: &str, reply: String) -> String {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = tokio::io::AsyncReadExt::read(&mut socket, &mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head.lines()
+                    .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    let response = format!(
+                        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                        status,
+                        reply.len(),
+                        reply
+                    );
+                    socket.write_all(response.as_bytes()).await.unwrap();
+                    return body.to_string();
+                }
+            }
+        }
+    }
+
+    /// A P-256 signing key and its JWKS entry
+    fn es256_key(kid: &str) -> (ring::signature::EcdsaKeyPair, Jwk) {
+        use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng).unwrap();
+        let point = pair.public_key().as_ref().to_vec();
+        let jwk = Jwk {
+            kid: Some(kid.to_string()),
+            kty: "EC".to_string(),
+            key_use: Some("sig".to_string()),
+            n: None,
+            e: None,
+            crv: Some("P-256".to_string()),
+            x: Some(URL_SAFE_NO_PAD.encode(&point[1..33])),
+            y: Some(URL_SAFE_NO_PAD.encode(&point[33..])),
+        };
+        (pair, jwk)
+    }
+
+    fn sign_id_token(pair: &ring::signature::EcdsaKeyPair, header: serde_json::Value, claims: serde_json::Value) -> String {
+        let input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(header.to_string()),
+            URL_SAFE_NO_PAD.encode(claims.to_string())
+        );
+        let sig = pair.sign(&ring::rand::SystemRandom::new(), input.as_bytes()).unwrap();
+        format!("{}.{}", input, URL_SAFE_NO_PAD.encode(sig.as_ref()))
+    }
+
+    #[test]
+    fn test_id_token_verification() {
+        let provider = OidcProvider::okta("dev-12345.okta.com", "test-client");
+        let (pair, jwk) = es256_key("key-1");
+        let now = 1_700_000_000;
+        let header = serde_json::json!({ "alg": "ES256", "kid": "key-1" });
+        let claims = serde_json::json!({
+            "iss": "https://dev-12345.okta.com",
+            "aud": "test-client",
+            "sub": "user-1",
+            "email": "alice@company.com",
+            "exp": now + 300,
+            "nbf": now - 10,
+            "nonce": "nonce-1",
+        });
+        let token = sign_id_token(&pair, header.clone(), claims.clone());
+        let verified = verify_jwt(&token, &jwk, &provider, Some("nonce-1"), now).unwrap();
+        assert_eq!(verified.user.sub, "user-1");
+        assert_eq!(verified.user.email.as_deref(), Some("alice@company.com"));
+
+        // A replayed login, or a token that has run out, is refused
+        assert!(verify_jwt(&token, &jwk, &provider, Some("nonce-2"), now).is_err());
+        assert!(verify_jwt(&token, &jwk, &provider, Some("nonce-1"), now + 300 + ID_TOKEN_LEEWAY).is_err());
+        assert!(verify_jwt(&token, &jwk, &provider, Some("nonce-1"), now - 200).is_err());
+
+        // Claims for another client or issuer, even correctly signed
+        for (claim, value) in [("aud", serde_json::json!(["other-client"])), ("iss", serde_json::json!("https://evil.example"))] {
+            let mut other = claims.clone();
+            other[claim] = value;
+            let token = sign_id_token(&pair, header.clone(), other);
+            assert!(verify_jwt(&token, &jwk, &provider, Some("nonce-1"), now).is_err());
+        }
+
+        // Edited claims, another key, or an unsigned token
+        let (parts, sig) = token.rsplit_once('.').unwrap();
+        let (head, _) = parts.split_once('.').unwrap();
+        let mut edited = claims.clone();
+        edited["sub"] = serde_json::json!("admin");
+        let forged = format!("{}.{}.{}", head, URL_SAFE_NO_PAD.encode(edited.to_string()), sig);
+        assert!(verify_jwt(&forged, &jwk, &provider, Some("nonce-1"), now).is_err());
+        let (_, other_jwk) = es256_key("key-1");
+        assert!(verify_jwt(&token, &other_jwk, &provider, Some("nonce-1"), now).is_err());
+        let unsigned = format!("{}.{}.", URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#), URL_SAFE_NO_PAD.encode(claims.to_string()));
+        assert!(verify_jwt(&unsigned, &jwk, &provider, Some("nonce-1"), now).is_err());
+    }
+
+    #[test]
+    fn test_unknown_key_id_refetches_jwks() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let jwks_server = AsyncTcpListener::bind("127.0.0.1:0").await.unwrap();
+            let mut provider = OidcProvider::okta("dev-12345.okta.com", "test-client");
+            let jwks_uri = format!("http://{}/keys", jwks_server.local_addr().unwrap());
+            provider.jwks_uri = Some(jwks_uri.clone());
+
+            // The cached set predates the provider's key rotation
+            let (_, old_jwk) = es256_key("old");
+            let (pair, new_jwk) = es256_key("new");
+            let published = serde_json::json!({ "keys": [{
+                "kid": "new", "kty": "EC", "use": "sig", "crv": "P-256", "x": new_jwk.x, "y": new_jwk.y,
+            }] });
+            let served = tokio::spawn(serve_json_once(jwks_server, published.to_string()));
+            let mut manager = SsoManager {
+                config: SsoConfig::default(),
+                http_client: reqwest::Client::new(),
+                pending_logins: HashMap::new(),
+                jwks: HashMap::from([(jwks_uri.clone(), HashMap::from([("old".to_string(), old_jwk)]))]),
+            };
+
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+            let token = sign_id_token(
+                &pair,
+                serde_json::json!({ "alg": "ES256", "kid": "new" }),
+                serde_json::json!({ "iss": provider.issuer, "aud": "test-client", "sub": "user-1", "exp": now + 300 }),
+            );
+            let claims = manager.verify_id_token(&provider, &token, None).await.unwrap();
+            assert_eq!(claims.user.sub, "user-1");
+            served.await.unwrap();
+            assert!(manager.jwks[&jwks_uri].contains_key("new"));
+
+            // Known now, so no second fetch is needed
+            assert!(manager.verify_id_token(&provider, &token, None).await.is_ok());
+        });
+    }
+
+    #[test]
+    fn test_pkce_verifier_reaches_token_exchange() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            // Stand-in token endpoint that keeps the form it was sent
+            let token_server = AsyncTcpListener::bind("127.0.0.1:0").await.unwrap();
+            let mut provider = OidcProvider::okta("dev-12345.okta.com", "test-client");
+            provider.token_endpoint = format!("http://{}/token", token_server.local_addr().unwrap());
+            let token_request = tokio::spawn(serve_json_once(token_server, r#"{"access_token":"token"}"#.to_string()));
+
+            let mut manager = SsoManager {
+                config: SsoConfig::default(),
+                http_client: reqwest::Client::new(),
+                pending_logins: HashMap::new(),
+                jwks: HashMap::new(),
+            };
+            let (auth_url, uri, state) = manager.start_login(&provider).unwrap();
+            let challenge = auth_url.split("code_challenge=").nth(1).unwrap().split('&').next().unwrap().to_string();
+
+            let address = uri.trim_start_matches("http://").trim_end_matches("/callback").to_string();
+            let request = format!("GET /callback?code=code-1&state={} HTTP/1.1\r\n\r\n", urlencoding::encode(&state));
+            tokio::spawn(async move {
+                let mut socket = tokio::net::TcpStream::connect(address).await.unwrap();
+                socket.write_all(request.as_bytes()).await.unwrap();
+                let mut response = String::new();
+                let _ = tokio::io::AsyncReadExt::read_to_string(&mut socket, &mut response).await;
+            });
+            let (code, pkce, _) = manager.receive_callback(&uri, &state).await.unwrap();
+            let tokens = manager.exchange_code(&provider, &code, &uri, pkce).await.unwrap();
+            assert_eq!(tokens.access_token, "token");
+
+            // The verifier sent is the one whose S256 challenge went to the browser
+            let form = token_request.await.unwrap();
+            let verifier = form.split('&')
+                .find_map(|pair| pair.strip_prefix("code_verifier="))
+                .map(|v| urlencoding::decode(v).unwrap())
+                .unwrap();
+            assert_eq!(URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes())), urlencoding::decode(&challenge).unwrap());
+        });
+    }
+
+    #[test]
+    fn test_logout_ends_provider_session() {
+        let provider = OidcProvider::okta("dev-12345.okta.com", "test-client");
+        let session = SsoSession {
+            user: serde_json::from_str(r#"{"sub":"user-1"}"#).unwrap(),
+            access_token: "access".to_string(),
+            expires_at: 4_000_000_000,
+            refresh_token: None,
+            id_token: Some("header.claims.sig".to_string()),
+            provider: "Okta".to_string(),
+        };
+        let mut manager = SsoManager {
+            config: SsoConfig::default(),
+            http_client: reqwest::Client::new(),
+            pending_logins: HashMap::new(),
+            jwks: HashMap::new(),
+        };
+        let (listener, uri, state) = manager.bind_callback().unwrap();
+        let url = end_session_url(&provider, &session, &uri, &state).unwrap();
+        assert!(url.starts_with("https://dev-12345.okta.com/oauth2/v1/logout?"));
+        assert!(url.contains("id_token_hint=header.claims.sig"));
+        assert!(url.contains(&format!("post_logout_redirect_uri={}", urlencoding::encode(&uri))));
+
+        // Providers without an end-session endpoint only log out locally
+        assert!(end_session_url(&OidcProvider::google("test-client", "secret"), &session, &uri, &state).is_none());
+
+        // The provider sends the browser back to the callback server
+        manager.pending_logins.insert(uri.clone(), PendingLogin {
+            listener,
+            state: state.clone(),
+            pkce: None,
+            nonce: String::new(),
+            started: Instant::now(),
+        });
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let address = uri.trim_start_matches("http://").trim_end_matches("/callback").to_string();
+            let request = format!("GET /callback?state={} HTTP/1.1\r\n\r\n", urlencoding::encode(&state));
+            let browser = tokio::spawn(async move {
+                let mut socket = tokio::net::TcpStream::connect(address).await.unwrap();
+                socket.write_all(request.as_bytes()).await.unwrap();
+                let mut response = String::new();
+                tokio::io::AsyncReadExt::read_to_string(&mut socket, &mut response).await.unwrap();
+                response
+            });
+            manager.wait_for_logout(&uri, &state).await.unwrap();
+            assert!(browser.await.unwrap().contains("Signed Out"));
+        });
+        assert!(manager.pending_logins.is_empty());
+    }
+
+    #[test]
+    fn test_device_login_polling() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let mut provider = OidcProvider::okta("dev-12345.okta.com", "test-client");
+            let manager = SsoManager {
+                config: SsoConfig::default(),
+                http_client: reqwest::Client::new(),
+                pending_logins: HashMap::new(),
+                jwks: HashMap::new(),
+            };
+
+            // The code and where to enter it come back for the CLI to print
+            let server = AsyncTcpListener::bind("127.0.0.1:0").await.unwrap();
+            provider.device_authorization_endpoint = Some(format!("http://{}/device", server.local_addr().unwrap()));
+            let reply = r#"{"device_code":"dev-code","user_code":"WDJB-MJHT","verification_url":"https://example.com/device","expires_in":600}"#;
+            let request = tokio::spawn(serve_json_once(server, reply.to_string()));
+            let authorization = manager.start_device_login(&provider).await.unwrap();
+            assert_eq!(authorization.user_code, "WDJB-MJHT");
+            assert_eq!(authorization.verification_uri, "https://example.com/device");
+            assert_eq!(authorization.interval, 5);
+            let form = request.await.unwrap();
+            assert!(form.contains("client_id=test-client") && form.contains("scope=openid"));
+
+            // Each token endpoint answer while polling
+            for (status, reply) in [
+                ("400 Bad Request", r#"{"error":"authorization_pending"}"#),
+                ("400 Bad Request", r#"{"error":"slow_down"}"#),
+                ("400 Bad Request", r#"{"error":"access_denied"}"#),
+                ("200 OK", r#"{"access_token":"token","id_token":"header.claims.sig"}"#),
+            ] {
+                let server = AsyncTcpListener::bind("127.0.0.1:0").await.unwrap();
+                provider.token_endpoint = format!("http://{}/token", server.local_addr().unwrap());
+                let request = tokio::spawn(serve_once(server, status, reply.to_string()));
+                let poll = manager.poll_device_token(&provider, &authorization.device_code).await;
+                assert!(request.await.unwrap().contains("device_code=dev-code"));
+                match reply {
+                    r if r.contains("authorization_pending") => assert!(matches!(poll, Ok(DevicePoll::Pending))),
+                    r if r.contains("slow_down") => assert!(matches!(poll, Ok(DevicePoll::SlowDown))),
+                    r if r.contains("access_denied") => assert!(poll.is_err()),
+                    _ => assert!(matches!(poll, Ok(DevicePoll::Tokens(t)) if t.access_token == "token")),
+                }
+            }
+        });
+    }
+
     #[test]
     fn test_urlencoding() {
         assert_eq!(urlencoding::encode("hello world"), "hello%20world");
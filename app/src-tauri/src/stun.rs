@@ -2,7 +2,8 @@
 
 use anyhow::Result;
 use std::net::{SocketAddr, UdpSocket, ToSocketAddrs};
-use std::time::Duration;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 /// STUN message types
 const STUN_BINDING_REQUEST: u16 = 0x0001;
@@ -15,22 +16,95 @@ const STUN_ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
 /// STUN magic cookie (RFC 5389)
 const STUN_MAGIC_COOKIE: u32 = 0x2112A442;
 
-/// Public STUN servers for address discovery
-const STUN_SERVERS: &[&str] = &[
+/// Default public STUN servers for address discovery
+pub const DEFAULT_STUN_SERVERS: &[&str] = &[
     "stun.l.google.com:19302",
     "stun1.l.google.com:19302",
     "stun2.l.google.com:19302",
     "stun.cloudflare.com:3478",
 ];
 
+/// How long a discovered public address is reused before querying again
+const PUBLIC_ADDR_TTL: Duration = Duration::from_secs(120);
+
+/// Configured servers and the last discovered address
+struct StunState {
+    enabled: bool,
+    /// None = use DEFAULT_STUN_SERVERS
+    servers: Option<Vec<String>>,
+    cached: Option<(SocketAddr, Instant)>,
+}
+
+impl StunState {
+    /// Cached public address, if still within its TTL
+    fn fresh_cached(&self, now: Instant) -> Option<SocketAddr> {
+        self.cached
+            .filter(|(_, at)| now.duration_since(*at) < PUBLIC_ADDR_TTL)
+            .map(|(addr, _)| addr)
+    }
+}
+
+static STUN_STATE: Mutex<StunState> = Mutex::new(StunState {
+    enabled: true,
+    servers: None,
+    cached: None,
+});
+
+/// Default STUN server list as owned strings (for config defaults)
+pub fn default_servers() -> Vec<String> {
+    DEFAULT_STUN_SERVERS.iter().map(|s| s.to_string()).collect()
+}
+
+/// Set whether STUN is used and which servers to query
+/// Clears the cached address so the next discovery uses the new settings
+pub fn configure(enabled: bool, servers: &[String]) {
+    let mut state = STUN_STATE.lock().unwrap_or_else(|e| e.into_inner());
+    state.enabled = enabled;
+    state.servers = Some(servers.to_vec());
+    state.cached = None;
+}
+
+/// Check that a STUN server entry is a host:port that resolves
+pub fn validate_server(server: &str) -> Result<()> {
+    let resolved = server
+        .to_socket_addrs()
+        .map_err(|e| anyhow::anyhow!("Invalid STUN server '{}': {}", server, e))?
+        .next();
+    if resolved.is_none() {
+        anyhow::bail!("STUN server '{}' did not resolve", server);
+    }
+    Ok(())
+}
+
 /// Discover public IP address using STUN
-/// Returns the public address as seen by STUN servers
+/// Returns the public address as seen by STUN servers, or None when STUN is
+/// disabled or no server answered. Results are cached for PUBLIC_ADDR_TTL.
 pub fn discover_public_address() -> Result<Option<SocketAddr>> {
+    let servers = {
+        let state = STUN_STATE.lock().unwrap_or_else(|e| e.into_inner());
+        if !state.enabled {
+            println!("[STUN] STUN disabled, skipping public address discovery");
+            return Ok(None);
+        }
+        // STUN is UDP, which neither proxy kind carries here; querying around the
+        // proxy would fail on locked-down networks and leak traffic past it
+        if let Some(proxy) = crate::proxy::current() {
+            println!("[STUN] Proxy {} in use, skipping public address discovery", proxy.display_url());
+            return Ok(None);
+        }
+        if let Some(addr) = state.fresh_cached(Instant::now()) {
+            println!("[STUN] Using cached public address: {}", addr);
+            return Ok(Some(addr));
+        }
+        state.servers.clone().unwrap_or_else(default_servers)
+    };
+
     // Try each STUN server until one works
-    for server in STUN_SERVERS {
+    for server in &servers {
         match query_stun_server(server) {
             Ok(addr) => {
                 println!("[STUN] Discovered public address: {} via {}", addr, server);
+                STUN_STATE.lock().unwrap_or_else(|e| e.into_inner()).cached = Some((addr, Instant::now()));
                 return Ok(Some(addr));
             }
             Err(e) => {
@@ -52,13 +126,15 @@ fn query_stun_server(server: &str) -> Result<SocketAddr> {
         .next()
         .ok_or_else(|| anyhow::anyhow!("Failed to resolve STUN server"))?;
 
-    // Create UDP socket
-    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    // Create UDP socket of the server's family (IPv6-only networks have no IPv4 route)
+    let bind_addr = if server_addr.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+    let socket = UdpSocket::bind(bind_addr)?;
     socket.set_read_timeout(Some(Duration::from_secs(3)))?;
     socket.set_write_timeout(Some(Duration::from_secs(3)))?;
 
     // Build STUN binding request
-    let request = build_binding_request();
+    let transaction_id: [u8; 12] = rand::random();
+    let request = build_binding_request(&transaction_id);
 
     // Send request
     socket.send_to(&request, server_addr)?;
@@ -68,11 +144,11 @@ fn query_stun_server(server: &str) -> Result<SocketAddr> {
     let (len, _) = socket.recv_from(&mut buf)?;
 
     // Parse response
-    parse_binding_response(&buf[..len])
+    parse_binding_response(&buf[..len], &transaction_id)
 }
 
 /// Build a STUN binding request
-fn build_binding_request() -> Vec<u8> {
+fn build_binding_request(transaction_id: &[u8; 12]) -> Vec<u8> {
     let mut request = Vec::with_capacity(20);
 
     // Message type (Binding Request)
@@ -85,14 +161,14 @@ fn build_binding_request() -> Vec<u8> {
     request.extend_from_slice(&STUN_MAGIC_COOKIE.to_be_bytes());
 
     // Transaction ID (12 random bytes)
-    let transaction_id: [u8; 12] = rand::random();
-    request.extend_from_slice(&transaction_id);
+    request.extend_from_slice(transaction_id);
 
     request
 }
 
-/// Parse a STUN binding response and extract the mapped address
-fn parse_binding_response(data: &[u8]) -> Result<SocketAddr> {
+/// Parse the STUN binding response to the request with `transaction_id` and
+/// extract the mapped address
+fn parse_binding_response(data: &[u8], transaction_id: &[u8; 12]) -> Result<SocketAddr> {
     if data.len() < 20 {
         anyhow::bail!("STUN response too short");
     }
@@ -103,6 +179,11 @@ fn parse_binding_response(data: &[u8]) -> Result<SocketAddr> {
         anyhow::bail!("Not a binding response: 0x{:04x}", msg_type);
     }
 
+    // A late answer to an earlier request, or not meant for us
+    if data[8..20] != transaction_id[..] {
+        anyhow::bail!("STUN response for another transaction");
+    }
+
     // Get message length
     let msg_len = u16::from_be_bytes([data[2], data[3]]) as usize;
     if data.len() < 20 + msg_len {
@@ -122,7 +203,7 @@ fn parse_binding_response(data: &[u8]) -> Result<SocketAddr> {
 
         match attr_type {
             STUN_ATTR_XOR_MAPPED_ADDRESS => {
-                return parse_xor_mapped_address(&data[pos..pos + attr_len]);
+                return parse_xor_mapped_address(&data[pos..pos + attr_len], transaction_id);
             }
             STUN_ATTR_MAPPED_ADDRESS => {
                 return parse_mapped_address(&data[pos..pos + attr_len]);
@@ -137,8 +218,8 @@ fn parse_binding_response(data: &[u8]) -> Result<SocketAddr> {
     anyhow::bail!("No mapped address in STUN response")
 }
 
-/// Parse XOR-MAPPED-ADDRESS attribute
-fn parse_xor_mapped_address(data: &[u8]) -> Result<SocketAddr> {
+/// Parse XOR-MAPPED-ADDRESS attribute (RFC 5389 §15.2)
+fn parse_xor_mapped_address(data: &[u8], transaction_id: &[u8; 12]) -> Result<SocketAddr> {
     if data.len() < 8 {
         anyhow::bail!("XOR-MAPPED-ADDRESS too short");
     }
@@ -161,8 +242,16 @@ fn parse_xor_mapped_address(data: &[u8]) -> Result<SocketAddr> {
             if data.len() < 20 {
                 anyhow::bail!("XOR-MAPPED-ADDRESS IPv6 too short");
             }
-            // XOR with magic cookie + transaction ID (we don't have it here, so use MAPPED-ADDRESS fallback)
-            anyhow::bail!("IPv6 XOR-MAPPED-ADDRESS not implemented")
+            // XOR with the magic cookie followed by the transaction ID
+            let mut key = [0u8; 16];
+            key[..4].copy_from_slice(&STUN_MAGIC_COOKIE.to_be_bytes());
+            key[4..].copy_from_slice(transaction_id);
+            let mut octets = [0u8; 16];
+            for (i, octet) in octets.iter_mut().enumerate() {
+                *octet = data[4 + i] ^ key[i];
+            }
+            let ip_addr = std::net::Ipv6Addr::from(octets);
+            Ok(SocketAddr::V6(std::net::SocketAddrV6::new(ip_addr, port, 0, 0)))
         }
         _ => anyhow::bail!("Unknown address family: {}", family),
     }
@@ -223,9 +312,58 @@ mod tests {
 
     #[test]
     fn test_build_binding_request() {
-        let request = build_binding_request();
+        let request = build_binding_request(&[7; 12]);
         assert_eq!(request.len(), 20);
         assert_eq!(request[0], 0x00);
         assert_eq!(request[1], 0x01); // Binding request
+        assert_eq!(request[8..20], [7; 12]);
+    }
+
+    #[test]
+    fn test_ipv6_xor_mapped_address() {
+        // IPv6 binding response test vector from RFC 5769 §2.3
+        let response = [
+            0x01, 0x01, 0x00, 0x48, 0x21, 0x12, 0xa4, 0x42,
+            0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34, 0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae,
+            // SOFTWARE "test vector", padded
+            0x80, 0x22, 0x00, 0x0b, 0x74, 0x65, 0x73, 0x74,
+            0x20, 0x76, 0x65, 0x63, 0x74, 0x6f, 0x72, 0x20,
+            // XOR-MAPPED-ADDRESS
+            0x00, 0x20, 0x00, 0x14, 0x00, 0x02, 0xa1, 0x47,
+            0x01, 0x13, 0xa9, 0xfa, 0xa5, 0xd3, 0xf1, 0x79,
+            0xbc, 0x25, 0xf4, 0xb5, 0xbe, 0xd2, 0xb9, 0xd9,
+            // MESSAGE-INTEGRITY
+            0x00, 0x08, 0x00, 0x14, 0xa3, 0x82, 0x95, 0x4e,
+            0x4b, 0xe6, 0x7b, 0xf1, 0x17, 0x84, 0xc9, 0x7c,
+            0x82, 0x92, 0xc2, 0x75, 0xbf, 0xe3, 0xed, 0x41,
+            // FINGERPRINT
+            0x80, 0x28, 0x00, 0x04, 0xc8, 0xfb, 0x0b, 0x4c,
+        ];
+        let transaction_id: [u8; 12] = response[8..20].try_into().unwrap();
+
+        let addr = parse_binding_response(&response, &transaction_id).unwrap();
+        assert_eq!(addr, "[2001:db8:1234:5678:11:2233:4455:6677]:32853".parse().unwrap());
+
+        // Answers to another request are not ours
+        assert!(parse_binding_response(&response, &[0; 12]).is_err());
+    }
+
+    #[test]
+    fn test_validate_server() {
+        assert!(validate_server("127.0.0.1:3478").is_ok());
+        assert!(validate_server("127.0.0.1").is_err()); // no port
+        assert!(validate_server("").is_err());
+    }
+
+    #[test]
+    fn test_cached_address_expires() {
+        let now = Instant::now();
+        let addr: SocketAddr = "203.0.113.5:40000".parse().unwrap();
+        let mut state = StunState { enabled: true, servers: None, cached: Some((addr, now)) };
+        assert_eq!(state.fresh_cached(now), Some(addr));
+        assert_eq!(state.fresh_cached(now + PUBLIC_ADDR_TTL), None);
+
+        state.cached = None;
+        assert_eq!(state.fresh_cached(now), None);
     }
 }
@@ -0,0 +1,430 @@
+//! Dirty-region video frames - only the parts of the screen that changed,
+//! each as its own JPEG, patched onto the viewer's copy of the last frame
+
+#![allow(dead_code)]
+
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::protocol;
+
+/// Tiles are aligned to JPEG's 16x16 macroblocks, so their edges do not show
+const TILE_ALIGN: u32 = 16;
+
+/// Above this share of the frame a keyframe is cheaper than tiles
+const MAX_TILED_SHARE: f64 = 0.5;
+
+/// Most tiles in one frame; more are merged into their bounding box
+pub const MAX_TILES: usize = 64;
+
+/// Captured frames whose damage is remembered for viewers that fell behind
+const DAMAGE_HISTORY: usize = 120;
+
+/// An area of the frame, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// From edges that may lie partly outside a `width` x `height` frame; None if nothing is left
+    pub fn clipped(left: i64, top: i64, right: i64, bottom: i64, width: u32, height: u32) -> Option<Self> {
+        let (left, top) = (left.max(0), top.max(0));
+        let (right, bottom) = (right.min(width as i64), bottom.min(height as i64));
+        (right > left && bottom > top).then(|| Self {
+            x: left as u32,
+            y: top as u32,
+            width: (right - left) as u32,
+            height: (bottom - top) as u32,
+        })
+    }
+
+    fn right(&self) -> u32 {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> u32 {
+        self.y + self.height
+    }
+
+    fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    fn overlaps(&self, other: &Self) -> bool {
+        self.x < other.right() && other.x < self.right() && self.y < other.bottom() && other.y < self.bottom()
+    }
+
+    fn union(&self, other: &Self) -> Self {
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        Self {
+            x,
+            y,
+            width: self.right().max(other.right()) - x,
+            height: self.bottom().max(other.bottom()) - y,
+        }
+    }
+
+    /// Grown outwards to the tile grid, within the frame
+    fn aligned(&self, width: u32, height: u32) -> Self {
+        let x = self.x / TILE_ALIGN * TILE_ALIGN;
+        let y = self.y / TILE_ALIGN * TILE_ALIGN;
+        let right = self.right().div_ceil(TILE_ALIGN).saturating_mul(TILE_ALIGN).min(width);
+        let bottom = self.bottom().div_ceil(TILE_ALIGN).saturating_mul(TILE_ALIGN).min(height);
+        Self { x, y, width: right.saturating_sub(x), height: bottom.saturating_sub(y) }
+    }
+}
+
+/// Turn damaged areas into the tiles to send: clipped to the frame, grown to
+/// the tile grid and merged where they overlap. None when they cover so much
+/// of the frame that a keyframe is the better deal.
+pub fn plan(damage: &[Rect], width: u32, height: u32) -> Option<Vec<Rect>> {
+    let mut tiles: Vec<Rect> = Vec::new();
+    for rect in damage {
+        let Some(mut rect) = Rect::clipped(
+            rect.x as i64, rect.y as i64, rect.right() as i64, rect.bottom() as i64, width, height,
+        ) else {
+            continue;
+        };
+        rect = rect.aligned(width, height);
+        // Absorb every tile the new one touches, again after each growth
+        while let Some(i) = tiles.iter().position(|tile| tile.overlaps(&rect)) {
+            rect = rect.union(&tiles.swap_remove(i));
+        }
+        tiles.push(rect);
+    }
+
+    if tiles.len() > MAX_TILES {
+        let bounds = tiles.iter().skip(1).fold(tiles[0], |bounds, tile| bounds.union(tile));
+        tiles = vec![bounds];
+    }
+    let covered: u64 = tiles.iter().map(Rect::area).sum();
+    if covered as f64 > (width as u64 * height as u64) as f64 * MAX_TILED_SHARE {
+        return None;
+    }
+    Some(tiles)
+}
+
+/// One changed area and its image (JPEG)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tile {
+    pub rect: Rect,
+    pub data: Vec<u8>,
+}
+
+/// A video::FRAME_TILES frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileFrame {
+    /// Size of the whole frame the tiles patch
+    pub width: u32,
+    pub height: u32,
+    pub captured_at: u64,
+    pub tiles: Vec<Tile>,
+}
+
+/// Encode a video::FRAME_TILES frame:
+/// [FRAME_TILES][width u16 LE][height u16 LE][timestamp u64 LE][count u16 LE] then per tile
+/// [x u16 LE][y u16 LE][width u16 LE][height u16 LE][len u32 LE][jpeg]
+pub fn encode(width: u32, height: u32, captured_at: u64, tiles: &[Tile]) -> Vec<u8> {
+    let size = 15 + tiles.iter().map(|tile| 12 + tile.data.len()).sum::<usize>();
+    let mut data = Vec::with_capacity(size);
+    data.push(protocol::video::FRAME_TILES);
+    data.extend(&(width as u16).to_le_bytes());
+    data.extend(&(height as u16).to_le_bytes());
+    data.extend(&captured_at.to_le_bytes());
+    data.extend(&(tiles.len() as u16).to_le_bytes());
+    for tile in tiles {
+        data.extend(&(tile.rect.x as u16).to_le_bytes());
+        data.extend(&(tile.rect.y as u16).to_le_bytes());
+        data.extend(&(tile.rect.width as u16).to_le_bytes());
+        data.extend(&(tile.rect.height as u16).to_le_bytes());
+        data.extend(&(tile.data.len() as u32).to_le_bytes());
+        data.extend(&tile.data);
+    }
+    data
+}
+
+/// Decode a video::FRAME_TILES frame (including the type byte)
+pub fn decode(data: &[u8]) -> Result<TileFrame> {
+    if data.len() < 15 || data[0] != protocol::video::FRAME_TILES {
+        anyhow::bail!("Not a tile frame");
+    }
+    let width = u16::from_le_bytes([data[1], data[2]]) as u32;
+    let height = u16::from_le_bytes([data[3], data[4]]) as u32;
+    let captured_at = u64::from_le_bytes(data[5..13].try_into()?);
+    let count = u16::from_le_bytes([data[13], data[14]]) as usize;
+
+    let mut tiles = Vec::with_capacity(count.min(MAX_TILES));
+    let mut pos = 15;
+    for _ in 0..count {
+        let header = data.get(pos..pos + 12)
+            .ok_or_else(|| anyhow::anyhow!("Tile frame truncated"))?;
+        let field = |at: usize| u16::from_le_bytes([header[at], header[at + 1]]) as u32;
+        let rect = Rect { x: field(0), y: field(2), width: field(4), height: field(6) };
+        let len = u32::from_le_bytes(header[8..12].try_into()?) as usize;
+        pos += 12;
+
+        let image = data.get(pos..pos.saturating_add(len))
+            .ok_or_else(|| anyhow::anyhow!("Tile frame truncated"))?;
+        pos += len;
+        tiles.push(Tile { rect, data: image.to_vec() });
+    }
+    Ok(TileFrame { width, height, captured_at, tiles })
+}
+
+/// Copy `rect` out of a packed RGB frame `width` pixels wide
+pub fn crop(rgb: &[u8], width: u32, rect: Rect) -> Vec<u8> {
+    let stride = width as usize * 3;
+    let (start, len) = (rect.x as usize * 3, rect.width as usize * 3);
+    let mut out = Vec::with_capacity(len * rect.height as usize);
+    for row in rgb.chunks_exact(stride).skip(rect.y as usize).take(rect.height as usize) {
+        out.extend_from_slice(&row[start..start + len]);
+    }
+    out
+}
+
+/// Sequence numbers are unique across captures, so a viewer's copy taken from
+/// a capture that has since been reopened never matches the new one's log
+static NEXT_SEQ: AtomicU64 = AtomicU64::new(1);
+
+/// What changed in each captured frame, so a viewer whose copy is from an
+/// earlier frame can be sent everything that changed since
+pub struct DamageLog {
+    /// Changes after this frame are all in `entries`
+    base: u64,
+    /// The latest frame
+    seq: u64,
+    entries: VecDeque<(u64, Vec<Rect>)>,
+}
+
+impl Default for DamageLog {
+    fn default() -> Self {
+        let seq = NEXT_SEQ.fetch_add(1, Ordering::Relaxed);
+        Self { base: seq, seq, entries: VecDeque::new() }
+    }
+}
+
+impl DamageLog {
+    /// The latest frame's sequence number
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Note a new frame and what changed in it (None: possibly all of it)
+    pub fn record(&mut self, damage: Option<Vec<Rect>>) {
+        self.seq = NEXT_SEQ.fetch_add(1, Ordering::Relaxed);
+        match damage {
+            Some(rects) => {
+                self.entries.push_back((self.seq, rects));
+                if self.entries.len() > DAMAGE_HISTORY {
+                    if let Some((dropped, _)) = self.entries.pop_front() {
+                        self.base = dropped;
+                    }
+                }
+            }
+            None => {
+                self.entries.clear();
+                self.base = self.seq;
+            }
+        }
+    }
+
+    /// Forget everything: no earlier frame can be patched any more
+    pub fn reset(&mut self) {
+        self.record(None);
+    }
+
+    /// Everything that changed after frame `since`; None when that frame is
+    /// not covered (too long ago, or from another capture)
+    pub fn since(&self, since: u64) -> Option<Vec<Rect>> {
+        if since < self.base || since > self.seq {
+            return None;
+        }
+        Some(self.entries.iter()
+            .filter(|(seq, _)| *seq > since)
+            .flat_map(|(_, rects)| rects.iter().copied())
+            .collect())
+    }
+}
+
+/// The viewer's copy of the remote screen, which tile frames patch
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    rgb: Vec<u8>,
+}
+
+impl Canvas {
+    /// Start over from a full frame (JPEG or PNG)
+    pub fn from_image(data: &[u8]) -> Result<Self> {
+        let image = image::load_from_memory(data)?.to_rgb8();
+        Ok(Self { width: image.width(), height: image.height(), rgb: image.into_raw() })
+    }
+
+    /// From packed RGB of `width` x `height`
+    pub fn from_rgb(width: u32, height: u32, rgb: Vec<u8>) -> Result<Self> {
+        if rgb.len() != (width * height * 3) as usize {
+            anyhow::bail!("{} bytes are not a {}x{} frame", rgb.len(), width, height);
+        }
+        Ok(Self { width, height, rgb })
+    }
+
+    /// Copy each tile in; a frame of another size, or a tile that does not fit, fails
+    pub fn apply(&mut self, frame: &TileFrame) -> Result<()> {
+        if (frame.width, frame.height) != (self.width, self.height) {
+            anyhow::bail!(
+                "Tiles for a {}x{} frame, showing {}x{}",
+                frame.width, frame.height, self.width, self.height
+            );
+        }
+        for tile in &frame.tiles {
+            let rect = tile.rect;
+            if rect.right() > self.width || rect.bottom() > self.height {
+                anyhow::bail!("Tile at {},{} runs off the frame", rect.x, rect.y);
+            }
+            let image = image::load_from_memory(&tile.data)?.to_rgb8();
+            if (image.width(), image.height()) != (rect.width, rect.height) {
+                anyhow::bail!("Tile image is {}x{}, not {}x{}", image.width(), image.height(), rect.width, rect.height);
+            }
+
+            let stride = self.width as usize * 3;
+            let len = rect.width as usize * 3;
+            for (row, src) in image.as_raw().chunks_exact(len).enumerate() {
+                let start = (rect.y as usize + row) * stride + rect.x as usize * 3;
+                self.rgb[start..start + len].copy_from_slice(src);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The patched frame as JPEG, for whoever takes full frames from here on
+    pub fn encode(&self, quality: u8) -> Result<Vec<u8>> {
+        use image::codecs::jpeg::JpegEncoder;
+        use image::ColorType;
+
+        let mut out = Vec::new();
+        JpegEncoder::new_with_quality(&mut out, quality).encode(&self.rgb, self.width, self.height, ColorType::Rgb8)?;
+        Ok(out)
+    }
+
+    #[cfg(test)]
+    fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        let at = ((y * self.width + x) * 3) as usize;
+        [self.rgb[at], self.rgb[at + 1], self.rgb[at + 2]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: u32, y: u32, width: u32, height: u32) -> Rect {
+        Rect { x, y, width, height }
+    }
+
+    fn png(width: u32, height: u32, colour: [u8; 3]) -> Vec<u8> {
+        use image::codecs::png::PngEncoder;
+        use image::{ColorType, ImageEncoder};
+        let rgb: Vec<u8> = colour.iter().copied().cycle().take((width * height * 3) as usize).collect();
+        let mut out = Vec::new();
+        PngEncoder::new(&mut out).write_image(&rgb, width, height, ColorType::Rgb8).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_plan_aligns_and_merges() {
+        // A caret blink and a neighbouring cell end up in one grid-aligned tile
+        let tiles = plan(&[rect(20, 5, 2, 10), rect(30, 8, 4, 4)], 640, 480).unwrap();
+        assert_eq!(tiles, vec![rect(16, 0, 32, 16)]);
+
+        // Apart, clipped to the frame, and capped at its right edge
+        let tiles = plan(&[rect(0, 0, 8, 8), rect(630, 470, 40, 40)], 640, 480).unwrap();
+        assert_eq!(tiles, vec![rect(0, 0, 16, 16), rect(624, 464, 16, 16)]);
+
+        // Merging can chain: the third rect bridges the first two
+        let tiles = plan(&[rect(0, 0, 16, 16), rect(64, 0, 16, 16), rect(8, 0, 64, 8)], 640, 480).unwrap();
+        assert_eq!(tiles, vec![rect(0, 0, 80, 16)]);
+
+        assert_eq!(plan(&[], 640, 480), Some(Vec::new()));
+        assert_eq!(plan(&[rect(700, 0, 10, 10)], 640, 480), Some(Vec::new()));
+        // Most of the screen changed: send a keyframe instead
+        assert_eq!(plan(&[rect(0, 0, 640, 300)], 640, 480), None);
+    }
+
+    #[test]
+    fn test_tile_frame_round_trip() {
+        let tiles = vec![
+            Tile { rect: rect(0, 16, 32, 16), data: vec![1, 2, 3] },
+            Tile { rect: rect(64, 0, 16, 16), data: Vec::new() },
+        ];
+        let encoded = encode(1920, 1080, 42, &tiles);
+        assert_eq!(encoded[0], protocol::video::FRAME_TILES);
+        assert_eq!(decode(&encoded).unwrap(), TileFrame { width: 1920, height: 1080, captured_at: 42, tiles });
+
+        assert!(decode(&encoded[..encoded.len() - 1]).is_err());
+        assert!(decode(&[protocol::video::FRAME_UNCHANGED]).is_err());
+    }
+
+    #[test]
+    fn test_damage_log() {
+        let mut log = DamageLog::default();
+        let start = log.seq();
+        log.record(Some(vec![rect(0, 0, 16, 16)]));
+        let first = log.seq();
+        log.record(Some(vec![rect(32, 0, 16, 16)]));
+
+        assert_eq!(log.since(start), Some(vec![rect(0, 0, 16, 16), rect(32, 0, 16, 16)]));
+        assert_eq!(log.since(first), Some(vec![rect(32, 0, 16, 16)]));
+        assert_eq!(log.since(log.seq()), Some(Vec::new()));
+
+        // Another capture's frames are never patched
+        let other = DamageLog::default();
+        assert_eq!(log.since(other.seq()), None);
+        assert_eq!(other.since(first), None);
+
+        // A full-screen change, or history running out, needs a keyframe
+        log.reset();
+        assert_eq!(log.since(first), None);
+        let reset = log.seq();
+        for _ in 0..DAMAGE_HISTORY + 1 {
+            log.record(Some(Vec::new()));
+        }
+        assert_eq!(log.since(reset), None);
+    }
+
+    #[test]
+    fn test_canvas_applies_tiles() {
+        let mut canvas = Canvas::from_image(&png(32, 32, [0, 0, 0])).unwrap();
+        let frame = TileFrame {
+            width: 32,
+            height: 32,
+            captured_at: 0,
+            tiles: vec![Tile { rect: rect(16, 0, 16, 16), data: png(16, 16, [255, 0, 0]) }],
+        };
+        canvas.apply(&frame).unwrap();
+        assert_eq!(canvas.pixel(16, 0), [255, 0, 0]);
+        assert_eq!(canvas.pixel(31, 15), [255, 0, 0]);
+        assert_eq!(canvas.pixel(15, 0), [0, 0, 0]);
+        assert_eq!(canvas.pixel(16, 16), [0, 0, 0]);
+
+        // Tiles for another resolution, or off the edge, are refused
+        assert!(canvas.apply(&TileFrame { width: 64, ..frame.clone() }).is_err());
+        let off = Tile { rect: rect(24, 24, 16, 16), data: png(16, 16, [0, 255, 0]) };
+        assert!(canvas.apply(&TileFrame { tiles: vec![off], ..frame }).is_err());
+        assert!(!canvas.encode(90).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_crop() {
+        let rgb: Vec<u8> = (0..4 * 2 * 3).map(|i| i as u8).collect();
+        assert_eq!(crop(&rgb, 4, rect(1, 1, 2, 1)), vec![15, 16, 17, 18, 19, 20]);
+    }
+}
@@ -0,0 +1,250 @@
+//! Session transcripts for support tickets
+//! Built from the session event timeline and written as JSON or a standalone HTML page
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+use crate::session_events::{SessionEvent, SessionMeta};
+
+/// Event kinds that change what the remote user sees or can do
+const PRIVACY_KINDS: &[&str] = &["black_screen_on", "black_screen_off", "input_block_on", "input_block_off"];
+
+/// A chat message in the transcript
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatLine {
+    /// Unix time in milliseconds
+    pub timestamp: u64,
+    /// "local" or "remote"
+    pub from: &'static str,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Transcript {
+    pub session_id: String,
+    /// Our device id
+    pub local_id: String,
+    #[serde(flatten)]
+    pub meta: SessionMeta,
+    /// Unix time in milliseconds
+    pub exported_at: u64,
+    pub chat: Vec<ChatLine>,
+    pub privacy: Vec<SessionEvent>,
+    /// Events whose kind starts with "file_"
+    pub file_transfers: Vec<SessionEvent>,
+    /// The whole timeline, oldest first (bounded, see session_events)
+    pub events: Vec<SessionEvent>,
+}
+
+impl Transcript {
+    pub fn new(session_id: &str, local_id: String, meta: SessionMeta, events: Vec<SessionEvent>, exported_at: u64) -> Self {
+        let chat = events
+            .iter()
+            .filter_map(|event| {
+                let from = match event.kind.as_str() {
+                    "chat_sent" => "local",
+                    "chat_received" => "remote",
+                    _ => return None,
+                };
+                Some(ChatLine {
+                    timestamp: event.timestamp,
+                    from,
+                    text: event.detail.clone().unwrap_or_default(),
+                })
+            })
+            .collect();
+        let privacy = events.iter().filter(|e| PRIVACY_KINDS.contains(&e.kind.as_str())).cloned().collect();
+        let file_transfers = events.iter().filter(|e| e.kind.starts_with("file_")).cloned().collect();
+
+        Self {
+            session_id: session_id.to_string(),
+            local_id,
+            meta,
+            exported_at,
+            chat,
+            privacy,
+            file_transfers,
+            events,
+        }
+    }
+
+    /// Write to `path`: HTML for .html/.htm, JSON otherwise
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let is_html = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("html") || e.eq_ignore_ascii_case("htm"));
+        let data = if is_html {
+            self.to_html().into_bytes()
+        } else {
+            serde_json::to_vec_pretty(self)?
+        };
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, data).with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn to_html(&self) -> String {
+        let meta = &self.meta;
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        html.push_str(&format!("<title>SecureDesk session {}</title>\n", escape(&meta.remote_id)));
+        html.push_str("<style>body{font-family:sans-serif;margin:2em}table{border-collapse:collapse}\
+            td,th{border:1px solid #ccc;padding:4px 8px;text-align:left;vertical-align:top}</style>\n");
+        html.push_str("</head>\n<body>\n<h1>Session transcript</h1>\n<table>\n");
+
+        let disconnected = meta.disconnected_at.map(format_time).unwrap_or_else(|| "Still connected".to_string());
+        let rows = [
+            ("Session", self.session_id.clone()),
+            ("Local device", self.local_id.clone()),
+            ("Remote device", format!("{} ({})", meta.remote_id, meta.remote_name)),
+            ("Connection", meta.connection_type.clone()),
+            ("Connected", format_time(meta.connected_at)),
+            ("Disconnected", disconnected),
+            ("Exported", format_time(self.exported_at)),
+        ];
+        for (label, value) in rows {
+            html.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", label, escape(&value)));
+        }
+        html.push_str("</table>\n");
+
+        html.push_str("<h2>Chat</h2>\n");
+        if self.chat.is_empty() {
+            html.push_str("<p>No messages</p>\n");
+        } else {
+            html.push_str("<table>\n<tr><th>Time</th><th>From</th><th>Message</th></tr>\n");
+            for line in &self.chat {
+                html.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    format_time(line.timestamp),
+                    line.from,
+                    escape(&line.text),
+                ));
+            }
+            html.push_str("</table>\n");
+        }
+
+        for (title, events) in [
+            ("Privacy", &self.privacy),
+            ("File transfers", &self.file_transfers),
+            ("Timeline", &self.events),
+        ] {
+            html.push_str(&format!("<h2>{}</h2>\n", title));
+            if events.is_empty() {
+                html.push_str("<p>None</p>\n");
+                continue;
+            }
+            html.push_str("<table>\n<tr><th>Time</th><th>Event</th><th>Detail</th></tr>\n");
+            for event in events {
+                html.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    format_time(event.timestamp),
+                    escape(&event.kind),
+                    escape(event.detail.as_deref().unwrap_or("")),
+                ));
+            }
+            html.push_str("</table>\n");
+        }
+
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Unix milliseconds as "YYYY-MM-DD HH:MM:SS UTC"
+fn format_time(millis: u64) -> String {
+    let secs = millis / 1000;
+    let days = (secs / 86_400) as i64;
+    let time = secs % 86_400;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year, month, day, time / 3600, time % 3600 / 60, time % 60,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(timestamp: u64, kind: &str, detail: Option<&str>) -> SessionEvent {
+        SessionEvent {
+            timestamp,
+            kind: kind.to_string(),
+            detail: detail.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_transcript_sections() {
+        let meta = SessionMeta {
+            remote_id: "123456789".to_string(),
+            remote_name: "Front desk".to_string(),
+            connection_type: "Relay".to_string(),
+            connected_at: 1_700_000_000_000,
+            disconnected_at: None,
+        };
+        let events = vec![
+            event(1_700_000_000_000, "connected", Some("123456789 via Relay")),
+            event(1_700_000_001_000, "chat_sent", Some("<b>hi</b> & welcome")),
+            event(1_700_000_002_000, "black_screen_on", None),
+            event(1_700_000_003_000, "chat_received", Some("thanks")),
+            event(1_700_000_004_000, "quality_changed", Some("high")),
+        ];
+        let transcript = Transcript::new("session_0", "987654321".to_string(), meta, events, 1_700_000_005_000);
+
+        assert_eq!(transcript.chat.len(), 2);
+        assert_eq!(transcript.chat[0].from, "local");
+        assert_eq!(transcript.chat[1].text, "thanks");
+        assert_eq!(transcript.privacy.len(), 1);
+        assert!(transcript.file_transfers.is_empty());
+        assert_eq!(transcript.events.len(), 5);
+
+        let json = serde_json::to_value(&transcript).unwrap();
+        assert_eq!(json["remote_id"], "123456789");
+        assert_eq!(json["chat"][0]["text"], "<b>hi</b> & welcome");
+
+        // Chat text is escaped, never interpreted
+        let html = transcript.to_html();
+        assert!(html.contains("&lt;b&gt;hi&lt;/b&gt; &amp; welcome"));
+        assert!(!html.contains("<b>hi"));
+        assert!(html.contains("2023-11-14 22:13:20 UTC"));
+    }
+
+    #[test]
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_time(951_782_400_000), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_time(1_767_225_599_999), "2025-12-31 23:59:59 UTC");
+    }
+}
@@ -0,0 +1,544 @@
+//! File transfer on the file channel: the client offers a file, the host takes
+//! it into its Downloads folder, and chunks follow with an ACK every ACK_EVERY
+//! chunks so a large file never has more than a window in flight ahead of video
+
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::protocol::{self, Frame};
+
+/// Largest chunk of file data per frame
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Chunks sent before the sender waits for an ACK
+pub const ACK_EVERY: u64 = 8;
+
+/// Longest file name accepted, in bytes
+const MAX_NAME_LEN: usize = 255;
+
+/// Finished transfers kept for `list_incoming_transfers`
+const MAX_FINISHED: usize = 50;
+
+/// Suffix of a file still being received
+const PART_SUFFIX: &str = ".part";
+
+/// A file channel message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// [id u32][size u64][name utf8]
+    Offer { id: u32, size: u64, name: String },
+    /// [id u32]
+    Accept { id: u32 },
+    /// [id u32][reason utf8]
+    Reject { id: u32, reason: String },
+    /// [id u32][offset u64][data]
+    Chunk { id: u32, offset: u64, data: Vec<u8> },
+    /// [id u32][bytes received u64]
+    Ack { id: u32, received: u64 },
+    /// [id u32][blake3 32]: all chunks sent, or from the host, the file is kept
+    Done { id: u32, hash: [u8; 32] },
+    /// [id u32][reason utf8]: either side gives up
+    Cancel { id: u32, reason: String },
+}
+
+impl Message {
+    pub fn frame(&self) -> Frame {
+        use protocol::file::*;
+
+        let (kind, id, rest) = match self {
+            Self::Offer { id, size, name } => (FILE_OFFER, id, [&size.to_le_bytes()[..], name.as_bytes()].concat()),
+            Self::Accept { id } => (FILE_ACCEPT, id, Vec::new()),
+            Self::Reject { id, reason } => (FILE_REJECT, id, reason.as_bytes().to_vec()),
+            Self::Chunk { id, offset, data } => (FILE_CHUNK, id, [&offset.to_le_bytes()[..], data].concat()),
+            Self::Ack { id, received } => (FILE_ACK, id, received.to_le_bytes().to_vec()),
+            Self::Done { id, hash } => (FILE_DONE, id, hash.to_vec()),
+            Self::Cancel { id, reason } => (FILE_CANCEL, id, reason.as_bytes().to_vec()),
+        };
+        Frame::file(kind, &[&id.to_le_bytes()[..], &rest].concat())
+    }
+
+    /// Parse a file channel payload, type byte included
+    pub fn decode(payload: &[u8]) -> Result<Self> {
+        use protocol::file::*;
+
+        if payload.len() < 5 {
+            anyhow::bail!("File message too short");
+        }
+        let id = u32::from_le_bytes(payload[1..5].try_into()?);
+        let rest = &payload[5..];
+        let u64_at = |at: usize| -> Result<u64> {
+            let bytes = rest.get(at..at + 8).ok_or_else(|| anyhow::anyhow!("File message truncated"))?;
+            Ok(u64::from_le_bytes(bytes.try_into()?))
+        };
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).to_string();
+
+        Ok(match payload[0] {
+            FILE_OFFER => Self::Offer { id, size: u64_at(0)?, name: text(&rest[8..]) },
+            FILE_ACCEPT => Self::Accept { id },
+            FILE_REJECT => Self::Reject { id, reason: text(rest) },
+            FILE_CHUNK => Self::Chunk { id, offset: u64_at(0)?, data: rest[8..].to_vec() },
+            FILE_ACK => Self::Ack { id, received: u64_at(0)? },
+            FILE_DONE => Self::Done { id, hash: rest.try_into().map_err(|_| anyhow::anyhow!("Bad file hash"))? },
+            FILE_CANCEL => Self::Cancel { id, reason: text(rest) },
+            other => anyhow::bail!("Unknown file message 0x{:02x}", other),
+        })
+    }
+}
+
+/// Where a transfer stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+    /// Offered, not yet accepted
+    Waiting,
+    Transferring,
+    Completed,
+    Failed,
+}
+
+/// A transfer as the UI sees it
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Progress {
+    pub id: u32,
+    pub name: String,
+    pub size: u64,
+    /// Bytes sent and acknowledged, or received
+    pub bytes: u64,
+    pub state: State,
+    pub error: Option<String>,
+    /// Where a received file was saved
+    pub path: Option<String>,
+    /// Host side: the viewer sending it
+    pub viewer_id: Option<u16>,
+}
+
+/// The folder received files are saved to
+pub fn downloads_dir() -> PathBuf {
+    dirs::download_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+/// The name an offered file is saved under: its last path component, with
+/// anything that could reach outside the folder refused
+pub fn sanitize_name(name: &str) -> Result<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or("").trim();
+    if name.is_empty() || name == "." || name == ".." {
+        anyhow::bail!("Invalid file name");
+    }
+    if name.len() > MAX_NAME_LEN {
+        anyhow::bail!("File name too long");
+    }
+    if name.chars().any(|c| c.is_control() || matches!(c, ':' | '*' | '?' | '"' | '<' | '>' | '|')) {
+        anyhow::bail!("File name has characters not allowed in a file name");
+    }
+    Ok(name.to_string())
+}
+
+/// `name` in `dir`, numbered "name (1).ext" and on while it is taken
+fn unused_path(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(name);
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (name, String::new()),
+    };
+    let taken = |path: &Path| path.exists() || part_path(path).exists();
+    if !taken(&path) {
+        return path;
+    }
+    (1..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, ext)))
+        .find(|path| !taken(path))
+        .expect("unbounded range")
+}
+
+fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(PART_SUFFIX);
+    PathBuf::from(part)
+}
+
+/// A file being sent (client side)
+pub struct Upload {
+    pub id: u32,
+    pub name: String,
+    pub size: u64,
+    file: File,
+    hasher: blake3::Hasher,
+    sent: u64,
+    acked: u64,
+    accepted: bool,
+    done_sent: bool,
+}
+
+impl Upload {
+    pub fn open(id: u32, path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            anyhow::bail!("{} is not a file", path.display());
+        }
+        let name = path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| anyhow::anyhow!("{} has no file name", path.display()))?;
+        Ok(Self {
+            id,
+            name: sanitize_name(&name)?,
+            size: metadata.len(),
+            file,
+            hasher: blake3::Hasher::new(),
+            sent: 0,
+            acked: 0,
+            accepted: false,
+            done_sent: false,
+        })
+    }
+
+    pub fn offer(&self) -> Message {
+        Message::Offer { id: self.id, size: self.size, name: self.name.clone() }
+    }
+
+    pub fn accepted(&mut self) {
+        self.accepted = true;
+    }
+
+    /// The receiver has `received` bytes
+    pub fn acked(&mut self, received: u64) {
+        self.acked = self.acked.max(received.min(self.sent));
+    }
+
+    /// The next message to send: a chunk while the window allows, then Done
+    /// None once Done went out, before acceptance, or while waiting for an ACK
+    pub fn next_message(&mut self) -> Result<Option<Message>> {
+        if !self.accepted || self.done_sent || self.sent - self.acked >= ACK_EVERY * CHUNK_SIZE as u64 {
+            return Ok(None);
+        }
+        if self.sent >= self.size {
+            self.done_sent = true;
+            return Ok(Some(Message::Done { id: self.id, hash: *self.hasher.finalize().as_bytes() }));
+        }
+
+        let len = (self.size - self.sent).min(CHUNK_SIZE as u64) as usize;
+        let mut data = vec![0; len];
+        self.file.read_exact(&mut data)?;
+        self.hasher.update(&data);
+        let offset = self.sent;
+        self.sent += len as u64;
+        Ok(Some(Message::Chunk { id: self.id, offset, data }))
+    }
+
+    pub fn progress(&self, state: State, error: Option<String>) -> Progress {
+        Progress {
+            id: self.id,
+            name: self.name.clone(),
+            size: self.size,
+            bytes: self.acked,
+            state,
+            error,
+            path: None,
+            viewer_id: None,
+        }
+    }
+}
+
+/// A file being received (host side), written to a ".part" file until verified
+pub struct Incoming {
+    pub viewer_id: u16,
+    pub id: u32,
+    pub name: String,
+    pub size: u64,
+    path: PathBuf,
+    file: File,
+    hasher: blake3::Hasher,
+    received: u64,
+    /// Bytes received when the last ACK went out
+    acked: u64,
+}
+
+impl Incoming {
+    /// Start receiving an offered file into `dir`
+    pub fn create(dir: &Path, viewer_id: u16, id: u32, name: &str, size: u64) -> Result<Self> {
+        let name = sanitize_name(name)?;
+        std::fs::create_dir_all(dir)?;
+        let path = unused_path(dir, &name);
+        let file = File::create(part_path(&path))?;
+        Ok(Self {
+            viewer_id,
+            id,
+            name,
+            size,
+            path,
+            file,
+            hasher: blake3::Hasher::new(),
+            received: 0,
+            acked: 0,
+        })
+    }
+
+    /// Write the chunk at `offset`; the ACK to send, if one is due
+    pub fn write(&mut self, offset: u64, data: &[u8]) -> Result<Option<Message>> {
+        if offset != self.received {
+            anyhow::bail!("Chunk at {} while expecting {}", offset, self.received);
+        }
+        if self.received + data.len() as u64 > self.size {
+            anyhow::bail!("More data than the {} bytes offered", self.size);
+        }
+        self.file.write_all(data)?;
+        self.hasher.update(data);
+        self.received += data.len() as u64;
+
+        let due = self.received - self.acked >= ACK_EVERY * CHUNK_SIZE as u64 || self.received == self.size;
+        if !due {
+            return Ok(None);
+        }
+        self.acked = self.received;
+        Ok(Some(Message::Ack { id: self.id, received: self.received }))
+    }
+
+    /// Check the sender's hash and move the file into place; the hash on success
+    /// A file that fails the check is deleted
+    pub fn finish(self, hash: &[u8; 32]) -> Result<(PathBuf, [u8; 32])> {
+        let ours = *self.hasher.finalize().as_bytes();
+        if self.received != self.size {
+            let error = anyhow::anyhow!("Received {} of {} bytes", self.received, self.size);
+            self.abort();
+            return Err(error);
+        }
+        if &ours != hash {
+            self.abort();
+            anyhow::bail!("Hash mismatch");
+        }
+        self.file.sync_all()?;
+        drop(self.file);
+        std::fs::rename(part_path(&self.path), &self.path)?;
+        Ok((self.path, ours))
+    }
+
+    /// Give up and delete what was received
+    pub fn abort(self) {
+        drop(self.file);
+        let _ = std::fs::remove_file(part_path(&self.path));
+    }
+
+    pub fn progress(&self) -> Progress {
+        Progress {
+            id: self.id,
+            name: self.name.clone(),
+            size: self.size,
+            bytes: self.received,
+            state: State::Transferring,
+            error: None,
+            path: None,
+            viewer_id: Some(self.viewer_id),
+        }
+    }
+}
+
+/// Files a host session is receiving, and the ones it finished lately
+#[derive(Default)]
+pub struct IncomingTransfers {
+    active: HashMap<(u16, u32), Incoming>,
+    finished: VecDeque<Progress>,
+}
+
+impl IncomingTransfers {
+    /// Handle a file message from `viewer_id`; the answer to send and the progress to report
+    pub fn handle(&mut self, dir: &Path, viewer_id: u16, message: Message) -> (Option<Message>, Option<Progress>) {
+        match message {
+            Message::Offer { id, size, name } => {
+                if self.active.contains_key(&(viewer_id, id)) {
+                    return (Some(Message::Reject { id, reason: "Transfer already in progress".into() }), None);
+                }
+                match Incoming::create(dir, viewer_id, id, &name, size) {
+                    Ok(incoming) => {
+                        println!("[TRANSFER] Receiving {} ({} bytes) from viewer {}", incoming.name, size, viewer_id);
+                        let progress = incoming.progress();
+                        self.active.insert((viewer_id, id), incoming);
+                        (Some(Message::Accept { id }), Some(progress))
+                    }
+                    Err(e) => {
+                        eprintln!("[TRANSFER] Refusing {:?} from viewer {}: {}", name, viewer_id, e);
+                        (Some(Message::Reject { id, reason: e.to_string() }), None)
+                    }
+                }
+            }
+            Message::Chunk { id, offset, data } => {
+                let Some(incoming) = self.active.get_mut(&(viewer_id, id)) else {
+                    return (None, None);
+                };
+                match incoming.write(offset, &data) {
+                    Ok(Some(ack)) => (Some(ack), Some(incoming.progress())),
+                    Ok(None) => (None, None),
+                    Err(e) => {
+                        let reason = e.to_string();
+                        let progress = self.fail(viewer_id, id, &reason);
+                        (Some(Message::Cancel { id, reason }), progress)
+                    }
+                }
+            }
+            Message::Done { id, hash } => {
+                let Some(incoming) = self.active.remove(&(viewer_id, id)) else {
+                    return (None, None);
+                };
+                let mut progress = incoming.progress();
+                match incoming.finish(&hash) {
+                    Ok((path, hash)) => {
+                        println!("[TRANSFER] Saved {}", path.display());
+                        progress.state = State::Completed;
+                        progress.path = Some(path.display().to_string());
+                        self.push_finished(progress.clone());
+                        (Some(Message::Done { id, hash }), Some(progress))
+                    }
+                    Err(e) => {
+                        eprintln!("[TRANSFER] Discarding {}: {}", progress.name, e);
+                        progress.state = State::Failed;
+                        progress.error = Some(e.to_string());
+                        self.push_finished(progress.clone());
+                        (Some(Message::Cancel { id, reason: e.to_string() }), Some(progress))
+                    }
+                }
+            }
+            Message::Cancel { id, reason } => (None, self.fail(viewer_id, id, &reason)),
+            _ => (None, None),
+        }
+    }
+
+    /// Drop a transfer and its partial file
+    fn fail(&mut self, viewer_id: u16, id: u32, reason: &str) -> Option<Progress> {
+        let incoming = self.active.remove(&(viewer_id, id))?;
+        eprintln!("[TRANSFER] {} from viewer {} failed: {}", incoming.name, viewer_id, reason);
+        let mut progress = incoming.progress();
+        progress.state = State::Failed;
+        progress.error = Some(reason.to_string());
+        incoming.abort();
+        self.push_finished(progress.clone());
+        Some(progress)
+    }
+
+    /// A viewer left: whatever it was sending is incomplete
+    pub fn viewer_left(&mut self, viewer_id: u16) -> Vec<Progress> {
+        let ids: Vec<u32> = self.active.keys().filter(|(viewer, _)| *viewer == viewer_id).map(|&(_, id)| id).collect();
+        ids.into_iter().filter_map(|id| self.fail(viewer_id, id, "Viewer disconnected")).collect()
+    }
+
+    fn push_finished(&mut self, progress: Progress) {
+        if self.finished.len() >= MAX_FINISHED {
+            self.finished.pop_front();
+        }
+        self.finished.push_back(progress);
+    }
+
+    /// Transfers in progress, then finished ones, newest first
+    pub fn list(&self) -> Vec<Progress> {
+        let mut list: Vec<Progress> = self.active.values().map(Incoming::progress).collect();
+        list.sort_by_key(|progress| (progress.viewer_id, progress.id));
+        list.extend(self.finished.iter().rev().cloned());
+        list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_round_trip() {
+        let messages = [
+            Message::Offer { id: 1, size: 1 << 40, name: "report.pdf".into() },
+            Message::Accept { id: 1 },
+            Message::Reject { id: 2, reason: "View-only".into() },
+            Message::Chunk { id: 1, offset: 65536, data: vec![1, 2, 3] },
+            Message::Ack { id: 1, received: 524288 },
+            Message::Done { id: 1, hash: [7; 32] },
+            Message::Cancel { id: 1, reason: String::new() },
+        ];
+        for message in messages {
+            let frame = message.frame();
+            assert_eq!(frame.channel, protocol::Channel::File);
+            assert_eq!(Message::decode(&frame.payload).unwrap(), message);
+        }
+        assert!(Message::decode(&[protocol::file::FILE_ACK, 1, 0, 0, 0, 1]).is_err());
+        assert!(Message::decode(&[protocol::file::FILE_DONE, 1, 0, 0, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_sanitize_name() {
+        assert_eq!(sanitize_name("notes.txt").unwrap(), "notes.txt");
+        assert_eq!(sanitize_name("../../etc/passwd").unwrap(), "passwd");
+        assert_eq!(sanitize_name("C:\\Users\\me\\a.txt").unwrap(), "a.txt");
+        assert!(sanitize_name("dir/..").is_err());
+        assert!(sanitize_name("").is_err());
+        assert!(sanitize_name("a:b").is_err());
+        assert!(sanitize_name(&"x".repeat(MAX_NAME_LEN + 1)).is_err());
+    }
+
+    #[test]
+    fn test_upload_into_downloads() {
+        let dir = std::env::temp_dir().join("securedesk_transfer_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("source.bin");
+        let content: Vec<u8> = (0..(CHUNK_SIZE * 10 + 123)).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&source, &content).unwrap();
+        let received = dir.join("received");
+        std::fs::create_dir_all(&received).unwrap();
+        std::fs::write(received.join("source.bin"), b"taken").unwrap();
+
+        let mut upload = Upload::open(9, &source).unwrap();
+        let mut host = IncomingTransfers::default();
+        let (answer, _) = host.handle(&received, 3, upload.offer());
+        assert_eq!(answer, Some(Message::Accept { id: 9 }));
+        assert!(upload.next_message().unwrap().is_none());
+        upload.accepted();
+
+        let mut chunks = 0;
+        let mut finished = None;
+        while let Some(message) = upload.next_message().unwrap() {
+            chunks += matches!(message, Message::Chunk { .. }) as u64;
+            match host.handle(&received, 3, message) {
+                (Some(Message::Ack { received, .. }), _) => upload.acked(received),
+                (Some(Message::Done { .. }), Some(progress)) => finished = Some(progress),
+                (None, _) => {}
+                (other, _) => panic!("unexpected answer {:?}", other),
+            }
+            // Without ACKs, the sender stops after a window of chunks
+            assert!(upload.sent - upload.acked <= ACK_EVERY * CHUNK_SIZE as u64);
+        }
+        assert_eq!(chunks, 11);
+
+        let finished = finished.unwrap();
+        assert_eq!(finished.state, State::Completed);
+        let saved = PathBuf::from(finished.path.unwrap());
+        assert_eq!(saved, received.join("source (1).bin"));
+        assert_eq!(std::fs::read(&saved).unwrap(), content);
+        assert_eq!(host.list().len(), 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_bad_hash_discards_file() {
+        let dir = std::env::temp_dir().join("securedesk_transfer_hash_test");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut host = IncomingTransfers::default();
+        host.handle(&dir, 1, Message::Offer { id: 1, size: 3, name: "a.txt".into() });
+        let (ack, _) = host.handle(&dir, 1, Message::Chunk { id: 1, offset: 0, data: b"abc".to_vec() });
+        assert_eq!(ack, Some(Message::Ack { id: 1, received: 3 }));
+        let (answer, progress) = host.handle(&dir, 1, Message::Done { id: 1, hash: [0; 32] });
+        assert!(matches!(answer, Some(Message::Cancel { .. })));
+        assert_eq!(progress.unwrap().state, State::Failed);
+        assert!(std::fs::read_dir(&dir).unwrap().next().is_none());
+
+        // Out of order data fails the transfer
+        host.handle(&dir, 1, Message::Offer { id: 2, size: 10, name: "b.txt".into() });
+        let (answer, _) = host.handle(&dir, 1, Message::Chunk { id: 2, offset: 5, data: vec![0; 5] });
+        assert!(matches!(answer, Some(Message::Cancel { .. })));
+        assert!(host.viewer_left(1).is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
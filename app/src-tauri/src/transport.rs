@@ -2,12 +2,26 @@
 
 use anyhow::Result;
 use async_trait::async_trait;
+use parking_lot::Mutex as SyncMutex;
 use std::net::SocketAddr;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::TcpStream;
+use tokio::sync::mpsc;
 use tokio_rustls::client::TlsStream;
 
-use crate::protocol::{Channel, Frame};
+use crate::crypto::{Identity, SecureChannel};
+use crate::protocol::{self, Channel, Frame};
+
+/// Largest handshake message accepted on a direct link before it is secured
+const MAX_HANDSHAKE_MESSAGE: usize = 1024;
+
+/// Longest frame on a secured direct link: one sealed Noise message
+const MAX_DIRECT_FRAME: usize = SecureChannel::MAX_PLAINTEXT + 16;
+
+/// Messages read off a direct link and not yet taken by the session
+const DIRECT_QUEUE_DEPTH: usize = 16;
 
 /// Connection type indicator
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -115,55 +129,242 @@ impl Transport for RelayTransport {
 }
 
 /// P2P transport - direct connection
-/// Used when P2P connection succeeds; provides same interface as relay
+/// Used when P2P connection succeeds; provides same interface as relay.
+/// Frames only pass once a Noise handshake has secured the link
 #[allow(dead_code)]
 pub struct P2PTransport {
     pub stream: TcpStream,
     pub remote: SocketAddr,
+    channel: Option<SecureChannel>,
 }
 
 #[allow(dead_code)]
 impl P2PTransport {
     pub fn new(stream: TcpStream, remote: SocketAddr) -> Self {
-        Self { stream, remote }
+        Self { stream, remote, channel: None }
     }
 
     pub fn into_inner(self) -> TcpStream {
         self.stream
     }
+
+    /// Whether the handshake has secured the link
+    pub fn is_secured(&self) -> bool {
+        self.channel.is_some()
+    }
+
+    /// Secure the link as the client: Noise_XK to the host key verified on the relay
+    pub async fn secure_initiator(&mut self, identity: &Identity, host_key: &[u8; 32]) -> Result<()> {
+        // Noise_XK: -> e, es  <- e, ee  -> s, se
+        let mut handshake = identity.create_initiator(host_key)?;
+        let mut buf = vec![0u8; 65535];
+
+        let len = handshake.write_message(&[], &mut buf)?;
+        self.write_handshake(&buf[..len]).await?;
+        let reply = self.read_handshake().await?;
+        handshake.read_message(&reply, &mut buf)
+            .map_err(|_| anyhow::anyhow!("P2P peer failed the key exchange"))?;
+        let len = handshake.write_message(&[], &mut buf)?;
+        self.write_handshake(&buf[..len]).await?;
+
+        if !handshake.is_handshake_finished() || handshake.get_remote_static() != Some(&host_key[..]) {
+            anyhow::bail!("P2P handshake did not authenticate the host");
+        }
+        let mut channel = SecureChannel::from_handshake(handshake)?;
+
+        // Only the host can tell whether our key was the expected one
+        let confirm = self.read_raw(MAX_HANDSHAKE_MESSAGE).await?;
+        let confirm = channel.decrypt(&confirm.payload)
+            .map_err(|_| anyhow::anyhow!("P2P peer did not confirm the handshake"))?;
+        if confirm.first() != Some(&protocol::control::P2P_READY) {
+            anyhow::bail!("P2P peer did not confirm the handshake");
+        }
+        self.channel = Some(channel);
+        Ok(())
+    }
+
+    /// Secure the link as the host; the peer must prove `client_key`, the key
+    /// the viewer authenticated with on the relay
+    pub async fn secure_responder(&mut self, identity: &Identity, client_key: &[u8; 32]) -> Result<()> {
+        let mut handshake = identity.create_responder()?;
+        let mut buf = vec![0u8; 65535];
+
+        let message = self.read_handshake().await?;
+        handshake.read_message(&message, &mut buf)?;
+        let len = handshake.write_message(&[], &mut buf)?;
+        self.write_handshake(&buf[..len]).await?;
+        let message = self.read_handshake().await?;
+        handshake.read_message(&message, &mut buf)
+            .map_err(|_| anyhow::anyhow!("P2P peer failed the key exchange"))?;
+
+        if !handshake.is_handshake_finished() || handshake.get_remote_static() != Some(&client_key[..]) {
+            anyhow::bail!("P2P peer is not the viewer of this session");
+        }
+        self.channel = Some(SecureChannel::from_handshake(handshake)?);
+        self.write_frame(Frame::control(protocol::control::P2P_READY, &[])).await
+    }
+
+    async fn write_handshake(&mut self, message: &[u8]) -> Result<()> {
+        let mut payload = vec![protocol::control::HANDSHAKE];
+        payload.extend_from_slice(message);
+        self.write_raw(Channel::Control, &payload).await
+    }
+
+    async fn read_handshake(&mut self) -> Result<Vec<u8>> {
+        let frame = self.read_raw(MAX_HANDSHAKE_MESSAGE).await?;
+        match frame.payload.split_first() {
+            Some((&protocol::control::HANDSHAKE, message)) if frame.channel == Channel::Control => Ok(message.to_vec()),
+            _ => anyhow::bail!("Expected a handshake on the P2P link"),
+        }
+    }
+
+    async fn read_raw(&mut self, max_len: usize) -> Result<Frame> {
+        read_raw(&mut self.stream, max_len).await
+    }
+
+    async fn write_raw(&mut self, channel: Channel, payload: &[u8]) -> Result<()> {
+        write_raw(&mut self.stream, channel as u8, payload).await
+    }
+
+    /// Start carrying session frames on the secured link
+    /// A task reads it and queues whole messages, so a session read that
+    /// times out never leaves the link halfway through a frame
+    pub fn into_direct(self) -> Result<(DirectReader, DirectWriter)> {
+        let channel = self.channel.ok_or_else(|| anyhow::anyhow!("P2P link is not secured"))?;
+        let channel = Arc::new(SyncMutex::new(channel));
+        let (read, write) = self.stream.into_split();
+        let (tx, messages) = mpsc::channel(DIRECT_QUEUE_DEPTH);
+        let task = tokio::spawn(DirectReader::run(read, channel.clone(), tx, self.remote));
+        Ok((DirectReader { messages, task }, DirectWriter { stream: write, channel }))
+    }
 }
 
-#[async_trait]
-impl Transport for P2PTransport {
-    async fn read_frame(&mut self) -> Result<Frame> {
-        let mut header = [0u8; 4];
-        self.stream.read_exact(&mut header).await?;
+/// Receiving side of a direct link carrying session frames
+/// Dropping it stops the task reading the link
+pub struct DirectReader {
+    messages: mpsc::Receiver<Frame>,
+    task: tokio::task::JoinHandle<()>,
+}
 
-        let channel = Channel::try_from(header[0])?;
-        let len = ((header[1] as usize) << 16)
-            | ((header[2] as usize) << 8)
-            | (header[3] as usize);
+impl DirectReader {
+    /// Next whole message from the peer; fails once the link has closed
+    pub async fn read_frame(&mut self) -> Result<Frame> {
+        self.messages.recv().await.ok_or_else(|| anyhow::anyhow!("P2P link closed"))
+    }
 
-        let mut payload = vec![0u8; len];
-        self.stream.read_exact(&mut payload).await?;
+    async fn run(
+        mut stream: OwnedReadHalf,
+        channel: Arc<SyncMutex<SecureChannel>>,
+        tx: mpsc::Sender<Frame>,
+        remote: SocketAddr,
+    ) {
+        let mut fragments = protocol::Reassembler::default();
+        loop {
+            let message = async {
+                let frame = read_raw(&mut stream, MAX_DIRECT_FRAME).await?;
+                crate::metrics::record_bytes_received(4 + frame.payload.len());
+                let payload = channel.lock().decrypt(&frame.payload)?;
+                fragments.push(Frame { payload, ..frame })
+            };
+            match message.await {
+                Ok(Some(message)) => {
+                    if tx.send(message).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    println!("[P2P] Direct link to {} closed: {}", remote, e);
+                    return;
+                }
+            }
+        }
+    }
+}
 
-        Ok(Frame::new(channel, payload))
+impl Drop for DirectReader {
+    fn drop(&mut self) {
+        self.task.abort();
     }
+}
 
-    async fn write_frame(&mut self, frame: Frame) -> Result<()> {
-        let len = frame.payload.len();
-        let header = [
-            frame.channel as u8,
-            (len >> 16) as u8,
-            (len >> 8) as u8,
-            len as u8,
-        ];
+/// Sending side of a direct link carrying session frames
+pub struct DirectWriter {
+    stream: OwnedWriteHalf,
+    channel: Arc<SyncMutex<SecureChannel>>,
+}
 
-        self.stream.write_all(&header).await?;
-        self.stream.write_all(&frame.payload).await?;
-        self.stream.flush().await?;
+impl DirectWriter {
+    /// Seal and send a message, split into fragments of one Noise message each
+    pub async fn write_frame(&mut self, frame: &Frame) -> Result<()> {
+        let chunks: Vec<&[u8]> = if frame.payload.is_empty() {
+            vec![&[]]
+        } else {
+            frame.payload.chunks(SecureChannel::MAX_PLAINTEXT).collect()
+        };
+        let last = chunks.len() - 1;
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            let sealed = self.channel.lock().encrypt(chunk)?;
+            let more = if i < last { protocol::FRAME_MORE_FLAG } else { 0 };
+            write_raw(&mut self.stream, frame.channel as u8 | more, &sealed).await?;
+            crate::metrics::record_bytes_sent(4 + sealed.len());
+        }
         Ok(())
     }
+}
+
+/// Read one frame off a direct link, refusing any longer than `max_len`
+async fn read_raw<R: AsyncRead + Unpin>(stream: &mut R, max_len: usize) -> Result<Frame> {
+    let mut header = [0u8; 4];
+    stream.read_exact(&mut header).await?;
+
+    let channel = Channel::try_from(header[0] & !protocol::FRAME_MORE_FLAG)?;
+    let len = ((header[1] as usize) << 16)
+        | ((header[2] as usize) << 8)
+        | (header[3] as usize);
+    if len > max_len {
+        anyhow::bail!("P2P frame too large");
+    }
+
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload).await?;
+
+    Ok(Frame { channel, payload, more: header[0] & protocol::FRAME_MORE_FLAG != 0 })
+}
+
+/// `channel_byte` may carry FRAME_MORE_FLAG
+async fn write_raw<W: AsyncWrite + Unpin>(stream: &mut W, channel_byte: u8, payload: &[u8]) -> Result<()> {
+    let len = payload.len();
+    let header = [
+        channel_byte,
+        (len >> 16) as u8,
+        (len >> 8) as u8,
+        len as u8,
+    ];
+
+    stream.write_all(&header).await?;
+    stream.write_all(payload).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+#[async_trait]
+impl Transport for P2PTransport {
+    async fn read_frame(&mut self) -> Result<Frame> {
+        if self.channel.is_none() {
+            anyhow::bail!("P2P link is not secured");
+        }
+        let frame = self.read_raw(MAX_DIRECT_FRAME).await?;
+        let channel = self.channel.as_mut().ok_or_else(|| anyhow::anyhow!("P2P link is not secured"))?;
+        Ok(Frame::new(frame.channel, channel.decrypt(&frame.payload)?))
+    }
+
+    /// Never sends plaintext: an unsecured link refuses every frame
+    async fn write_frame(&mut self, frame: Frame) -> Result<()> {
+        let channel = self.channel.as_mut().ok_or_else(|| anyhow::anyhow!("P2P link is not secured"))?;
+        let payload = channel.encrypt(&frame.payload)?;
+        self.write_raw(frame.channel, &payload).await
+    }
 
     fn connection_type(&self) -> ConnectionType {
         ConnectionType::P2P
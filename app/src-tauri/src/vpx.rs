@@ -0,0 +1,435 @@
+//! VP8/VP9 video through libvpx (the `codec` feature)
+//!
+//! Instead of a JPEG per frame the host encodes a stream of keyframes and
+//! deltas, which the client decodes with the same state. Only used when both
+//! peers offer codec::VP8 or codec::VP9; everything else stays on JPEG.
+
+use anyhow::Result;
+use vpx_sys as ffi;
+use std::ffi::CStr;
+use std::os::raw::{c_int, c_ulong};
+use std::ptr;
+
+use crate::capture::RgbFrame;
+use crate::protocol::{self, Capabilities};
+
+/// Lowest and highest bitrate asked of the encoder, in kbit/s
+const MIN_BITRATE: u32 = 250;
+const MAX_BITRATE: u32 = 20_000;
+
+/// Which libvpx codec a session's video uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VpxCodec {
+    Vp8,
+    Vp9,
+}
+
+impl VpxCodec {
+    /// The better codec both peers offer; None keeps the session on JPEG
+    pub fn negotiated(capabilities: &Capabilities) -> Option<Self> {
+        if capabilities.supports_codec(protocol::codec::VP9) {
+            Some(Self::Vp9)
+        } else if capabilities.supports_codec(protocol::codec::VP8) {
+            Some(Self::Vp8)
+        } else {
+            None
+        }
+    }
+
+    fn encoder(self) -> *const ffi::vpx_codec_iface_t {
+        unsafe {
+            match self {
+                Self::Vp8 => ffi::vpx_codec_vp8_cx(),
+                Self::Vp9 => ffi::vpx_codec_vp9_cx(),
+            }
+        }
+    }
+
+    fn decoder(self) -> *const ffi::vpx_codec_iface_t {
+        unsafe {
+            match self {
+                Self::Vp8 => ffi::vpx_codec_vp8_dx(),
+                Self::Vp9 => ffi::vpx_codec_vp9_dx(),
+            }
+        }
+    }
+}
+
+/// Target bitrate for a frame size at a JPEG quality setting (1-100)
+/// Scaled by resolution so a 1080p desktop at quality 75 gets ~4.6 Mbit/s
+pub fn bitrate_kbps(quality: u8, width: u32, height: u32) -> u32 {
+    let kilopixels = width as u64 * height as u64 / 1000;
+    let kbps = kilopixels * quality.clamp(1, 100) as u64 * 30 / 1000;
+    (kbps as u32).clamp(MIN_BITRATE, MAX_BITRATE)
+}
+
+fn check(err: ffi::vpx_codec_err_t, what: &str) -> Result<()> {
+    if err == ffi::vpx_codec_err_t::VPX_CODEC_OK {
+        return Ok(());
+    }
+    let message = unsafe { CStr::from_ptr(ffi::vpx_codec_err_to_string(err)) };
+    anyhow::bail!("{} failed: {}", what, message.to_string_lossy())
+}
+
+/// Width and height of the U and V planes of an I420 image
+fn chroma_size(width: u32, height: u32) -> (usize, usize) {
+    (width.div_ceil(2) as usize, height.div_ceil(2) as usize)
+}
+
+/// Convert packed RGB into I420 planes (BT.601, studio range); chroma is the
+/// average of each 2x2 block
+fn rgb_to_i420(rgb: &[u8], width: u32, height: u32, planes: [&mut [u8]; 3], strides: [usize; 3]) {
+    let (width, height) = (width as usize, height as usize);
+    let [y_plane, u_plane, v_plane] = planes;
+    for row in 0..height {
+        for col in 0..width {
+            let at = (row * width + col) * 3;
+            let (r, g, b) = (rgb[at] as i32, rgb[at + 1] as i32, rgb[at + 2] as i32);
+            y_plane[row * strides[0] + col] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+        }
+    }
+
+    let (chroma_width, chroma_height) = chroma_size(width as u32, height as u32);
+    for row in 0..chroma_height {
+        for col in 0..chroma_width {
+            let (mut r, mut g, mut b, mut count) = (0, 0, 0, 0);
+            for y in (row * 2)..(row * 2 + 2).min(height) {
+                for x in (col * 2)..(col * 2 + 2).min(width) {
+                    let at = (y * width + x) * 3;
+                    r += rgb[at] as i32;
+                    g += rgb[at + 1] as i32;
+                    b += rgb[at + 2] as i32;
+                    count += 1;
+                }
+            }
+            let (r, g, b) = (r / count, g / count, b / count);
+            u_plane[row * strides[1] + col] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+            v_plane[row * strides[2] + col] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+        }
+    }
+}
+
+/// Convert I420 planes back into packed RGB
+fn i420_to_rgb(planes: [&[u8]; 3], strides: [usize; 3], width: u32, height: u32) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for row in 0..height {
+        for col in 0..width {
+            let c = planes[0][row * strides[0] + col] as i32 - 16;
+            let d = planes[1][(row / 2) * strides[1] + col / 2] as i32 - 128;
+            let e = planes[2][(row / 2) * strides[2] + col / 2] as i32 - 128;
+            rgb.push(((298 * c + 409 * e + 128) >> 8).clamp(0, 255) as u8);
+            rgb.push(((298 * c - 100 * d - 208 * e + 128) >> 8).clamp(0, 255) as u8);
+            rgb.push(((298 * c + 516 * d + 128) >> 8).clamp(0, 255) as u8);
+        }
+    }
+    rgb
+}
+
+/// An encoded frame
+pub struct Packet {
+    pub width: u32,
+    pub height: u32,
+    pub keyframe: bool,
+    pub data: Vec<u8>,
+}
+
+impl Packet {
+    /// [FRAME_VPX][width u16 LE][height u16 LE][timestamp u64 LE][keyframe u8][packet]
+    pub fn frame(&self, captured_at: u64) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(14 + self.data.len());
+        payload.push(protocol::video::FRAME_VPX);
+        payload.extend(&(self.width as u16).to_le_bytes());
+        payload.extend(&(self.height as u16).to_le_bytes());
+        payload.extend(&captured_at.to_le_bytes());
+        payload.push(self.keyframe as u8);
+        payload.extend(&self.data);
+        payload
+    }
+
+    /// Parse a video::FRAME_VPX payload, type byte included; returns the capture stamp too
+    pub fn parse(payload: &[u8]) -> Result<(Self, u64)> {
+        if payload.len() <= 14 || payload[0] != protocol::video::FRAME_VPX {
+            anyhow::bail!("VPX frame too short");
+        }
+        let packet = Self {
+            width: u16::from_le_bytes([payload[1], payload[2]]) as u32,
+            height: u16::from_le_bytes([payload[3], payload[4]]) as u32,
+            keyframe: payload[13] != 0,
+            data: payload[14..].to_vec(),
+        };
+        Ok((packet, u64::from_le_bytes(payload[5..13].try_into()?)))
+    }
+}
+
+/// A libvpx encoder for one frame size
+pub struct Encoder {
+    ctx: ffi::vpx_codec_ctx_t,
+    cfg: ffi::vpx_codec_enc_cfg_t,
+    image: *mut ffi::vpx_image_t,
+    width: u32,
+    height: u32,
+    pts: i64,
+}
+
+// The context and image are only touched through &mut self
+unsafe impl Send for Encoder {}
+
+impl Encoder {
+    pub fn new(codec: VpxCodec, width: u32, height: u32, bitrate_kbps: u32) -> Result<Self> {
+        unsafe {
+            let iface = codec.encoder();
+            let mut cfg: ffi::vpx_codec_enc_cfg_t = std::mem::zeroed();
+            check(ffi::vpx_codec_enc_config_default(iface, &mut cfg, 0), "Encoder defaults")?;
+            cfg.g_w = width;
+            cfg.g_h = height;
+            cfg.g_timebase = ffi::vpx_rational { num: 1, den: 1000 };
+            cfg.g_threads = std::thread::available_parallelism().map_or(1, |n| n.get().min(4)) as u32;
+            // Every frame goes out as soon as it is encoded
+            cfg.g_lag_in_frames = 0;
+            cfg.rc_end_usage = ffi::vpx_rc_mode::VPX_CBR;
+            cfg.rc_target_bitrate = bitrate_kbps;
+            // The host decides when to send keyframes
+            cfg.kf_mode = ffi::vpx_kf_mode::VPX_KF_DISABLED;
+
+            let mut ctx: ffi::vpx_codec_ctx_t = std::mem::zeroed();
+            check(
+                ffi::vpx_codec_enc_init_ver(&mut ctx, iface, &cfg, 0, ffi::VPX_ENCODER_ABI_VERSION as c_int),
+                "Encoder init",
+            )?;
+            let speed = match codec {
+                VpxCodec::Vp8 => 10,
+                VpxCodec::Vp9 => 7,
+            };
+            ffi::vpx_codec_control_(&mut ctx, ffi::vp8e_enc_control_id::VP8E_SET_CPUUSED as c_int, speed as c_int);
+            if codec == VpxCodec::Vp9 {
+                ffi::vpx_codec_control_(
+                    &mut ctx,
+                    ffi::vp8e_enc_control_id::VP9E_SET_TUNE_CONTENT as c_int,
+                    ffi::vp9e_tune_content::VP9E_CONTENT_SCREEN as c_int,
+                );
+            }
+
+            let image = ffi::vpx_img_alloc(ptr::null_mut(), ffi::vpx_img_fmt::VPX_IMG_FMT_I420, width, height, 1);
+            if image.is_null() {
+                ffi::vpx_codec_destroy(&mut ctx);
+                anyhow::bail!("Failed to allocate a {}x{} frame", width, height);
+            }
+            println!("[VPX] {:?} encoder for {}x{} at {} kbit/s", codec, width, height, bitrate_kbps);
+            Ok(Self { ctx, cfg, image, width, height, pts: 0 })
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn set_bitrate(&mut self, kbps: u32) -> Result<()> {
+        if self.cfg.rc_target_bitrate == kbps {
+            return Ok(());
+        }
+        self.cfg.rc_target_bitrate = kbps;
+        unsafe { check(ffi::vpx_codec_enc_config_set(&mut self.ctx, &self.cfg), "Bitrate change") }
+    }
+
+    /// Encode packed RGB of this encoder's size; None when rate control dropped it
+    pub fn encode(&mut self, rgb: &[u8], keyframe: bool) -> Result<Option<Packet>> {
+        if rgb.len() != (self.width * self.height * 3) as usize {
+            anyhow::bail!("Frame is not {}x{}", self.width, self.height);
+        }
+        unsafe {
+            let image = &*self.image;
+            let (chroma_width, chroma_height) = chroma_size(self.width, self.height);
+            let strides = [image.stride[0] as usize, image.stride[1] as usize, image.stride[2] as usize];
+            let planes = [
+                std::slice::from_raw_parts_mut(image.planes[0], strides[0] * self.height as usize),
+                std::slice::from_raw_parts_mut(image.planes[1], strides[1] * (chroma_height - 1) + chroma_width),
+                std::slice::from_raw_parts_mut(image.planes[2], strides[2] * (chroma_height - 1) + chroma_width),
+            ];
+            rgb_to_i420(rgb, self.width, self.height, planes, strides);
+
+            let flags = if keyframe { ffi::VPX_EFLAG_FORCE_KF as ffi::vpx_enc_frame_flags_t } else { 0 };
+            check(
+                ffi::vpx_codec_encode(&mut self.ctx, self.image, self.pts, 1, flags, ffi::VPX_DL_REALTIME as c_ulong),
+                "Encode",
+            )?;
+            self.pts += 1;
+
+            let mut data = Vec::new();
+            let mut is_key = false;
+            let mut iter: ffi::vpx_codec_iter_t = ptr::null();
+            loop {
+                let packet = ffi::vpx_codec_get_cx_data(&mut self.ctx, &mut iter);
+                if packet.is_null() {
+                    break;
+                }
+                if (*packet).kind != ffi::vpx_codec_cx_pkt_kind::VPX_CODEC_CX_FRAME_PKT {
+                    continue;
+                }
+                let frame = (*packet).data.frame;
+                data.extend_from_slice(std::slice::from_raw_parts(frame.buf as *const u8, frame.sz));
+                is_key |= frame.flags & ffi::VPX_FRAME_IS_KEY != 0;
+            }
+            Ok((!data.is_empty()).then_some(Packet { width: self.width, height: self.height, keyframe: is_key, data }))
+        }
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::vpx_img_free(self.image);
+            ffi::vpx_codec_destroy(&mut self.ctx);
+        }
+    }
+}
+
+/// A libvpx decoder; keeps the reference frames deltas build on
+pub struct Decoder {
+    ctx: ffi::vpx_codec_ctx_t,
+}
+
+unsafe impl Send for Decoder {}
+
+impl Decoder {
+    pub fn new(codec: VpxCodec) -> Result<Self> {
+        unsafe {
+            let mut ctx: ffi::vpx_codec_ctx_t = std::mem::zeroed();
+            check(
+                ffi::vpx_codec_dec_init_ver(&mut ctx, codec.decoder(), ptr::null(), 0, ffi::VPX_DECODER_ABI_VERSION as c_int),
+                "Decoder init",
+            )?;
+            Ok(Self { ctx })
+        }
+    }
+
+    /// Decode one packet into packed RGB; None if it held no picture
+    pub fn decode(&mut self, data: &[u8]) -> Result<Option<(u32, u32, Vec<u8>)>> {
+        unsafe {
+            check(
+                ffi::vpx_codec_decode(&mut self.ctx, data.as_ptr(), data.len() as u32, ptr::null_mut(), 0),
+                "Decode",
+            )?;
+            let mut iter: ffi::vpx_codec_iter_t = ptr::null();
+            let image = ffi::vpx_codec_get_frame(&mut self.ctx, &mut iter);
+            if image.is_null() {
+                return Ok(None);
+            }
+            let image = &*image;
+            if image.fmt != ffi::vpx_img_fmt::VPX_IMG_FMT_I420 {
+                anyhow::bail!("Decoded frame is {:?}, not I420", image.fmt);
+            }
+
+            let (width, height) = (image.d_w, image.d_h);
+            let (chroma_width, chroma_height) = chroma_size(width, height);
+            let strides = [image.stride[0] as usize, image.stride[1] as usize, image.stride[2] as usize];
+            let planes = [
+                std::slice::from_raw_parts(image.planes[0], strides[0] * (height as usize - 1) + width as usize),
+                std::slice::from_raw_parts(image.planes[1], strides[1] * (chroma_height - 1) + chroma_width),
+                std::slice::from_raw_parts(image.planes[2], strides[2] * (chroma_height - 1) + chroma_width),
+            ];
+            Ok(Some((width, height, i420_to_rgb(planes, strides, width, height))))
+        }
+    }
+}
+
+impl Drop for Decoder {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::vpx_codec_destroy(&mut self.ctx);
+        }
+    }
+}
+
+/// One viewer's video on the host: the encoder for the current frame size,
+/// the frame it last encoded and when the next keyframe is due
+pub struct Stream {
+    codec: VpxCodec,
+    encoder: Option<Encoder>,
+    last_seq: Option<u64>,
+    keyframe_interval: u32,
+    since_keyframe: u32,
+}
+
+impl Stream {
+    pub fn new(codec: VpxCodec, keyframe_interval: u32) -> Self {
+        Self { codec, encoder: None, last_seq: None, keyframe_interval, since_keyframe: 0 }
+    }
+
+    /// Whether the stream has encoded nothing yet, so wants a fresh frame
+    pub fn is_idle(&self) -> bool {
+        self.last_seq.is_none()
+    }
+
+    /// Encode `frame` unless it is the one encoded last; None means nothing new
+    pub fn encode(&mut self, frame: &RgbFrame, quality: u8) -> Result<Option<Packet>> {
+        if self.last_seq == Some(frame.seq) {
+            return Ok(None);
+        }
+        let bitrate = bitrate_kbps(quality, frame.width, frame.height);
+        let encoder = match &mut self.encoder {
+            Some(encoder) if encoder.size() == (frame.width, frame.height) => encoder,
+            slot => {
+                // A new size starts over from a keyframe
+                *slot = None;
+                self.since_keyframe = self.keyframe_interval;
+                slot.insert(Encoder::new(self.codec, frame.width, frame.height, bitrate)?)
+            }
+        };
+        encoder.set_bitrate(bitrate)?;
+
+        let keyframe = self.since_keyframe >= self.keyframe_interval;
+        let packet = encoder.encode(&frame.rgb, keyframe)?;
+        self.last_seq = Some(frame.seq);
+        match &packet {
+            Some(packet) if packet.keyframe => self.since_keyframe = 0,
+            Some(_) => self.since_keyframe += 1,
+            None => {}
+        }
+        Ok(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_i420_round_trip() {
+        // 3x3 so the last chroma column and row cover one pixel
+        let rgb: Vec<u8> = (0..27).map(|i| (i * 9) as u8).collect();
+        let mut y = vec![0u8; 9];
+        let mut u = vec![0u8; 4];
+        let mut v = vec![0u8; 4];
+        rgb_to_i420(&rgb, 3, 3, [&mut y, &mut u, &mut v], [3, 2, 2]);
+        assert_eq!(y[0], 22);
+
+        let back = i420_to_rgb([&y, &u, &v], [3, 2, 2], 3, 3);
+        assert_eq!(back.len(), rgb.len());
+        // Chroma is shared by 2x2 blocks; luma keeps each pixel close
+        for (got, want) in back.iter().zip(&rgb) {
+            assert!((*got as i32 - *want as i32).abs() <= 40, "{} vs {}", got, want);
+        }
+
+        let grey = vec![128u8; 12];
+        let (mut y, mut u, mut v) = (vec![0u8; 4], vec![0u8; 1], vec![0u8; 1]);
+        rgb_to_i420(&grey, 2, 2, [&mut y, &mut u, &mut v], [2, 1, 1]);
+        assert_eq!((u[0], v[0]), (128, 128));
+        for value in i420_to_rgb([&y, &u, &v], [2, 1, 1], 2, 2) {
+            assert!((value as i32 - 128).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn test_bitrate_and_frame() {
+        assert_eq!(bitrate_kbps(75, 1920, 1080), 4664);
+        assert_eq!(bitrate_kbps(1, 640, 480), MIN_BITRATE);
+        assert_eq!(bitrate_kbps(100, 7680, 4320), MAX_BITRATE);
+
+        let packet = Packet { width: 1920, height: 1080, keyframe: true, data: vec![1, 2, 3] };
+        let (parsed, captured_at) = Packet::parse(&packet.frame(42)).unwrap();
+        assert_eq!((parsed.width, parsed.height, parsed.keyframe, captured_at), (1920, 1080, true, 42));
+        assert_eq!(parsed.data, [1, 2, 3]);
+        assert!(Packet::parse(&packet.frame(42)[..14]).is_err());
+    }
+}
@@ -6,8 +6,10 @@ import TitleBar from './components/TitleBar';
 import ThisDevice from './components/ThisDevice';
 import RemoteConnect from './components/RemoteConnect';
 import SessionView from './components/SessionView';
-import ConnectionPopup from './components/ConnectionPopup';
+import HostChat from './components/HostChat';
+import ConnectionPopup, { type TrustDuration } from './components/ConnectionPopup';
 import Settings from './components/Settings';
+import { errorCode, errorMessage, upgradePrompt } from './errors';
 import './styles/app.css';
 
 export type AppMode = 'idle' | 'hosting' | 'connecting' | 'connected';
@@ -25,6 +27,12 @@ export interface SessionInfo {
 }
 
 interface ConnectionRequest {
+  request_id: number;
+  remote_id: string;
+}
+
+interface ControlRequest {
+  viewer_id: number;
   remote_id: string;
 }
 
@@ -34,9 +42,15 @@ function App() {
   const [session, setSession] = useState<SessionInfo | null>(null);
   const [blackScreen, setBlackScreen] = useState(false);
   const [inputBlock, setInputBlock] = useState(false);
-  const [incomingConnection, setIncomingConnection] = useState<string | null>(null);
+  // Requests awaiting approval, oldest first - the popup shows the first one
+  const [pendingRequests, setPendingRequests] = useState<ConnectionRequest[]>([]);
+  const incomingRequest = pendingRequests[0] ?? null;
+  // Connected viewers asking for input control, shown once no connection request is waiting
+  const [controlRequests, setControlRequests] = useState<ControlRequest[]>([]);
+  const controlRequest = incomingRequest ? null : controlRequests[0] ?? null;
   const [p2pEnabled, setP2pEnabled] = useState(true);
   const [connectionType, setConnectionType] = useState('None');
+  const [hostOffline, setHostOffline] = useState(false);
   const [settingsOpen, setSettingsOpen] = useState(false);
 
   useEffect(() => {
@@ -54,7 +68,10 @@ function App() {
     }).catch(console.error);
 
     // Listen for incoming connections
-    invoke('start_host_listener').catch(console.error);
+    invoke('start_host_listener').catch((error) => {
+      console.error(error);
+      setHostOffline(true);
+    });
 
     // Listen for connection request events from backend
     const unlistenRequest = listen<ConnectionRequest>('connection-request', async (event) => {
@@ -65,42 +82,110 @@ function App() {
         const isTrusted = await invoke<boolean>('is_device_trusted', { deviceId: event.payload.remote_id });
         if (isTrusted) {
           console.log('Auto-accepting trusted device:', event.payload.remote_id);
-          await invoke('respond_to_connection', { accept: true });
+          await invoke('respond_to_connection', { accept: true, requestId: event.payload.request_id });
           return;
         }
       } catch (error) {
         console.error('Failed to check trusted device:', error);
       }
 
-      // Not trusted - show popup
-      setIncomingConnection(event.payload.remote_id);
+      // Not trusted - queue for the popup
+      const { request_id, remote_id } = event.payload;
+      setPendingRequests(prev => [...prev, { request_id, remote_id }]);
+    });
+
+    // Answered, declined or timed out - drop it from the queue
+    const unlistenClosed = listen<ConnectionRequest>('connection-request-closed', (event) => {
+      setPendingRequests(prev => prev.filter(r => r.request_id !== event.payload.request_id));
+    });
+
+    // Nobody answered in time - the backend has already declined it
+    const unlistenTimeout = listen<ConnectionRequest>('connection-request-timeout', (event) => {
+      console.log('Connection request timed out:', event.payload.remote_id);
+      setPendingRequests(prev => prev.filter(r => r.request_id !== event.payload.request_id));
     });
 
     // Listen for connection accepted events
     const unlistenAccepted = listen<ConnectionRequest>('connection-accepted', (event) => {
       console.log('Connection accepted from:', event.payload.remote_id);
-      setIncomingConnection(null);
+      setPendingRequests(prev => prev.filter(r => r.request_id !== event.payload.request_id));
       setMode('hosting');
     });
 
-    // Listen for connection type changes
-    const unlistenTypeChange = listen<{ type: string }>('connection-type-changed', (event) => {
+    // A view-only viewer asked for input control
+    const unlistenControlRequested = listen<ControlRequest>('control-requested', (event) => {
+      const { viewer_id, remote_id } = event.payload;
+      setControlRequests(prev => prev.some(r => r.viewer_id === viewer_id)
+        ? prev
+        : [...prev, { viewer_id, remote_id }]);
+    });
+
+    // Granted or revoked (from here or elsewhere) - the request is settled
+    const settleControlRequest = (event: { payload: { viewer_id: number } }) => {
+      setControlRequests(prev => prev.filter(r => r.viewer_id !== event.payload.viewer_id));
+    };
+    const unlistenControlGranted = listen<{ viewer_id: number }>('control-granted', settleControlRequest);
+    const unlistenControlRevoked = listen<{ viewer_id: number }>('control-revoked', settleControlRequest);
+    const unlistenViewerLeft = listen<{ viewer_id: number }>('connection-ended', settleControlRequest);
+
+    // Listen for connection type changes (host side, or a client session moving between relay and P2P)
+    const unlistenTypeChange = listen<{ type: string; session_id?: string }>('connection-type-changed', (event) => {
       console.log('Connection type changed:', event.payload.type);
       setConnectionType(event.payload.type);
     });
 
+    // The host acknowledged a privacy toggle - show what it actually applied
+    const unlistenPrivacy = listen<{ session_id: string; black_screen: boolean; input_blocked: boolean }>('privacy-status', (event) => {
+      setBlackScreen(event.payload.black_screen);
+      setInputBlock(event.payload.input_blocked);
+    });
+
+    // The host gave up reconnecting to the relay (reconnect_max_attempts)
+    const unlistenReconnectFailed = listen<{ attempts: number }>('host-reconnect-failed', (event) => {
+      console.warn('Relay unreachable after', event.payload.attempts, 'attempts');
+      setHostOffline(true);
+    });
+
+    // A viewer's input hit a window protected by the window policy
+    const unlistenPolicyBlock = listen<{ viewer_id: number; pattern: string; title: string; process: string }>('control-blocked-by-policy', (event) => {
+      console.info('Remote input blocked by window policy:', event.payload.pattern, '-', event.payload.title || event.payload.process);
+    });
+
+    // SSO session ran out; with SSO required the backend has dropped our sessions
+    const unlistenSsoExpired = listen<{ disconnected: boolean }>('sso-session-expired', (event) => {
+      console.log('SSO session expired');
+      if (event.payload.disconnected) {
+        setSession(null);
+        setBlackScreen(false);
+        setInputBlock(false);
+        setMode('idle');
+      }
+    });
+
     // Cleanup listeners on unmount
     return () => {
       unlistenRequest.then(fn => fn());
+      unlistenClosed.then(fn => fn());
+      unlistenTimeout.then(fn => fn());
       unlistenAccepted.then(fn => fn());
+      unlistenControlRequested.then(fn => fn());
+      unlistenControlGranted.then(fn => fn());
+      unlistenControlRevoked.then(fn => fn());
+      unlistenViewerLeft.then(fn => fn());
       unlistenTypeChange.then(fn => fn());
+      unlistenSsoExpired.then(fn => fn());
+      unlistenReconnectFailed.then(fn => fn());
+      unlistenPolicyBlock.then(fn => fn());
+      unlistenPrivacy.then(fn => fn());
     };
   }, []);
 
-  const handleConnect = async (remoteId: string) => {
+  const handleConnect = async (remoteId: string, password?: string) => {
     setMode('connecting');
     try {
-      await invoke('connect_to_remote', { remoteId: remoteId.replace(/\s/g, '') });
+      // Device IDs are sent without spaces; aliases are sent as typed
+      const target = /^[\d\s]+$/.test(remoteId) ? remoteId.replace(/\s/g, '') : remoteId;
+      await invoke('connect_to_remote', { remoteId: target, password: password ?? null });
       setSession({
         remoteId,
         remoteName: `Remote-${remoteId.substring(0, 3)}`,
@@ -114,6 +199,23 @@ function App() {
       setConnectionType(connType);
     } catch (error) {
       console.error('Connection failed:', error);
+      // A different device answered for this ID - never retry silently
+      if (errorCode(error) === 'identity_mismatch') {
+        alert(errorMessage(error));
+      }
+      // Over the licensed number of concurrent sessions
+      const upgrade = upgradePrompt(error);
+      if (upgrade) {
+        alert(upgrade);
+      }
+      // An unattended host wants its access password: ask, then try again
+      if (errorCode(error) === 'auth') {
+        const entered = window.prompt(`${errorMessage(error)}. Access password for ${remoteId}:`);
+        if (entered) {
+          void handleConnect(remoteId, entered);
+          return;
+        }
+      }
       setMode('idle');
     }
   };
@@ -169,34 +271,60 @@ function App() {
         name: 'This Computer',
       });
       // Restart host listener with new identity
-      invoke('start_host_listener').catch(console.error);
+      invoke('stop_host_listener')
+        .then(() => invoke('start_host_listener'))
+        .catch(console.error);
     } catch (error) {
       console.error('Regenerate ID failed:', error);
-      alert('Failed to regenerate ID: ' + error);
+      alert('Failed to regenerate ID: ' + errorMessage(error));
+    }
+  };
+
+  const handleReconnectHost = async () => {
+    try {
+      await invoke('start_host_listener');
+      setHostOffline(false);
+    } catch (error) {
+      console.error('Reconnect to relay failed:', error);
     }
   };
 
-  const handleAcceptConnection = async () => {
-    if (!incomingConnection) return;
+  const dropRequest = (requestId: number) => {
+    setPendingRequests(prev => prev.filter(r => r.request_id !== requestId));
+  };
+
+  const handleAcceptConnection = async (remember: TrustDuration) => {
+    if (!incomingRequest) return;
     try {
-      console.log('Accepting connection from:', incomingConnection);
-      await invoke('respond_to_connection', { accept: true });
+      console.log('Accepting connection from:', incomingRequest.remote_id, 'remember:', remember);
+      await invoke('respond_to_connection', { accept: true, requestId: incomingRequest.request_id, remember });
       // The connection-accepted event will handle state updates
     } catch (error) {
       console.error('Accept connection failed:', error);
-      setIncomingConnection(null);
+      dropRequest(incomingRequest.request_id);
     }
   };
 
   const handleDeclineConnection = async () => {
-    if (!incomingConnection) return;
+    if (!incomingRequest) return;
     try {
-      console.log('Declining connection from:', incomingConnection);
-      await invoke('respond_to_connection', { accept: false });
+      console.log('Declining connection from:', incomingRequest.remote_id);
+      await invoke('respond_to_connection', { accept: false, requestId: incomingRequest.request_id });
     } catch (error) {
       console.error('Decline connection failed:', error);
     }
-    setIncomingConnection(null);
+    dropRequest(incomingRequest.request_id);
+  };
+
+  const answerControlRequest = async (grant: boolean) => {
+    if (!controlRequest) return;
+    const { viewer_id } = controlRequest;
+    try {
+      await invoke(grant ? 'grant_control' : 'revoke_control', { viewerId: viewer_id });
+    } catch (error) {
+      console.error('Answering control request failed:', error);
+    }
+    setControlRequests(prev => prev.filter(r => r.viewer_id !== viewer_id));
   };
 
   return (
@@ -205,10 +333,16 @@ function App() {
 
       {/* Connection Popup */}
       <ConnectionPopup
-        remoteId={incomingConnection}
+        remoteId={incomingRequest?.remote_id ?? null}
         onAccept={handleAcceptConnection}
         onDecline={handleDeclineConnection}
       />
+      <ConnectionPopup
+        remoteId={controlRequest?.remote_id ?? null}
+        onAccept={() => answerControlRequest(true)}
+        onDecline={() => answerControlRequest(false)}
+        controlRequest
+      />
 
       {/* Settings Panel */}
       <Settings
@@ -252,6 +386,8 @@ function App() {
                   <ThisDevice
                     device={myDevice}
                     isHosting={mode === 'hosting'}
+                    isOffline={hostOffline}
+                    onReconnect={handleReconnectHost}
                     p2pEnabled={p2pEnabled}
                     onRegenerateId={handleRegenerateId}
                     onOpenSettings={() => setSettingsOpen(true)}
@@ -264,6 +400,9 @@ function App() {
                   />
                 </div>
 
+                {/* Chat with the viewers of this device */}
+                {mode === 'hosting' && <HostChat />}
+
                 {/* Features */}
                 <div className="features-row">
                   <div className="feature-card">
@@ -1,16 +1,20 @@
 import React, { useCallback, useState } from 'react';
-import { invoke } from '@tauri-apps/api/core';
 import { motion, AnimatePresence } from 'framer-motion';
 import './ConnectionPopup.css';
 
+/** How long accepting trusts the device (TrustDuration in src-tauri/src/host.rs) */
+export type TrustDuration = 'once' | 'session' | 'hour' | 'always';
+
 interface ConnectionPopupProps {
   remoteId: string | null;
-  onAccept: () => void;
+  onAccept: (remember: TrustDuration) => void;
   onDecline: () => void;
+  /** An already connected viewer asking for input control, not a new connection */
+  controlRequest?: boolean;
 }
 
-const ConnectionPopup: React.FC<ConnectionPopupProps> = ({ remoteId, onAccept, onDecline }) => {
-  const [trustDevice, setTrustDevice] = useState(false);
+const ConnectionPopup: React.FC<ConnectionPopupProps> = ({ remoteId, onAccept, onDecline, controlRequest = false }) => {
+  const [remember, setRemember] = useState<TrustDuration>('once');
 
   if (!remoteId) return null;
 
@@ -21,22 +25,14 @@ const ConnectionPopup: React.FC<ConnectionPopupProps> = ({ remoteId, onAccept, o
   };
 
   // Direct click handlers that stop propagation
-  const handleAcceptClick = async (e: React.MouseEvent) => {
+  const handleAcceptClick = (e: React.MouseEvent) => {
     e.preventDefault();
     e.stopPropagation();
-    console.log('Accept button clicked, trust:', trustDevice);
-
-    // If trust checkbox is checked, add to trusted devices
-    if (trustDevice && remoteId) {
-      try {
-        await invoke('add_trusted_device', { deviceId: remoteId, name: null });
-        console.log('Device added to trusted list:', remoteId);
-      } catch (error) {
-        console.error('Failed to add trusted device:', error);
-      }
-    }
+    console.log('Accept button clicked, remember:', remember);
 
-    onAccept();
+    // The backend records the trust along with the answer
+    onAccept(controlRequest ? 'once' : remember);
+    setRemember('once');
   };
 
   const handleDeclineClick = useCallback((e: React.MouseEvent) => {
@@ -77,27 +73,38 @@ const ConnectionPopup: React.FC<ConnectionPopupProps> = ({ remoteId, onAccept, o
               </svg>
             </div>
             <div className="popup-content">
-              <h3>Incoming Connection Request</h3>
+              <h3>{controlRequest ? 'Control Request' : 'Incoming Connection Request'}</h3>
               <p className="popup-id">{formatId(remoteId)}</p>
-              <p className="popup-subtitle">wants to connect to your device</p>
+              <p className="popup-subtitle">
+                {controlRequest ? 'is viewing your screen and wants to control it' : 'wants to connect to your device'}
+              </p>
             </div>
             <div className="popup-warning">
               <svg viewBox="0 0 24 24" fill="none" stroke="currentColor" strokeWidth="2">
                 <path d="M12 9v2m0 4h.01m-6.938 4h13.856c1.54 0 2.502-1.667 1.732-3L13.732 4c-.77-1.333-2.694-1.333-3.464 0L3.34 16c-.77 1.333.192 3 1.732 3z" />
               </svg>
-              <span>They will be able to see and control your screen</span>
+              <span>
+                {controlRequest
+                  ? 'They will be able to use your mouse and keyboard'
+                  : 'They will be able to see and control your screen'}
+              </span>
             </div>
 
-            {/* Trust device checkbox */}
-            <label className="trust-checkbox">
-              <input
-                type="checkbox"
-                checked={trustDevice}
-                onChange={(e) => setTrustDevice(e.target.checked)}
-              />
-              <span className="checkmark"></span>
-              <span className="trust-label">Trust this device (auto-accept future connections)</span>
-            </label>
+            {/* How long to let this device in without asking */}
+            {!controlRequest && (
+              <label className="trust-duration">
+                <span className="trust-label">Accept future connections</span>
+                <select
+                  value={remember}
+                  onChange={(e) => setRemember(e.target.value as TrustDuration)}
+                >
+                  <option value="once">Ask every time</option>
+                  <option value="session">Until hosting stops</option>
+                  <option value="hour">For 1 hour</option>
+                  <option value="always">Always (trust this device)</option>
+                </select>
+              </label>
+            )}
 
             <div className="popup-actions">
               <button
@@ -0,0 +1,93 @@
+import React, { useEffect, useRef, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
+import { listen } from '@tauri-apps/api/event';
+import { FiMessageSquare, FiSend } from 'react-icons/fi';
+import './HostChat.css';
+
+interface ChatMessageEvent {
+  session_id: string | null;
+  from: string;
+  text: string;
+}
+
+interface ChatEntry {
+  from: 'local' | 'remote';
+  text: string;
+}
+
+// Chat with the viewers of this device; messages go to every connected viewer
+const HostChat: React.FC = () => {
+  const [messages, setMessages] = useState<ChatEntry[]>([]);
+  const [draft, setDraft] = useState('');
+  const listRef = useRef<HTMLDivElement>(null);
+
+  useEffect(() => {
+    const unlistenChat = listen<ChatMessageEvent>('chat-message', (event) => {
+      // Messages with a session id belong to a session we are viewing
+      if (event.payload.session_id !== null) return;
+      setMessages(prev => [...prev, { from: 'remote', text: event.payload.text }]);
+    });
+
+    return () => {
+      unlistenChat.then(fn => fn());
+    };
+  }, []);
+
+  useEffect(() => {
+    listRef.current?.scrollTo({ top: listRef.current.scrollHeight });
+  }, [messages]);
+
+  const send = async () => {
+    const text = draft.trim();
+    if (!text) return;
+    try {
+      await invoke('send_host_chat', { text });
+      setMessages(prev => [...prev, { from: 'local', text }]);
+      setDraft('');
+    } catch (error) {
+      console.error('Failed to send chat message:', error);
+    }
+  };
+
+  return (
+    <div className="panel host-chat">
+      <div className="panel-header">
+        <div className="panel-title">
+          <FiMessageSquare className="panel-icon" />
+          <span>Chat</span>
+        </div>
+        <div className="panel-subtitle">Messages to and from the devices viewing this one</div>
+      </div>
+
+      <div className="host-chat-messages" ref={listRef}>
+        {messages.length === 0 ? (
+          <div className="host-chat-empty">No messages yet</div>
+        ) : (
+          messages.map((msg, i) => (
+            <div key={i} className={`host-chat-message ${msg.from}`}>
+              {msg.text}
+            </div>
+          ))
+        )}
+      </div>
+
+      <div className="host-chat-input">
+        <input
+          type="text"
+          value={draft}
+          maxLength={4096}
+          placeholder="Type a message..."
+          onChange={(e) => setDraft(e.target.value)}
+          onKeyDown={(e) => {
+            if (e.key === 'Enter') send();
+          }}
+        />
+        <button className="host-chat-send" onClick={send} title="Send" disabled={!draft.trim()}>
+          <FiSend />
+        </button>
+      </div>
+    </div>
+  );
+};
+
+export default HostChat;
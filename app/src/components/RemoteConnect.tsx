@@ -1,8 +1,17 @@
 import React, { useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
 import { motion } from 'framer-motion';
-import { FiArrowRight, FiLoader, FiLink } from 'react-icons/fi';
+import { FiArrowRight, FiLoader, FiLink, FiRefreshCw } from 'react-icons/fi';
 import './RemoteConnect.css';
 
+// A host announcing itself on the local network (lan.rs)
+interface LanPeer {
+  device_id: string;
+  alias: string | null;
+  name: string;
+  address: string;
+}
+
 interface RemoteConnectProps {
   onConnect: (remoteId: string) => void;
   isConnecting: boolean;
@@ -10,8 +19,16 @@ interface RemoteConnectProps {
 
 const RemoteConnect: React.FC<RemoteConnectProps> = ({ onConnect, isConnecting }) => {
   const [remoteId, setRemoteId] = useState('');
+  const [lanPeers, setLanPeers] = useState<LanPeer[]>([]);
+  const [isScanning, setIsScanning] = useState(false);
+
+  // Anything that doesn't start with a digit is treated as an alias
+  const isAlias = (value: string) => /^\s*[^\d\s]/.test(value);
 
   const formatId = (value: string) => {
+    if (isAlias(value)) {
+      return value.slice(0, 64);
+    }
     const digits = value.replace(/\D/g, '').slice(0, 9);
     const parts = [];
     for (let i = 0; i < digits.length; i += 3) {
@@ -20,14 +37,29 @@ const RemoteConnect: React.FC<RemoteConnectProps> = ({ onConnect, isConnecting }
     return parts.join(' ');
   };
 
+  const isValidId = isAlias(remoteId)
+    ? remoteId.trim().length > 0
+    : remoteId.replace(/\s/g, '').length === 9;
+
   const handleInputChange = (e: React.ChangeEvent<HTMLInputElement>) => {
     setRemoteId(formatId(e.target.value));
   };
 
   const handleSubmit = (e: React.FormEvent) => {
     e.preventDefault();
-    if (remoteId.replace(/\s/g, '').length === 9 && !isConnecting) {
-      onConnect(remoteId);
+    if (isValidId && !isConnecting) {
+      onConnect(isAlias(remoteId) ? remoteId.trim() : remoteId);
+    }
+  };
+
+  const scanLan = async () => {
+    setIsScanning(true);
+    try {
+      setLanPeers(await invoke<LanPeer[]>('discover_lan_peers'));
+    } catch (error) {
+      console.error('LAN discovery failed:', error);
+    } finally {
+      setIsScanning(false);
     }
   };
 
@@ -37,8 +69,6 @@ const RemoteConnect: React.FC<RemoteConnectProps> = ({ onConnect, isConnecting }
     }
   };
 
-  const isValidId = remoteId.replace(/\s/g, '').length === 9;
-
   return (
     <div className="panel remote-connect">
       <div className="panel-header">
@@ -46,16 +76,16 @@ const RemoteConnect: React.FC<RemoteConnectProps> = ({ onConnect, isConnecting }
           <FiLink className="panel-icon" />
           <span>Remote Desktop</span>
         </div>
-        <div className="panel-subtitle">Enter the ID of the device to connect</div>
+        <div className="panel-subtitle">Enter the ID or alias of the device to connect</div>
       </div>
 
       <form className="connect-form" onSubmit={handleSubmit}>
         <div className="input-wrapper">
-          <label className="input-label">Remote ID</label>
+          <label className="input-label">Remote ID or Alias</label>
           <input
             type="text"
             className="remote-id-input"
-            placeholder="000 000 000"
+            placeholder="000 000 000 or alias"
             value={remoteId}
             onChange={handleInputChange}
             onKeyDown={handleKeyDown}
@@ -85,6 +115,41 @@ const RemoteConnect: React.FC<RemoteConnectProps> = ({ onConnect, isConnecting }
         </motion.button>
       </form>
 
+      <div className="recent-section">
+        <div className="recent-label">
+          Nearby Devices
+          <button
+            type="button"
+            className="lan-scan-button"
+            onClick={scanLan}
+            disabled={isScanning}
+            title="Look for SecureDesk devices on this network"
+          >
+            <FiRefreshCw className={isScanning ? 'spin' : undefined} />
+          </button>
+        </div>
+        <div className="recent-list">
+          {lanPeers.length === 0 ? (
+            <div className="recent-empty">{isScanning ? 'Searching...' : 'No devices found on this network'}</div>
+          ) : (
+            lanPeers.map((peer) => (
+              <div
+                key={peer.device_id}
+                className="recent-item"
+                onClick={() => !isConnecting && onConnect(peer.device_id)}
+                title="Connects through the relay, then directly over this network"
+              >
+                <div className="recent-item-info">
+                  <span className="recent-item-id">{peer.alias ?? peer.name}</span>
+                  <span className="recent-item-time">{formatId(peer.device_id)} · {peer.address}</span>
+                </div>
+                <FiArrowRight />
+              </div>
+            ))
+          )}
+        </div>
+      </div>
+
       <div className="recent-section">
         <div className="recent-label">Recent Connections</div>
         <div className="recent-list">
@@ -24,15 +24,21 @@ import {
   FiUpload,
   FiCircle,
   FiSquare,
+  FiShield,
+  FiMessageSquare,
+  FiSend,
+  FiTarget,
+  FiVolume2,
+  FiVolumeX,
 } from 'react-icons/fi';
 import { SessionInfo } from '../App';
+import { upgradePrompt } from '../errors';
 import './SessionView.css';
 
-interface VideoFrame {
-  width: number;
-  height: number;
-  data: string; // Base64 encoded
-}
+// request_video_frame_binary answers with this header, then the image bytes:
+// [format u8 (1 = PNG)][reserved u8][width u16 LE][height u16 LE][interval_ms u16 LE]
+const FRAME_HEADER_BYTES = 8;
+const FRAME_FORMAT_PNG = 1;
 
 interface SessionViewProps {
   session: SessionInfo;
@@ -76,6 +82,84 @@ interface ClipboardContent {
   text?: string;
   image_data?: string;
   files?: string[];
+  html?: string;
+  rtf?: string;
+}
+
+interface RemoteSessionInfo {
+  session_id: string;
+  is_active: boolean;
+  host_elevated: boolean | null;
+  focus_lock: boolean | null;
+  audio: boolean | null;
+  quality: string;
+}
+
+interface CaptureSource {
+  id: number;
+  name: string;
+  width: number;
+  height: number;
+  primary: boolean;
+}
+
+interface ChatMessageEvent {
+  session_id: string | null;
+  from: string;
+  text: string;
+}
+
+interface ResolutionChangedEvent {
+  session_id: string;
+  width: number;
+  height: number;
+}
+
+interface CaptureUnavailableEvent {
+  session_id: string;
+  reason: string;
+}
+
+interface ControlChangeEvent {
+  session_id: string;
+}
+
+// Host cursor in frame pixels, at its hotspot; image only when it changed
+interface RemoteCursorEvent {
+  session_id: string;
+  visible: boolean;
+  x: number;
+  y: number;
+  image: { hotspot_x: number; hotspot_y: number; png: string } | null;
+}
+
+interface RemoteCursor {
+  visible: boolean;
+  x: number;
+  y: number;
+  hotspotX: number;
+  hotspotY: number;
+  url: string | null;
+}
+
+interface ChatEntry {
+  from: 'local' | 'remote';
+  text: string;
+}
+
+interface ClipboardSyncResult {
+  session_id: string;
+  success: boolean;
+  error: string | null;
+  data_type: string;
+  byte_count: number;
+}
+
+interface ClipboardTooLarge {
+  session_id: string | null;
+  data_type: string;
+  byte_count: number;
+  max_size: number;
 }
 
 const SessionView: React.FC<SessionViewProps> = ({
@@ -91,8 +175,16 @@ const SessionView: React.FC<SessionViewProps> = ({
   const [controlMode, setControlMode] = useState(true);
   const [zoom, setZoom] = useState(100);
   const [duration, setDuration] = useState('00:00:00');
-  const [frameData, setFrameData] = useState<string | null>(null);
+  // Object URL of the latest frame
+  const [frameSrc, setFrameSrc] = useState<string | null>(null);
   const [frameSize, setFrameSize] = useState({ width: 1920, height: 1080 });
+  const [captureError, setCaptureError] = useState<string | null>(null);
+  const [remoteCursor, setRemoteCursor] = useState<RemoteCursor | null>(null);
+  // We don't hold input control on the host; our input is ignored
+  const [viewOnly, setViewOnly] = useState(false);
+  const [controlRequested, setControlRequested] = useState(false);
+  const [remoteFingerprint, setRemoteFingerprint] = useState<string | null>(null);
+  const [encrypted, setEncrypted] = useState<boolean | null>(null);
   const [fps, setFps] = useState(0);
   const [latency, setLatency] = useState(0);
   const [showClipboardPanel, setShowClipboardPanel] = useState(false);
@@ -101,10 +193,25 @@ const SessionView: React.FC<SessionViewProps> = ({
   const [localClipboard, setLocalClipboard] = useState<ClipboardContent | null>(null);
   const [isRecording, setIsRecording] = useState(false);
   const [recordingDuration, setRecordingDuration] = useState('00:00');
+  const [hostElevated, setHostElevated] = useState<boolean | null>(null);
+  // Keystrokes pinned to the window last clicked on the host (null = host can't)
+  const [focusLock, setFocusLock] = useState<boolean | null>(null);
+  const [audio, setAudio] = useState<boolean | null>(null);
+  const [quality, setQuality] = useState('auto');
+  const [activeSessionId, setActiveSessionId] = useState<string | null>(null);
+  const [monitors, setMonitors] = useState<CaptureSource[]>([]);
+  // Monitor index, or 'all' for every monitor stitched together
+  const [monitor, setMonitor] = useState<string | null>(null);
+  const [showChatPanel, setShowChatPanel] = useState(false);
+  const [chatMessages, setChatMessages] = useState<ChatEntry[]>([]);
+  const [chatDraft, setChatDraft] = useState('');
+  const [unreadChat, setUnreadChat] = useState(0);
   const viewportRef = useRef<HTMLDivElement>(null);
   const canvasRef = useRef<HTMLCanvasElement>(null);
   const imageRef = useRef<HTMLImageElement>(null);
   const frameCountRef = useRef(0);
+  // The frame on screen and the one before it; older ones are revoked
+  const frameUrlsRef = useRef<string[]>([]);
   const lastFpsTimeRef = useRef(Date.now());
 
   // Send viewport resolution to host for adaptive scaling
@@ -139,22 +246,107 @@ const SessionView: React.FC<SessionViewProps> = ({
     };
   }, [sendViewportResolution]);
 
+  // Host display resolution changed: reflow to the new size right away
+  useEffect(() => {
+    const unlistenResolution = listen<ResolutionChangedEvent>('resolution-changed', (event) => {
+      setFrameSize({ width: event.payload.width, height: event.payload.height });
+      sendViewportResolution();
+    });
+
+    return () => {
+      unlistenResolution.then(fn => fn());
+    };
+  }, [sendViewportResolution]);
+
+  // Verified host fingerprint, for reading back to the remote user
+  useEffect(() => {
+    invoke<string | null>('get_remote_fingerprint')
+      .then(setRemoteFingerprint)
+      .catch(console.error);
+    invoke<boolean>('is_encrypted')
+      .then(setEncrypted)
+      .catch(console.error);
+  }, [session.remoteId]);
+
+  // Host can't capture its screen (permission, no display) - say why instead of a blank view
+  useEffect(() => {
+    const unlistenUnavailable = listen<CaptureUnavailableEvent>('capture-unavailable', (event) => {
+      setCaptureError(event.payload.reason);
+    });
+    const unlistenAvailable = listen('capture-available', () => {
+      setCaptureError(null);
+    });
+
+    return () => {
+      unlistenUnavailable.then(fn => fn());
+      unlistenAvailable.then(fn => fn());
+    };
+  }, []);
+
+  // The host sends its cursor apart from the video, so it stays sharp and current
+  useEffect(() => {
+    const unlistenCursor = listen<RemoteCursorEvent>('remote-cursor', (event) => {
+      const { visible, x, y, image } = event.payload;
+      setRemoteCursor(previous => ({
+        visible,
+        x,
+        y,
+        hotspotX: image ? image.hotspot_x : previous?.hotspotX ?? 0,
+        hotspotY: image ? image.hotspot_y : previous?.hotspotY ?? 0,
+        url: image ? `data:image/png;base64,${image.png}` : previous?.url ?? null,
+      }));
+    });
+
+    return () => {
+      unlistenCursor.then(fn => fn());
+    };
+  }, []);
+
+  // The host grants input control to one viewer at a time, and may start us view-only
+  useEffect(() => {
+    const unlistenGranted = listen<ControlChangeEvent>('control-granted', () => {
+      setViewOnly(false);
+      setControlRequested(false);
+    });
+    const unlistenRevoked = listen<ControlChangeEvent>('control-revoked', () => {
+      setViewOnly(true);
+      setControlRequested(false);
+    });
+
+    return () => {
+      unlistenGranted.then(fn => fn());
+      unlistenRevoked.then(fn => fn());
+    };
+  }, []);
+
   // Request video frames continuously
   useEffect(() => {
     let running = true;
     let consecutiveErrors = 0;
+    let interval = 33;
 
     const fetchFrame = async () => {
       if (!running) return;
 
       const startTime = Date.now();
       try {
-        const frame = await invoke<VideoFrame | null>('request_video_frame');
-        if (frame && running) {
-          setFrameData(frame.data);
-          setFrameSize({ width: frame.width, height: frame.height });
+        // Raw bytes rather than base64 in JSON - a third less data and no big strings per frame
+        const buffer = await invoke<ArrayBuffer>('request_video_frame_binary');
+        // No new frame (empty body) would render as a broken image; keep the current frame instead
+        if (buffer.byteLength > FRAME_HEADER_BYTES && running) {
+          const header = new DataView(buffer, 0, FRAME_HEADER_BYTES);
+          const type = header.getUint8(0) === FRAME_FORMAT_PNG ? 'image/png' : 'image/jpeg';
+          const url = URL.createObjectURL(new Blob([new Uint8Array(buffer, FRAME_HEADER_BYTES)], { type }));
+          // Keep the previous frame until the new one has replaced it on screen
+          frameUrlsRef.current.push(url);
+          while (frameUrlsRef.current.length > 2) {
+            URL.revokeObjectURL(frameUrlsRef.current.shift()!);
+          }
+          setFrameSrc(url);
+          setFrameSize({ width: header.getUint16(2, true), height: header.getUint16(4, true) });
           setLatency(Date.now() - startTime);
           consecutiveErrors = 0;
+          interval = header.getUint16(6, true) || interval;
 
           // Count FPS
           frameCountRef.current++;
@@ -174,9 +366,9 @@ const SessionView: React.FC<SessionViewProps> = ({
         }
       }
 
-      // Request next frame (target ~30 FPS)
+      // Request next frame at the session's pace (~30 FPS unless adapting)
       if (running) {
-        setTimeout(fetchFrame, 33);
+        setTimeout(fetchFrame, interval);
       }
     };
 
@@ -184,6 +376,8 @@ const SessionView: React.FC<SessionViewProps> = ({
 
     return () => {
       running = false;
+      frameUrlsRef.current.forEach(url => URL.revokeObjectURL(url));
+      frameUrlsRef.current = [];
     };
   }, []);
 
@@ -232,9 +426,12 @@ const SessionView: React.FC<SessionViewProps> = ({
     if (!controlMode) return;
     e.preventDefault();
 
-    // Normalize scroll delta (different browsers report different values)
-    const deltaY = Math.sign(e.deltaY) * -1; // Invert for natural scrolling
-    const deltaX = Math.sign(e.deltaX);
+    // Convert to high-resolution wheel units (120 per notch) so trackpads scroll
+    // smoothly; sensitivity and natural scrolling are applied by the backend
+    const unitsPerDelta = e.deltaMode === 1 ? 40 : e.deltaMode === 2 ? 360 : 1.2;
+    const deltaY = Math.round(-e.deltaY * unitsPerDelta); // Positive = scroll up
+    const deltaX = Math.round(e.deltaX * unitsPerDelta);
+    if (deltaX === 0 && deltaY === 0) return;
 
     try {
       await invoke('send_mouse', { x: deltaX, y: deltaY, eventType: 'scroll', button: null });
@@ -247,6 +444,9 @@ const SessionView: React.FC<SessionViewProps> = ({
   const handleKeyEvent = useCallback(async (e: KeyboardEvent, pressed: boolean) => {
     if (!controlMode) return;
 
+    // Typing into a panel field (e.g. chat) stays local
+    if (e.target instanceof HTMLInputElement) return;
+
     // Prevent default for most keys to avoid browser shortcuts
     if (e.code !== 'F5' && e.code !== 'F12') {
       e.preventDefault();
@@ -302,9 +502,8 @@ const SessionView: React.FC<SessionViewProps> = ({
   const sendClipboardToRemote = useCallback(async () => {
     try {
       setClipboardStatus('Sending...');
+      // Outcome is reported by the 'clipboard-sync-result' event
       await invoke('send_clipboard_to_remote');
-      setClipboardStatus('Sent!');
-      setTimeout(() => setClipboardStatus(null), 2000);
     } catch (error) {
       console.error('Failed to send clipboard:', error);
       setClipboardStatus('Failed');
@@ -332,9 +531,40 @@ const SessionView: React.FC<SessionViewProps> = ({
       await invoke('set_clipboard_sync_enabled', { enabled: newState });
     } catch (error) {
       console.error('Failed to toggle clipboard sync:', error);
+      setClipboardSyncEnabled(!newState);
+      const upgrade = upgradePrompt(error);
+      if (upgrade) {
+        alert(upgrade);
+      }
     }
   }, [clipboardSyncEnabled]);
 
+  // Chat with the host
+  const sendChat = useCallback(async () => {
+    const text = chatDraft.trim();
+    if (!text) return;
+    try {
+      await invoke('send_chat', { text });
+      setChatMessages(prev => [...prev, { from: 'local', text }]);
+      setChatDraft('');
+    } catch (error) {
+      console.error('Failed to send chat message:', error);
+    }
+  }, [chatDraft]);
+
+  useEffect(() => {
+    const unlistenChat = listen<ChatMessageEvent>('chat-message', (event) => {
+      // Host-side messages (no session id) are shown by HostChat in the host view
+      if (event.payload.session_id === null) return;
+      setChatMessages(prev => [...prev, { from: 'remote', text: event.payload.text }]);
+      setUnreadChat(prev => (showChatPanel ? 0 : prev + 1));
+    });
+
+    return () => {
+      unlistenChat.then(fn => fn());
+    };
+  }, [showChatPanel]);
+
   // Listen for clipboard events
   useEffect(() => {
     const unlistenClipboard = listen('clipboard-received', () => {
@@ -343,11 +573,30 @@ const SessionView: React.FC<SessionViewProps> = ({
       refreshLocalClipboard();
     });
 
+    const unlistenSyncResult = listen<ClipboardSyncResult>('clipboard-sync-result', (event) => {
+      const { success, error } = event.payload;
+      if (!success) {
+        console.error('Remote failed to apply clipboard:', error);
+      }
+      setClipboardStatus(success ? 'Sent!' : `Failed: ${error ?? 'unknown error'}`);
+      setTimeout(() => setClipboardStatus(null), 2000);
+    });
+
+    const unlistenTooLarge = listen<ClipboardTooLarge>('clipboard-too-large', (event) => {
+      // Host-side clipboards (no session id) are not this view's
+      if (event.payload.session_id === null) return;
+      const mb = (bytes: number) => (bytes / (1024 * 1024)).toFixed(1);
+      setClipboardStatus(`Too large: ${mb(event.payload.byte_count)} MB (limit ${mb(event.payload.max_size)} MB)`);
+      setTimeout(() => setClipboardStatus(null), 3000);
+    });
+
     // Initial clipboard sync state
     invoke<boolean>('get_clipboard_sync_enabled').then(setClipboardSyncEnabled).catch(console.error);
 
     return () => {
       unlistenClipboard.then(fn => fn());
+      unlistenSyncResult.then(fn => fn());
+      unlistenTooLarge.then(fn => fn());
     };
   }, [refreshLocalClipboard]);
 
@@ -390,6 +639,103 @@ const SessionView: React.FC<SessionViewProps> = ({
     invoke<boolean>('is_recording').then(setIsRecording).catch(console.error);
   }, []);
 
+  // Track whether the host runs elevated (reported by the host after accept)
+  useEffect(() => {
+    const interval = setInterval(async () => {
+      try {
+        const sessions = await invoke<RemoteSessionInfo[]>('list_sessions');
+        const active = sessions.find(s => s.is_active);
+        setHostElevated(active?.host_elevated ?? null);
+        setFocusLock(active?.focus_lock ?? null);
+        setAudio(active?.audio ?? null);
+        if (active) setQuality(active.quality);
+        setActiveSessionId(active?.session_id ?? null);
+      } catch (error) {
+        console.error('Failed to get session info:', error);
+      }
+    }, 2000);
+
+    return () => clearInterval(interval);
+  }, []);
+
+  const changeQuality = useCallback(async (level: string) => {
+    try {
+      await invoke('set_session_quality', { level });
+      setQuality(level);
+    } catch (error) {
+      console.error('Failed to change quality:', error);
+    }
+  }, []);
+
+  // The remote host's monitors, for switching which one is captured
+  useEffect(() => {
+    if (!activeSessionId) return;
+    invoke<CaptureSource[]>('get_host_monitors', { sessionId: activeSessionId })
+      .then((listed) => {
+        setMonitors(listed);
+        const primary = listed.find(m => m.primary);
+        setMonitor((current) => current ?? (primary ? String(primary.id) : null));
+      })
+      .catch(() => setMonitors([]));
+  }, [activeSessionId]);
+
+  const changeMonitor = useCallback(async (value: string) => {
+    const mode = value === 'all'
+      ? { kind: 'all_monitors' }
+      : { kind: 'single', index: Number(value) };
+    try {
+      await invoke('set_capture_mode', { mode });
+      setMonitor(value);
+    } catch (error) {
+      console.error('Failed to switch monitor:', error);
+    }
+  }, []);
+
+  const requestHostElevation = useCallback(async () => {
+    try {
+      await invoke('request_host_elevation');
+    } catch (error) {
+      console.error('Failed to request elevation:', error);
+    }
+  }, []);
+
+  const requestControl = useCallback(async () => {
+    try {
+      await invoke('request_control');
+      setControlRequested(true);
+    } catch (error) {
+      console.error('Failed to request control:', error);
+    }
+  }, []);
+
+  const toggleFocusLock = useCallback(async () => {
+    const enabled = !focusLock;
+    try {
+      await invoke('set_focus_lock', { enabled });
+      setFocusLock(enabled);
+    } catch (error) {
+      console.error('Failed to toggle focus lock:', error);
+    }
+  }, [focusLock]);
+
+  const toggleAudio = useCallback(async () => {
+    const enabled = !audio;
+    try {
+      await invoke('set_audio_enabled', { enabled });
+      setAudio(enabled);
+    } catch (error) {
+      console.error('Failed to toggle sound:', error);
+    }
+  }, [audio]);
+
+  // While controlling, the local pointer wears the host cursor and moves at
+  // once; otherwise the host cursor is drawn where the host says it is
+  const controlling = controlMode && !viewOnly;
+  const localCursor = controlling && remoteCursor?.url
+    ? `url(${remoteCursor.url}) ${remoteCursor.hotspotX} ${remoteCursor.hotspotY}, default`
+    : 'none';
+  const showRemoteCursor = !controlling && remoteCursor?.visible && remoteCursor.url;
+
   return (
     <div className="session-view" ref={viewportRef}>
       {/* Toolbar */}
@@ -440,6 +786,39 @@ const SessionView: React.FC<SessionViewProps> = ({
               {inputBlock ? <FiLock /> : <FiUnlock />}
               <span>Block Input</span>
             </button>
+            <button
+              className={`toolbar-btn privacy ${hostElevated ? 'active' : ''}`}
+              onClick={requestHostElevation}
+              disabled={hostElevated !== false}
+              title={hostElevated
+                ? 'Host is elevated - input reaches admin dialogs'
+                : 'Ask the host to restart as administrator'}
+            >
+              <FiShield />
+              <span>{hostElevated ? 'Elevated' : 'Request Admin'}</span>
+            </button>
+            <button
+              className={`toolbar-btn privacy ${focusLock ? 'active' : ''}`}
+              onClick={toggleFocusLock}
+              disabled={focusLock === null}
+              title={focusLock === null
+                ? 'The host cannot pin keyboard focus'
+                : 'Keep typing in the window you last clicked, even if another window takes focus'}
+            >
+              <FiTarget />
+              <span>Pin Focus</span>
+            </button>
+            <button
+              className={`toolbar-btn privacy ${audio ? 'active' : ''}`}
+              onClick={toggleAudio}
+              disabled={audio === null}
+              title={audio === null
+                ? 'The host does not send sound'
+                : audio ? 'Mute the host\'s sound' : 'Play the host\'s sound'}
+            >
+              {audio === false ? <FiVolumeX /> : <FiVolume2 />}
+              <span>Sound</span>
+            </button>
           </div>
 
           <div className="toolbar-sep" />
@@ -463,6 +842,45 @@ const SessionView: React.FC<SessionViewProps> = ({
             >
               <FiClipboard />
             </button>
+            <button
+              className={`toolbar-btn icon-only ${showChatPanel ? 'active' : ''}`}
+              title={unreadChat > 0 ? `Chat (${unreadChat} new)` : 'Chat'}
+              onClick={() => {
+                setShowChatPanel(!showChatPanel);
+                setUnreadChat(0);
+              }}
+            >
+              <FiMessageSquare />
+              {unreadChat > 0 && <span className="chat-badge">{unreadChat}</span>}
+            </button>
+            <select
+              className="toolbar-select"
+              value={quality}
+              onChange={(e) => changeQuality(e.target.value)}
+              title="Quality"
+            >
+              <option value="auto">Auto</option>
+              <option value="quality">Best Quality</option>
+              <option value="balanced">Balanced</option>
+              <option value="speed">Best Speed</option>
+              <option value="lossless">Lossless</option>
+            </select>
+            {monitors.length > 1 && (
+              <select
+                className="toolbar-select"
+                value={monitor ?? ''}
+                onChange={(e) => changeMonitor(e.target.value)}
+                disabled={viewOnly}
+                title="Monitor"
+              >
+                {monitors.map((m) => (
+                  <option key={m.id} value={String(m.id)}>
+                    {m.name} ({m.width}x{m.height})
+                  </option>
+                ))}
+                <option value="all">All monitors</option>
+              </select>
+            )}
             <button className="toolbar-btn icon-only" title="File Transfer">
               <FiFolder />
             </button>
@@ -524,10 +942,10 @@ const SessionView: React.FC<SessionViewProps> = ({
           className="canvas-content"
           style={{ transform: `scale(${zoom / 100})` }}
         >
-          {frameData ? (
+          {frameSrc ? (
             <img
               ref={imageRef}
-              src={`data:image/jpeg;base64,${frameData}`}
+              src={frameSrc}
               alt="Remote Desktop"
               className="remote-frame"
               tabIndex={0}
@@ -535,7 +953,7 @@ const SessionView: React.FC<SessionViewProps> = ({
                 width: '100%',
                 height: '100%',
                 objectFit: 'contain',
-                cursor: controlMode ? 'none' : 'default',
+                cursor: controlMode ? localCursor : 'default',
                 outline: 'none',
               }}
               onMouseMove={(e) => handleMouseEvent(e, 'move')}
@@ -550,11 +968,42 @@ const SessionView: React.FC<SessionViewProps> = ({
               <FiMonitor className="placeholder-icon" />
               <span className="placeholder-title">Remote Desktop</span>
               <span className="placeholder-text">
-                Connecting to video stream...
+                {captureError
+                  ? `The remote device can't capture its screen: ${captureError}`
+                  : 'Connecting to video stream...'}
               </span>
             </div>
           )}
+          {frameSrc && showRemoteCursor && remoteCursor && (
+            <img
+              className="remote-cursor"
+              src={remoteCursor.url ?? undefined}
+              alt=""
+              style={{
+                left: `${(remoteCursor.x / frameSize.width) * 100}%`,
+                top: `${(remoteCursor.y / frameSize.height) * 100}%`,
+                transform: `translate(${-remoteCursor.hotspotX}px, ${-remoteCursor.hotspotY}px)`,
+              }}
+            />
+          )}
         </div>
+        {frameSrc && captureError && (
+          <div className="capture-error-banner">
+            Screen capture stopped on the remote device: {captureError}. Retrying...
+          </div>
+        )}
+        {frameSrc && !captureError && viewOnly && (
+          <div className="capture-error-banner">
+            View only - you don't have control of this device{' '}
+            <button
+              className="banner-action"
+              onClick={requestControl}
+              disabled={controlRequested}
+            >
+              {controlRequested ? 'Control requested...' : 'Request Control'}
+            </button>
+          </div>
+        )}
 
         {/* Clipboard Panel */}
         <AnimatePresence>
@@ -617,12 +1066,17 @@ const SessionView: React.FC<SessionViewProps> = ({
                   <h4>Local Clipboard</h4>
                   {localClipboard ? (
                     <div className="clipboard-content">
-                      {localClipboard.data_type === 'text' && (
+                      {(localClipboard.data_type === 'text' || localClipboard.data_type === 'rich') && (
                         <pre className="clipboard-text">
                           {localClipboard.text?.substring(0, 500)}
                           {(localClipboard.text?.length || 0) > 500 && '...'}
                         </pre>
                       )}
+                      {localClipboard.data_type === 'rich' && (
+                        <div className="clipboard-format">
+                          Formatted text ({[localClipboard.html && 'HTML', localClipboard.rtf && 'RTF'].filter(Boolean).join(', ')})
+                        </div>
+                      )}
                       {localClipboard.data_type === 'image' && (
                         <div className="clipboard-image">
                           <FiCopy /> Image in clipboard
@@ -649,6 +1103,56 @@ const SessionView: React.FC<SessionViewProps> = ({
           )}
         </AnimatePresence>
 
+        {/* Chat Panel */}
+        <AnimatePresence>
+          {showChatPanel && (
+            <motion.div
+              className="clipboard-panel chat-panel"
+              initial={{ opacity: 0, x: 20 }}
+              animate={{ opacity: 1, x: 0 }}
+              exit={{ opacity: 0, x: 20 }}
+            >
+              <div className="clipboard-panel-header">
+                <h3>Chat</h3>
+                <button
+                  className="clipboard-close"
+                  onClick={() => setShowChatPanel(false)}
+                >
+                  <FiX />
+                </button>
+              </div>
+
+              <div className="chat-messages">
+                {chatMessages.length === 0 ? (
+                  <div className="clipboard-empty">No messages yet</div>
+                ) : (
+                  chatMessages.map((msg, i) => (
+                    <div key={i} className={`chat-message ${msg.from}`}>
+                      {msg.text}
+                    </div>
+                  ))
+                )}
+              </div>
+
+              <div className="chat-input">
+                <input
+                  type="text"
+                  value={chatDraft}
+                  maxLength={4096}
+                  placeholder="Type a message..."
+                  onChange={(e) => setChatDraft(e.target.value)}
+                  onKeyDown={(e) => {
+                    if (e.key === 'Enter') sendChat();
+                  }}
+                />
+                <button className="clipboard-action-btn" onClick={sendChat} title="Send">
+                  <FiSend />
+                </button>
+              </div>
+            </motion.div>
+          )}
+        </AnimatePresence>
+
         {/* Privacy Indicator - shows when black screen is active on remote */}
         {blackScreen && (
           <motion.div
@@ -666,16 +1170,27 @@ const SessionView: React.FC<SessionViewProps> = ({
       <div className="session-status">
         <div className="status-left">
           <span className="status-item">
-            <span className={`status-dot ${frameData ? 'connected' : 'connecting'}`} />
-            {frameData ? 'Streaming' : 'Connecting...'}
+            <span className={`status-dot ${frameSrc ? 'connected' : 'connecting'}`} />
+            {frameSrc ? 'Streaming' : 'Connecting...'}
           </span>
           <span className={`status-item connection-badge ${connectionType === 'P2P' ? 'p2p' : 'relay'}`}>
             {connectionType === 'P2P' ? '⚡ P2P Direct' : '🔒 Relay'}
           </span>
-          <span className="status-item">
-            <FiLock className="status-lock" />
-            E2E Encrypted
-          </span>
+          {encrypted === false ? (
+            <span className="status-item" title="Traffic is not end-to-end encrypted">
+              <FiUnlock className="status-lock unencrypted" />
+              Not Encrypted
+            </span>
+          ) : (
+            <span
+              className="status-item"
+              title={remoteFingerprint ? `Remote fingerprint: ${remoteFingerprint}` : undefined}
+            >
+              <FiLock className="status-lock" />
+              E2E Encrypted
+              {remoteFingerprint && ` · ${remoteFingerprint.split(' ').slice(0, 2).join(' ')}…`}
+            </span>
+          )}
         </div>
         <div className="status-right">
           <span className="status-item">{frameSize.width}×{frameSize.height}</span>
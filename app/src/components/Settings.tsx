@@ -1,6 +1,7 @@
 import React, { useState, useEffect } from 'react';
 import { invoke } from '@tauri-apps/api/core';
 import { open } from '@tauri-apps/plugin-shell';
+import { errorMessage } from '../errors';
 import './Settings.css';
 
 interface SettingsProps {
@@ -23,19 +24,59 @@ interface TrustedDevice {
   device_id: string;
   name: string | null;
   trusted_at: number;
+  fingerprint?: string | null;
   last_connected: number | null;
 }
 
+interface AllowedViewer {
+  device_id: string;
+  allowed_at: number;
+}
+
+interface TemporaryTrust {
+  device_id: string;
+  /** Seconds left; null until hosting stops */
+  expires_in_secs: number | null;
+}
+
+interface PolicyRule {
+  pattern: string;
+  match_on: 'title' | 'process';
+  blackout: boolean;
+}
+
 interface AppSettings {
   start_with_windows: boolean;
   minimize_to_tray: boolean;
   show_notifications: boolean;
   p2p_enabled: boolean;
   connection_quality: string;
+  coalesce_frame_requests: boolean;
+  relay_multiplexing: boolean;
+  jitter_buffer_frames: number;
   require_approval: boolean;
   lock_on_disconnect: boolean;
   session_timeout: number;
+  approval_timeout_secs: number;
+  start_view_only: boolean;
   hide_from_address_book: boolean;
+  capture_backend: string;
+  content_mode: string;
+  capture_active_fps: number;
+  capture_idle_fps: number;
+  scroll_sensitivity: number;
+  natural_scroll: boolean;
+  input_batch_ms: number;
+  max_clipboard_size_mb: number;
+  proxy_url: string;
+  relay_ca_cert: string;
+  relay_tls_insecure: boolean;
+  metrics_enabled: boolean;
+  metrics_port: number;
+  automation_enabled: boolean;
+  reconnect_max_attempts: number;
+  reconnect_max_backoff_secs: number;
+  license_activation_url: string;
 }
 
 type SettingsCategory =
@@ -45,6 +86,8 @@ type SettingsCategory =
   | 'privacy'
   | 'license'
   | 'trusted'
+  | 'blocked'
+  | 'policy'
   | 'about';
 
 function Settings({ p2pEnabled, onP2PToggle, isOpen, onClose }: SettingsProps) {
@@ -54,20 +97,97 @@ function Settings({ p2pEnabled, onP2PToggle, isOpen, onClose }: SettingsProps) {
   const [licenseError, setLicenseError] = useState('');
   const [licenseSuccess, setLicenseSuccess] = useState('');
   const [trustedDevices, setTrustedDevices] = useState<TrustedDevice[]>([]);
+  const [allowedViewers, setAllowedViewers] = useState<AllowedViewer[]>([]);
+  const [temporaryTrust, setTemporaryTrust] = useState<TemporaryTrust[]>([]);
+  const [blockedDevices, setBlockedDevices] = useState<string[]>([]);
+  const [blockDeviceId, setBlockDeviceId] = useState('');
+  const [blockError, setBlockError] = useState('');
+  const [hasUnattendedPassword, setHasUnattendedPassword] = useState(false);
+  const [unattendedPassword, setUnattendedPassword] = useState('');
+  const [unattendedError, setUnattendedError] = useState('');
+  const [policyRules, setPolicyRules] = useState<PolicyRule[]>([]);
+  const [policyPattern, setPolicyPattern] = useState('');
+  const [policyMatchOn, setPolicyMatchOn] = useState<PolicyRule['match_on']>('title');
+  const [policyBlackout, setPolicyBlackout] = useState(false);
+  const [policyError, setPolicyError] = useState('');
   const [settings, setSettings] = useState<AppSettings | null>(null);
+  const [stunEnabled, setStunEnabled] = useState(true);
+  const [stunServers, setStunServers] = useState('');
+  const [stunError, setStunError] = useState('');
+  const [proxyUrl, setProxyUrl] = useState('');
+  const [proxyError, setProxyError] = useState('');
+  const [relayCaCert, setRelayCaCert] = useState('');
+  const [relayCaError, setRelayCaError] = useState('');
 
   useEffect(() => {
     if (isOpen) {
       loadSettings();
       loadLicenseInfo();
       loadTrustedDevices();
+      loadAllowedViewers();
+      loadTemporaryTrust();
+      loadBlockedDevices();
+      loadUnattendedAccess();
+      loadControlPolicy();
+      loadStunConfig();
     }
   }, [isOpen]);
 
+  const loadStunConfig = async () => {
+    try {
+      const stun = await invoke<{ enabled: boolean; servers: string[] }>('get_stun_config');
+      setStunEnabled(stun.enabled);
+      setStunServers(stun.servers.join(', '));
+    } catch (error) {
+      console.error('Failed to load STUN config:', error);
+    }
+  };
+
+  const toggleStun = async (enabled: boolean) => {
+    try {
+      await invoke('set_stun_enabled', { enabled });
+      setStunEnabled(enabled);
+    } catch (error) {
+      console.error('Failed to update STUN:', error);
+    }
+  };
+
+  const saveStunServers = async () => {
+    setStunError('');
+    const servers = stunServers.split(',').map(s => s.trim()).filter(s => s.length > 0);
+    try {
+      await invoke('set_stun_servers', { servers });
+    } catch (error) {
+      setStunError(errorMessage(error));
+    }
+  };
+
+  const saveProxyUrl = async () => {
+    setProxyError('');
+    try {
+      await invoke('set_setting_string', { key: 'proxy_url', value: proxyUrl.trim() });
+      setSettings(prev => prev ? { ...prev, proxy_url: proxyUrl.trim() } : null);
+    } catch (error) {
+      setProxyError(errorMessage(error));
+    }
+  };
+
+  const saveRelayCaCert = async () => {
+    setRelayCaError('');
+    try {
+      await invoke('set_setting_string', { key: 'relay_ca_cert', value: relayCaCert.trim() });
+      setSettings(prev => prev ? { ...prev, relay_ca_cert: relayCaCert.trim() } : null);
+    } catch (error) {
+      setRelayCaError(errorMessage(error));
+    }
+  };
+
   const loadSettings = async () => {
     try {
       const s = await invoke<AppSettings>('get_settings');
       setSettings(s);
+      setProxyUrl(s.proxy_url);
+      setRelayCaCert(s.relay_ca_cert);
     } catch (error) {
       console.error('Failed to load settings:', error);
     }
@@ -138,7 +258,7 @@ function Settings({ p2pEnabled, onP2PToggle, isOpen, onClose }: SettingsProps) {
       setLicenseKey('');
       loadLicenseInfo();
     } catch (error) {
-      setLicenseError(String(error));
+      setLicenseError(errorMessage(error));
     }
   };
 
@@ -148,10 +268,44 @@ function Settings({ p2pEnabled, onP2PToggle, isOpen, onClose }: SettingsProps) {
       setLicenseSuccess('License deactivated. You are now on Free tier.');
       loadLicenseInfo();
     } catch (error) {
-      setLicenseError(String(error));
+      setLicenseError(errorMessage(error));
+    }
+  };
+
+  const loadAllowedViewers = async () => {
+    try {
+      setAllowedViewers(await invoke<AllowedViewer[]>('list_allowed_viewers'));
+    } catch (error) {
+      console.error('Failed to load allowed viewers:', error);
     }
   };
 
+  const handleRemoveAllowedViewer = async (deviceId: string) => {
+    try {
+      await invoke('remove_allowed_viewer', { deviceId });
+    } catch (error) {
+      console.error('Failed to remove allowed viewer:', error);
+    }
+    loadAllowedViewers();
+  };
+
+  const loadTemporaryTrust = async () => {
+    try {
+      setTemporaryTrust(await invoke<TemporaryTrust[]>('list_temporary_trust'));
+    } catch (error) {
+      console.error('Failed to load temporary trust:', error);
+    }
+  };
+
+  const handleRevokeTemporaryTrust = async (deviceId: string) => {
+    try {
+      await invoke('revoke_temporary_trust', { deviceId });
+    } catch (error) {
+      console.error('Failed to revoke temporary trust:', error);
+    }
+    loadTemporaryTrust();
+  };
+
   const handleRemoveTrustedDevice = async (deviceId: string) => {
     try {
       await invoke('remove_trusted_device', { deviceId });
@@ -161,6 +315,100 @@ function Settings({ p2pEnabled, onP2PToggle, isOpen, onClose }: SettingsProps) {
     }
   };
 
+  const loadBlockedDevices = async () => {
+    try {
+      const devices = await invoke<string[]>('get_blocked_devices');
+      setBlockedDevices(devices);
+    } catch (error) {
+      console.error('Failed to load blocked devices:', error);
+    }
+  };
+
+  const handleBlockDevice = async () => {
+    setBlockError('');
+    const deviceId = blockDeviceId.replace(/\s/g, '');
+    if (!deviceId) return;
+    try {
+      await invoke('block_device', { deviceId });
+      setBlockDeviceId('');
+      loadBlockedDevices();
+      // Blocking a device also removes it from the trusted list
+      loadTrustedDevices();
+      loadAllowedViewers();
+      loadTemporaryTrust();
+    } catch (error) {
+      setBlockError(errorMessage(error));
+    }
+  };
+
+  const handleUnblockDevice = async (deviceId: string) => {
+    try {
+      await invoke('unblock_device', { deviceId });
+      loadBlockedDevices();
+    } catch (error) {
+      console.error('Failed to unblock device:', error);
+    }
+  };
+
+  const loadUnattendedAccess = async () => {
+    try {
+      setHasUnattendedPassword(await invoke<boolean>('has_unattended_password'));
+    } catch (error) {
+      console.error('Failed to load unattended access:', error);
+    }
+  };
+
+  const handleSetUnattendedPassword = async () => {
+    setUnattendedError('');
+    try {
+      await invoke('set_unattended_password', { password: unattendedPassword });
+      setUnattendedPassword('');
+      loadUnattendedAccess();
+    } catch (error) {
+      setUnattendedError(errorMessage(error));
+    }
+  };
+
+  const handleClearUnattendedPassword = async () => {
+    setUnattendedError('');
+    try {
+      await invoke('clear_unattended_password');
+      loadUnattendedAccess();
+    } catch (error) {
+      setUnattendedError(errorMessage(error));
+    }
+  };
+
+  const loadControlPolicy = async () => {
+    try {
+      setPolicyRules(await invoke<PolicyRule[]>('get_control_policy'));
+    } catch (error) {
+      console.error('Failed to load window policy:', error);
+    }
+  };
+
+  const saveControlPolicy = async (rules: PolicyRule[]) => {
+    setPolicyError('');
+    try {
+      await invoke('set_control_policy', { rules });
+      loadControlPolicy();
+      return true;
+    } catch (error) {
+      setPolicyError(errorMessage(error));
+      return false;
+    }
+  };
+
+  const handleAddPolicyRule = async () => {
+    const pattern = policyPattern.trim();
+    if (!pattern) return;
+    const added = await saveControlPolicy([
+      ...policyRules,
+      { pattern, match_on: policyMatchOn, blackout: policyBlackout },
+    ]);
+    if (added) setPolicyPattern('');
+  };
+
   const formatDeviceId = (id: string) => {
     const clean = id.replace(/\s/g, '');
     return clean.replace(/(.{3})(?=.)/g, '$1 ');
@@ -179,6 +427,8 @@ function Settings({ p2pEnabled, onP2PToggle, isOpen, onClose }: SettingsProps) {
     { id: 'privacy', label: 'Privacy', icon: '👁️' },
     { id: 'license', label: 'License', icon: '🔑' },
     { id: 'trusted', label: 'Trusted Devices', icon: '✓' },
+    { id: 'blocked', label: 'Blocked Devices', icon: '⛔' },
+    { id: 'policy', label: 'Window Policy', icon: '🚫' },
     { id: 'about', label: 'About', icon: 'ℹ️' },
   ];
 
@@ -259,6 +509,144 @@ function Settings({ p2pEnabled, onP2PToggle, isOpen, onClose }: SettingsProps) {
                 <span className="toggle-slider"></span>
               </label>
             </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Public address discovery (STUN)</span>
+                <span className="settings-item-desc">
+                  Ask STUN servers for your public address so P2P works across the internet. Off = LAN-only P2P, no third-party lookups.
+                </span>
+              </div>
+              <label className="toggle-switch">
+                <input
+                  type="checkbox"
+                  checked={stunEnabled}
+                  onChange={(e) => toggleStun(e.target.checked)}
+                />
+                <span className="toggle-slider"></span>
+              </label>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">STUN servers</span>
+                <span className="settings-item-desc">
+                  Comma-separated host:port list, queried in order
+                </span>
+                {stunError && <div className="license-message error">{stunError}</div>}
+              </div>
+              <input
+                type="text"
+                className="settings-select"
+                value={stunServers}
+                disabled={!stunEnabled}
+                onChange={(e) => setStunServers(e.target.value)}
+                onBlur={saveStunServers}
+              />
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Proxy</span>
+                <span className="settings-item-desc">
+                  http://host:port or socks5://host:port for relay connections. Empty = use HTTPS_PROXY/ALL_PROXY, "none" = always direct. STUN is skipped while a proxy is in use.
+                </span>
+                {proxyError && <div className="license-message error">{proxyError}</div>}
+              </div>
+              <input
+                type="text"
+                className="settings-select"
+                value={proxyUrl}
+                placeholder="Use system environment"
+                onChange={(e) => setProxyUrl(e.target.value)}
+                onBlur={saveProxyUrl}
+              />
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Relay CA certificate</span>
+                <span className="settings-item-desc">
+                  PEM file with the CA (or self-signed certificate) of a self-hosted relay, trusted in addition to the public roots
+                </span>
+                {relayCaError && <div className="license-message error">{relayCaError}</div>}
+              </div>
+              <input
+                type="text"
+                className="settings-select"
+                value={relayCaCert}
+                placeholder="Public roots only"
+                onChange={(e) => setRelayCaCert(e.target.value)}
+                onBlur={saveRelayCaCert}
+              />
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Skip relay certificate checks</span>
+                <span className="settings-item-desc">
+                  For testing only: any relay certificate is accepted, so the relay connection can be intercepted. Sessions stay end-to-end encrypted.
+                </span>
+              </div>
+              <label className="toggle-switch">
+                <input
+                  type="checkbox"
+                  checked={settings?.relay_tls_insecure ?? false}
+                  onChange={(e) => updateBoolSetting('relay_tls_insecure', e.target.checked)}
+                />
+                <span className="toggle-slider"></span>
+              </label>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Local metrics endpoint</span>
+                <span className="settings-item-desc">
+                  Serve Prometheus metrics on 127.0.0.1:{settings?.metrics_port ?? 9464}/metrics for local scraping. Nothing is sent anywhere.
+                </span>
+              </div>
+              <label className="toggle-switch">
+                <input
+                  type="checkbox"
+                  checked={settings?.metrics_enabled ?? false}
+                  onChange={(e) => updateBoolSetting('metrics_enabled', e.target.checked)}
+                />
+                <span className="toggle-slider"></span>
+              </label>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Metrics port</span>
+                <span className="settings-item-desc">
+                  Local port for the metrics endpoint
+                </span>
+              </div>
+              <input
+                type="number"
+                className="settings-select"
+                min={1}
+                max={65535}
+                defaultValue={settings?.metrics_port ?? 9464}
+                key={settings?.metrics_port}
+                disabled={!(settings?.metrics_enabled ?? false)}
+                onBlur={(e) => {
+                  const port = parseInt(e.target.value);
+                  if (port >= 1 && port <= 65535 && port !== settings?.metrics_port) {
+                    updateNumberSetting('metrics_port', port);
+                  }
+                }}
+              />
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Automation socket</span>
+                <span className="settings-item-desc">
+                  Let scripts send input to open sessions with the securedesk input command. Local only; each request needs a token only this user can read.
+                </span>
+              </div>
+              <label className="toggle-switch">
+                <input
+                  type="checkbox"
+                  checked={settings?.automation_enabled ?? false}
+                  onChange={(e) => updateBoolSetting('automation_enabled', e.target.checked)}
+                />
+                <span className="toggle-slider"></span>
+              </label>
+            </div>
             <div className="settings-item">
               <div className="settings-item-info">
                 <span className="settings-item-label">Connection quality</span>
@@ -275,6 +663,239 @@ function Settings({ p2pEnabled, onP2PToggle, isOpen, onClose }: SettingsProps) {
                 <option value="quality">Best Quality</option>
                 <option value="balanced">Balanced</option>
                 <option value="speed">Best Speed</option>
+                <option value="lossless">Lossless (LAN only)</option>
+              </select>
+              {settings?.connection_quality === 'lossless' && (
+                <span className="settings-item-desc">
+                  Lossless frames use several times the bandwidth - best over LAN or P2P
+                </span>
+              )}
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Frame buffering</span>
+                <span className="settings-item-desc">
+                  Hold a few frames to smooth playback on unstable links, at the cost of latency
+                </span>
+              </div>
+              <select
+                className="settings-select"
+                value={settings?.jitter_buffer_frames ?? 0}
+                onChange={(e) => updateNumberSetting('jitter_buffer_frames', parseInt(e.target.value))}
+              >
+                <option value="0">Off (lowest latency)</option>
+                <option value="1">1 frame</option>
+                <option value="2">2 frames</option>
+                <option value="3">3 frames</option>
+              </select>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Coalesce frame requests</span>
+                <span className="settings-item-desc">
+                  Skip frames the remote can't keep up with instead of queueing them
+                </span>
+              </div>
+              <label className="toggle-switch">
+                <input
+                  type="checkbox"
+                  checked={settings?.coalesce_frame_requests ?? true}
+                  onChange={(e) => updateBoolSetting('coalesce_frame_requests', e.target.checked)}
+                />
+                <span className="toggle-slider"></span>
+              </label>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Share relay connection</span>
+                <span className="settings-item-desc">
+                  Run sessions through one relay connection instead of one each
+                </span>
+              </div>
+              <label className="toggle-switch">
+                <input
+                  type="checkbox"
+                  checked={settings?.relay_multiplexing ?? false}
+                  onChange={(e) => updateBoolSetting('relay_multiplexing', e.target.checked)}
+                />
+                <span className="toggle-slider"></span>
+              </label>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Relay reconnect attempts</span>
+                <span className="settings-item-desc">
+                  When the relay connection drops, stop retrying and show this device as offline after this many attempts
+                </span>
+              </div>
+              <select
+                className="settings-select"
+                value={settings?.reconnect_max_attempts ?? 0}
+                onChange={(e) => updateNumberSetting('reconnect_max_attempts', parseInt(e.target.value))}
+              >
+                <option value="0">Keep trying</option>
+                <option value="5">5 attempts</option>
+                <option value="10">10 attempts</option>
+                <option value="30">30 attempts</option>
+              </select>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Longest reconnect wait</span>
+                <span className="settings-item-desc">
+                  Retries start after a couple of seconds and wait longer each time, up to this
+                </span>
+              </div>
+              <select
+                className="settings-select"
+                value={settings?.reconnect_max_backoff_secs ?? 120}
+                onChange={(e) => updateNumberSetting('reconnect_max_backoff_secs', parseInt(e.target.value))}
+              >
+                <option value="30">30 seconds</option>
+                <option value="60">1 minute</option>
+                <option value="120">2 minutes</option>
+                <option value="300">5 minutes</option>
+                <option value="900">15 minutes</option>
+              </select>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Screen capture method</span>
+                <span className="settings-item-desc">
+                  Use GDI if the remote screen stays black (Windows only)
+                </span>
+              </div>
+              <select
+                className="settings-select"
+                value={settings?.capture_backend ?? 'auto'}
+                onChange={(e) => updateStringSetting('capture_backend', e.target.value)}
+              >
+                <option value="auto">Auto (Recommended)</option>
+                <option value="dxgi">DXGI Desktop Duplication</option>
+                <option value="gdi">GDI (Compatibility)</option>
+              </select>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Shared screen content</span>
+                <span className="settings-item-desc">
+                  Sharper text for documents and code, smaller frames for video and photos
+                </span>
+              </div>
+              <select
+                className="settings-select"
+                value={settings?.content_mode ?? 'auto'}
+                onChange={(e) => updateStringSetting('content_mode', e.target.value)}
+              >
+                <option value="auto">Detect automatically (Recommended)</option>
+                <option value="text">Text</option>
+                <option value="photo">Photos and video</option>
+                <option value="off">Off</option>
+              </select>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Capture frame rate</span>
+                <span className="settings-item-desc">
+                  Maximum frames per second shared while the screen is changing
+                </span>
+              </div>
+              <select
+                className="settings-select"
+                value={settings?.capture_active_fps ?? 30}
+                onChange={(e) => updateNumberSetting('capture_active_fps', parseInt(e.target.value))}
+              >
+                <option value="15">15 FPS</option>
+                <option value="30">30 FPS</option>
+                <option value="60">60 FPS</option>
+              </select>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Idle frame rate</span>
+                <span className="settings-item-desc">
+                  Capture rate once the screen has stopped changing, to save CPU and battery
+                </span>
+              </div>
+              <select
+                className="settings-select"
+                value={settings?.capture_idle_fps ?? 2}
+                onChange={(e) => updateNumberSetting('capture_idle_fps', parseInt(e.target.value))}
+              >
+                <option value="1">1 FPS</option>
+                <option value="2">2 FPS</option>
+                <option value="5">5 FPS</option>
+                <option value="10">10 FPS</option>
+              </select>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Scroll sensitivity</span>
+                <span className="settings-item-desc">
+                  How far the remote screen scrolls per wheel or trackpad movement
+                </span>
+              </div>
+              <select
+                className="settings-select"
+                value={settings?.scroll_sensitivity ?? 100}
+                onChange={(e) => updateNumberSetting('scroll_sensitivity', parseInt(e.target.value))}
+              >
+                <option value="50">Slow (50%)</option>
+                <option value="100">Normal (100%)</option>
+                <option value="150">Fast (150%)</option>
+                <option value="200">Very fast (200%)</option>
+              </select>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Natural scrolling</span>
+                <span className="settings-item-desc">
+                  Content follows your fingers, like a touchscreen
+                </span>
+              </div>
+              <label className="toggle-switch">
+                <input
+                  type="checkbox"
+                  checked={settings?.natural_scroll ?? false}
+                  onChange={(e) => updateBoolSetting('natural_scroll', e.target.checked)}
+                />
+                <span className="toggle-slider"></span>
+              </label>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Input batching</span>
+                <span className="settings-item-desc">
+                  Send mouse movement in batches to save bandwidth; clicks and keys are never delayed
+                </span>
+              </div>
+              <select
+                className="settings-select"
+                value={settings?.input_batch_ms ?? 0}
+                onChange={(e) => updateNumberSetting('input_batch_ms', parseInt(e.target.value))}
+              >
+                <option value="0">Off</option>
+                <option value="4">4 ms</option>
+                <option value="8">8 ms</option>
+                <option value="16">16 ms</option>
+              </select>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Clipboard size limit</span>
+                <span className="settings-item-desc">
+                  Larger images are compressed first; anything still over the limit is not synced
+                </span>
+              </div>
+              <select
+                className="settings-select"
+                value={settings?.max_clipboard_size_mb ?? 5}
+                onChange={(e) => updateNumberSetting('max_clipboard_size_mb', parseInt(e.target.value))}
+              >
+                <option value="1">1 MB</option>
+                <option value="2">2 MB</option>
+                <option value="5">5 MB</option>
+                <option value="10">10 MB</option>
               </select>
             </div>
             <div className="settings-info-box">
@@ -337,12 +958,74 @@ function Settings({ p2pEnabled, onP2PToggle, isOpen, onClose }: SettingsProps) {
                 onChange={(e) => updateNumberSetting('session_timeout', parseInt(e.target.value))}
               >
                 <option value="0">Never</option>
-                <option value="5">5 minutes</option>
-                <option value="15">15 minutes</option>
-                <option value="30">30 minutes</option>
-                <option value="60">1 hour</option>
+                <option value="300">5 minutes</option>
+                <option value="900">15 minutes</option>
+                <option value="1800">30 minutes</option>
+                <option value="3600">1 hour</option>
               </select>
             </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Approval timeout</span>
+                <span className="settings-item-desc">
+                  Decline connection requests nobody answers in time
+                </span>
+              </div>
+              <select
+                className="settings-select"
+                value={settings?.approval_timeout_secs ?? 30}
+                onChange={(e) => updateNumberSetting('approval_timeout_secs', parseInt(e.target.value))}
+              >
+                <option value="0">Decline immediately</option>
+                <option value="15">15 seconds</option>
+                <option value="30">30 seconds</option>
+                <option value="60">1 minute</option>
+                <option value="120">2 minutes</option>
+                <option value="300">5 minutes</option>
+              </select>
+            </div>
+            <div className="settings-item">
+              <div className="settings-item-info">
+                <span className="settings-item-label">Start sessions view-only</span>
+                <span className="settings-item-desc">
+                  Viewers only watch until they ask for control and you grant it
+                </span>
+              </div>
+              <label className="toggle-switch">
+                <input
+                  type="checkbox"
+                  checked={settings?.start_view_only ?? false}
+                  onChange={(e) => updateBoolSetting('start_view_only', e.target.checked)}
+                />
+                <span className="toggle-slider"></span>
+              </label>
+            </div>
+            <div className="license-input-section">
+              <label className="settings-label">
+                Unattended access {hasUnattendedPassword ? '(on)' : '(off)'}
+              </label>
+              <p className="settings-description">
+                Viewers that give this password connect without asking; every other connection is refused (Pro)
+              </p>
+              <div className="license-input-row">
+                <input
+                  type="password"
+                  className="license-input"
+                  placeholder={hasUnattendedPassword ? 'New access password' : 'Access password'}
+                  value={unattendedPassword}
+                  onChange={(e) => setUnattendedPassword(e.target.value)}
+                />
+                <button className="license-btn activate" onClick={handleSetUnattendedPassword}>
+                  Set
+                </button>
+                {hasUnattendedPassword && (
+                  <button className="license-btn deactivate" onClick={handleClearUnattendedPassword}>
+                    Turn off
+                  </button>
+                )}
+              </div>
+              {unattendedError && <div className="license-message error">{unattendedError}</div>}
+            </div>
             <div className="settings-info-box info">
               <span className="info-icon">🔐</span>
               <p>
@@ -360,7 +1043,7 @@ function Settings({ p2pEnabled, onP2PToggle, isOpen, onClose }: SettingsProps) {
               <div className="settings-item-info">
                 <span className="settings-item-label">Hide from address book</span>
                 <span className="settings-item-desc">
-                  Don't allow others to save your device in their address book
+                  Don't allow others to save your device in their address book, and don't announce it on the local network
                 </span>
               </div>
               <label className="toggle-switch">
@@ -513,35 +1196,220 @@ function Settings({ p2pEnabled, onP2PToggle, isOpen, onClose }: SettingsProps) {
           <div className="settings-category-content">
             <h2>Trusted Devices</h2>
             <p className="settings-description">
-              Trusted devices can connect without requiring approval. They will be automatically accepted.
+              Devices that connect to this computer without requiring approval, as long as they hold the same keys as when you allowed them.
             </p>
 
-            {trustedDevices.length === 0 ? (
+            {allowedViewers.length === 0 ? (
               <div className="empty-state">
                 <span className="empty-icon">🔓</span>
                 <p>No trusted devices yet</p>
                 <p className="empty-hint">
-                  When accepting a connection, check "Trust this device" to add it here.
+                  When accepting a connection, choose "Always" to add it here.
                 </p>
               </div>
             ) : (
               <div className="trusted-devices-list">
-                {trustedDevices.map((device) => (
-                  <div key={device.device_id} className="trusted-device-item">
+                {allowedViewers.map((viewer) => (
+                  <div key={viewer.device_id} className="trusted-device-item">
                     <div className="device-info">
-                      <span className="device-id">{formatDeviceId(device.device_id)}</span>
-                      <span className="device-name">{device.name || 'Unnamed device'}</span>
-                      <span className="device-meta">
-                        Trusted on {formatDate(device.trusted_at)}
-                        {device.last_connected && (
-                          <> · Last connected {formatDate(device.last_connected)}</>
+                      <span className="device-id">{formatDeviceId(viewer.device_id)}</span>
+                      <span className="device-meta">Allowed on {formatDate(viewer.allowed_at)}</span>
+                    </div>
+                    <button
+                      className="remove-device-btn"
+                      onClick={() => handleRemoveAllowedViewer(viewer.device_id)}
+                      title="Ask before letting this device in"
+                    >
+                      ×
+                    </button>
+                  </div>
+                ))}
+              </div>
+            )}
+
+            {temporaryTrust.length > 0 && (
+              <>
+                <h3>Temporary Access</h3>
+                <p className="settings-description">
+                  Accepted for a limited time. They connect without approval until it runs out.
+                </p>
+                <div className="trusted-devices-list">
+                  {temporaryTrust.map((grant) => (
+                    <div key={grant.device_id} className="trusted-device-item">
+                      <div className="device-info">
+                        <span className="device-id">{formatDeviceId(grant.device_id)}</span>
+                        <span className="device-meta">
+                          {grant.expires_in_secs === null
+                            ? 'Until hosting stops'
+                            : `${Math.max(1, Math.ceil(grant.expires_in_secs / 60))} min left`}
+                        </span>
+                      </div>
+                      <button
+                        className="remove-device-btn"
+                        onClick={() => handleRevokeTemporaryTrust(grant.device_id)}
+                        title="Revoke temporary access"
+                      >
+                        ×
+                      </button>
+                    </div>
+                  ))}
+                </div>
+              </>
+            )}
+
+            {trustedDevices.length > 0 && (
+              <>
+                <h3>Known Hosts</h3>
+                <p className="settings-description">
+                  Computers you connect to. Their keys are pinned, so a different computer answering for one is refused.
+                </p>
+                <div className="trusted-devices-list">
+                  {trustedDevices.map((device) => (
+                    <div key={device.device_id} className="trusted-device-item">
+                      <div className="device-info">
+                        <span className="device-id">{formatDeviceId(device.device_id)}</span>
+                        <span className="device-name">{device.name || 'Unnamed device'}</span>
+                        <span className="device-meta">
+                          Trusted on {formatDate(device.trusted_at)}
+                          {device.last_connected && (
+                            <> · Last connected {formatDate(device.last_connected)}</>
+                          )}
+                        </span>
+                        {device.fingerprint && (
+                          <span className="device-meta">Fingerprint {device.fingerprint}</span>
                         )}
+                      </div>
+                      <button
+                        className="remove-device-btn"
+                        onClick={() => handleRemoveTrustedDevice(device.device_id)}
+                        title="Forget this host"
+                      >
+                        ×
+                      </button>
+                    </div>
+                  ))}
+                </div>
+              </>
+            )}
+          </div>
+        );
+
+      case 'blocked':
+        return (
+          <div className="settings-category-content">
+            <h2>Blocked Devices</h2>
+            <p className="settings-description">
+              Connection requests from blocked devices are declined automatically. You will not be notified.
+            </p>
+
+            <div className="license-input-section">
+              <label className="settings-label">Block a device ID</label>
+              <div className="license-input-row">
+                <input
+                  type="text"
+                  className="license-input"
+                  placeholder="123 456 789"
+                  value={blockDeviceId}
+                  onChange={(e) => setBlockDeviceId(e.target.value)}
+                />
+                <button
+                  className="license-btn deactivate"
+                  onClick={handleBlockDevice}
+                >
+                  Block
+                </button>
+              </div>
+              {blockError && <div className="license-message error">{blockError}</div>}
+            </div>
+
+            {blockedDevices.length === 0 ? (
+              <div className="empty-state">
+                <span className="empty-icon">✅</span>
+                <p>No blocked devices</p>
+              </div>
+            ) : (
+              <div className="trusted-devices-list">
+                {blockedDevices.map((deviceId) => (
+                  <div key={deviceId} className="trusted-device-item">
+                    <div className="device-info">
+                      <span className="device-id">{formatDeviceId(deviceId)}</span>
+                    </div>
+                    <button
+                      className="remove-device-btn"
+                      onClick={() => handleUnblockDevice(deviceId)}
+                      title="Unblock this device"
+                    >
+                      ×
+                    </button>
+                  </div>
+                ))}
+              </div>
+            )}
+          </div>
+        );
+
+      case 'policy':
+        return (
+          <div className="settings-category-content">
+            <h2>Window Policy</h2>
+            <p className="settings-description">
+              Remote input is ignored while a matching window has focus on this computer.
+              Patterns ignore case and match the whole title or program name; use * as a wildcard.
+            </p>
+
+            <div className="license-input-section">
+              <label className="settings-label">Protect a window</label>
+              <div className="license-input-row">
+                <input
+                  type="text"
+                  className="license-input"
+                  placeholder={policyMatchOn === 'process' ? 'keepass.exe' : '*Online Banking*'}
+                  value={policyPattern}
+                  onChange={(e) => setPolicyPattern(e.target.value)}
+                />
+                <select
+                  className="settings-select"
+                  value={policyMatchOn}
+                  onChange={(e) => setPolicyMatchOn(e.target.value as PolicyRule['match_on'])}
+                >
+                  <option value="title">Title</option>
+                  <option value="process">Program</option>
+                </select>
+                <button className="license-btn deactivate" onClick={handleAddPolicyRule}>
+                  Protect
+                </button>
+              </div>
+              <label className="settings-label">
+                <input
+                  type="checkbox"
+                  checked={policyBlackout}
+                  onChange={(e) => setPolicyBlackout(e.target.checked)}
+                />
+                {' '}Also hide the window from the shared screen
+              </label>
+              {policyError && <div className="license-message error">{policyError}</div>}
+            </div>
+
+            {policyRules.length === 0 ? (
+              <div className="empty-state">
+                <span className="empty-icon">✅</span>
+                <p>No protected windows</p>
+              </div>
+            ) : (
+              <div className="trusted-devices-list">
+                {policyRules.map((rule, index) => (
+                  <div key={`${rule.match_on}:${rule.pattern}:${index}`} className="trusted-device-item">
+                    <div className="device-info">
+                      <span className="device-id">{rule.pattern}</span>
+                      <span className="device-meta">
+                        {rule.match_on === 'process' ? 'Program' : 'Title'}
+                        {rule.blackout ? ' · hidden from screen' : ''}
                       </span>
                     </div>
                     <button
                       className="remove-device-btn"
-                      onClick={() => handleRemoveTrustedDevice(device.device_id)}
-                      title="Remove from trusted devices"
+                      onClick={() => saveControlPolicy(policyRules.filter((_, i) => i !== index))}
+                      title="Remove this rule"
                     >
                       ×
                     </button>
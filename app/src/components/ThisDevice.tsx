@@ -1,4 +1,5 @@
-import React, { useState } from 'react';
+import React, { useEffect, useState } from 'react';
+import { invoke } from '@tauri-apps/api/core';
 import { FiCopy, FiCheck, FiMonitor, FiRefreshCw, FiSettings } from 'react-icons/fi';
 import { DeviceInfo } from '../App';
 import './ThisDevice.css';
@@ -6,14 +7,23 @@ import './ThisDevice.css';
 interface ThisDeviceProps {
   device: DeviceInfo;
   isHosting: boolean;
+  /** The relay could not be reached and the host stopped retrying */
+  isOffline?: boolean;
+  onReconnect?: () => Promise<void>;
   p2pEnabled: boolean;
   onRegenerateId?: () => Promise<void>;
   onOpenSettings?: () => void;
 }
 
-const ThisDevice: React.FC<ThisDeviceProps> = ({ device, isHosting, p2pEnabled, onRegenerateId, onOpenSettings }) => {
+const ThisDevice: React.FC<ThisDeviceProps> = ({ device, isHosting, isOffline, onReconnect, p2pEnabled, onRegenerateId, onOpenSettings }) => {
   const [copied, setCopied] = useState(false);
   const [regenerating, setRegenerating] = useState(false);
+  const [fingerprint, setFingerprint] = useState('');
+
+  // Changes along with the ID when the identity is regenerated
+  useEffect(() => {
+    invoke<string>('get_device_fingerprint').then(setFingerprint).catch(console.error);
+  }, [device.id]);
 
   const copyId = async () => {
     await navigator.clipboard.writeText(device.id.replace(/\s/g, ''));
@@ -63,13 +73,23 @@ const ThisDevice: React.FC<ThisDeviceProps> = ({ device, isHosting, p2pEnabled,
             </button>
           </div>
         </div>
+        {fingerprint && (
+          <div className="id-fingerprint" title="Read this to the other side to verify the connection">
+            Fingerprint: {fingerprint}
+          </div>
+        )}
       </div>
 
       <div className="status-row">
-        <div className={`status-dot ${isHosting ? 'active' : 'ready'}`} />
+        <div className={`status-dot ${isOffline ? 'offline' : isHosting ? 'active' : 'ready'}`} />
         <span className="status-text">
-          {isHosting ? 'Session active' : 'Ready to receive connections'}
+          {isOffline ? 'Offline - cannot reach the relay' : isHosting ? 'Session active' : 'Ready to receive connections'}
         </span>
+        {isOffline && onReconnect && (
+          <button className="status-retry" onClick={onReconnect} title="Try the relay again">
+            <FiRefreshCw />
+          </button>
+        )}
       </div>
 
       <div className="password-section">
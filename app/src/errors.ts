@@ -0,0 +1,48 @@
+// Errors rejected by Tauri commands (AppError in src-tauri/src/error.rs)
+
+export type AppErrorCode =
+  | 'no_active_session'
+  | 'session_not_found'
+  | 'no_host_session'
+  | 'invalid_input'
+  | 'permission_denied'
+  | 'license_required'
+  | 'auth'
+  | 'identity_mismatch'
+  | 'network'
+  | 'internal';
+
+export interface AppError {
+  code: AppErrorCode;
+  message: string;
+  // license_required only: the refused feature and the lowest tier with it
+  feature?: string;
+  required_tier?: string | null;
+}
+
+export function isAppError(error: unknown): error is AppError {
+  return typeof error === 'object' && error !== null
+    && typeof (error as AppError).code === 'string'
+    && typeof (error as AppError).message === 'string';
+}
+
+// Code of a command error, null for anything else (e.g. a JS exception)
+export function errorCode(error: unknown): AppErrorCode | null {
+  return isAppError(error) ? error.code : null;
+}
+
+// Text to show for a command error
+export function errorMessage(error: unknown): string {
+  if (isAppError(error)) return error.message;
+  if (error instanceof Error) return error.message;
+  return String(error);
+}
+
+// Upgrade prompt for a license_required error, null for any other error
+export function upgradePrompt(error: unknown): string | null {
+  if (!isAppError(error) || error.code !== 'license_required') return null;
+  const upgrade = error.required_tier
+    ? `Upgrade to ${error.required_tier} under Settings > License.`
+    : 'Upgrade your license under Settings > License.';
+  return `${error.message}. ${upgrade}`;
+}
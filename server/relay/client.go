@@ -7,22 +7,34 @@ import (
 	"errors"
 	"io"
 	"net"
+	"strings"
 	"sync"
 	"time"
 )
 
 // Client types
 const (
-	ClientTypeEndpoint   uint8 = 0x01
-	ClientTypeTechnician uint8 = 0x02
+	ClientTypeEndpoint      uint8 = 0x01
+	ClientTypeTechnician    uint8 = 0x02
+	ClientTypeEndpointAlias uint8 = 0x03 // Endpoint that also claims an alias
+	ClientTypeResolve       uint8 = 0x04 // One-shot alias lookup
+	ClientTypeTechnicianMux uint8 = 0x05 // Technician carrying several sessions
+	ClientTypeEndpointMux   uint8 = 0x06 // Endpoint serving several technicians at once
 )
 
+// MaxAliasLength is the longest alias accepted, in bytes
+const MaxAliasLength = 64
+
 // Errors
 var (
 	ErrEndpointNotFound = errors.New("endpoint not found")
 	ErrInvalidHandshake = errors.New("invalid handshake")
 	ErrSessionClosed    = errors.New("session closed")
 	ErrFrameTooLarge    = errors.New("frame too large")
+	ErrAliasNotFound    = errors.New("alias not found")
+	ErrAliasTaken       = errors.New("alias already in use")
+	ErrInvalidAlias     = errors.New("invalid alias")
+	ErrInvalidStream    = errors.New("invalid stream")
 )
 
 // Frame represents a protocol frame
@@ -40,8 +52,10 @@ type Client struct {
 	Type          uint8
 	ID            string
 	TargetID      string
+	Alias         string
 	PublicKeyHash string
 	Paired        *Client
+	Mux           *endpointMux // Set for multiplexed endpoints
 	Done          chan struct{}
 	writeMu       sync.Mutex
 	bufferPool    *sync.Pool
@@ -93,25 +107,49 @@ func (c *Client) ReadHandshake() error {
 
 	// For technicians, also read target endpoint ID
 	if c.Type == ClientTypeTechnician {
-		if _, err := io.ReadFull(c.reader, lenBuf); err != nil {
+		target, err := c.readField(lenBuf)
+		if err != nil {
 			return err
 		}
-		targetLen := binary.BigEndian.Uint16(lenBuf)
-
-		if targetLen > 1024 {
-			return ErrInvalidHandshake
-		}
+		c.TargetID = target
+	}
 
-		targetBuf := make([]byte, targetLen)
-		if _, err := io.ReadFull(c.reader, targetBuf); err != nil {
+	// Alias registrations and lookups carry the alias as the second field;
+	// for multiplexed endpoints it is optional (length 0 = no alias)
+	if c.Type == ClientTypeEndpointAlias || c.Type == ClientTypeResolve || c.Type == ClientTypeEndpointMux {
+		alias, err := c.readField(lenBuf)
+		if err != nil {
 			return err
 		}
-		c.TargetID = string(targetBuf)
+		c.Alias = NormalizeAlias(alias)
 	}
 
 	return nil
 }
 
+// readField reads a length-prefixed (2 bytes, big-endian) handshake field
+func (c *Client) readField(lenBuf []byte) (string, error) {
+	if _, err := io.ReadFull(c.reader, lenBuf); err != nil {
+		return "", err
+	}
+	fieldLen := binary.BigEndian.Uint16(lenBuf)
+
+	if fieldLen > 1024 {
+		return "", ErrInvalidHandshake
+	}
+
+	fieldBuf := make([]byte, fieldLen)
+	if _, err := io.ReadFull(c.reader, fieldBuf); err != nil {
+		return "", err
+	}
+	return string(fieldBuf), nil
+}
+
+// NormalizeAlias returns the form aliases are compared in (trimmed, lowercase)
+func NormalizeAlias(alias string) string {
+	return strings.ToLower(strings.TrimSpace(alias))
+}
+
 // ReadFrame reads a protocol frame from the connection
 // Frame format: [channel_id (1 byte)][length (3 bytes)][payload]
 func (c *Client) ReadFrame() (*Frame, error) {
@@ -201,6 +239,33 @@ func (c *Client) SendSuccess() {
 	c.WriteFrame(frame)
 }
 
+// SendAliasRegistered confirms an endpoint's alias claim
+func (c *Client) SendAliasRegistered() {
+	frame := &Frame{
+		ChannelID: 0x00,
+		Payload:   []byte{0x03}, // 0x03 = alias registered
+	}
+	c.WriteFrame(frame)
+}
+
+// SendResolved answers an alias lookup with the endpoint's device ID
+func (c *Client) SendResolved(deviceID string) {
+	frame := &Frame{
+		ChannelID: 0x00,
+		Payload:   append([]byte{0x04}, []byte(deviceID)...), // 0x04 = alias resolved
+	}
+	c.WriteFrame(frame)
+}
+
+// SendMuxReady confirms a multiplexed technician or endpoint connection
+func (c *Client) SendMuxReady() {
+	frame := &Frame{
+		ChannelID: 0x00,
+		Payload:   []byte{0x05}, // 0x05 = multiplexing ready
+	}
+	c.WriteFrame(frame)
+}
+
 // NotifyConnection notifies endpoint of incoming technician connection
 func (c *Client) NotifyConnection(technicianKeyHash string) {
 	frame := &Frame{
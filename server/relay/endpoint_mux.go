@@ -0,0 +1,194 @@
+package relay
+
+import "sync"
+
+// Multiplexed endpoints serve several technicians over one connection.
+// Framing matches technician multiplexing: every payload starts with a
+// 2-byte stream ID and stream 0 carries [op][stream (2)][data]. The relay
+// opens a stream per technician with MuxOpen [stream][technician key hash];
+// MuxClose [stream] ends one in either direction.
+
+// endpointRoute delivers one stream's frames from the endpoint to its technician
+type endpointRoute struct {
+	session *Session
+	deliver func(*Frame) error
+}
+
+// endpointMux tracks the technicians sharing one multiplexed endpoint
+type endpointMux struct {
+	endpoint *Client
+	streams  map[uint16]*endpointRoute
+	next     uint16
+	mu       sync.Mutex
+}
+
+func newEndpointMux(endpoint *Client) *endpointMux {
+	return &endpointMux{endpoint: endpoint, streams: make(map[uint16]*endpointRoute)}
+}
+
+// open assigns a stream to a technician session and announces it to the
+// endpoint; false if every stream ID is taken
+func (m *endpointMux) open(session *Session, technicianKeyHash string, deliver func(*Frame) error) bool {
+	m.mu.Lock()
+	streamID, ok := m.allocate()
+	if ok {
+		m.streams[streamID] = &endpointRoute{session: session, deliver: deliver}
+		session.EndpointStream = streamID
+	}
+	m.mu.Unlock()
+
+	if ok {
+		m.sendControl(MuxOpen, streamID, []byte(technicianKeyHash))
+	}
+	return ok
+}
+
+// allocate picks the next free stream ID, skipping the control stream
+// Caller holds m.mu
+func (m *endpointMux) allocate() (uint16, bool) {
+	for i := 0; i < 0xFFFF; i++ {
+		m.next++
+		if m.next == MuxControlStream {
+			m.next++
+		}
+		if _, used := m.streams[m.next]; !used {
+			return m.next, true
+		}
+	}
+	return 0, false
+}
+
+func (m *endpointMux) get(streamID uint16) *endpointRoute {
+	m.mu.Lock()
+	defer m.mu.Unlock()
+	return m.streams[streamID]
+}
+
+// writeStream forwards a frame to the endpoint tagged with its stream ID
+func (m *endpointMux) writeStream(streamID uint16, frame *Frame) error {
+	return m.endpoint.WriteFrame(&Frame{
+		ChannelID: frame.ChannelID,
+		Payload:   muxPayload(streamID, frame.Payload),
+	})
+}
+
+// sendControl sends a control message on stream 0
+func (m *endpointMux) sendControl(op uint8, streamID uint16, data []byte) {
+	payload := append([]byte{op}, muxPayload(streamID, data)...)
+	m.writeStream(MuxControlStream, &Frame{ChannelID: 0x00, Payload: payload})
+}
+
+// release drops the stream of a session that ended and tells the endpoint
+// Only the session that owns the stream can release it
+func (m *endpointMux) release(streamID uint16, owner *Session) {
+	m.mu.Lock()
+	route, ok := m.streams[streamID]
+	if ok && route.session == owner {
+		delete(m.streams, streamID)
+	} else {
+		ok = false
+	}
+	m.mu.Unlock()
+
+	if ok {
+		m.sendControl(MuxClose, streamID, nil)
+	}
+}
+
+// drop removes a stream the endpoint closed, returning its session
+func (m *endpointMux) drop(streamID uint16) *Session {
+	m.mu.Lock()
+	defer m.mu.Unlock()
+	route, ok := m.streams[streamID]
+	if !ok {
+		return nil
+	}
+	delete(m.streams, streamID)
+	return route.session
+}
+
+// closeAll ends every technician session when the endpoint goes away
+func (m *endpointMux) closeAll(sm *SessionManager) {
+	m.mu.Lock()
+	streams := m.streams
+	m.streams = make(map[uint16]*endpointRoute)
+	m.mu.Unlock()
+
+	for _, route := range streams {
+		sm.CloseSession(route.session.ID)
+	}
+}
+
+// handleEndpointMux serves an endpoint that accepts several technicians at once
+func (s *Server) handleEndpointMux(client *Client) {
+	// Same as plain endpoints: the alias reply comes before anything else
+	if client.Alias != "" {
+		if err := s.sessions.RegisterAlias(client); err != nil {
+			client.SendError(err)
+		} else {
+			defer s.sessions.UnregisterAlias(client)
+			client.SendAliasRegistered()
+		}
+	}
+
+	client.Mux = newEndpointMux(client)
+	defer client.Mux.closeAll(s.sessions)
+	client.SendMuxReady()
+
+	s.sessions.RegisterEndpoint(client)
+	defer s.sessions.UnregisterEndpoint(client.ID)
+
+	for {
+		frame, err := client.ReadFrame()
+		if err != nil {
+			return
+		}
+
+		streamID, payload, ok := splitMuxPayload(frame.Payload)
+		if !ok {
+			return
+		}
+		if streamID == MuxControlStream {
+			if len(payload) >= 3 && payload[0] == MuxClose {
+				closed, _, _ := splitMuxPayload(payload[1:])
+				if session := client.Mux.drop(closed); session != nil {
+					s.sessions.CloseSession(session.ID)
+				}
+			}
+			continue
+		}
+
+		route := client.Mux.get(streamID)
+		if route == nil {
+			continue // Technician already gone; drop late frames
+		}
+		if err := route.deliver(&Frame{ChannelID: frame.ChannelID, Payload: payload}); err != nil {
+			s.sessions.CloseSession(route.session.ID)
+		}
+	}
+}
+
+// joinEndpointMux attaches a plain technician connection to a stream of a
+// multiplexed endpoint
+func (s *Server) joinEndpointMux(client *Client, endpoint *Client) {
+	session := s.sessions.CreateMuxSession(client, endpoint)
+	defer s.sessions.CloseSession(session.ID)
+
+	// Success goes out before MuxOpen so the technician is listening when
+	// the endpoint answers
+	client.SendSuccess()
+	if !endpoint.Mux.open(session, client.PublicKeyHash, client.WriteFrame) {
+		return
+	}
+
+	// Technician -> Endpoint, tagged with the stream ID
+	for {
+		frame, err := client.ReadFrame()
+		if err != nil || session.IsClosed() {
+			return
+		}
+		if err := session.SendToEndpoint(frame); err != nil {
+			return
+		}
+	}
+}
@@ -0,0 +1,194 @@
+package relay
+
+import (
+	"encoding/binary"
+	"sync"
+)
+
+// Multiplexed technician connections carry several sessions at once.
+// Every frame payload starts with a 2-byte big-endian stream ID; stream 0
+// carries control messages [op][stream (2)][data] that open and close the rest.
+const (
+	MuxControlStream uint16 = 0
+	MuxOpen          uint8  = 0x01 // [stream][target ID]; echoed [stream] once connected
+	MuxClose         uint8  = 0x02 // [stream]; either direction
+	MuxError         uint8  = 0xFF // [stream][message]
+)
+
+// muxConn tracks the open streams of one multiplexed technician connection
+type muxConn struct {
+	client  *Client
+	streams map[uint16]*Session
+	mu      sync.Mutex
+}
+
+func (m *muxConn) get(streamID uint16) *Session {
+	m.mu.Lock()
+	defer m.mu.Unlock()
+	return m.streams[streamID]
+}
+
+func (m *muxConn) add(streamID uint16, session *Session) {
+	m.mu.Lock()
+	defer m.mu.Unlock()
+	m.streams[streamID] = session
+}
+
+// writeStream forwards a frame to the technician tagged with its stream ID
+func (m *muxConn) writeStream(streamID uint16, frame *Frame) error {
+	return m.client.WriteFrame(&Frame{
+		ChannelID: frame.ChannelID,
+		Payload:   muxPayload(streamID, frame.Payload),
+	})
+}
+
+// sendControl sends a control message on stream 0
+func (m *muxConn) sendControl(op uint8, streamID uint16, data []byte) {
+	payload := append([]byte{op}, muxPayload(streamID, data)...)
+	m.writeStream(MuxControlStream, &Frame{ChannelID: 0x00, Payload: payload})
+}
+
+// closeStream ends one stream's session; notify tells the technician
+// A non-nil owner only closes the stream if it still belongs to that
+// session, so a late close never ends a newer stream reusing the ID
+func (m *muxConn) closeStream(sm *SessionManager, streamID uint16, owner *Session, notify bool) {
+	m.mu.Lock()
+	session, ok := m.streams[streamID]
+	if ok && (owner == nil || session == owner) {
+		delete(m.streams, streamID)
+	} else {
+		ok = false
+	}
+	m.mu.Unlock()
+
+	if !ok {
+		return
+	}
+	sm.CloseSession(session.ID)
+	if notify {
+		m.sendControl(MuxClose, streamID, nil)
+	}
+}
+
+// closeAll ends every stream when the shared connection goes away
+func (m *muxConn) closeAll(sm *SessionManager) {
+	m.mu.Lock()
+	streams := m.streams
+	m.streams = make(map[uint16]*Session)
+	m.mu.Unlock()
+
+	for _, session := range streams {
+		sm.CloseSession(session.ID)
+	}
+}
+
+// muxPayload prefixes a payload with its stream ID
+func muxPayload(streamID uint16, payload []byte) []byte {
+	out := make([]byte, 2+len(payload))
+	binary.BigEndian.PutUint16(out, streamID)
+	copy(out[2:], payload)
+	return out
+}
+
+// splitMuxPayload separates the stream ID from the payload
+func splitMuxPayload(payload []byte) (uint16, []byte, bool) {
+	if len(payload) < 2 {
+		return 0, nil, false
+	}
+	return binary.BigEndian.Uint16(payload), payload[2:], true
+}
+
+// handleTechnicianMux serves several technician sessions over one connection
+func (s *Server) handleTechnicianMux(client *Client) {
+	mux := &muxConn{client: client, streams: make(map[uint16]*Session)}
+	defer mux.closeAll(s.sessions)
+
+	client.SendMuxReady()
+
+	for {
+		frame, err := client.ReadFrame()
+		if err != nil {
+			return
+		}
+
+		streamID, payload, ok := splitMuxPayload(frame.Payload)
+		if !ok {
+			return
+		}
+		if streamID == MuxControlStream {
+			s.handleMuxControl(mux, payload)
+			continue
+		}
+
+		session := mux.get(streamID)
+		if session == nil {
+			continue // Stream already closed; drop late frames
+		}
+		if err := session.SendToEndpoint(&Frame{ChannelID: frame.ChannelID, Payload: payload}); err != nil {
+			mux.closeStream(s.sessions, streamID, session, true)
+		}
+	}
+}
+
+// handleMuxControl opens or closes a stream
+func (s *Server) handleMuxControl(mux *muxConn, payload []byte) {
+	if len(payload) < 3 {
+		return
+	}
+	streamID, data, _ := splitMuxPayload(payload[1:])
+
+	switch payload[0] {
+	case MuxOpen:
+		s.openMuxStream(mux, streamID, string(data))
+	case MuxClose:
+		mux.closeStream(s.sessions, streamID, nil, false)
+	}
+}
+
+// openMuxStream pairs a new stream with its endpoint (no logging of IDs for privacy)
+func (s *Server) openMuxStream(mux *muxConn, streamID uint16, targetID string) {
+	if streamID == MuxControlStream || mux.get(streamID) != nil {
+		mux.sendControl(MuxError, streamID, []byte(ErrInvalidStream.Error()))
+		return
+	}
+
+	endpoint := s.sessions.GetEndpoint(targetID)
+	if endpoint == nil {
+		mux.sendControl(MuxError, streamID, []byte(ErrEndpointNotFound.Error()))
+		return
+	}
+
+	session := s.sessions.CreateMuxSession(nil, endpoint)
+	mux.add(streamID, session)
+	mux.sendControl(MuxOpen, streamID, nil)
+
+	// A multiplexed endpoint routes its own frames back by stream
+	if endpoint.Mux != nil {
+		deliver := func(frame *Frame) error { return mux.writeStream(streamID, frame) }
+		if !endpoint.Mux.open(session, mux.client.PublicKeyHash, deliver) {
+			mux.closeStream(s.sessions, streamID, session, true)
+			return
+		}
+		go func() {
+			<-session.Done()
+			mux.closeStream(s.sessions, streamID, session, true)
+		}()
+		return
+	}
+	endpoint.NotifyConnection(mux.client.PublicKeyHash)
+
+	// Endpoint -> Technician, tagged with the stream ID
+	go func() {
+		for {
+			frame, err := endpoint.ReadFrame()
+			if err != nil || session.IsClosed() {
+				mux.closeStream(s.sessions, streamID, session, true)
+				return
+			}
+			if err := mux.writeStream(streamID, frame); err != nil {
+				mux.closeStream(s.sessions, streamID, session, false)
+				return
+			}
+		}
+	}()
+}
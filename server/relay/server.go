@@ -156,15 +156,32 @@ func (s *Server) handleConnection(conn net.Conn) {
 
 	// Register client based on type (no logging of IDs for privacy)
 	switch client.Type {
-	case ClientTypeEndpoint:
+	case ClientTypeEndpoint, ClientTypeEndpointAlias:
 		s.handleEndpoint(client)
+	case ClientTypeEndpointMux:
+		s.handleEndpointMux(client)
 	case ClientTypeTechnician:
 		s.handleTechnician(client)
+	case ClientTypeTechnicianMux:
+		s.handleTechnicianMux(client)
+	case ClientTypeResolve:
+		s.handleResolve(client)
 	}
 }
 
 // handleEndpoint manages an endpoint connection
 func (s *Server) handleEndpoint(client *Client) {
+	// Claim the alias first so the reply precedes any session notification;
+	// the endpoint stays reachable by ID even if the claim fails
+	if client.Type == ClientTypeEndpointAlias {
+		if err := s.sessions.RegisterAlias(client); err != nil {
+			client.SendError(err)
+		} else {
+			defer s.sessions.UnregisterAlias(client)
+			client.SendAliasRegistered()
+		}
+	}
+
 	// Register endpoint with its ID
 	s.sessions.RegisterEndpoint(client)
 	defer s.sessions.UnregisterEndpoint(client.ID)
@@ -184,6 +201,11 @@ func (s *Server) handleTechnician(client *Client) {
 		return
 	}
 
+	if endpoint.Mux != nil {
+		s.joinEndpointMux(client, endpoint)
+		return
+	}
+
 	// Create session pairing
 	session := s.sessions.CreateSession(client, endpoint)
 	defer s.sessions.CloseSession(session.ID)
@@ -198,6 +220,16 @@ func (s *Server) handleTechnician(client *Client) {
 	s.bridgeSession(session)
 }
 
+// handleResolve answers a one-shot alias lookup (no logging of aliases for privacy)
+func (s *Server) handleResolve(client *Client) {
+	deviceID, ok := s.sessions.ResolveAlias(client.Alias)
+	if !ok {
+		client.SendError(ErrAliasNotFound)
+		return
+	}
+	client.SendResolved(deviceID)
+}
+
 // bridgeSession forwards traffic bidirectionally with minimal overhead
 func (s *Server) bridgeSession(session *Session) {
 	var wg sync.WaitGroup
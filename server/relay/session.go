@@ -12,6 +12,8 @@ type Session struct {
 	ID         string
 	Technician *Client
 	Endpoint   *Client
+	// EndpointStream is the session's stream on a multiplexed endpoint
+	EndpointStream uint16
 	done       chan struct{}
 	closeOnce  sync.Once
 }
@@ -30,11 +32,23 @@ func NewSession(id string, technician, endpoint *Client) *Session {
 func (s *Session) Close() {
 	s.closeOnce.Do(func() {
 		close(s.done)
-		s.Technician.Close()
+		// Multiplexed sessions have no technician connection of their own
+		if s.Technician != nil {
+			s.Technician.Close()
+		}
 		// Don't close endpoint - it may accept other sessions
 	})
 }
 
+// SendToEndpoint forwards a technician frame, tagged with the session's
+// stream when the endpoint is multiplexed
+func (s *Session) SendToEndpoint(frame *Frame) error {
+	if s.Endpoint.Mux != nil {
+		return s.Endpoint.Mux.writeStream(s.EndpointStream, frame)
+	}
+	return s.Endpoint.WriteFrame(frame)
+}
+
 // Done returns a channel that's closed when the session ends
 func (s *Session) Done() <-chan struct{} {
 	return s.done
@@ -54,6 +68,7 @@ func (s *Session) IsClosed() bool {
 // NOTE: No logging, no persistence, no history - privacy by design
 type SessionManager struct {
 	endpoints map[string]*Client  // key: public key hash
+	aliases   map[string]*Client  // key: normalized alias
 	sessions  map[string]*Session // key: session ID
 	mu        sync.RWMutex
 }
@@ -62,6 +77,7 @@ type SessionManager struct {
 func NewSessionManager() *SessionManager {
 	return &SessionManager{
 		endpoints: make(map[string]*Client),
+		aliases:   make(map[string]*Client),
 		sessions:  make(map[string]*Session),
 	}
 }
@@ -92,6 +108,44 @@ func (sm *SessionManager) GetEndpoint(id string) *Client {
 	return sm.endpoints[id]
 }
 
+// RegisterAlias claims client.Alias for the endpoint
+// An alias held by a different device stays with its current owner;
+// the same device reconnecting takes it over
+func (sm *SessionManager) RegisterAlias(client *Client) error {
+	if client.Alias == "" || len(client.Alias) > MaxAliasLength {
+		return ErrInvalidAlias
+	}
+
+	sm.mu.Lock()
+	defer sm.mu.Unlock()
+
+	if existing, ok := sm.aliases[client.Alias]; ok && existing.ID != client.ID {
+		return ErrAliasTaken
+	}
+	sm.aliases[client.Alias] = client
+	return nil
+}
+
+// UnregisterAlias releases the client's alias if it still holds it
+func (sm *SessionManager) UnregisterAlias(client *Client) {
+	sm.mu.Lock()
+	defer sm.mu.Unlock()
+	if sm.aliases[client.Alias] == client {
+		delete(sm.aliases, client.Alias)
+	}
+}
+
+// ResolveAlias returns the device ID registered under an alias
+func (sm *SessionManager) ResolveAlias(alias string) (string, bool) {
+	sm.mu.RLock()
+	defer sm.mu.RUnlock()
+	client, ok := sm.aliases[alias]
+	if !ok {
+		return "", false
+	}
+	return client.ID, true
+}
+
 // EndpointCount returns the number of registered endpoints
 func (sm *SessionManager) EndpointCount() int {
 	sm.mu.RLock()
@@ -111,21 +165,35 @@ func (sm *SessionManager) CreateSession(technician, endpoint *Client) *Session {
 	sm.mu.Lock()
 	defer sm.mu.Unlock()
 
-	// Generate random session ID (not logged anywhere)
-	idBytes := make([]byte, 16)
-	rand.Read(idBytes)
-	sessionID := hex.EncodeToString(idBytes)
-
-	session := NewSession(sessionID, technician, endpoint)
+	session := NewSession(newSessionID(), technician, endpoint)
 
 	// Pair the clients for bidirectional forwarding
 	technician.Paired = endpoint
 	endpoint.Paired = technician
 
-	sm.sessions[sessionID] = session
+	sm.sessions[session.ID] = session
 	return session
 }
 
+// CreateMuxSession creates a session on a multiplexed connection without
+// pairing: technician is nil for a stream of a multiplexed technician, and
+// closing the session leaves the shared connections open
+func (sm *SessionManager) CreateMuxSession(technician, endpoint *Client) *Session {
+	sm.mu.Lock()
+	defer sm.mu.Unlock()
+
+	session := NewSession(newSessionID(), technician, endpoint)
+	sm.sessions[session.ID] = session
+	return session
+}
+
+// newSessionID generates a random session ID (not logged anywhere)
+func newSessionID() string {
+	idBytes := make([]byte, 16)
+	rand.Read(idBytes)
+	return hex.EncodeToString(idBytes)
+}
+
 // CloseSession closes and removes a session
 func (sm *SessionManager) CloseSession(id string) {
 	sm.mu.Lock()
@@ -142,6 +210,9 @@ func (sm *SessionManager) CloseSession(id string) {
 		}
 		if session.Endpoint != nil {
 			session.Endpoint.Paired = nil
+			if session.Endpoint.Mux != nil {
+				session.Endpoint.Mux.release(session.EndpointStream, session)
+			}
 		}
 		session.Close()
 	}
@@ -161,4 +232,5 @@ func (sm *SessionManager) CloseAll() {
 
 	sm.sessions = make(map[string]*Session)
 	sm.endpoints = make(map[string]*Client)
+	sm.aliases = make(map[string]*Client)
 }